@@ -115,8 +115,15 @@ export default function ProjectDetailPage() {
           codeContext: t.code_context,
           analysisResult: t.analysis_result,
           isAnalyzing: t.is_analyzing,
+          stale: t.stale,
           logs: [], // Khởi tạo empty array
         })))
+
+        // Deep link từ email thông báo: ?ticket=<id> mở chi tiết ticket
+        const linkedTicketId = new URLSearchParams(window.location.search).get('ticket')
+        if (linkedTicketId && data.some((t: any) => t.id === linkedTicketId)) {
+          openDetailModal(linkedTicketId)
+        }
       } catch (error) {
         console.error('Failed to load tickets:', error)
       }
@@ -124,7 +131,7 @@ export default function ProjectDetailPage() {
     if (!isLoading) {
       loadTickets()
     }
-  }, [projectId, isLoading, setTickets])
+  }, [projectId, isLoading, setTickets, openDetailModal])
 
   // Connect WebSocket (only for real-time logs)
   useEffect(() => {
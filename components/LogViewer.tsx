@@ -324,6 +324,14 @@ function LogEntry({ log }: { log: StructuredLog }) {
                 second: '2-digit',
               })}
             </span>
+            {log.metadata?.compacted_count && (
+              <span
+                className="text-xs text-gray-400 bg-gray-800 px-1.5 rounded"
+                title={log.metadata.compacted_files}
+              >
+                ×{log.metadata.compacted_count}
+              </span>
+            )}
           </div>
 
           {/* Plain text summary */}
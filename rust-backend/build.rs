@@ -0,0 +1,17 @@
+fn main() {
+    // `sqlx::migrate!` embeds migrations/ at compile time; rebuild when a file is added
+    println!("cargo:rerun-if-changed=migrations");
+
+    // Generate the gRPC service only when the feature is enabled, using protox
+    // so building does not require a system `protoc`.
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/analysis.proto");
+        let file_descriptors = protox::compile(["analysis.proto"], ["proto"])
+            .expect("Failed to parse proto/analysis.proto");
+        tonic_prost_build::configure()
+            .build_client(false)
+            .compile_fds(file_descriptors)
+            .expect("Failed to generate gRPC code");
+    }
+}
@@ -0,0 +1,199 @@
+use anyhow::{anyhow, bail, Context, Result};
+use reqwest::{Method, RequestBuilder, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::path::PathBuf;
+
+#[derive(Debug, Deserialize)]
+pub struct User {
+    pub email: String,
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LoginResponse {
+    pub token: String,
+    pub expires_at: String,
+    pub user: User,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Project {
+    pub id: String,
+    pub name: String,
+    pub directory_path: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Ticket {
+    pub id: String,
+    pub project_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Preferences {
+    pub default_project_id: Option<String>,
+}
+
+/// REST client for the backend, authenticated with a bearer token when one is known
+pub struct ApiClient {
+    server: String,
+    token: Option<String>,
+    http: reqwest::Client,
+}
+
+impl ApiClient {
+    pub fn new(server: &str, token: Option<String>) -> Self {
+        Self {
+            server: server.trim_end_matches('/').to_string(),
+            token,
+            http: reqwest::Client::new(),
+        }
+    }
+
+    pub fn server(&self) -> &str {
+        &self.server
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let request = self.http.request(method, format!("{}{}", self.server, path));
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    async fn send<T: DeserializeOwned>(&self, request: RequestBuilder, what: &str) -> Result<T> {
+        let response = request
+            .send()
+            .await
+            .with_context(|| format!("Cannot reach {}", self.server))?;
+
+        match response.status() {
+            status if status.is_success() => response.json().await.with_context(|| format!("Invalid {} response", what)),
+            StatusCode::UNAUTHORIZED => bail!("{} failed: not logged in (run `explain-source login`)", what),
+            status => bail!("{} failed: HTTP {}", what, status),
+        }
+    }
+
+    // POST /api/auth/login
+    pub async fn login(&self, email: &str, password: &str) -> Result<LoginResponse> {
+        let request = self
+            .request(Method::POST, "/api/auth/login")
+            .json(&json!({ "email": email, "password": password }));
+        self.send(request, "Login").await
+    }
+
+    // POST /api/auth/logout
+    pub async fn logout(&self) -> Result<()> {
+        let response = self.request(Method::POST, "/api/auth/logout").send().await?;
+        if !response.status().is_success() {
+            bail!("Logout failed: HTTP {}", response.status());
+        }
+        Ok(())
+    }
+
+    // GET /api/projects
+    pub async fn projects(&self) -> Result<Vec<Project>> {
+        self.send(self.request(Method::GET, "/api/projects"), "Listing projects").await
+    }
+
+    // GET /api/me/preferences
+    pub async fn preferences(&self) -> Result<Preferences> {
+        self.send(self.request(Method::GET, "/api/me/preferences"), "Reading preferences").await
+    }
+
+    // POST /api/projects/:project_id/tickets
+    pub async fn create_ticket(&self, project_id: &str, title: &str, description: &str) -> Result<Ticket> {
+        let request = self
+            .request(Method::POST, &format!("/api/projects/{}/tickets", project_id))
+            .json(&json!({ "title": title, "description": description, "status": "in-progress" }));
+        self.send(request, "Creating the ticket").await
+    }
+
+    // POST /api/tickets/:id/stop-analysis
+    pub async fn stop_analysis(&self, ticket_id: &str) -> Result<()> {
+        let request = self.request(Method::POST, &format!("/api/tickets/{}/stop-analysis", ticket_id));
+        self.send::<Value>(request, "Stopping the analysis").await.map(|_| ())
+    }
+
+    /// `/ws` on the same host, carrying the token as a query parameter
+    pub fn ws_url(&self) -> Result<String> {
+        let base = if let Some(rest) = self.server.strip_prefix("https://") {
+            format!("wss://{}", rest)
+        } else if let Some(rest) = self.server.strip_prefix("http://") {
+            format!("ws://{}", rest)
+        } else {
+            return Err(anyhow!("Server URL must start with http:// or https://: {}", self.server));
+        };
+
+        Ok(match &self.token {
+            Some(token) => format!("{}/ws?token={}", base, token),
+            None => format!("{}/ws", base),
+        })
+    }
+}
+
+/// Token saved by `login`, tied to the server it was issued by
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Credentials {
+    pub server: String,
+    pub token: String,
+}
+
+impl Credentials {
+    /// `$XDG_CONFIG_HOME/explain-source/credentials.json`, else under `~/.config`
+    fn path() -> Result<PathBuf> {
+        let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+            .ok_or_else(|| anyhow!("Cannot locate a config directory (set HOME or XDG_CONFIG_HOME)"))?;
+        Ok(config_dir.join("explain-source").join("credentials.json"))
+    }
+
+    /// Saved token for `server`, if any
+    pub fn load(server: &str) -> Option<String> {
+        let content = std::fs::read_to_string(Self::path().ok()?).ok()?;
+        let credentials: Credentials = serde_json::from_str(&content).ok()?;
+        (credentials.server == server).then_some(credentials.token)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path()?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Cannot write {}", path.display()))?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+        }
+        Ok(())
+    }
+
+    pub fn remove() -> Result<()> {
+        match std::fs::remove_file(Self::path()?) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ws_url() {
+        let client = ApiClient::new("https://qa.example.com/", Some("abc".to_string()));
+        assert_eq!(client.ws_url().unwrap(), "wss://qa.example.com/ws?token=abc");
+
+        let client = ApiClient::new("http://localhost:9000", None);
+        assert_eq!(client.ws_url().unwrap(), "ws://localhost:9000/ws");
+
+        assert!(ApiClient::new("localhost:9000", None).ws_url().is_err());
+    }
+}
@@ -0,0 +1,272 @@
+//! `explain-source`: ask the QA backend about a project from the terminal
+//!
+//! ```text
+//! explain-source login --email me@example.com
+//! explain-source ask --project shop "how does checkout work?"
+//! ```
+
+mod api;
+mod output;
+
+use anyhow::{anyhow, bail, Context, Result};
+use api::{ApiClient, Credentials, Project};
+use clap::{Parser, Subcommand, ValueEnum};
+use futures_util::{SinkExt, StreamExt};
+use output::Printer;
+use serde_json::{json, Value};
+use tokio_tungstenite::tungstenite::Message;
+
+/// Longest ticket title derived from a question
+const TITLE_MAX_CHARS: usize = 80;
+
+#[derive(Parser)]
+#[command(name = "explain-source", version, about = "Ask questions about your code from the terminal")]
+struct Cli {
+    /// Backend base URL
+    #[arg(long, global = true, env = "EXPLAIN_SOURCE_URL", default_value = "http://localhost:9000")]
+    server: String,
+
+    /// API token; defaults to the one saved by `login`
+    #[arg(long, global = true, env = "EXPLAIN_SOURCE_TOKEN", hide_env_values = true)]
+    token: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Log in and save the token for later commands
+    Login {
+        #[arg(long)]
+        email: String,
+        /// Prompted for when unset
+        #[arg(long, env = "EXPLAIN_SOURCE_PASSWORD", hide_env_values = true)]
+        password: Option<String>,
+    },
+    /// Revoke and forget the saved token
+    Logout,
+    /// List the projects you can ask about
+    Projects,
+    /// Ask a question about a project, streaming the analysis; the answer goes to stdout
+    Ask {
+        /// Project name or ID; your default project (`/api/me/preferences`) when unset
+        #[arg(long, short)]
+        project: Option<String>,
+        question: String,
+        /// Agent to use (see `GET /api/agents`); the server default when unset
+        #[arg(long)]
+        agent: Option<String>,
+        #[arg(long, value_enum, default_value_t = Mode::Ask)]
+        mode: Mode,
+        /// Print only the answer
+        #[arg(long, short)]
+        quiet: bool,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Mode {
+    /// Free-form answer
+    Ask,
+    /// QA test cases
+    Testcases,
+}
+
+impl Mode {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Mode::Ask => "ask",
+            Mode::Testcases => "testcases",
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+    match run(cli).await {
+        Ok(code) => std::process::exit(code),
+        Err(e) => {
+            Printer::new(false).error(&format!("error: {:#}", e));
+            std::process::exit(1);
+        }
+    }
+}
+
+async fn run(cli: Cli) -> Result<i32> {
+    let server = cli.server.trim_end_matches('/').to_string();
+    let token = cli.token.or_else(|| Credentials::load(&server));
+    let client = ApiClient::new(&server, token);
+
+    match cli.command {
+        Command::Login { email, password } => {
+            let password = match password {
+                Some(password) => password,
+                None => rpassword::prompt_password("Password: ")?,
+            };
+            let login = client.login(&email, &password).await?;
+            Credentials {
+                server: client.server().to_string(),
+                token: login.token,
+            }
+            .save()?;
+            println!("Logged in as {} <{}> (token expires {})", login.user.name, login.user.email, login.expires_at);
+        }
+        Command::Logout => {
+            client.logout().await?;
+            Credentials::remove()?;
+            println!("Logged out");
+        }
+        Command::Projects => {
+            for project in client.projects().await? {
+                println!("{}\t{}\t{}", project.id, project.name, project.directory_path);
+            }
+        }
+        Command::Ask {
+            project,
+            question,
+            agent,
+            mode,
+            quiet,
+        } => return ask(&client, project.as_deref(), &question, agent.as_deref(), mode, &Printer::new(quiet)).await,
+    }
+    Ok(0)
+}
+
+/// Project by ID or case-insensitive name
+fn find_project<'a>(projects: &'a [Project], wanted: &str) -> Option<&'a Project> {
+    projects
+        .iter()
+        .find(|p| p.id == wanted)
+        .or_else(|| projects.iter().find(|p| p.name.eq_ignore_ascii_case(wanted)))
+}
+
+fn ticket_title(question: &str) -> String {
+    let line = question.lines().next().unwrap_or_default().trim();
+    match line.char_indices().nth(TITLE_MAX_CHARS) {
+        Some((cut, _)) => format!("{}…", line[..cut].trim_end()),
+        None => line.to_string(),
+    }
+}
+
+/// Create a ticket, start its analysis over `/ws` and follow it to the end.
+/// Exit code 0 when the analysis succeeds, 1 when it fails, 130 when interrupted.
+async fn ask(
+    client: &ApiClient,
+    project: Option<&str>,
+    question: &str,
+    agent: Option<&str>,
+    mode: Mode,
+    printer: &Printer,
+) -> Result<i32> {
+    let project = match project {
+        Some(project) => project.to_string(),
+        None => client
+            .preferences()
+            .await?
+            .default_project_id
+            .context("No --project given and no default project set in your preferences")?,
+    };
+    let projects = client.projects().await?;
+    let Some(project) = find_project(&projects, &project) else {
+        let names: Vec<&str> = projects.iter().map(|p| p.name.as_str()).collect();
+        bail!("No project named {}. Available: {}", project, names.join(", "));
+    };
+
+    let ticket = client.create_ticket(&project.id, &ticket_title(question), question).await?;
+    printer.status(&format!("Ticket {} in {}", ticket.id, project.name));
+
+    let (mut socket, _) = tokio_tungstenite::connect_async(client.ws_url()?)
+        .await
+        .context("Cannot open the WebSocket")?;
+    let start = json!({
+        "type": "start-code-analysis",
+        "ticketId": ticket.id,
+        "projectId": ticket.project_id,
+        "question": question,
+        "codeContext": "",
+        "agentType": agent,
+        "mode": mode.as_str(),
+    });
+    socket.send(Message::Text(start.to_string())).await?;
+
+    loop {
+        let frame = tokio::select! {
+            frame = socket.next() => frame,
+            _ = tokio::signal::ctrl_c() => {
+                client.stop_analysis(&ticket.id).await?;
+                printer.error("Analysis stopped");
+                return Ok(130);
+            }
+        };
+        let text = match frame {
+            Some(Ok(Message::Text(text))) => text,
+            Some(Ok(Message::Close(_))) | None => bail!("Connection closed before the analysis finished"),
+            Some(Ok(_)) => continue,
+            Some(Err(e)) => return Err(anyhow!(e).context("WebSocket error")),
+        };
+        let Ok(event) = serde_json::from_str::<Value>(&text) else {
+            continue;
+        };
+
+        match event["message_type"].as_str().unwrap_or_default() {
+            "structured-log" if event["log"]["ticket_id"] == ticket.id.as_str() => printer.log(&event["log"]),
+            "structured-log-batch" => event["logs"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter(|log| log["ticket_id"] == ticket.id.as_str())
+                .for_each(|log| printer.log(log)),
+            _ if event["ticket_id"] != ticket.id.as_str() => {}
+            "analysis-started" => {
+                let run_id: Value = serde_json::from_str(event["content"].as_str().unwrap_or("{}")).unwrap_or_default();
+                printer.status(&format!("Run {}", run_id["run_id"].as_str().unwrap_or("?")));
+            }
+            "analysis-progress" => {
+                let progress: Value = serde_json::from_str(event["content"].as_str().unwrap_or("{}")).unwrap_or_default();
+                if let Some(label) = progress["label"].as_str() {
+                    printer.status(&format!("… {}", label));
+                }
+            }
+            "code-analysis-complete" => {
+                printer.success("Analysis complete");
+                println!("{}", event["content"].as_str().unwrap_or_default());
+                return Ok(0);
+            }
+            "code-analysis-error" => {
+                printer.error(&format!("Analysis failed: {}", event["content"].as_str().unwrap_or_default()));
+                return Ok(1);
+            }
+            "analysis-stopped" => {
+                printer.error("Analysis stopped");
+                return Ok(1);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project(id: &str, name: &str) -> Project {
+        Project {
+            id: id.to_string(),
+            name: name.to_string(),
+            directory_path: format!("/src/{}", name),
+        }
+    }
+
+    #[test]
+    fn test_find_project_and_title() {
+        let projects = vec![project("p-1", "Shop"), project("p-2", "shop-admin")];
+        assert_eq!(find_project(&projects, "shop").map(|p| p.id.as_str()), Some("p-1"));
+        assert_eq!(find_project(&projects, "p-2").map(|p| p.id.as_str()), Some("p-2"));
+        assert!(find_project(&projects, "billing").is_none());
+
+        assert_eq!(ticket_title("how does checkout work?\nmore context"), "how does checkout work?");
+        assert_eq!(ticket_title(&"q".repeat(100)).chars().count(), TITLE_MAX_CHARS + 1);
+    }
+}
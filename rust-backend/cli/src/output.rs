@@ -0,0 +1,148 @@
+use serde_json::Value;
+use std::io::IsTerminal;
+
+/// Longest log line printed; the full entries stay in the ticket's history
+const LINE_MAX_CHARS: usize = 200;
+
+const RED: &str = "31";
+const GREEN: &str = "32";
+const CYAN: &str = "36";
+const DIM: &str = "2";
+
+/// Progress goes to stderr so stdout carries only the answer (`explain-source ask … > answer.md`)
+pub struct Printer {
+    color: bool,
+    quiet: bool,
+}
+
+impl Printer {
+    pub fn new(quiet: bool) -> Self {
+        Self {
+            color: std::io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none(),
+            quiet,
+        }
+    }
+
+    fn paint(&self, code: &str, text: &str) -> String {
+        if self.color {
+            format!("\x1b[{}m{}\x1b[0m", code, text)
+        } else {
+            text.to_string()
+        }
+    }
+
+    /// One structured log entry (`log_json` shape from the `/ws` stream)
+    pub fn log(&self, log: &Value) {
+        if self.quiet {
+            return;
+        }
+        let message_type = log["message_type"].as_str().unwrap_or("system");
+        let Some(line) = log_line(log["content"].as_str().unwrap_or_default()) else {
+            return;
+        };
+
+        let line = match message_type {
+            "tool_use" => self.paint(CYAN, &format!("→ {}", line)),
+            "error" => self.paint(RED, &line),
+            "result" => self.paint(GREEN, &line),
+            "assistant" => line,
+            _ => self.paint(DIM, &line),
+        };
+        eprintln!("{}", line);
+    }
+
+    pub fn status(&self, text: &str) {
+        if !self.quiet {
+            eprintln!("{}", self.paint(DIM, text));
+        }
+    }
+
+    pub fn success(&self, text: &str) {
+        if !self.quiet {
+            eprintln!("{}", self.paint(GREEN, text));
+        }
+    }
+
+    /// Errors are printed even with `--quiet`
+    pub fn error(&self, text: &str) {
+        eprintln!("{}", self.paint(RED, text));
+    }
+}
+
+/// Single-line rendering of a log entry's content; None for entries not worth a line
+/// (streaming deltas, tool results, agent result events: the answer is printed in full at the end)
+pub fn log_line(content: &str) -> Option<String> {
+    let line = match serde_json::from_str::<Value>(content) {
+        Ok(json) if json.is_object() => json_event_line(&json)?,
+        _ => content.trim().to_string(),
+    };
+    let line = line.lines().map(str::trim).find(|l| !l.is_empty())?.to_string();
+
+    Some(match line.char_indices().nth(LINE_MAX_CHARS) {
+        Some((cut, _)) => format!("{}…", &line[..cut]),
+        None => line,
+    })
+}
+
+fn json_event_line(json: &Value) -> Option<String> {
+    if let Some(error) = json.get("error") {
+        return Some(error.as_str().map(str::to_string).unwrap_or_else(|| error.to_string()));
+    }
+
+    match json["type"].as_str().unwrap_or_default() {
+        // Claude stream-json: {"type":"assistant","message":{"content":[...]}}
+        "assistant" => {
+            let blocks = json.pointer("/message/content")?.as_array()?;
+            blocks.iter().find_map(|block| match block["type"].as_str()? {
+                "tool_use" => Some(tool_line(block["name"].as_str()?, &block["input"])),
+                "text" => block["text"].as_str().map(str::to_string),
+                _ => None,
+            })
+        }
+        // Gemini / Cursor: {"type":"tool_use","tool_name":"read_file","parameters":{...}}
+        "tool_use" | "tool_call" => {
+            let name = json["tool_name"].as_str().or_else(|| json["name"].as_str())?;
+            Some(tool_line(name, &json["parameters"]))
+        }
+        "message" if json["role"] == "assistant" && json["delta"] != true => {
+            json["content"].as_str().map(str::to_string)
+        }
+        _ => None,
+    }
+}
+
+/// `Read src/checkout.ts`: the tool and its most telling argument
+fn tool_line(name: &str, input: &Value) -> String {
+    let target = ["file_path", "path", "absolute_path", "pattern", "query", "command"]
+        .iter()
+        .find_map(|key| input.get(*key).and_then(Value::as_str));
+    match target {
+        Some(target) => format!("{} {}", name, target),
+        None => name.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_line() {
+        assert_eq!(log_line("  🔄 Khởi động agent...\n"), Some("🔄 Khởi động agent...".to_string()));
+        assert_eq!(
+            log_line(r#"{"type":"assistant","message":{"content":[{"type":"tool_use","name":"Read","input":{"file_path":"src/a.rs"}}]}}"#),
+            Some("Read src/a.rs".to_string())
+        );
+        assert_eq!(
+            log_line(r#"{"type":"tool_use","tool_name":"grep","parameters":{"pattern":"checkout"}}"#),
+            Some("grep checkout".to_string())
+        );
+        assert_eq!(
+            log_line(r#"{"type":"message","role":"assistant","content":"The","delta":true}"#),
+            None
+        );
+        assert_eq!(log_line(r#"{"type":"tool_result","output":"..."}"#), None);
+        assert_eq!(log_line(r#"{"type":"error","error":"quota exceeded"}"#), Some("quota exceeded".to_string()));
+        assert_eq!(log_line(&"x".repeat(300)).map(|l| l.chars().count()), Some(LINE_MAX_CHARS + 1));
+    }
+}
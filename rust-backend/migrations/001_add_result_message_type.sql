@@ -1,10 +1,7 @@
 -- Migration: Add 'result' message type to structured_logs table
 -- Date: 2025-01-28
 -- Description: Updates CHECK constraint to allow 'result' message type
-
-PRAGMA foreign_keys=off;
-
-BEGIN TRANSACTION;
+-- Runs inside the migrator's transaction, so no BEGIN/COMMIT or PRAGMA here
 
 -- Tạo bảng mới với CHECK constraint đã update
 CREATE TABLE structured_logs_new (
@@ -30,7 +27,3 @@ ALTER TABLE structured_logs_new RENAME TO structured_logs;
 -- Tạo lại indexes
 CREATE INDEX idx_logs_ticket_id ON structured_logs(ticket_id);
 CREATE INDEX idx_logs_timestamp ON structured_logs(timestamp);
-
-COMMIT;
-
-PRAGMA foreign_keys=on;
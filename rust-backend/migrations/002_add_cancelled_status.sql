@@ -1,10 +1,7 @@
 -- Migration: Add 'cancelled' status to analysis_sessions table
 -- Date: 2025-01-28
 -- Description: Updates CHECK constraint to allow 'cancelled' status
-
-PRAGMA foreign_keys=off;
-
-BEGIN TRANSACTION;
+-- Runs inside the migrator's transaction, so no BEGIN/COMMIT or PRAGMA here
 
 -- Tạo bảng mới với CHECK constraint đã update
 CREATE TABLE analysis_sessions_new (
@@ -26,7 +23,3 @@ DROP TABLE analysis_sessions;
 -- Rename bảng mới
 ALTER TABLE analysis_sessions_new RENAME TO analysis_sessions;
 
-COMMIT;
-
-PRAGMA foreign_keys=on;
-
@@ -0,0 +1,7 @@
+-- Migration: Record which agent ran each analysis session
+-- Date: 2026-10-16
+-- Description: Adds nullable agent_type column to analysis_sessions (NULL for sessions created before this migration)
+
+ALTER TABLE analysis_sessions ADD COLUMN agent_type TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_sessions_started_at ON analysis_sessions(started_at);
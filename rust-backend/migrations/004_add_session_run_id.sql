@@ -0,0 +1,7 @@
+-- Migration: Store the correlation run ID of each analysis session
+-- Date: 2026-10-16
+-- Description: Adds nullable run_id column to analysis_sessions so a run ID quoted in a bug report can be traced to its session
+
+ALTER TABLE analysis_sessions ADD COLUMN run_id TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_sessions_run_id ON analysis_sessions(run_id);
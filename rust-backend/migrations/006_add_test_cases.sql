@@ -0,0 +1,20 @@
+-- Migration: Store test cases generated by the testcases analysis mode
+-- Date: 2026-10-16
+-- Description: Adds test_cases table; preconditions and steps are JSON string arrays.
+-- Each successful testcases run replaces the ticket's previous cases.
+
+CREATE TABLE IF NOT EXISTS test_cases (
+    id TEXT PRIMARY KEY,
+    ticket_id TEXT NOT NULL,
+    run_id TEXT,
+    position INTEGER NOT NULL,
+    title TEXT NOT NULL,
+    preconditions TEXT NOT NULL DEFAULT '[]',
+    steps TEXT NOT NULL DEFAULT '[]',
+    expected TEXT NOT NULL,
+    priority TEXT,
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (ticket_id) REFERENCES tickets(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_test_cases_ticket_id ON test_cases(ticket_id, position);
@@ -0,0 +1,13 @@
+-- Migration: Group test cases by flow and label tickets
+-- Date: 2026-10-16
+-- Description: Adds nullable flow column to test_cases (one Gherkin feature per flow) and a
+-- ticket_labels table whose labels become Gherkin tags on export
+
+ALTER TABLE test_cases ADD COLUMN flow TEXT;
+
+CREATE TABLE IF NOT EXISTS ticket_labels (
+    ticket_id TEXT NOT NULL,
+    label TEXT NOT NULL,
+    PRIMARY KEY (ticket_id, label),
+    FOREIGN KEY (ticket_id) REFERENCES tickets(id) ON DELETE CASCADE
+);
@@ -0,0 +1,15 @@
+-- Migration: Record which source files each ticket's analysis touched
+-- Date: 2026-10-16
+-- Description: Adds ticket_files, filled from agents' tool_use events. analyzed_at is the start
+-- of the latest run that touched the file, so edits made after it mark the analysis stale.
+
+CREATE TABLE IF NOT EXISTS ticket_files (
+    ticket_id TEXT NOT NULL,
+    file_path TEXT NOT NULL,
+    run_id TEXT,
+    analyzed_at TEXT NOT NULL,
+    PRIMARY KEY (ticket_id, file_path),
+    FOREIGN KEY (ticket_id) REFERENCES tickets(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_ticket_files_file_path ON ticket_files(file_path);
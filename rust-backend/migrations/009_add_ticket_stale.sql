@@ -0,0 +1,6 @@
+-- Migration: Flag tickets whose analysis predates changes to the files it read
+-- Date: 2026-10-16
+-- Description: Adds tickets.stale, maintained by the background stale checker from ticket_files
+-- and git history, and cleared when a new run records its files
+
+ALTER TABLE tickets ADD COLUMN stale BOOLEAN NOT NULL DEFAULT 0;
@@ -0,0 +1,18 @@
+-- Migration: Public read-only share links for tickets
+-- Date: 2026-10-16
+-- Description: Adds ticket_shares. Only a hash of the link token is stored; links can expire,
+-- be revoked, and require an (argon2-hashed) password
+
+CREATE TABLE IF NOT EXISTS ticket_shares (
+    id TEXT PRIMARY KEY,
+    ticket_id TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    password_hash TEXT,
+    created_by TEXT,
+    created_at TEXT NOT NULL,
+    expires_at TEXT,
+    revoked_at TEXT,
+    FOREIGN KEY (ticket_id) REFERENCES tickets(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_ticket_shares_ticket_id ON ticket_shares(ticket_id);
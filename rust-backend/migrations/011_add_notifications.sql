@@ -0,0 +1,25 @@
+-- Migration: Email notifications for finished analyses
+-- Date: 2026-10-16
+-- Description: Adds per-user notification preferences (email off / immediate / digest) and the
+-- queue of completion notices waiting for a user's next digest email
+
+CREATE TABLE IF NOT EXISTS notification_preferences (
+    user_id TEXT PRIMARY KEY,
+    email_mode TEXT NOT NULL CHECK(email_mode IN ('off', 'immediate', 'digest')),
+    updated_at TEXT NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS pending_notifications (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    ticket_id TEXT NOT NULL,
+    ticket_title TEXT NOT NULL,
+    succeeded BOOLEAN NOT NULL,
+    summary TEXT NOT NULL,
+    link TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_pending_notifications_user_id ON pending_notifications(user_id);
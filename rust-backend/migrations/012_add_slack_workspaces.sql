@@ -0,0 +1,16 @@
+-- Migration: Slack workspaces linked to organizations
+-- Date: 2026-10-16
+-- Description: Stores the bot token of each Slack workspace installed for an organization, used by
+-- the /explain slash command to post progress and results back to Slack
+
+CREATE TABLE IF NOT EXISTS slack_workspaces (
+    team_id TEXT PRIMARY KEY,
+    org_id TEXT NOT NULL,
+    team_name TEXT NOT NULL,
+    bot_token TEXT NOT NULL,
+    installed_by TEXT,
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (org_id) REFERENCES organizations(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_slack_workspaces_org_id ON slack_workspaces(org_id);
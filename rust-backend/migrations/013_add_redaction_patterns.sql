@@ -0,0 +1,12 @@
+-- Migration: Admin-defined secret redaction patterns
+-- Date: 2026-10-16
+-- Description: Regexes applied to agent logs and results, on top of the built-in secret formats,
+-- before they are stored or broadcast
+
+CREATE TABLE IF NOT EXISTS redaction_patterns (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    pattern TEXT NOT NULL,
+    created_by TEXT,
+    created_at TEXT NOT NULL
+);
@@ -0,0 +1,12 @@
+-- Migration: Per-project include/exclude path globs
+-- Date: 2026-10-16
+-- Description: Restricts which files agents may read for a project (e.g. never secrets/ or *.pem).
+-- Both columns hold a JSON array of gitignore-style patterns.
+
+CREATE TABLE IF NOT EXISTS project_path_rules (
+    project_id TEXT PRIMARY KEY,
+    include_patterns TEXT NOT NULL DEFAULT '[]',
+    exclude_patterns TEXT NOT NULL DEFAULT '[]',
+    updated_at TEXT NOT NULL,
+    FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+);
@@ -0,0 +1,13 @@
+-- Migration: Per-project tool permission policy
+-- Date: 2026-10-16
+-- Description: Tools agents may use without asking (JSON array, Claude Code naming). Projects
+-- without a row get their analysis mode's read-only default. Each session records the policy it ran with.
+
+CREATE TABLE IF NOT EXISTS project_tool_policies (
+    project_id TEXT PRIMARY KEY,
+    allowed_tools TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+);
+
+ALTER TABLE analysis_sessions ADD COLUMN tool_policy TEXT;
@@ -0,0 +1,18 @@
+-- Migration: Idempotency keys for retried POSTs
+-- Date: 2026-10-16
+-- Description: Maps a client's Idempotency-Key to the response of the request that first used it,
+-- so a retry gets that response instead of creating a duplicate. A row without a response is a
+-- request still in progress. Rows expire after IDEMPOTENCY_KEY_TTL_HOURS.
+
+CREATE TABLE IF NOT EXISTS idempotency_keys (
+    scope TEXT NOT NULL,
+    idempotency_key TEXT NOT NULL,
+    fingerprint TEXT NOT NULL,
+    status_code INTEGER,
+    response TEXT,
+    created_at TEXT NOT NULL,
+    expires_at TEXT NOT NULL,
+    PRIMARY KEY (scope, idempotency_key)
+);
+
+CREATE INDEX IF NOT EXISTS idx_idempotency_keys_expires_at ON idempotency_keys(expires_at);
@@ -0,0 +1,15 @@
+-- Migration: External identities for SSO logins
+-- Date: 2026-10-16
+-- Description: Maps an OIDC provider's (issuer, subject) to the local user it signs in, so a
+-- user keeps their account when their email changes at the identity provider.
+
+CREATE TABLE IF NOT EXISTS user_identities (
+    issuer TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    PRIMARY KEY (issuer, subject),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_user_identities_user_id ON user_identities(user_id);
@@ -0,0 +1,10 @@
+-- Migration: Soft delete for projects
+-- Date: 2026-10-16
+-- Description: Deleting a project moves it to the trash instead of cascading to its tickets.
+-- Trashed projects are hidden from every list/get query, can be restored, and are purged for
+-- good after TRASH_RETENTION_DAYS.
+
+ALTER TABLE projects ADD COLUMN deleted_at TEXT;
+ALTER TABLE projects ADD COLUMN deleted_by TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_projects_deleted_at ON projects(deleted_at);
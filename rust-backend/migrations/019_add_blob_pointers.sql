@@ -0,0 +1,9 @@
+-- Migration: Pointers to results and raw logs kept in the blob store
+-- Date: 2026-10-17
+-- Description: Analysis results and raw log lines above BLOB_INLINE_MAX_BYTES are written to the
+-- blob store (filesystem or S3). The original columns then keep a preview, and these columns the
+-- blob key (plus the full result size, for display).
+
+ALTER TABLE tickets ADD COLUMN analysis_result_blob TEXT;
+ALTER TABLE tickets ADD COLUMN analysis_result_size INTEGER;
+ALTER TABLE structured_logs ADD COLUMN raw_log_blob TEXT;
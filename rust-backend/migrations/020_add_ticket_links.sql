@@ -0,0 +1,20 @@
+-- Migration: Typed links between tickets
+-- Date: 2026-10-17
+-- Description: A ticket can block, relate to or duplicate another ticket of the same project.
+-- `blocks` and `duplicates` must stay acyclic (checked by the API). Links of a deleted ticket go
+-- with it.
+
+CREATE TABLE IF NOT EXISTS ticket_links (
+    id TEXT PRIMARY KEY,
+    source_ticket_id TEXT NOT NULL,
+    target_ticket_id TEXT NOT NULL,
+    link_type TEXT NOT NULL CHECK (link_type IN ('blocks', 'relates-to', 'duplicates')),
+    created_by TEXT,
+    created_at TEXT NOT NULL,
+    UNIQUE (source_ticket_id, target_ticket_id, link_type),
+    FOREIGN KEY (source_ticket_id) REFERENCES tickets(id) ON DELETE CASCADE,
+    FOREIGN KEY (target_ticket_id) REFERENCES tickets(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_ticket_links_source ON ticket_links(source_ticket_id);
+CREATE INDEX IF NOT EXISTS idx_ticket_links_target ON ticket_links(target_ticket_id);
@@ -0,0 +1,11 @@
+-- Migration: Run environment on analysis sessions
+-- Date: 2026-10-17
+-- Description: What produced a run, so odd results can be traced back: the agent CLI's
+-- `--version` output, the model reported by the agent's init event, the analyzed repository's
+-- commit, a SHA-256 of the prompt and a JSON snapshot of the agent configuration (no secrets).
+
+ALTER TABLE analysis_sessions ADD COLUMN cli_version TEXT;
+ALTER TABLE analysis_sessions ADD COLUMN model TEXT;
+ALTER TABLE analysis_sessions ADD COLUMN git_commit TEXT;
+ALTER TABLE analysis_sessions ADD COLUMN prompt_hash TEXT;
+ALTER TABLE analysis_sessions ADD COLUMN config_snapshot TEXT;
@@ -0,0 +1,9 @@
+-- Migration: Replayable analysis sessions
+-- Date: 2026-10-17
+-- Description: Each session keeps the request its agent received (question, code context, mode,
+-- path rules) so it can be re-run as is, and a replay points back at the session it re-ran.
+
+ALTER TABLE analysis_sessions ADD COLUMN request_json TEXT;
+ALTER TABLE analysis_sessions ADD COLUMN replay_of TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_analysis_sessions_replay_of ON analysis_sessions(replay_of);
@@ -0,0 +1,17 @@
+-- Migration: Files resolved from a ticket's code context
+-- Date: 2026-10-17
+-- Description: Fuzzy code contexts ("the login controller") are resolved to project files by
+-- filename and symbol matching. The chosen files are kept per ticket, together with the context
+-- text they were resolved for, and handed to the agent as concrete paths.
+
+CREATE TABLE IF NOT EXISTS ticket_context_files (
+    ticket_id TEXT NOT NULL,
+    file_path TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    score INTEGER NOT NULL DEFAULT 0,
+    reasons TEXT NOT NULL DEFAULT '[]',
+    resolved_for TEXT NOT NULL,
+    resolved_at TEXT NOT NULL,
+    PRIMARY KEY (ticket_id, file_path),
+    FOREIGN KEY (ticket_id) REFERENCES tickets(id) ON DELETE CASCADE
+);
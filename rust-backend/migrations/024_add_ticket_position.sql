@@ -0,0 +1,17 @@
+-- Migration: Kanban position of tickets
+-- Date: 2026-10-17
+-- Description: Tickets are ordered by `position` within their project and status column
+-- (fractional, so a move only rewrites the moved ticket). Existing tickets keep the newest-first
+-- order the board showed so far.
+
+ALTER TABLE tickets ADD COLUMN position REAL NOT NULL DEFAULT 0;
+
+UPDATE tickets SET position = (
+    SELECT COUNT(*) FROM tickets AS newer
+    WHERE newer.project_id = tickets.project_id
+      AND newer.status = tickets.status
+      AND (newer.created_at > tickets.created_at
+           OR (newer.created_at = tickets.created_at AND newer.id < tickets.id))
+);
+
+CREATE INDEX IF NOT EXISTS idx_tickets_project_status_position ON tickets(project_id, status, position);
@@ -0,0 +1,29 @@
+-- Migration: Ticket watchers and activity
+-- Date: 2026-10-17
+-- Description: Users watch tickets (automatically when they create one, or manually), and what
+-- happens to a ticket is recorded once in ticket_activity. A user's feed is the activity of the
+-- tickets they watch since they started watching.
+
+CREATE TABLE IF NOT EXISTS ticket_watchers (
+    ticket_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    reason TEXT NOT NULL CHECK (reason IN ('creator', 'assignee', 'manual')),
+    created_at TEXT NOT NULL,
+    PRIMARY KEY (ticket_id, user_id),
+    FOREIGN KEY (ticket_id) REFERENCES tickets(id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_ticket_watchers_user_id ON ticket_watchers(user_id);
+
+CREATE TABLE IF NOT EXISTS ticket_activity (
+    id TEXT PRIMARY KEY,
+    ticket_id TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    actor_id TEXT,
+    summary TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (ticket_id) REFERENCES tickets(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_ticket_activity_ticket ON ticket_activity(ticket_id, created_at);
@@ -0,0 +1,8 @@
+-- Migration: Ticket assignees
+-- Date: 2026-10-17
+-- Description: A ticket can be assigned to one user of its organization. Removing the user
+-- leaves their tickets unassigned.
+
+ALTER TABLE tickets ADD COLUMN assignee_id TEXT REFERENCES users(id) ON DELETE SET NULL;
+
+CREATE INDEX IF NOT EXISTS idx_tickets_assignee ON tickets(assignee_id);
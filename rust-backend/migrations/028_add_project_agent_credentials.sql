@@ -0,0 +1,16 @@
+-- Migration: Per-project agent API keys
+-- Date: 2026-10-17
+-- Description: Projects can run agents with their own API key per provider (anthropic, gemini,
+-- cursor) instead of the server's. Keys are AES-256-GCM encrypted with AGENT_CREDENTIALS_KEY;
+-- masked_key keeps the last characters for listing.
+
+CREATE TABLE IF NOT EXISTS project_agent_credentials (
+    project_id TEXT NOT NULL,
+    provider TEXT NOT NULL CHECK (provider IN ('anthropic', 'gemini', 'cursor')),
+    ciphertext TEXT NOT NULL,
+    masked_key TEXT NOT NULL,
+    updated_by TEXT,
+    updated_at TEXT NOT NULL,
+    PRIMARY KEY (project_id, provider),
+    FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+);
@@ -0,0 +1,15 @@
+-- Migration: Analysis result summaries
+-- Date: 2026-10-17
+-- Description: A short TL;DR of the ticket's analysis result, written by a summarization pass
+-- after the run and cleared when a new result replaces it. Projects opt in, optionally with
+-- their own line count.
+
+ALTER TABLE tickets ADD COLUMN summary TEXT;
+
+CREATE TABLE IF NOT EXISTS project_summary_settings (
+    project_id TEXT PRIMARY KEY,
+    enabled INTEGER NOT NULL DEFAULT 0,
+    max_lines INTEGER,
+    updated_at TEXT NOT NULL,
+    FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+);
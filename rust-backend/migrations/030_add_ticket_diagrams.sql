@@ -0,0 +1,14 @@
+-- Migration: Ticket diagrams
+-- Date: 2026-10-17
+-- Description: The Mermaid diagram extracted from a ticket's last `diagram` run, with its
+-- rendered SVG cached once requested (when the server has the Mermaid CLI).
+
+CREATE TABLE IF NOT EXISTS ticket_diagrams (
+    ticket_id TEXT PRIMARY KEY,
+    run_id TEXT,
+    kind TEXT NOT NULL,
+    source TEXT NOT NULL,
+    svg TEXT,
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (ticket_id) REFERENCES tickets(id) ON DELETE CASCADE
+);
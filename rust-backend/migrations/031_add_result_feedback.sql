@@ -0,0 +1,22 @@
+-- Migration: Analysis result feedback
+-- Date: 2026-10-17
+-- Description: Thumbs up (1) or down (-1) plus an optional comment on a ticket's analysis
+-- result, tied to the session that produced it. A user rating the same result again replaces
+-- their earlier rating; anonymous ratings (auth disabled) are all kept.
+
+CREATE TABLE IF NOT EXISTS result_feedback (
+    id TEXT PRIMARY KEY,
+    ticket_id TEXT NOT NULL,
+    session_id TEXT NOT NULL,
+    user_id TEXT,
+    rating INTEGER NOT NULL CHECK(rating IN (-1, 1)),
+    comment TEXT,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    FOREIGN KEY (ticket_id) REFERENCES tickets(id) ON DELETE CASCADE,
+    FOREIGN KEY (session_id) REFERENCES analysis_sessions(id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE SET NULL
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_result_feedback_session_user ON result_feedback(session_id, user_id);
+CREATE INDEX IF NOT EXISTS idx_result_feedback_ticket ON result_feedback(ticket_id);
@@ -0,0 +1,37 @@
+-- Migration: Prompt A/B experiments
+-- Date: 2026-10-17
+-- Description: A project runs at most one experiment at a time; each new analysis of the project
+-- gets one of its prompt variants at random (by weight). Sessions record the experiment and
+-- variant they ran under, and the cost the agent reported, to compare variants.
+
+CREATE TABLE IF NOT EXISTS prompt_experiments (
+    id TEXT PRIMARY KEY,
+    project_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'running' CHECK (status IN ('running', 'stopped')),
+    created_by TEXT,
+    created_at TEXT NOT NULL,
+    stopped_at TEXT,
+    FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE,
+    FOREIGN KEY (created_by) REFERENCES users(id) ON DELETE SET NULL
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_prompt_experiments_running
+    ON prompt_experiments(project_id) WHERE status = 'running';
+
+CREATE TABLE IF NOT EXISTS prompt_variants (
+    id TEXT PRIMARY KEY,
+    experiment_id TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    template TEXT NOT NULL,
+    weight INTEGER NOT NULL DEFAULT 1 CHECK (weight > 0),
+    UNIQUE (experiment_id, name),
+    FOREIGN KEY (experiment_id) REFERENCES prompt_experiments(id) ON DELETE CASCADE
+);
+
+ALTER TABLE analysis_sessions ADD COLUMN experiment_id TEXT;
+ALTER TABLE analysis_sessions ADD COLUMN variant_id TEXT;
+ALTER TABLE analysis_sessions ADD COLUMN cost_usd REAL;
+
+CREATE INDEX IF NOT EXISTS idx_analysis_sessions_variant ON analysis_sessions(variant_id);
@@ -0,0 +1,38 @@
+-- Migration: Shared analysis queue
+-- Date: 2026-10-17
+-- Description: With `--role api` / `--role worker`, API processes enqueue analyses here and
+-- worker processes claim them (one atomic UPDATE per claim, so two workers never take the same
+-- job). Claims carry a heartbeat; a job whose worker stopped heartbeating is claimed again.
+-- stream_events carries the workers' log entries and broadcasts back to the API processes.
+
+CREATE TABLE IF NOT EXISTS analysis_jobs (
+    seq INTEGER PRIMARY KEY AUTOINCREMENT,
+    ticket_id TEXT NOT NULL,
+    run_id TEXT NOT NULL,
+    priority INTEGER NOT NULL,
+    request_json TEXT NOT NULL,
+    follow_ups_json TEXT NOT NULL DEFAULT '[]',
+    requested_by TEXT,
+    status TEXT NOT NULL DEFAULT 'queued' CHECK (status IN ('queued', 'claimed')),
+    worker_id TEXT,
+    cancel_requested INTEGER NOT NULL DEFAULT 0,
+    enqueued_at TEXT NOT NULL,
+    claimed_at TEXT,
+    heartbeat_at TEXT,
+    FOREIGN KEY (ticket_id) REFERENCES tickets(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_analysis_jobs_claim ON analysis_jobs(status, priority DESC, seq);
+CREATE INDEX IF NOT EXISTS idx_analysis_jobs_ticket ON analysis_jobs(ticket_id);
+
+CREATE TABLE IF NOT EXISTS stream_events (
+    seq INTEGER PRIMARY KEY AUTOINCREMENT,
+    origin TEXT NOT NULL,
+    kind TEXT NOT NULL CHECK (kind IN ('log', 'broadcast')),
+    ticket_id TEXT NOT NULL,
+    org_id TEXT,
+    payload TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_stream_events_created ON stream_events(created_at);
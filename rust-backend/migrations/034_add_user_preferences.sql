@@ -0,0 +1,16 @@
+-- Migration: User preferences
+-- Date: 2026-10-17
+-- Description: Per-user defaults applied when a request leaves them out: the agent to run,
+-- the language answers are written in, the timezone times are shown in and the project the
+-- CLI asks about. Email notifications stay in notification_preferences.
+
+CREATE TABLE IF NOT EXISTS user_preferences (
+    user_id TEXT PRIMARY KEY,
+    default_agent TEXT,
+    response_language TEXT,
+    timezone TEXT,
+    default_project_id TEXT,
+    updated_at TEXT NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY (default_project_id) REFERENCES projects(id) ON DELETE SET NULL
+);
@@ -0,0 +1,11 @@
+-- Migration: Per-project pre-flight checks
+-- Date: 2026-10-17
+-- Description: Checks run before an analysis spawns its agent (JSON array of git-clean, branch,
+-- path-exists and command checks). A failing check ends the run before any model is called.
+
+CREATE TABLE IF NOT EXISTS project_preflight_checks (
+    project_id TEXT PRIMARY KEY,
+    checks TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+);
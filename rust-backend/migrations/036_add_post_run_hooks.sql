@@ -0,0 +1,15 @@
+-- Migration: Per-project post-run hooks
+-- Date: 2026-10-17
+-- Description: Shell commands run after an analysis that could edit the project (JSON array),
+-- and whether a failing one moves the ticket back to in-progress. Each session records the
+-- exit codes of the hooks run after it.
+
+CREATE TABLE IF NOT EXISTS project_post_run_hooks (
+    project_id TEXT PRIMARY KEY,
+    hooks TEXT NOT NULL,
+    reopen_on_failure INTEGER NOT NULL DEFAULT 0,
+    updated_at TEXT NOT NULL,
+    FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+);
+
+ALTER TABLE analysis_sessions ADD COLUMN hook_results TEXT;
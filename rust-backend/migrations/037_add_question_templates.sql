@@ -0,0 +1,23 @@
+-- Migration: Question template library
+-- Date: 2026-10-17
+-- Description: Reusable questions grouped by category, either global to an organization
+-- (project_id NULL) or owned by one project, with how many tickets were created from each.
+
+CREATE TABLE IF NOT EXISTS question_templates (
+    id TEXT PRIMARY KEY,
+    org_id TEXT NOT NULL,
+    project_id TEXT,
+    category TEXT NOT NULL DEFAULT 'general',
+    title TEXT NOT NULL,
+    question TEXT NOT NULL,
+    mode TEXT NOT NULL DEFAULT 'ask',
+    code_context TEXT,
+    usage_count INTEGER NOT NULL DEFAULT 0,
+    created_by TEXT,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_question_templates_org ON question_templates(org_id, category);
+CREATE INDEX IF NOT EXISTS idx_question_templates_project ON question_templates(project_id);
@@ -0,0 +1,6 @@
+-- Migration: Output violations of analysis sessions
+-- Date: 2026-10-17
+-- Description: JSON list of the ways a session's output ignored its mode (files written by a
+-- read-only run, missing test cases or diagram), found when it completed.
+
+ALTER TABLE analysis_sessions ADD COLUMN output_violations TEXT;
@@ -0,0 +1,37 @@
+-- Migration: Log alert rules
+-- Date: 2026-10-17
+-- Description: Conditions on the log entries of a run (message type, content regex, how many
+-- matches) and what to do when they are met (JSON actions: webhook, email, notification),
+-- with one event per rule and run it fired for.
+
+CREATE TABLE IF NOT EXISTS alert_rules (
+    id TEXT PRIMARY KEY,
+    org_id TEXT NOT NULL,
+    project_id TEXT,
+    name TEXT NOT NULL,
+    message_type TEXT,
+    content_pattern TEXT,
+    threshold INTEGER NOT NULL DEFAULT 1,
+    actions TEXT NOT NULL,
+    enabled INTEGER NOT NULL DEFAULT 1,
+    created_by TEXT,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_alert_rules_org ON alert_rules(org_id);
+
+CREATE TABLE IF NOT EXISTS alert_events (
+    id TEXT PRIMARY KEY,
+    rule_id TEXT NOT NULL,
+    ticket_id TEXT NOT NULL,
+    run_id TEXT NOT NULL,
+    match_count INTEGER NOT NULL,
+    sample TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (rule_id) REFERENCES alert_rules(id) ON DELETE CASCADE,
+    UNIQUE (rule_id, run_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_alert_events_rule ON alert_events(rule_id, created_at);
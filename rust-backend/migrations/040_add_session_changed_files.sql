@@ -0,0 +1,6 @@
+-- Migration: Changed files of analysis sessions
+-- Date: 2026-10-17
+-- Description: JSON list of the files a session created, modified or deleted through its
+-- write/edit tool calls, with the change in size of each.
+
+ALTER TABLE analysis_sessions ADD COLUMN changed_files TEXT;
@@ -0,0 +1,28 @@
+-- Migration: Ticket status history
+-- Date: 2026-10-17
+-- Description: One row per status transition of a ticket, for time-in-status, cycle time and
+-- SLA checks. Backfilled from the status-changed entries of ticket_activity.
+
+CREATE TABLE IF NOT EXISTS ticket_status_history (
+    id TEXT PRIMARY KEY,
+    ticket_id TEXT NOT NULL,
+    from_status TEXT,
+    to_status TEXT NOT NULL,
+    actor_id TEXT,
+    changed_at TEXT NOT NULL,
+    FOREIGN KEY (ticket_id) REFERENCES tickets(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_ticket_status_history_ticket ON ticket_status_history(ticket_id, changed_at);
+
+-- Summaries read "Status changed from <from> to <to>"; "Status changed from " is 20 characters
+INSERT OR IGNORE INTO ticket_status_history (id, ticket_id, from_status, to_status, actor_id, changed_at)
+SELECT a.id,
+       a.ticket_id,
+       substr(a.summary, 21, instr(a.summary, ' to ') - 21),
+       substr(a.summary, instr(a.summary, ' to ') + 4),
+       a.actor_id,
+       a.created_at
+FROM ticket_activity a
+JOIN tickets t ON t.id = a.ticket_id
+WHERE a.kind = 'status-changed' AND a.summary LIKE 'Status changed from % to %';
@@ -0,0 +1,54 @@
+-- Migration: Accent-insensitive ticket search
+-- Date: 2026-10-17
+-- Description: FTS5 index over ticket titles, descriptions, summaries and results. The
+-- unicode61 tokenizer folds case and strips diacritics ("phân tích" matches "phan tich");
+-- "đ", a letter of its own to Unicode, is folded to "d" by the triggers, and by the queries
+-- (see `search::match_query`). Kept in sync with tickets by triggers.
+
+CREATE VIRTUAL TABLE IF NOT EXISTS ticket_search USING fts5(
+    ticket_id UNINDEXED,
+    title,
+    description,
+    summary,
+    analysis_result,
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+
+CREATE TRIGGER IF NOT EXISTS ticket_search_insert AFTER INSERT ON tickets BEGIN
+    INSERT INTO ticket_search (rowid, ticket_id, title, description, summary, analysis_result)
+    VALUES (
+        new.rowid,
+        new.id,
+        replace(replace(new.title, 'đ', 'd'), 'Đ', 'D'),
+        replace(replace(new.description, 'đ', 'd'), 'Đ', 'D'),
+        replace(replace(new.summary, 'đ', 'd'), 'Đ', 'D'),
+        replace(replace(new.analysis_result, 'đ', 'd'), 'Đ', 'D')
+    );
+END;
+
+CREATE TRIGGER IF NOT EXISTS ticket_search_update
+AFTER UPDATE OF title, description, summary, analysis_result ON tickets BEGIN
+    DELETE FROM ticket_search WHERE rowid = old.rowid;
+    INSERT INTO ticket_search (rowid, ticket_id, title, description, summary, analysis_result)
+    VALUES (
+        new.rowid,
+        new.id,
+        replace(replace(new.title, 'đ', 'd'), 'Đ', 'D'),
+        replace(replace(new.description, 'đ', 'd'), 'Đ', 'D'),
+        replace(replace(new.summary, 'đ', 'd'), 'Đ', 'D'),
+        replace(replace(new.analysis_result, 'đ', 'd'), 'Đ', 'D')
+    );
+END;
+
+CREATE TRIGGER IF NOT EXISTS ticket_search_delete AFTER DELETE ON tickets BEGIN
+    DELETE FROM ticket_search WHERE rowid = old.rowid;
+END;
+
+INSERT INTO ticket_search (rowid, ticket_id, title, description, summary, analysis_result)
+SELECT rowid,
+       id,
+       replace(replace(title, 'đ', 'd'), 'Đ', 'D'),
+       replace(replace(description, 'đ', 'd'), 'Đ', 'D'),
+       replace(replace(summary, 'đ', 'd'), 'Đ', 'D'),
+       replace(replace(analysis_result, 'đ', 'd'), 'Đ', 'D')
+FROM tickets;
@@ -0,0 +1,10 @@
+-- Migration: Activity feed read marker
+-- Date: 2026-10-17
+-- Description: When each user last read their activity feed; entries after it count as
+-- unread notifications (the WebSocket `hello` snapshot, `POST /api/me/activity/read`).
+
+CREATE TABLE IF NOT EXISTS activity_reads (
+    user_id TEXT PRIMARY KEY,
+    read_at TEXT NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
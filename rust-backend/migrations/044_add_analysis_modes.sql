@@ -0,0 +1,12 @@
+-- Migration: Ticket and session analysis modes
+-- Date: 2026-10-17
+-- Description: The mode a ticket is analyzed in unless a run overrides it, and the mode each
+-- session actually ran in. Sessions are backfilled from the request they recorded.
+
+ALTER TABLE tickets ADD COLUMN mode TEXT NOT NULL DEFAULT 'ask';
+
+ALTER TABLE analysis_sessions ADD COLUMN mode TEXT;
+
+UPDATE analysis_sessions
+SET mode = COALESCE(json_extract(request_json, '$.mode'), 'ask')
+WHERE request_json IS NOT NULL AND json_valid(request_json);
@@ -0,0 +1,17 @@
+-- Migration: Session transcripts
+-- Date: 2026-10-17
+-- Description: What a CLI agent run exchanged with its process, for debugging: the assembled
+-- prompt, argv, the names of the environment variables set for it, and its stdout/stderr as
+-- gzipped JSON lines, inline or in the blob store (`transcript_blob`) when it is large.
+
+CREATE TABLE IF NOT EXISTS session_transcripts (
+    session_id TEXT PRIMARY KEY,
+    prompt TEXT NOT NULL,
+    argv TEXT NOT NULL,
+    env_names TEXT NOT NULL,
+    transcript BLOB,
+    transcript_blob TEXT,
+    transcript_size INTEGER NOT NULL,
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (session_id) REFERENCES analysis_sessions(id) ON DELETE CASCADE
+);
@@ -0,0 +1,13 @@
+-- Migration: Per-project log persistence
+-- Date: 2026-10-17
+-- Description: Which log entry types a project's runs write to structured_logs (JSON array, NULL
+-- for all) and whether raw_log is kept only on errors. Projects without a row use the server's
+-- LOG_PERSIST_* settings; live broadcasts always carry every entry.
+
+CREATE TABLE IF NOT EXISTS project_log_persistence (
+    project_id TEXT PRIMARY KEY,
+    message_types TEXT,
+    raw_log_errors_only INTEGER NOT NULL DEFAULT 0,
+    updated_at TEXT NOT NULL,
+    FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+);
@@ -0,0 +1,35 @@
+-- Migration: Monthly budgets
+-- Date: 2026-10-17
+-- Description: Tokens the agent reported per session, monthly limits (USD or tokens) per project
+-- and per organization with how they are enforced, and the threshold warnings already sent for
+-- a budget's month so each goes out once.
+
+ALTER TABLE analysis_sessions ADD COLUMN tokens INTEGER;
+
+CREATE TABLE IF NOT EXISTS project_budgets (
+    project_id TEXT PRIMARY KEY,
+    metric TEXT NOT NULL,
+    monthly_limit REAL NOT NULL,
+    enforcement TEXT NOT NULL,
+    updated_by TEXT,
+    updated_at TEXT NOT NULL,
+    FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS org_budgets (
+    org_id TEXT PRIMARY KEY,
+    metric TEXT NOT NULL,
+    monthly_limit REAL NOT NULL,
+    enforcement TEXT NOT NULL,
+    updated_by TEXT,
+    updated_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS budget_warnings (
+    scope TEXT NOT NULL,
+    scope_id TEXT NOT NULL,
+    period TEXT NOT NULL,
+    threshold INTEGER NOT NULL,
+    created_at TEXT NOT NULL,
+    PRIMARY KEY (scope, scope_id, period, threshold)
+);
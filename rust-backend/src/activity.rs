@@ -0,0 +1,222 @@
+use crate::database::{ActivityRecord, Database, StatusChangeRecord, TicketWatcherRecord, UserRecord};
+use anyhow::Result;
+use chrono::Utc;
+use tracing::error;
+
+/// Characters of an analysis error quoted in a feed entry
+const SUMMARY_MAX_CHARS: usize = 200;
+
+/// Why a user watches a ticket
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchReason {
+    Creator,
+    Assignee,
+    Manual,
+}
+
+impl WatchReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WatchReason::Creator => "creator",
+            WatchReason::Assignee => "assignee",
+            WatchReason::Manual => "manual",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActivityKind {
+    StatusChanged,
+    Assigned,
+    AnalysisCompleted,
+    AnalysisFailed,
+}
+
+impl ActivityKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ActivityKind::StatusChanged => "status-changed",
+            ActivityKind::Assigned => "assigned",
+            ActivityKind::AnalysisCompleted => "analysis-completed",
+            ActivityKind::AnalysisFailed => "analysis-failed",
+        }
+    }
+}
+
+/// Make `user_id` a watcher of the ticket; false when they already were
+pub async fn watch(database: &Database, ticket_id: &str, user_id: &str, reason: WatchReason) -> Result<bool> {
+    database
+        .add_ticket_watcher(&TicketWatcherRecord {
+            ticket_id: ticket_id.to_string(),
+            user_id: user_id.to_string(),
+            reason: reason.as_str().to_string(),
+            created_at: Utc::now().to_rfc3339(),
+        })
+        .await
+}
+
+/// Watch a ticket its creator just made; anonymous callers have nobody to watch for
+pub async fn watch_created(database: &Database, ticket_id: &str, creator: Option<&str>) {
+    let Some(user_id) = creator else {
+        return;
+    };
+    if let Err(e) = watch(database, ticket_id, user_id, WatchReason::Creator).await {
+        error!("Failed to add creator {} as watcher of ticket {}: {}", user_id, ticket_id, e);
+    }
+}
+
+/// Add an entry to the ticket's activity; failures are logged, never passed on to the action
+/// being recorded
+pub async fn record(database: &Database, ticket_id: &str, kind: ActivityKind, actor_id: Option<&str>, summary: String) {
+    let activity = ActivityRecord {
+        id: uuid::Uuid::new_v4().to_string(),
+        ticket_id: ticket_id.to_string(),
+        kind: kind.as_str().to_string(),
+        actor_id: actor_id.map(str::to_string),
+        summary,
+        created_at: Utc::now().to_rfc3339(),
+    };
+    if let Err(e) = database.create_activity(&activity).await {
+        error!("Failed to record {} activity of ticket {}: {}", activity.kind, ticket_id, e);
+    }
+}
+
+/// Record a status change in the activity and in the ticket's status history
+pub async fn status_changed(database: &Database, ticket_id: &str, actor_id: Option<&str>, from: &str, to: &str) {
+    if from == to {
+        return;
+    }
+    let summary = format!("Status changed from {} to {}", from, to);
+    record(database, ticket_id, ActivityKind::StatusChanged, actor_id, summary).await;
+
+    let change = StatusChangeRecord {
+        id: uuid::Uuid::new_v4().to_string(),
+        ticket_id: ticket_id.to_string(),
+        from_status: Some(from.to_string()),
+        to_status: to.to_string(),
+        actor_id: actor_id.map(str::to_string),
+        changed_at: Utc::now().to_rfc3339(),
+    };
+    if let Err(e) = database.create_status_change(&change).await {
+        error!("Failed to record status change of ticket {}: {}", ticket_id, e);
+    }
+}
+
+/// Record a new assignee (None when unassigned), who starts watching the ticket
+pub async fn assigned(database: &Database, ticket_id: &str, actor_id: Option<&str>, assignee: Option<&UserRecord>) {
+    let summary = match assignee {
+        Some(user) => {
+            if let Err(e) = watch(database, ticket_id, &user.id, WatchReason::Assignee).await {
+                error!("Failed to add assignee {} as watcher of ticket {}: {}", user.id, ticket_id, e);
+            }
+            format!("Assigned to {}", user.name)
+        }
+        None => "Unassigned".to_string(),
+    };
+    record(database, ticket_id, ActivityKind::Assigned, actor_id, summary).await;
+}
+
+/// Record how a run ended; runs have no actor so everyone watching sees them
+pub async fn analysis_finished(database: &Database, ticket_id: &str, error: Option<&str>) {
+    match error {
+        None => {
+            record(database, ticket_id, ActivityKind::AnalysisCompleted, None, "Analysis completed".to_string()).await
+        }
+        Some(error) => {
+            let error: String = error.chars().take(SUMMARY_MAX_CHARS).collect();
+            let summary = format!("Analysis failed: {}", error);
+            record(database, ticket_id, ActivityKind::AnalysisFailed, None, summary).await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{TicketRecord, UserRecord, DEFAULT_ORG_ID};
+    use crate::test_support::{project_fixture, test_db, ticket_fixture};
+
+    async fn setup() -> Database {
+        let db = test_db().await.unwrap();
+        db.create_project(&project_fixture("p1")).await.unwrap();
+        db.create_ticket(&TicketRecord {
+            title: "Login flow".to_string(),
+            ..ticket_fixture("t1", "p1")
+        })
+        .await
+        .unwrap();
+        let now = Utc::now().to_rfc3339();
+        for id in ["alice", "bob"] {
+            db.create_user(&UserRecord {
+                id: id.to_string(),
+                org_id: DEFAULT_ORG_ID.to_string(),
+                email: format!("{}@example.com", id),
+                name: id.to_string(),
+                password_hash: None,
+                role: "member".to_string(),
+                created_at: now.clone(),
+            })
+            .await
+            .unwrap();
+        }
+        db
+    }
+
+    #[tokio::test]
+    async fn test_feed_shows_watched_activity_of_others() {
+        let db = setup().await;
+        watch_created(&db, "t1", Some("alice")).await;
+        assert!(watch(&db, "t1", "bob", WatchReason::Manual).await.unwrap());
+        // Watching again keeps the first reason
+        assert!(!watch(&db, "t1", "alice", WatchReason::Manual).await.unwrap());
+        assert_eq!(db.list_ticket_watchers("t1").await.unwrap()[0].reason, "creator");
+
+        status_changed(&db, "t1", Some("bob"), "todo", "in-progress").await;
+        status_changed(&db, "t1", Some("bob"), "done", "done").await;
+        analysis_finished(&db, "t1", None).await;
+
+        let alice = db.list_user_feed("alice", DEFAULT_ORG_ID, None, 50).await.unwrap();
+        let kinds: Vec<_> = alice.iter().map(|e| e.kind.as_str()).collect();
+        assert_eq!(kinds, vec!["analysis-completed", "status-changed"]);
+        assert_eq!(alice[1].summary, "Status changed from todo to in-progress");
+        assert_eq!(alice[1].ticket_title, "Login flow");
+
+        // Bob's own status change stays out of his feed
+        let bob = db.list_user_feed("bob", DEFAULT_ORG_ID, None, 50).await.unwrap();
+        assert_eq!(bob.len(), 1);
+
+        let older = db
+            .list_user_feed("alice", DEFAULT_ORG_ID, Some(&alice[0].created_at), 50)
+            .await
+            .unwrap();
+        assert!(older.iter().all(|e| e.created_at < alice[0].created_at));
+
+        assert!(db.remove_ticket_watcher("t1", "alice").await.unwrap());
+        assert!(db.list_user_feed("alice", DEFAULT_ORG_ID, None, 50).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_assignee_watches_and_lists_ticket() {
+        let db = setup().await;
+        let bob = db.get_user("bob").await.unwrap().unwrap();
+        db.set_ticket_assignee("t1", Some("bob")).await.unwrap();
+        assigned(&db, "t1", Some("alice"), Some(&bob)).await;
+
+        let watchers = db.list_ticket_watchers("t1").await.unwrap();
+        assert_eq!(watchers.len(), 1);
+        assert_eq!(watchers[0].reason, "assignee");
+        let feed = db.list_user_feed("bob", DEFAULT_ORG_ID, None, 50).await.unwrap();
+        assert_eq!(feed[0].summary, "Assigned to bob");
+
+        assert_eq!(db.get_ticket("t1").await.unwrap().unwrap().assignee_id.as_deref(), Some("bob"));
+        assert_eq!(db.list_tickets_by_assignee("p1", Some("bob")).await.unwrap().len(), 1);
+        assert!(db.list_tickets_by_assignee("p1", None).await.unwrap().is_empty());
+        assert_eq!(db.list_user_tickets("bob", DEFAULT_ORG_ID, None).await.unwrap().len(), 1);
+        assert!(db.list_user_tickets("bob", DEFAULT_ORG_ID, Some("done")).await.unwrap().is_empty());
+        assert!(db.list_user_tickets("bob", "other-org", None).await.unwrap().is_empty());
+
+        db.set_ticket_assignee("t1", None).await.unwrap();
+        assert_eq!(db.list_tickets_by_assignee("p1", None).await.unwrap().len(), 1);
+        assert!(db.list_user_tickets("bob", DEFAULT_ORG_ID, None).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,319 @@
+//! Per-project agent API keys, encrypted at rest
+//!
+//! Keys are sealed with AES-256-GCM under `AGENT_CREDENTIALS_KEY` (64 hex characters), or the
+//! key read from `AGENT_CREDENTIALS_KEY_FILE` when a secret manager or KMS mounts it as a file.
+//! Each row is bound to its project and provider, so a ciphertext copied to another row won't
+//! decrypt. Only the last characters of a key are ever returned by the API.
+
+use crate::agent_factory::AgentType;
+use crate::database::{Database, ProjectCredentialRecord};
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use anyhow::{anyhow, bail, Context, Result};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use tracing::{error, warn};
+
+/// Version prefix of stored ciphertexts, leaving room for another scheme
+const FORMAT_V1: &str = "v1";
+const NONCE_BYTES: usize = 12;
+/// Characters of a key shown when it's listed
+const HINT_CHARS: usize = 4;
+
+/// Who issued a key; agents of the same vendor share one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Provider {
+    /// Claude Code CLI and the Anthropic API agent
+    Anthropic,
+    /// Gemini CLI and the Gemini API agent
+    Gemini,
+    Cursor,
+}
+
+impl Provider {
+    pub const ALL: [Provider; 3] = [Self::Anthropic, Self::Gemini, Self::Cursor];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Anthropic => "anthropic",
+            Self::Gemini => "gemini",
+            Self::Cursor => "cursor",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|provider| provider.as_str() == s)
+    }
+
+    /// Provider whose key the agent runs with; None for agents without one (Ollama, mock)
+    pub fn for_agent(agent_type: AgentType) -> Option<Self> {
+        match agent_type {
+            AgentType::Claude | AgentType::ClaudeApi => Some(Self::Anthropic),
+            AgentType::Gemini | AgentType::GeminiApi => Some(Self::Gemini),
+            AgentType::Cursor => Some(Self::Cursor),
+            AgentType::Ollama | AgentType::Mock => None,
+        }
+    }
+
+    /// Agents (`GET /api/agents` ids) using this provider's key
+    pub fn agents(&self) -> Vec<&'static str> {
+        AgentType::ALL
+            .into_iter()
+            .filter(|agent_type| Self::for_agent(*agent_type) == Some(*self))
+            .map(|agent_type| agent_type.id())
+            .collect()
+    }
+}
+
+/// A decrypted API key; kept out of `Debug` output and never serialized
+#[derive(Clone, PartialEq, Eq)]
+pub struct ApiKey(String);
+
+impl ApiKey {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for ApiKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ApiKey({})", mask(&self.0))
+    }
+}
+
+/// `••••` followed by the key's last characters; keys too short to hint at stay fully hidden
+pub fn mask(key: &str) -> String {
+    let chars: Vec<char> = key.chars().collect();
+    if chars.len() <= HINT_CHARS * 2 {
+        return "••••".to_string();
+    }
+    format!("••••{}", chars[chars.len() - HINT_CHARS..].iter().collect::<String>())
+}
+
+/// A stored credential as the API lists it
+#[derive(Debug, Clone, Serialize)]
+pub struct MaskedCredential {
+    pub provider: Provider,
+    pub masked_key: String,
+    pub agents: Vec<&'static str>,
+    pub updated_by: Option<String>,
+    pub updated_at: String,
+}
+
+impl MaskedCredential {
+    pub fn from_record(record: &ProjectCredentialRecord) -> Option<Self> {
+        let provider = Provider::from_str(&record.provider)?;
+        Some(Self {
+            provider,
+            masked_key: record.masked_key.clone(),
+            agents: provider.agents(),
+            updated_by: record.updated_by.clone(),
+            updated_at: record.updated_at.clone(),
+        })
+    }
+}
+
+/// Seals and opens stored keys with the server's master key
+pub struct CredentialCipher {
+    cipher: Aes256Gcm,
+}
+
+impl CredentialCipher {
+    pub fn new(key: &[u8; 32]) -> Self {
+        Self {
+            cipher: Aes256Gcm::new(key.into()),
+        }
+    }
+
+    /// None when no master key is configured, which disables per-project keys
+    pub fn from_env() -> Option<Result<Self>> {
+        let (source, hex_key) = if let Ok(key) = std::env::var("AGENT_CREDENTIALS_KEY") {
+            ("AGENT_CREDENTIALS_KEY", key)
+        } else {
+            let path = std::env::var("AGENT_CREDENTIALS_KEY_FILE").ok()?;
+            match std::fs::read_to_string(&path) {
+                Ok(key) => ("AGENT_CREDENTIALS_KEY_FILE", key),
+                Err(e) => return Some(Err(anyhow!("Cannot read {}: {}", path, e))),
+            }
+        };
+        if hex_key.trim().is_empty() {
+            return None;
+        }
+        Some(Self::parse_key(hex_key.trim()).with_context(|| format!("Invalid {}", source)).map(|key| Self::new(&key)))
+    }
+
+    fn parse_key(hex_key: &str) -> Result<[u8; 32]> {
+        let bytes = hex::decode(hex_key).context("expected 64 hex characters")?;
+        bytes
+            .try_into()
+            .map_err(|bytes: Vec<u8>| anyhow!("expected a 32 byte key, got {} bytes", bytes.len()))
+    }
+
+    /// Associated data tying a ciphertext to its row
+    fn aad(project_id: &str, provider: Provider) -> String {
+        format!("{}:{}", project_id, provider.as_str())
+    }
+
+    /// `v1:<hex nonce>:<hex ciphertext>` of the key
+    pub fn encrypt(&self, project_id: &str, provider: Provider, key: &str) -> Result<String> {
+        let mut nonce = [0u8; NONCE_BYTES];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let aad = Self::aad(project_id, provider);
+        let sealed = self
+            .cipher
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: key.as_bytes(), aad: aad.as_bytes() })
+            .map_err(|_| anyhow!("Encryption failed"))?;
+
+        Ok(format!("{}:{}:{}", FORMAT_V1, hex::encode(nonce), hex::encode(sealed)))
+    }
+
+    pub fn decrypt(&self, project_id: &str, provider: Provider, stored: &str) -> Result<ApiKey> {
+        let mut parts = stored.splitn(3, ':');
+        let (Some(FORMAT_V1), Some(nonce), Some(sealed)) = (parts.next(), parts.next(), parts.next()) else {
+            bail!("Unknown credential format");
+        };
+        let nonce = hex::decode(nonce).context("Invalid nonce")?;
+        if nonce.len() != NONCE_BYTES {
+            bail!("Invalid nonce");
+        }
+        let sealed = hex::decode(sealed).context("Invalid ciphertext")?;
+        let aad = Self::aad(project_id, provider);
+        let key = self
+            .cipher
+            .decrypt(Nonce::from_slice(&nonce), Payload { msg: &sealed, aad: aad.as_bytes() })
+            .map_err(|_| anyhow!("Credential does not decrypt with the configured key"))?;
+
+        Ok(ApiKey(String::from_utf8(key).context("Decrypted key is not UTF-8")?))
+    }
+
+    /// Encrypt and store a project's key, replacing any previous one
+    pub async fn store(
+        &self,
+        database: &Database,
+        project_id: &str,
+        provider: Provider,
+        key: &str,
+        updated_by: Option<&str>,
+    ) -> Result<ProjectCredentialRecord> {
+        let record = ProjectCredentialRecord {
+            project_id: project_id.to_string(),
+            provider: provider.as_str().to_string(),
+            ciphertext: self.encrypt(project_id, provider, key)?,
+            masked_key: mask(key),
+            updated_by: updated_by.map(str::to_string),
+            updated_at: chrono::Utc::now().to_rfc3339(),
+        };
+        database.upsert_project_credential(&record).await?;
+        Ok(record)
+    }
+}
+
+/// The project's own key for an agent, when it has one. Failures are logged and the run falls
+/// back to the server's key.
+pub async fn project_key(
+    database: &Database,
+    cipher: Option<&CredentialCipher>,
+    project_id: &str,
+    agent_type: AgentType,
+) -> Option<ApiKey> {
+    provider_key(database, cipher, project_id, Provider::for_agent(agent_type)?).await
+}
+
+/// The project's own key of a provider, as `project_key`
+pub async fn provider_key(
+    database: &Database,
+    cipher: Option<&CredentialCipher>,
+    project_id: &str,
+    provider: Provider,
+) -> Option<ApiKey> {
+    let record = match database.get_project_credential(project_id, provider.as_str()).await {
+        Ok(record) => record?,
+        Err(e) => {
+            error!("Failed to load {} credential of project {}: {}", provider.as_str(), project_id, e);
+            return None;
+        }
+    };
+    let Some(cipher) = cipher else {
+        warn!(
+            "Project {} has a {} key but AGENT_CREDENTIALS_KEY is not set; using the server key",
+            project_id,
+            provider.as_str()
+        );
+        return None;
+    };
+    match cipher.decrypt(project_id, provider, &record.ciphertext) {
+        Ok(key) => Some(key),
+        Err(e) => {
+            error!("Failed to decrypt {} credential of project {}: {}", provider.as_str(), project_id, e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{project_fixture, test_db};
+
+    const SECRET: &str = "sk-ant-REDACTED";
+
+    fn cipher(byte: u8) -> CredentialCipher {
+        CredentialCipher::new(&[byte; 32])
+    }
+
+    #[test]
+    fn test_encrypt_roundtrip_bound_to_row() {
+        let cipher = cipher(7);
+        let stored = cipher.encrypt("p1", Provider::Anthropic, SECRET).unwrap();
+        assert!(stored.starts_with("v1:"));
+        assert!(!stored.contains("wxyz"));
+        // A fresh nonce every time
+        assert_ne!(stored, cipher.encrypt("p1", Provider::Anthropic, SECRET).unwrap());
+
+        assert_eq!(cipher.decrypt("p1", Provider::Anthropic, &stored).unwrap().expose(), SECRET);
+        assert!(cipher.decrypt("p2", Provider::Anthropic, &stored).is_err());
+        assert!(cipher.decrypt("p1", Provider::Gemini, &stored).is_err());
+        assert!(self::cipher(8).decrypt("p1", Provider::Anthropic, &stored).is_err());
+    }
+
+    #[test]
+    fn test_mask_and_parse_key() {
+        assert_eq!(mask(SECRET), "••••wxyz");
+        assert_eq!(mask("short"), "••••");
+        assert_eq!(format!("{:?}", ApiKey(SECRET.to_string())), "ApiKey(••••wxyz)");
+
+        assert!(CredentialCipher::parse_key(&"ab".repeat(32)).is_ok());
+        assert!(CredentialCipher::parse_key(&"ab".repeat(16)).is_err());
+        assert!(CredentialCipher::parse_key("not hex").is_err());
+
+        assert_eq!(Provider::for_agent(AgentType::ClaudeApi), Some(Provider::Anthropic));
+        assert_eq!(Provider::Gemini.agents(), vec!["gemini", "gemini-api"]);
+        assert_eq!(Provider::for_agent(AgentType::Ollama), None);
+    }
+
+    #[tokio::test]
+    async fn test_project_key_lookup() {
+        let db = test_db().await.unwrap();
+        db.create_project(&project_fixture("p1")).await.unwrap();
+
+        let cipher = cipher(7);
+        let record = cipher.store(&db, "p1", Provider::Anthropic, SECRET, Some("alice")).await.unwrap();
+        assert_eq!(record.masked_key, "••••wxyz");
+
+        let key = project_key(&db, Some(&cipher), "p1", AgentType::Claude).await;
+        assert_eq!(key.unwrap().expose(), SECRET);
+        assert!(project_key(&db, Some(&cipher), "p1", AgentType::Gemini).await.is_none());
+        assert!(project_key(&db, None, "p1", AgentType::Claude).await.is_none());
+
+        // Replacing keeps one row per provider
+        cipher.store(&db, "p1", Provider::Anthropic, "sk-ant-second-key-1234", None).await.unwrap();
+        let listed = db.list_project_credentials("p1").await.unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].masked_key, "••••1234");
+
+        assert!(db.delete_project_credential("p1", "anthropic").await.unwrap());
+        assert!(project_key(&db, Some(&cipher), "p1", AgentType::ClaudeApi).await.is_none());
+    }
+}
@@ -1,25 +1,72 @@
+use crate::api_agent::{ApiAgent, ApiAgentConfig, ApiProvider};
 use crate::claude_agent::{ClaudeAgent, ClaudeAgentConfig};
-use crate::code_agent::CodeAgent;
+use crate::code_agent::{AnalysisMode, CodeAgent};
+use crate::container::ContainerConfig;
 use crate::cursor_agent::{CursorAgent, CursorAgentConfig};
 use crate::gemini_agent::{GeminiAgent, GeminiAgentConfig};
-use std::sync::Arc;
+use crate::mock_agent::{MockAgent, MockAgentConfig};
+use crate::ollama_agent::{OllamaAgent, OllamaAgentConfig};
+use serde::Serialize;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 use tracing::{info, warn, debug};
 
 /// Type of code analysis agent
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum AgentType {
     Claude,
     Gemini,
     Cursor,
+    Ollama,
+    /// Anthropic Messages API, no CLI required
+    ClaudeApi,
+    /// Gemini REST API, no CLI required
+    GeminiApi,
+    /// Canned runs for demos and load tests, no model at all
+    Mock,
 }
 
 impl AgentType {
+    pub const ALL: [AgentType; 7] = [
+        Self::Claude,
+        Self::Gemini,
+        Self::Cursor,
+        Self::Ollama,
+        Self::ClaudeApi,
+        Self::GeminiApi,
+        Self::Mock,
+    ];
+
+    /// Stable identifier, as accepted by `AGENT_TYPE` and `from_str`
+    pub fn id(&self) -> &'static str {
+        match self {
+            Self::Claude => "claude",
+            Self::Gemini => "gemini",
+            Self::Cursor => "cursor",
+            Self::Ollama => "ollama",
+            Self::ClaudeApi => "claude-api",
+            Self::GeminiApi => "gemini-api",
+            Self::Mock => "mock",
+        }
+    }
+
+    /// Analysis modes this agent can run
+    pub fn supported_modes(&self) -> &'static [AnalysisMode] {
+        // Modes are prompt templates (`CodeAnalysisRequest::prompt_question`), so every agent can run them
+        &[AnalysisMode::Ask, AnalysisMode::TestCases, AnalysisMode::Diagram]
+    }
+
     /// Parse agent type from string
     pub fn from_str(s: &str) -> Option<Self> {
         match s.to_lowercase().as_str() {
             "claude" => Some(Self::Claude),
             "gemini" => Some(Self::Gemini),
             "cursor" => Some(Self::Cursor),
+            "ollama" => Some(Self::Ollama),
+            "claude-api" | "anthropic" => Some(Self::ClaudeApi),
+            "gemini-api" => Some(Self::GeminiApi),
+            "mock" => Some(Self::Mock),
             _ => None,
         }
     }
@@ -30,6 +77,10 @@ impl AgentType {
             Self::Claude => "Claude Code",
             Self::Gemini => "Gemini CLI",
             Self::Cursor => "Cursor Agent",
+            Self::Ollama => "Ollama",
+            Self::ClaudeApi => "Anthropic API",
+            Self::GeminiApi => "Gemini API",
+            Self::Mock => "Mock (demo)",
         }
     }
 }
@@ -42,7 +93,7 @@ pub fn create_agent(agent_type: AgentType) -> Arc<dyn CodeAgent> {
             info!("🔧 Creating Claude Code agent");
             info!("  - Executable: {}", config.executable_path);
             info!("  - Timeout: {}s", config.timeout_seconds);
-            info!("  - Retries: {}", config.max_retries);
+            info!("  - Retries: {}", config.retry.describe());
             info!("  - Output format: {:?}", config.output_format);
             if config.api_key.is_some() {
                 info!("  - API key: [SET]");
@@ -54,7 +105,7 @@ pub fn create_agent(agent_type: AgentType) -> Arc<dyn CodeAgent> {
             info!("🔧 Creating Gemini CLI agent");
             info!("  - Executable: {}", config.executable_path);
             info!("  - Timeout: {}s", config.timeout_seconds);
-            info!("  - Retries: {}", config.max_retries);
+            info!("  - Retries: {}", config.retry.describe());
             info!("  - Output format: {:?}", config.output_format);
             if config.api_key.is_some() {
                 info!("  - API key: [SET]");
@@ -66,22 +117,60 @@ pub fn create_agent(agent_type: AgentType) -> Arc<dyn CodeAgent> {
             info!("🔧 Creating Cursor Agent");
             info!("  - Executable: {}", config.executable_path);
             info!("  - Timeout: {}s", config.timeout_seconds);
-            info!("  - Retries: {}", config.max_retries);
+            info!("  - Retries: {}", config.retry.describe());
             info!("  - Output format: {:?}", config.output_format);
             if config.api_key.is_some() {
                 info!("  - API key: [SET]");
             }
             Arc::new(CursorAgent::with_config(config))
         }
+        AgentType::Ollama => {
+            let config = OllamaAgentConfig::from_env();
+            info!("🔧 Creating Ollama agent");
+            info!("  - Endpoint: {}", config.endpoint);
+            info!("  - Model: {}", config.model);
+            info!("  - Timeout: {}s", config.timeout_seconds);
+            info!("  - Retries: {}", config.retry.describe());
+            Arc::new(OllamaAgent::with_config(config))
+        }
+        AgentType::ClaudeApi | AgentType::GeminiApi => {
+            let provider = match agent_type {
+                AgentType::ClaudeApi => ApiProvider::Anthropic,
+                _ => ApiProvider::Gemini,
+            };
+            let config = ApiAgentConfig::from_env(provider);
+            info!("🔧 Creating {} agent", agent_type.name());
+            info!("  - Model: {}", config.model);
+            info!("  - Endpoint: {}", config.base_url);
+            info!("  - Timeout: {}s", config.timeout_seconds);
+            info!("  - Max tool turns: {}", config.max_turns);
+            info!("  - Retries: {}", config.retry.describe());
+            if config.api_key.is_some() {
+                info!("  - API key: [SET]");
+            } else {
+                warn!("⚠️ {} API key chưa được cấu hình", agent_type.name());
+            }
+            Arc::new(ApiAgent::with_config(config))
+        }
+        AgentType::Mock => {
+            let config = MockAgentConfig::from_env();
+            info!("🔧 Creating Mock agent (canned runs, no model)");
+            match &config.fixtures_dir {
+                Some(dir) => info!("  - Fixtures: {}", dir.display()),
+                None => info!("  - Fixtures: built-in"),
+            }
+            info!("  - Delay: {}ms (jitter {})", config.delay_ms, config.jitter);
+            Arc::new(MockAgent::with_config(config))
+        }
     }
 }
 
-/// Create a code agent from environment variables
+/// Default agent type from environment variables
 ///
-/// Reads the `AGENT_TYPE` environment variable to determine which agent to create.
+/// Reads the `AGENT_TYPE` environment variable to determine which agent to use.
 /// **Default: Claude** - If `AGENT_TYPE` is not set, empty, or has an invalid value,
 /// the system will automatically use Claude Code Agent as the default.
-pub fn create_agent_from_env() -> Arc<dyn CodeAgent> {
+pub fn agent_type_from_env() -> AgentType {
     // Read AGENT_TYPE from environment
     let agent_type_env = std::env::var("AGENT_TYPE").ok();
     
@@ -123,7 +212,292 @@ pub fn create_agent_from_env() -> Arc<dyn CodeAgent> {
 
     info!("🤖 Selected code analysis agent: {}", agent_type.name());
 
-    create_agent(agent_type)
+    agent_type
+}
+
+/// How an agent authenticates with its model provider
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthStatus {
+    /// An API key is configured
+    ApiKey,
+    /// No key configured; relies on the CLI's own login, which can't be checked here
+    CliLogin,
+    /// Local model, no credentials needed
+    NotRequired,
+    /// The agent needs an API key that is not configured
+    Missing,
+}
+
+/// Description of one agent, as returned by `GET /api/agents`
+#[derive(Debug, Clone, Serialize)]
+pub struct AgentInfo {
+    pub id: &'static str,
+    pub name: &'static str,
+    pub modes: Vec<AnalysisMode>,
+    /// Executable found (CLIs) or credentials present (API agents)
+    pub available: bool,
+    pub auth_status: AuthStatus,
+    pub default_timeout_seconds: u64,
+    /// Model name for agents that call a model directly
+    pub model: Option<String>,
+    /// Agent used when a request does not pick one (`AGENT_TYPE`)
+    pub is_default: bool,
+}
+
+impl AgentInfo {
+    /// Describe an agent from its environment configuration
+    pub fn from_env(agent_type: AgentType, is_default: bool) -> Self {
+        let cli_auth = |api_key: &Option<String>| match api_key {
+            Some(_) => AuthStatus::ApiKey,
+            None => AuthStatus::CliLogin,
+        };
+
+        let (available, auth_status, default_timeout_seconds, model) = match agent_type {
+            AgentType::Claude => {
+                let config = ClaudeAgentConfig::from_env();
+                let available = config.container.is_some() || executable_available(&config.executable_path);
+                (available, cli_auth(&config.api_key), config.timeout_seconds, None)
+            }
+            AgentType::Gemini => {
+                let config = GeminiAgentConfig::from_env();
+                let available = config.container.is_some() || executable_available(&config.executable_path);
+                (available, cli_auth(&config.api_key), config.timeout_seconds, None)
+            }
+            AgentType::Cursor => {
+                let config = CursorAgentConfig::from_env();
+                let available = config.container.is_some() || executable_available(&config.executable_path);
+                (available, cli_auth(&config.api_key), config.timeout_seconds, None)
+            }
+            AgentType::Ollama => {
+                let config = OllamaAgentConfig::from_env();
+                (true, AuthStatus::NotRequired, config.timeout_seconds, Some(config.model))
+            }
+            AgentType::ClaudeApi | AgentType::GeminiApi => {
+                let provider = match agent_type {
+                    AgentType::ClaudeApi => ApiProvider::Anthropic,
+                    _ => ApiProvider::Gemini,
+                };
+                let config = ApiAgentConfig::from_env(provider);
+                let auth_status = match config.api_key {
+                    Some(_) => AuthStatus::ApiKey,
+                    None => AuthStatus::Missing,
+                };
+                (auth_status == AuthStatus::ApiKey, auth_status, config.timeout_seconds, Some(config.model))
+            }
+            AgentType::Mock => {
+                let config = MockAgentConfig::from_env();
+                (true, AuthStatus::NotRequired, config.timeout_seconds, None)
+            }
+        };
+
+        Self {
+            id: agent_type.id(),
+            name: agent_type.name(),
+            modes: agent_type.supported_modes().to_vec(),
+            available,
+            auth_status,
+            default_timeout_seconds,
+            model,
+            is_default,
+        }
+    }
+}
+
+/// How an agent is configured to run, recorded on each session (see `run_environment`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgentSettings {
+    /// CLI to ask for `--version`; None for agents that call a model directly
+    pub executable: Option<String>,
+    /// Configured model, for agents that pick one
+    pub model: Option<String>,
+    /// Settings that shape the answer, without credentials
+    pub config: serde_json::Value,
+}
+
+impl AgentSettings {
+    pub fn from_env(agent_type: AgentType) -> Self {
+        let cli = |executable: String, container: Option<ContainerConfig>, mut config: serde_json::Value| match container {
+            // The host's `--version` says nothing about the CLI inside the image
+            Some(container) => {
+                config["container_image"] = json!(container.image);
+                Self {
+                    executable: None,
+                    model: None,
+                    config,
+                }
+            }
+            None => Self {
+                executable: Some(executable),
+                model: None,
+                config,
+            },
+        };
+
+        match agent_type {
+            AgentType::Claude => {
+                let config = ClaudeAgentConfig::from_env();
+                cli(
+                    config.executable_path,
+                    config.container,
+                    json!({
+                        "timeout_seconds": config.timeout_seconds,
+                        "retry": config.retry.describe(),
+                        "output_format": format!("{:?}", config.output_format),
+                        "interactive": config.interactive,
+                    }),
+                )
+            }
+            AgentType::Gemini => {
+                let config = GeminiAgentConfig::from_env();
+                cli(
+                    config.executable_path,
+                    config.container,
+                    json!({
+                        "timeout_seconds": config.timeout_seconds,
+                        "retry": config.retry.describe(),
+                        "output_format": format!("{:?}", config.output_format),
+                    }),
+                )
+            }
+            AgentType::Cursor => {
+                let config = CursorAgentConfig::from_env();
+                cli(
+                    config.executable_path,
+                    config.container,
+                    json!({
+                        "timeout_seconds": config.timeout_seconds,
+                        "retry": config.retry.describe(),
+                        "output_format": format!("{:?}", config.output_format),
+                        "interactive": config.interactive,
+                    }),
+                )
+            }
+            AgentType::Ollama => {
+                let config = OllamaAgentConfig::from_env();
+                Self {
+                    executable: None,
+                    model: Some(config.model),
+                    config: json!({
+                        "endpoint": config.endpoint,
+                        "timeout_seconds": config.timeout_seconds,
+                        "retry": config.retry.describe(),
+                    }),
+                }
+            }
+            AgentType::ClaudeApi | AgentType::GeminiApi => {
+                let provider = match agent_type {
+                    AgentType::ClaudeApi => ApiProvider::Anthropic,
+                    _ => ApiProvider::Gemini,
+                };
+                let config = ApiAgentConfig::from_env(provider);
+                Self {
+                    executable: None,
+                    model: Some(config.model),
+                    config: json!({
+                        "base_url": config.base_url,
+                        "timeout_seconds": config.timeout_seconds,
+                        "retry": config.retry.describe(),
+                        "max_turns": config.max_turns,
+                        "max_output_tokens": config.max_output_tokens,
+                    }),
+                }
+            }
+            AgentType::Mock => {
+                let config = MockAgentConfig::from_env();
+                Self {
+                    executable: None,
+                    model: None,
+                    config: json!({
+                        "fixtures_dir": config.fixtures_dir,
+                        "delay_ms": config.delay_ms,
+                        "jitter": config.jitter,
+                    }),
+                }
+            }
+        }
+    }
+}
+
+/// Whether `path` is an existing file, or a command found on `PATH`
+fn executable_available(path: &str) -> bool {
+    if path.contains('/') || path.contains('\\') {
+        return std::path::Path::new(path).is_file();
+    }
+    std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).any(|dir| dir.join(path).is_file()))
+        .unwrap_or(false)
+}
+
+/// All agents this server can run, with the default one pre-created
+///
+/// Non-default agents are created on first use and cached.
+pub struct AgentRegistry {
+    default_type: AgentType,
+    agents: Mutex<HashMap<AgentType, Arc<dyn CodeAgent>>>,
+    infos: Vec<AgentInfo>,
+}
+
+impl AgentRegistry {
+    pub fn from_env() -> Self {
+        let default_type = agent_type_from_env();
+        let mut agents = HashMap::new();
+        agents.insert(default_type, create_agent(default_type));
+
+        let infos = AgentType::ALL
+            .iter()
+            .map(|agent_type| AgentInfo::from_env(*agent_type, *agent_type == default_type))
+            .collect();
+
+        Self {
+            default_type,
+            agents: Mutex::new(agents),
+            infos,
+        }
+    }
+
+    /// Registry whose default agent is `agent`, e.g. a scripted one in tests
+    pub fn with_agent(default_type: AgentType, agent: Arc<dyn CodeAgent>) -> Self {
+        let infos = AgentType::ALL
+            .iter()
+            .map(|agent_type| AgentInfo::from_env(*agent_type, *agent_type == default_type))
+            .collect();
+
+        Self {
+            default_type,
+            agents: Mutex::new(HashMap::from([(default_type, agent)])),
+            infos,
+        }
+    }
+
+    /// Agent used when a request does not pick one
+    pub fn default_type(&self) -> AgentType {
+        self.default_type
+    }
+
+    pub fn list(&self) -> &[AgentInfo] {
+        &self.infos
+    }
+
+    pub fn get(&self, agent_type: AgentType) -> Arc<dyn CodeAgent> {
+        let mut agents = self.agents.lock().unwrap_or_else(|e| e.into_inner());
+        agents
+            .entry(agent_type)
+            .or_insert_with(|| create_agent(agent_type))
+            .clone()
+    }
+
+    /// Agent for a request's `agent_type`, falling back to the default for unknown ids
+    pub fn resolve(&self, agent_type: Option<&str>) -> (AgentType, Arc<dyn CodeAgent>) {
+        let selected = match agent_type.map(str::trim).filter(|s| !s.is_empty()) {
+            Some(id) => AgentType::from_str(id).unwrap_or_else(|| {
+                warn!("⚠️ Unknown agent '{}', dùng agent mặc định {}", id, self.default_type.name());
+                self.default_type
+            }),
+            None => self.default_type,
+        };
+        (selected, self.get(selected))
+    }
 }
 
 #[cfg(test)]
@@ -141,13 +515,29 @@ mod tests {
         assert_eq!(AgentType::from_str("cursor"), Some(AgentType::Cursor));
         assert_eq!(AgentType::from_str("Cursor"), Some(AgentType::Cursor));
         assert_eq!(AgentType::from_str("CURSOR"), Some(AgentType::Cursor));
+        assert_eq!(AgentType::from_str("ollama"), Some(AgentType::Ollama));
+        assert_eq!(AgentType::from_str("claude-api"), Some(AgentType::ClaudeApi));
+        assert_eq!(AgentType::from_str("anthropic"), Some(AgentType::ClaudeApi));
+        assert_eq!(AgentType::from_str("gemini-api"), Some(AgentType::GeminiApi));
+        assert_eq!(AgentType::from_str("mock"), Some(AgentType::Mock));
         assert_eq!(AgentType::from_str("invalid"), None);
     }
 
+    #[test]
+    fn test_agent_type_id_round_trips() {
+        for agent_type in AgentType::ALL {
+            assert_eq!(AgentType::from_str(agent_type.id()), Some(agent_type));
+        }
+    }
+
     #[test]
     fn test_agent_type_name() {
         assert_eq!(AgentType::Claude.name(), "Claude Code");
         assert_eq!(AgentType::Gemini.name(), "Gemini CLI");
         assert_eq!(AgentType::Cursor.name(), "Cursor Agent");
+        assert_eq!(AgentType::Ollama.name(), "Ollama");
+        assert_eq!(AgentType::ClaudeApi.name(), "Anthropic API");
+        assert_eq!(AgentType::GeminiApi.name(), "Gemini API");
+        assert_eq!(AgentType::Mock.name(), "Mock (demo)");
     }
 }
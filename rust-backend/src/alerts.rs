@@ -0,0 +1,542 @@
+//! Alerts on the logs of runs (`/api/alert-rules`): a rule counts the entries of a run matching
+//! its message type and content regex, and fires once per run when the count reaches its
+//! threshold, calling a webhook, emailing or notifying the organization's open clients.
+
+use crate::database::{AlertEventRecord, AlertRuleRecord, Database};
+use crate::message_store::StructuredLogEntry;
+use crate::AppState;
+use chrono::Utc;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tracing::{debug, error, info, warn};
+
+/// Actions one rule may have
+pub const MAX_ACTIONS: usize = 5;
+
+const MAX_THRESHOLD: i64 = 10_000;
+
+/// Broadcast to the organization's clients by `notification` actions
+pub const TRIGGERED: &str = "alert-triggered";
+
+/// Characters of the triggering entry kept in the event and sent with it
+const SAMPLE_CHARS: usize = 500;
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Rules are re-read this often, so changes made through another instance apply here too
+const RELOAD_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Runs (and tickets) tracked at once before the counters start over; the events table keeps
+/// a rule from firing twice for a run anyway
+const MAX_TRACKED: usize = 10_000;
+
+const MESSAGE_TYPES: &[&str] = &["tool_use", "assistant", "error", "system", "result"];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum AlertAction {
+    /// POST of the alert as JSON
+    Webhook { url: String },
+    Email { to: Vec<String> },
+    /// `alert-triggered` to the organization's WebSocket clients
+    Notification,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AlertError {
+    #[error("{0}")]
+    Invalid(String),
+
+    #[error(transparent)]
+    Database(#[from] anyhow::Error),
+}
+
+fn default_threshold() -> i64 {
+    1
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// Body of `POST /api/alert-rules` and `PUT /api/alert-rules/:id`
+#[derive(Debug, Clone, Deserialize)]
+pub struct AlertRuleInput {
+    /// Only runs of this project; every project of the organization when unset
+    pub project_id: Option<String>,
+    pub name: String,
+    pub message_type: Option<String>,
+    pub content_pattern: Option<String>,
+    #[serde(default = "default_threshold")]
+    pub threshold: i64,
+    pub actions: Vec<AlertAction>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+/// A rule as the API returns it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AlertRule {
+    pub id: String,
+    pub org_id: String,
+    pub project_id: Option<String>,
+    pub name: String,
+    pub message_type: Option<String>,
+    pub content_pattern: Option<String>,
+    pub threshold: i64,
+    pub actions: Vec<AlertAction>,
+    pub enabled: bool,
+    pub created_by: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl AlertRule {
+    pub fn from_record(record: AlertRuleRecord) -> anyhow::Result<Self> {
+        Ok(Self {
+            actions: serde_json::from_str(&record.actions)?,
+            id: record.id,
+            org_id: record.org_id,
+            project_id: record.project_id,
+            name: record.name,
+            message_type: record.message_type,
+            content_pattern: record.content_pattern,
+            threshold: record.threshold,
+            enabled: record.enabled,
+            created_by: record.created_by,
+            created_at: record.created_at,
+            updated_at: record.updated_at,
+        })
+    }
+}
+
+fn compile(pattern: &str) -> Result<Regex, String> {
+    let regex = Regex::new(pattern).map_err(|e| e.to_string())?;
+    if regex.is_match("") {
+        return Err("Pattern must not match the empty string".to_string());
+    }
+    Ok(regex)
+}
+
+/// Check an admin-supplied rule before it is stored
+pub fn validate(input: &AlertRuleInput) -> Result<(), AlertError> {
+    let invalid = |message: String| Err(AlertError::Invalid(message));
+    if input.name.trim().is_empty() {
+        return invalid("A rule needs a name".to_string());
+    }
+    if input.message_type.is_none() && input.content_pattern.is_none() {
+        return invalid("A rule needs a message_type, a content_pattern or both".to_string());
+    }
+    if let Some(message_type) = &input.message_type {
+        if !MESSAGE_TYPES.contains(&message_type.as_str()) {
+            return invalid(format!("Unknown message_type '{}'; expected one of {}", message_type, MESSAGE_TYPES.join(", ")));
+        }
+    }
+    if let Some(pattern) = &input.content_pattern {
+        if let Err(e) = compile(pattern) {
+            return invalid(format!("Invalid content_pattern: {}", e));
+        }
+    }
+    if !(1..=MAX_THRESHOLD).contains(&input.threshold) {
+        return invalid(format!("threshold must be between 1 and {}", MAX_THRESHOLD));
+    }
+    if input.actions.is_empty() || input.actions.len() > MAX_ACTIONS {
+        return invalid(format!("A rule needs 1 to {} actions", MAX_ACTIONS));
+    }
+    for action in &input.actions {
+        match action {
+            AlertAction::Webhook { url } => {
+                if !(url.starts_with("https://") || url.starts_with("http://")) || reqwest::Url::parse(url).is_err() {
+                    return invalid(format!("Invalid webhook URL '{}'", url));
+                }
+            }
+            AlertAction::Email { to } => {
+                if to.is_empty() {
+                    return invalid("An email action needs recipients".to_string());
+                }
+                if let Some(address) = to.iter().find(|address| address.parse::<lettre::Address>().is_err()) {
+                    return invalid(format!("Invalid email address '{}'", address));
+                }
+            }
+            AlertAction::Notification => {}
+        }
+    }
+    Ok(())
+}
+
+/// Create a rule of the organization, or replace `existing` (the caller checks the project is
+/// the organization's)
+pub async fn save(
+    db: &Database,
+    org_id: &str,
+    existing: Option<&AlertRuleRecord>,
+    input: &AlertRuleInput,
+    created_by: Option<String>,
+) -> Result<AlertRule, AlertError> {
+    validate(input)?;
+    let now = Utc::now().to_rfc3339();
+    let record = AlertRuleRecord {
+        id: existing.map(|rule| rule.id.clone()).unwrap_or_else(|| uuid::Uuid::new_v4().to_string()),
+        org_id: org_id.to_string(),
+        project_id: input.project_id.clone(),
+        name: input.name.trim().to_string(),
+        message_type: input.message_type.clone(),
+        content_pattern: input.content_pattern.clone(),
+        threshold: input.threshold,
+        actions: serde_json::to_string(&input.actions).map_err(anyhow::Error::from)?,
+        enabled: input.enabled,
+        created_by: existing.map_or(created_by, |rule| rule.created_by.clone()),
+        created_at: existing.map_or(now.clone(), |rule| rule.created_at.clone()),
+        updated_at: now,
+    };
+    db.save_alert_rule(&record).await?;
+    Ok(AlertRule::from_record(record)?)
+}
+
+/// An enabled rule ready to be matched
+#[derive(Debug)]
+struct CompiledRule {
+    rule: AlertRule,
+    regex: Option<Regex>,
+}
+
+impl CompiledRule {
+    fn matches(&self, entry: &StructuredLogEntry) -> bool {
+        let type_matches = self
+            .rule
+            .message_type
+            .as_deref()
+            .is_none_or(|message_type| entry.message_type.as_str() == message_type);
+        type_matches && self.regex.as_ref().is_none_or(|regex| regex.is_match(&entry.content))
+    }
+
+    fn applies_to(&self, project_id: &str, org_id: &str) -> bool {
+        self.rule.org_id == org_id && self.rule.project_id.as_deref().is_none_or(|id| id == project_id)
+    }
+}
+
+/// Enabled rules and the match counts of the runs seen, for one instance
+pub struct AlertEngine {
+    rules: RwLock<Vec<Arc<CompiledRule>>>,
+    /// (rule ID, run ID) → matching entries so far
+    counts: Mutex<HashMap<(String, String), i64>>,
+    /// Ticket ID → (project ID, organization ID)
+    tickets: Mutex<HashMap<String, (String, String)>>,
+    http: reqwest::Client,
+}
+
+impl Default for AlertEngine {
+    fn default() -> Self {
+        Self {
+            rules: RwLock::default(),
+            counts: Mutex::default(),
+            tickets: Mutex::default(),
+            http: reqwest::Client::builder().timeout(WEBHOOK_TIMEOUT).build().unwrap_or_default(),
+        }
+    }
+}
+
+impl AlertEngine {
+    /// Replace the rules with the database's enabled ones; invalid ones are skipped
+    pub async fn reload(&self, database: &Database) -> anyhow::Result<()> {
+        let records = database.list_enabled_alert_rules().await?;
+        self.set_rules(records);
+        Ok(())
+    }
+
+    fn set_rules(&self, records: Vec<AlertRuleRecord>) {
+        let rules: Vec<Arc<CompiledRule>> = records
+            .into_iter()
+            .filter_map(|record| {
+                let name = record.name.clone();
+                let regex = match record.content_pattern.as_deref().map(compile).transpose() {
+                    Ok(regex) => regex,
+                    Err(e) => {
+                        warn!("⚠️ Bỏ qua alert rule '{}' không hợp lệ: {}", name, e);
+                        return None;
+                    }
+                };
+                match AlertRule::from_record(record) {
+                    Ok(rule) => Some(Arc::new(CompiledRule { rule, regex })),
+                    Err(e) => {
+                        warn!("⚠️ Bỏ qua alert rule '{}' không hợp lệ: {}", name, e);
+                        None
+                    }
+                }
+            })
+            .collect();
+        debug!("Loaded {} alert rules", rules.len());
+        *self.rules.write().unwrap() = rules;
+    }
+
+    /// Rules whose conditions the entry meets, whatever their scope
+    fn candidates(&self, entry: &StructuredLogEntry) -> Vec<Arc<CompiledRule>> {
+        let rules = self.rules.read().unwrap();
+        rules.iter().filter(|rule| rule.matches(entry)).cloned().collect()
+    }
+
+    /// Count the entry toward the candidates in scope; those reaching their threshold with it
+    /// are returned with their count
+    fn count(
+        &self,
+        candidates: Vec<Arc<CompiledRule>>,
+        run_id: &str,
+        project_id: &str,
+        org_id: &str,
+    ) -> Vec<(Arc<CompiledRule>, i64)> {
+        let mut counts = self.counts.lock().unwrap();
+        if counts.len() > MAX_TRACKED {
+            counts.clear();
+        }
+        candidates
+            .into_iter()
+            .filter(|rule| rule.applies_to(project_id, org_id))
+            .filter_map(|rule| {
+                let count = counts.entry((rule.rule.id.clone(), run_id.to_string())).or_insert(0);
+                *count += 1;
+                let count = *count;
+                (count == rule.rule.threshold).then_some((rule, count))
+            })
+            .collect()
+    }
+
+    async fn ticket_scope(&self, database: &Database, ticket_id: &str) -> Option<(String, String)> {
+        if let Some(scope) = self.tickets.lock().unwrap().get(ticket_id) {
+            return Some(scope.clone());
+        }
+        let ticket = database.get_ticket(ticket_id).await.ok()??;
+        let project = database.get_project(&ticket.project_id).await.ok()??;
+        let scope = (project.id, project.org_id);
+
+        let mut tickets = self.tickets.lock().unwrap();
+        if tickets.len() > MAX_TRACKED {
+            tickets.clear();
+        }
+        tickets.insert(ticket_id.to_string(), scope.clone());
+        Some(scope)
+    }
+}
+
+/// Evaluate the rules on every log entry of this process, API instances only (workers' logs
+/// are relayed to them). Each instance counts the entries it sees; the first to record the
+/// event acts on it.
+pub fn spawn(state: AppState) {
+    let tasks = state.tasks.clone();
+    tasks.spawn("alert-evaluator", async move {
+        let mut log_rx = state.msg_store.subscribe();
+        let mut reload = tokio::time::interval(RELOAD_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = reload.tick() => {
+                    if let Err(e) = state.alerts.reload(&state.database).await {
+                        error!("Failed to load alert rules: {}", e);
+                    }
+                }
+                received = log_rx.recv() => match received {
+                    Ok(entry) => evaluate(&state, entry).await,
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!("⚠️ Bộ đánh giá alert bỏ lỡ {} log", skipped);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+            }
+        }
+    });
+}
+
+async fn evaluate(state: &AppState, entry: StructuredLogEntry) {
+    let Some(run_id) = entry.metadata.get("run_id") else {
+        return;
+    };
+    let engine = &state.alerts;
+    let candidates = engine.candidates(&entry);
+    if candidates.is_empty() {
+        return;
+    }
+    let Some((project_id, org_id)) = engine.ticket_scope(&state.database, &entry.ticket_id).await else {
+        return;
+    };
+    for (rule, count) in engine.count(candidates, run_id, &project_id, &org_id) {
+        let name = format!("alert {} run {}", rule.rule.id, run_id);
+        let (task_state, entry, project_id) = (state.clone(), entry.clone(), project_id.clone());
+        state.tasks.spawn(name, async move { fire(&task_state, &rule.rule, &entry, count, &project_id).await });
+    }
+}
+
+async fn fire(state: &AppState, rule: &AlertRule, entry: &StructuredLogEntry, count: i64, project_id: &str) {
+    let run_id = entry.metadata.get("run_id").cloned().unwrap_or_default();
+    let event = AlertEventRecord {
+        id: uuid::Uuid::new_v4().to_string(),
+        rule_id: rule.id.clone(),
+        ticket_id: entry.ticket_id.clone(),
+        run_id: run_id.clone(),
+        match_count: count,
+        sample: entry.content.chars().take(SAMPLE_CHARS).collect(),
+        created_at: Utc::now().to_rfc3339(),
+    };
+    match state.database.record_alert_event(&event).await {
+        Ok(true) => {}
+        Ok(false) => return,
+        Err(e) => {
+            error!("Failed to record alert event of rule {}: {}", rule.id, e);
+            return;
+        }
+    }
+    warn!("🚨 Alert '{}' cho ticket {} (run {}, {} log khớp)", rule.name, entry.ticket_id, run_id, count);
+
+    let payload = serde_json::json!({
+        "rule_id": rule.id,
+        "rule": rule.name,
+        "ticket_id": event.ticket_id,
+        "project_id": project_id,
+        "run_id": run_id,
+        "match_count": count,
+        "sample": event.sample,
+        "triggered_at": event.created_at,
+    });
+    for action in &rule.actions {
+        match action {
+            AlertAction::Webhook { url } => match state.alerts.http.post(url).json(&payload).send().await {
+                Ok(response) if response.status().is_success() => {}
+                Ok(response) => warn!("⚠️ Webhook alert {} trả về {}", url, response.status()),
+                Err(e) => warn!("⚠️ Không gọi được webhook alert {}: {}", url, e),
+            },
+            AlertAction::Email { to } => {
+                let Some(notifier) = &state.notifier else {
+                    warn!("⚠️ Alert '{}' cần gửi email nhưng SMTP chưa cấu hình", rule.name);
+                    continue;
+                };
+                let ticket = match state.database.get_ticket(&event.ticket_id).await {
+                    Ok(Some(ticket)) => ticket,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        error!("Failed to get ticket {}: {}", event.ticket_id, e);
+                        continue;
+                    }
+                };
+                notifier.alert(to, &rule.name, &ticket, count, &event.sample).await;
+            }
+            AlertAction::Notification => {
+                let _ = state.broadcast_tx.send(crate::BroadcastMessage {
+                    ticket_id: event.ticket_id.clone(),
+                    message_type: TRIGGERED.to_string(),
+                    content: payload.to_string(),
+                    timestamp: Utc::now(),
+                    org_id: Some(rule.org_id.clone()),
+                    changed_files: None,
+                });
+            }
+        }
+    }
+    info!("🚨 Đã xử lý {} hành động của alert '{}'", rule.actions.len(), rule.name);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message_store::LogMessageType;
+
+    fn input(message_type: Option<&str>, pattern: Option<&str>, threshold: i64) -> AlertRuleInput {
+        AlertRuleInput {
+            project_id: None,
+            name: "Auth".to_string(),
+            message_type: message_type.map(str::to_string),
+            content_pattern: pattern.map(str::to_string),
+            threshold,
+            actions: vec![AlertAction::Notification],
+            enabled: true,
+        }
+    }
+
+    fn record(id: &str, org_id: &str, input: &AlertRuleInput) -> AlertRuleRecord {
+        AlertRuleRecord {
+            id: id.to_string(),
+            org_id: org_id.to_string(),
+            project_id: input.project_id.clone(),
+            name: input.name.clone(),
+            message_type: input.message_type.clone(),
+            content_pattern: input.content_pattern.clone(),
+            threshold: input.threshold,
+            actions: serde_json::to_string(&input.actions).unwrap(),
+            enabled: input.enabled,
+            created_by: None,
+            created_at: String::new(),
+            updated_at: String::new(),
+        }
+    }
+
+    fn entry(message_type: LogMessageType, content: &str) -> StructuredLogEntry {
+        StructuredLogEntry {
+            id: uuid::Uuid::new_v4().to_string(),
+            ticket_id: "t1".to_string(),
+            message_type,
+            content: content.to_string(),
+            raw_log: None,
+            metadata: HashMap::new(),
+            timestamp: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate(&input(Some("error"), None, 3)).is_ok());
+        assert!(validate(&input(None, Some("(?i)authentication required"), 1)).is_ok());
+        assert!(validate(&input(None, None, 1)).is_err());
+        assert!(validate(&input(Some("warning"), None, 1)).is_err());
+        assert!(validate(&input(None, Some("a*"), 1)).is_err());
+        assert!(validate(&input(Some("error"), None, 0)).is_err());
+
+        let mut with_actions = input(Some("error"), None, 1);
+        with_actions.actions = vec![AlertAction::Webhook { url: "ftp://hooks".to_string() }];
+        assert!(validate(&with_actions).is_err());
+        with_actions.actions = vec![AlertAction::Email { to: vec!["qa-at-example".to_string()] }];
+        assert!(validate(&with_actions).is_err());
+
+        let parsed: Vec<AlertAction> = serde_json::from_value(serde_json::json!([
+            { "type": "webhook", "url": "https://hooks.example.com/qa" },
+            { "type": "email", "to": ["qa@example.com"] },
+            { "type": "notification" }
+        ]))
+        .unwrap();
+        with_actions.actions = parsed;
+        assert!(validate(&with_actions).is_ok());
+    }
+
+    #[test]
+    fn test_rules_fire_once_at_threshold() {
+        let engine = AlertEngine::default();
+        let errors = input(Some("error"), None, 2);
+        let auth = AlertRuleInput {
+            project_id: Some("p1".to_string()),
+            ..input(None, Some("(?i)authentication required"), 1)
+        };
+        engine.set_rules(vec![record("errors", "org", &errors), record("auth", "org", &auth)]);
+
+        let count = |entry: &StructuredLogEntry, run_id: &str, project_id: &str, org_id: &str| -> Vec<String> {
+            let candidates = engine.candidates(entry);
+            engine
+                .count(candidates, run_id, project_id, org_id)
+                .into_iter()
+                .map(|(rule, _)| rule.rule.id.clone())
+                .collect()
+        };
+
+        let error = entry(LogMessageType::Error, "Rate limited");
+        assert!(count(&error, "r1", "p1", "org").is_empty());
+        assert_eq!(count(&error, "r1", "p1", "org"), vec!["errors"]);
+        // Past the threshold it doesn't fire again; another run counts from zero
+        assert!(count(&error, "r1", "p1", "org").is_empty());
+        assert!(count(&error, "r2", "p1", "org").is_empty());
+
+        let auth_required = entry(LogMessageType::Assistant, "Error: Authentication required, run login");
+        assert_eq!(count(&auth_required, "r1", "p1", "org"), vec!["auth"]);
+        // Other projects and organizations are out of scope
+        assert!(count(&auth_required, "r3", "p2", "org").is_empty());
+        assert!(count(&error, "r4", "p1", "other").is_empty());
+        assert!(count(&error, "r4", "p1", "other").is_empty());
+    }
+}
@@ -0,0 +1,1128 @@
+use crate::activity;
+use crate::agent_factory::AgentType;
+use crate::budgets;
+use crate::code_agent::{AnalysisCancelled, AnalysisMode};
+use crate::database::{Database, TicketRecord};
+use crate::job_queue::{AnalysisPriority, JobQueue, QueuedJob};
+use crate::message_store::MsgStore;
+use crate::notifications::AnalysisOutcome;
+use crate::preflight::{self, PreflightFailed};
+use crate::test_cases;
+use crate::worker::Role;
+use crate::{AppState, BroadcastMessage, CodeAnalysisRequest};
+use serde::Serialize;
+use serde_json::json;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::{broadcast, Mutex};
+use tokio::task::AbortHandle;
+use tokio::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, info_span, warn, Instrument};
+
+/// How long a cancelled agent gets to stop its process and return before its task is aborted
+const CANCEL_GRACE: Duration = Duration::from_secs(10);
+
+/// Analysis in progress, keyed by ticket ID in `AppState::running_tasks`
+pub struct RunningAnalysis {
+    pub run_id: String,
+    priority: AnalysisPriority,
+    started: Instant,
+    /// Cancelled to make room for an urgent run; requeued instead of recorded as stopped
+    preempted: bool,
+    cancel: CancellationToken,
+    abort: AbortHandle,
+}
+
+pub type RunningTasks = Arc<Mutex<HashMap<String, RunningAnalysis>>>;
+
+/// Analyses waiting for a slot, in `AppState::job_queue`. Lock after `RunningTasks` when both are needed.
+pub type AnalysisQueue = Arc<Mutex<JobQueue>>;
+
+/// Drop the ticket's entry unless a newer run has replaced it
+fn forget_run(tasks: &mut HashMap<String, RunningAnalysis>, ticket_id: &str, run_id: &str) {
+    if tasks.get(ticket_id).is_some_and(|running| running.run_id == run_id) {
+        tasks.remove(ticket_id);
+    }
+}
+
+/// Mode of a run on `ticket`: `requested` overrides the ticket's mode for this run only, unless
+/// `remember` also makes it the ticket's mode
+pub async fn choose_mode(
+    state: &AppState,
+    ticket: &TicketRecord,
+    requested: Option<AnalysisMode>,
+    remember: bool,
+) -> anyhow::Result<AnalysisMode> {
+    let Some(mode) = requested else {
+        return Ok(ticket.mode);
+    };
+    if remember && mode != ticket.mode {
+        state.database.set_ticket_mode(&ticket.id, mode).await?;
+        let _ = state.broadcast_tx.send(BroadcastMessage {
+            ticket_id: ticket.id.clone(),
+            message_type: "ticket-mode".to_string(),
+            content: mode.as_str().to_string(),
+            timestamp: chrono::Utc::now(),
+            org_id: None,
+            changed_files: None,
+        });
+    }
+    Ok(mode)
+}
+
+/// Queue a code analysis and start it as soon as a slot is free
+///
+/// Shared by every entry point that can start an analysis (WebSocket, gRPC),
+/// so completion/error broadcasts and task bookkeeping behave identically.
+/// Returns the run ID attached to the session, every log entry and the tracing span.
+/// `requested_by` (a user ID) gets an email when the run ends, per their notification settings.
+pub async fn start_analysis(
+    state: &AppState,
+    mut request: CodeAnalysisRequest,
+    requested_by: Option<String>,
+) -> String {
+    // What the request leaves out comes from the requester's preferences
+    if let Some(user_id) = &requested_by {
+        match crate::preferences::load(&state.database, user_id).await {
+            Ok(preferences) => {
+                if request.agent_type.as_deref().is_none_or(|agent| agent.trim().is_empty()) {
+                    request.agent_type = preferences.default_agent;
+                }
+                if request.response_language.is_none() {
+                    request.response_language = preferences.response_language;
+                }
+            }
+            Err(e) => error!("Failed to load preferences of user {}: {}", user_id, e),
+        }
+    }
+    let (agent_type, _) = state.agents.resolve(request.agent_type.as_deref());
+    request.agent_type = Some(agent_type.id().to_string());
+    let msg_store = state.msg_store.clone();
+    let ticket_id = request.ticket_id.clone();
+
+    let run_id = uuid::Uuid::new_v4().to_string();
+    request.run_id = Some(run_id.clone());
+    msg_store.begin_run(&ticket_id, &run_id).await;
+
+    let mut notices = Vec::new();
+    if request.include_linked_results {
+        match crate::ticket_links::linked_context(&state.database, &ticket_id).await {
+            Ok(Some((context, count))) => {
+                notices.push(format!("🔗 Đã thêm kết quả của {} ticket liên kết vào code context", count));
+                request.code_context = if request.code_context.trim().is_empty() {
+                    context
+                } else {
+                    format!("{}\n\n{}", request.code_context.trim_end(), context)
+                };
+            }
+            Ok(None) => {}
+            Err(e) => error!("Failed to load linked results of ticket {}: {}", ticket_id, e),
+        }
+    }
+
+    // The project's path rules travel with the request; paths they exclude never reach a prompt
+    match state.database.get_project_path_rules(&request.project_id).await {
+        Ok(rules) => request.path_rules = rules,
+        Err(e) => error!("Failed to load path rules of project {}: {}", request.project_id, e),
+    }
+    match request.path_rules.compile() {
+        Ok(policy) => {
+            let (code_context, removed) = crate::project_files::strip_excluded_paths(&request.code_context, &policy);
+            if !removed.is_empty() {
+                notices.push(format!("🚫 Bỏ qua path bị chặn bởi cấu hình project: {}", removed.join(", ")));
+                request.code_context = code_context;
+            }
+            // A fuzzy context ("the login controller") becomes concrete project paths
+            let resolved =
+                crate::context_resolver::resolve_request(&state.database, &mut request, &policy, state.context_resolver)
+                    .await;
+            notices.extend(resolved);
+        }
+        Err(e) => error!("Invalid path rules of project {}: {}", request.project_id, e),
+    }
+    // Tools the agent may use unasked: the project's list, or the mode's read-only default
+    match state.database.get_project_tool_policy(&request.project_id).await {
+        Ok(tools) => request.tool_policy = crate::tool_policy::ToolPolicy::resolve(tools, request.mode),
+        Err(e) => error!("Failed to load tool policy of project {}: {}", request.project_id, e),
+    }
+    // A running prompt experiment gives the run one of its variants
+    match crate::experiments::assign(&state.database, &mut request).await {
+        Ok(notice) => notices.extend(notice),
+        Err(e) => error!("Failed to assign experiment variant for project {}: {}", request.project_id, e),
+    }
+    request.api_key = crate::agent_credentials::project_key(
+        &state.database,
+        state.credentials.as_deref(),
+        &request.project_id,
+        agent_type,
+    )
+    .await;
+
+    // Oversized input is cut here for every agent, with a note in the ticket's log
+    notices.extend(crate::prompt::fit_request(&mut request, &state.prompt_limits));
+    for notice in notices {
+        warn!("{} (ticket {})", notice, ticket_id);
+        let log_entry = crate::log_normalizer::LogNormalizer::new().normalize(notice, ticket_id.clone());
+        msg_store.push(log_entry).await;
+    }
+
+    let priority = request.priority;
+    if state.role.shared_queue() {
+        enqueue_shared(state, request, requested_by).await;
+        return run_id;
+    }
+    state.job_queue.lock().await.push(request, requested_by);
+    dispatch(state).await;
+
+    let position = state.job_queue.lock().await.position(&ticket_id);
+    if let Some(position) = position {
+        // Shown as analyzing (and stoppable) while it waits
+        if let Err(e) = state.database.update_ticket_analyzing(&ticket_id, true).await {
+            error!("Failed to update ticket {} analyzing status: {}", ticket_id, e);
+        }
+        announce_queued(state, &ticket_id, &run_id, priority, position, false).await;
+    }
+
+    run_id
+}
+
+/// Hand a prepared request to the workers (`--role api|worker`); it shows as queued until one
+/// claims it
+async fn enqueue_shared(state: &AppState, request: CodeAnalysisRequest, requested_by: Option<String>) {
+    let ticket_id = request.ticket_id.clone();
+    let run_id = request.run_id.clone().unwrap_or_default();
+    let priority = request.priority;
+    match crate::worker::enqueue(&state.database, request, requested_by).await {
+        Ok(position) => {
+            if let Err(e) = state.database.update_ticket_analyzing(&ticket_id, true).await {
+                error!("Failed to update ticket {} analyzing status: {}", ticket_id, e);
+            }
+            announce_queued(state, &ticket_id, &run_id, priority, position.unwrap_or(1), false).await;
+        }
+        Err(e) => {
+            error!("❌ Không đưa được phân tích ticket {} vào hàng đợi chung: {}", ticket_id, e);
+            let _ = state.broadcast_tx.send(BroadcastMessage {
+                ticket_id: ticket_id.clone(),
+                message_type: "code-analysis-error".to_string(),
+                content: e.to_string(),
+                timestamp: chrono::Utc::now(),
+                org_id: None,
+                changed_files: None,
+            });
+            state.msg_store.end_run(&ticket_id, &run_id).await;
+        }
+    }
+    // A worker's follow-up runs are claimed right away if it has a free slot
+    if state.role == Role::Worker {
+        dispatch(state).await;
+    }
+}
+
+/// Tell open boards that a run started (or was queued), as a user starting it would
+pub fn announce_started(state: &AppState, ticket_id: &str, run_id: &str) {
+    let _ = state.broadcast_tx.send(BroadcastMessage {
+        ticket_id: ticket_id.to_string(),
+        message_type: "analysis-started".to_string(),
+        content: json!({ "run_id": run_id }).to_string(),
+        timestamp: chrono::Utc::now(),
+        org_id: None,
+        changed_files: None,
+    });
+}
+
+/// Start the next analysis of a finished run's chain, handing it the rest. Tickets that are
+/// already analyzing (started by hand meanwhile) are skipped.
+async fn start_follow_up(state: &AppState, mut follow_ups: Vec<CodeAnalysisRequest>, requested_by: Option<String>) {
+    while !follow_ups.is_empty() {
+        let mut next = follow_ups.remove(0);
+        if is_active(state, &next.ticket_id).await {
+            warn!("⚠️ Ticket {} đang được phân tích, bỏ qua trong chuỗi phân tích", next.ticket_id);
+            continue;
+        }
+        next.then = follow_ups;
+        let ticket_id = next.ticket_id.clone();
+        let run_id = Box::pin(start_analysis(state, next, requested_by)).await;
+        announce_started(state, &ticket_id, &run_id);
+        return;
+    }
+}
+
+/// Tell the ticket's log and clients that its run waits in the queue
+async fn announce_queued(
+    state: &AppState,
+    ticket_id: &str,
+    run_id: &str,
+    priority: AnalysisPriority,
+    position: usize,
+    preempted: bool,
+) {
+    info!("⏳ Ticket {} chờ trong hàng đợi (vị trí {}, ưu tiên {})", ticket_id, position, priority.as_str());
+    let log_entry = crate::log_normalizer::LogNormalizer::new().normalize(
+        format!("⏳ Đang chờ trong hàng đợi: vị trí {}, ưu tiên {}", position, priority.as_str()),
+        ticket_id.to_string(),
+    );
+    state.msg_store.push(log_entry).await;
+
+    let _ = state.broadcast_tx.send(BroadcastMessage {
+        ticket_id: ticket_id.to_string(),
+        message_type: "analysis-queued".to_string(),
+        content: json!({
+            "run_id": run_id,
+            "priority": priority,
+            "position": position,
+            "preempted": preempted,
+        })
+        .to_string(),
+        timestamp: chrono::Utc::now(),
+        org_id: None,
+        changed_files: None,
+    });
+}
+
+/// Start queued analyses while slots are free, highest priority first. With preemption on,
+/// urgent jobs that find every slot busy pause the lowest-priority running analyses; those
+/// requeue themselves once their agent has stopped, which frees the slot for the urgent job.
+pub async fn dispatch(state: &AppState) {
+    let mut tasks = state.running_tasks.lock().await;
+    let mut queue = state.job_queue.lock().await;
+
+    // Workers fill their free slots from the shared queue
+    if state.role == Role::Worker {
+        while queue.has_capacity(tasks.len() + queue.len()) {
+            match crate::worker::claim(state, &mut queue).await {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(e) => {
+                    error!("Failed to claim a job from the shared queue: {}", e);
+                    break;
+                }
+            }
+        }
+    }
+
+    while queue.has_capacity(tasks.len()) {
+        let Some(job) = queue.pop() else { break };
+        launch(state, &mut tasks, job);
+    }
+
+    if !queue.config().preemption {
+        return;
+    }
+    let pausing = tasks.values().filter(|running| running.preempted).count();
+    for _ in pausing..queue.count_at(AnalysisPriority::Urgent) {
+        // Lowest priority first; among equals the one that started last has the least to lose
+        let victim = tasks
+            .iter_mut()
+            .filter(|(_, running)| !running.preempted && running.priority < AnalysisPriority::Urgent)
+            .min_by_key(|(_, running)| (running.priority, Reverse(running.started)));
+        let Some((ticket_id, running)) = victim else { break };
+        info!(
+            "⏸️ Tạm dừng phân tích ticket {} (ưu tiên {}) để nhường chỗ cho phân tích khẩn cấp",
+            ticket_id,
+            running.priority.as_str()
+        );
+        running.preempted = true;
+        running.cancel.cancel();
+    }
+}
+
+/// Spawn a dequeued analysis and register it in `tasks` for cancellation
+fn launch(state: &AppState, tasks: &mut HashMap<String, RunningAnalysis>, job: QueuedJob) {
+    let (agent_type, code_agent) = state.agents.resolve(job.request.agent_type.as_deref());
+    let msg_store = state.msg_store.clone();
+    let database = state.database.clone();
+    let broadcast_tx = state.broadcast_tx.clone();
+    let running_tasks = state.running_tasks.clone();
+    let notifier = state.notifier.clone();
+    let state = state.clone();
+    let mut request = job.request.clone();
+    // The job keeps its chain for requeues; the run hands it on when it ends
+    let follow_ups = std::mem::take(&mut request.then);
+    let requested_by = job.requested_by.clone();
+    let ticket_id = request.ticket_id.clone();
+    let ticket_id_for_cleanup = ticket_id.clone();
+    let run_id = request.run_id.clone().unwrap_or_default();
+    let priority = request.priority;
+
+    let span = info_span!("analysis", run_id = %run_id, ticket_id = %ticket_id);
+    let run_id_for_cleanup = run_id.clone();
+
+    // Derive progress stages from the ticket's log stream while the agent runs.
+    // Dropping `progress_done` (including on abort) stops the tracker.
+    let (progress_done, progress_done_rx) = tokio::sync::oneshot::channel();
+    let supervisor = state.tasks.clone();
+    supervisor.spawn(format!("progress {}", ticket_id), crate::progress::track_progress(
+        ticket_id.clone(),
+        msg_store.subscribe(),
+        broadcast_tx.clone(),
+        database.clone(),
+        progress_done_rx,
+    ));
+    // Same lifecycle for the files the agent reads, recorded for project coverage
+    let (files_done, files_done_rx) = tokio::sync::oneshot::channel();
+    supervisor.spawn(format!("file-tracker {}", ticket_id), crate::coverage::track_files(
+        ticket_id.clone(),
+        run_id.clone(),
+        msg_store.subscribe(),
+        database.clone(),
+        files_done_rx,
+    ));
+
+    // And for the versions, model and commit that produced the run
+    let (environment_done, environment_done_rx) = tokio::sync::oneshot::channel();
+    let environment = supervisor.spawn(format!("environment {}", ticket_id), crate::run_environment::track_environment(
+        request.clone(),
+        agent_type,
+        msg_store.subscribe(),
+        database.clone(),
+        environment_done_rx,
+    ));
+
+    let cancel = CancellationToken::new();
+    let agent_cancel = cancel.clone();
+
+    // The caller holds the lock, so the task can't finish and unregister before it is added
+    let started = Instant::now();
+    let handle = supervisor.spawn(format!("analysis {} run {}", ticket_id, run_id), async move {
+        // Replays run in a throwaway worktree at the original commit, removed when the task
+        // ends (or is aborted)
+        let checkout = crate::replay::checkout(&database, &request).await;
+        let (outcome, changed_files) = match &checkout {
+            Ok(checkout) => {
+                let mut request = request.clone();
+                request.working_dir = checkout.as_ref().map(|c| c.working_dir().to_string_lossy().into_owned());
+                let ready = async {
+                    check_directory(&state, &request, agent_type).await?;
+                    check_budget(&state, &request, agent_type).await?;
+                    run_preflight(&state, &request, agent_type, &agent_cancel).await
+                }
+                .await;
+                match ready {
+                    Ok(()) => {
+                        let run = crate::output_validation::analyze(&state, &code_agent, request.clone(), agent_cancel);
+                        crate::changed_files::record(&state, &request, run).await
+                    }
+                    Err(e) => (Err(e), Vec::new()),
+                }
+            }
+            Err(e) => {
+                if let Err(e) = database.update_ticket_analyzing(&request.ticket_id, false).await {
+                    error!("Failed to update ticket {} analyzing status: {}", request.ticket_id, e);
+                }
+                (Err(anyhow::anyhow!("Không tạo được bản checkout để phát lại: {}", e)), Vec::new())
+            }
+        };
+        drop(checkout);
+        let _ = progress_done.send(());
+        let _ = files_done.send(());
+        let _ = environment_done.send(());
+        // The run's usage counts toward its budgets once the tracker has stored it
+        let (budget_state, project_id, budget_ticket_id) =
+            (state.clone(), request.project_id.clone(), ticket_id_for_cleanup.clone());
+        state.tasks.spawn(format!("budget {}", ticket_id_for_cleanup), async move {
+            let _ = environment.await;
+            budgets::warn_thresholds(&budget_state, &project_id, &budget_ticket_id).await;
+        });
+
+        let notice = AnalysisOutcome {
+            succeeded: outcome.is_ok(),
+            detail: match &outcome {
+                Ok(response) => response.result.clone(),
+                Err(e) => e.to_string(),
+            },
+            elapsed: started.elapsed(),
+        };
+
+        match outcome {
+            Ok(response) => {
+                crate::post_run::after_run(&state, &request).await;
+                match request.mode {
+                    AnalysisMode::TestCases => {
+                        store_test_cases(&database, &broadcast_tx, &request, &response.result).await
+                    }
+                    AnalysisMode::Diagram => store_diagram(&database, &broadcast_tx, &request, &response.result).await,
+                    AnalysisMode::Ask => crate::summary::spawn(
+                        &state,
+                        &request.ticket_id,
+                        &request.project_id,
+                        &request.question,
+                        &response.result,
+                    ),
+                }
+
+                // Broadcast completion message
+                let _ = broadcast_tx.send(crate::BroadcastMessage {
+                    ticket_id: response.ticket_id,
+                    message_type: "code-analysis-complete".to_string(),
+                    content: response.result,
+                    timestamp: chrono::Utc::now(),
+                    org_id: None,
+                    changed_files: Some(changed_files),
+                });
+
+                info!("✅ Phân tích hoàn tất cho ticket {}", request.ticket_id);
+            }
+            Err(e) if e.is::<AnalysisCancelled>() => {
+                let preempted = {
+                    let mut tasks = running_tasks.lock().await;
+                    let preempted = tasks
+                        .get(&ticket_id_for_cleanup)
+                        .is_some_and(|running| running.run_id == run_id_for_cleanup && running.preempted);
+                    forget_run(&mut tasks, &ticket_id_for_cleanup, &run_id_for_cleanup);
+                    preempted
+                };
+                if preempted {
+                    record_preempted(&state, job).await;
+                } else {
+                    record_stopped(&database, &msg_store, &broadcast_tx, &request.ticket_id).await;
+                    msg_store.end_run(&ticket_id_for_cleanup, &run_id_for_cleanup).await;
+                    if !follow_ups.is_empty() {
+                        info!("⏹️ Bỏ {} phân tích nối tiếp của ticket {}", follow_ups.len(), ticket_id_for_cleanup);
+                    }
+                }
+                dispatch(&state).await;
+                return;
+            }
+            Err(e) => {
+                error!("❌ Lỗi phân tích code: {}", e);
+
+                // Broadcast error message
+                let _ = broadcast_tx.send(crate::BroadcastMessage {
+                    ticket_id: request.ticket_id,
+                    message_type: "code-analysis-error".to_string(),
+                    content: e.to_string(),
+                    timestamp: chrono::Utc::now(),
+                    org_id: None,
+                    changed_files: None,
+                });
+            }
+        }
+
+        let failure = (!notice.succeeded).then_some(notice.detail.as_str());
+        activity::analysis_finished(&database, &ticket_id_for_cleanup, failure).await;
+
+        if let (Some(notifier), Some(user_id)) = (notifier, requested_by.clone()) {
+            let database = database.clone();
+            let ticket_id = ticket_id_for_cleanup.clone();
+            state.tasks.spawn(format!("email {}", ticket_id), async move {
+                if let Err(e) = notifier.analysis_finished(&database, &user_id, &ticket_id, &notice).await {
+                    warn!("⚠️ Không gửi được email thông báo cho ticket {}: {}", ticket_id, e);
+                }
+            });
+        }
+
+        msg_store.end_run(&ticket_id_for_cleanup, &run_id_for_cleanup).await;
+
+        // Clean up task handle when analysis completes, and let the next queued job start
+        forget_run(&mut *running_tasks.lock().await, &ticket_id_for_cleanup, &run_id_for_cleanup);
+        dispatch(&state).await;
+
+        // A chain goes on after failed runs too; only stopping a run ends it
+        start_follow_up(&state, follow_ups, requested_by).await;
+    }.instrument(span));
+
+    tasks.insert(
+        ticket_id,
+        RunningAnalysis {
+            run_id,
+            priority,
+            started,
+            preempted: false,
+            cancel,
+            abort: handle.abort_handle(),
+        },
+    );
+}
+
+/// Run the project's pre-flight checks in the directory the agent would work in. A failed
+/// check is recorded as a failed session, so the run shows up in the ticket's history.
+/// Replays are exempt: they run at a pinned commit in a detached checkout.
+async fn run_preflight(
+    state: &AppState,
+    request: &CodeAnalysisRequest,
+    agent_type: AgentType,
+    cancel: &CancellationToken,
+) -> anyhow::Result<()> {
+    if request.replay.is_some() {
+        return Ok(());
+    }
+    let database = &state.database;
+    let checks = match database.get_project_preflight_checks(&request.project_id).await {
+        Ok(checks) if !checks.is_empty() => checks,
+        Ok(_) => return Ok(()),
+        Err(e) => {
+            error!("Failed to load pre-flight checks of project {}: {}", request.project_id, e);
+            return Ok(());
+        }
+    };
+    let Some(dir) = crate::code_agent::working_directory(request, database).await else {
+        return Ok(());
+    };
+
+    let checked = crate::code_agent::until_cancelled(cancel, async {
+        Ok(preflight::run(&state.preflight, &checks, Path::new(&dir), &request.ticket_id, &state.msg_store).await?)
+    })
+    .await;
+    let Err(e) = &checked else {
+        return Ok(());
+    };
+    if let Some(failed) = e.downcast_ref::<PreflightFailed>() {
+        warn!("🛫 Ticket {} không qua kiểm tra trước khi phân tích: {}", request.ticket_id, failed);
+        record_refused(state, request, agent_type, &failed.to_string()).await;
+    }
+    checked
+}
+
+/// Check the project directory is still under `PROJECT_ROOTS` before an agent is pointed at
+/// it: the roots may have changed since the project was saved. Replays run in a checkout the
+/// server made, so they are exempt like from the pre-flight checks.
+async fn check_directory(state: &AppState, request: &CodeAnalysisRequest, agent_type: AgentType) -> anyhow::Result<()> {
+    if request.replay.is_some() || !state.project_roots.is_restricted() {
+        return Ok(());
+    }
+    let Some(dir) = crate::code_agent::working_directory(request, &state.database).await else {
+        return Ok(());
+    };
+    let Err(e) = state.project_roots.check(&dir) else {
+        return Ok(());
+    };
+    warn!("🚫 Không chạy agent cho ticket {}: {}", request.ticket_id, e);
+    record_refused(state, request, agent_type, &e.to_string()).await;
+    Err(e.into())
+}
+
+/// Refuse the run when a budget of its project is used up. The entry points users start runs
+/// from check first; this covers queued runs and the ones started without a user (follow-ups,
+/// stale re-runs, Slack). A failed check lets the run go on.
+async fn check_budget(state: &AppState, request: &CodeAnalysisRequest, agent_type: AgentType) -> anyhow::Result<()> {
+    let exceeded = match budgets::check(&state.database, &request.project_id, request.budget_override).await {
+        Ok(Some(exceeded)) => exceeded,
+        Ok(None) => return Ok(()),
+        Err(e) => {
+            error!("Failed to check budgets of project {}: {}", request.project_id, e);
+            return Ok(());
+        }
+    };
+    warn!("💸 Không chạy agent cho ticket {}: {}", request.ticket_id, exceeded);
+    record_refused(state, request, agent_type, &exceeded.to_string()).await;
+    Err(exceeded.into())
+}
+
+/// Record a run refused before its agent spawned as a failed session, so it shows up in the
+/// ticket's history, and let the ticket be analyzed again
+async fn record_refused(state: &AppState, request: &CodeAnalysisRequest, agent_type: AgentType, reason: &str) {
+    let database = &state.database;
+    match database
+        .create_session(&request.ticket_id, agent_type.id(), request.run_id.as_deref(), &request.tool_policy, request.mode)
+        .await
+    {
+        Ok(session_id) => {
+            if let Err(e) = database.fail_session(&session_id, reason).await {
+                error!("Failed to record failed session {}: {}", session_id, e);
+            }
+        }
+        Err(e) => error!("Failed to create session of ticket {}: {}", request.ticket_id, e),
+    }
+    if let Err(e) = database.update_ticket_analyzing(&request.ticket_id, false).await {
+        error!("Failed to update ticket {} analyzing status: {}", request.ticket_id, e);
+    }
+}
+
+/// Close the session of a run paused for an urgent one and put the run back in the queue.
+/// The run keeps its ID and `is_analyzing`; its next attempt opens a new session.
+async fn record_preempted(state: &AppState, job: QueuedJob) {
+    let ticket_id = job.request.ticket_id.clone();
+    let run_id = job.request.run_id.clone().unwrap_or_default();
+    let priority = job.request.priority;
+
+    match state.database.get_active_session_by_ticket(&ticket_id).await {
+        Ok(Some(session)) => {
+            if let Err(e) = state.database.cancel_session(&session.id, "Preempted by an urgent analysis").await {
+                error!("Failed to cancel session {}: {}", session.id, e);
+            }
+        }
+        Ok(None) => {}
+        Err(e) => error!("Failed to get active session of ticket {}: {}", ticket_id, e),
+    }
+
+    let log_entry = crate::log_normalizer::LogNormalizer::new().normalize(
+        "⏸️ Tạm dừng để nhường chỗ cho phân tích khẩn cấp, sẽ chạy lại khi có slot trống".to_string(),
+        ticket_id.clone(),
+    );
+    state.msg_store.push(log_entry).await;
+
+    let position = {
+        let mut queue = state.job_queue.lock().await;
+        queue.requeue(job);
+        queue.position(&ticket_id)
+    };
+    if let Some(position) = position {
+        announce_queued(state, &ticket_id, &run_id, priority, position, true).await;
+    }
+}
+
+/// Whether the ticket has an analysis running or waiting in the queue
+pub async fn is_active(state: &AppState, ticket_id: &str) -> bool {
+    if state.running_tasks.lock().await.contains_key(ticket_id)
+        || state.job_queue.lock().await.position(ticket_id).is_some()
+    {
+        return true;
+    }
+    state.role.shared_queue() && matches!(state.database.find_job(ticket_id).await, Ok(Some(_)))
+}
+
+/// Where a ticket's analysis stands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnalysisState {
+    /// Never analyzed
+    Idle,
+    Queued,
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+/// Snapshot of a ticket's latest analysis, for clients that poll instead of using the WebSocket
+#[derive(Debug, Clone, Serialize)]
+pub struct AnalysisStatus {
+    pub state: AnalysisState,
+    pub run_id: Option<String>,
+    pub session_id: Option<String>,
+    /// Only known while the run is queued or running
+    pub priority: Option<AnalysisPriority>,
+    /// 1-based place in the queue while queued
+    pub queue_position: Option<usize>,
+    pub started_at: Option<String>,
+    pub completed_at: Option<String>,
+    /// Seconds since the start, or the run's duration once it ended
+    pub elapsed_seconds: Option<i64>,
+    /// Latest progress stage (init, exploring_files, reasoning, writing_answer)
+    pub stage: Option<String>,
+    pub error: Option<String>,
+    /// The ticket's analysis result, once the run completed
+    pub result: Option<String>,
+}
+
+pub async fn analysis_status(state: &AppState, ticket: &TicketRecord) -> anyhow::Result<AnalysisStatus> {
+    let running = state
+        .running_tasks
+        .lock()
+        .await
+        .get(&ticket.id)
+        .map(|running| (running.run_id.clone(), running.priority));
+    let queued = state
+        .job_queue
+        .lock()
+        .await
+        .find(&ticket.id)
+        .map(|(position, job)| (position, job.request.run_id.clone(), job.request.priority));
+    // Otherwise the shared queue knows whether a worker has it
+    let (running, queued) = match (running, queued) {
+        (None, None) if state.role.shared_queue() => match state.database.find_job(&ticket.id).await? {
+            Some((job, Some(position))) => (
+                None,
+                Some((position as usize, Some(job.run_id), AnalysisPriority::from_rank(job.priority))),
+            ),
+            Some((job, None)) => (Some((job.run_id, AnalysisPriority::from_rank(job.priority))), None),
+            None => (None, None),
+        },
+        local => local,
+    };
+
+    let mut status = AnalysisStatus {
+        state: AnalysisState::Idle,
+        run_id: None,
+        session_id: None,
+        priority: None,
+        queue_position: None,
+        started_at: None,
+        completed_at: None,
+        elapsed_seconds: None,
+        stage: None,
+        error: None,
+        result: None,
+    };
+
+    let session = if let Some((position, run_id, priority)) = queued {
+        // Nothing has started yet; a preempted run's cancelled session doesn't describe it either
+        status.state = AnalysisState::Queued;
+        status.run_id = run_id;
+        status.priority = Some(priority);
+        status.queue_position = Some(position);
+        return Ok(status);
+    } else if let Some((run_id, priority)) = running {
+        status.state = AnalysisState::Running;
+        status.priority = Some(priority);
+        let session = state.database.get_session_by_run_id(&ticket.id, &run_id).await?;
+        status.run_id = Some(run_id);
+        session
+    } else {
+        let session = state.database.get_latest_session_by_ticket(&ticket.id).await?;
+        if let Some(session) = &session {
+            status.state = match session.status.as_str() {
+                "completed" => AnalysisState::Completed,
+                "failed" => AnalysisState::Failed,
+                "cancelled" => AnalysisState::Cancelled,
+                _ => AnalysisState::Running,
+            };
+            status.run_id = session.run_id.clone();
+        }
+        session
+    };
+
+    let Some(session) = session else {
+        return Ok(status);
+    };
+
+    let started = chrono::DateTime::parse_from_rfc3339(&session.started_at).ok();
+    let ended = match &session.completed_at {
+        Some(completed_at) => chrono::DateTime::parse_from_rfc3339(completed_at).ok().map(|t| t.to_utc()),
+        None => Some(chrono::Utc::now()),
+    };
+    status.elapsed_seconds = started.zip(ended).map(|(started, ended)| (ended - started.to_utc()).num_seconds());
+    status.stage = state
+        .database
+        .get_session_stages(&session.id)
+        .await?
+        .pop()
+        .map(|stage| stage.stage);
+    if status.state == AnalysisState::Completed {
+        status.result = state.database.full_result(ticket).await?;
+    }
+    status.session_id = Some(session.id);
+    status.started_at = Some(session.started_at);
+    status.completed_at = session.completed_at;
+    status.error = session.error_message;
+
+    Ok(status)
+}
+
+/// Ask the ticket's running analysis to stop; its agent kills its process and the run records
+/// the cancellation. A run that hasn't stopped after `CANCEL_GRACE` is aborted and recorded here.
+/// A queued analysis is simply dropped from the queue.
+/// Returns the run ID, or None when nothing runs or waits for the ticket.
+pub async fn cancel_analysis(state: &AppState, ticket_id: &str) -> Option<String> {
+    let running = {
+        let mut tasks = state.running_tasks.lock().await;
+        tasks.get_mut(ticket_id).map(|running| {
+            // A stop request wins over a pending preemption: the run must not come back
+            running.preempted = false;
+            running.cancel.cancel();
+            (running.run_id.clone(), running.abort.clone())
+        })
+    };
+    let Some((run_id, abort)) = running else {
+        let Some(job) = state.job_queue.lock().await.remove(ticket_id) else {
+            return crate::worker::cancel(state, ticket_id).await;
+        };
+        let run_id = job.request.run_id.unwrap_or_default();
+        info!("⛔ Đã bỏ phân tích ticket {} khỏi hàng đợi (run {})", ticket_id, run_id);
+        record_stopped(&state.database, &state.msg_store, &state.broadcast_tx, ticket_id).await;
+        state.msg_store.end_run(ticket_id, &run_id).await;
+        return Some(run_id);
+    };
+    info!("⛔ Đã yêu cầu dừng phân tích ticket {} (run {})", ticket_id, run_id);
+
+    let supervisor = state.tasks.clone();
+    let state = state.clone();
+    let ticket_id = ticket_id.to_string();
+    let fallback_run_id = run_id.clone();
+    supervisor.spawn(format!("stop {}", ticket_id), async move {
+        tokio::time::sleep(CANCEL_GRACE).await;
+        if abort.is_finished() {
+            return;
+        }
+        warn!("⚠️ Agent không dừng sau {}s, huỷ task của ticket {}", CANCEL_GRACE.as_secs(), ticket_id);
+        abort.abort();
+        record_stopped(&state.database, &state.msg_store, &state.broadcast_tx, &ticket_id).await;
+        state.msg_store.end_run(&ticket_id, &fallback_run_id).await;
+        forget_run(&mut *state.running_tasks.lock().await, &ticket_id, &fallback_run_id);
+        dispatch(&state).await;
+    });
+
+    Some(run_id)
+}
+
+/// Record that the ticket's analysis stopped on request: cancel its active session, clear
+/// `is_analyzing`, add a final log entry and tell clients. Returns the stopped session's run ID.
+pub async fn record_stopped(
+    database: &Database,
+    msg_store: &MsgStore,
+    broadcast_tx: &broadcast::Sender<BroadcastMessage>,
+    ticket_id: &str,
+) -> Option<String> {
+    let mut run_id = None;
+    match database.get_active_session_by_ticket(ticket_id).await {
+        Ok(Some(session)) => {
+            if let Err(e) = database.cancel_session(&session.id, "Cancelled by user").await {
+                error!("Failed to cancel session {}: {}", session.id, e);
+            }
+            run_id = session.run_id;
+        }
+        Ok(None) => {}
+        Err(e) => error!("Failed to get active session of ticket {}: {}", ticket_id, e),
+    }
+    if let Err(e) = database.update_ticket_analyzing(ticket_id, false).await {
+        error!("Failed to update ticket {} analyzing status: {}", ticket_id, e);
+    }
+
+    let log_entry = crate::log_normalizer::LogNormalizer::new()
+        .normalize("⛔ Đã dừng phân tích theo yêu cầu".to_string(), ticket_id.to_string());
+    msg_store.push(log_entry).await;
+
+    let _ = broadcast_tx.send(BroadcastMessage {
+        ticket_id: ticket_id.to_string(),
+        message_type: "analysis-stopped".to_string(),
+        content: "Analysis stopped by user".to_string(),
+        timestamp: chrono::Utc::now(),
+        org_id: None,
+        changed_files: None,
+    });
+
+    info!("⛔ Đã dừng phân tích ticket {}", ticket_id);
+    run_id
+}
+
+/// Parse a `testcases` run's answer into the ticket's test cases and tell clients how it went
+async fn store_test_cases(
+    database: &Database,
+    broadcast_tx: &broadcast::Sender<BroadcastMessage>,
+    request: &CodeAnalysisRequest,
+    result: &str,
+) {
+    let outcome = match test_cases::parse_test_cases(result) {
+        Ok(cases) => {
+            let records = test_cases::to_records(&request.ticket_id, request.run_id.as_deref(), cases);
+            database
+                .replace_test_cases(&request.ticket_id, &records)
+                .await
+                .map(|_| records.len())
+                .map_err(|e| e.to_string())
+        }
+        Err(e) => Err(e.to_string()),
+    };
+
+    let (message_type, content) = match outcome {
+        Ok(count) => {
+            info!("🧪 Đã lưu {} test case cho ticket {}", count, request.ticket_id);
+            ("test-cases-generated", count.to_string())
+        }
+        Err(e) => {
+            warn!("⚠️ Không đọc được test case cho ticket {}: {}", request.ticket_id, e);
+            ("test-cases-error", e)
+        }
+    };
+
+    let _ = broadcast_tx.send(BroadcastMessage {
+        ticket_id: request.ticket_id.clone(),
+        message_type: message_type.to_string(),
+        content,
+        timestamp: chrono::Utc::now(),
+        org_id: None,
+        changed_files: None,
+    });
+}
+
+/// Extract a `diagram` run's Mermaid diagram into the ticket's diagram and tell clients how it went
+async fn store_diagram(
+    database: &Database,
+    broadcast_tx: &broadcast::Sender<BroadcastMessage>,
+    request: &CodeAnalysisRequest,
+    result: &str,
+) {
+    let outcome = match crate::diagram::extract(result) {
+        Ok(diagram) => {
+            let record = crate::diagram::to_record(&request.ticket_id, request.run_id.as_deref(), diagram);
+            database
+                .replace_ticket_diagram(&record)
+                .await
+                .map(|_| record.kind)
+                .map_err(|e| e.to_string())
+        }
+        Err(e) => Err(e.to_string()),
+    };
+
+    let (message_type, content) = match outcome {
+        Ok(kind) => {
+            info!("🗺️ Đã lưu diagram {} cho ticket {}", kind, request.ticket_id);
+            ("diagram-generated", kind)
+        }
+        Err(e) => {
+            warn!("⚠️ Không đọc được diagram cho ticket {}: {}", request.ticket_id, e);
+            ("diagram-error", e)
+        }
+    };
+
+    let _ = broadcast_tx.send(BroadcastMessage {
+        ticket_id: request.ticket_id.clone(),
+        message_type: message_type.to_string(),
+        content,
+        timestamp: chrono::Utc::now(),
+        org_id: None,
+        changed_files: None,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_agent::{MockAgent, MockAgentConfig};
+    use crate::test_support::{project_fixture, temp_dir, test_database, test_state, ticket_fixture};
+
+    fn request(ticket_id: &str, run_id: &str) -> CodeAnalysisRequest {
+        CodeAnalysisRequest {
+            ticket_id: ticket_id.to_string(),
+            code_context: String::new(),
+            question: "How does login work?".to_string(),
+            project_id: "p1".to_string(),
+            run_id: Some(run_id.to_string()),
+            agent_type: None,
+            mode: Default::default(),
+            path_rules: Default::default(),
+            tool_policy: Default::default(),
+            priority: Default::default(),
+            include_linked_results: false,
+            budget_override: false,
+            replay: None,
+            working_dir: None,
+            prompt_variant: None,
+            response_language: None,
+            api_key: None,
+            then: Vec::new(),
+        }
+    }
+
+    async fn setup(dir: &Path, tickets: &[&str]) -> AppState {
+        let database = test_database(dir).await.unwrap();
+        database.create_project(&project_fixture("p1")).await.unwrap();
+        for id in tickets {
+            database
+                .create_ticket(&TicketRecord {
+                    // A previous run's result, shown only while the latest run completed
+                    analysis_result: Some("Earlier answer".to_string()),
+                    ..ticket_fixture(id, "p1")
+                })
+                .await
+                .unwrap();
+        }
+        let agent = Arc::new(MockAgent::with_config(MockAgentConfig::from_env()));
+        test_state(database, agent).unwrap()
+    }
+
+    async fn status(state: &AppState, ticket_id: &str) -> AnalysisStatus {
+        let ticket = state.database.get_ticket(ticket_id).await.unwrap().unwrap();
+        analysis_status(state, &ticket).await.unwrap()
+    }
+
+    async fn session(state: &AppState, ticket_id: &str, run_id: &str) -> String {
+        state
+            .database
+            .create_session(ticket_id, "claude", Some(run_id), &Default::default(), Default::default())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_analysis_status_states() {
+        let dir = temp_dir("analysis-status").unwrap();
+        let state = setup(&dir, &["idle", "queued", "running", "completed", "failed", "cancelled"]).await;
+
+        let idle = status(&state, "idle").await;
+        assert_eq!(idle.state, AnalysisState::Idle);
+        assert!(idle.session_id.is_none() && idle.result.is_none());
+
+        // Queued behind an earlier job, even with a cancelled session from before
+        let old = session(&state, "queued", "run-old").await;
+        state.database.cancel_session(&old, "Preempted").await.unwrap();
+        {
+            let mut queue = state.job_queue.lock().await;
+            queue.push(request("running", "run-ahead"), None);
+            queue.push(request("queued", "run-q"), None);
+        }
+        let queued = status(&state, "queued").await;
+        assert_eq!(queued.state, AnalysisState::Queued);
+        assert_eq!((queued.queue_position, queued.run_id.as_deref()), (Some(2), Some("run-q")));
+        assert_eq!(queued.priority, Some(AnalysisPriority::Normal));
+        assert!(queued.session_id.is_none() && queued.result.is_none());
+        state.job_queue.lock().await.remove("running");
+
+        let running_session = session(&state, "running", "run-r").await;
+        state.database.record_session_stage(&running_session, "reasoning").await.unwrap();
+        let task = tokio::spawn(std::future::pending::<()>());
+        state.running_tasks.lock().await.insert(
+            "running".to_string(),
+            RunningAnalysis {
+                run_id: "run-r".to_string(),
+                priority: AnalysisPriority::Urgent,
+                started: Instant::now(),
+                preempted: false,
+                cancel: CancellationToken::new(),
+                abort: task.abort_handle(),
+            },
+        );
+        let running = status(&state, "running").await;
+        assert_eq!(running.state, AnalysisState::Running);
+        assert_eq!(running.session_id.as_deref(), Some(running_session.as_str()));
+        assert_eq!((running.stage.as_deref(), running.priority), (Some("reasoning"), Some(AnalysisPriority::Urgent)));
+        assert!(running.completed_at.is_none() && running.elapsed_seconds.is_some() && running.result.is_none());
+        task.abort();
+
+        let completed = session(&state, "completed", "run-c").await;
+        state.database.complete_session(&completed, "").await.unwrap();
+        state.database.update_ticket_result("completed", "Login goes through auth.rs").await.unwrap();
+        let status_completed = status(&state, "completed").await;
+        assert_eq!(status_completed.state, AnalysisState::Completed);
+        assert_eq!(status_completed.run_id.as_deref(), Some("run-c"));
+        assert_eq!(status_completed.result.as_deref(), Some("Login goes through auth.rs"));
+        assert!(status_completed.completed_at.is_some());
+
+        let failed = session(&state, "failed", "run-f").await;
+        state.database.fail_session(&failed, "Agent exited with code 3").await.unwrap();
+        let status_failed = status(&state, "failed").await;
+        assert_eq!(status_failed.state, AnalysisState::Failed);
+        assert_eq!(status_failed.error.as_deref(), Some("Agent exited with code 3"));
+        assert!(status_failed.result.is_none());
+
+        let cancelled = session(&state, "cancelled", "run-x").await;
+        state.database.cancel_session(&cancelled, "Stopped by user").await.unwrap();
+        let status_cancelled = status(&state, "cancelled").await;
+        assert_eq!(status_cancelled.state, AnalysisState::Cancelled);
+        assert!(status_cancelled.result.is_none());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_analysis_status_from_shared_queue() {
+        let dir = temp_dir("analysis-status").unwrap();
+        let mut state = setup(&dir, &["t1", "t2"]).await;
+        state.role = Role::Api;
+
+        crate::worker::enqueue(&state.database, request("t1", "run-1"), None).await.unwrap();
+        crate::worker::enqueue(&state.database, request("t2", "run-2"), None).await.unwrap();
+        let queued = status(&state, "t2").await;
+        assert_eq!(queued.state, AnalysisState::Queued);
+        assert_eq!((queued.queue_position, queued.run_id.as_deref()), (Some(2), Some("run-2")));
+
+        // A worker claimed t1 and started its session
+        let claimed = state.database.claim_job("w1", "2000-01-01T00:00:00+00:00").await.unwrap().unwrap();
+        assert_eq!(claimed.ticket_id, "t1");
+        let session_id = session(&state, "t1", "run-1").await;
+        let running = status(&state, "t1").await;
+        assert_eq!(running.state, AnalysisState::Running);
+        assert_eq!(running.session_id.as_deref(), Some(session_id.as_str()));
+        assert_eq!(status(&state, "t2").await.queue_position, Some(1));
+
+        // Once its job is gone, the session tells how it ended
+        state.database.complete_session(&session_id, "").await.unwrap();
+        state.database.delete_job(claimed.seq).await.unwrap();
+        assert_eq!(status(&state, "t1").await.state, AnalysisState::Completed);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,706 @@
+use crate::code_agent::{until_cancelled, AnalysisCancelled, CodeAgent, CodeAnalysisRequest, CodeAnalysisResponse};
+use crate::database::Database;
+use crate::log_normalizer::LogNormalizer;
+use crate::message_store::MsgStore;
+use crate::project_files::{self, PathPolicy};
+use crate::retry::{self, Retryable, RetryPolicy};
+use anyhow::Result;
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::time::{timeout, Duration};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info};
+
+/// Bytes of a single file returned by the `read_file` tool
+const TOOL_READ_MAX_BYTES: usize = 60_000;
+/// Entries returned by the `list_files` tool
+const TOOL_LIST_MAX_FILES: usize = 500;
+/// Matches returned by the `search_code` tool
+const TOOL_SEARCH_MAX_RESULTS: usize = 100;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ApiAgentError {
+    #[error("Request timeout after {0}s")]
+    Timeout(u64),
+    #[error("{0} API key is not configured")]
+    MissingApiKey(&'static str),
+    #[error("API request failed: {0}")]
+    Request(String),
+    #[error("API returned HTTP {0}: {1}")]
+    HttpStatus(u16, String),
+    #[error("Unexpected API response: {0}")]
+    InvalidResponse(String),
+    #[error("Model did not finish within {0} tool turns")]
+    TooManyTurns(u32),
+    #[error("Working directory not accessible: {0}")]
+    DirectoryNotAccessible(String),
+}
+
+impl Retryable for ApiAgentError {
+    /// Transport errors, rate limits and server errors; 4xx such as a rejected key won't change
+    fn is_retryable(&self) -> bool {
+        match self {
+            Self::Timeout(_) | Self::Request(_) => true,
+            Self::HttpStatus(status, _) => *status == 429 || *status >= 500,
+            Self::MissingApiKey(_)
+            | Self::InvalidResponse(_)
+            | Self::TooManyTurns(_)
+            | Self::DirectoryNotAccessible(_) => false,
+        }
+    }
+}
+
+/// Hosted model API spoken by an [`ApiAgent`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiProvider {
+    /// Anthropic Messages API
+    Anthropic,
+    /// Gemini `generateContent` REST API
+    Gemini,
+}
+
+impl ApiProvider {
+    /// Identifier recorded on analysis sessions
+    pub fn agent_id(&self) -> &'static str {
+        match self {
+            Self::Anthropic => "claude-api",
+            Self::Gemini => "gemini-api",
+        }
+    }
+
+    fn display_name(&self) -> &'static str {
+        match self {
+            Self::Anthropic => "Anthropic",
+            Self::Gemini => "Gemini",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ApiAgentConfig {
+    pub provider: ApiProvider,
+    pub api_key: Option<String>,
+    pub model: String,
+    pub base_url: String,
+    /// Timeout for the whole analysis, all tool turns included
+    pub timeout_seconds: u64,
+    /// Attempts per HTTP request on transport errors, 429 and 5xx
+    pub retry: RetryPolicy,
+    /// Model round-trips allowed before giving up
+    pub max_turns: u32,
+    pub max_output_tokens: u32,
+    pub working_dir: Option<String>,
+}
+
+impl ApiAgentConfig {
+    pub fn from_env(provider: ApiProvider) -> Self {
+        let (api_key, model, base_url) = match provider {
+            ApiProvider::Anthropic => (
+                std::env::var("ANTHROPIC_API_KEY").ok(),
+                std::env::var("ANTHROPIC_MODEL").unwrap_or_else(|_| "claude-sonnet-4-20250514".to_string()),
+                std::env::var("ANTHROPIC_API_URL").unwrap_or_else(|_| "https://api.anthropic.com".to_string()),
+            ),
+            ApiProvider::Gemini => (
+                std::env::var("GEMINI_API_KEY").ok(),
+                std::env::var("GEMINI_API_MODEL").unwrap_or_else(|_| "gemini-2.5-pro".to_string()),
+                std::env::var("GEMINI_API_URL")
+                    .unwrap_or_else(|_| "https://generativelanguage.googleapis.com".to_string()),
+            ),
+        };
+
+        Self {
+            provider,
+            api_key: api_key.filter(|k| !k.trim().is_empty()),
+            model,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            timeout_seconds: std::env::var("API_AGENT_TIMEOUT")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(300),
+            retry: RetryPolicy::from_env("API_AGENT"),
+            max_turns: std::env::var("API_AGENT_MAX_TURNS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(25),
+            max_output_tokens: 8192,
+            working_dir: std::env::var("API_AGENT_WORKING_DIR").ok(),
+        }
+    }
+}
+
+/// A tool call requested by the model
+#[derive(Debug, Clone, PartialEq)]
+struct ToolCall {
+    id: String,
+    name: String,
+    args: Value,
+}
+
+/// One model response, reduced to what the tool loop needs
+#[derive(Debug, Default, PartialEq)]
+struct Turn {
+    text: String,
+    tool_calls: Vec<ToolCall>,
+}
+
+/// Code agent calling a hosted model API directly, with its own file tools,
+/// so no CLI has to be installed on the server
+#[derive(Debug)]
+pub struct ApiAgent {
+    config: ApiAgentConfig,
+    client: reqwest::Client,
+}
+
+impl ApiAgent {
+    pub fn with_config(config: ApiAgentConfig) -> Self {
+        Self {
+            config,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub async fn analyze_code(
+        &self,
+        request: CodeAnalysisRequest,
+        msg_store: Arc<MsgStore>,
+        database: Arc<Database>,
+        cancel: CancellationToken,
+    ) -> Result<CodeAnalysisResponse> {
+        info!("🚀 Bắt đầu phân tích code cho ticket: {}", request.ticket_id);
+
+        // Check if ticket exists, auto-create if not to prevent FK constraint failure
+        let ticket = database.get_ticket(&request.ticket_id).await?;
+        if ticket.is_none() {
+            info!("🔧 Ticket {} chưa tồn tại, tự động tạo ticket", request.ticket_id);
+
+            let auto_ticket = crate::database::TicketRecord {
+                id: request.ticket_id.clone(),
+                project_id: request.project_id.clone(),
+                title: "Auto-created".to_string(),
+                description: request.question.clone(),
+                status: "in-progress".to_string(),
+                code_context: Some(request.code_context.clone()),
+                analysis_result: None,
+                is_analyzing: true,
+                created_at: chrono::Utc::now().to_rfc3339(),
+                updated_at: chrono::Utc::now().to_rfc3339(),
+                stale: false,
+                analysis_result_blob: None,
+                analysis_result_size: None,
+                position: 0.0,
+                assignee_id: None,
+                summary: None,
+                mode: request.mode,
+            };
+
+            database.create_ticket(&auto_ticket).await?;
+            info!("✅ Đã tự động tạo ticket: {}", request.ticket_id);
+        }
+
+        let session_id = database
+            .create_session(
+                &request.ticket_id,
+                self.config.provider.agent_id(),
+                request.run_id.as_deref(),
+                &request.tool_policy,
+                request.mode,
+            )
+            .await?;
+
+        database
+            .update_ticket_analyzing(&request.ticket_id, true)
+            .await?;
+
+        let mut logs = Vec::new();
+        let normalizer = LogNormalizer::new();
+
+        let start_log = format!(
+            "🔄 Khởi động {} API agent (model: {})...",
+            self.config.provider.display_name(),
+            self.config.model
+        );
+        msg_store
+            .push(normalizer.normalize(start_log.clone(), request.ticket_id.clone()))
+            .await;
+        logs.push(start_log);
+
+        let working_directory = crate::code_agent::working_directory(&request, &database).await;
+
+        let analysis = self.run_tool_loop(&request, working_directory, &msg_store, &normalizer);
+        let analysis = until_cancelled(&cancel, analysis);
+        let outcome = match timeout(Duration::from_secs(self.config.timeout_seconds), analysis).await {
+            Ok(outcome) => outcome,
+            Err(_) => Err(ApiAgentError::Timeout(self.config.timeout_seconds).into()),
+        };
+
+        let result = match outcome {
+            Ok(output) => {
+                info!("✅ {} API agent hoàn thành phân tích", self.config.provider.display_name());
+
+                let completion_log = "✅ Phân tích hoàn tất!";
+                let mut entry = normalizer.normalize(
+                    completion_log.to_string(),
+                    request.ticket_id.clone(),
+                );
+                entry.message_type = crate::message_store::LogMessageType::Result;
+                msg_store.push(entry).await;
+                logs.push(completion_log.to_string());
+
+                let output = msg_store.redactor().redact_text(output);
+                database.complete_session(&session_id, "Success").await?;
+                database
+                    .update_ticket_result(&request.ticket_id, &output)
+                    .await?;
+
+                output
+            }
+            Err(e) if e.is::<AnalysisCancelled>() => {
+                info!("⛔ Đã dừng {} API agent cho ticket {}", self.config.provider.display_name(), request.ticket_id);
+                return Err(e);
+            }
+            Err(e) => {
+                error!("❌ Lỗi khi thực thi {} API agent: {}", self.config.provider.display_name(), e);
+
+                let error_log = format!("❌ Lỗi: {}", e);
+                let entry = normalizer.normalize(error_log.clone(), request.ticket_id.clone());
+                msg_store.push(entry).await;
+                logs.push(error_log);
+
+                database.fail_session(&session_id, &e.to_string()).await?;
+                database
+                    .update_ticket_analyzing(&request.ticket_id, false)
+                    .await?;
+
+                format!("Không thể phân tích code do lỗi: {}", e)
+            }
+        };
+
+        Ok(CodeAnalysisResponse {
+            ticket_id: request.ticket_id,
+            result,
+            logs,
+            success: true,
+        })
+    }
+
+    async fn run_tool_loop(
+        &self,
+        request: &CodeAnalysisRequest,
+        working_directory: Option<String>,
+        msg_store: &Arc<MsgStore>,
+        normalizer: &LogNormalizer,
+    ) -> Result<String> {
+        let api_key = request
+            .api_key
+            .as_ref()
+            .map(|key| key.expose().to_string())
+            .or_else(|| self.config.api_key.clone())
+            .ok_or(ApiAgentError::MissingApiKey(self.config.provider.display_name()))?;
+
+        let root = working_directory
+            .or(self.config.working_dir.clone())
+            .map(PathBuf::from);
+        if let Some(dir) = &root {
+            if let Err(e) = tokio::fs::metadata(dir).await {
+                error!("⚠️ Không thể access directory {}: {}", dir.display(), e);
+                return Err(ApiAgentError::DirectoryNotAccessible(dir.display().to_string()).into());
+            }
+        }
+
+        // Enforced on every tool call, whatever the model asks for
+        let policy = Arc::new(request.path_rules.compile()?);
+
+        let mut history = vec![self.user_message(&create_analysis_prompt(request))];
+
+        for turn_index in 1..=self.config.max_turns {
+            debug!("Model turn {}/{}", turn_index, self.config.max_turns);
+            let response = self.send_with_retry(&api_key, &history).await?;
+            let turn = self.parse_turn(&response)?;
+            history.push(self.assistant_message(&response)?);
+
+            if !turn.text.trim().is_empty() {
+                let event = json!({ "type": "message", "role": "assistant", "content": turn.text });
+                msg_store
+                    .push(normalizer.normalize(event.to_string(), request.ticket_id.clone()))
+                    .await;
+            }
+
+            if turn.tool_calls.is_empty() {
+                return Ok(turn.text);
+            }
+
+            let mut results = Vec::with_capacity(turn.tool_calls.len());
+            for call in turn.tool_calls {
+                let event = json!({
+                    "type": "tool_use",
+                    "tool_name": call.name,
+                    "tool_id": call.id,
+                    "parameters": call.args,
+                });
+                msg_store
+                    .push(normalizer.normalize(event.to_string(), request.ticket_id.clone()))
+                    .await;
+
+                let output = match &root {
+                    _ if !request.tool_policy.allows(tool_permission(&call.name)) => {
+                        Err(format!("Tool {} is not allowed by the project's tool policy", call.name))
+                    }
+                    Some(root) => {
+                        let (root, name, args) = (root.clone(), call.name.clone(), call.args.clone());
+                        let policy = policy.clone();
+                        tokio::task::spawn_blocking(move || run_tool(&root, &name, &args, &policy)).await?
+                    }
+                    None => Err("No project directory is configured for this ticket".to_string()),
+                };
+
+                let event = json!({
+                    "type": "tool_result",
+                    "tool_id": call.id,
+                    "status": if output.is_ok() { "success" } else { "error" },
+                });
+                msg_store
+                    .push(normalizer.normalize(event.to_string(), request.ticket_id.clone()))
+                    .await;
+
+                results.push((call, output));
+            }
+            history.push(self.tool_results_message(&results));
+        }
+
+        Err(ApiAgentError::TooManyTurns(self.config.max_turns).into())
+    }
+
+    async fn send_with_retry(&self, api_key: &str, history: &[Value]) -> Result<Value> {
+        retry::run(&self.config.retry, "API request", retry::classify::<ApiAgentError>, |_| async {
+            Ok(self.send(api_key, SYSTEM_PROMPT, true, history).await?)
+        })
+        .await
+    }
+
+    /// One reply to `prompt` without tools or a working directory, e.g. a result summary
+    pub async fn complete(&self, api_key: &str, system: &str, prompt: &str) -> Result<String> {
+        let history = [self.user_message(prompt)];
+        let response = timeout(
+            Duration::from_secs(self.config.timeout_seconds),
+            retry::run(&self.config.retry, "API request", retry::classify::<ApiAgentError>, |_| async {
+                Ok(self.send(api_key, system, false, &history).await?)
+            }),
+        )
+        .await
+        .map_err(|_| ApiAgentError::Timeout(self.config.timeout_seconds))??;
+
+        Ok(self.parse_turn(&response)?.text)
+    }
+
+    async fn send(&self, api_key: &str, system: &str, tools: bool, history: &[Value]) -> Result<Value, ApiAgentError> {
+        let request = match self.config.provider {
+            ApiProvider::Anthropic => {
+                let mut body = json!({
+                    "model": self.config.model,
+                    "max_tokens": self.config.max_output_tokens,
+                    "system": system,
+                    "messages": history,
+                });
+                if tools {
+                    body["tools"] = json!(anthropic_tools());
+                }
+                self.client
+                    .post(format!("{}/v1/messages", self.config.base_url))
+                    .header("x-api-key", api_key)
+                    .header("anthropic-version", "2023-06-01")
+                    .json(&body)
+            }
+            ApiProvider::Gemini => {
+                let mut body = json!({
+                    "systemInstruction": { "parts": [{ "text": system }] },
+                    "generationConfig": { "maxOutputTokens": self.config.max_output_tokens },
+                    "contents": history,
+                });
+                if tools {
+                    body["tools"] = json!([{ "functionDeclarations": gemini_tools() }]);
+                }
+                self.client
+                    .post(format!(
+                        "{}/v1beta/models/{}:generateContent",
+                        self.config.base_url, self.config.model
+                    ))
+                    .header("x-goog-api-key", api_key)
+                    .json(&body)
+            }
+        };
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| ApiAgentError::Request(e.to_string()))?;
+        let status = response.status();
+        let body = response
+            .text()
+            .await
+            .map_err(|e| ApiAgentError::Request(e.to_string()))?;
+
+        if !status.is_success() {
+            return Err(ApiAgentError::HttpStatus(status.as_u16(), body));
+        }
+        serde_json::from_str(&body).map_err(|e| ApiAgentError::InvalidResponse(e.to_string()))
+    }
+
+    fn user_message(&self, text: &str) -> Value {
+        match self.config.provider {
+            ApiProvider::Anthropic => json!({ "role": "user", "content": text }),
+            ApiProvider::Gemini => json!({ "role": "user", "parts": [{ "text": text }] }),
+        }
+    }
+
+    /// The model's reply, echoed back verbatim in the next request
+    fn assistant_message(&self, response: &Value) -> Result<Value> {
+        let message = match self.config.provider {
+            ApiProvider::Anthropic => response
+                .get("content")
+                .map(|content| json!({ "role": "assistant", "content": content })),
+            ApiProvider::Gemini => response.pointer("/candidates/0/content").cloned(),
+        };
+        message.ok_or_else(|| ApiAgentError::InvalidResponse("missing message content".to_string()).into())
+    }
+
+    fn parse_turn(&self, response: &Value) -> Result<Turn> {
+        match self.config.provider {
+            ApiProvider::Anthropic => parse_anthropic_turn(response),
+            ApiProvider::Gemini => parse_gemini_turn(response),
+        }
+    }
+
+    fn tool_results_message(&self, results: &[(ToolCall, Result<String, String>)]) -> Value {
+        match self.config.provider {
+            ApiProvider::Anthropic => json!({
+                "role": "user",
+                "content": results.iter().map(|(call, output)| json!({
+                    "type": "tool_result",
+                    "tool_use_id": call.id,
+                    "content": output.as_ref().unwrap_or_else(|e| e),
+                    "is_error": output.is_err(),
+                })).collect::<Vec<_>>(),
+            }),
+            ApiProvider::Gemini => json!({
+                "role": "user",
+                "parts": results.iter().map(|(call, output)| json!({
+                    "functionResponse": {
+                        "name": call.name,
+                        "response": match output {
+                            Ok(content) => json!({ "content": content }),
+                            Err(error) => json!({ "error": error }),
+                        },
+                    }
+                })).collect::<Vec<_>>(),
+            }),
+        }
+    }
+}
+
+fn parse_anthropic_turn(response: &Value) -> Result<Turn> {
+    let blocks = response
+        .get("content")
+        .and_then(|v| v.as_array())
+        .ok_or_else(|| ApiAgentError::InvalidResponse("missing content blocks".to_string()))?;
+
+    let mut turn = Turn::default();
+    for block in blocks {
+        match block.get("type").and_then(|v| v.as_str()) {
+            Some("text") => turn.text.push_str(block.get("text").and_then(|v| v.as_str()).unwrap_or("")),
+            Some("tool_use") => turn.tool_calls.push(ToolCall {
+                id: block.get("id").and_then(|v| v.as_str()).unwrap_or("").to_string(),
+                name: block.get("name").and_then(|v| v.as_str()).unwrap_or("").to_string(),
+                args: block.get("input").cloned().unwrap_or(Value::Null),
+            }),
+            _ => {}
+        }
+    }
+    Ok(turn)
+}
+
+fn parse_gemini_turn(response: &Value) -> Result<Turn> {
+    let parts = response
+        .pointer("/candidates/0/content/parts")
+        .and_then(|v| v.as_array())
+        .ok_or_else(|| ApiAgentError::InvalidResponse("missing candidate parts".to_string()))?;
+
+    let mut turn = Turn::default();
+    for (index, part) in parts.iter().enumerate() {
+        if let Some(text) = part.get("text").and_then(|v| v.as_str()) {
+            turn.text.push_str(text);
+        }
+        if let Some(call) = part.get("functionCall") {
+            turn.tool_calls.push(ToolCall {
+                // Gemini has no call IDs; responses are matched by name and order
+                id: format!("call-{}", index),
+                name: call.get("name").and_then(|v| v.as_str()).unwrap_or("").to_string(),
+                args: call.get("args").cloned().unwrap_or(Value::Null),
+            });
+        }
+    }
+    Ok(turn)
+}
+
+/// Execute a file tool inside `root`, limited to the files `policy` allows; errors are returned
+/// to the model, not raised
+fn run_tool(root: &std::path::Path, name: &str, args: &Value, policy: &PathPolicy) -> Result<String, String> {
+    let arg = |key: &str| args.get(key).and_then(|v| v.as_str()).unwrap_or("");
+
+    match name {
+        "list_files" => project_files::list_project_files(root, arg("path"), TOOL_LIST_MAX_FILES, policy)
+            .map(|files| files.join("\n")),
+        "read_file" => project_files::read_project_file(root, arg("path"), TOOL_READ_MAX_BYTES, policy),
+        "search_code" => project_files::search_project(root, arg("query"), TOOL_SEARCH_MAX_RESULTS, policy)
+            .map(|hits| if hits.is_empty() { "No matches".to_string() } else { hits.join("\n") }),
+        _ => return Err(format!("Unknown tool: {}", name)),
+    }
+    .map_err(|e| e.to_string())
+}
+
+/// Name of a file tool in the project tool policy (`tool_policy::KNOWN_TOOLS`)
+fn tool_permission(name: &str) -> &str {
+    match name {
+        "list_files" => "LS",
+        "read_file" => "Read",
+        "search_code" => "Grep",
+        other => other,
+    }
+}
+
+/// Tool name, description and JSON schema of its parameters
+fn tool_specs() -> Vec<(&'static str, &'static str, Value)> {
+    let path_param = |description: &str| {
+        json!({
+            "type": "object",
+            "properties": { "path": { "type": "string", "description": description } },
+            "required": ["path"],
+        })
+    };
+
+    vec![
+        (
+            "list_files",
+            "List source files under a directory of the project.",
+            path_param("Directory relative to the project root; use \".\" for the root"),
+        ),
+        (
+            "read_file",
+            "Read a source file of the project.",
+            path_param("File path relative to the project root"),
+        ),
+        (
+            "search_code",
+            "Case-insensitive text search across the project's source files. Returns path:line: text matches.",
+            json!({
+                "type": "object",
+                "properties": { "query": { "type": "string", "description": "Text to search for" } },
+                "required": ["query"],
+            }),
+        ),
+    ]
+}
+
+fn anthropic_tools() -> Vec<Value> {
+    tool_specs()
+        .into_iter()
+        .map(|(name, description, schema)| {
+            json!({ "name": name, "description": description, "input_schema": schema })
+        })
+        .collect()
+}
+
+fn gemini_tools() -> Vec<Value> {
+    tool_specs()
+        .into_iter()
+        .map(|(name, description, schema)| {
+            json!({ "name": name, "description": description, "parameters": schema })
+        })
+        .collect()
+}
+
+const SYSTEM_PROMPT: &str = "You are a senior engineer helping QA testers understand a codebase. \
+Use the tools to explore the project before answering. Explain business flows in plain language \
+and reference the file paths you read.";
+
+fn create_analysis_prompt(request: &CodeAnalysisRequest) -> String {
+    if request.code_context.is_empty() {
+        format!(
+            "Analyze the code to help QA understand the business flow. Question: {}",
+            request.prompt_question()
+        )
+    } else {
+        format!(
+            "Analyze the code in {} to help QA understand the business flow. Question: {}",
+            request.code_context, request.prompt_question()
+        )
+    }
+}
+
+#[async_trait]
+impl CodeAgent for ApiAgent {
+    async fn analyze_code(
+        &self,
+        request: CodeAnalysisRequest,
+        msg_store: Arc<MsgStore>,
+        database: Arc<Database>,
+        cancel: CancellationToken,
+    ) -> Result<CodeAnalysisResponse> {
+        self.analyze_code(request, msg_store, database, cancel).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_anthropic_turn() {
+        let response = json!({
+            "content": [
+                { "type": "text", "text": "Let me look." },
+                { "type": "tool_use", "id": "toolu_1", "name": "read_file", "input": { "path": "src/a.rs" } }
+            ],
+            "stop_reason": "tool_use"
+        });
+
+        let turn = parse_anthropic_turn(&response).unwrap();
+        assert_eq!(turn.text, "Let me look.");
+        assert_eq!(
+            turn.tool_calls,
+            vec![ToolCall {
+                id: "toolu_1".to_string(),
+                name: "read_file".to_string(),
+                args: json!({ "path": "src/a.rs" }),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_gemini_turn() {
+        let response = json!({
+            "candidates": [{
+                "content": {
+                    "role": "model",
+                    "parts": [
+                        { "functionCall": { "name": "search_code", "args": { "query": "checkout" } } }
+                    ]
+                }
+            }]
+        });
+
+        let turn = parse_gemini_turn(&response).unwrap();
+        assert!(turn.text.is_empty());
+        assert_eq!(turn.tool_calls[0].name, "search_code");
+        assert_eq!(turn.tool_calls[0].args, json!({ "query": "checkout" }));
+    }
+
+    #[test]
+    fn test_run_tool_stays_in_project() {
+        let root = std::env::temp_dir();
+        let policy = PathPolicy::default();
+        assert!(run_tool(&root, "read_file", &json!({ "path": "../etc/passwd" }), &policy).is_err());
+        assert!(run_tool(&root, "delete_file", &json!({ "path": "x" }), &policy).is_err());
+    }
+}
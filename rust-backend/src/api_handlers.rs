@@ -1,17 +1,176 @@
 use axum::{
     extract::{Path, Query, State},
-    http::StatusCode,
-    response::Json,
+    http::{header, HeaderMap, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Json, Redirect, Response,
+    },
 };
 use chrono::Utc;
+use futures_util::StreamExt;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
 use tracing::{error, info, warn};
 
-use crate::database::{ProjectRecord, StructuredLogRecord, TicketRecord};
+use crate::database::{
+    AgentBreakdown, AlertEventRecord, AlertRuleRecord, AnalysisSession, AnalyticsFilter, CustomFieldRecord, DailyRuns, ExperimentRecord, FeedbackQuality, LogFilter, LogOrder, MigrationStatus, ProjectRecord,
+    ProjectRuns, ProjectSummarySettingsRecord, QuestionTemplateRecord, RedactionPatternRecord, ResultFeedbackRecord, StructuredLogRecord, TestCaseRecord, TicketFileRecord,
+    TicketDiagramRecord, TicketLinkRecord, TicketRecord, TicketWatcherRecord, TrashedProjectRecord,
+};
+use crate::activity::{self, WatchReason};
+use crate::agent_credentials::{MaskedCredential, Provider};
+use crate::agent_factory::AgentInfo;
+use crate::alerts::{self, AlertError, AlertRule, AlertRuleInput};
+use crate::analysis_runner;
+use crate::auth::AuthContext;
+use crate::backup::{self, BackupDownload, BackupInfo};
+use crate::board::{self, TicketMove};
+use crate::changed_files::ChangedFile;
+use crate::bootstrap::{self, Bootstrapped};
+use crate::budgets::{self, Budget, BudgetStatus};
+use crate::code_agent::{AnalysisMode, CodeAnalysisRequest};
+use crate::context_resolver::{self, ResolvedFile};
+use crate::coverage::{self, ProjectCoverage};
+use crate::custom_fields::{self, FieldError, FieldType, FieldValues, TicketWithFields};
+use crate::dashboard::{self, ProjectDashboard};
+use crate::db_maintenance::{self, DatabaseStats, MaintenanceReport, MaintenanceTask};
+use crate::experiments::{self, Experiment, ExperimentError, ExperimentReport, NewVariant};
+use crate::feedback::{self, FeedbackError, Rating};
+use crate::idempotency::{self, IdempotencyKey};
+use crate::job_queue::AnalysisPriority;
+use crate::log_persistence::LogPersistence;
+use crate::log_replay::Replay;
+use crate::markdown;
+use crate::message_store::{LogMessageType, StructuredLogEntry};
+use crate::post_run::{self, PostRunHooks};
+use crate::preflight::{self, PreflightCheck};
+use crate::presence;
+use crate::project_files::{self, PathPolicy, PathRules};
+use crate::project_roots::RootError;
+use crate::question_templates::{self, TemplateError, TemplateInput};
+use crate::redaction::{self, RedactionPatternInfo};
+use crate::replay::{self, ReplayError};
+use crate::search;
+use crate::session_report::SessionReport;
+use crate::summary::{self, SummarySettings};
+use crate::tasks::TaskList;
+use crate::test_cases::{self, GherkinGrouping};
+use crate::ticket_links::{self, LinkError, LinkType, TicketGraph};
+use crate::ticket_title;
+use crate::time_tracking::{self, TicketTime, TimeAnalytics};
+use crate::timeline::{self, TicketTimeline};
+use crate::tool_policy;
+use crate::trash;
+use crate::project_transfer::{self, ConflictStrategy, ImportError, ImportOptions, ProjectExport};
 use crate::AppState;
 
 // Request/Response types
+#[derive(Debug, Deserialize)]
+pub struct ExportQueryParams {
+    #[serde(default)]
+    pub include_logs: bool,
+    /// Upload to object storage and answer with a download link instead of the export
+    #[serde(default)]
+    pub link: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExportLinkResponse {
+    pub url: String,
+    pub expires_at: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TestCaseExportFormat {
+    #[default]
+    Csv,
+    Gherkin,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionRawFormat {
+    #[default]
+    Json,
+    /// The stored JSON lines as they are, gzipped
+    Gz,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SessionRawParams {
+    #[serde(default)]
+    pub format: SessionRawFormat,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SessionRawResponse {
+    pub session_id: String,
+    pub prompt: String,
+    pub argv: Vec<String>,
+    /// Names only; values are never stored
+    pub env_names: Vec<String>,
+    /// JSON lines `{"at_ms", "stream", "line"}`
+    pub transcript: String,
+    pub transcript_size: i64,
+    pub created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TestCaseExportParams {
+    #[serde(default)]
+    pub format: TestCaseExportFormat,
+    /// Gherkin only: one feature per ticket (default) or per flow
+    #[serde(default)]
+    pub group_by: GherkinGrouping,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiagramFormat {
+    /// The diagram with its source and SVG
+    #[default]
+    Json,
+    /// Raw Mermaid source
+    Mmd,
+    /// Rendered SVG image
+    Svg,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DiagramQueryParams {
+    #[serde(default)]
+    pub format: DiagramFormat,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DiagramResponse {
+    #[serde(flatten)]
+    pub diagram: TicketDiagramRecord,
+    /// Whether `svg` can be rendered here (the server has the Mermaid CLI)
+    pub svg_available: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TicketLabelsRequest {
+    pub labels: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportQueryParams {
+    #[serde(default)]
+    pub on_conflict: ConflictStrategy,
+    pub directory_path: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportResponse {
+    pub project: ProjectRecord,
+    pub tickets_imported: usize,
+    pub logs_imported: usize,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct CreateProjectRequest {
     pub name: String,
@@ -26,12 +185,89 @@ pub struct UpdateProjectRequest {
     pub directory_path: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct CreateTicketRequest {
+    /// Generated from the description when empty (`TICKET_TITLE_MODE`)
+    #[serde(default)]
     pub title: String,
     pub description: String,
     pub status: String,
     pub code_context: Option<String>,
+    /// Mode its analyses use unless a run overrides it
+    #[serde(default)]
+    pub mode: AnalysisMode,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TrashedProject {
+    #[serde(flatten)]
+    pub project: TrashedProjectRecord,
+    /// When it is deleted for good; None when TRASH_RETENTION_DAYS=0
+    pub purge_at: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TrashResponse {
+    pub retention_days: i64,
+    pub projects: Vec<TrashedProject>,
+}
+
+/// Overrides for an analysis started over HTTP; the ticket supplies anything left out
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct StartAnalysisRequest {
+    pub question: Option<String>,
+    pub code_context: Option<String>,
+    pub agent_type: Option<String>,
+    /// This run's mode; the ticket's when unset
+    pub mode: Option<AnalysisMode>,
+    /// Make `mode` the ticket's mode, not just this run's
+    #[serde(default)]
+    pub set_ticket_mode: bool,
+    #[serde(default)]
+    pub priority: AnalysisPriority,
+    #[serde(default)]
+    pub include_linked_results: bool,
+    /// Start even though a budget that allows overrides is used up (organization admins)
+    #[serde(default)]
+    pub override_budget: bool,
+}
+
+/// `POST /api/projects/:id/bootstrap`; every server template when `templates` is unset
+#[derive(Debug, Default, Deserialize)]
+pub struct BootstrapProjectRequest {
+    /// Keys of the templates to create
+    pub templates: Option<Vec<String>>,
+    pub agent_type: Option<String>,
+    #[serde(default)]
+    pub priority: AnalysisPriority,
+}
+
+/// `GET /api/question-templates`: the global templates, plus the project's when given
+#[derive(Debug, Deserialize)]
+pub struct QuestionTemplateQueryParams {
+    pub project_id: Option<String>,
+    pub category: Option<String>,
+}
+
+/// `POST /api/question-templates/:id/tickets`
+#[derive(Debug, Default, Deserialize)]
+pub struct TicketFromTemplateRequest {
+    /// Required for global templates; project templates create tickets in their project
+    pub project_id: Option<String>,
+    /// `todo` when unset
+    pub status: Option<String>,
+    /// Start the analysis right away, in the template's mode
+    #[serde(default)]
+    pub start: bool,
+    pub agent_type: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TicketFromTemplate {
+    pub ticket: TicketRecord,
+    pub mode: AnalysisMode,
+    /// Set when the analysis was started
+    pub run_id: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -39,12 +275,91 @@ pub struct UpdateStatusRequest {
     pub status: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct UpdatePositionRequest {
+    /// Column to move into; the ticket's current status when omitted
+    pub status: Option<String>,
+    /// Index within the column, 0 being the top
+    pub index: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TicketListQueryParams {
+    /// A user ID, `me` or `none` for unassigned tickets
+    pub assignee: Option<String>,
+    /// `cf.<field name>=<value>` custom field filters
+    #[serde(flatten)]
+    pub fields: HashMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TicketSearchQuery {
+    /// Words to find, case and accents ignored ("phan tich" matches "Phân tích")
+    pub q: String,
+    /// Only this project's tickets; all of the organization's otherwise
+    pub project_id: Option<String>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateCustomFieldRequest {
+    pub name: String,
+    pub field_type: FieldType,
+    #[serde(default)]
+    pub options: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateExperimentRequest {
+    pub name: String,
+    pub variants: Vec<NewVariant>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateCustomFieldRequest {
+    pub name: String,
+    #[serde(default)]
+    pub options: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AssignTicketRequest {
+    /// None unassigns the ticket
+    pub assignee_id: Option<String>,
+}
+
+/// Content of a `ticket-assigned` broadcast
+#[derive(Debug, Serialize)]
+pub struct TicketAssignment {
+    pub ticket_id: String,
+    pub assignee_id: Option<String>,
+    pub previous_assignee_id: Option<String>,
+    pub assigned_by: Option<String>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct LogsQueryParams {
     pub limit: Option<u64>,
     pub offset: Option<u64>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct LogTailQueryParams {
+    /// Last log ID the caller has; all of the ticket's logs when unset
+    pub after_id: Option<String>,
+    /// Seconds to hold the request when nothing is newer (0 answers at once)
+    pub wait: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LogTailResponse {
+    pub logs: Vec<StructuredLogEntry>,
+    /// `after_id` for the next poll
+    pub next_after_id: Option<String>,
+    /// Whether a run is still going or queued; once false, no more logs are coming
+    pub is_analyzing: bool,
+}
+
 #[derive(Debug, Serialize)]
 pub struct PaginatedLogsResponse {
     pub logs: Vec<StructuredLogRecord>,
@@ -52,9 +367,202 @@ pub struct PaginatedLogsResponse {
     pub has_more: bool,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct ProjectLogsQueryParams {
+    /// Comma-separated message types
+    pub message_type: Option<String>,
+    pub ticket_id: Option<String>,
+    /// Inclusive start, `YYYY-MM-DD` or RFC 3339
+    pub from: Option<String>,
+    /// End, `YYYY-MM-DD` (inclusive) or RFC 3339 (exclusive)
+    pub until: Option<String>,
+    /// Case-insensitive text in the log content
+    pub q: Option<String>,
+    #[serde(default)]
+    pub sort: LogOrder,
+    pub limit: Option<u64>,
+    pub offset: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProjectLogsResponse {
+    pub logs: Vec<StructuredLogRecord>,
+    /// Logs matching every filter
+    pub total: u64,
+    pub has_more: bool,
+    /// Matching logs per message type, ignoring the `message_type` filter
+    pub counts_by_type: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateRedactionPatternRequest {
+    pub name: String,
+    /// Regex; a `secret` named group limits the replacement to that part of the match
+    pub pattern: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RedactionPatternsResponse {
+    /// False when REDACTION_ENABLED=false
+    pub enabled: bool,
+    pub patterns: Vec<RedactionPatternInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ToolPolicyRequest {
+    /// None resets the project to the mode defaults
+    pub allowed_tools: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ToolPolicyResponse {
+    /// None when the project uses the mode defaults
+    pub allowed_tools: Option<Vec<String>>,
+    /// Used by ask and testcases runs when `allowed_tools` is None
+    pub default_tools: &'static [&'static str],
+    pub known_tools: &'static [&'static str],
+}
+
+impl ToolPolicyResponse {
+    fn new(allowed_tools: Option<Vec<String>>) -> Self {
+        Self {
+            allowed_tools,
+            default_tools: tool_policy::READ_ONLY_TOOLS,
+            known_tools: tool_policy::KNOWN_TOOLS,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LogPersistenceRequest {
+    /// None resets the project to the server's policy
+    pub persistence: Option<LogPersistence>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LogPersistenceResponse {
+    /// None when the project uses the server's policy
+    pub persistence: Option<LogPersistence>,
+    /// The server's policy (`LOG_PERSIST_*`)
+    pub default: LogPersistence,
+}
+
+/// `PUT /api/projects/:id/budget` and `PUT /api/orgs/current/budget`
+#[derive(Debug, Deserialize)]
+pub struct BudgetRequest {
+    /// None removes the budget
+    pub budget: Option<Budget>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BudgetResponse {
+    /// The project's (or organization's) own budget
+    pub budget: Option<Budget>,
+    /// Every budget the runs count toward, with this month's usage
+    pub status: Vec<BudgetStatus>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PreflightRequest {
+    /// Run in order before each analysis; empty removes them
+    pub checks: Vec<PreflightCheck>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PreflightResponse {
+    pub checks: Vec<PreflightCheck>,
+    /// Whether `command` checks may be configured (`PREFLIGHT_ALLOW_COMMANDS`)
+    pub allow_commands: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SummarySettingsRequest {
+    pub enabled: bool,
+    /// None uses the server's `SUMMARY_MAX_LINES`
+    #[serde(default)]
+    pub max_lines: Option<u32>,
+}
+
+#[derive(Deserialize)]
+pub struct SetAgentCredentialRequest {
+    pub api_key: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AgentCredentialsResponse {
+    /// False when `AGENT_CREDENTIALS_KEY` isn't set: keys can't be stored and runs use the server's
+    pub enabled: bool,
+    pub credentials: Vec<MaskedCredential>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LogLevelRequest {
+    /// RUST_LOG-style directives, e.g. `info,qa_chatbot_backend::claude_agent=debug`
+    pub directives: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AnalyticsQueryParams {
+    /// Inclusive start date, `YYYY-MM-DD` or RFC 3339
+    pub from: Option<String>,
+    /// Inclusive end date, `YYYY-MM-DD` or RFC 3339
+    pub to: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AnalyticsResponse {
+    pub project_id: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub total_runs: i64,
+    pub completed: i64,
+    pub failed: i64,
+    pub cancelled: i64,
+    pub running: i64,
+    pub failure_rate: f64,
+    pub cancel_rate: f64,
+    pub avg_duration_ms: Option<f64>,
+    pub max_duration_ms: Option<f64>,
+    pub runs_per_day: Vec<DailyRuns>,
+    pub by_agent: Vec<AgentBreakdown>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub busiest_projects: Option<Vec<ProjectRuns>>,
+    /// Result ratings per agent and mode
+    pub quality: Vec<FeedbackQuality>,
+    /// Time in status and cycle time of the tickets created in the range
+    pub time: TimeAnalytics,
+}
+
+/// Project in the caller's organization, 404 otherwise
+async fn authorized_project(state: &AppState, auth: &AuthContext, id: &str) -> Result<ProjectRecord, StatusCode> {
+    match auth.project(&state.database, id).await {
+        Ok(Some(project)) => Ok(project),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            tracing::error!("Failed to get project: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Ticket in the caller's organization, 404 otherwise
+async fn authorized_ticket(state: &AppState, auth: &AuthContext, id: &str) -> Result<TicketRecord, StatusCode> {
+    match auth.ticket(&state.database, id).await {
+        Ok(Some(ticket)) => Ok(ticket),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            tracing::error!("Failed to get ticket: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
 // GET /api/projects
-pub async fn list_projects(State(state): State<AppState>) -> Result<Json<Vec<ProjectRecord>>, StatusCode> {
-    match state.database.list_projects().await {
+pub async fn list_projects(
+    auth: AuthContext,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<ProjectRecord>>, StatusCode> {
+    match state.database.list_projects_by_org(&auth.org_id).await {
         Ok(projects) => Ok(Json(projects)),
         Err(e) => {
             tracing::error!("Failed to list projects: {}", e);
@@ -65,83 +573,204 @@ pub async fn list_projects(State(state): State<AppState>) -> Result<Json<Vec<Pro
 
 // GET /api/projects/:id
 pub async fn get_project(
+    auth: AuthContext,
     Path(id): Path<String>,
     State(state): State<AppState>,
 ) -> Result<Json<ProjectRecord>, StatusCode> {
-    match state.database.get_project(&id).await {
-        Ok(Some(project)) => Ok(Json(project)),
-        Ok(None) => Err(StatusCode::NOT_FOUND),
-        Err(e) => {
-            tracing::error!("Failed to get project: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
-        }
-    }
+    Ok(Json(authorized_project(&state, &auth, &id).await?))
 }
 
 // POST /api/projects
 pub async fn create_project(
+    auth: AuthContext,
     State(state): State<AppState>,
     Json(data): Json<CreateProjectRequest>,
-) -> Result<Json<ProjectRecord>, StatusCode> {
+) -> Result<Json<ProjectRecord>, (StatusCode, Json<Value>)> {
+    let status_only = |status: StatusCode| (status, Json(json!({ "error": status.canonical_reason() })));
+    let directory_path = state.project_roots.check(&data.directory_path).map_err(root_error)?;
     let project = ProjectRecord {
         id: uuid::Uuid::new_v4().to_string(),
         name: data.name,
         description: data.description,
-        directory_path: data.directory_path,
+        directory_path,
         created_at: Utc::now().to_rfc3339(),
         updated_at: Utc::now().to_rfc3339(),
+        org_id: auth.org_id,
     };
 
     match state.database.create_project(&project).await {
         Ok(_) => Ok(Json(project)),
         Err(e) => {
             tracing::error!("Failed to create project: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+            Err(status_only(StatusCode::INTERNAL_SERVER_ERROR))
         }
     }
 }
 
-// PUT /api/projects/:id
-pub async fn update_project(
+fn root_error(e: RootError) -> (StatusCode, Json<Value>) {
+    warn!("Rejected project directory: {}", e);
+    (StatusCode::BAD_REQUEST, Json(json!({ "error": e.to_string() })))
+}
+
+// GET /api/projects/:id/export
+pub async fn export_project(
+    auth: AuthContext,
     Path(id): Path<String>,
+    Query(params): Query<ExportQueryParams>,
     State(state): State<AppState>,
-    Json(data): Json<UpdateProjectRequest>,
-) -> Result<Json<ProjectRecord>, StatusCode> {
-    // Get existing project first
-    let existing = match state.database.get_project(&id).await {
-        Ok(Some(project)) => project,
+) -> Result<Response, StatusCode> {
+    authorized_project(&state, &auth, &id).await?;
+
+    // Links need shared storage; a file on one replica's disk isn't reachable from the others
+    let store = match (params.link, &state.exports) {
+        (false, _) => None,
+        (true, Some(store)) => Some(store),
+        (true, None) => return Err(StatusCode::BAD_REQUEST),
+    };
+
+    let export = match project_transfer::export_project(&state.database, &id, params.include_logs).await {
+        Ok(Some(export)) => export,
         Ok(None) => return Err(StatusCode::NOT_FOUND),
         Err(e) => {
-            tracing::error!("Failed to get project: {}", e);
+            tracing::error!("Failed to export project: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    let Some(store) = store else {
+        return Ok(Json(export).into_response());
+    };
+
+    let key = format!("{}/{}.json", id, Utc::now().format("%Y%m%dT%H%M%S%3fZ"));
+    let body = serde_json::to_vec(&export).map_err(|e| {
+        tracing::error!("Failed to serialize export: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    if let Err(e) = store.put(&key, body).await {
+        tracing::error!("Failed to upload export {}: {}", key, e);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    match store.presigned_url(&key, &format!("project-{}.json", id)) {
+        Ok(Some(link)) => Ok(Json(ExportLinkResponse {
+            url: link.url,
+            expires_at: link.expires_at.to_rfc3339(),
+        })
+        .into_response()),
+        Ok(None) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+        Err(e) => {
+            tracing::error!("Failed to presign export {}: {}", key, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// POST /api/projects/import
+pub async fn import_project(
+    auth: AuthContext,
+    Query(params): Query<ImportQueryParams>,
+    State(state): State<AppState>,
+    Json(export): Json<ProjectExport>,
+) -> Result<Json<ImportResponse>, StatusCode> {
+    let existing_names = match state.database.list_projects_by_org(&auth.org_id).await {
+        Ok(projects) => projects.into_iter().map(|p| p.name).collect(),
+        Err(e) => {
+            tracing::error!("Failed to list projects: {}", e);
             return Err(StatusCode::INTERNAL_SERVER_ERROR);
         }
     };
 
+    let options = ImportOptions {
+        on_conflict: params.on_conflict,
+        directory_path: params.directory_path,
+        org_id: auth.org_id,
+    };
+    let mut prepared = match project_transfer::prepare_import(export, &existing_names, &options) {
+        Ok(prepared) => prepared,
+        Err(e) => {
+            warn!("Rejected project import: {}", e);
+            return Err(match e {
+                ImportError::NameConflict(_) => StatusCode::CONFLICT,
+                _ => StatusCode::BAD_REQUEST,
+            });
+        }
+    };
+    prepared.project.directory_path = match state.project_roots.check(&prepared.project.directory_path) {
+        Ok(directory_path) => directory_path,
+        Err(e) => {
+            warn!("Rejected project import: {}", e);
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    };
+
+    match state
+        .database
+        .import_project(&prepared.project, &prepared.tickets, &prepared.logs)
+        .await
+    {
+        Ok(_) => {
+            info!(
+                "Imported project {} with {} tickets and {} logs",
+                prepared.project.id,
+                prepared.tickets.len(),
+                prepared.logs.len()
+            );
+            Ok(Json(ImportResponse {
+                tickets_imported: prepared.tickets.len(),
+                logs_imported: prepared.logs.len(),
+                project: prepared.project,
+            }))
+        }
+        Err(e) => {
+            tracing::error!("Failed to import project: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// PUT /api/projects/:id
+pub async fn update_project(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    Json(data): Json<UpdateProjectRequest>,
+) -> Result<Json<ProjectRecord>, (StatusCode, Json<Value>)> {
+    let status_only = |status: StatusCode| (status, Json(json!({ "error": status.canonical_reason() })));
+    // Get existing project first
+    let existing = authorized_project(&state, &auth, &id).await.map_err(status_only)?;
+    let directory_path = state.project_roots.check(&data.directory_path).map_err(root_error)?;
+
     let updated = ProjectRecord {
         id: existing.id.clone(),
         name: data.name,
         description: data.description,
-        directory_path: data.directory_path,
+        directory_path,
         created_at: existing.created_at,
         updated_at: Utc::now().to_rfc3339(),
+        org_id: existing.org_id,
     };
 
     match state.database.update_project(&updated).await {
         Ok(_) => Ok(Json(updated)),
         Err(e) => {
             tracing::error!("Failed to update project: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+            Err(status_only(StatusCode::INTERNAL_SERVER_ERROR))
         }
     }
 }
 
 // DELETE /api/projects/:id
 pub async fn delete_project(
+    auth: AuthContext,
     Path(id): Path<String>,
     State(state): State<AppState>,
 ) -> Result<StatusCode, StatusCode> {
-    match state.database.delete_project(&id).await {
-        Ok(_) => Ok(StatusCode::NO_CONTENT),
+    authorized_project(&state, &auth, &id).await?;
+
+    match trash::trash_project(&state, &id, auth.user_id.as_deref()).await {
+        Ok(_) => {
+            info!("Project {} moved to the trash", id);
+            Ok(StatusCode::NO_CONTENT)
+        }
         Err(e) => {
             tracing::error!("Failed to delete project: {}", e);
             Err(StatusCode::INTERNAL_SERVER_ERROR)
@@ -149,56 +778,327 @@ pub async fn delete_project(
     }
 }
 
-// GET /api/projects/:project_id/tickets
-pub async fn list_tickets(
-    Path(project_id): Path<String>,
-    State(state): State<AppState>,
-) -> Result<Json<Vec<TicketRecord>>, StatusCode> {
-    match state.database.list_tickets_by_project(&project_id).await {
-        Ok(tickets) => Ok(Json(tickets)),
+// GET /api/trash
+pub async fn list_trash(auth: AuthContext, State(state): State<AppState>) -> Result<Json<TrashResponse>, StatusCode> {
+    match state.database.list_trashed_projects_by_org(&auth.org_id).await {
+        Ok(projects) => Ok(Json(TrashResponse {
+            retention_days: state.trash.retention_days,
+            projects: projects
+                .into_iter()
+                .map(|project| TrashedProject {
+                    purge_at: state.trash.purge_at(&project.deleted_at),
+                    project,
+                })
+                .collect(),
+        })),
         Err(e) => {
-            tracing::error!("Failed to list tickets: {}", e);
+            tracing::error!("Failed to list trash: {}", e);
             Err(StatusCode::INTERNAL_SERVER_ERROR)
         }
     }
 }
 
-// POST /api/projects/:project_id/tickets
-pub async fn create_ticket(
-    Path(project_id): Path<String>,
+// POST /api/trash/projects/:id/restore
+pub async fn restore_project(
+    auth: AuthContext,
+    Path(id): Path<String>,
     State(state): State<AppState>,
-    Json(data): Json<CreateTicketRequest>,
-) -> Result<Json<TicketRecord>, StatusCode> {
-    let ticket = TicketRecord {
-        id: uuid::Uuid::new_v4().to_string(),
-        project_id: project_id.clone(),
-        title: data.title,
-        description: data.description,
-        status: data.status,
-        code_context: data.code_context,
-        analysis_result: None,
-        is_analyzing: false,
-        created_at: Utc::now().to_rfc3339(),
-        updated_at: Utc::now().to_rfc3339(),
+) -> Result<Json<ProjectRecord>, StatusCode> {
+    let trashed = match state.database.get_trashed_project(&id).await {
+        Ok(Some(trashed)) if trashed.project.org_id == auth.org_id => trashed,
+        Ok(_) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            tracing::error!("Failed to get trashed project: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
     };
 
-    match state.database.create_ticket(&ticket).await {
-        Ok(_) => Ok(Json(ticket)),
+    match state.database.restore_project(&id).await {
+        Ok(true) => {
+            info!("Project {} restored from the trash", id);
+            let project = authorized_project(&state, &auth, &id).await?;
+            // Open boards add it back like a new project
+            let _ = state.broadcast_tx.send(crate::BroadcastMessage {
+                ticket_id: "system".to_string(),
+                message_type: "project-created".to_string(),
+                content: serde_json::to_string(&project).unwrap_or_default(),
+                timestamp: Utc::now(),
+                org_id: Some(auth.org_id.clone()),
+                changed_files: None,
+            });
+            Ok(Json(project))
+        }
+        // Restored concurrently, or purged in between
+        Ok(false) => Err(StatusCode::NOT_FOUND),
         Err(e) => {
-            tracing::error!("Failed to create ticket: {}", e);
+            tracing::error!("Failed to restore project {}: {}", trashed.project.id, e);
             Err(StatusCode::INTERNAL_SERVER_ERROR)
         }
     }
 }
 
-// PUT /api/tickets/:id/status
-pub async fn update_ticket_status(
-    Path(id): Path<String>,
-    State(state): State<AppState>,
-    Json(data): Json<UpdateStatusRequest>,
+// GET /api/projects/:project_id/tickets
+pub async fn list_tickets(
+    auth: AuthContext,
+    Path(project_id): Path<String>,
+    Query(params): Query<TicketListQueryParams>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<TicketWithFields>>, StatusCode> {
+    authorized_project(&state, &auth, &project_id).await?;
+
+    let tickets = match params.assignee.as_deref() {
+        None => state.database.list_tickets_by_project(&project_id).await,
+        Some("none") => state.database.list_tickets_by_assignee(&project_id, None).await,
+        Some("me") => {
+            let user_id = auth.user_id.as_deref().ok_or(StatusCode::BAD_REQUEST)?;
+            state.database.list_tickets_by_assignee(&project_id, Some(user_id)).await
+        }
+        Some(user_id) => state.database.list_tickets_by_assignee(&project_id, Some(user_id)).await,
+    };
+    let filters = custom_fields::filters_from_query(&params.fields);
+    let tickets = match tickets {
+        Ok(tickets) => custom_fields::with_fields(&state.database, tickets, &filters).await,
+        Err(e) => Err(e),
+    };
+    let tickets = match tickets {
+        Ok(mut tickets) => time_tracking::flag_overdue(&state.database, &state.sla, &mut tickets)
+            .await
+            .map(|_| tickets),
+        Err(e) => Err(e),
+    };
+    match tickets {
+        Ok(tickets) => Ok(Json(tickets)),
+        Err(e) => {
+            tracing::error!("Failed to list tickets: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+const SEARCH_DEFAULT_LIMIT: u32 = 20;
+const SEARCH_MAX_LIMIT: u32 = 100;
+
+// GET /api/search/tickets?q=&project_id=&limit=
+pub async fn search_tickets(
+    auth: AuthContext,
+    Query(params): Query<TicketSearchQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<TicketWithFields>>, StatusCode> {
+    let match_query = search::match_query(&params.q).ok_or(StatusCode::BAD_REQUEST)?;
+    if let Some(project_id) = &params.project_id {
+        authorized_project(&state, &auth, project_id).await?;
+    }
+    let limit = params.limit.unwrap_or(SEARCH_DEFAULT_LIMIT).clamp(1, SEARCH_MAX_LIMIT);
+
+    let tickets = state
+        .database
+        .search_tickets(&auth.org_id, params.project_id.as_deref(), &match_query, limit)
+        .await;
+    let tickets = match tickets {
+        Ok(tickets) => custom_fields::with_fields(&state.database, tickets, &[]).await,
+        Err(e) => Err(e),
+    };
+    let tickets = match tickets {
+        Ok(mut tickets) => time_tracking::flag_overdue(&state.database, &state.sla, &mut tickets)
+            .await
+            .map(|_| tickets),
+        Err(e) => Err(e),
+    };
+    match tickets {
+        Ok(tickets) => Ok(Json(tickets)),
+        Err(e) => {
+            tracing::error!("Failed to search tickets: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// POST /api/projects/:project_id/tickets
+pub async fn create_ticket(
+    auth: AuthContext,
+    Path(project_id): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(data): Json<CreateTicketRequest>,
+) -> Result<Response, StatusCode> {
+    authorized_project(&state, &auth, &project_id).await?;
+
+    // A retried POST with the same Idempotency-Key gets the ticket created the first time
+    let key = IdempotencyKey::from_headers(&headers, &auth, &format!("POST /api/projects/{}/tickets", project_id), &data)?;
+    idempotency::run_once(&state, key, || async {
+        let title = ticket_title::resolve(&state, &project_id, &data.title, &data.description).await;
+        let ticket = TicketRecord {
+            id: uuid::Uuid::new_v4().to_string(),
+            project_id: project_id.clone(),
+            title,
+            description: data.description,
+            status: data.status,
+            code_context: data.code_context,
+            analysis_result: None,
+            is_analyzing: false,
+            created_at: Utc::now().to_rfc3339(),
+            updated_at: Utc::now().to_rfc3339(),
+            stale: false,
+            analysis_result_blob: None,
+            analysis_result_size: None,
+            position: 0.0,
+            assignee_id: None,
+            summary: None,
+            mode: data.mode,
+        };
+
+        match state.database.create_ticket(&ticket).await {
+            Ok(position) => {
+                activity::watch_created(&state.database, &ticket.id, auth.user_id.as_deref()).await;
+                Ok(Json(TicketRecord { position, ..ticket }))
+            }
+            Err(e) => {
+                tracing::error!("Failed to create ticket: {}", e);
+                Err(StatusCode::INTERNAL_SERVER_ERROR)
+            }
+        }
+    })
+    .await
+}
+
+// POST /api/projects/:id/bootstrap
+pub async fn bootstrap_project(
+    auth: AuthContext,
+    Path(project_id): Path<String>,
+    State(state): State<AppState>,
+    body: Option<Json<BootstrapProjectRequest>>,
+) -> Result<(StatusCode, Json<Bootstrapped>), (StatusCode, Json<Value>)> {
+    let status_only = |status: StatusCode| (status, Json(json!({ "error": status.canonical_reason() })));
+    authorized_project(&state, &auth, &project_id).await.map_err(status_only)?;
+    let data = body.map(|Json(data)| data).unwrap_or_default();
+
+    let titles: Vec<String> = match state.database.list_tickets_by_project(&project_id).await {
+        Ok(tickets) => tickets.into_iter().map(|ticket| ticket.title).collect(),
+        Err(e) => {
+            error!("Failed to list tickets of project {}: {}", project_id, e);
+            return Err(status_only(StatusCode::INTERNAL_SERVER_ERROR));
+        }
+    };
+    let templates = bootstrap::plan(&state.bootstrap.templates, data.templates.as_deref(), &titles)
+        .map_err(|key| (StatusCode::BAD_REQUEST, Json(json!({ "error": format!("Unknown template: {}", key) }))))?;
+    if templates.is_empty() {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(json!({ "error": "The project already has the bootstrap tickets" })),
+        ));
+    }
+
+    match bootstrap::run(&state, &project_id, &templates, data.agent_type, data.priority, auth.user_id.clone()).await {
+        Ok(bootstrapped) => Ok((StatusCode::ACCEPTED, Json(bootstrapped))),
+        Err(e) => {
+            error!("Failed to bootstrap project {}: {}", project_id, e);
+            Err(status_only(StatusCode::INTERNAL_SERVER_ERROR))
+        }
+    }
+}
+
+// POST /api/tickets/:id/analyze
+pub async fn start_analysis(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Option<Json<StartAnalysisRequest>>,
+) -> Result<Response, StatusCode> {
+    let ticket = authorized_ticket(&state, &auth, &id).await?;
+    let data = body.map(|Json(data)| data).unwrap_or_default();
+    if data.override_budget {
+        auth.require_org_admin()?;
+    }
+
+    // A retried POST with the same Idempotency-Key gets the first run instead of restarting it
+    let key = IdempotencyKey::from_headers(&headers, &auth, &format!("POST /api/tickets/{}/analyze", id), &data)?;
+    idempotency::run_once(&state, key, || async {
+        if ticket.is_analyzing {
+            return Ok(Json(json!({
+                "success": false,
+                "message": "Ticket is already being analyzed"
+            })));
+        }
+        match budgets::check(&state.database, &ticket.project_id, data.override_budget).await {
+            Ok(None) => {}
+            Ok(Some(exceeded)) => {
+                return Ok(Json(json!({
+                    "success": false,
+                    "message": exceeded.to_string(),
+                    "budget_exceeded": true,
+                    "override_allowed": exceeded.overridable
+                })));
+            }
+            Err(e) => {
+                tracing::error!("Failed to check budgets of project {}: {}", ticket.project_id, e);
+                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            }
+        }
+
+        let mode = analysis_runner::choose_mode(&state, &ticket, data.mode, data.set_ticket_mode)
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to set mode of ticket {}: {}", id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        let request = CodeAnalysisRequest {
+            ticket_id: ticket.id.clone(),
+            code_context: data.code_context.or(ticket.code_context).unwrap_or_default(),
+            question: data.question.unwrap_or(ticket.description),
+            project_id: ticket.project_id,
+            run_id: None,
+            agent_type: data.agent_type,
+            mode,
+            path_rules: Default::default(),
+            tool_policy: Default::default(),
+            priority: data.priority,
+            include_linked_results: data.include_linked_results,
+            budget_override: data.override_budget,
+            replay: None,
+            working_dir: None,
+            prompt_variant: None,
+            response_language: None,
+            api_key: None,
+            then: Vec::new(),
+        };
+
+        info!("🚀 Analysis requested over HTTP for ticket: {}", id);
+        let run_id = analysis_runner::start_analysis(&state, request, auth.user_id.clone()).await;
+
+        // Same event as a WebSocket start, so open boards show the run
+        let _ = state.broadcast_tx.send(crate::BroadcastMessage {
+            ticket_id: id.clone(),
+            message_type: "analysis-started".to_string(),
+            content: json!({ "run_id": run_id }).to_string(),
+            timestamp: Utc::now(),
+            org_id: None,
+            changed_files: None,
+        });
+
+        Ok(Json(json!({
+            "success": true,
+            "message": "Analysis started",
+            "run_id": run_id
+        })))
+    })
+    .await
+}
+
+// PUT /api/tickets/:id/status
+pub async fn update_ticket_status(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    Json(data): Json<UpdateStatusRequest>,
 ) -> Result<StatusCode, StatusCode> {
+    let ticket = authorized_ticket(&state, &auth, &id).await?;
+
     match state.database.update_ticket_status(&id, &data.status).await {
-        Ok(_) => Ok(StatusCode::NO_CONTENT),
+        Ok(_) => {
+            activity::status_changed(&state.database, &id, auth.user_id.as_deref(), &ticket.status, &data.status).await;
+            Ok(StatusCode::NO_CONTENT)
+        }
         Err(e) => {
             tracing::error!("Failed to update ticket status: {}", e);
             Err(StatusCode::INTERNAL_SERVER_ERROR)
@@ -206,12 +1106,199 @@ pub async fn update_ticket_status(
     }
 }
 
+// PUT /api/tickets/:id/position
+pub async fn update_ticket_position(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    Json(data): Json<UpdatePositionRequest>,
+) -> Result<Json<TicketMove>, (StatusCode, Json<Value>)> {
+    let status_only = |status: StatusCode| (status, Json(json!({ "error": status.canonical_reason() })));
+    let ticket = authorized_ticket(&state, &auth, &id).await.map_err(status_only)?;
+
+    let status = data.status.unwrap_or_else(|| ticket.status.clone());
+    if !board::STATUSES.contains(&status.as_str()) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": format!("Unknown status {}", status) })),
+        ));
+    }
+
+    let moved = board::move_ticket(&state.database, &ticket, &status, data.index)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to move ticket {}: {}", id, e);
+            status_only(StatusCode::INTERNAL_SERVER_ERROR)
+        })?;
+    activity::status_changed(&state.database, &id, auth.user_id.as_deref(), &ticket.status, &status).await;
+
+    let _ = state.broadcast_tx.send(crate::BroadcastMessage {
+        ticket_id: id,
+        message_type: "ticket-reordered".to_string(),
+        content: serde_json::to_string(&moved).unwrap_or_default(),
+        timestamp: Utc::now(),
+        org_id: None,
+        changed_files: None,
+    });
+    Ok(Json(moved))
+}
+
+// PUT /api/tickets/:id/assignee
+pub async fn assign_ticket(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    Json(data): Json<AssignTicketRequest>,
+) -> Result<Json<TicketRecord>, (StatusCode, Json<Value>)> {
+    let status_only = |status: StatusCode| (status, Json(json!({ "error": status.canonical_reason() })));
+    let internal = |e: anyhow::Error| {
+        tracing::error!("Failed to assign ticket {}: {}", id, e);
+        status_only(StatusCode::INTERNAL_SERVER_ERROR)
+    };
+    let ticket = authorized_ticket(&state, &auth, &id).await.map_err(status_only)?;
+
+    // Only members of the ticket's organization can be assigned
+    let assignee = match data.assignee_id.as_deref() {
+        Some(user_id) => match state.database.get_user(user_id).await.map_err(internal)? {
+            Some(user) if user.org_id == auth.org_id => Some(user),
+            _ => {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({ "error": format!("Unknown user {}", user_id) })),
+                ))
+            }
+        },
+        None => None,
+    };
+    if ticket.assignee_id == data.assignee_id {
+        return Ok(Json(ticket));
+    }
+
+    state
+        .database
+        .set_ticket_assignee(&id, data.assignee_id.as_deref())
+        .await
+        .map_err(internal)?;
+    let updated = state
+        .database
+        .get_ticket(&id)
+        .await
+        .map_err(internal)?
+        .ok_or_else(|| status_only(StatusCode::NOT_FOUND))?;
+    activity::assigned(&state.database, &id, auth.user_id.as_deref(), assignee.as_ref()).await;
+
+    let assignment = TicketAssignment {
+        ticket_id: id.clone(),
+        assignee_id: data.assignee_id.clone(),
+        previous_assignee_id: ticket.assignee_id,
+        assigned_by: auth.user_id.clone(),
+    };
+    let _ = state.broadcast_tx.send(crate::BroadcastMessage {
+        ticket_id: id,
+        message_type: "ticket-assigned".to_string(),
+        content: serde_json::to_string(&assignment).unwrap_or_default(),
+        timestamp: Utc::now(),
+        org_id: None,
+        changed_files: None,
+    });
+
+    // Nobody needs an email about assigning themselves
+    if let (Some(notifier), Some(user)) = (state.notifier.clone(), assignee) {
+        if auth.user_id.as_deref() != Some(user.id.as_str()) {
+            let database = state.database.clone();
+            let ticket = updated.clone();
+            let assigned_by = auth.user_id.clone();
+            state.tasks.spawn(format!("email {}", ticket.id), async move {
+                if let Err(e) = notifier.ticket_assigned(&database, &user.id, &ticket, assigned_by.as_deref()).await {
+                    tracing::warn!("⚠️ Không gửi được email giao ticket {}: {}", ticket.id, e);
+                }
+            });
+        }
+    }
+
+    Ok(Json(updated))
+}
+
+// GET /api/tickets/:id/time
+pub async fn get_ticket_time(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<TicketTime>, StatusCode> {
+    let ticket = authorized_ticket(&state, &auth, &id).await?;
+
+    match time_tracking::ticket_time(&state.database, &ticket, &state.sla).await {
+        Ok(time) => Ok(Json(time)),
+        Err(e) => {
+            tracing::error!("Failed to compute time in status of ticket {}: {}", id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// GET /api/tickets/:id/watchers
+pub async fn list_ticket_watchers(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<TicketWatcherRecord>>, StatusCode> {
+    authorized_ticket(&state, &auth, &id).await?;
+
+    match state.database.list_ticket_watchers(&id).await {
+        Ok(watchers) => Ok(Json(watchers)),
+        Err(e) => {
+            tracing::error!("Failed to list watchers of ticket {}: {}", id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// POST /api/tickets/:id/watch
+pub async fn watch_ticket(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, StatusCode> {
+    authorized_ticket(&state, &auth, &id).await?;
+    // Anonymous access has no feed to follow the ticket in
+    let user_id = auth.user_id.as_deref().ok_or(StatusCode::BAD_REQUEST)?;
+
+    match activity::watch(&state.database, &id, user_id, WatchReason::Manual).await {
+        Ok(_) => Ok(StatusCode::NO_CONTENT),
+        Err(e) => {
+            tracing::error!("Failed to watch ticket {}: {}", id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// DELETE /api/tickets/:id/watch
+pub async fn unwatch_ticket(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, StatusCode> {
+    authorized_ticket(&state, &auth, &id).await?;
+    let user_id = auth.user_id.as_deref().ok_or(StatusCode::BAD_REQUEST)?;
+
+    match state.database.remove_ticket_watcher(&id, user_id).await {
+        Ok(_) => Ok(StatusCode::NO_CONTENT),
+        Err(e) => {
+            tracing::error!("Failed to unwatch ticket {}: {}", id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
 // GET /api/tickets/:id/logs
 pub async fn get_ticket_logs(
+    auth: AuthContext,
     Path(id): Path<String>,
     Query(params): Query<LogsQueryParams>,
     State(state): State<AppState>,
 ) -> Result<Json<PaginatedLogsResponse>, StatusCode> {
+    authorized_ticket(&state, &auth, &id).await?;
+
     // Validate and log pagination parameters
     let limit = params.limit;
     let offset = params.offset;
@@ -267,80 +1354,2386 @@ pub async fn get_ticket_logs(
     }))
 }
 
-// POST /api/tickets/:id/stop-analysis
-pub async fn stop_analysis(
+/// Stored-timestamp form of a `from`/`until` filter; a bare date means the start of that day,
+/// or of the next day when `end_of_day` (an inclusive end date)
+fn log_timestamp_param(value: &str, end_of_day: bool) -> Option<String> {
+    if let Ok(at) = chrono::DateTime::parse_from_rfc3339(value) {
+        return Some(at.with_timezone(&Utc).to_rfc3339());
+    }
+    let date = chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?;
+    let date = if end_of_day { date.succ_opt()? } else { date };
+    Some(date.and_time(chrono::NaiveTime::MIN).and_utc().to_rfc3339())
+}
+
+// GET /api/projects/:id/logs
+pub async fn get_project_logs(
+    auth: AuthContext,
     Path(id): Path<String>,
+    Query(params): Query<ProjectLogsQueryParams>,
     State(state): State<AppState>,
-) -> Result<Json<Value>, StatusCode> {
-    info!("⛔ Stop analysis requested for ticket: {}", id);
+) -> Result<Json<ProjectLogsResponse>, (StatusCode, Json<Value>)> {
+    let status_only = |status: StatusCode| (status, Json(json!({ "error": status.canonical_reason() })));
+    authorized_project(&state, &auth, &id).await.map_err(status_only)?;
 
-    // Check if ticket exists
-    let ticket = match state.database.get_ticket(&id).await {
-        Ok(Some(ticket)) => ticket,
+    let bad_request = |message: String| (StatusCode::BAD_REQUEST, Json(json!({ "error": message })));
+    let from = match params.from.as_deref() {
+        Some(value) => {
+            Some(log_timestamp_param(value, false).ok_or_else(|| bad_request(format!("Invalid from: {}", value)))?)
+        }
+        None => None,
+    };
+    let until = match params.until.as_deref() {
+        Some(value) => {
+            Some(log_timestamp_param(value, true).ok_or_else(|| bad_request(format!("Invalid until: {}", value)))?)
+        }
+        None => None,
+    };
+    let message_types: Vec<String> = params
+        .message_type
+        .iter()
+        .flat_map(|types| types.split(','))
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .map(str::to_string)
+        .collect();
+
+    let filter = LogFilter {
+        message_types: Vec::new(),
+        ticket_id: params.ticket_id,
+        from,
+        until,
+        search: params.q.filter(|q| !q.trim().is_empty()),
+    };
+    let db_error = |e: anyhow::Error| {
+        tracing::error!("Failed to query logs of project {}: {}", id, e);
+        status_only(StatusCode::INTERNAL_SERVER_ERROR)
+    };
+
+    let counts_by_type: BTreeMap<String, u64> = state
+        .database
+        .count_project_logs_by_type(&id, &filter)
+        .await
+        .map_err(db_error)?
+        .into_iter()
+        .collect();
+    let total = counts_by_type
+        .iter()
+        .filter(|(message_type, _)| message_types.is_empty() || message_types.contains(message_type))
+        .map(|(_, count)| count)
+        .sum();
+
+    let limit = params.limit.unwrap_or(100).clamp(1, 1000);
+    let offset = params.offset.unwrap_or(0);
+    let filter = LogFilter { message_types, ..filter };
+    let logs = state
+        .database
+        .query_project_logs(&id, &filter, params.sort, limit, offset)
+        .await
+        .map_err(db_error)?;
+
+    let has_more = offset + (logs.len() as u64) < total;
+    Ok(Json(ProjectLogsResponse {
+        logs,
+        total,
+        has_more,
+        counts_by_type,
+    }))
+}
+
+/// Stream the blob under `key`, or send `inline` for a value that was never offloaded
+async fn blob_or_inline(
+    state: &AppState,
+    key: Option<&str>,
+    inline: Option<String>,
+    content_type: &'static str,
+) -> Result<Response, StatusCode> {
+    let Some(key) = key else {
+        let body = inline.ok_or(StatusCode::NOT_FOUND)?;
+        return Ok(([(header::CONTENT_TYPE, content_type)], body).into_response());
+    };
+
+    let blob = match state.database.open_blob(key).await {
+        Ok(Some(blob)) => blob,
         Ok(None) => {
-            error!("Ticket {} not found", id);
+            tracing::error!("Blob {} is missing", key);
             return Err(StatusCode::NOT_FOUND);
         }
         Err(e) => {
-            error!("Failed to get ticket {}: {}", id, e);
+            tracing::error!("Failed to open blob {}: {}", key, e);
             return Err(StatusCode::INTERNAL_SERVER_ERROR);
         }
     };
 
-    // Check if ticket is currently analyzing
-    if !ticket.is_analyzing {
-        warn!("Ticket {} is not currently being analyzed", id);
-        return Ok(Json(json!({
-            "success": false,
-            "message": "Ticket is not being analyzed"
-        })));
+    let mut response = ([(header::CONTENT_TYPE, content_type)], axum::body::Body::from_stream(blob.stream)).into_response();
+    if let Some(size) = blob.size {
+        response.headers_mut().insert(header::CONTENT_LENGTH, size.into());
     }
+    Ok(response)
+}
+
+// GET /api/tickets/:id/result
+pub async fn get_ticket_result(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Response, StatusCode> {
+    let ticket = authorized_ticket(&state, &auth, &id).await?;
+    blob_or_inline(
+        &state,
+        ticket.analysis_result_blob.as_deref(),
+        ticket.analysis_result,
+        "text/markdown; charset=utf-8",
+    )
+    .await
+}
+
+const TAIL_DEFAULT_WAIT_SECS: u64 = 30;
+pub(crate) const TAIL_MAX_WAIT_SECS: u64 = 60;
+
+// GET /api/tickets/:id/logs/tail
+// Long-polling stand-in for the WebSocket log stream
+pub async fn tail_ticket_logs(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    Query(params): Query<LogTailQueryParams>,
+    State(state): State<AppState>,
+) -> Result<Json<LogTailResponse>, StatusCode> {
+    authorized_ticket(&state, &auth, &id).await?;
+
+    let wait = params.wait.unwrap_or(TAIL_DEFAULT_WAIT_SECS).min(TAIL_MAX_WAIT_SECS);
+    let after_id = params.after_id.filter(|after_id| !after_id.is_empty());
+    let logs = state
+        .msg_store
+        .tail(&id, after_id.as_deref(), std::time::Duration::from_secs(wait))
+        .await;
+
+    Ok(Json(LogTailResponse {
+        next_after_id: logs.last().map(|entry| entry.id.clone()).or(after_id),
+        logs,
+        is_analyzing: analysis_runner::is_active(&state, &id).await,
+    }))
+}
 
-    // Lookup and abort the running task
-    let handle = {
-        let mut tasks = state.running_tasks.lock().await;
-        tasks.remove(&id)
+// GET /api/tickets/:id/logs/:log_id/raw
+pub async fn get_log_raw(
+    auth: AuthContext,
+    Path((id, log_id)): Path<(String, String)>,
+    State(state): State<AppState>,
+) -> Result<Response, StatusCode> {
+    authorized_ticket(&state, &auth, &id).await?;
+
+    let log = match state.database.get_log(&log_id).await {
+        Ok(Some(log)) if log.ticket_id == id => log,
+        Ok(_) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            tracing::error!("Failed to get log: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    blob_or_inline(&state, log.raw_log_blob.as_deref(), log.raw_log, "text/plain; charset=utf-8").await
+}
+
+// GET /api/tickets/:id/result/html
+pub async fn get_ticket_result_html(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let ticket = authorized_ticket(&state, &auth, &id).await?;
+    let result = match state.database.full_result(&ticket).await {
+        Ok(result) => result.ok_or(StatusCode::NOT_FOUND)?,
+        Err(e) => {
+            tracing::error!("Failed to read result of ticket {}: {}", id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
     };
 
-    if let Some(handle) = handle {
-        handle.abort();
-        info!("⛔ Aborted analysis task for ticket {}", id);
-    } else {
-        warn!("No running task found for ticket {} (may have already completed)", id);
+    Ok((
+        [(header::CONTENT_TYPE, "text/html; charset=utf-8")],
+        markdown::render_markdown(&result),
+    ))
+}
+
+// GET /api/tickets/:id/test-cases
+pub async fn list_test_cases(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<TestCaseRecord>>, StatusCode> {
+    authorized_ticket(&state, &auth, &id).await?;
+
+    match state.database.list_test_cases(&id).await {
+        Ok(cases) => Ok(Json(cases)),
+        Err(e) => {
+            tracing::error!("Failed to list test cases: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
     }
+}
 
-    // Update database: set is_analyzing = false
-    if let Err(e) = state.database.update_ticket_analyzing(&id, false).await {
-        error!("Failed to update ticket {} analyzing status: {}", id, e);
-        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+// GET /api/tickets/:id/test-cases/export?format=csv|gherkin&group_by=ticket|flow
+pub async fn export_test_cases(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    Query(params): Query<TestCaseExportParams>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let ticket = authorized_ticket(&state, &auth, &id).await?;
+
+    let cases = state.database.list_test_cases(&id).await.map_err(|e| {
+        tracing::error!("Failed to list test cases: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let (content_type, extension, body) = match params.format {
+        TestCaseExportFormat::Csv => ("text/csv; charset=utf-8", "csv", test_cases::to_csv(&cases).into_bytes()),
+        TestCaseExportFormat::Gherkin => {
+            let labels = state.database.get_ticket_labels(&id).await.map_err(|e| {
+                tracing::error!("Failed to get ticket labels: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+            let features = test_cases::gherkin_features(&ticket, &labels, &cases, params.group_by);
+            let archive = test_cases::zip_features(&features).map_err(|e| {
+                tracing::error!("Failed to build feature archive: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+            ("application/zip", "zip", archive)
+        }
+    };
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"test-cases-{}.{}\"", id, extension),
+            ),
+        ],
+        body,
+    ))
+}
+
+// GET /api/tickets/:id/diagram?format=json|mmd|svg
+pub async fn get_ticket_diagram(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    Query(params): Query<DiagramQueryParams>,
+    State(state): State<AppState>,
+) -> Result<Response, (StatusCode, Json<Value>)> {
+    let status_only = |status: StatusCode| (status, Json(json!({ "error": status.canonical_reason() })));
+    authorized_ticket(&state, &auth, &id).await.map_err(status_only)?;
+
+    let mut diagram = match state.database.get_ticket_diagram(&id).await {
+        Ok(Some(diagram)) => diagram,
+        Ok(None) => return Err(status_only(StatusCode::NOT_FOUND)),
+        Err(e) => {
+            tracing::error!("Failed to get ticket diagram: {}", e);
+            return Err(status_only(StatusCode::INTERNAL_SERVER_ERROR));
+        }
+    };
+
+    if let DiagramFormat::Mmd = params.format {
+        return Ok(([(header::CONTENT_TYPE, "text/plain; charset=utf-8")], diagram.source).into_response());
     }
 
-    // Find active session and cancel it
-    if let Ok(Some(session)) = state.database.get_active_session_by_ticket(&id).await {
-        if let Err(e) = state.database.cancel_session(&session.id, "Cancelled by user").await {
-            error!("Failed to cancel session {}: {}", session.id, e);
+    // Rendered once, then served from the cache until a new run replaces the diagram
+    if diagram.svg.is_none() {
+        if let Some(renderer) = &state.mermaid {
+            match renderer.render(&diagram.source).await {
+                Ok(svg) => {
+                    if let Err(e) = state.database.set_ticket_diagram_svg(&id, &diagram.created_at, &svg).await {
+                        tracing::error!("Failed to cache diagram SVG of ticket {}: {}", id, e);
+                    }
+                    diagram.svg = Some(svg);
+                }
+                Err(e) => warn!("⚠️ Không render được diagram của ticket {}: {}", id, e),
+            }
         }
     }
 
-    // Create and broadcast stop log
-    let log_entry = crate::log_normalizer::LogNormalizer::new().normalize(
-        "⛔ Đã dừng phân tích theo yêu cầu".to_string(),
-        id.clone(),
-    );
-    state.msg_store.push(log_entry).await;
+    match params.format {
+        DiagramFormat::Svg => match diagram.svg {
+            Some(svg) => Ok(([(header::CONTENT_TYPE, "image/svg+xml")], svg).into_response()),
+            None if state.mermaid.is_none() => Err((
+                StatusCode::NOT_IMPLEMENTED,
+                Json(json!({ "error": "SVG rendering is not configured (MERMAID_CLI_PATH)" })),
+            )),
+            None => Err((
+                StatusCode::UNPROCESSABLE_ENTITY,
+                Json(json!({ "error": "The diagram could not be rendered" })),
+            )),
+        },
+        _ => Ok(Json(DiagramResponse {
+            svg_available: state.mermaid.is_some(),
+            diagram,
+        })
+        .into_response()),
+    }
+}
 
-    // Broadcast stop event to all connected clients
-    let _ = state.broadcast_tx.send(crate::BroadcastMessage {
-        ticket_id: id.clone(),
-        message_type: "analysis-stopped".to_string(),
-        content: "Analysis stopped by user".to_string(),
-        timestamp: chrono::Utc::now(),
-    });
+// GET /api/tickets/:id/labels
+pub async fn get_ticket_labels(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<String>>, StatusCode> {
+    authorized_ticket(&state, &auth, &id).await?;
 
-    info!("✅ Successfully stopped analysis for ticket {}", id);
-    Ok(Json(json!({
-        "success": true,
-        "message": "Analysis stopped successfully"
-    })))
+    match state.database.get_ticket_labels(&id).await {
+        Ok(labels) => Ok(Json(labels)),
+        Err(e) => {
+            tracing::error!("Failed to get ticket labels: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
 }
 
+// PUT /api/tickets/:id/labels
+pub async fn set_ticket_labels(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    Json(data): Json<TicketLabelsRequest>,
+) -> Result<Json<Vec<String>>, StatusCode> {
+    authorized_ticket(&state, &auth, &id).await?;
+
+    let labels: Vec<String> = data
+        .labels
+        .iter()
+        .map(|label| label.trim().to_string())
+        .filter(|label| !label.is_empty())
+        .collect();
+
+    match state.database.set_ticket_labels(&id, &labels).await {
+        Ok(()) => get_ticket_labels(auth, Path(id), State(state)).await,
+        Err(e) => {
+            tracing::error!("Failed to set ticket labels: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+fn field_error(e: FieldError) -> (StatusCode, Json<Value>) {
+    match e {
+        FieldError::Database(e) => {
+            tracing::error!("Custom field operation failed: {}", e);
+            let status = StatusCode::INTERNAL_SERVER_ERROR;
+            (status, Json(json!({ "error": status.canonical_reason() })))
+        }
+        e @ FieldError::Duplicate(_) | e @ FieldError::OptionsInUse(_) => {
+            (StatusCode::CONFLICT, Json(json!({ "error": e.to_string() })))
+        }
+        e => (StatusCode::BAD_REQUEST, Json(json!({ "error": e.to_string() }))),
+    }
+}
+
+/// A custom field of the project, 404 otherwise
+async fn project_field(state: &AppState, project_id: &str, field_id: &str) -> Result<CustomFieldRecord, StatusCode> {
+    match state.database.get_custom_field(field_id).await {
+        Ok(Some(field)) if field.project_id == project_id => Ok(field),
+        Ok(_) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            tracing::error!("Failed to get custom field {}: {}", field_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// GET /api/projects/:id/custom-fields
+pub async fn list_custom_fields(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<CustomFieldRecord>>, StatusCode> {
+    authorized_project(&state, &auth, &id).await?;
+
+    match state.database.list_custom_fields(&id).await {
+        Ok(fields) => Ok(Json(fields)),
+        Err(e) => {
+            tracing::error!("Failed to list custom fields: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// POST /api/projects/:id/custom-fields (organization admins)
+pub async fn create_custom_field(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    Json(data): Json<CreateCustomFieldRequest>,
+) -> Result<(StatusCode, Json<CustomFieldRecord>), (StatusCode, Json<Value>)> {
+    let status_only = |status: StatusCode| (status, Json(json!({ "error": status.canonical_reason() })));
+    auth.require_org_admin().map_err(status_only)?;
+    authorized_project(&state, &auth, &id).await.map_err(status_only)?;
+
+    let field = custom_fields::create_field(&state.database, &id, &data.name, data.field_type, data.options)
+        .await
+        .map_err(field_error)?;
+    info!("🏷️ Project {} có custom field mới: {} ({})", id, field.name, field.field_type);
+    Ok((StatusCode::CREATED, Json(field)))
+}
+
+// PUT /api/projects/:id/custom-fields/:field_id (organization admins)
+pub async fn update_custom_field(
+    auth: AuthContext,
+    Path((id, field_id)): Path<(String, String)>,
+    State(state): State<AppState>,
+    Json(data): Json<UpdateCustomFieldRequest>,
+) -> Result<Json<CustomFieldRecord>, (StatusCode, Json<Value>)> {
+    let status_only = |status: StatusCode| (status, Json(json!({ "error": status.canonical_reason() })));
+    auth.require_org_admin().map_err(status_only)?;
+    authorized_project(&state, &auth, &id).await.map_err(status_only)?;
+    let field = project_field(&state, &id, &field_id).await.map_err(status_only)?;
+
+    let field = custom_fields::update_field(&state.database, &field, &data.name, data.options)
+        .await
+        .map_err(field_error)?;
+    Ok(Json(field))
+}
+
+// DELETE /api/projects/:id/custom-fields/:field_id (organization admins)
+pub async fn delete_custom_field(
+    auth: AuthContext,
+    Path((id, field_id)): Path<(String, String)>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, StatusCode> {
+    auth.require_org_admin()?;
+    authorized_project(&state, &auth, &id).await?;
+    project_field(&state, &id, &field_id).await?;
+
+    match state.database.delete_custom_field(&field_id).await {
+        Ok(()) => Ok(StatusCode::NO_CONTENT),
+        Err(e) => {
+            tracing::error!("Failed to delete custom field {}: {}", field_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+fn template_error(e: TemplateError) -> (StatusCode, Json<Value>) {
+    match e {
+        TemplateError::Database(e) => {
+            tracing::error!("Question template operation failed: {}", e);
+            let status = StatusCode::INTERNAL_SERVER_ERROR;
+            (status, Json(json!({ "error": status.canonical_reason() })))
+        }
+        e => (StatusCode::BAD_REQUEST, Json(json!({ "error": e.to_string() }))),
+    }
+}
+
+/// A template of the caller's organization they may change: org admins for global templates,
+/// members of the project for the project's. 404 otherwise.
+async fn editable_template(
+    state: &AppState,
+    auth: &AuthContext,
+    id: &str,
+) -> Result<QuestionTemplateRecord, StatusCode> {
+    let template = match state.database.get_question_template(id).await {
+        Ok(Some(template)) if template.org_id == auth.org_id => template,
+        Ok(_) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            tracing::error!("Failed to get question template {}: {}", id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    match &template.project_id {
+        Some(project_id) => {
+            authorized_project(state, auth, project_id).await?;
+        }
+        None => auth.require_org_admin()?,
+    }
+    Ok(template)
+}
+
+// GET /api/question-templates
+pub async fn list_question_templates(
+    auth: AuthContext,
+    Query(params): Query<QuestionTemplateQueryParams>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<QuestionTemplateRecord>>, StatusCode> {
+    if let Some(project_id) = &params.project_id {
+        authorized_project(&state, &auth, project_id).await?;
+    }
+    let category = params.category.as_deref().map(|category| category.trim().to_lowercase());
+
+    match state
+        .database
+        .list_question_templates(&auth.org_id, params.project_id.as_deref(), category.as_deref())
+        .await
+    {
+        Ok(templates) => Ok(Json(templates)),
+        Err(e) => {
+            tracing::error!("Failed to list question templates: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// POST /api/question-templates (organization admins for global templates)
+pub async fn create_question_template(
+    auth: AuthContext,
+    State(state): State<AppState>,
+    Json(data): Json<TemplateInput>,
+) -> Result<(StatusCode, Json<QuestionTemplateRecord>), (StatusCode, Json<Value>)> {
+    let status_only = |status: StatusCode| (status, Json(json!({ "error": status.canonical_reason() })));
+    match &data.project_id {
+        Some(project_id) => {
+            authorized_project(&state, &auth, project_id).await.map_err(status_only)?;
+        }
+        None => auth.require_org_admin().map_err(status_only)?,
+    }
+
+    let template = question_templates::create(&state.database, &auth.org_id, &data, auth.user_id.clone())
+        .await
+        .map_err(template_error)?;
+    info!("📋 Template câu hỏi mới: {} ({})", template.title, template.category);
+    Ok((StatusCode::CREATED, Json(template)))
+}
+
+// PUT /api/question-templates/:id
+pub async fn update_question_template(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    Json(data): Json<TemplateInput>,
+) -> Result<Json<QuestionTemplateRecord>, (StatusCode, Json<Value>)> {
+    let status_only = |status: StatusCode| (status, Json(json!({ "error": status.canonical_reason() })));
+    let template = editable_template(&state, &auth, &id).await.map_err(status_only)?;
+
+    let template = question_templates::update(&state.database, &template, &data)
+        .await
+        .map_err(template_error)?;
+    Ok(Json(template))
+}
+
+// DELETE /api/question-templates/:id
+pub async fn delete_question_template(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, StatusCode> {
+    editable_template(&state, &auth, &id).await?;
+
+    match state.database.delete_question_template(&id).await {
+        Ok(_) => Ok(StatusCode::NO_CONTENT),
+        Err(e) => {
+            tracing::error!("Failed to delete question template {}: {}", id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// POST /api/question-templates/:id/tickets
+pub async fn create_ticket_from_template(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    body: Option<Json<TicketFromTemplateRequest>>,
+) -> Result<(StatusCode, Json<TicketFromTemplate>), (StatusCode, Json<Value>)> {
+    let status_only = |status: StatusCode| (status, Json(json!({ "error": status.canonical_reason() })));
+    let data = body.map(|Json(data)| data).unwrap_or_default();
+
+    let template = match state.database.get_question_template(&id).await {
+        Ok(Some(template)) if template.org_id == auth.org_id => template,
+        Ok(_) => return Err(status_only(StatusCode::NOT_FOUND)),
+        Err(e) => {
+            error!("Failed to get question template {}: {}", id, e);
+            return Err(status_only(StatusCode::INTERNAL_SERVER_ERROR));
+        }
+    };
+    let project_id = match (&template.project_id, data.project_id) {
+        (Some(owner), Some(requested)) if *owner != requested => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": "The template belongs to another project" })),
+            ));
+        }
+        (Some(owner), _) => owner.clone(),
+        (None, Some(requested)) => requested,
+        (None, None) => {
+            return Err((StatusCode::BAD_REQUEST, Json(json!({ "error": "project_id is required" }))));
+        }
+    };
+    authorized_project(&state, &auth, &project_id).await.map_err(status_only)?;
+
+    let status = data.status.unwrap_or_else(|| "todo".to_string());
+    let ticket = question_templates::create_ticket(&state, &template, &project_id, &status, auth.user_id.as_deref())
+        .await
+        .map_err(|e| {
+            error!("Failed to create a ticket from question template {}: {}", id, e);
+            status_only(StatusCode::INTERNAL_SERVER_ERROR)
+        })?;
+    let run_id = match data.start {
+        true => Some(question_templates::start(&state, &template, &ticket, data.agent_type, auth.user_id.clone()).await),
+        false => None,
+    };
+
+    Ok((
+        StatusCode::CREATED,
+        Json(TicketFromTemplate {
+            mode: question_templates::mode(&template),
+            ticket,
+            run_id,
+        }),
+    ))
+}
+
+fn alert_error(e: AlertError) -> (StatusCode, Json<Value>) {
+    match e {
+        AlertError::Database(e) => {
+            tracing::error!("Alert rule operation failed: {}", e);
+            let status = StatusCode::INTERNAL_SERVER_ERROR;
+            (status, Json(json!({ "error": status.canonical_reason() })))
+        }
+        e => (StatusCode::BAD_REQUEST, Json(json!({ "error": e.to_string() }))),
+    }
+}
+
+/// An alert rule of the caller's organization, 404 otherwise
+async fn authorized_alert_rule(state: &AppState, auth: &AuthContext, id: &str) -> Result<AlertRuleRecord, StatusCode> {
+    match state.database.get_alert_rule(id).await {
+        Ok(Some(rule)) if rule.org_id == auth.org_id => Ok(rule),
+        Ok(_) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            tracing::error!("Failed to get alert rule {}: {}", id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Make this instance use the stored rules right away; the others catch up on their own
+async fn reload_alert_rules(state: &AppState) {
+    if let Err(e) = state.alerts.reload(&state.database).await {
+        tracing::error!("Failed to reload alert rules: {}", e);
+    }
+}
+
+// GET /api/alert-rules (organization admins)
+pub async fn list_alert_rules(
+    auth: AuthContext,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<AlertRule>>, StatusCode> {
+    auth.require_org_admin()?;
+    let rules = state.database.list_alert_rules(&auth.org_id).await.map_err(|e| {
+        tracing::error!("Failed to list alert rules: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    rules
+        .into_iter()
+        .map(AlertRule::from_record)
+        .collect::<anyhow::Result<Vec<_>>>()
+        .map(Json)
+        .map_err(|e| {
+            tracing::error!("Failed to read alert rules: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+// POST /api/alert-rules (organization admins)
+pub async fn create_alert_rule(
+    auth: AuthContext,
+    State(state): State<AppState>,
+    Json(data): Json<AlertRuleInput>,
+) -> Result<(StatusCode, Json<AlertRule>), (StatusCode, Json<Value>)> {
+    let status_only = |status: StatusCode| (status, Json(json!({ "error": status.canonical_reason() })));
+    auth.require_org_admin().map_err(status_only)?;
+    if let Some(project_id) = &data.project_id {
+        authorized_project(&state, &auth, project_id).await.map_err(status_only)?;
+    }
+
+    let rule = alerts::save(&state.database, &auth.org_id, None, &data, auth.user_id.clone())
+        .await
+        .map_err(alert_error)?;
+    reload_alert_rules(&state).await;
+    info!("🚨 Alert rule mới: {}", rule.name);
+    Ok((StatusCode::CREATED, Json(rule)))
+}
+
+// PUT /api/alert-rules/:id (organization admins)
+pub async fn update_alert_rule(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    Json(data): Json<AlertRuleInput>,
+) -> Result<Json<AlertRule>, (StatusCode, Json<Value>)> {
+    let status_only = |status: StatusCode| (status, Json(json!({ "error": status.canonical_reason() })));
+    auth.require_org_admin().map_err(status_only)?;
+    let existing = authorized_alert_rule(&state, &auth, &id).await.map_err(status_only)?;
+    if let Some(project_id) = &data.project_id {
+        authorized_project(&state, &auth, project_id).await.map_err(status_only)?;
+    }
+
+    let rule = alerts::save(&state.database, &auth.org_id, Some(&existing), &data, None)
+        .await
+        .map_err(alert_error)?;
+    reload_alert_rules(&state).await;
+    Ok(Json(rule))
+}
+
+// DELETE /api/alert-rules/:id (organization admins)
+pub async fn delete_alert_rule(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, StatusCode> {
+    auth.require_org_admin()?;
+    authorized_alert_rule(&state, &auth, &id).await?;
+
+    match state.database.delete_alert_rule(&id).await {
+        Ok(_) => {
+            reload_alert_rules(&state).await;
+            Ok(StatusCode::NO_CONTENT)
+        }
+        Err(e) => {
+            tracing::error!("Failed to delete alert rule {}: {}", id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Firings `GET /api/alert-rules/:id/events` returns
+const ALERT_EVENTS_LIMIT: i64 = 100;
+
+// GET /api/alert-rules/:id/events (organization admins), latest first
+pub async fn list_alert_events(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<AlertEventRecord>>, StatusCode> {
+    auth.require_org_admin()?;
+    authorized_alert_rule(&state, &auth, &id).await?;
+
+    match state.database.list_alert_events(&id, ALERT_EVENTS_LIMIT).await {
+        Ok(events) => Ok(Json(events)),
+        Err(e) => {
+            tracing::error!("Failed to list events of alert rule {}: {}", id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+fn experiment_error(e: ExperimentError) -> (StatusCode, Json<Value>) {
+    match e {
+        ExperimentError::Database(e) => {
+            tracing::error!("Experiment operation failed: {}", e);
+            let status = StatusCode::INTERNAL_SERVER_ERROR;
+            (status, Json(json!({ "error": status.canonical_reason() })))
+        }
+        e @ ExperimentError::AlreadyRunning(_) | e @ ExperimentError::AlreadyStopped => {
+            (StatusCode::CONFLICT, Json(json!({ "error": e.to_string() })))
+        }
+        e => (StatusCode::BAD_REQUEST, Json(json!({ "error": e.to_string() }))),
+    }
+}
+
+/// An experiment of the project, 404 otherwise
+async fn project_experiment(
+    state: &AppState,
+    project_id: &str,
+    experiment_id: &str,
+) -> Result<ExperimentRecord, StatusCode> {
+    match state.database.get_experiment(experiment_id).await {
+        Ok(Some(experiment)) if experiment.project_id == project_id => Ok(experiment),
+        Ok(_) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            tracing::error!("Failed to get experiment {}: {}", experiment_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// GET /api/projects/:id/experiments
+pub async fn list_experiments(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<ExperimentRecord>>, StatusCode> {
+    authorized_project(&state, &auth, &id).await?;
+
+    match state.database.list_experiments(&id).await {
+        Ok(experiments) => Ok(Json(experiments)),
+        Err(e) => {
+            tracing::error!("Failed to list experiments: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// POST /api/projects/:id/experiments (organization admins)
+pub async fn create_experiment(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    Json(data): Json<CreateExperimentRequest>,
+) -> Result<(StatusCode, Json<Experiment>), (StatusCode, Json<Value>)> {
+    let status_only = |status: StatusCode| (status, Json(json!({ "error": status.canonical_reason() })));
+    auth.require_org_admin().map_err(status_only)?;
+    authorized_project(&state, &auth, &id).await.map_err(status_only)?;
+
+    let experiment = experiments::create_experiment(&state.database, &id, &data.name, data.variants, auth.user_id.clone())
+        .await
+        .map_err(experiment_error)?;
+    info!(
+        "🧪 Project {} bắt đầu thử nghiệm prompt {} ({} biến thể)",
+        id,
+        experiment.experiment.name,
+        experiment.variants.len()
+    );
+    Ok((StatusCode::CREATED, Json(experiment)))
+}
+
+// GET /api/projects/:id/experiments/:experiment_id
+pub async fn get_experiment_report(
+    auth: AuthContext,
+    Path((id, experiment_id)): Path<(String, String)>,
+    State(state): State<AppState>,
+) -> Result<Json<ExperimentReport>, StatusCode> {
+    authorized_project(&state, &auth, &id).await?;
+    let experiment = project_experiment(&state, &id, &experiment_id).await?;
+
+    match experiments::report(&state.database, experiment).await {
+        Ok(report) => Ok(Json(report)),
+        Err(e) => {
+            tracing::error!("Failed to report experiment {}: {}", experiment_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// POST /api/projects/:id/experiments/:experiment_id/stop (organization admins)
+pub async fn stop_experiment(
+    auth: AuthContext,
+    Path((id, experiment_id)): Path<(String, String)>,
+    State(state): State<AppState>,
+) -> Result<Json<ExperimentRecord>, (StatusCode, Json<Value>)> {
+    let status_only = |status: StatusCode| (status, Json(json!({ "error": status.canonical_reason() })));
+    auth.require_org_admin().map_err(status_only)?;
+    authorized_project(&state, &auth, &id).await.map_err(status_only)?;
+    let experiment = project_experiment(&state, &id, &experiment_id).await.map_err(status_only)?;
+
+    let experiment = experiments::stop_experiment(&state.database, &experiment)
+        .await
+        .map_err(experiment_error)?;
+    info!("🧪 Đã dừng thử nghiệm prompt {} của project {}", experiment.name, id);
+    Ok(Json(experiment))
+}
+
+// GET /api/tickets/:id/custom-fields
+pub async fn get_ticket_custom_fields(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<FieldValues>, StatusCode> {
+    authorized_ticket(&state, &auth, &id).await?;
+
+    match custom_fields::ticket_values(&state.database, &id).await {
+        Ok(values) => Ok(Json(values)),
+        Err(e) => {
+            tracing::error!("Failed to get custom field values: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// PUT /api/tickets/:id/custom-fields
+pub async fn set_ticket_custom_fields(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    Json(data): Json<FieldValues>,
+) -> Result<Json<FieldValues>, (StatusCode, Json<Value>)> {
+    let status_only = |status: StatusCode| (status, Json(json!({ "error": status.canonical_reason() })));
+    let ticket = authorized_ticket(&state, &auth, &id).await.map_err(status_only)?;
+
+    let values = custom_fields::set_values(&state.database, &ticket, data)
+        .await
+        .map_err(field_error)?;
+    Ok(Json(values))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateTicketLinkRequest {
+    pub target_ticket_id: String,
+    pub link_type: LinkType,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResultFeedbackRequest {
+    /// `up` or `down`
+    pub rating: Rating,
+    pub comment: Option<String>,
+}
+
+// GET /api/tickets/:id/feedback
+pub async fn list_ticket_feedback(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<ResultFeedbackRecord>>, StatusCode> {
+    authorized_ticket(&state, &auth, &id).await?;
+
+    match state.database.list_result_feedback(&id).await {
+        Ok(feedback) => Ok(Json(feedback)),
+        Err(e) => {
+            tracing::error!("Failed to list ticket feedback: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// POST /api/tickets/:id/feedback
+pub async fn rate_ticket_result(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    Json(data): Json<ResultFeedbackRequest>,
+) -> Result<Json<ResultFeedbackRecord>, (StatusCode, Json<serde_json::Value>)> {
+    let status_only = |status: StatusCode| (status, Json(json!({ "error": status.canonical_reason() })));
+    let ticket = authorized_ticket(&state, &auth, &id).await.map_err(status_only)?;
+
+    match feedback::rate(&state.database, &ticket, auth.user_id.clone(), data.rating, data.comment).await {
+        Ok(feedback) => {
+            info!("⭐ Ticket {} được đánh giá {:?} (session {})", id, data.rating, feedback.session_id);
+            Ok(Json(feedback))
+        }
+        Err(FeedbackError::Database(e)) => {
+            tracing::error!("Failed to store feedback: {}", e);
+            Err(status_only(StatusCode::INTERNAL_SERVER_ERROR))
+        }
+        Err(e @ FeedbackError::NoResult) => Err((StatusCode::CONFLICT, Json(json!({ "error": e.to_string() })))),
+        Err(e) => Err((StatusCode::BAD_REQUEST, Json(json!({ "error": e.to_string() })))),
+    }
+}
+
+// GET /api/tickets/:id/links
+pub async fn list_ticket_links(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<TicketLinkRecord>>, StatusCode> {
+    authorized_ticket(&state, &auth, &id).await?;
+
+    match state.database.list_ticket_links(&id).await {
+        Ok(links) => Ok(Json(links)),
+        Err(e) => {
+            tracing::error!("Failed to list ticket links: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// POST /api/tickets/:id/links
+pub async fn create_ticket_link(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    Json(data): Json<CreateTicketLinkRequest>,
+) -> Result<(StatusCode, Json<TicketLinkRecord>), (StatusCode, Json<serde_json::Value>)> {
+    let status_only = |status: StatusCode| (status, Json(json!({ "error": status.canonical_reason() })));
+    let ticket = authorized_ticket(&state, &auth, &id).await.map_err(status_only)?;
+
+    match ticket_links::create_link(&state.database, &ticket, &data.target_ticket_id, data.link_type, auth.user_id.clone()).await {
+        Ok(link) => {
+            info!("🔗 Ticket {} {} ticket {}", id, link.link_type, link.target_ticket_id);
+            Ok((StatusCode::CREATED, Json(link)))
+        }
+        Err(LinkError::Database(e)) => {
+            tracing::error!("Failed to create ticket link: {}", e);
+            Err(status_only(StatusCode::INTERNAL_SERVER_ERROR))
+        }
+        Err(e @ LinkError::Exists(_)) | Err(e @ LinkError::Cycle(..)) => {
+            Err((StatusCode::CONFLICT, Json(json!({ "error": e.to_string() }))))
+        }
+        Err(e) => Err((StatusCode::BAD_REQUEST, Json(json!({ "error": e.to_string() })))),
+    }
+}
+
+// DELETE /api/tickets/:id/links/:link_id
+pub async fn delete_ticket_link(
+    auth: AuthContext,
+    Path((id, link_id)): Path<(String, String)>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, StatusCode> {
+    authorized_ticket(&state, &auth, &id).await?;
+
+    let link = state.database.get_ticket_link(&link_id).await.map_err(|e| {
+        tracing::error!("Failed to get ticket link: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    match link {
+        Some(link) if link.source_ticket_id == id || link.target_ticket_id == id => {}
+        _ => return Err(StatusCode::NOT_FOUND),
+    }
+
+    match state.database.delete_ticket_link(&link_id).await {
+        Ok(()) => Ok(StatusCode::NO_CONTENT),
+        Err(e) => {
+            tracing::error!("Failed to delete ticket link: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// GET /api/projects/:id/ticket-graph
+pub async fn get_ticket_graph(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<TicketGraph>, StatusCode> {
+    authorized_project(&state, &auth, &id).await?;
+
+    let tickets = state.database.list_tickets_by_project(&id).await;
+    let links = state.database.list_project_ticket_links(&id).await;
+    match tickets.and_then(|tickets| Ok((tickets, links?))) {
+        Ok((tickets, links)) => Ok(Json(ticket_links::build_graph(tickets, links))),
+        Err(e) => {
+            tracing::error!("Failed to build ticket graph: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// GET /api/tickets/:id/sessions
+pub async fn list_ticket_sessions(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<AnalysisSession>>, StatusCode> {
+    authorized_ticket(&state, &auth, &id).await?;
+
+    match state.database.list_sessions_by_ticket(&id).await {
+        Ok(sessions) => Ok(Json(sessions)),
+        Err(e) => {
+            tracing::error!("Failed to list ticket sessions: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// GET /api/sessions/:id
+pub async fn get_session(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<AnalysisSession>, StatusCode> {
+    let session = state
+        .database
+        .get_session(&id)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to get session: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    authorized_ticket(&state, &auth, &session.ticket_id).await?;
+
+    Ok(Json(session))
+}
+
+// GET /api/sessions/:id/changed-files
+pub async fn get_session_changed_files(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<ChangedFile>>, StatusCode> {
+    let Json(session) = get_session(auth, Path(id.clone()), State(state)).await?;
+    let Some(json) = session.changed_files else {
+        return Ok(Json(Vec::new()));
+    };
+    serde_json::from_str(&json).map(Json).map_err(|e| {
+        tracing::error!("Failed to read changed files of session {}: {}", id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+// GET /api/sessions/:id/raw?format=json|gz
+pub async fn get_session_raw(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    Query(params): Query<SessionRawParams>,
+    State(state): State<AppState>,
+) -> Result<Response, StatusCode> {
+    // Prompts and CLI output can quote anything the agent read
+    auth.require_org_admin()?;
+    let Json(session) = get_session(auth, Path(id), State(state.clone())).await?;
+    let id = session.id;
+
+    let internal = |e: anyhow::Error| {
+        tracing::error!("Failed to read transcript of session {}: {}", id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    let record = state
+        .database
+        .get_session_transcript(&id)
+        .await
+        .map_err(internal)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let gzipped = state.database.read_session_transcript(&record).await.map_err(internal)?;
+
+    if let SessionRawFormat::Gz = params.format {
+        let disposition = format!("attachment; filename=\"session-{}.jsonl.gz\"", id.replace(['"', '\\'], ""));
+        return Ok((
+            [
+                (header::CONTENT_TYPE, "application/gzip".to_string()),
+                (header::CONTENT_DISPOSITION, disposition),
+            ],
+            gzipped,
+        )
+            .into_response());
+    }
+
+    Ok(Json(SessionRawResponse {
+        transcript: crate::transcript::decompress(&gzipped).map_err(internal)?,
+        argv: serde_json::from_str(&record.argv).map_err(|e| internal(e.into()))?,
+        env_names: serde_json::from_str(&record.env_names).map_err(|e| internal(e.into()))?,
+        session_id: record.session_id,
+        prompt: record.prompt,
+        transcript_size: record.transcript_size,
+        created_at: record.created_at,
+    })
+    .into_response())
+}
+
+// GET /api/sessions/:id/report
+pub async fn get_session_report(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let tz = match &auth.user_id {
+        Some(user_id) => crate::preferences::load(&state.database, user_id)
+            .await
+            .ok()
+            .and_then(|preferences| preferences.tz()),
+        None => None,
+    };
+    let Json(session) = get_session(auth, Path(id.clone()), State(state.clone())).await?;
+    let report = SessionReport::build(&state.database, session)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to build report of session {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let disposition = format!("inline; filename=\"session-{}.html\"", id.replace(['"', '\\'], ""));
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/html; charset=utf-8".to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+            // Standalone: no scripts and nothing loaded from elsewhere
+            (
+                header::CONTENT_SECURITY_POLICY,
+                "default-src 'none'; style-src 'unsafe-inline'; img-src data:".to_string(),
+            ),
+        ],
+        report.render(Utc::now(), tz),
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReplayStreamParams {
+    /// 1.0 (default) keeps the original pace, 0 sends everything at once
+    pub speed: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReplayResponse {
+    pub run_id: String,
+    pub replay_of: String,
+}
+
+// GET /api/sessions/:id/replay-stream?speed=1
+pub async fn replay_session_stream(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    Query(params): Query<ReplayStreamParams>,
+    State(state): State<AppState>,
+) -> Result<Sse<impl futures_util::Stream<Item = Result<Event, axum::Error>>>, StatusCode> {
+    let Json(session) = get_session(auth, Path(id), State(state.clone())).await?;
+    if session.status == "running" {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let replay = Replay::load(&state.database, &session, params.speed).await.map_err(|e| {
+        tracing::error!("Failed to load logs of session {}: {}", session.id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    info!("⏯️ Phát lại {} log của session {}", replay.len(), session.id);
+
+    let events = replay.frames().map(|frame| {
+        Event::default()
+            .event(frame["message_type"].as_str().unwrap_or("message"))
+            .json_data(&frame)
+    });
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+// POST /api/sessions/:id/replay
+pub async fn replay_session(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<(StatusCode, Json<ReplayResponse>), (StatusCode, Json<serde_json::Value>)> {
+    let status_only = |status: StatusCode| (status, Json(json!({ "error": status.canonical_reason() })));
+    let Json(session) = get_session(auth.clone(), Path(id), State(state.clone()))
+        .await
+        .map_err(status_only)?;
+
+    match replay::replay_session(&state, &session, auth.user_id.clone()).await {
+        Ok(run_id) => Ok((
+            StatusCode::ACCEPTED,
+            Json(ReplayResponse {
+                run_id,
+                replay_of: session.id,
+            }),
+        )),
+        Err(ReplayError::Other(e)) => {
+            tracing::error!("Failed to replay session {}: {}", session.id, e);
+            Err(status_only(StatusCode::INTERNAL_SERVER_ERROR))
+        }
+        Err(e @ ReplayError::NotReplayable) => {
+            Err((StatusCode::UNPROCESSABLE_ENTITY, Json(json!({ "error": e.to_string() }))))
+        }
+        Err(e) => Err((StatusCode::CONFLICT, Json(json!({ "error": e.to_string() })))),
+    }
+}
+
+// GET /api/tickets/:id/files
+pub async fn list_ticket_files(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<TicketFileRecord>>, StatusCode> {
+    authorized_ticket(&state, &auth, &id).await?;
+
+    match state.database.list_ticket_files(&id).await {
+        Ok(files) => Ok(Json(files)),
+        Err(e) => {
+            tracing::error!("Failed to list ticket files: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResolveContextRequest {
+    pub code_context: String,
+    pub max_files: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TicketContextFiles {
+    /// Code context the files were resolved for; None when nothing was resolved yet
+    pub resolved_for: Option<String>,
+    pub resolved_at: Option<String>,
+    pub files: Vec<ResolvedFile>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetContextFilesRequest {
+    /// Context the selection is for; the ticket's code context when unset
+    pub code_context: Option<String>,
+    pub paths: Vec<String>,
+}
+
+/// The project's directory and compiled path rules
+async fn project_scope(state: &AppState, project: &ProjectRecord) -> Result<(std::path::PathBuf, PathPolicy), StatusCode> {
+    let rules = state.database.get_project_path_rules(&project.id).await.map_err(|e| {
+        tracing::error!("Failed to get project path rules: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let policy = rules.compile().map_err(|e| {
+        tracing::error!("Invalid path rules of project {}: {}", project.id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok((std::path::PathBuf::from(&project.directory_path), policy))
+}
+
+// POST /api/projects/:id/resolve-context
+pub async fn resolve_context(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    Json(data): Json<ResolveContextRequest>,
+) -> Result<Json<Vec<ResolvedFile>>, StatusCode> {
+    let project = authorized_project(&state, &auth, &id).await?;
+    let (root, policy) = project_scope(&state, &project).await?;
+    let max_files = data.max_files.unwrap_or(state.context_resolver.max_files).clamp(1, 50);
+
+    let resolved =
+        tokio::task::spawn_blocking(move || context_resolver::resolve(&root, &data.code_context, max_files, &policy)).await;
+    match resolved {
+        Ok(Ok(files)) => Ok(Json(files)),
+        Ok(Err(e)) => {
+            tracing::error!("Failed to resolve code context: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+        Err(e) => {
+            tracing::error!("Code context resolution panicked: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// GET /api/tickets/:id/context-files
+pub async fn get_ticket_context_files(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<TicketContextFiles>, StatusCode> {
+    authorized_ticket(&state, &auth, &id).await?;
+
+    match state.database.get_ticket_context_files(&id).await {
+        Ok(files) => Ok(Json(TicketContextFiles {
+            resolved_for: files.first().map(|f| f.resolved_for.clone()),
+            resolved_at: files.first().map(|f| f.resolved_at.clone()),
+            files: files.into_iter().map(ResolvedFile::from).collect(),
+        })),
+        Err(e) => {
+            tracing::error!("Failed to get ticket context files: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// PUT /api/tickets/:id/context-files
+pub async fn set_ticket_context_files(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    Json(data): Json<SetContextFilesRequest>,
+) -> Result<Json<TicketContextFiles>, (StatusCode, Json<serde_json::Value>)> {
+    let status_only = |status: StatusCode| (status, Json(json!({ "error": status.canonical_reason() })));
+    let ticket = authorized_ticket(&state, &auth, &id).await.map_err(status_only)?;
+    let project = authorized_project(&state, &auth, &ticket.project_id).await.map_err(status_only)?;
+    let (root, policy) = project_scope(&state, &project).await.map_err(status_only)?;
+
+    let code_context = data.code_context.or(ticket.code_context).unwrap_or_default();
+    if code_context.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, Json(json!({ "error": "Ticket has no code context" }))));
+    }
+    let mut files = Vec::new();
+    for path in data.paths.iter().map(|p| p.trim()).filter(|p| !p.is_empty()) {
+        let is_file = project_files::resolve_in_project(&root, path).is_ok_and(|resolved| resolved.is_file());
+        if !is_file || !policy.allows(path) {
+            return Err((StatusCode::BAD_REQUEST, Json(json!({ "error": format!("{} is not a project file", path) }))));
+        }
+        files.push(ResolvedFile {
+            path: path.to_string(),
+            score: 0,
+            reasons: vec!["selected".to_string()],
+        });
+    }
+
+    if let Err(e) = context_resolver::store(&state.database, &id, &code_context, &files).await {
+        tracing::error!("Failed to store ticket context files: {}", e);
+        return Err(status_only(StatusCode::INTERNAL_SERVER_ERROR));
+    }
+    get_ticket_context_files(auth, Path(id), State(state))
+        .await
+        .map_err(status_only)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TimelineQueryParams {
+    /// Run to show; the latest run when unset
+    pub run_id: Option<String>,
+}
+
+// GET /api/tickets/:id/timeline
+pub async fn get_ticket_timeline(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    Query(params): Query<TimelineQueryParams>,
+    State(state): State<AppState>,
+) -> Result<Json<TicketTimeline>, StatusCode> {
+    authorized_ticket(&state, &auth, &id).await?;
+
+    match timeline::ticket_timeline(&state.database, &id, params.run_id.as_deref()).await {
+        Ok(Some(timeline)) => Ok(Json(timeline)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            tracing::error!("Failed to build ticket timeline: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// GET /api/tickets/:id/presence
+pub async fn get_ticket_presence(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<presence::Viewer>>, StatusCode> {
+    authorized_ticket(&state, &auth, &id).await?;
+    Ok(Json(state.presence.snapshot(&id, Utc::now())))
+}
+
+// GET /api/markdown/highlight.css
+pub async fn get_highlight_css() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/css; charset=utf-8")],
+        markdown::highlight_css(),
+    )
+}
+
+// GET /api/tickets/:id/analysis-status
+pub async fn get_analysis_status(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<analysis_runner::AnalysisStatus>, StatusCode> {
+    let ticket = authorized_ticket(&state, &auth, &id).await?;
+
+    match analysis_runner::analysis_status(&state, &ticket).await {
+        Ok(status) => Ok(Json(status)),
+        Err(e) => {
+            tracing::error!("Failed to get analysis status: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// POST /api/tickets/:id/stop-analysis
+pub async fn stop_analysis(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<Value>, StatusCode> {
+    info!("⛔ Stop analysis requested for ticket: {}", id);
+
+    // Check if ticket exists
+    let ticket = match auth.ticket(&state.database, &id).await {
+        Ok(Some(ticket)) => ticket,
+        Ok(None) => {
+            error!("Ticket {} not found", id);
+            return Err(StatusCode::NOT_FOUND);
+        }
+        Err(e) => {
+            error!("Failed to get ticket {}: {}", id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    // Check if ticket is currently analyzing
+    if !ticket.is_analyzing {
+        warn!("Ticket {} is not currently being analyzed", id);
+        return Ok(Json(json!({
+            "success": false,
+            "message": "Ticket is not being analyzed"
+        })));
+    }
+
+    // The run cleans up after its agent stops; without one (e.g. after a restart) clean up here
+    let run_id = match analysis_runner::cancel_analysis(&state, &id).await {
+        Some(run_id) => Some(run_id),
+        None => {
+            warn!("No running task found for ticket {} (may have already completed)", id);
+            let run_id = analysis_runner::record_stopped(&state.database, &state.msg_store, &state.broadcast_tx, &id).await;
+            if let Some(run_id) = &run_id {
+                state.msg_store.end_run(&id, run_id).await;
+            }
+            run_id
+        }
+    };
+
+    info!("✅ Stop requested for analysis of ticket {}", id);
+    Ok(Json(json!({
+        "success": true,
+        "message": "Analysis stopped successfully",
+        "run_id": run_id
+    })))
+}
+
+
+/// Number of projects listed in `busiest_projects`
+const BUSIEST_PROJECTS_LIMIT: u32 = 10;
+
+fn is_valid_date_param(value: &str) -> bool {
+    chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d").is_ok()
+        || chrono::DateTime::parse_from_rfc3339(value).is_ok()
+}
+
+async fn build_analytics(
+    state: &AppState,
+    auth: &AuthContext,
+    params: AnalyticsQueryParams,
+    project_id: Option<String>,
+) -> Result<AnalyticsResponse, StatusCode> {
+    for value in [&params.from, &params.to].into_iter().flatten() {
+        if !is_valid_date_param(value) {
+            warn!("Invalid analytics date filter: {}", value);
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    }
+
+    let filter = AnalyticsFilter {
+        from: params.from,
+        to: params.to,
+        project_id,
+        org_id: Some(auth.org_id.clone()),
+    };
+
+    let db_error = |e: anyhow::Error| {
+        error!("Failed to compute analytics: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let totals = state.database.get_session_totals(&filter).await.map_err(db_error)?;
+    let runs_per_day = state.database.get_runs_per_day(&filter).await.map_err(db_error)?;
+    let by_agent = state.database.get_agent_breakdown(&filter).await.map_err(db_error)?;
+    let busiest_projects = match filter.project_id {
+        Some(_) => None,
+        None => Some(
+            state
+                .database
+                .get_busiest_projects(&filter, BUSIEST_PROJECTS_LIMIT)
+                .await
+                .map_err(db_error)?,
+        ),
+    };
+
+    let quality = state.database.get_feedback_quality(&filter).await.map_err(db_error)?;
+    let time = time_tracking::analytics(&state.database, &filter, &state.sla)
+        .await
+        .map_err(db_error)?;
+
+    Ok(AnalyticsResponse {
+        failure_rate: totals.rate(totals.failed),
+        cancel_rate: totals.rate(totals.cancelled),
+        project_id: filter.project_id,
+        from: filter.from,
+        to: filter.to,
+        total_runs: totals.total_runs,
+        completed: totals.completed,
+        failed: totals.failed,
+        cancelled: totals.cancelled,
+        running: totals.running,
+        avg_duration_ms: totals.avg_duration_ms,
+        max_duration_ms: totals.max_duration_ms,
+        runs_per_day,
+        by_agent,
+        busiest_projects,
+        quality,
+        time,
+    })
+}
+
+// GET /api/analytics/summary
+pub async fn get_analytics_summary(
+    auth: AuthContext,
+    Query(params): Query<AnalyticsQueryParams>,
+    State(state): State<AppState>,
+) -> Result<Json<AnalyticsResponse>, StatusCode> {
+    build_analytics(&state, &auth, params, None).await.map(Json)
+}
+
+/// Examples in a feedback export unless `limit` says otherwise, and the most it may ask for
+const FEEDBACK_EXPORT_LIMIT: u32 = 500;
+const MAX_FEEDBACK_EXPORT_LIMIT: u32 = 5000;
+
+#[derive(Debug, Deserialize)]
+pub struct FeedbackExportParams {
+    /// Inclusive start date of the rated runs, `YYYY-MM-DD` or RFC 3339
+    pub from: Option<String>,
+    /// Inclusive end date of the rated runs, `YYYY-MM-DD` or RFC 3339
+    pub to: Option<String>,
+    pub project_id: Option<String>,
+    pub limit: Option<u32>,
+}
+
+// GET /api/analytics/feedback/export
+pub async fn export_low_rated_feedback(
+    auth: AuthContext,
+    Query(params): Query<FeedbackExportParams>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, StatusCode> {
+    for value in [&params.from, &params.to].into_iter().flatten() {
+        if !is_valid_date_param(value) {
+            warn!("Invalid feedback export date filter: {}", value);
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    }
+    if let Some(project_id) = &params.project_id {
+        authorized_project(&state, &auth, project_id).await?;
+    }
+
+    let filter = AnalyticsFilter {
+        from: params.from,
+        to: params.to,
+        project_id: params.project_id,
+        org_id: Some(auth.org_id.clone()),
+    };
+    let limit = params.limit.unwrap_or(FEEDBACK_EXPORT_LIMIT).clamp(1, MAX_FEEDBACK_EXPORT_LIMIT);
+    let examples = feedback::low_rated_examples(&state.database, &filter, limit)
+        .await
+        .map_err(|e| {
+            error!("Failed to export feedback: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/x-ndjson; charset=utf-8".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"low-rated-feedback.jsonl\"".to_string(),
+            ),
+        ],
+        feedback::to_jsonl(&examples),
+    ))
+}
+
+// GET /api/projects/:id/analytics
+pub async fn get_project_analytics(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    Query(params): Query<AnalyticsQueryParams>,
+    State(state): State<AppState>,
+) -> Result<Json<AnalyticsResponse>, StatusCode> {
+    authorized_project(&state, &auth, &id).await?;
+
+    build_analytics(&state, &auth, params, Some(id)).await.map(Json)
+}
+
+// GET /api/projects/:id/dashboard
+pub async fn get_project_dashboard(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<ProjectDashboard>, StatusCode> {
+    authorized_project(&state, &auth, &id).await?;
+
+    match dashboard::build(&state.database, &id, Utc::now()).await {
+        Ok(dashboard) => Ok(Json(dashboard)),
+        Err(e) => {
+            tracing::error!("Failed to build dashboard of project {}: {}", id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// GET /api/projects/:id/coverage
+pub async fn get_project_coverage(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<ProjectCoverage>, StatusCode> {
+    let project = authorized_project(&state, &auth, &id).await?;
+
+    match coverage::project_coverage(&state.database, &project).await {
+        Ok(coverage) => Ok(Json(coverage)),
+        Err(e) => {
+            tracing::error!("Failed to build project coverage: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// GET /api/projects/:id/path-policy
+pub async fn get_project_path_policy(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<PathRules>, StatusCode> {
+    authorized_project(&state, &auth, &id).await?;
+
+    match state.database.get_project_path_rules(&id).await {
+        Ok(rules) => Ok(Json(rules)),
+        Err(e) => {
+            tracing::error!("Failed to get project path rules: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// PUT /api/projects/:id/path-policy (organization admins)
+pub async fn set_project_path_policy(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    Json(data): Json<PathRules>,
+) -> Result<Json<PathRules>, (StatusCode, Json<Value>)> {
+    let status_only = |status: StatusCode| (status, Json(json!({ "error": status.canonical_reason() })));
+    auth.require_org_admin().map_err(status_only)?;
+    authorized_project(&state, &auth, &id).await.map_err(status_only)?;
+
+    let clean = |patterns: Vec<String>| -> Vec<String> {
+        patterns
+            .into_iter()
+            .map(|p| p.trim().to_string())
+            .filter(|p| !p.is_empty())
+            .collect()
+    };
+    let rules = PathRules {
+        include: clean(data.include),
+        exclude: clean(data.exclude),
+    };
+    if let Err(e) = rules.compile() {
+        warn!("Rejected path rules for project {}: {}", id, e);
+        return Err((StatusCode::BAD_REQUEST, Json(json!({ "error": e.to_string() }))));
+    }
+
+    match state.database.set_project_path_rules(&id, &rules).await {
+        Ok(()) => {
+            info!("Path rules of project {} updated", id);
+            Ok(Json(rules))
+        }
+        Err(e) => {
+            tracing::error!("Failed to save project path rules: {}", e);
+            Err(status_only(StatusCode::INTERNAL_SERVER_ERROR))
+        }
+    }
+}
+
+// GET /api/projects/:id/tool-policy
+pub async fn get_project_tool_policy(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<ToolPolicyResponse>, StatusCode> {
+    authorized_project(&state, &auth, &id).await?;
+
+    match state.database.get_project_tool_policy(&id).await {
+        Ok(allowed_tools) => Ok(Json(ToolPolicyResponse::new(allowed_tools))),
+        Err(e) => {
+            tracing::error!("Failed to get project tool policy: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// PUT /api/projects/:id/tool-policy (organization admins)
+pub async fn set_project_tool_policy(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    Json(data): Json<ToolPolicyRequest>,
+) -> Result<Json<ToolPolicyResponse>, (StatusCode, Json<Value>)> {
+    let status_only = |status: StatusCode| (status, Json(json!({ "error": status.canonical_reason() })));
+    auth.require_org_admin().map_err(status_only)?;
+    authorized_project(&state, &auth, &id).await.map_err(status_only)?;
+
+    let allowed_tools = data.allowed_tools.map(|tools| {
+        let mut clean: Vec<String> = Vec::new();
+        for tool in tools.iter().map(|t| t.trim()).filter(|t| !t.is_empty()) {
+            if !clean.iter().any(|c| c == tool) {
+                clean.push(tool.to_string());
+            }
+        }
+        clean
+    });
+    if let Some(tools) = &allowed_tools {
+        if let Err(e) = tool_policy::validate(tools) {
+            warn!("Rejected tool policy for project {}: {}", id, e);
+            return Err((StatusCode::BAD_REQUEST, Json(json!({ "error": e }))));
+        }
+    }
+
+    match state.database.set_project_tool_policy(&id, allowed_tools.as_deref()).await {
+        Ok(()) => {
+            info!("Tool policy of project {} updated: {:?}", id, allowed_tools);
+            Ok(Json(ToolPolicyResponse::new(allowed_tools)))
+        }
+        Err(e) => {
+            tracing::error!("Failed to save project tool policy: {}", e);
+            Err(status_only(StatusCode::INTERNAL_SERVER_ERROR))
+        }
+    }
+}
+
+// GET /api/projects/:id/log-persistence
+pub async fn get_project_log_persistence(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<LogPersistenceResponse>, StatusCode> {
+    authorized_project(&state, &auth, &id).await?;
+
+    match state.database.get_project_log_persistence(&id).await {
+        Ok(persistence) => Ok(Json(LogPersistenceResponse {
+            persistence,
+            default: state.msg_store.log_persistence().clone(),
+        })),
+        Err(e) => {
+            tracing::error!("Failed to get project log persistence: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// PUT /api/projects/:id/log-persistence (organization admins; applies from the next run)
+pub async fn set_project_log_persistence(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    Json(data): Json<LogPersistenceRequest>,
+) -> Result<Json<LogPersistenceResponse>, StatusCode> {
+    auth.require_org_admin()?;
+    authorized_project(&state, &auth, &id).await?;
+
+    let persistence = data.persistence.map(|mut persistence| {
+        if let Some(types) = persistence.message_types.take() {
+            let mut clean: Vec<LogMessageType> = Vec::new();
+            for message_type in types {
+                if !clean.contains(&message_type) {
+                    clean.push(message_type);
+                }
+            }
+            persistence.message_types = Some(clean);
+        }
+        persistence
+    });
+    match state.database.set_project_log_persistence(&id, persistence.as_ref()).await {
+        Ok(()) => {
+            info!("Log persistence of project {} updated: {:?}", id, persistence);
+            Ok(Json(LogPersistenceResponse {
+                persistence,
+                default: state.msg_store.log_persistence().clone(),
+            }))
+        }
+        Err(e) => {
+            tracing::error!("Failed to save project log persistence: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// GET /api/projects/:id/budget
+pub async fn get_project_budget(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<BudgetResponse>, StatusCode> {
+    authorized_project(&state, &auth, &id).await?;
+
+    match tokio::try_join!(state.database.get_project_budget(&id), budgets::status(&state.database, &id, Utc::now())) {
+        Ok((budget, status)) => Ok(Json(BudgetResponse { budget, status })),
+        Err(e) => {
+            tracing::error!("Failed to get budget of project {}: {}", id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// PUT /api/projects/:id/budget (organization admins)
+pub async fn set_project_budget(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    Json(data): Json<BudgetRequest>,
+) -> Result<Json<BudgetResponse>, (StatusCode, Json<Value>)> {
+    let status_only = |status: StatusCode| (status, Json(json!({ "error": status.canonical_reason() })));
+    auth.require_org_admin().map_err(status_only)?;
+    authorized_project(&state, &auth, &id).await.map_err(status_only)?;
+    if let Some(Err(e)) = data.budget.as_ref().map(Budget::validate) {
+        return Err((StatusCode::BAD_REQUEST, Json(json!({ "error": e }))));
+    }
+
+    let saved = async {
+        state.database.set_project_budget(&id, data.budget.as_ref(), auth.user_id.as_deref()).await?;
+        budgets::status(&state.database, &id, Utc::now()).await
+    };
+    match saved.await {
+        Ok(status) => {
+            info!("Budget of project {} updated: {:?}", id, data.budget);
+            Ok(Json(BudgetResponse {
+                budget: data.budget,
+                status,
+            }))
+        }
+        Err(e) => {
+            tracing::error!("Failed to save budget of project {}: {}", id, e);
+            Err(status_only(StatusCode::INTERNAL_SERVER_ERROR))
+        }
+    }
+}
+
+// GET /api/projects/:id/preflight
+pub async fn get_project_preflight(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<PreflightResponse>, StatusCode> {
+    authorized_project(&state, &auth, &id).await?;
+
+    match state.database.get_project_preflight_checks(&id).await {
+        Ok(checks) => Ok(Json(PreflightResponse {
+            checks,
+            allow_commands: state.preflight.allow_commands,
+        })),
+        Err(e) => {
+            tracing::error!("Failed to get project pre-flight checks: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// PUT /api/projects/:id/preflight (organization admins)
+pub async fn set_project_preflight(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    Json(data): Json<PreflightRequest>,
+) -> Result<Json<PreflightResponse>, (StatusCode, Json<Value>)> {
+    let status_only = |status: StatusCode| (status, Json(json!({ "error": status.canonical_reason() })));
+    auth.require_org_admin().map_err(status_only)?;
+    authorized_project(&state, &auth, &id).await.map_err(status_only)?;
+
+    if let Err(e) = preflight::validate(&data.checks, &state.preflight) {
+        warn!("Rejected pre-flight checks for project {}: {}", id, e);
+        return Err((StatusCode::BAD_REQUEST, Json(json!({ "error": e }))));
+    }
+
+    match state.database.set_project_preflight_checks(&id, &data.checks).await {
+        Ok(()) => {
+            info!("Pre-flight checks of project {} updated: {} check(s)", id, data.checks.len());
+            Ok(Json(PreflightResponse {
+                checks: data.checks,
+                allow_commands: state.preflight.allow_commands,
+            }))
+        }
+        Err(e) => {
+            tracing::error!("Failed to save project pre-flight checks: {}", e);
+            Err(status_only(StatusCode::INTERNAL_SERVER_ERROR))
+        }
+    }
+}
+
+// GET /api/projects/:id/post-run-hooks
+pub async fn get_project_post_run_hooks(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<PostRunHooks>, StatusCode> {
+    authorized_project(&state, &auth, &id).await?;
+
+    match post_run::load(&state.database, &id).await {
+        Ok(hooks) => Ok(Json(hooks)),
+        Err(e) => {
+            tracing::error!("Failed to get project post-run hooks: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// PUT /api/projects/:id/post-run-hooks (organization admins)
+pub async fn set_project_post_run_hooks(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    Json(data): Json<PostRunHooks>,
+) -> Result<Json<PostRunHooks>, (StatusCode, Json<Value>)> {
+    let status_only = |status: StatusCode| (status, Json(json!({ "error": status.canonical_reason() })));
+    auth.require_org_admin().map_err(status_only)?;
+    authorized_project(&state, &auth, &id).await.map_err(status_only)?;
+
+    if let Err(e) = post_run::validate(&data, &state.preflight) {
+        warn!("Rejected post-run hooks for project {}: {}", id, e);
+        return Err((StatusCode::BAD_REQUEST, Json(json!({ "error": e }))));
+    }
+
+    match post_run::save(&state.database, &id, &data).await {
+        Ok(()) => {
+            info!("Post-run hooks of project {} updated: {} hook(s)", id, data.hooks.len());
+            Ok(Json(data))
+        }
+        Err(e) => {
+            tracing::error!("Failed to save project post-run hooks: {}", e);
+            Err(status_only(StatusCode::INTERNAL_SERVER_ERROR))
+        }
+    }
+}
+
+// GET /api/projects/:id/summary-settings
+pub async fn get_project_summary_settings(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<SummarySettings>, StatusCode> {
+    authorized_project(&state, &auth, &id).await?;
+
+    match state.summarizer.settings(&state.database, &id).await {
+        Ok(settings) => Ok(Json(settings)),
+        Err(e) => {
+            tracing::error!("Failed to get project summary settings: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// PUT /api/projects/:id/summary-settings (organization admins)
+pub async fn set_project_summary_settings(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    Json(data): Json<SummarySettingsRequest>,
+) -> Result<Json<SummarySettings>, (StatusCode, Json<Value>)> {
+    let status_only = |status: StatusCode| (status, Json(json!({ "error": status.canonical_reason() })));
+    auth.require_org_admin().map_err(status_only)?;
+    authorized_project(&state, &auth, &id).await.map_err(status_only)?;
+
+    if data.max_lines.is_some_and(|n| !(1..=summary::MAX_LINES_LIMIT).contains(&n)) {
+        let error = format!("max_lines must be between 1 and {}", summary::MAX_LINES_LIMIT);
+        return Err((StatusCode::BAD_REQUEST, Json(json!({ "error": error }))));
+    }
+
+    let record = ProjectSummarySettingsRecord {
+        project_id: id.clone(),
+        enabled: data.enabled,
+        max_lines: data.max_lines.map(i64::from),
+        updated_at: Utc::now().to_rfc3339(),
+    };
+    let saved = match state.database.set_project_summary_settings(&record).await {
+        Ok(()) => state.summarizer.settings(&state.database, &id).await,
+        Err(e) => Err(e),
+    };
+    match saved {
+        Ok(settings) => {
+            info!("Summary settings of project {} updated: {:?}", id, settings);
+            Ok(Json(settings))
+        }
+        Err(e) => {
+            tracing::error!("Failed to save project summary settings: {}", e);
+            Err(status_only(StatusCode::INTERNAL_SERVER_ERROR))
+        }
+    }
+}
+
+// GET /api/projects/:id/agent-credentials (organization admins)
+pub async fn list_agent_credentials(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<AgentCredentialsResponse>, StatusCode> {
+    auth.require_org_admin()?;
+    authorized_project(&state, &auth, &id).await?;
+
+    match state.database.list_project_credentials(&id).await {
+        Ok(records) => Ok(Json(AgentCredentialsResponse {
+            enabled: state.credentials.is_some(),
+            credentials: records.iter().filter_map(MaskedCredential::from_record).collect(),
+        })),
+        Err(e) => {
+            tracing::error!("Failed to list agent credentials of project {}: {}", id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// PUT /api/projects/:id/agent-credentials/:provider (organization admins)
+pub async fn set_agent_credential(
+    auth: AuthContext,
+    Path((id, provider)): Path<(String, String)>,
+    State(state): State<AppState>,
+    Json(data): Json<SetAgentCredentialRequest>,
+) -> Result<Json<MaskedCredential>, (StatusCode, Json<Value>)> {
+    let status_only = |status: StatusCode| (status, Json(json!({ "error": status.canonical_reason() })));
+    auth.require_org_admin().map_err(status_only)?;
+    authorized_project(&state, &auth, &id).await.map_err(status_only)?;
+    let provider = Provider::from_str(&provider).ok_or(status_only(StatusCode::NOT_FOUND))?;
+    let Some(cipher) = state.credentials.as_deref() else {
+        return Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({ "error": "AGENT_CREDENTIALS_KEY is not configured on the server" })),
+        ));
+    };
+
+    let api_key = data.api_key.trim();
+    if api_key.is_empty() || api_key.len() > 512 || api_key.chars().any(char::is_whitespace) {
+        return Err((StatusCode::BAD_REQUEST, Json(json!({ "error": "Invalid API key" }))));
+    }
+
+    match cipher.store(&state.database, &id, provider, api_key, auth.user_id.as_deref()).await {
+        Ok(record) => {
+            info!("🔑 Project {} dùng API key {} riêng", id, provider.as_str());
+            MaskedCredential::from_record(&record)
+                .map(Json)
+                .ok_or(status_only(StatusCode::INTERNAL_SERVER_ERROR))
+        }
+        Err(e) => {
+            tracing::error!("Failed to store {} credential of project {}: {}", provider.as_str(), id, e);
+            Err(status_only(StatusCode::INTERNAL_SERVER_ERROR))
+        }
+    }
+}
+
+// DELETE /api/projects/:id/agent-credentials/:provider (organization admins)
+pub async fn delete_agent_credential(
+    auth: AuthContext,
+    Path((id, provider)): Path<(String, String)>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, StatusCode> {
+    auth.require_org_admin()?;
+    authorized_project(&state, &auth, &id).await?;
+    let provider = Provider::from_str(&provider).ok_or(StatusCode::NOT_FOUND)?;
+
+    match state.database.delete_project_credential(&id, provider.as_str()).await {
+        Ok(true) => {
+            info!("🔑 Project {} quay lại dùng API key {} của server", id, provider.as_str());
+            Ok(StatusCode::NO_CONTENT)
+        }
+        Ok(false) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            tracing::error!("Failed to delete {} credential of project {}: {}", provider.as_str(), id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// GET /api/agents
+pub async fn list_agents(State(state): State<AppState>) -> Json<Vec<AgentInfo>> {
+    Json(state.agents.list().to_vec())
+}
+
+// GET /api/metrics
+pub async fn get_metrics(auth: AuthContext, State(state): State<AppState>) -> Result<Json<Value>, StatusCode> {
+    auth.require_instance_admin()?;
+
+    let running_analyses = state.running_tasks.lock().await.len();
+    let queued_analyses = state.job_queue.lock().await.len();
+    Ok(Json(json!({
+        "running_analyses": running_analyses,
+        "queued_analyses": queued_analyses,
+        "db_cache": state.database.cache_stats(),
+    })))
+}
+
+// GET /api/admin/migrations
+pub async fn list_migrations(
+    auth: AuthContext,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<MigrationStatus>>, StatusCode> {
+    auth.require_instance_admin()?;
+
+    match state.database.migration_status().await {
+        Ok(statuses) => Ok(Json(statuses)),
+        Err(e) => {
+            tracing::error!("Failed to read migration status: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// GET /api/admin/backups
+pub async fn list_backups(auth: AuthContext, State(state): State<AppState>) -> Result<Json<Vec<BackupInfo>>, StatusCode> {
+    auth.require_instance_admin()?;
+
+    match backup::list_backups(&state.backups).await {
+        Ok(backups) => Ok(Json(backups)),
+        Err(e) => {
+            tracing::error!("Failed to list backups: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// POST /api/admin/backups
+pub async fn create_backup(auth: AuthContext, State(state): State<AppState>) -> Result<Json<BackupInfo>, StatusCode> {
+    auth.require_instance_admin()?;
+
+    match backup::create_backup(&state.database, &state.backups).await {
+        Ok(backup) => Ok(Json(backup)),
+        Err(e) => {
+            tracing::error!("Failed to create backup: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// GET /api/admin/backups/:name
+pub async fn download_backup(
+    auth: AuthContext,
+    Path(name): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Response, StatusCode> {
+    auth.require_instance_admin()?;
+
+    let blob = match backup::open_backup(&state.backups, &name).await {
+        // Large backups download straight from the bucket
+        Ok(Some(BackupDownload::Redirect(url))) => return Ok(Redirect::temporary(&url).into_response()),
+        Ok(Some(BackupDownload::Stream(blob))) => blob,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            warn!("Backup download rejected: {}", e);
+            return Err(StatusCode::NOT_FOUND);
+        }
+    };
+
+    let mut response = (
+        [
+            (header::CONTENT_TYPE, "application/vnd.sqlite3".to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", name)),
+        ],
+        axum::body::Body::from_stream(blob.stream),
+    )
+        .into_response();
+    if let Some(size) = blob.size {
+        response.headers_mut().insert(header::CONTENT_LENGTH, size.into());
+    }
+    Ok(response)
+}
+
+// GET /api/admin/database/stats
+pub async fn get_database_stats(auth: AuthContext, State(state): State<AppState>) -> Result<Json<DatabaseStats>, StatusCode> {
+    auth.require_instance_admin()?;
+
+    match db_maintenance::stats(&state.database).await {
+        Ok(stats) => Ok(Json(stats)),
+        Err(e) => {
+            tracing::error!("Failed to read database stats: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// POST /api/admin/database/integrity-check
+pub async fn check_database_integrity(
+    auth: AuthContext,
+    State(state): State<AppState>,
+) -> Result<Json<MaintenanceReport>, StatusCode> {
+    run_database_maintenance(auth, state, MaintenanceTask::IntegrityCheck).await
+}
+
+// POST /api/admin/database/vacuum
+pub async fn vacuum_database(auth: AuthContext, State(state): State<AppState>) -> Result<Json<MaintenanceReport>, StatusCode> {
+    run_database_maintenance(auth, state, MaintenanceTask::Vacuum).await
+}
+
+// POST /api/admin/database/analyze
+pub async fn analyze_database(auth: AuthContext, State(state): State<AppState>) -> Result<Json<MaintenanceReport>, StatusCode> {
+    run_database_maintenance(auth, state, MaintenanceTask::Analyze).await
+}
+
+async fn run_database_maintenance(
+    auth: AuthContext,
+    state: AppState,
+    task: MaintenanceTask,
+) -> Result<Json<MaintenanceReport>, StatusCode> {
+    auth.require_instance_admin()?;
+
+    match db_maintenance::run(&state.database, &[task]).await {
+        Ok(report) => {
+            info!("🧹 Database {:?}: {} → {} bytes", task, report.size_before, report.size_after);
+            Ok(Json(report))
+        }
+        Err(e) => {
+            tracing::error!("Database {:?} failed: {}", task, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// GET /api/admin/redaction-patterns
+pub async fn list_redaction_patterns(
+    auth: AuthContext,
+    State(state): State<AppState>,
+) -> Result<Json<RedactionPatternsResponse>, StatusCode> {
+    auth.require_instance_admin()?;
+
+    let redactor = state.msg_store.redactor();
+    Ok(Json(RedactionPatternsResponse {
+        enabled: redactor.enabled(),
+        patterns: redactor.patterns(),
+    }))
+}
+
+// POST /api/admin/redaction-patterns
+pub async fn create_redaction_pattern(
+    auth: AuthContext,
+    State(state): State<AppState>,
+    Json(data): Json<CreateRedactionPatternRequest>,
+) -> Result<Json<RedactionPatternRecord>, (StatusCode, Json<Value>)> {
+    if !auth.is_instance_admin() {
+        return Err((StatusCode::FORBIDDEN, Json(json!({ "error": "Instance admin required" }))));
+    }
+    let name = data.name.trim();
+    if name.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, Json(json!({ "error": "Name must not be empty" }))));
+    }
+    if let Err(e) = redaction::compile(&data.pattern) {
+        warn!("Rejected redaction pattern {:?}: {}", data.pattern, e);
+        return Err((StatusCode::BAD_REQUEST, Json(json!({ "error": e }))));
+    }
+
+    let internal_error = |e: anyhow::Error| {
+        error!("Failed to save redaction pattern: {}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": "Failed to save redaction pattern" })))
+    };
+    let record = RedactionPatternRecord {
+        id: uuid::Uuid::new_v4().to_string(),
+        name: name.to_string(),
+        pattern: data.pattern,
+        created_by: auth.user_id.clone(),
+        created_at: Utc::now().to_rfc3339(),
+    };
+    state.database.insert_redaction_pattern(&record).await.map_err(internal_error)?;
+    state.msg_store.redactor().reload(&state.database).await.map_err(internal_error)?;
+
+    info!("Redaction pattern '{}' added", record.name);
+    Ok(Json(record))
+}
+
+// DELETE /api/admin/redaction-patterns/:id
+pub async fn delete_redaction_pattern(
+    auth: AuthContext,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    auth.require_instance_admin()?;
+
+    match state.database.delete_redaction_pattern(&id).await {
+        Ok(true) => {}
+        Ok(false) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to delete redaction pattern {}: {}", id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+    if let Err(e) = state.msg_store.redactor().reload(&state.database).await {
+        error!("Failed to reload redaction patterns: {}", e);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    info!("Redaction pattern {} deleted", id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// GET /api/admin/log-level
+pub async fn get_log_level(auth: AuthContext, State(state): State<AppState>) -> Result<Json<Value>, StatusCode> {
+    auth.require_instance_admin()?;
+
+    Ok(Json(json!({ "directives": state.log_level.current() })))
+}
+
+// GET /api/admin/tasks
+pub async fn list_tasks(auth: AuthContext, State(state): State<AppState>) -> Result<Json<TaskList>, StatusCode> {
+    auth.require_instance_admin()?;
+
+    Ok(Json(state.tasks.snapshot()))
+}
+
+// PUT /api/admin/log-level
+pub async fn set_log_level(
+    auth: AuthContext,
+    State(state): State<AppState>,
+    Json(data): Json<LogLevelRequest>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    if !auth.is_instance_admin() {
+        return Err((StatusCode::FORBIDDEN, Json(json!({ "error": "Instance admin required" }))));
+    }
+
+    match state.log_level.set(&data.directives) {
+        Ok(()) => {
+            info!("Log filter changed to: {}", data.directives);
+            Ok(Json(json!({ "directives": state.log_level.current() })))
+        }
+        Err(e) => {
+            warn!("Rejected log filter {:?}: {}", data.directives, e);
+            Err((StatusCode::BAD_REQUEST, Json(json!({ "error": e }))))
+        }
+    }
+}
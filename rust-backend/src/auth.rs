@@ -0,0 +1,395 @@
+use crate::database::{Database, ProjectRecord, TicketRecord, UserRecord, DEFAULT_ORG_ID};
+use crate::AppState;
+use anyhow::{anyhow, Result};
+use argon2::password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use async_trait::async_trait;
+use axum::extract::FromRequestParts;
+use axum::http::{header, request::Parts, StatusCode};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::warn;
+
+/// Authentication settings, read from `AUTH_REQUIRED` / `AUTH_TOKEN_TTL_HOURS`
+#[derive(Debug, Clone)]
+pub struct AuthConfig {
+    /// When false, requests without a token act as an owner of the default organization
+    /// (single-tenant deployments and the bundled frontend keep working unchanged)
+    pub required: bool,
+    pub token_ttl_hours: i64,
+    pub invite_ttl_hours: i64,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            required: false,
+            token_ttl_hours: 24 * 30,
+            invite_ttl_hours: 24 * 7,
+        }
+    }
+}
+
+impl AuthConfig {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            required: std::env::var("AUTH_REQUIRED")
+                .map(|v| matches!(v.to_lowercase().as_str(), "1" | "true" | "yes"))
+                .unwrap_or(defaults.required),
+            token_ttl_hours: std::env::var("AUTH_TOKEN_TTL_HOURS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(defaults.token_ttl_hours),
+            invite_ttl_hours: std::env::var("AUTH_INVITE_TTL_HOURS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(defaults.invite_ttl_hours),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OrgRole {
+    Owner,
+    Admin,
+    Member,
+}
+
+impl OrgRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrgRole::Owner => "owner",
+            OrgRole::Admin => "admin",
+            OrgRole::Member => "member",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "owner" => Some(OrgRole::Owner),
+            "admin" => Some(OrgRole::Admin),
+            "member" => Some(OrgRole::Member),
+            _ => None,
+        }
+    }
+}
+
+/// Who an external provider (OIDC, LDAP) says signed in
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExternalIdentity {
+    /// The provider: an OIDC issuer or an LDAP server URL
+    pub issuer: String,
+    /// Stable ID of the user at the provider (OIDC subject, LDAP DN)
+    pub subject: String,
+    pub email: Option<String>,
+    /// True only when the provider vouched for the email
+    pub email_verified: bool,
+    pub name: Option<String>,
+    /// None when the provider reported no groups at all
+    pub groups: Option<Vec<String>>,
+}
+
+/// How users of an external provider get local accounts and roles, read from `<PREFIX>_ORG_ID`,
+/// `<PREFIX>_AUTO_PROVISION`, `<PREFIX>_ROLE_MAPPING` and `<PREFIX>_DEFAULT_ROLE`
+#[derive(Debug, Clone)]
+pub struct Provisioning {
+    /// Organization new users join
+    pub org_id: String,
+    /// Create unknown users on first login; when false only existing accounts may sign in
+    pub auto_provision: bool,
+    /// Sign an unlinked identity in as the existing account with the same verified email.
+    /// Off by default: it trusts the provider to own every address it vouches for
+    pub link_by_email: bool,
+    /// Group → role, the highest match wins; empty leaves roles alone
+    pub role_mapping: Vec<(String, OrgRole)>,
+    /// Role of provisioned users matching no mapped group
+    pub default_role: OrgRole,
+}
+
+impl Provisioning {
+    pub fn from_env(prefix: &str) -> Self {
+        let var = |name: &str| std::env::var(format!("{}_{}", prefix, name)).ok();
+        Self {
+            org_id: var("ORG_ID").unwrap_or_else(|| DEFAULT_ORG_ID.to_string()),
+            auto_provision: var("AUTO_PROVISION")
+                .map(|v| matches!(v.trim().to_lowercase().as_str(), "true" | "1" | "yes" | "on"))
+                .unwrap_or(true),
+            link_by_email: var("LINK_BY_EMAIL")
+                .map(|v| matches!(v.trim().to_lowercase().as_str(), "true" | "1" | "yes" | "on"))
+                .unwrap_or(false),
+            role_mapping: var("ROLE_MAPPING")
+                .map(|mapping| parse_role_mapping(&mapping))
+                .unwrap_or_default(),
+            default_role: var("DEFAULT_ROLE")
+                .and_then(|role| OrgRole::parse(role.trim()))
+                .unwrap_or(OrgRole::Member),
+        }
+    }
+
+    /// Role for a user in `groups`; None when no group is mapped
+    pub fn mapped_role(&self, groups: &[String]) -> Option<OrgRole> {
+        self.role_mapping
+            .iter()
+            .filter(|(group, _)| groups.contains(group))
+            .map(|(_, role)| *role)
+            .max_by_key(|role| role_rank(*role))
+    }
+}
+
+/// `qa-owners=owner,qa-admins=admin`; entries with an unknown role are skipped
+fn parse_role_mapping(mapping: &str) -> Vec<(String, OrgRole)> {
+    mapping
+        .split(',')
+        .filter_map(|entry| {
+            let (group, role) = entry.rsplit_once('=')?;
+            let role = OrgRole::parse(role.trim().to_lowercase().as_str());
+            if role.is_none() {
+                warn!("Ignoring role mapping '{}': unknown role", entry.trim());
+            }
+            Some((group.trim().to_string(), role?))
+        })
+        .filter(|(group, _)| !group.is_empty())
+        .collect()
+}
+
+fn role_rank(role: OrgRole) -> u8 {
+    match role {
+        OrgRole::Member => 0,
+        OrgRole::Admin => 1,
+        OrgRole::Owner => 2,
+    }
+}
+
+/// Who is making a request and which organization's data it may touch.
+/// Extracted from `Authorization: Bearer <token>` or, for WebSocket upgrades where browsers
+/// can't set headers, a `token` query parameter.
+#[derive(Debug, Clone)]
+pub struct AuthContext {
+    /// None for anonymous requests (only possible when auth isn't required)
+    pub user_id: Option<String>,
+    pub org_id: String,
+    pub role: OrgRole,
+}
+
+impl AuthContext {
+    pub fn anonymous() -> Self {
+        Self {
+            user_id: None,
+            org_id: DEFAULT_ORG_ID.to_string(),
+            role: OrgRole::Owner,
+        }
+    }
+
+    pub fn from_user(user: &UserRecord) -> Self {
+        Self {
+            user_id: Some(user.id.clone()),
+            org_id: user.org_id.clone(),
+            role: OrgRole::parse(&user.role).unwrap_or(OrgRole::Member),
+        }
+    }
+
+    pub fn can_manage_org(&self) -> bool {
+        matches!(self.role, OrgRole::Owner | OrgRole::Admin)
+    }
+
+    /// Instance-wide operations (backups, log level, metrics, creating organizations) are
+    /// reserved to admins of the default organization, i.e. the deployment's operators
+    pub fn is_instance_admin(&self) -> bool {
+        self.org_id == DEFAULT_ORG_ID && self.can_manage_org()
+    }
+
+    pub fn require_instance_admin(&self) -> Result<(), StatusCode> {
+        if self.is_instance_admin() {
+            Ok(())
+        } else {
+            Err(StatusCode::FORBIDDEN)
+        }
+    }
+
+    pub fn require_org_admin(&self) -> Result<(), StatusCode> {
+        if self.can_manage_org() {
+            Ok(())
+        } else {
+            Err(StatusCode::FORBIDDEN)
+        }
+    }
+
+    /// A project visible to this caller; other organizations' projects look nonexistent
+    pub async fn project(&self, db: &Database, project_id: &str) -> Result<Option<ProjectRecord>> {
+        Ok(db
+            .get_project(project_id)
+            .await?
+            .filter(|project| project.org_id == self.org_id))
+    }
+
+    /// A ticket visible to this caller, through its project's organization
+    pub async fn ticket(&self, db: &Database, ticket_id: &str) -> Result<Option<TicketRecord>> {
+        let Some(ticket) = db.get_ticket(ticket_id).await? else {
+            return Ok(None);
+        };
+        Ok(self
+            .project(db, &ticket.project_id)
+            .await?
+            .map(|_| ticket))
+    }
+}
+
+/// Create an owner account in the default organization from `ADMIN_EMAIL`/`ADMIN_PASSWORD`
+/// when no users exist yet, so `AUTH_REQUIRED=true` deployments have a first login
+pub async fn bootstrap_admin(db: &Database) -> Result<()> {
+    let (Ok(email), Ok(password)) = (std::env::var("ADMIN_EMAIL"), std::env::var("ADMIN_PASSWORD")) else {
+        return Ok(());
+    };
+    if db.count_users().await? > 0 {
+        return Ok(());
+    }
+
+    db.create_user(&UserRecord {
+        id: uuid::Uuid::new_v4().to_string(),
+        org_id: DEFAULT_ORG_ID.to_string(),
+        email: email.trim().to_lowercase(),
+        name: "Administrator".to_string(),
+        password_hash: Some(hash_password(&password)?),
+        role: OrgRole::Owner.as_str().to_string(),
+        created_at: chrono::Utc::now().to_rfc3339(),
+    })
+    .await?;
+
+    tracing::info!("👤 Created bootstrap admin {}", email);
+    Ok(())
+}
+
+#[async_trait]
+impl FromRequestParts<AppState> for AuthContext {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        match request_token(parts) {
+            Some(token) => match state.database.get_user_by_token(&hash_token(&token)).await {
+                Ok(Some(user)) => Ok(AuthContext::from_user(&user)),
+                Ok(None) => Err(StatusCode::UNAUTHORIZED),
+                Err(e) => {
+                    tracing::error!("Failed to resolve auth token: {}", e);
+                    Err(StatusCode::INTERNAL_SERVER_ERROR)
+                }
+            },
+            None if state.auth.required => Err(StatusCode::UNAUTHORIZED),
+            None => Ok(AuthContext::anonymous()),
+        }
+    }
+}
+
+/// Whether a request to `path` may carry its token as `?token=`: only WebSocket upgrades and
+/// server-sent event streams, whose browser APIs can't set an `Authorization` header
+fn accepts_query_token(path: &str) -> bool {
+    matches!(path, "/ws" | "/graphql/ws") || path.ends_with("/replay-stream")
+}
+
+/// Raw bearer token of a request, if any
+pub fn request_token(parts: &Parts) -> Option<String> {
+    let from_header = parts
+        .headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|v| v.trim().to_string());
+
+    from_header.or_else(|| {
+        if !accepts_query_token(parts.uri.path()) {
+            return None;
+        }
+        let pairs: Vec<(String, String)> = serde_urlencoded::from_str(parts.uri.query()?).ok()?;
+        pairs.into_iter().find(|(key, _)| key == "token").map(|(_, value)| value)
+    })
+}
+
+/// New random token; only its hash is stored
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+pub fn hash_password(password: &str) -> Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| anyhow!("Failed to hash password: {}", e))
+}
+
+pub fn verify_password(password: &str, password_hash: &str) -> bool {
+    match PasswordHash::new(password_hash) {
+        Ok(parsed) => Argon2::default()
+            .verify_password(password.as_bytes(), &parsed)
+            .is_ok(),
+        Err(e) => {
+            warn!("Stored password hash is invalid: {}", e);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::Request;
+
+    #[test]
+    fn test_group_role_mapping() {
+        let provisioning = Provisioning {
+            org_id: DEFAULT_ORG_ID.to_string(),
+            auto_provision: true,
+            link_by_email: false,
+            role_mapping: parse_role_mapping("qa-admins=admin, qa-owners = Owner,bad=superuser,=member"),
+            default_role: OrgRole::Member,
+        };
+        assert_eq!(provisioning.role_mapping.len(), 2);
+
+        let groups = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        assert_eq!(provisioning.mapped_role(&groups(&["qa-admins", "qa-owners"])), Some(OrgRole::Owner));
+        assert_eq!(provisioning.mapped_role(&groups(&["qa-admins", "devs"])), Some(OrgRole::Admin));
+        assert_eq!(provisioning.mapped_role(&groups(&["devs"])), None);
+    }
+
+    #[test]
+    fn test_password_roundtrip() {
+        let hash = hash_password("correct horse").unwrap();
+        assert!(verify_password("correct horse", &hash));
+        assert!(!verify_password("wrong", &hash));
+    }
+
+    #[test]
+    fn test_request_token_sources() {
+        let (parts, _) = Request::builder()
+            .uri("/ws?foo=1&token=abc")
+            .body(())
+            .unwrap()
+            .into_parts();
+        assert_eq!(request_token(&parts).as_deref(), Some("abc"));
+
+        let parts = |uri: &str| Request::builder().uri(uri).body(()).unwrap().into_parts().0;
+        assert_eq!(request_token(&parts("/ws?token=a%2Bb%20c")).as_deref(), Some("a+b c"));
+        assert_eq!(request_token(&parts("/api/sessions/s1/replay-stream?token=abc")).as_deref(), Some("abc"));
+        // Other routes only take the header, so tokens stay out of URLs
+        assert_eq!(request_token(&parts("/api/projects?token=abc")), None);
+
+        let (parts, _) = Request::builder()
+            .uri("/api/projects")
+            .header("Authorization", "Bearer xyz")
+            .body(())
+            .unwrap()
+            .into_parts();
+        assert_eq!(request_token(&parts).as_deref(), Some("xyz"));
+
+        assert_eq!(hash_token("abc").len(), 64);
+        assert_ne!(generate_token(), generate_token());
+    }
+}
@@ -0,0 +1,256 @@
+use crate::blob_store::{self, Blob, BlobStore, FsBlobStore};
+use crate::database::Database;
+use crate::tasks::TaskSupervisor;
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::time::Duration;
+use tracing::{error, info};
+
+const BACKUP_PREFIX: &str = "qa_chatbot-";
+const BACKUP_SUFFIX: &str = ".db";
+
+/// Backup settings, read from `BACKUP_DIR` / `BACKUP_INTERVAL_HOURS` / `BACKUP_KEEP` and
+/// `OBJECT_STORE`
+#[derive(Debug, Clone)]
+pub struct BackupConfig {
+    /// Where snapshots are written, and kept unless `store` is shared storage
+    pub dir: PathBuf,
+    /// 0 disables scheduled backups (manual ones still work)
+    pub interval_hours: u64,
+    /// Number of most recent backups kept after each run
+    pub keep: usize,
+    /// Where finished backups are kept: `dir` itself, or the bucket with `OBJECT_STORE=s3`
+    pub store: Arc<dyn BlobStore>,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self::local(PathBuf::from("backups"), 24, 7)
+    }
+}
+
+impl BackupConfig {
+    /// Backups kept in `dir`
+    pub fn local(dir: PathBuf, interval_hours: u64, keep: usize) -> Self {
+        Self {
+            store: Arc::new(FsBlobStore::new(&dir)),
+            dir,
+            interval_hours,
+            keep,
+        }
+    }
+
+    pub fn from_env() -> Result<Self> {
+        let defaults = Self::default();
+        let config = Self::local(
+            std::env::var("BACKUP_DIR")
+                .map(PathBuf::from)
+                .unwrap_or(defaults.dir),
+            std::env::var("BACKUP_INTERVAL_HOURS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(defaults.interval_hours),
+            std::env::var("BACKUP_KEEP")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .map(|n: usize| n.max(1))
+                .unwrap_or(defaults.keep),
+        );
+
+        Ok(match blob_store::object_store_from_env("backups/")? {
+            Some(store) => Self { store, ..config },
+            None => config,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BackupInfo {
+    pub name: String,
+    pub size_bytes: u64,
+    pub created_at: String,
+}
+
+/// Take an online snapshot with `VACUUM INTO`, move it to the store, then prune old backups
+pub async fn create_backup(db: &Database, config: &BackupConfig) -> Result<BackupInfo> {
+    tokio::fs::create_dir_all(&config.dir).await?;
+
+    let created_at = Utc::now();
+    let name = format!(
+        "{}{}{}",
+        BACKUP_PREFIX,
+        created_at.format("%Y%m%dT%H%M%S%3fZ"),
+        BACKUP_SUFFIX
+    );
+    // Not a backup name, so a failed run never shows up in the list
+    let snapshot = config.dir.join(format!("{}.partial", name));
+    db.vacuum_into(&snapshot.to_string_lossy()).await?;
+
+    let size_bytes = tokio::fs::metadata(&snapshot).await?.len();
+    if let Err(e) = config.store.put_file(&name, &snapshot).await {
+        let _ = tokio::fs::remove_file(&snapshot).await;
+        return Err(e);
+    }
+    info!("💾 Đã tạo backup {} ({} bytes)", name, size_bytes);
+
+    rotate_backups(config).await?;
+
+    Ok(BackupInfo {
+        name,
+        size_bytes,
+        created_at: created_at.to_rfc3339(),
+    })
+}
+
+/// Backups in the store, newest first
+pub async fn list_backups(config: &BackupConfig) -> Result<Vec<BackupInfo>> {
+    let mut backups: Vec<BackupInfo> = config
+        .store
+        .list("")
+        .await?
+        .into_iter()
+        .filter(|blob| is_backup_name(&blob.key))
+        .map(|blob| BackupInfo {
+            name: blob.key,
+            size_bytes: blob.size,
+            created_at: blob.modified_at.to_rfc3339(),
+        })
+        .collect();
+
+    // Names embed the timestamp, so they sort chronologically
+    backups.sort_by(|a, b| b.name.cmp(&a.name));
+    Ok(backups)
+}
+
+/// How a backup is downloaded: straight from the bucket, or streamed through this server
+pub enum BackupDownload {
+    Redirect(String),
+    Stream(Blob),
+}
+
+/// A backup by name, rejecting anything that isn't a backup; None when it doesn't exist
+pub async fn open_backup(config: &BackupConfig, name: &str) -> Result<Option<BackupDownload>> {
+    if !is_backup_name(name) {
+        return Err(anyhow!("Invalid backup name: {}", name));
+    }
+    if !list_backups(config).await?.iter().any(|backup| backup.name == name) {
+        return Ok(None);
+    }
+    if let Some(link) = config.store.presigned_url(name, name)? {
+        return Ok(Some(BackupDownload::Redirect(link.url)));
+    }
+    Ok(config.store.open(name).await?.map(BackupDownload::Stream))
+}
+
+async fn rotate_backups(config: &BackupConfig) -> Result<()> {
+    for old in list_backups(config).await?.into_iter().skip(config.keep) {
+        config.store.delete(&old.name).await?;
+        info!("🗑️ Đã xoá backup cũ {}", old.name);
+    }
+    Ok(())
+}
+
+fn is_backup_name(name: &str) -> bool {
+    name.starts_with(BACKUP_PREFIX)
+        && name.ends_with(BACKUP_SUFFIX)
+        && !name.contains(['/', '\\'])
+        && !name.contains("..")
+}
+
+/// Run `create_backup` every `interval_hours`; no-op when the interval is 0
+pub fn spawn_scheduler(db: Arc<Database>, config: Arc<BackupConfig>, tasks: &TaskSupervisor) {
+    if config.interval_hours == 0 {
+        info!("💾 Scheduled backups disabled (BACKUP_INTERVAL_HOURS=0)");
+        return;
+    }
+
+    info!(
+        "💾 Scheduled backups every {}h (keeping {})",
+        config.interval_hours,
+        config.keep
+    );
+
+    tasks.spawn("backup-scheduler", async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(config.interval_hours * 3600));
+        // The first tick fires immediately; skip it so startup doesn't write a backup
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if let Err(e) = create_backup(&db, &config).await {
+                error!("❌ Backup định kỳ thất bại: {}", e);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::ProjectRecord;
+    use crate::test_support::project_fixture;
+
+    fn temp_config(keep: usize) -> BackupConfig {
+        BackupConfig::local(std::env::temp_dir().join(format!("backups-{}", uuid::Uuid::new_v4())), 0, keep)
+    }
+
+    /// File-backed database; `VACUUM INTO` doesn't work from sqlx's shared in-memory databases
+    async fn temp_database(config: &BackupConfig) -> Database {
+        std::fs::create_dir_all(&config.dir).unwrap();
+        let url = format!("sqlite:{}?mode=rwc", config.dir.join("live.sqlite").display());
+        let db = Database::new(&url).await.unwrap();
+        db.init_schema().await.unwrap();
+        db.run_migrations().await.unwrap();
+        db
+    }
+
+    /// Backup, then restore by opening the backup file as the database (the documented
+    /// restore path: stop the server and point DATABASE_URL at / copy over the backup)
+    #[tokio::test]
+    async fn test_backup_and_restore() {
+        let config = temp_config(7);
+        let db = temp_database(&config).await;
+        db.create_project(&ProjectRecord {
+            name: "Shop".to_string(),
+            ..project_fixture("p1")
+        })
+        .await
+        .unwrap();
+
+        let backup = create_backup(&db, &config).await.unwrap();
+        assert!(backup.size_bytes > 0);
+
+        let Some(BackupDownload::Stream(_)) = open_backup(&config, &backup.name).await.unwrap() else {
+            panic!("local backups are streamed");
+        };
+        let path = config.dir.join(&backup.name);
+        let restored = Database::new(&format!("sqlite:{}", path.display())).await.unwrap();
+        restored.init_schema().await.unwrap();
+        restored.run_migrations().await.unwrap();
+        let projects = restored.list_projects().await.unwrap();
+        assert_eq!(projects.len(), 1);
+        assert_eq!(projects[0].name, "Shop");
+
+        std::fs::remove_dir_all(&config.dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rotation_keeps_newest() {
+        let config = temp_config(2);
+        let db = temp_database(&config).await;
+        let mut names = Vec::new();
+        for _ in 0..3 {
+            names.push(create_backup(&db, &config).await.unwrap().name);
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+
+        let kept: Vec<_> = list_backups(&config).await.unwrap().into_iter().map(|b| b.name).collect();
+        assert_eq!(kept, vec![names[2].clone(), names[1].clone()]);
+        assert!(open_backup(&config, &names[0]).await.unwrap().is_none());
+        assert!(open_backup(&config, "../qa_chatbot.db").await.is_err());
+
+        std::fs::remove_dir_all(&config.dir).unwrap();
+    }
+}
@@ -0,0 +1,53 @@
+//! Scripted stand-in for the Claude CLI: `fake-agent <script.jsonl> [CLI arguments...]` prints
+//! the script's lines to stdout as the CLI would print stream-json, ignoring the arguments and the
+//! prompt. Lines of the form `{"fake": {...}}` are directives instead:
+//! - `{"fake": {"sleep_ms": 200}}` waits before the next line
+//! - `{"fake": {"stderr": "warning"}}` writes a line to stderr
+//! - `{"fake": {"exit": 2}}` exits at once with that code
+//!
+//! The arguments it was started with are written next to the script (`<script>.args.json`), so
+//! tests can check how the agent was invoked. Scripts are built with `test_support::Script`.
+
+use serde_json::Value;
+use std::io::Write;
+use std::time::Duration;
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let Some(script_path) = args.next() else {
+        eprintln!("usage: fake-agent <script.jsonl> [args...]");
+        std::process::exit(64);
+    };
+    let cli_args: Vec<String> = args.collect();
+    let _ = std::fs::write(
+        format!("{}.args.json", script_path),
+        serde_json::to_string(&cli_args).unwrap_or_default(),
+    );
+
+    let script = match std::fs::read_to_string(&script_path) {
+        Ok(script) => script,
+        Err(e) => {
+            eprintln!("fake-agent: cannot read {}: {}", script_path, e);
+            std::process::exit(66);
+        }
+    };
+
+    let mut stdout = std::io::stdout().lock();
+    for line in script.lines().filter(|line| !line.trim().is_empty()) {
+        let directive = serde_json::from_str::<Value>(line).ok().and_then(|value| value.get("fake").cloned());
+        let Some(directive) = directive else {
+            let _ = writeln!(stdout, "{}", line);
+            let _ = stdout.flush();
+            continue;
+        };
+        if let Some(ms) = directive.get("sleep_ms").and_then(Value::as_u64) {
+            std::thread::sleep(Duration::from_millis(ms));
+        }
+        if let Some(message) = directive.get("stderr").and_then(Value::as_str) {
+            eprintln!("{}", message);
+        }
+        if let Some(code) = directive.get("exit").and_then(Value::as_i64) {
+            std::process::exit(code as i32);
+        }
+    }
+}
@@ -0,0 +1,385 @@
+use crate::s3::{PresignedUrl, S3Client, S3Config};
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use axum::body::Bytes;
+use chrono::{DateTime, Utc};
+use futures_util::{Stream, StreamExt, TryStreamExt};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+
+pub type BlobStream = Pin<Box<dyn Stream<Item = std::io::Result<Bytes>> + Send>>;
+
+/// A stored blob opened for streaming
+pub struct Blob {
+    /// Length in bytes, when the store knows it up front
+    pub size: Option<u64>,
+    pub stream: BlobStream,
+}
+
+/// A stored blob, as listed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlobInfo {
+    pub key: String,
+    pub size: u64,
+    pub modified_at: DateTime<Utc>,
+}
+
+/// Storage for blobs addressed by `/`-separated keys: oversized values moved out of SQLite,
+/// backups and exports
+#[async_trait]
+pub trait BlobStore: Send + Sync + std::fmt::Debug {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<()>;
+
+    /// Move a local file into the store
+    async fn put_file(&self, key: &str, path: &Path) -> Result<()> {
+        self.put(key, tokio::fs::read(path).await?).await?;
+        tokio::fs::remove_file(path).await?;
+        Ok(())
+    }
+
+    /// None when nothing is stored under `key`
+    async fn open(&self, key: &str) -> Result<Option<Blob>>;
+
+    /// Deleting a missing blob succeeds
+    async fn delete(&self, key: &str) -> Result<()>;
+
+    /// Blobs directly under `prefix` (a `/`-terminated "directory", or empty for the top level)
+    async fn list(&self, prefix: &str) -> Result<Vec<BlobInfo>>;
+
+    /// A URL clients can download the blob from without going through this server, saved as
+    /// `download_name`; None when the store can't hand out such URLs
+    fn presigned_url(&self, _key: &str, _download_name: &str) -> Result<Option<PresignedUrl>> {
+        Ok(None)
+    }
+
+    /// The whole blob in memory
+    async fn read(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let Some(blob) = self.open(key).await? else {
+            return Ok(None);
+        };
+        let mut data = Vec::with_capacity(blob.size.unwrap_or_default() as usize);
+        let mut stream = blob.stream;
+        while let Some(chunk) = stream.next().await {
+            data.extend_from_slice(&chunk?);
+        }
+        Ok(Some(data))
+    }
+}
+
+/// Blobs as files under a directory
+#[derive(Debug)]
+pub struct FsBlobStore {
+    dir: PathBuf,
+}
+
+impl FsBlobStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf> {
+        let relative = Path::new(key);
+        if relative.is_absolute()
+            || relative.components().any(|c| !matches!(c, std::path::Component::Normal(_)))
+        {
+            bail!("Invalid blob key: {}", key);
+        }
+        Ok(self.dir.join(relative))
+    }
+}
+
+#[async_trait]
+impl BlobStore for FsBlobStore {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<()> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        // Readers never see a half-written blob
+        let tmp = path.with_extension(format!("tmp-{}", uuid::Uuid::new_v4()));
+        tokio::fs::write(&tmp, data).await?;
+        tokio::fs::rename(&tmp, &path).await?;
+        Ok(())
+    }
+
+    async fn put_file(&self, key: &str, path: &Path) -> Result<()> {
+        let target = self.path(key)?;
+        if let Some(parent) = target.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        // Renaming fails across filesystems; copy then
+        if tokio::fs::rename(path, &target).await.is_err() {
+            tokio::fs::copy(path, &target).await?;
+            tokio::fs::remove_file(path).await?;
+        }
+        Ok(())
+    }
+
+    async fn open(&self, key: &str) -> Result<Option<Blob>> {
+        let file = match tokio::fs::File::open(self.path(key)?).await {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let size = file.metadata().await?.len();
+        Ok(Some(Blob {
+            size: Some(size),
+            stream: Box::pin(tokio_util::io::ReaderStream::new(file)),
+        }))
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        match tokio::fs::remove_file(self.path(key)?).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<BlobInfo>> {
+        let mut entries = match tokio::fs::read_dir(self.path(prefix)?).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut blobs = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            let metadata = entry.metadata().await?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !metadata.is_file() || name.contains(".tmp-") {
+                continue;
+            }
+            blobs.push(BlobInfo {
+                key: format!("{}{}", prefix, name),
+                size: metadata.len(),
+                modified_at: metadata.modified()?.into(),
+            });
+        }
+        Ok(blobs)
+    }
+}
+
+/// Blobs as objects in an S3-compatible bucket, under `prefix`
+#[derive(Debug)]
+pub struct S3BlobStore {
+    client: S3Client,
+    prefix: String,
+}
+
+impl S3BlobStore {
+    pub fn new(client: S3Client, prefix: impl Into<String>) -> Self {
+        Self {
+            client,
+            prefix: prefix.into(),
+        }
+    }
+
+    fn object_key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+}
+
+#[async_trait]
+impl BlobStore for S3BlobStore {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<()> {
+        self.client
+            .put_object(&self.object_key(key), data, "application/octet-stream")
+            .await
+    }
+
+    async fn open(&self, key: &str) -> Result<Option<Blob>> {
+        let Some(response) = self.client.get_object(&self.object_key(key)).await? else {
+            return Ok(None);
+        };
+        Ok(Some(Blob {
+            size: response.content_length(),
+            stream: Box::pin(response.bytes_stream().map_err(std::io::Error::other)),
+        }))
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.client.delete_object(&self.object_key(key)).await
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<BlobInfo>> {
+        let objects = self.client.list_objects(&self.object_key(prefix)).await?;
+        Ok(objects
+            .into_iter()
+            .filter_map(|object| {
+                Some(BlobInfo {
+                    key: object.key.strip_prefix(&self.prefix)?.to_string(),
+                    size: object.size,
+                    modified_at: object.last_modified,
+                })
+            })
+            .collect())
+    }
+
+    fn presigned_url(&self, key: &str, download_name: &str) -> Result<Option<PresignedUrl>> {
+        self.client.presigned_get_url(&self.object_key(key), download_name).map(Some)
+    }
+}
+
+/// Shared storage for backups and exports from `OBJECT_STORE=s3`: the `S3_*` bucket, under
+/// `prefix`. None for `local` (the default), which keeps them on this server's disk.
+pub fn object_store_from_env(prefix: &str) -> Result<Option<Arc<dyn BlobStore>>> {
+    match std::env::var("OBJECT_STORE").as_deref().unwrap_or("local") {
+        "local" => Ok(None),
+        "s3" => {
+            let s3 = S3Config::from_env()?.context("OBJECT_STORE=s3 requires S3_BUCKET")?;
+            Ok(Some(Arc::new(S3BlobStore::new(S3Client::new(s3), prefix))))
+        }
+        other => Err(anyhow!("Unknown OBJECT_STORE {} (expected local or s3)", other)),
+    }
+}
+
+/// When values move out of SQLite, read from `BLOB_INLINE_MAX_BYTES` / `BLOB_PREVIEW_CHARS`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlobConfig {
+    /// Analysis results and raw logs longer than this go to the blob store
+    pub inline_max_bytes: usize,
+    /// Characters of an offloaded value kept in SQLite as its preview
+    pub preview_chars: usize,
+}
+
+impl Default for BlobConfig {
+    fn default() -> Self {
+        Self {
+            inline_max_bytes: 64 * 1024,
+            preview_chars: 2000,
+        }
+    }
+}
+
+impl BlobConfig {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            inline_max_bytes: std::env::var("BLOB_INLINE_MAX_BYTES")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(defaults.inline_max_bytes),
+            preview_chars: std::env::var("BLOB_PREVIEW_CHARS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(defaults.preview_chars),
+        }
+    }
+}
+
+/// A blob store and the size above which values are offloaded to it
+#[derive(Debug, Clone)]
+pub struct Blobs {
+    pub store: Arc<dyn BlobStore>,
+    pub config: BlobConfig,
+}
+
+impl Blobs {
+    /// From `BLOB_STORE`: `fs` (default, under `BLOB_DIR`), `s3` (the `S3_*` bucket, under
+    /// `BLOB_S3_PREFIX`) or `none` to keep everything in SQLite
+    pub fn from_env() -> Result<Option<Self>> {
+        let config = BlobConfig::from_env();
+        let store: Arc<dyn BlobStore> = match std::env::var("BLOB_STORE").as_deref().unwrap_or("fs") {
+            "none" => return Ok(None),
+            "fs" => Arc::new(FsBlobStore::new(std::env::var("BLOB_DIR").unwrap_or_else(|_| "blobs".to_string()))),
+            "s3" => {
+                let s3 = S3Config::from_env()?.context("BLOB_STORE=s3 requires S3_BUCKET")?;
+                let prefix = std::env::var("BLOB_S3_PREFIX").unwrap_or_else(|_| "blobs/".to_string());
+                Arc::new(S3BlobStore::new(S3Client::new(s3), prefix))
+            }
+            other => return Err(anyhow!("Unknown BLOB_STORE {} (expected fs, s3 or none)", other)),
+        };
+        Ok(Some(Self { store, config }))
+    }
+
+    /// Whether `value` is too large to keep inline
+    pub fn should_offload(&self, value: &str) -> bool {
+        value.len() > self.config.inline_max_bytes
+    }
+
+    /// The start of `value` kept in SQLite in its place
+    pub fn preview(&self, value: &str) -> String {
+        match value.char_indices().nth(self.config.preview_chars) {
+            Some((end, _)) => value[..end].to_string(),
+            None => value.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{StructuredLogRecord, TicketRecord};
+    use crate::test_support::{project_fixture, test_db, ticket_fixture};
+    use chrono::Utc;
+
+    #[tokio::test]
+    async fn test_large_values_are_offloaded() {
+        let dir = std::env::temp_dir().join(format!("blobs-{}", uuid::Uuid::new_v4()));
+        let blobs = Blobs {
+            store: Arc::new(FsBlobStore::new(&dir)),
+            config: BlobConfig {
+                inline_max_bytes: 16,
+                preview_chars: 4,
+            },
+        };
+        let db = test_db().await.unwrap().with_blobs(Some(blobs));
+
+        let now = Utc::now().to_rfc3339();
+        db.create_project(&project_fixture("p1")).await.unwrap();
+        db.create_ticket(&TicketRecord {
+            description: "How does checkout work?".to_string(),
+            ..ticket_fixture("t1", "p1")
+        })
+        .await
+        .unwrap();
+
+        let long_result = "# Checkout flow explained at length";
+        db.update_ticket_result("t1", long_result).await.unwrap();
+        let ticket = db.get_ticket("t1").await.unwrap().unwrap();
+        assert_eq!(ticket.analysis_result.as_deref(), Some("# Ch"));
+        assert_eq!(ticket.analysis_result_size, Some(long_result.len() as i64));
+        assert_eq!(db.full_result(&ticket).await.unwrap().as_deref(), Some(long_result));
+
+        // A short result replaces the blob, which is removed
+        let old_blob = ticket.analysis_result_blob.unwrap();
+        db.update_ticket_result("t1", "short").await.unwrap();
+        let ticket = db.get_ticket("t1").await.unwrap().unwrap();
+        assert_eq!(ticket.analysis_result.as_deref(), Some("short"));
+        assert!(ticket.analysis_result_blob.is_none());
+        assert!(db.open_blob(&old_blob).await.unwrap().is_none());
+
+        let raw = r#"{"type":"assistant","message":"a long line"}"#;
+        db.save_logs_batch(&[StructuredLogRecord {
+            id: "l1".to_string(),
+            ticket_id: "t1".to_string(),
+            message_type: "assistant".to_string(),
+            content: "a long line".to_string(),
+            raw_log: Some(raw.to_string()),
+            metadata: None,
+            timestamp: now.clone(),
+            raw_log_blob: None,
+        }])
+        .await
+        .unwrap();
+        let log = db.get_log("l1").await.unwrap().unwrap();
+        assert_eq!(log.raw_log.as_deref(), Some("{\"ty"));
+        assert_eq!(db.full_raw_log(&log).await.unwrap().as_deref(), Some(raw));
+
+        let log_blob = log.raw_log_blob.unwrap();
+        db.clear_logs_for_ticket("t1").await.unwrap();
+        assert!(db.open_blob(&log_blob).await.unwrap().is_none());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_fs_keys_stay_inside_the_directory() {
+        let store = FsBlobStore::new("/srv/blobs");
+        assert!(store.path("results/t1/a.md").is_ok());
+        assert!(store.path("").is_ok());
+        assert!(store.path("../qa_chatbot.db").is_err());
+        assert!(store.path("/etc/passwd").is_err());
+    }
+}
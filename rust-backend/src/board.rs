@@ -0,0 +1,156 @@
+use crate::database::{Database, TicketRecord};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// Columns of the ticket board, in display order
+pub const STATUSES: &[&str] = &["todo", "in-progress", "done"];
+
+/// Neighbours closer than this leave no room for a midpoint; the column is renumbered instead
+const MIN_GAP: f64 = 1e-6;
+
+/// Where a ticket ended up after a move, as broadcast in `ticket-reordered`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TicketMove {
+    pub ticket_id: String,
+    pub status: String,
+    pub position: f64,
+    /// Index within the column, 0 being the top
+    pub index: usize,
+    /// Other tickets of the column got new positions too; boards should reload it
+    pub renumbered: bool,
+}
+
+/// Position between two neighbours of a column; None for a missing neighbour at either end
+fn position_between(before: Option<f64>, after: Option<f64>) -> Option<f64> {
+    match (before, after) {
+        (None, None) => Some(0.0),
+        (None, Some(after)) => Some(after - 1.0),
+        (Some(before), None) => Some(before + 1.0),
+        (Some(before), Some(after)) if after - before >= MIN_GAP => Some((before + after) / 2.0),
+        _ => None,
+    }
+}
+
+/// Move a ticket to `index` of the `status` column of its project (the end when past it). Only
+/// the moved ticket is written unless its neighbours are too close, then the column is renumbered.
+pub async fn move_ticket(database: &Database, ticket: &TicketRecord, status: &str, index: usize) -> Result<TicketMove> {
+    let mut column: Vec<(String, f64)> = database
+        .list_ticket_column(&ticket.project_id, status)
+        .await?
+        .into_iter()
+        .filter(|(id, _)| id != &ticket.id)
+        .collect();
+    let index = index.min(column.len());
+
+    let before = index.checked_sub(1).map(|i| column[i].1);
+    let after = column.get(index).map(|(_, position)| *position);
+    let (position, renumbered) = match position_between(before, after) {
+        Some(position) => {
+            database.set_ticket_positions(status, &[(ticket.id.clone(), position)]).await?;
+            (position, false)
+        }
+        None => {
+            column.insert(index, (ticket.id.clone(), 0.0));
+            let renumbered: Vec<(String, f64)> = column
+                .into_iter()
+                .enumerate()
+                .map(|(i, (id, _))| (id, i as f64))
+                .collect();
+            database.set_ticket_positions(status, &renumbered).await?;
+            (index as f64, true)
+        }
+    };
+
+    Ok(TicketMove {
+        ticket_id: ticket.id.clone(),
+        status: status.to_string(),
+        position,
+        index,
+        renumbered,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{project_fixture, test_db, ticket_fixture};
+
+    async fn setup() -> Database {
+        let db = test_db().await.unwrap();
+        db.create_project(&project_fixture("p1")).await.unwrap();
+        db
+    }
+
+    async fn create(db: &Database, id: &str, status: &str) -> TicketRecord {
+        let mut ticket = TicketRecord {
+            status: status.to_string(),
+            ..ticket_fixture(id, "p1")
+        };
+        ticket.position = db.create_ticket(&ticket).await.unwrap();
+        ticket
+    }
+
+    async fn column(db: &Database, status: &str) -> Vec<String> {
+        db.list_ticket_column("p1", status)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    #[test]
+    fn test_position_between() {
+        assert_eq!(position_between(None, None), Some(0.0));
+        assert_eq!(position_between(None, Some(2.0)), Some(1.0));
+        assert_eq!(position_between(Some(2.0), None), Some(3.0));
+        assert_eq!(position_between(Some(1.0), Some(2.0)), Some(1.5));
+        assert_eq!(position_between(Some(1.0), Some(1.0 + MIN_GAP / 2.0)), None);
+    }
+
+    #[tokio::test]
+    async fn test_new_tickets_go_on_top_and_moves_keep_order() {
+        let db = setup().await;
+        let a = create(&db, "a", "todo").await;
+        create(&db, "b", "todo").await;
+        create(&db, "c", "todo").await;
+        assert_eq!(column(&db, "todo").await, vec!["c", "b", "a"]);
+
+        let moved = move_ticket(&db, &a, "todo", 1).await.unwrap();
+        assert!(!moved.renumbered);
+        assert_eq!(column(&db, "todo").await, vec!["c", "a", "b"]);
+
+        // Into another column, past its end
+        let moved = move_ticket(&db, &a, "done", 5).await.unwrap();
+        assert_eq!(moved.index, 0);
+        assert_eq!(column(&db, "todo").await, vec!["c", "b"]);
+        assert_eq!(db.get_ticket("a").await.unwrap().unwrap().status, "done");
+
+        // A status change alone puts the ticket on top of its new column
+        db.update_ticket_status("b", "done").await.unwrap();
+        assert_eq!(column(&db, "done").await, vec!["b", "a"]);
+    }
+
+    #[tokio::test]
+    async fn test_move_renumbers_crowded_column() {
+        let db = setup().await;
+        let a = create(&db, "a", "todo").await;
+        create(&db, "b", "todo").await;
+        create(&db, "c", "todo").await;
+        db.set_ticket_positions("todo", &[("c".to_string(), 1.0), ("b".to_string(), 1.0)])
+            .await
+            .unwrap();
+
+        let moved = move_ticket(&db, &a, "todo", 1).await.unwrap();
+        assert!(moved.renumbered);
+        assert_eq!(column(&db, "todo").await, vec!["c", "a", "b"]);
+        let positions: Vec<f64> = db
+            .list_ticket_column("p1", "todo")
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(_, position)| position)
+            .collect();
+        assert_eq!(positions, vec![0.0, 1.0, 2.0]);
+    }
+}
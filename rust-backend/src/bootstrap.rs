@@ -0,0 +1,260 @@
+//! Baseline documentation for new projects
+//!
+//! `POST /api/projects/:id/bootstrap` creates one ticket per template (architecture overview,
+//! main business flows, key entry points by default, or `BOOTSTRAP_TEMPLATES_FILE`) and runs
+//! them one after the other: only the first is queued, each run queues the next when it ends.
+
+use crate::activity;
+use crate::analysis_runner;
+use crate::code_agent::AnalysisMode;
+use crate::database::TicketRecord;
+use crate::job_queue::AnalysisPriority;
+use crate::{AppState, CodeAnalysisRequest};
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use tracing::{info, warn};
+
+/// A standard ticket created by the bootstrap
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BootstrapTemplate {
+    /// Stable name clients select templates by
+    pub key: String,
+    pub title: String,
+    /// Asked to the agent, and the ticket's description
+    pub question: String,
+    #[serde(default)]
+    pub mode: AnalysisMode,
+}
+
+impl BootstrapTemplate {
+    fn new(key: &str, title: &str, question: &str, mode: AnalysisMode) -> Self {
+        Self {
+            key: key.to_string(),
+            title: title.to_string(),
+            question: question.to_string(),
+            mode,
+        }
+    }
+}
+
+pub fn default_templates() -> Vec<BootstrapTemplate> {
+    vec![
+        BootstrapTemplate::new(
+            "architecture",
+            "Architecture overview",
+            "Describe the architecture of this codebase: its main components, modules and layers, \
+             how they depend on each other, the frameworks and storage they use, and how a request \
+             travels through them.",
+            AnalysisMode::Ask,
+        ),
+        BootstrapTemplate::new(
+            "business-flows",
+            "Main business flows",
+            "List the main business flows this codebase implements. For each one, explain the steps, \
+             the business rules and validations applied, and the classes and functions involved.",
+            AnalysisMode::Ask,
+        ),
+        BootstrapTemplate::new(
+            "entry-points",
+            "Key entry points",
+            "List the entry points of this codebase: HTTP endpoints, CLI commands, scheduled jobs, \
+             message consumers and UI screens, with the file and function handling each one and what it does.",
+            AnalysisMode::Ask,
+        ),
+    ]
+}
+
+/// Templates of the server, read at startup
+#[derive(Debug, Clone)]
+pub struct BootstrapConfig {
+    pub templates: Vec<BootstrapTemplate>,
+}
+
+impl BootstrapConfig {
+    /// `BOOTSTRAP_TEMPLATES_FILE` (a JSON array of templates) when set and valid, the defaults otherwise
+    pub fn from_env() -> Self {
+        let templates = match std::env::var("BOOTSTRAP_TEMPLATES_FILE").ok().filter(|path| !path.trim().is_empty()) {
+            Some(path) => match load_templates(&path) {
+                Ok(templates) => templates,
+                Err(e) => {
+                    warn!("⚠️ Không đọc được BOOTSTRAP_TEMPLATES_FILE {}: {}; dùng template mặc định", path, e);
+                    default_templates()
+                }
+            },
+            None => default_templates(),
+        };
+        Self { templates }
+    }
+}
+
+fn load_templates(path: &str) -> Result<Vec<BootstrapTemplate>> {
+    let raw = std::fs::read_to_string(path).context("Cannot read file")?;
+    let templates: Vec<BootstrapTemplate> = serde_json::from_str(&raw).context("Invalid JSON")?;
+    validate(&templates)?;
+    Ok(templates)
+}
+
+fn validate(templates: &[BootstrapTemplate]) -> Result<()> {
+    if templates.is_empty() {
+        return Err(anyhow!("No templates"));
+    }
+    let mut keys = HashSet::new();
+    for template in templates {
+        if template.key.trim().is_empty() || template.title.trim().is_empty() || template.question.trim().is_empty() {
+            return Err(anyhow!("Templates need a key, a title and a question"));
+        }
+        if !keys.insert(template.key.as_str()) {
+            return Err(anyhow!("Duplicate template key: {}", template.key));
+        }
+    }
+    Ok(())
+}
+
+/// Templates to create: the selected ones (all when `keys` is None) whose title no ticket of the
+/// project has yet, so bootstrapping twice doesn't duplicate the baseline. Errors on unknown keys.
+pub fn plan<'a>(
+    templates: &'a [BootstrapTemplate],
+    keys: Option<&[String]>,
+    existing_titles: &[String],
+) -> Result<Vec<&'a BootstrapTemplate>, String> {
+    if let Some(keys) = keys {
+        if let Some(unknown) = keys.iter().find(|key| !templates.iter().any(|t| &t.key == *key)) {
+            return Err(unknown.clone());
+        }
+    }
+    let existing: HashSet<String> = existing_titles.iter().map(|title| title.trim().to_lowercase()).collect();
+
+    Ok(templates
+        .iter()
+        .filter(|template| keys.is_none_or(|keys| keys.contains(&template.key)))
+        .filter(|template| !existing.contains(&template.title.trim().to_lowercase()))
+        .collect())
+}
+
+/// Tickets created by a bootstrap, in the order they run
+#[derive(Debug, Serialize)]
+pub struct Bootstrapped {
+    pub tickets: Vec<TicketRecord>,
+    /// Run of the first ticket; the others get theirs when their turn comes
+    pub run_id: String,
+}
+
+/// Create a ticket per template and queue their analyses as one chain
+pub async fn run(
+    state: &AppState,
+    project_id: &str,
+    templates: &[&BootstrapTemplate],
+    agent_type: Option<String>,
+    priority: AnalysisPriority,
+    requested_by: Option<String>,
+) -> Result<Bootstrapped> {
+    let mut tickets = Vec::new();
+    for template in templates {
+        let ticket = TicketRecord {
+            id: uuid::Uuid::new_v4().to_string(),
+            project_id: project_id.to_string(),
+            title: template.title.clone(),
+            description: template.question.clone(),
+            status: "todo".to_string(),
+            code_context: None,
+            analysis_result: None,
+            is_analyzing: false,
+            created_at: chrono::Utc::now().to_rfc3339(),
+            updated_at: chrono::Utc::now().to_rfc3339(),
+            stale: false,
+            analysis_result_blob: None,
+            analysis_result_size: None,
+            position: 0.0,
+            assignee_id: None,
+            summary: None,
+            mode: template.mode,
+        };
+        let position = state.database.create_ticket(&ticket).await?;
+        activity::watch_created(&state.database, &ticket.id, requested_by.as_deref()).await;
+        let ticket = TicketRecord { position, ..ticket };
+
+        let _ = state.broadcast_tx.send(crate::BroadcastMessage {
+            ticket_id: ticket.id.clone(),
+            message_type: "ticket-created".to_string(),
+            content: serde_json::to_string(&ticket).unwrap_or_default(),
+            timestamp: chrono::Utc::now(),
+            org_id: None,
+            changed_files: None,
+        });
+        tickets.push(ticket);
+    }
+
+    let mut requests: Vec<CodeAnalysisRequest> = tickets
+        .iter()
+        .zip(templates)
+        .map(|(ticket, template)| CodeAnalysisRequest {
+            ticket_id: ticket.id.clone(),
+            code_context: String::new(),
+            question: template.question.clone(),
+            project_id: project_id.to_string(),
+            run_id: None,
+            agent_type: agent_type.clone(),
+            mode: template.mode,
+            path_rules: Default::default(),
+            tool_policy: Default::default(),
+            priority,
+            include_linked_results: false,
+            budget_override: false,
+            replay: None,
+            working_dir: None,
+            prompt_variant: None,
+            response_language: None,
+            api_key: None,
+            then: Vec::new(),
+        })
+        .collect();
+    if requests.is_empty() {
+        return Err(anyhow!("No templates to run"));
+    }
+    let mut first = requests.remove(0);
+    first.then = requests;
+
+    info!("🧭 Khởi tạo project {} với {} ticket tài liệu", project_id, tickets.len());
+    let ticket_id = first.ticket_id.clone();
+    let run_id = analysis_runner::start_analysis(state, first, requested_by).await;
+    analysis_runner::announce_started(state, &ticket_id, &run_id);
+
+    Ok(Bootstrapped { tickets, run_id })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_skips_existing_titles() {
+        let templates = default_templates();
+        let planned = plan(&templates, None, &[" architecture OVERVIEW ".to_string()]).unwrap();
+        let keys: Vec<&str> = planned.iter().map(|t| t.key.as_str()).collect();
+        assert_eq!(keys, vec!["business-flows", "entry-points"]);
+
+        let selected = plan(&templates, Some(&["entry-points".to_string()]), &[]).unwrap();
+        assert_eq!(selected.len(), 1);
+        assert_eq!(selected[0].title, "Key entry points");
+
+        assert_eq!(plan(&templates, Some(&["nope".to_string()]), &[]).unwrap_err(), "nope");
+    }
+
+    #[test]
+    fn test_template_files_are_validated() {
+        let templates: Vec<BootstrapTemplate> = serde_json::from_str(
+            r#"[{"key": "api", "title": "API reference", "question": "Document the REST API"},
+                {"key": "flows", "title": "Login flow", "question": "Draw the login flow", "mode": "diagram"}]"#,
+        )
+        .unwrap();
+        assert_eq!(templates[0].mode, AnalysisMode::Ask);
+        assert_eq!(templates[1].mode, AnalysisMode::Diagram);
+        assert!(validate(&templates).is_ok());
+
+        let duplicated = vec![templates[0].clone(), templates[0].clone()];
+        assert!(validate(&duplicated).is_err());
+        assert!(validate(&[]).is_err());
+        assert!(validate(&default_templates()).is_ok());
+    }
+}
@@ -0,0 +1,380 @@
+//! Monthly budgets (`/api/projects/:id/budget`, `/api/orgs/current/budget`): a limit in USD or
+//! tokens on what agents report for the runs of a project, or of every project of an
+//! organization, in a calendar month (UTC). A run is refused before its agent spawns once a
+//! budget is used up, unless the budget lets an organization admin override it for that run.
+//! The organization's admins are emailed, and its open clients sent `budget-threshold`, when a
+//! month's usage reaches 80% and 100%.
+
+use crate::dashboard::month_start;
+use crate::database::Database;
+use crate::AppState;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::fmt;
+use tracing::{error, warn};
+
+/// Percentages of a budget a warning goes out at, once per month each
+pub const THRESHOLDS: [i64; 2] = [80, 100];
+
+/// Broadcast to the organization's clients when a threshold is reached
+pub const THRESHOLD_REACHED: &str = "budget-threshold";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BudgetMetric {
+    /// `cost_usd` of the sessions; runs whose agent reports no cost count as free
+    Usd,
+    /// Input plus output `tokens` of the sessions
+    Tokens,
+}
+
+impl BudgetMetric {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BudgetMetric::Usd => "usd",
+            BudgetMetric::Tokens => "tokens",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "usd" => Some(BudgetMetric::Usd),
+            "tokens" => Some(BudgetMetric::Tokens),
+            _ => None,
+        }
+    }
+
+    /// `amount` as shown to people
+    pub fn format(&self, amount: f64) -> String {
+        match self {
+            BudgetMetric::Usd => format!("${:.2}", amount),
+            BudgetMetric::Tokens => format!("{} tokens", amount.round() as i64),
+        }
+    }
+}
+
+/// What happens to runs once the budget is used up
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Enforcement {
+    /// Refused until next month or until the limit is raised
+    #[default]
+    Reject,
+    /// Refused unless an organization admin starts it with `override_budget`
+    RequireOverride,
+}
+
+impl Enforcement {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Enforcement::Reject => "reject",
+            Enforcement::RequireOverride => "require-override",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "reject" => Some(Enforcement::Reject),
+            "require-override" => Some(Enforcement::RequireOverride),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Budget {
+    pub metric: BudgetMetric,
+    pub monthly_limit: f64,
+    #[serde(default)]
+    pub enforcement: Enforcement,
+}
+
+impl Budget {
+    /// Check an admin-supplied budget before it is stored
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.monthly_limit.is_finite() || self.monthly_limit <= 0.0 {
+            return Err("monthly_limit must be a positive number".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BudgetScope {
+    Project,
+    Org,
+}
+
+impl BudgetScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BudgetScope::Project => "project",
+            BudgetScope::Org => "org",
+        }
+    }
+}
+
+impl fmt::Display for BudgetScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A budget and how much of it this month used
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BudgetStatus {
+    pub scope: BudgetScope,
+    /// The project's or the organization's ID
+    pub scope_id: String,
+    pub budget: Budget,
+    /// First day of the month counted, `YYYY-MM-DD` (UTC)
+    pub since: String,
+    pub used: f64,
+    pub percent: f64,
+}
+
+impl BudgetStatus {
+    fn new(scope: BudgetScope, scope_id: &str, budget: Budget, since: DateTime<Utc>, usage: (f64, i64)) -> Self {
+        let used = match budget.metric {
+            BudgetMetric::Usd => usage.0,
+            BudgetMetric::Tokens => usage.1 as f64,
+        };
+        Self {
+            scope,
+            scope_id: scope_id.to_string(),
+            percent: used / budget.monthly_limit * 100.0,
+            budget,
+            since: since.format("%Y-%m-%d").to_string(),
+            used,
+        }
+    }
+
+    pub fn exceeded(&self) -> bool {
+        self.used >= self.budget.monthly_limit
+    }
+
+    /// The highest threshold this month's usage reached
+    pub fn threshold(&self) -> Option<i64> {
+        THRESHOLDS.iter().rev().copied().find(|threshold| self.percent >= *threshold as f64)
+    }
+}
+
+/// Why a run was refused, as reported to the user
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[error("Monthly {scope} budget used up: {used} of {limit}")]
+pub struct BudgetExceeded {
+    pub scope: BudgetScope,
+    pub used: String,
+    pub limit: String,
+    /// Whether an organization admin may start the run anyway
+    pub overridable: bool,
+}
+
+impl From<&BudgetStatus> for BudgetExceeded {
+    fn from(status: &BudgetStatus) -> Self {
+        let metric = status.budget.metric;
+        Self {
+            scope: status.scope,
+            used: metric.format(status.used),
+            limit: metric.format(status.budget.monthly_limit),
+            overridable: status.budget.enforcement == Enforcement::RequireOverride,
+        }
+    }
+}
+
+/// The budgets that apply to the project's runs, its own then its organization's, with this
+/// month's usage
+pub async fn status(database: &Database, project_id: &str, now: DateTime<Utc>) -> Result<Vec<BudgetStatus>> {
+    let Some(project) = database.get_project(project_id).await? else {
+        return Ok(Vec::new());
+    };
+    let since = month_start(now);
+    let mut statuses = Vec::new();
+    if let Some(budget) = database.get_project_budget(project_id).await? {
+        let usage = database.get_project_usage(project_id, &since.to_rfc3339()).await?;
+        statuses.push(BudgetStatus::new(BudgetScope::Project, project_id, budget, since, usage));
+    }
+    statuses.extend(org_status(database, &project.org_id, now).await?);
+    Ok(statuses)
+}
+
+/// The organization's budget with this month's usage; None without one
+pub async fn org_status(database: &Database, org_id: &str, now: DateTime<Utc>) -> Result<Option<BudgetStatus>> {
+    let Some(budget) = database.get_org_budget(org_id).await? else {
+        return Ok(None);
+    };
+    let since = month_start(now);
+    let usage = database.get_org_usage(org_id, &since.to_rfc3339()).await?;
+    Ok(Some(BudgetStatus::new(BudgetScope::Org, org_id, budget, since, usage)))
+}
+
+/// The used-up budget that keeps a run of the project from starting; `override_budget` lifts
+/// the ones that allow it
+pub async fn check(database: &Database, project_id: &str, override_budget: bool) -> Result<Option<BudgetExceeded>> {
+    Ok(status(database, project_id, Utc::now())
+        .await?
+        .iter()
+        .filter(|status| status.exceeded())
+        .map(BudgetExceeded::from)
+        .find(|exceeded| !(override_budget && exceeded.overridable)))
+}
+
+/// Warn about the thresholds the project's budgets reached, once per budget, month and
+/// threshold; called after a run's usage is recorded
+pub async fn warn_thresholds(state: &AppState, project_id: &str, ticket_id: &str) {
+    let database = &state.database;
+    let (project, statuses) = match tokio::try_join!(database.get_project(project_id), status(database, project_id, Utc::now())) {
+        Ok((Some(project), statuses)) => (project, statuses),
+        Ok((None, _)) => return,
+        Err(e) => {
+            error!("Failed to check budgets of project {}: {}", project_id, e);
+            return;
+        }
+    };
+
+    for status in statuses {
+        let Some(threshold) = status.threshold() else {
+            continue;
+        };
+        match database.record_budget_warning(status.scope.as_str(), &status.scope_id, &status.since, threshold).await {
+            Ok(true) => {}
+            Ok(false) => continue,
+            Err(e) => {
+                error!("Failed to record budget warning of {} {}: {}", status.scope, status.scope_id, e);
+                continue;
+            }
+        }
+        let metric = status.budget.metric;
+        warn!(
+            "💸 Ngân sách {} {} đã dùng {}% ({} / {})",
+            status.scope,
+            status.scope_id,
+            threshold,
+            metric.format(status.used),
+            metric.format(status.budget.monthly_limit)
+        );
+
+        let _ = state.broadcast_tx.send(crate::BroadcastMessage {
+            ticket_id: ticket_id.to_string(),
+            message_type: THRESHOLD_REACHED.to_string(),
+            content: json!({
+                "scope": status.scope,
+                "scope_id": status.scope_id,
+                "project_id": project.id,
+                "threshold": threshold,
+                "budget": status.budget,
+                "used": status.used,
+                "percent": status.percent,
+                "since": status.since,
+            })
+            .to_string(),
+            timestamp: Utc::now(),
+            org_id: Some(project.org_id.clone()),
+            changed_files: None,
+        });
+
+        let Some(notifier) = &state.notifier else {
+            continue;
+        };
+        let name = match status.scope {
+            BudgetScope::Project => format!("project \"{}\"", project.name),
+            BudgetScope::Org => "organization".to_string(),
+        };
+        if let Err(e) = notifier.budget_warning(database, &project, &name, &status, threshold).await {
+            warn!("⚠️ Không gửi được email cảnh báo ngân sách {} {}: {}", status.scope, status.scope_id, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::DEFAULT_ORG_ID;
+    use crate::test_support::{project_fixture, test_db, ticket_fixture};
+    use crate::run_environment::RunEnvironment;
+    use crate::tool_policy::ToolPolicy;
+
+    async fn database() -> Database {
+        let db = test_db().await.unwrap();
+        for project_id in ["p1", "p2"] {
+            db.create_project(&project_fixture(project_id)).await.unwrap();
+            db.create_ticket(&ticket_fixture(&format!("t-{}", project_id), project_id)).await.unwrap();
+        }
+        db
+    }
+
+    async fn record_run(db: &Database, project_id: &str, cost_usd: f64, tokens: i64) {
+        let ticket_id = format!("t-{}", project_id);
+        let session_id = db.create_session(&ticket_id, "claude", None, &ToolPolicy::default(), Default::default()).await.unwrap();
+        let environment = RunEnvironment {
+            cost_usd: Some(cost_usd),
+            tokens: Some(tokens),
+            ..Default::default()
+        };
+        db.record_session_environment(&session_id, &environment).await.unwrap();
+    }
+
+    fn budget(metric: BudgetMetric, monthly_limit: f64, enforcement: Enforcement) -> Budget {
+        Budget {
+            metric,
+            monthly_limit,
+            enforcement,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_and_thresholds() {
+        let db = database().await;
+        record_run(&db, "p1", 4.0, 1_000).await;
+        record_run(&db, "p2", 4.5, 9_000).await;
+        assert!(status(&db, "p1", Utc::now()).await.unwrap().is_empty());
+        assert_eq!(check(&db, "p1", false).await.unwrap(), None);
+
+        db.set_project_budget("p1", Some(&budget(BudgetMetric::Usd, 5.0, Enforcement::RequireOverride)), None)
+            .await
+            .unwrap();
+        db.set_org_budget(DEFAULT_ORG_ID, Some(&budget(BudgetMetric::Tokens, 12_000.0, Enforcement::Reject)), None)
+            .await
+            .unwrap();
+        let statuses = status(&db, "p1", Utc::now()).await.unwrap();
+        assert_eq!(statuses.len(), 2);
+        assert_eq!((statuses[0].scope, statuses[0].used, statuses[0].threshold()), (BudgetScope::Project, 4.0, Some(80)));
+        assert_eq!((statuses[1].scope, statuses[1].used, statuses[1].threshold()), (BudgetScope::Org, 10_000.0, Some(80)));
+        assert_eq!(check(&db, "p1", false).await.unwrap(), None);
+
+        record_run(&db, "p1", 1.5, 500).await;
+        let exceeded = check(&db, "p1", false).await.unwrap().unwrap();
+        assert_eq!(exceeded.to_string(), "Monthly project budget used up: $5.50 of $5.00");
+        assert!(exceeded.overridable);
+        assert_eq!(check(&db, "p1", true).await.unwrap(), None);
+        // p2 only has the organization's budget, 10 500 of 12 000 tokens
+        assert_eq!(check(&db, "p2", false).await.unwrap(), None);
+
+        record_run(&db, "p2", 0.0, 2_000).await;
+        let exceeded = check(&db, "p1", true).await.unwrap().unwrap();
+        assert_eq!((exceeded.scope, exceeded.overridable), (BudgetScope::Org, false));
+
+        // Each threshold is warned about once a month, until the budget changes
+        assert!(db.record_budget_warning("project", "p1", "2026-10-01", 80).await.unwrap());
+        assert!(!db.record_budget_warning("project", "p1", "2026-10-01", 80).await.unwrap());
+        assert!(db.record_budget_warning("project", "p1", "2026-10-01", 100).await.unwrap());
+        db.set_project_budget("p1", Some(&budget(BudgetMetric::Usd, 20.0, Enforcement::Reject)), None)
+            .await
+            .unwrap();
+        assert!(db.record_budget_warning("project", "p1", "2026-10-01", 80).await.unwrap());
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(budget(BudgetMetric::Usd, 100.0, Enforcement::Reject).validate().is_ok());
+        assert!(budget(BudgetMetric::Usd, 0.0, Enforcement::Reject).validate().is_err());
+        assert!(budget(BudgetMetric::Tokens, f64::NAN, Enforcement::Reject).validate().is_err());
+
+        let parsed: Budget = serde_json::from_value(json!({ "metric": "tokens", "monthly_limit": 5e6 })).unwrap();
+        assert_eq!(parsed, budget(BudgetMetric::Tokens, 5e6, Enforcement::Reject));
+        assert_eq!(BudgetMetric::Tokens.format(1234.4), "1234 tokens");
+    }
+}
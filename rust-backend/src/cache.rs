@@ -0,0 +1,205 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Cache sizing, read from `DB_CACHE_CAPACITY` / `DB_CACHE_TTL_SECONDS`
+#[derive(Debug, Clone, Copy)]
+pub struct CacheConfig {
+    /// Max entries per cache; 0 disables caching
+    pub capacity: usize,
+    pub ttl: Duration,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 1000,
+            ttl: Duration::from_secs(30),
+        }
+    }
+}
+
+impl CacheConfig {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            capacity: std::env::var("DB_CACHE_CAPACITY")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(defaults.capacity),
+            ttl: std::env::var("DB_CACHE_TTL_SECONDS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.ttl),
+        }
+    }
+}
+
+/// Snapshot of a cache's counters
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub entries: usize,
+    pub capacity: usize,
+    pub ttl_seconds: u64,
+}
+
+#[derive(Debug)]
+struct Entry<V> {
+    value: V,
+    inserted_at: Instant,
+    last_used: u64,
+}
+
+#[derive(Debug)]
+struct Inner<V> {
+    entries: HashMap<String, Entry<V>>,
+    /// Monotonic use counter; the entry with the smallest `last_used` is evicted first
+    tick: u64,
+}
+
+/// Small TTL + LRU cache keyed by record id. Only found records are cached, so a
+/// miss always falls through to the database.
+#[derive(Debug)]
+pub struct TtlLruCache<V> {
+    config: CacheConfig,
+    inner: Mutex<Inner<V>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl<V: Clone> TtlLruCache<V> {
+    pub fn new(config: CacheConfig) -> Self {
+        Self {
+            config,
+            inner: Mutex::new(Inner {
+                entries: HashMap::new(),
+                tick: 0,
+            }),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<V> {
+        if self.config.capacity == 0 {
+            return None;
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+        inner.tick += 1;
+        let tick = inner.tick;
+
+        let expired = match inner.entries.get_mut(key) {
+            Some(entry) if entry.inserted_at.elapsed() < self.config.ttl => {
+                entry.last_used = tick;
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return Some(entry.value.clone());
+            }
+            Some(_) => true,
+            None => false,
+        };
+        if expired {
+            inner.entries.remove(key);
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        None
+    }
+
+    pub fn insert(&self, key: &str, value: V) {
+        if self.config.capacity == 0 {
+            return;
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+        inner.tick += 1;
+        let tick = inner.tick;
+
+        if !inner.entries.contains_key(key) && inner.entries.len() >= self.config.capacity {
+            let oldest = inner
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                inner.entries.remove(&oldest);
+                self.evictions.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        inner.entries.insert(
+            key.to_string(),
+            Entry {
+                value,
+                inserted_at: Instant::now(),
+                last_used: tick,
+            },
+        );
+    }
+
+    pub fn invalidate(&self, key: &str) {
+        self.inner.lock().unwrap().entries.remove(key);
+    }
+
+    pub fn clear(&self) {
+        self.inner.lock().unwrap().entries.clear();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            entries: self.inner.lock().unwrap().entries.len(),
+            capacity: self.config.capacity,
+            ttl_seconds: self.config.ttl.as_secs(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lru_eviction_and_counters() {
+        let cache = TtlLruCache::new(CacheConfig {
+            capacity: 2,
+            ttl: Duration::from_secs(60),
+        });
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        assert_eq!(cache.get("a"), Some(1));
+
+        // "b" is least recently used
+        cache.insert("c", 3);
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("a"), Some(1));
+        assert_eq!(cache.get("c"), Some(3));
+
+        cache.invalidate("a");
+        assert_eq!(cache.get("a"), None);
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.evictions, stats.entries), (3, 2, 1, 1));
+    }
+
+    #[test]
+    fn test_expired_entries_miss() {
+        let cache = TtlLruCache::new(CacheConfig {
+            capacity: 10,
+            ttl: Duration::ZERO,
+        });
+        cache.insert("a", 1);
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(cache.stats().entries, 0);
+    }
+}
@@ -0,0 +1,246 @@
+//! Files an analysis created, modified or deleted, from its write/edit tool calls: recorded on
+//! the session (`GET /api/sessions/:id/changed-files`) and sent with `code-analysis-complete`
+
+use crate::code_agent::{CodeAnalysisRequest, CodeAnalysisResponse};
+use crate::coverage;
+use crate::message_store::StructuredLogEntry;
+use crate::output_validation;
+use crate::AppState;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::path::{Component, Path, PathBuf};
+use tokio::sync::{broadcast, oneshot};
+use tracing::{debug, error, info};
+
+/// Files recorded for one session; further ones are dropped
+const MAX_CHANGED_FILES: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FileOperation {
+    Created,
+    Modified,
+    Deleted,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChangedFile {
+    /// Relative to the project directory
+    pub path: String,
+    pub operation: FileOperation,
+    /// Write/edit tools called on it, in order, without repeats
+    pub tools: Vec<String>,
+    /// Size after the run minus size before its first write (bytes)
+    pub bytes_delta: i64,
+}
+
+/// A file seen in a write call: its size (None: missing) when first seen, and the tools called on it
+#[derive(Debug, Default)]
+struct Touched {
+    size_before: Option<u64>,
+    tools: Vec<String>,
+}
+
+/// `raw` (as the agent passed it) relative to the project `root`, checked lexically since the
+/// file may not exist yet. Paths outside the project are dropped.
+fn relative_path(root: &Path, raw: &str) -> Option<String> {
+    let raw = Path::new(raw.trim());
+    let relative = if raw.is_absolute() {
+        raw.strip_prefix(root).ok()?
+    } else {
+        raw
+    };
+    let mut parts: Vec<String> = Vec::new();
+    for component in relative.components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_string_lossy().into_owned()),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    (!parts.is_empty()).then(|| parts.join("/"))
+}
+
+async fn size(path: &Path) -> Option<u64> {
+    tokio::fs::metadata(path).await.ok().filter(|m| m.is_file()).map(|m| m.len())
+}
+
+/// Note the paths a log entry writes; sizes are taken when the call is first logged, which is
+/// before the tool runs
+async fn observe(entry: &StructuredLogEntry, root: &Path, touched: &mut BTreeMap<String, Touched>) {
+    for call in output_validation::write_calls(entry) {
+        let mut raw_paths = Vec::new();
+        coverage::collect_paths(&call.input, &mut raw_paths);
+        for path in raw_paths.iter().filter_map(|raw| relative_path(root, raw)) {
+            if !touched.contains_key(&path) {
+                if touched.len() >= MAX_CHANGED_FILES {
+                    continue;
+                }
+                let size_before = size(&root.join(&path)).await;
+                touched.insert(path.clone(), Touched { size_before, tools: Vec::new() });
+            }
+            let tools = &mut touched.get_mut(&path).expect("inserted above").tools;
+            if !tools.contains(&call.tool) {
+                tools.push(call.tool.clone());
+            }
+        }
+    }
+}
+
+/// Compare each touched file with its size before; calls that left no file behind are dropped
+async fn changes(root: &Path, touched: BTreeMap<String, Touched>) -> Vec<ChangedFile> {
+    let mut changed = Vec::new();
+    for (path, touched) in touched {
+        let size_after = size(&root.join(&path)).await;
+        let operation = match (touched.size_before, size_after) {
+            (None, Some(_)) => FileOperation::Created,
+            (Some(_), Some(_)) => FileOperation::Modified,
+            (Some(_), None) => FileOperation::Deleted,
+            (None, None) => continue,
+        };
+        changed.push(ChangedFile {
+            path,
+            operation,
+            tools: touched.tools,
+            bytes_delta: size_after.unwrap_or(0) as i64 - touched.size_before.unwrap_or(0) as i64,
+        });
+    }
+    changed
+}
+
+/// Collect the files a ticket's run writes under `root` until `done` fires (or is dropped)
+async fn track_changes(
+    ticket_id: String,
+    root: PathBuf,
+    mut log_rx: broadcast::Receiver<StructuredLogEntry>,
+    mut done: oneshot::Receiver<()>,
+) -> Vec<ChangedFile> {
+    let mut touched = BTreeMap::new();
+    loop {
+        tokio::select! {
+            received = log_rx.recv() => match received {
+                Ok(entry) if entry.ticket_id == ticket_id => observe(&entry, &root, &mut touched).await,
+                Ok(_) => {}
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    debug!("Change tracker for ticket {} skipped {} entries", ticket_id, skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            _ = &mut done => {
+                while let Ok(entry) = log_rx.try_recv() {
+                    if entry.ticket_id == ticket_id {
+                        observe(&entry, &root, &mut touched).await;
+                    }
+                }
+                break;
+            }
+        }
+    }
+    changes(&root, touched).await
+}
+
+/// Await `run`, the analysis of `request`, while tracking the files it changes in its working
+/// directory. A completed run's changes are recorded on its (latest) session.
+pub async fn record(
+    state: &AppState,
+    request: &CodeAnalysisRequest,
+    run: impl Future<Output = Result<CodeAnalysisResponse>>,
+) -> (Result<CodeAnalysisResponse>, Vec<ChangedFile>) {
+    let root = match crate::code_agent::working_directory(request, &state.database).await {
+        Some(dir) => PathBuf::from(&dir).canonicalize().unwrap_or_else(|_| PathBuf::from(dir)),
+        None => return (run.await, Vec::new()),
+    };
+    let (done, done_rx) = oneshot::channel();
+    let tracker = state.tasks.spawn(
+        format!("changed-files {}", request.ticket_id),
+        track_changes(request.ticket_id.clone(), root, state.msg_store.subscribe(), done_rx),
+    );
+
+    let outcome = run.await;
+    let _ = done.send(());
+    let changed = tracker.await.unwrap_or_default();
+    if outcome.is_err() || changed.is_empty() {
+        return (outcome, changed);
+    }
+
+    info!("📝 Ticket {} đã thay đổi {} file", request.ticket_id, changed.len());
+    let database = &state.database;
+    match database.get_latest_session_by_ticket(&request.ticket_id).await {
+        Ok(Some(session)) => {
+            let json = serde_json::to_string(&changed).unwrap_or_default();
+            if let Err(e) = database.set_session_changed_files(&session.id, &json).await {
+                error!("Failed to record changed files of session {}: {}", session.id, e);
+            }
+        }
+        Ok(None) => {}
+        Err(e) => error!("Failed to get the session of ticket {}: {}", request.ticket_id, e),
+    }
+    (outcome, changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message_store::LogMessageType;
+    use std::collections::HashMap;
+
+    fn entry(content: &str) -> StructuredLogEntry {
+        StructuredLogEntry {
+            id: "1".to_string(),
+            ticket_id: "t1".to_string(),
+            message_type: LogMessageType::ToolUse,
+            content: content.to_string(),
+            raw_log: None,
+            metadata: HashMap::new(),
+            timestamp: chrono::Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_relative_path() {
+        let root = Path::new("/srv/shop");
+        assert_eq!(relative_path(root, "/srv/shop/src/cart.rs"), Some("src/cart.rs".to_string()));
+        assert_eq!(relative_path(root, "./src/new.rs"), Some("src/new.rs".to_string()));
+        assert_eq!(relative_path(root, "../secrets/key"), None);
+        assert_eq!(relative_path(root, "/etc/passwd"), None);
+    }
+
+    #[tokio::test]
+    async fn test_changes() {
+        let root = std::env::temp_dir().join(format!("changed-files-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::write(root.join("src/cart.rs"), "fn total() {}").unwrap();
+        std::fs::write(root.join("old.txt"), "obsolete").unwrap();
+        let mut touched = BTreeMap::new();
+
+        let edit = format!(
+            r#"{{"type":"assistant","message":{{"content":[{{"type":"tool_use","name":"Read","input":{{"file_path":"{0}/README.md"}}}},{{"type":"tool_use","name":"Edit","input":{{"file_path":"{0}/src/cart.rs"}}}}]}}}}"#,
+            root.display()
+        );
+        observe(&entry(&edit), &root, &mut touched).await;
+        observe(&entry(r#"{"type":"tool_use","tool_name":"write_file","parameters":{"file_path":"src/new.rs"}}"#), &root, &mut touched).await;
+        observe(&entry(r#"{"type":"tool_call","subtype":"started","tool_call":{"deleteToolCall":{"args":{"path":"old.txt"}}}}"#), &root, &mut touched).await;
+        observe(&entry(r#"{"type":"tool_use","tool_name":"write_file","parameters":{"file_path":"src/never.rs"}}"#), &root, &mut touched).await;
+        // The tools run after their calls are logged
+        std::fs::write(root.join("src/cart.rs"), "fn total() -> u32 { 0 }").unwrap();
+        std::fs::write(root.join("src/new.rs"), "mod cart;").unwrap();
+        std::fs::remove_file(root.join("old.txt")).unwrap();
+
+        let changed = changes(&root, touched).await;
+        let summary: Vec<(&str, FileOperation, i64)> =
+            changed.iter().map(|file| (file.path.as_str(), file.operation, file.bytes_delta)).collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("old.txt", FileOperation::Deleted, -8),
+                ("src/cart.rs", FileOperation::Modified, 10),
+                ("src/new.rs", FileOperation::Created, 9),
+            ]
+        );
+        assert_eq!(changed[1].tools, vec!["Edit"]);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
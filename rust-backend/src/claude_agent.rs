@@ -1,13 +1,22 @@
-use crate::code_agent::{CodeAgent, CodeAnalysisRequest, CodeAnalysisResponse};
+use crate::agent_credentials::ApiKey;
+use crate::code_agent::{until_cancelled, AnalysisCancelled, CodeAgent, CodeAnalysisRequest, CodeAnalysisResponse};
+use crate::container::{self, ContainerConfig};
 use crate::database::Database;
+use crate::interaction::{self, InputProtocol};
+use crate::json_frames::JsonFrames;
 use crate::log_normalizer::LogNormalizer;
 use crate::message_store::MsgStore;
+use crate::prompt::{PromptDelivery, PromptLimits};
+use crate::resource_limits::ResourceLimits;
+use crate::retry::{self, Retryable, RetryPolicy};
+use crate::stall::StallPolicy;
+use crate::transcript::Transcript;
 use anyhow::Result;
 use async_trait::async_trait;
 use std::sync::Arc;
 use tokio::io::{AsyncBufReadExt, BufReader};
-use tokio::process::Command;
 use tokio::time::{timeout, Duration};
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info, warn};
 
 #[derive(Debug, thiserror::Error)]
@@ -24,14 +33,33 @@ pub enum ClaudeAgentError {
     DirectoryNotAccessible(String),
 }
 
+impl Retryable for ClaudeAgentError {
+    /// A crash or hang may be a one-off; a missing binary or directory will still be missing
+    fn is_retryable(&self) -> bool {
+        match self {
+            Self::Timeout(_) | Self::ProcessFailed(_) | Self::SpawnFailed(_) => true,
+            Self::ExecutableNotFound(_) | Self::DirectoryNotAccessible(_) => false,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ClaudeAgentConfig {
     pub executable_path: String,
     pub timeout_seconds: u64,
-    pub max_retries: u32,
+    pub retry: RetryPolicy,
+    pub prompt_limits: PromptLimits,
     pub working_dir: Option<String>,
     pub output_format: OutputFormat,
     pub api_key: Option<String>,
+    /// Answer permission prompts from the UI (stream-json output only)
+    pub interactive: bool,
+    /// Run the CLI in a Docker container instead of on the host
+    pub container: Option<ContainerConfig>,
+    /// Memory, CPU, file and wall-clock limits on the CLI process
+    pub limits: ResourceLimits,
+    /// Killing the CLI once it has gone silent for too long
+    pub stall: StallPolicy,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -47,10 +75,15 @@ impl Default for ClaudeAgentConfig {
         Self {
             executable_path: "claude".to_string(),
             timeout_seconds: 300, // 5 minutes
-            max_retries: 2,
+            retry: RetryPolicy::default(),
+            prompt_limits: PromptLimits::default(),
             working_dir: None,
             output_format: OutputFormat::StreamJson,
             api_key: std::env::var("CLAUDE_API_KEY").ok(),
+            interactive: false,
+            container: None,
+            limits: ResourceLimits::default(),
+            stall: StallPolicy::default(),
         }
     }
 }
@@ -75,13 +108,15 @@ impl ClaudeAgentConfig {
                 .ok()
                 .and_then(|s| s.parse().ok())
                 .unwrap_or(300),
-            max_retries: std::env::var("CLAUDE_AGENT_MAX_RETRIES")
-                .ok()
-                .and_then(|s| s.parse().ok())
-                .unwrap_or(2),
+            retry: RetryPolicy::from_env("CLAUDE_AGENT"),
+            prompt_limits: PromptLimits::from_env(),
             working_dir: std::env::var("CLAUDE_AGENT_WORKING_DIR").ok(),
             output_format,
             api_key: std::env::var("CLAUDE_API_KEY").ok(),
+            interactive: interaction::interactive_from_env("CLAUDE_AGENT"),
+            container: ContainerConfig::from_env(),
+            limits: ResourceLimits::from_env(),
+            stall: StallPolicy::from_env(),
         }
     }
 }
@@ -101,6 +136,7 @@ impl ClaudeAgent {
         request: CodeAnalysisRequest,
         msg_store: Arc<MsgStore>,
         database: Arc<Database>,
+        cancel: CancellationToken,
     ) -> Result<CodeAnalysisResponse> {
         info!("🚀 Bắt đầu phân tích code cho ticket: {}", request.ticket_id);
 
@@ -121,6 +157,13 @@ impl ClaudeAgent {
                 is_analyzing: true,
                 created_at: chrono::Utc::now().to_rfc3339(),
                 updated_at: chrono::Utc::now().to_rfc3339(),
+                stale: false,
+                analysis_result_blob: None,
+                analysis_result_size: None,
+                position: 0.0,
+                assignee_id: None,
+                summary: None,
+                mode: request.mode,
             };
             
             database.create_ticket(&auto_ticket).await?;
@@ -128,7 +171,9 @@ impl ClaudeAgent {
         }
 
         // Create analysis session in database
-        let session_id = database.create_session(&request.ticket_id).await?;
+        let session_id = database
+            .create_session(&request.ticket_id, "claude", request.run_id.as_deref(), &request.tool_policy, request.mode)
+            .await?;
 
         // Update ticket status to analyzing
         database
@@ -148,23 +193,17 @@ impl ClaudeAgent {
         logs.push(start_log.to_string());
 
         // Get project directory for analysis scope
-        let working_directory = if !request.project_id.is_empty() {
-            if let Ok(Some(project)) = database.get_project(&request.project_id).await {
-                info!("📂 Working directory: {}", project.directory_path);
-                Some(project.directory_path)
-            } else {
-                error!("⚠️ Không tìm thấy project {}", request.project_id);
-                None
-            }
-        } else {
-            None
-        };
+        let working_directory = crate::code_agent::working_directory(&request, &database).await;
 
         // Execute Claude Agent analysis
-        let result = match self
-            .execute_claude_agent(&request, working_directory, &msg_store, &normalizer)
-            .await
-        {
+        let transcript = Transcript::new();
+        let outcome = until_cancelled(
+            &cancel,
+            self.execute_claude_agent(&request, working_directory, &msg_store, &normalizer, &transcript),
+        )
+        .await;
+        transcript.save(&database, &session_id, msg_store.redactor()).await;
+        let result = match outcome {
             Ok(output) => {
                 info!("✅ Claude Code Agent hoàn thành phân tích");
 
@@ -179,7 +218,8 @@ impl ClaudeAgent {
                 msg_store.push(entry).await;
                 logs.push(completion_log.to_string());
 
-                // Update database with success
+                // Update database with success, secrets masked like the logs
+                let output = msg_store.redactor().redact_text(output);
                 database.complete_session(&session_id, "Success").await?;
                 database
                     .update_ticket_result(&request.ticket_id, &output)
@@ -187,6 +227,10 @@ impl ClaudeAgent {
 
                 output
             }
+            Err(e) if e.is::<AnalysisCancelled>() => {
+                info!("⛔ Đã dừng Claude Code Agent cho ticket {}", request.ticket_id);
+                return Err(e);
+            }
             Err(e) => {
                 error!("❌ Lỗi khi thực thi Claude Code Agent: {}", e);
 
@@ -220,6 +264,7 @@ impl ClaudeAgent {
         working_directory: Option<String>,
         msg_store: &Arc<MsgStore>,
         normalizer: &LogNormalizer,
+        transcript: &Transcript,
     ) -> Result<String> {
         info!("🎯 Executing analysis for: {}", request.code_context);
         
@@ -236,7 +281,10 @@ impl ClaudeAgent {
 
         // Validate executable exists only for absolute paths
         // For executables in PATH, let spawn() handle the error
-        if self.config.executable_path.contains('/') || self.config.executable_path.contains('\\') {
+        // In a container the executable is a path inside the image
+        if self.config.container.is_some() {
+            debug!("Skipping executable check: runs in container");
+        } else if self.config.executable_path.contains('/') || self.config.executable_path.contains('\\') {
             // It's an absolute path, check if exists
             if let Err(_e) = tokio::fs::metadata(&self.config.executable_path).await {
                 error!("⚠️ Claude Code executable không tồn tại: {}", self.config.executable_path);
@@ -263,28 +311,10 @@ impl ClaudeAgent {
         }
 
         // Execute with retry logic
-        let mut last_error = None;
-        for attempt in 1..=self.config.max_retries {
-            info!("🔄 Attempt {}/{} for analysis", attempt, self.config.max_retries);
-            
-            match self.spawn_claude_process(request, analysis_dir.clone(), msg_store, normalizer).await {
-                Ok(result) => {
-                    info!("✅ Analysis completed successfully on attempt {}", attempt);
-                    return Ok(result);
-                }
-                Err(e) => {
-                    warn!("❌ Attempt {} failed: {}", attempt, e);
-                    last_error = Some(e);
-                    
-                    if attempt < self.config.max_retries {
-                        info!("⏳ Waiting before retry...");
-                        tokio::time::sleep(Duration::from_secs(2)).await;
-                    }
-                }
-            }
-        }
-
-        Err(last_error.unwrap_or_else(|| anyhow::anyhow!("All retry attempts failed")))
+        retry::run(&self.config.retry, "analysis", retry::classify::<ClaudeAgentError>, |_| {
+            self.spawn_claude_process(request, analysis_dir.clone(), msg_store, normalizer, transcript)
+        })
+        .await
     }
 
 
@@ -294,17 +324,52 @@ impl ClaudeAgent {
         working_directory: Option<String>,
         msg_store: &Arc<MsgStore>,
         _normalizer: &LogNormalizer,
+        transcript: &Transcript,
     ) -> Result<String> {
-        let prompt = self.create_analysis_prompt(request);
+        let mut prompt = self.create_analysis_prompt(request);
+        if let Some(notice) = request.path_rules.prompt_notice() {
+            prompt.push_str(&notice);
+        }
         let ticket_id = request.ticket_id.clone();
 
         info!("🚀 Spawning Claude Code Agent process: {}", self.config.executable_path);
         debug!("Prompt: {}", prompt);
+        let delivery = PromptDelivery::for_prompt(&prompt, &self.config.prompt_limits)?;
+        // Permission requests arrive as control messages, which only stream-json carries
+        let interactive = self.config.interactive
+            && matches!(
+                self.config.output_format,
+                OutputFormat::StreamJson | OutputFormat::StreamPartialOutput
+            );
 
         // Build command with proper Claude CLI arguments according to documentation
         // Reference: https://code.claude.com/docs/en/headless
-        let mut cmd = Command::new(&self.config.executable_path);
+        // The project is mounted read-write only when the policy allows changing it
+        let (mut cmd, _container) = container::command(
+            self.config.container.as_ref(),
+            &self.config.executable_path,
+            working_directory.as_deref(),
+            !request.tool_policy.read_only(),
+            &["CLAUDE_API_KEY"],
+        );
         
+        // Excluded paths become Read deny rules; given before -p so the variadic flag
+        // can't swallow the prompt
+        if !request.path_rules.exclude.is_empty() {
+            let rules: Vec<String> = request
+                .path_rules
+                .exclude
+                .iter()
+                .map(|pattern| match crate::project_files::to_glob(pattern) {
+                    glob if glob.starts_with("**") => format!("Read({})", glob),
+                    glob => format!("Read(./{})", glob),
+                })
+                .collect();
+            cmd.arg("--disallowedTools").arg(rules.join(","));
+        }
+        // Tools outside the policy are denied, or asked about in interactive mode
+        cmd.arg("--allowedTools").arg(request.tool_policy.claude_allowed_tools());
+
         // Print mode for non-interactive scripting (use either -p OR --print, not both)
         cmd.arg("-p");
         
@@ -333,6 +398,12 @@ impl ClaudeAgent {
             }
             _ => {}
         }
+
+        // Interactive runs take the prompt and permission answers as stream-json on stdin
+        if interactive {
+            cmd.arg("--input-format").arg("stream-json");
+            cmd.arg("--permission-prompt-tool").arg("stdio");
+        }
         
         // Set working directory using Rust's Command::current_dir()
         // Claude CLI will execute in the specified directory context
@@ -340,27 +411,51 @@ impl ClaudeAgent {
             cmd.current_dir(dir);
         }
         
-        // Add the actual prompt/command as the final argument
-        cmd.arg(&prompt);
+        // Add the actual prompt/command as the final argument (or send it on stdin below)
+        if !interactive {
+            delivery.push_arg(&mut cmd, &prompt);
+        }
 
         // Set API key if available
-        if let Some(ref api_key) = self.config.api_key {
+        // The project's own key wins over the server's
+        let api_key = request.api_key.as_ref().map(ApiKey::expose).or(self.config.api_key.as_deref());
+        if let Some(api_key) = api_key {
             cmd.env("CLAUDE_API_KEY", api_key);
         }
 
         cmd.stdin(std::process::Stdio::piped());  // Key fix: pipe stdin to close it later
+
+        // Dropping the run (cancel, timeout) must not leave the CLI running
+
+        cmd.kill_on_drop(true);
         cmd.stdout(std::process::Stdio::piped());
         cmd.stderr(std::process::Stdio::piped());
 
         // Spawn the process
+        let mut limits = self.config.limits.apply(&mut cmd);
+        transcript.spawned(&cmd, &prompt);
         let mut child = cmd.spawn()
             .map_err(|e| ClaudeAgentError::SpawnFailed(e.to_string()))?;
-
-        // Close stdin immediately to signal EOF
-        // This forces Claude Agent to exit after processing instead of waiting for more input
-        let _stdin = child.stdin.take();
-        drop(_stdin);
-        info!("🔒 Closed stdin to signal EOF to Claude Code Agent");
+        limits.watch(child.id());
+        let mut stall = self.config.stall.watch(child.id(), &ticket_id, msg_store.clone());
+
+        // Close stdin (after writing a long prompt) to signal EOF
+        // This forces Claude Agent to exit after processing instead of waiting for more input.
+        // Interactive runs keep it open until the final result event.
+        let input_session = match child.stdin.take() {
+            Some(stdin) if interactive => Some(msg_store.inputs().open(
+                &ticket_id,
+                InputProtocol::ClaudeControl,
+                stdin,
+                Some(interaction::claude_user_message(&prompt)),
+            )),
+            stdin => {
+                child.stdin = stdin;
+                delivery.feed_stdin(&mut child, &prompt);
+                info!("🔒 Closed stdin to signal EOF to Claude Code Agent");
+                None
+            }
+        };
 
         let stdout = child.stdout.take().ok_or_else(|| 
             ClaudeAgentError::SpawnFailed("Failed to get stdout pipe".to_string()))?;
@@ -370,20 +465,29 @@ impl ClaudeAgent {
         // Clone for async tasks
         let msg_store_clone = msg_store.clone();
         let ticket_id_clone = ticket_id.clone();
+        let stdout_transcript = transcript.clone();
+        let stdout_heartbeat = stall.heartbeat();
 
         // Spawn task to capture stdout
         let stdout_handle = tokio::spawn(async move {
-            let reader = BufReader::new(stdout);
-            let mut lines = reader.lines();
+            // Events split over several lines arrive reassembled
+            let mut frames = JsonFrames::new(stdout).on_line(move |line| {
+                info!("📤 STDOUT: {}", line);
+                stdout_transcript.stdout(line);
+                stdout_heartbeat.beat();
+            });
             let mut output_lines = Vec::new();
             let normalizer = LogNormalizer::new();
 
-            while let Ok(Some(line)) = lines.next_line().await {
-                info!("📤 STDOUT: {}", line);
+            while let Some(line) = frames.next_frame().await {
                 output_lines.push(line.clone());
                 
+                let awaiting = input_session.as_ref().and_then(|session| session.observe(&line));
                 let entry = normalizer.normalize(line, ticket_id_clone.clone());
                 msg_store_clone.push(entry).await;
+                if let Some(awaiting) = awaiting {
+                    msg_store_clone.push(awaiting).await;
+                }
             }
 
             info!("📤 Finished reading stdout, total lines: {}", output_lines.len());
@@ -394,6 +498,8 @@ impl ClaudeAgent {
         // Spawn task to capture stderr
         let stderr_ticket_id = request.ticket_id.clone();
         let stderr_msg_store = msg_store.clone();
+        let stderr_transcript = transcript.clone();
+        let stderr_heartbeat = stall.heartbeat();
 
         let stderr_handle = tokio::spawn(async move {
             let reader = BufReader::new(stderr);
@@ -402,6 +508,8 @@ impl ClaudeAgent {
 
             while let Ok(Some(line)) = lines.next_line().await {
                 info!("⚠️ STDERR: {}", line);
+                stderr_transcript.stderr(&line);
+                stderr_heartbeat.beat();
                 let error_line = format!("ERROR: {}", line);
                 let entry = stderr_normalizer.normalize(error_line, stderr_ticket_id.clone());
                 stderr_msg_store.push(entry).await;
@@ -422,6 +530,18 @@ impl ClaudeAgent {
                 
                 // Wait for log capture to complete
                 let (stdout_result, _) = tokio::join!(stdout_handle, stderr_handle);
+                transcript.exited(status.code());
+
+                if let Some(stalled) = stall.stalled() {
+                    error!("⛔ {}", stalled);
+                    msg_store.push(stalled.log_entry(&ticket_id)).await;
+                    return Err(stalled.into());
+                }
+                if let Some(exceeded) = limits.exceeded(&status) {
+                    error!("⛔ {}", exceeded);
+                    msg_store.push(exceeded.log_entry(&ticket_id)).await;
+                    return Err(exceeded.into());
+                }
                 
                 let output_lines = stdout_result.map_err(|e| 
                     ClaudeAgentError::SpawnFailed(format!("Stdout task failed: {}", e)))?;
@@ -467,12 +587,12 @@ impl ClaudeAgent {
         if request.code_context.is_empty() {
             format!(
                 "Phân tích code để giúp QA hiểu business flow. Câu hỏi: {}",
-                request.question
+                request.prompt_question()
             )
         } else {
             format!(
                 "Analyze the code in {} to help QA understand the business flow. Question: {}",
-                request.code_context, request.question
+                request.code_context, request.prompt_question()
             )
         }
     }
@@ -486,9 +606,10 @@ impl CodeAgent for ClaudeAgent {
         request: CodeAnalysisRequest,
         msg_store: Arc<MsgStore>,
         database: Arc<Database>,
+        cancel: CancellationToken,
     ) -> Result<CodeAnalysisResponse> {
         // Delegate to existing implementation
-        self.analyze_code(request, msg_store, database).await
+        self.analyze_code(request, msg_store, database, cancel).await
     }
 }
 
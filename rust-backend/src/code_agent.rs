@@ -1,9 +1,44 @@
+use crate::agent_credentials::ApiKey;
 use crate::database::Database;
+use crate::experiments::PromptVariant;
+use crate::job_queue::AnalysisPriority;
 use crate::message_store::MsgStore;
+use crate::project_files::PathRules;
+use crate::replay::Replay;
+use crate::tool_policy::ToolPolicy;
 use anyhow::Result;
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
+use std::future::Future;
 use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info};
+
+/// What an analysis is asked to produce
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Default, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "lowercase")]
+pub enum AnalysisMode {
+    /// Free-form question answering about the code (default)
+    #[default]
+    Ask,
+    /// Structured QA test cases (preconditions, steps, expected), stored per ticket
+    #[serde(rename = "testcases", alias = "test_cases")]
+    TestCases,
+    /// Mermaid sequence or flow diagram of the flow, stored per ticket
+    Diagram,
+}
+
+impl AnalysisMode {
+    /// The name clients send and the database stores
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Ask => "ask",
+            Self::TestCases => "testcases",
+            Self::Diagram => "diagram",
+        }
+    }
+}
 
 /// Request for code analysis
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -12,6 +47,69 @@ pub struct CodeAnalysisRequest {
     pub code_context: String,
     pub question: String,
     pub project_id: String,
+    /// Correlation ID of this run, assigned by `analysis_runner::start_analysis`
+    #[serde(default)]
+    pub run_id: Option<String>,
+    /// Agent to run this analysis (`GET /api/agents` ids); the server default when unset
+    #[serde(default)]
+    pub agent_type: Option<String>,
+    #[serde(default)]
+    pub mode: AnalysisMode,
+    /// The project's include/exclude globs, filled in by `analysis_runner::start_analysis`
+    #[serde(default)]
+    pub path_rules: PathRules,
+    /// Tools the agent may use without asking, resolved by `analysis_runner::start_analysis`
+    #[serde(default)]
+    pub tool_policy: ToolPolicy,
+    /// Place in the analysis queue; `normal` when unset
+    #[serde(default)]
+    pub priority: AnalysisPriority,
+    /// Add the results of linked tickets to the code context (see `ticket_links`)
+    #[serde(default)]
+    pub include_linked_results: bool,
+    /// Start even when a budget that allows overrides is used up (see `budgets`); only set for
+    /// organization admins
+    #[serde(default)]
+    pub budget_override: bool,
+    /// Session this run re-runs, and the commit to check out for it (see `replay`)
+    #[serde(default)]
+    pub replay: Option<Replay>,
+    /// Directory to analyze instead of the project's, set by `analysis_runner` for replays
+    #[serde(default)]
+    pub working_dir: Option<String>,
+    /// Prompt template of the experiment variant this run was assigned to (see `experiments`);
+    /// kept with the request so replays use the same prompt
+    #[serde(default)]
+    pub prompt_variant: Option<PromptVariant>,
+    /// Language to answer in, from the requester's preferences when the request has none
+    #[serde(default)]
+    pub response_language: Option<String>,
+    /// The project's own key for the agent, set by `analysis_runner`; never stored with the request
+    #[serde(skip)]
+    pub api_key: Option<ApiKey>,
+    /// Analyses to queue once this one ends, in order (see `bootstrap`); never stored with the request
+    #[serde(skip)]
+    pub then: Vec<CodeAnalysisRequest>,
+}
+
+impl CodeAnalysisRequest {
+    /// The question as agents should put it in their prompt, with the mode's template and the
+    /// experiment variant's template applied, and the response language asked for
+    pub fn prompt_question(&self) -> String {
+        let question = match self.mode {
+            AnalysisMode::Ask => self.question.clone(),
+            AnalysisMode::TestCases => crate::test_cases::build_prompt(&self.question),
+            AnalysisMode::Diagram => crate::diagram::build_prompt(&self.question),
+        };
+        let question = match &self.prompt_variant {
+            Some(variant) => variant.apply(&question),
+            None => question,
+        };
+        match &self.response_language {
+            Some(language) => format!("{}\n\nWrite the answer in {}.", question, language),
+            None => question,
+        }
+    }
 }
 
 /// Response from code analysis
@@ -23,6 +121,38 @@ pub struct CodeAnalysisResponse {
     pub success: bool,
 }
 
+/// Returned by an agent whose run was cancelled; `analysis_runner` cleans up the session and ticket
+#[derive(Debug, thiserror::Error)]
+#[error("Analysis cancelled")]
+pub struct AnalysisCancelled;
+
+/// Run an agent's work until it finishes or `cancel` fires. The work is dropped on cancel,
+/// so agent processes must be spawned with `kill_on_drop(true)` to die with it.
+pub async fn until_cancelled<T>(cancel: &CancellationToken, work: impl Future<Output = Result<T>>) -> Result<T> {
+    tokio::select! {
+        result = work => result,
+        _ = cancel.cancelled() => Err(AnalysisCancelled.into()),
+    }
+}
+
+/// Directory an agent works in: the replay checkout when there is one, else the project's
+pub async fn working_directory(request: &CodeAnalysisRequest, database: &Database) -> Option<String> {
+    if let Some(dir) = &request.working_dir {
+        info!("📂 Working directory (replay): {}", dir);
+        return Some(dir.clone());
+    }
+    if request.project_id.is_empty() {
+        return None;
+    }
+    if let Ok(Some(project)) = database.get_project(&request.project_id).await {
+        info!("📂 Working directory: {}", project.directory_path);
+        Some(project.directory_path)
+    } else {
+        error!("⚠️ Không tìm thấy project {}", request.project_id);
+        None
+    }
+}
+
 /// Trait for code analysis agents
 ///
 /// Implementations must be Send + Sync to work with Arc<dyn CodeAgent>
@@ -34,6 +164,8 @@ pub trait CodeAgent: Send + Sync {
     /// * `request` - The analysis request containing ticket info and question
     /// * `msg_store` - Message store for real-time log streaming
     /// * `database` - Database for persisting analysis results
+    /// * `cancel` - Fired when the user stops the analysis; the agent then returns `AnalysisCancelled`
+    ///   without failing the session, and the runner records the cancellation
     ///
     /// # Returns
     /// Result containing the analysis response or an error
@@ -42,5 +174,49 @@ pub trait CodeAgent: Send + Sync {
         request: CodeAnalysisRequest,
         msg_store: Arc<MsgStore>,
         database: Arc<Database>,
+        cancel: CancellationToken,
     ) -> Result<CodeAnalysisResponse>;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Running (not exited or zombie) according to /proc
+    #[cfg(target_os = "linux")]
+    fn process_alive(pid: u32) -> bool {
+        std::fs::read_to_string(format!("/proc/{}/stat", pid))
+            .map(|stat| !stat.contains(") Z "))
+            .unwrap_or(false)
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_cancel_kills_agent_process() {
+        let cancel = CancellationToken::new();
+        let (pid_tx, pid_rx) = tokio::sync::oneshot::channel();
+        let work = async move {
+            let mut child = tokio::process::Command::new("sleep").arg("30").kill_on_drop(true).spawn()?;
+            let _ = pid_tx.send(child.id().unwrap());
+            child.wait().await?;
+            Ok("finished")
+        };
+
+        let canceller = cancel.clone();
+        let (outcome, pid) = tokio::join!(until_cancelled(&cancel, work), async move {
+            let pid = pid_rx.await.unwrap();
+            assert!(process_alive(pid));
+            canceller.cancel();
+            pid
+        });
+        assert!(outcome.unwrap_err().is::<AnalysisCancelled>());
+
+        for _ in 0..50 {
+            if !process_alive(pid) {
+                return;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        panic!("agent process {} still running after cancel", pid);
+    }
+}
@@ -0,0 +1,359 @@
+use anyhow::{bail, Result};
+use std::path::PathBuf;
+
+/// Server-level configuration loaded from environment variables
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub port: u16,
+    /// Built frontend bundle (e.g. Next.js `out/`) to serve alongside the API
+    pub frontend_dist_dir: Option<PathBuf>,
+    /// `Cache-Control` max-age for fingerprinted assets under `/_next/static/`
+    pub static_max_age_seconds: u64,
+    /// Native TLS termination; `None` serves plain HTTP
+    pub tls: Option<TlsConfig>,
+}
+
+/// TLS settings, enabled when both `TLS_CERT_PATH` and `TLS_KEY_PATH` are set; setting only
+/// one of them is a startup error rather than a silent fallback to plain HTTP
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    /// PEM certificate chain
+    pub cert_path: PathBuf,
+    /// PEM private key
+    pub key_path: PathBuf,
+    /// Plain HTTP port that redirects every request to HTTPS
+    pub http_redirect_port: Option<u16>,
+}
+
+impl TlsConfig {
+    pub fn from_env() -> Result<Option<Self>> {
+        let path = |var: &str| std::env::var(var).ok().filter(|s| !s.trim().is_empty());
+        let Some((cert_path, key_path)) = tls_paths(path("TLS_CERT_PATH"), path("TLS_KEY_PATH"))? else {
+            return Ok(None);
+        };
+
+        Ok(Some(Self {
+            cert_path,
+            key_path,
+            http_redirect_port: std::env::var("TLS_HTTP_REDIRECT_PORT")
+                .ok()
+                .and_then(|s| s.parse().ok()),
+        }))
+    }
+}
+
+fn tls_paths(cert_path: Option<String>, key_path: Option<String>) -> Result<Option<(PathBuf, PathBuf)>> {
+    match (cert_path, key_path) {
+        (Some(cert_path), Some(key_path)) => Ok(Some((PathBuf::from(cert_path), PathBuf::from(key_path)))),
+        (None, None) => Ok(None),
+        (Some(_), None) => bail!("TLS_CERT_PATH is set but TLS_KEY_PATH isn't; set both to serve HTTPS, or neither"),
+        (None, Some(_)) => bail!("TLS_KEY_PATH is set but TLS_CERT_PATH isn't; set both to serve HTTPS, or neither"),
+    }
+}
+
+/// Load shedding, timeouts and body limits applied to every HTTP request (see `http_limits`),
+/// read from `HTTP_*`
+#[derive(Debug, Clone)]
+pub struct HttpLimitsConfig {
+    /// Requests handled at once; more are answered 503 at once instead of queueing (0 = no limit)
+    pub max_concurrent_requests: usize,
+    /// Time to produce a response before answering 408 (0 = none). Streaming bodies (SSE,
+    /// downloads) and WebSocket connections only need their response head within it; the log
+    /// tail long-poll has a built-in route limit above its longest wait.
+    pub request_timeout_secs: u64,
+    /// Largest request body; bigger ones are answered 413
+    pub max_body_bytes: usize,
+    /// `Retry-After` sent with 503 and 408
+    pub retry_after_secs: u64,
+    /// Overrides for slow or large routes, built-in ones first; the last match wins
+    pub routes: Vec<RouteLimit>,
+}
+
+/// Timeout and/or body limit for routes matching `pattern`: a route as written in the route
+/// table (`/api/projects/:id/export`), or a prefix ending in `*`
+#[derive(Debug, Clone, PartialEq)]
+pub struct RouteLimit {
+    pub pattern: String,
+    pub timeout_secs: Option<u64>,
+    pub max_body_bytes: Option<usize>,
+}
+
+impl RouteLimit {
+    pub fn matches(&self, route: &str) -> bool {
+        match self.pattern.strip_suffix('*') {
+            Some(prefix) => route.starts_with(prefix),
+            None => route == self.pattern,
+        }
+    }
+}
+
+impl Default for HttpLimitsConfig {
+    fn default() -> Self {
+        let timeout = |pattern: &str, secs| RouteLimit {
+            pattern: pattern.to_string(),
+            timeout_secs: Some(secs),
+            max_body_bytes: None,
+        };
+        Self {
+            max_concurrent_requests: 512,
+            request_timeout_secs: 30,
+            max_body_bytes: 2 * 1024 * 1024,
+            retry_after_secs: 5,
+            routes: vec![
+                timeout("/api/projects/:id/export", 300),
+                timeout("/api/admin/backups", 600),
+                timeout("/api/admin/backups/:name", 600),
+                // Long-poll: answers only after waiting up to `TAIL_MAX_WAIT_SECS`
+                timeout("/api/tickets/:id/logs/tail", 90),
+                RouteLimit {
+                    pattern: "/api/projects/import".to_string(),
+                    timeout_secs: Some(300),
+                    max_body_bytes: Some(64 * 1024 * 1024),
+                },
+            ],
+        }
+    }
+}
+
+impl HttpLimitsConfig {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let mut routes = defaults.routes;
+        // `HTTP_ROUTE_TIMEOUTS=/api/projects/:id/export=600,/api/reports/*=120`
+        for (pattern, secs) in route_values("HTTP_ROUTE_TIMEOUTS") {
+            routes.push(RouteLimit {
+                pattern,
+                timeout_secs: Some(secs),
+                max_body_bytes: None,
+            });
+        }
+        // `HTTP_ROUTE_BODY_LIMITS=/api/projects/import=268435456`
+        for (pattern, bytes) in route_values("HTTP_ROUTE_BODY_LIMITS") {
+            routes.push(RouteLimit {
+                pattern,
+                timeout_secs: None,
+                max_body_bytes: Some(bytes as usize),
+            });
+        }
+
+        Self {
+            max_concurrent_requests: std::env::var("HTTP_MAX_CONCURRENT_REQUESTS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(defaults.max_concurrent_requests),
+            request_timeout_secs: std::env::var("HTTP_REQUEST_TIMEOUT_SECS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(defaults.request_timeout_secs),
+            max_body_bytes: std::env::var("HTTP_MAX_BODY_BYTES")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|n| *n > 0)
+                .unwrap_or(defaults.max_body_bytes),
+            retry_after_secs: std::env::var("HTTP_RETRY_AFTER_SECS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(defaults.retry_after_secs),
+            routes,
+        }
+    }
+
+    /// Timeout (None: no timeout) and body limit for a request to `route`
+    pub fn for_route(&self, route: &str) -> (Option<u64>, usize) {
+        let mut timeout_secs = self.request_timeout_secs;
+        let mut max_body_bytes = self.max_body_bytes;
+        for limit in self.routes.iter().filter(|limit| limit.matches(route)) {
+            timeout_secs = limit.timeout_secs.unwrap_or(timeout_secs);
+            max_body_bytes = limit.max_body_bytes.unwrap_or(max_body_bytes);
+        }
+        (Some(timeout_secs).filter(|secs| *secs > 0), max_body_bytes)
+    }
+}
+
+/// `pattern=number` pairs from a comma-separated variable; malformed pairs are skipped
+fn route_values(var: &str) -> Vec<(String, u64)> {
+    let Ok(value) = std::env::var(var) else {
+        return Vec::new();
+    };
+    value
+        .split(',')
+        .filter_map(|pair| {
+            let (pattern, number) = pair.trim().rsplit_once('=')?;
+            let number = number.trim().parse().ok()?;
+            Some((pattern.trim().to_string(), number)).filter(|(pattern, _)| pattern.starts_with('/'))
+        })
+        .collect()
+}
+
+/// SQLite connection pool settings, read from `DATABASE_URL` and `DB_*`
+#[derive(Debug, Clone)]
+pub struct DatabaseConfig {
+    pub url: String,
+    pub max_connections: u32,
+    /// How long a request waits for a free pooled connection
+    pub acquire_timeout_secs: u64,
+    /// How long a statement waits on a database locked by another connection
+    pub busy_timeout_secs: u64,
+    /// Create the database file, and its directory, when missing
+    pub create_if_missing: bool,
+    /// `delete`, `truncate`, `persist`, `memory`, `wal` or `off`; the file keeps its current
+    /// mode when unset
+    pub journal_mode: Option<String>,
+    /// Read-only copies of the database (LiteFS / Litestream replicas) for analytics and log
+    /// search, from the comma-separated `DATABASE_REPLICA_URLS`
+    pub replica_urls: Vec<String>,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            url: "sqlite:qa_chatbot.db".to_string(),
+            max_connections: 10,
+            acquire_timeout_secs: 30,
+            busy_timeout_secs: 5,
+            create_if_missing: true,
+            journal_mode: None,
+            replica_urls: Vec::new(),
+        }
+    }
+}
+
+impl DatabaseConfig {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+
+        Self {
+            url: std::env::var("DATABASE_URL").unwrap_or(defaults.url),
+            max_connections: std::env::var("DB_MAX_CONNECTIONS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|n| *n > 0)
+                .unwrap_or(defaults.max_connections),
+            acquire_timeout_secs: std::env::var("DB_ACQUIRE_TIMEOUT_SECS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(defaults.acquire_timeout_secs),
+            busy_timeout_secs: std::env::var("DB_BUSY_TIMEOUT_SECS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(defaults.busy_timeout_secs),
+            create_if_missing: std::env::var("DB_CREATE_IF_MISSING")
+                .map(|v| v != "false" && v != "0")
+                .unwrap_or(defaults.create_if_missing),
+            journal_mode: std::env::var("DB_JOURNAL_MODE")
+                .ok()
+                .filter(|s| !s.trim().is_empty()),
+            replica_urls: std::env::var("DATABASE_REPLICA_URLS")
+                .map(|urls| {
+                    urls.split(',')
+                        .map(str::trim)
+                        .filter(|url| !url.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                })
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Database file named by the URL; None for in-memory databases
+    pub fn file_path(&self) -> Option<PathBuf> {
+        let rest = self
+            .url
+            .strip_prefix("sqlite://")
+            .or_else(|| self.url.strip_prefix("sqlite:"))?;
+        let (path, query) = rest.split_once('?').unwrap_or((rest, ""));
+
+        if path.is_empty() || path == ":memory:" || query.split('&').any(|param| param == "mode=memory") {
+            return None;
+        }
+        Some(PathBuf::from(path))
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            port: 9000,
+            frontend_dist_dir: None,
+            static_max_age_seconds: 31_536_000, // 1 year
+            tls: None,
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn from_env() -> Result<Self> {
+        let defaults = Self::default();
+
+        Ok(Self {
+            port: std::env::var("SERVER_PORT")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(defaults.port),
+            frontend_dist_dir: std::env::var("FRONTEND_DIST_DIR")
+                .ok()
+                .filter(|s| !s.trim().is_empty())
+                .map(PathBuf::from),
+            static_max_age_seconds: std::env::var("STATIC_MAX_AGE_SECONDS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(defaults.static_max_age_seconds),
+            tls: TlsConfig::from_env()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_database_file_path() {
+        let config = |url: &str| DatabaseConfig {
+            url: url.to_string(),
+            ..Default::default()
+        };
+
+        assert_eq!(config("sqlite:qa_chatbot.db").file_path(), Some(PathBuf::from("qa_chatbot.db")));
+        assert_eq!(
+            config("sqlite:///var/lib/qa/db.sqlite?mode=rwc").file_path(),
+            Some(PathBuf::from("/var/lib/qa/db.sqlite"))
+        );
+        assert_eq!(config("sqlite::memory:").file_path(), None);
+        assert_eq!(config("sqlite:shared?mode=memory&cache=shared").file_path(), None);
+        assert_eq!(config("postgres://localhost/qa").file_path(), None);
+    }
+
+    #[test]
+    fn test_tls_paths_come_in_pairs() {
+        let path = |s: &str| Some(s.to_string());
+        assert!(tls_paths(None, None).unwrap().is_none());
+        assert_eq!(
+            tls_paths(path("cert.pem"), path("key.pem")).unwrap(),
+            Some((PathBuf::from("cert.pem"), PathBuf::from("key.pem")))
+        );
+        assert!(tls_paths(path("cert.pem"), None).unwrap_err().to_string().contains("TLS_KEY_PATH isn't"));
+        assert!(tls_paths(None, path("key.pem")).unwrap_err().to_string().contains("TLS_CERT_PATH isn't"));
+    }
+
+    #[test]
+    fn test_http_route_limits() {
+        let mut config = HttpLimitsConfig::default();
+        assert_eq!(config.for_route("/api/projects"), (Some(30), 2 * 1024 * 1024));
+        assert_eq!(config.for_route("/api/projects/:id/export"), (Some(300), 2 * 1024 * 1024));
+        assert_eq!(config.for_route("/api/projects/import"), (Some(300), 64 * 1024 * 1024));
+        // The log tail's longest wait fits in its timeout
+        let (tail_timeout, _) = config.for_route("/api/tickets/:id/logs/tail");
+        assert!(tail_timeout.unwrap() > crate::api_handlers::TAIL_MAX_WAIT_SECS);
+        assert_eq!(config.for_route("/api/tickets/:id"), (Some(30), 2 * 1024 * 1024));
+
+        config.routes.push(RouteLimit {
+            pattern: "/api/tickets/*".to_string(),
+            timeout_secs: Some(0),
+            max_body_bytes: None,
+        });
+        // 0 turns the timeout off, and the last match wins
+        assert_eq!(config.for_route("/api/tickets/:id/logs/tail"), (None, 2 * 1024 * 1024));
+        assert!(!config.routes[0].matches("/api/projects/:id/export/extra"));
+    }
+}
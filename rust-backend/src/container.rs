@@ -0,0 +1,161 @@
+use tokio::process::Command;
+use tracing::{debug, info};
+
+/// Where the project directory is mounted inside the container
+const WORKSPACE: &str = "/workspace";
+
+/// Runs the CLI agents inside a Docker container instead of on the host
+/// (`AGENT_CONTAINER_IMAGE`). The image must contain the agent CLIs; their
+/// `*_AGENT_PATH` is then a path inside the image.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContainerConfig {
+    pub docker_path: String,
+    pub image: String,
+    /// `--cpus`, e.g. `1.5`
+    pub cpus: Option<String>,
+    /// `--memory`, e.g. `2g`
+    pub memory: Option<String>,
+    /// `--network`; `none` also cuts the agent off from its model API
+    pub network: String,
+}
+
+impl ContainerConfig {
+    /// None unless `AGENT_CONTAINER_IMAGE` is set
+    pub fn from_env() -> Option<Self> {
+        let var = |name: &str| std::env::var(name).ok().map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+        Some(Self {
+            image: var("AGENT_CONTAINER_IMAGE")?,
+            docker_path: var("AGENT_CONTAINER_DOCKER_PATH").unwrap_or_else(|| "docker".to_string()),
+            cpus: var("AGENT_CONTAINER_CPUS"),
+            memory: var("AGENT_CONTAINER_MEMORY"),
+            network: var("AGENT_CONTAINER_NETWORK").unwrap_or_else(|| "bridge".to_string()),
+        })
+    }
+
+    /// `docker run` arguments up to and including the executable; the caller appends the
+    /// CLI's own arguments. `env` names are passed through from the docker client's
+    /// environment, so their values never show up in the process list.
+    pub fn run_args(&self, name: &str, project_dir: Option<&str>, writable: bool, env: &[&str], executable: &str) -> Vec<String> {
+        let mut args: Vec<String> = ["run", "--rm", "-i", "--name", name]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        args.extend(["--network".to_string(), self.network.clone()]);
+        if let Some(cpus) = &self.cpus {
+            args.extend(["--cpus".to_string(), cpus.clone()]);
+        }
+        if let Some(memory) = &self.memory {
+            args.extend(["--memory".to_string(), memory.clone()]);
+        }
+        args.extend(
+            ["--cap-drop", "ALL", "--security-opt", "no-new-privileges"]
+                .iter()
+                .map(|s| s.to_string()),
+        );
+        if let Some(dir) = project_dir {
+            let mode = if writable { "rw" } else { "ro" };
+            args.extend([
+                "-v".to_string(),
+                format!("{}:{}:{}", dir, WORKSPACE, mode),
+                "-w".to_string(),
+                WORKSPACE.to_string(),
+            ]);
+        }
+        for key in env {
+            args.extend(["-e".to_string(), key.to_string()]);
+        }
+        args.push(self.image.clone());
+        args.push(executable.to_string());
+        args
+    }
+}
+
+/// Removes the container when the run ends or is dropped (cancel, timeout): killing the
+/// `docker run` client alone leaves the container running
+#[derive(Debug)]
+pub struct ContainerGuard {
+    docker_path: String,
+    name: String,
+}
+
+impl Drop for ContainerGuard {
+    fn drop(&mut self) {
+        let docker_path = self.docker_path.clone();
+        let name = std::mem::take(&mut self.name);
+        // `--rm` already removed a container that exited; this is a no-op then
+        std::thread::spawn(move || {
+            let _ = std::process::Command::new(docker_path)
+                .args(["rm", "-f", &name])
+                .stdout(std::process::Stdio::null())
+                .stderr(std::process::Stdio::null())
+                .status();
+        });
+    }
+}
+
+/// The command to start `executable` with: directly, or inside a container when one is
+/// configured. Keep the guard alive for as long as the process runs.
+pub fn command(
+    config: Option<&ContainerConfig>,
+    executable: &str,
+    project_dir: Option<&str>,
+    writable: bool,
+    env: &[&str],
+) -> (Command, Option<ContainerGuard>) {
+    let Some(config) = config else {
+        return (Command::new(executable), None);
+    };
+
+    let name = format!("explain-source-{}", uuid::Uuid::new_v4());
+    let args = config.run_args(&name, project_dir, writable, env, executable);
+    info!(
+        "🐳 Chạy {} trong container {} ({}, mount {})",
+        executable,
+        name,
+        config.image,
+        if writable { "rw" } else { "ro" }
+    );
+    debug!("docker {}", args.join(" "));
+
+    let mut cmd = Command::new(&config.docker_path);
+    cmd.args(args);
+    let guard = ContainerGuard {
+        docker_path: config.docker_path.clone(),
+        name,
+    };
+    (cmd, Some(guard))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> ContainerConfig {
+        ContainerConfig {
+            docker_path: "docker".to_string(),
+            image: "explain-source/agents:latest".to_string(),
+            cpus: Some("2".to_string()),
+            memory: Some("4g".to_string()),
+            network: "agents".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_run_args() {
+        let args = config().run_args("es-1", Some("/srv/repo"), false, &["CLAUDE_API_KEY"], "claude");
+        let line = args.join(" ");
+
+        assert!(line.starts_with("run --rm -i --name es-1 --network agents --cpus 2 --memory 4g"));
+        assert!(line.contains("-v /srv/repo:/workspace:ro -w /workspace"));
+        assert!(line.ends_with("-e CLAUDE_API_KEY explain-source/agents:latest claude"));
+
+        let writable = config().run_args("es-2", Some("/srv/repo"), true, &[], "claude").join(" ");
+        assert!(writable.contains("/srv/repo:/workspace:rw"));
+
+        let mut unlimited = config();
+        unlimited.cpus = None;
+        unlimited.memory = None;
+        let line = unlimited.run_args("es-3", None, false, &[], "gemini").join(" ");
+        assert!(!line.contains("--cpus") && !line.contains("--memory") && !line.contains("-v "));
+    }
+}
@@ -0,0 +1,308 @@
+use crate::code_agent::CodeAnalysisRequest;
+use crate::database::{ContextFileRecord, Database};
+use crate::project_files::{self, PathPolicy};
+use anyhow::Result;
+use chrono::Utc;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashSet};
+use std::path::Path;
+use std::sync::OnceLock;
+use tracing::error;
+
+/// Files larger than this are matched by name only
+const MAX_SYMBOL_SCAN_BYTES: usize = 200_000;
+
+/// Words that say nothing about which file is meant
+const STOPWORDS: &[&str] = &[
+    "the", "and", "for", "with", "from", "that", "this", "file", "files", "code", "how", "what",
+    "where", "does", "about", "của", "các", "những", "trong",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResolverConfig {
+    /// Files kept per resolution; 0 turns resolution off
+    pub max_files: usize,
+}
+
+impl Default for ResolverConfig {
+    fn default() -> Self {
+        Self { max_files: 10 }
+    }
+}
+
+impl ResolverConfig {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            max_files: std::env::var("CONTEXT_RESOLVE_MAX_FILES")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(defaults.max_files),
+        }
+    }
+}
+
+/// A project file matching a fuzzy code context, with why it matched
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResolvedFile {
+    /// Root-relative path
+    pub path: String,
+    pub score: i64,
+    /// e.g. `filename: login`, `symbol: LoginController`
+    pub reasons: Vec<String>,
+}
+
+impl From<ContextFileRecord> for ResolvedFile {
+    fn from(record: ContextFileRecord) -> Self {
+        Self {
+            path: record.file_path,
+            score: record.score,
+            reasons: record.reasons,
+        }
+    }
+}
+
+/// Lowercased words of the context worth matching
+fn keywords(text: &str) -> Vec<String> {
+    let mut seen = HashSet::new();
+    text.split(|c: char| !c.is_alphanumeric())
+        .map(str::to_lowercase)
+        .filter(|w| w.chars().count() >= 3 && !STOPWORDS.contains(&w.as_str()))
+        .filter(|w| seen.insert(w.clone()))
+        .collect()
+}
+
+/// Names defined in a source file: functions, classes, structs, types and top-level bindings
+fn symbols(content: &str) -> impl Iterator<Item = &str> {
+    static DEFINITION: OnceLock<Regex> = OnceLock::new();
+    let definition = DEFINITION.get_or_init(|| {
+        Regex::new(
+            r"(?m)^\s*(?:export\s+)?(?:default\s+)?(?:pub(?:\([^)]*\))?\s+)?(?:async\s+)?(?:abstract\s+)?(?:fn|function|class|struct|interface|trait|enum|type|def|func|const|let|var|object)\s+([A-Za-z_][A-Za-z0-9_]*)",
+        )
+        .unwrap()
+    });
+    definition
+        .captures_iter(content)
+        .filter_map(|caps| caps.get(1))
+        .map(|m| m.as_str())
+}
+
+/// Score one file against the keywords: 3 per keyword in its file name, 1 per keyword only in
+/// its directories, 2 per keyword found in a symbol it defines, plus 2 when every keyword matched
+fn score_file(path: &str, content: Option<&str>, keywords: &[String]) -> Option<ResolvedFile> {
+    let lower = path.to_lowercase();
+    let (dirs, name) = lower.rsplit_once('/').unwrap_or(("", lower.as_str()));
+    let stem = name.split('.').next().unwrap_or(name);
+    let squashed_stem: String = stem.chars().filter(|c| c.is_alphanumeric()).collect();
+
+    let mut score = 0;
+    let mut reasons = Vec::new();
+    let mut matched = BTreeSet::new();
+    for keyword in keywords {
+        if squashed_stem.contains(keyword.as_str()) {
+            score += 3;
+            reasons.push(format!("filename: {}", keyword));
+            matched.insert(keyword);
+        } else if dirs.contains(keyword.as_str()) {
+            score += 1;
+            reasons.push(format!("directory: {}", keyword));
+            matched.insert(keyword);
+        }
+    }
+
+    if let Some(content) = content {
+        let mut symbol_hits = BTreeSet::new();
+        for symbol in symbols(content) {
+            let lower = symbol.to_lowercase().replace('_', "");
+            for keyword in keywords {
+                if lower.contains(keyword.as_str()) && symbol_hits.insert(keyword) {
+                    score += 2;
+                    reasons.push(format!("symbol: {}", symbol));
+                    matched.insert(keyword);
+                }
+            }
+        }
+    }
+
+    if score == 0 {
+        return None;
+    }
+    if keywords.len() > 1 && matched.len() == keywords.len() {
+        score += 2;
+    }
+    Some(ResolvedFile {
+        path: path.to_string(),
+        score,
+        reasons,
+    })
+}
+
+/// Best matching project files for a fuzzy code context, highest score first
+pub fn resolve(root: &Path, code_context: &str, max_files: usize, policy: &PathPolicy) -> Result<Vec<ResolvedFile>> {
+    let keywords = keywords(code_context);
+    if keywords.is_empty() || max_files == 0 {
+        return Ok(Vec::new());
+    }
+
+    let mut matches: Vec<ResolvedFile> = project_files::list_project_files(root, "", usize::MAX, policy)?
+        .into_iter()
+        .filter_map(|path| {
+            let content = project_files::read_project_file(root, &path, MAX_SYMBOL_SCAN_BYTES, policy).ok();
+            score_file(&path, content.as_deref(), &keywords)
+        })
+        .collect();
+    matches.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| a.path.cmp(&b.path)));
+    matches.truncate(max_files);
+    Ok(matches)
+}
+
+/// Whether some token of the context already names a project path
+pub fn names_paths(root: &Path, code_context: &str) -> bool {
+    code_context
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|token| !token.is_empty())
+        .any(|token| project_files::resolve_in_project(root, token).is_ok())
+}
+
+pub async fn store(database: &Database, ticket_id: &str, code_context: &str, files: &[ResolvedFile]) -> Result<()> {
+    let resolved_at = Utc::now().to_rfc3339();
+    let records: Vec<ContextFileRecord> = files
+        .iter()
+        .enumerate()
+        .map(|(position, file)| ContextFileRecord {
+            ticket_id: ticket_id.to_string(),
+            file_path: file.path.clone(),
+            position: position as i64,
+            score: file.score,
+            reasons: file.reasons.clone(),
+            resolved_for: code_context.trim().to_string(),
+            resolved_at: resolved_at.clone(),
+        })
+        .collect();
+    database.set_ticket_context_files(ticket_id, &records).await
+}
+
+/// Give the agent concrete paths for a fuzzy code context: the ticket's stored selection when it
+/// was made for this context, else a fresh resolution that is stored for next time. Returns a
+/// notice for the ticket's log when paths were added.
+pub async fn resolve_request(
+    database: &Database,
+    request: &mut CodeAnalysisRequest,
+    policy: &PathPolicy,
+    config: ResolverConfig,
+) -> Option<String> {
+    let code_context = request.code_context.trim().to_string();
+    if config.max_files == 0 || code_context.is_empty() {
+        return None;
+    }
+    let root = match database.get_project(&request.project_id).await {
+        Ok(Some(project)) => project.directory_path,
+        Ok(None) => return None,
+        Err(e) => {
+            error!("Failed to load project {}: {}", request.project_id, e);
+            return None;
+        }
+    };
+
+    let stored = match database.get_ticket_context_files(&request.ticket_id).await {
+        Ok(files) => files,
+        Err(e) => {
+            error!("Failed to load context files of ticket {}: {}", request.ticket_id, e);
+            Vec::new()
+        }
+    };
+    let files: Vec<ResolvedFile> = if stored.first().is_some_and(|f| f.resolved_for == code_context) {
+        stored.into_iter().map(ResolvedFile::from).collect()
+    } else {
+        let (context, policy) = (code_context.clone(), policy.clone());
+        let resolved = tokio::task::spawn_blocking(move || {
+            let root = Path::new(&root);
+            if names_paths(root, &context) {
+                return Ok(Vec::new());
+            }
+            resolve(root, &context, config.max_files, &policy)
+        })
+        .await;
+        let files = match resolved {
+            Ok(Ok(files)) => files,
+            Ok(Err(e)) => {
+                error!("Failed to resolve code context of ticket {}: {}", request.ticket_id, e);
+                return None;
+            }
+            Err(e) => {
+                error!("Code context resolution panicked: {}", e);
+                return None;
+            }
+        };
+        if !files.is_empty() {
+            if let Err(e) = store(database, &request.ticket_id, &code_context, &files).await {
+                error!("Failed to store context files of ticket {}: {}", request.ticket_id, e);
+            }
+        }
+        files
+    };
+
+    // Paths excluded since the selection was made never reach the prompt
+    let paths: Vec<&str> = files
+        .iter()
+        .map(|f| f.path.as_str())
+        .filter(|path| policy.allows(path))
+        .collect();
+    if paths.is_empty() {
+        return None;
+    }
+    request.code_context = format!("{}\n\nResolved files:\n{}", code_context, paths.join("\n"));
+    Some(format!("🧭 Code context \"{}\" → {}", code_context, paths.join(", ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_project() -> std::path::PathBuf {
+        let root = std::env::temp_dir().join(format!("context-resolver-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(root.join("src/auth")).unwrap();
+        std::fs::create_dir_all(root.join("src/billing")).unwrap();
+        std::fs::write(
+            root.join("src/auth/LoginController.ts"),
+            "export class LoginController {\n  login() {}\n}\n",
+        )
+        .unwrap();
+        std::fs::write(
+            root.join("src/auth/session.rs"),
+            "pub fn start_login_session() {}\n",
+        )
+        .unwrap();
+        std::fs::write(root.join("src/billing/invoice.py"), "def create_invoice():\n    pass\n").unwrap();
+        root
+    }
+
+    #[test]
+    fn test_keywords_drop_stopwords() {
+        assert_eq!(keywords("the Login controller, and the login"), vec!["login", "controller"]);
+    }
+
+    #[test]
+    fn test_symbols() {
+        let source = "pub(crate) async fn run_job() {}\nexport default class Foo {}\nconst bar = 1\ndef baz():\n";
+        assert_eq!(symbols(source).collect::<Vec<_>>(), vec!["run_job", "Foo", "bar", "baz"]);
+    }
+
+    #[test]
+    fn test_resolve_ranks_filename_and_symbol_matches() {
+        let root = temp_project();
+        let files = resolve(&root, "the login controller", 10, &PathPolicy::default()).unwrap();
+        let paths: Vec<_> = files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(paths, vec!["src/auth/LoginController.ts", "src/auth/session.rs"]);
+        assert!(files[0].reasons.contains(&"filename: controller".to_string()));
+        assert!(files[1].reasons.contains(&"symbol: start_login_session".to_string()));
+
+        assert!(resolve(&root, "invoices", 10, &PathPolicy::default()).unwrap().is_empty());
+        assert_eq!(resolve(&root, "invoice", 10, &PathPolicy::default()).unwrap()[0].path, "src/billing/invoice.py");
+
+        assert!(names_paths(&root, "src/auth"));
+        assert!(!names_paths(&root, "the login controller"));
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
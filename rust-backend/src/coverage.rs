@@ -0,0 +1,383 @@
+use crate::database::{Database, ProjectRecord, TicketFileRecord};
+use crate::message_store::StructuredLogEntry;
+use crate::project_files::{self, PathPolicy};
+use crate::stale::{self, FileChange};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::{broadcast, oneshot};
+use tracing::{debug, error, info};
+
+/// Tool argument names agents use for file paths (Claude Read, Gemini read_file, our API tools, ...)
+const PATH_KEYS: &[&str] = &["file_path", "filePath", "absolute_path", "path", "notebook_path", "target_file"];
+const PATH_LIST_KEYS: &[&str] = &["paths", "file_paths", "absolute_paths"];
+
+/// Upper bound on project files counted for per-directory totals
+const MAX_COVERAGE_FILES: usize = 10_000;
+
+/// Raw file path arguments of a tool call, as the agent passed them (relative or absolute)
+pub fn touched_paths(entry: &StructuredLogEntry) -> Vec<String> {
+    let mut paths: Vec<String> = entry.metadata.get("file_path").cloned().into_iter().collect();
+
+    let Ok(json) = serde_json::from_str::<Value>(&entry.content) else {
+        return paths;
+    };
+    match json.get("type").and_then(|v| v.as_str()) {
+        Some("tool_use") | Some("tool_call") => collect_paths(&json, &mut paths),
+        // Claude stream-json: {"type":"assistant","message":{"content":[{"type":"tool_use","input":{...}}]}}
+        Some("assistant") => {
+            let blocks = json.pointer("/message/content").and_then(|v| v.as_array());
+            for block in blocks.into_iter().flatten() {
+                if block.get("type").and_then(|v| v.as_str()) == Some("tool_use") {
+                    if let Some(input) = block.get("input") {
+                        collect_paths(input, &mut paths);
+                    }
+                }
+            }
+        }
+        _ => {}
+    }
+    paths
+}
+
+/// File path arguments anywhere in a tool's input
+pub fn collect_paths(value: &Value, paths: &mut Vec<String>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                match value {
+                    Value::String(path) if PATH_KEYS.contains(&key.as_str()) => paths.push(path.clone()),
+                    Value::Array(items) if PATH_LIST_KEYS.contains(&key.as_str()) => {
+                        paths.extend(items.iter().filter_map(|item| item.as_str()).map(str::to_string));
+                    }
+                    _ => collect_paths(value, paths),
+                }
+            }
+        }
+        Value::Array(items) => items.iter().for_each(|item| collect_paths(item, paths)),
+        _ => {}
+    }
+}
+
+/// Root-relative path of an existing file inside the (canonical) project root. Directories
+/// (list_files arguments) and paths outside the project are dropped.
+pub fn project_relative_file(root: &Path, raw: &str) -> Option<String> {
+    let raw = raw.trim();
+    let candidate = Path::new(raw);
+    let resolved = if candidate.is_absolute() {
+        candidate.canonicalize().ok()?
+    } else {
+        project_files::resolve_in_project(root, raw).ok()?
+    };
+    if !resolved.is_file() {
+        return None;
+    }
+
+    let relative = resolved.strip_prefix(root).ok()?;
+    Some(relative.to_string_lossy().replace('\\', "/"))
+}
+
+/// Collect the files a run touches and record them in `ticket_files` when it ends
+/// (completion signal or abort, i.e. `done` firing or being dropped)
+pub async fn track_files(
+    ticket_id: String,
+    run_id: String,
+    mut log_rx: broadcast::Receiver<StructuredLogEntry>,
+    database: Arc<Database>,
+    mut done: oneshot::Receiver<()>,
+) {
+    let started_at = Utc::now().to_rfc3339();
+    let mut raw_paths = HashSet::new();
+
+    loop {
+        tokio::select! {
+            received = log_rx.recv() => match received {
+                Ok(entry) if entry.ticket_id == ticket_id => raw_paths.extend(touched_paths(&entry)),
+                Ok(_) => {}
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    debug!("File tracker for ticket {} skipped {} entries", ticket_id, skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            _ = &mut done => {
+                while let Ok(entry) = log_rx.try_recv() {
+                    if entry.ticket_id == ticket_id {
+                        raw_paths.extend(touched_paths(&entry));
+                    }
+                }
+                break;
+            }
+        }
+    }
+
+    if raw_paths.is_empty() {
+        return;
+    }
+    if let Err(e) = record_files(&database, &ticket_id, &run_id, raw_paths, &started_at).await {
+        error!("Failed to record files for ticket {}: {}", ticket_id, e);
+    }
+}
+
+async fn record_files(
+    database: &Database,
+    ticket_id: &str,
+    run_id: &str,
+    raw_paths: HashSet<String>,
+    analyzed_at: &str,
+) -> Result<()> {
+    let Some(ticket) = database.get_ticket(ticket_id).await? else {
+        return Ok(());
+    };
+    let Some(project) = database.get_project(&ticket.project_id).await? else {
+        return Ok(());
+    };
+
+    let root = PathBuf::from(project.directory_path);
+    let files: Vec<String> = tokio::task::spawn_blocking(move || {
+        let Ok(root) = root.canonicalize() else {
+            return Vec::new();
+        };
+        raw_paths
+            .iter()
+            .filter_map(|raw| project_relative_file(&root, raw))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    })
+    .await?;
+
+    if files.is_empty() {
+        return Ok(());
+    }
+    database
+        .record_ticket_files(ticket_id, Some(run_id), &files, analyzed_at)
+        .await?;
+    info!("🗂️ Đã ghi nhận {} file cho ticket {}", files.len(), ticket_id);
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileCoverage {
+    pub path: String,
+    /// Tickets whose analysis read this file
+    pub tickets: Vec<String>,
+    /// Tickets analyzed before the file's last change (or before it was deleted)
+    pub stale_tickets: Vec<String>,
+    pub last_analyzed_at: String,
+    /// Last change per `stale::file_changes`; unset when git shows none since the analyses
+    pub changed_at: Option<String>,
+    pub exists: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DirectoryCoverage {
+    /// Root-relative directory, "." for the project root
+    pub directory: String,
+    pub total_files: usize,
+    pub analyzed_files: usize,
+    pub stale_files: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProjectCoverage {
+    pub project_id: String,
+    pub total_files: usize,
+    pub analyzed_files: usize,
+    pub stale_files: usize,
+    /// Tickets with at least one stale file
+    pub stale_tickets: Vec<String>,
+    pub directories: Vec<DirectoryCoverage>,
+    pub files: Vec<FileCoverage>,
+}
+
+/// Coverage of a project's source tree by ticket analyses, as served at
+/// `GET /api/projects/:id/coverage`. Files excluded by the project's path rules are left out.
+pub async fn project_coverage(db: &Database, project: &ProjectRecord) -> Result<ProjectCoverage> {
+    let policy = db.get_project_path_rules(&project.id).await?.compile()?;
+    let records = db
+        .list_ticket_files_by_project(&project.id)
+        .await?
+        .into_iter()
+        .filter(|record| policy.allows(&record.file_path))
+        .collect();
+    let root = PathBuf::from(&project.directory_path);
+    let project_id = project.id.clone();
+    Ok(tokio::task::spawn_blocking(move || build_coverage(&root, project_id, records, &policy)).await?)
+}
+
+fn build_coverage(
+    root: &Path,
+    project_id: String,
+    records: Vec<TicketFileRecord>,
+    policy: &PathPolicy,
+) -> ProjectCoverage {
+    let project_files = project_files::list_project_files(root, "", MAX_COVERAGE_FILES, policy).unwrap_or_default();
+
+    let mut by_file: BTreeMap<String, Vec<TicketFileRecord>> = BTreeMap::new();
+    for record in records {
+        by_file.entry(record.file_path.clone()).or_default().push(record);
+    }
+
+    let paths: Vec<String> = by_file.keys().cloned().collect();
+    let since = by_file
+        .values()
+        .flatten()
+        .filter_map(|r| DateTime::parse_from_rfc3339(&r.analyzed_at).ok())
+        .min()
+        .map(|t| t.with_timezone(&Utc));
+    let changes = stale::file_changes(root, &paths, since);
+
+    let files: Vec<FileCoverage> = by_file
+        .into_iter()
+        .map(|(path, records)| {
+            let change = changes.get(&path).copied().unwrap_or(FileChange {
+                exists: false,
+                changed_at: None,
+            });
+            let stale_tickets = records
+                .iter()
+                .filter(|r| change.is_newer_than(&r.analyzed_at))
+                .map(|r| r.ticket_id.clone())
+                .collect();
+
+            FileCoverage {
+                last_analyzed_at: records.iter().map(|r| r.analyzed_at.clone()).max().unwrap_or_default(),
+                tickets: records.into_iter().map(|r| r.ticket_id).collect(),
+                stale_tickets,
+                changed_at: change.changed_at.map(|t| t.to_rfc3339()),
+                exists: change.exists,
+                path,
+            }
+        })
+        .collect();
+
+    let analyzed: HashSet<&str> = files.iter().map(|f| f.path.as_str()).collect();
+    let mut directories: BTreeMap<String, DirectoryCoverage> = BTreeMap::new();
+    for path in project_files.iter().filter(|p| !analyzed.contains(p.as_str())) {
+        directory_entry(&mut directories, path).total_files += 1;
+    }
+    for file in &files {
+        let dir = directory_entry(&mut directories, &file.path);
+        if file.exists {
+            dir.total_files += 1;
+        }
+        dir.analyzed_files += 1;
+        if !file.stale_tickets.is_empty() {
+            dir.stale_files += 1;
+        }
+    }
+
+    let stale_tickets: BTreeSet<String> = files.iter().flat_map(|f| f.stale_tickets.iter().cloned()).collect();
+    let directories: Vec<DirectoryCoverage> = directories.into_values().collect();
+
+    ProjectCoverage {
+        project_id,
+        total_files: directories.iter().map(|d| d.total_files).sum(),
+        analyzed_files: files.len(),
+        stale_files: files.iter().filter(|f| !f.stale_tickets.is_empty()).count(),
+        stale_tickets: stale_tickets.into_iter().collect(),
+        directories,
+        files,
+    }
+}
+
+fn directory_entry<'a>(directories: &'a mut BTreeMap<String, DirectoryCoverage>, path: &str) -> &'a mut DirectoryCoverage {
+    let name = match path.rsplit_once('/') {
+        Some((directory, _)) => directory.to_string(),
+        None => ".".to_string(),
+    };
+    directories.entry(name.clone()).or_insert_with(|| DirectoryCoverage {
+        directory: name,
+        total_files: 0,
+        analyzed_files: 0,
+        stale_files: 0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message_store::LogMessageType;
+    use std::collections::HashMap;
+
+    fn entry(content: &str) -> StructuredLogEntry {
+        StructuredLogEntry {
+            id: "log-1".to_string(),
+            ticket_id: "t-1".to_string(),
+            message_type: LogMessageType::ToolUse,
+            content: content.to_string(),
+            raw_log: None,
+            metadata: HashMap::new(),
+            timestamp: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_touched_paths_from_tool_calls() {
+        let gemini = entry(r#"{"type":"tool_use","tool_name":"read_file","parameters":{"absolute_path":"/repo/src/pay.rs"}}"#);
+        assert_eq!(touched_paths(&gemini), vec!["/repo/src/pay.rs"]);
+
+        let claude = entry(
+            r#"{"type":"assistant","message":{"content":[{"type":"text","text":"path: nope"},{"type":"tool_use","name":"Read","input":{"file_path":"src/a.rs"}}]}}"#,
+        );
+        assert_eq!(touched_paths(&claude), vec!["src/a.rs"]);
+
+        let many = entry(r#"{"type":"tool_call","tool_call":{"readManyFiles":{"args":{"paths":["a.rs","b.rs"]}}}}"#);
+        assert_eq!(touched_paths(&many), vec!["a.rs", "b.rs"]);
+
+        let mut plain = entry("Reading file: src/auth/login.js");
+        plain.metadata.insert("file_path".to_string(), "src/auth/login.js".to_string());
+        assert_eq!(touched_paths(&plain), vec!["src/auth/login.js"]);
+    }
+
+    #[test]
+    fn test_build_coverage_marks_stale_files() {
+        let root = std::env::temp_dir().join(format!("coverage-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(root.join("src/api")).unwrap();
+        std::fs::write(root.join("main.rs"), "fn main() {}").unwrap();
+        std::fs::write(root.join("src/api/pay.rs"), "// pay").unwrap();
+        std::fs::write(root.join("src/api/refund.rs"), "// refund").unwrap();
+        let root = root.canonicalize().unwrap();
+
+        assert_eq!(project_relative_file(&root, "src/api/pay.rs").as_deref(), Some("src/api/pay.rs"));
+        let absolute = root.join("main.rs");
+        assert_eq!(project_relative_file(&root, absolute.to_str().unwrap()).as_deref(), Some("main.rs"));
+        assert_eq!(project_relative_file(&root, "src/api"), None);
+        assert_eq!(project_relative_file(&root, "../etc/passwd"), None);
+
+        let record = |ticket: &str, path: &str, analyzed_at: DateTime<Utc>| TicketFileRecord {
+            ticket_id: ticket.to_string(),
+            file_path: path.to_string(),
+            run_id: None,
+            analyzed_at: analyzed_at.to_rfc3339(),
+        };
+        let past = Utc::now() - chrono::Duration::days(1);
+        let future = Utc::now() + chrono::Duration::days(1);
+        let coverage = build_coverage(
+            &root,
+            "p-1".to_string(),
+            vec![
+                record("old", "src/api/pay.rs", past),
+                record("new", "src/api/pay.rs", future),
+                record("new", "main.rs", future),
+                record("gone", "src/removed.rs", future),
+            ],
+            &PathPolicy::default(),
+        );
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(coverage.total_files, 3);
+        assert_eq!(coverage.analyzed_files, 3);
+        assert_eq!(coverage.stale_files, 2);
+        assert_eq!(coverage.stale_tickets, vec!["gone", "old"]);
+
+        let api = coverage.directories.iter().find(|d| d.directory == "src/api").unwrap();
+        assert_eq!((api.total_files, api.analyzed_files, api.stale_files), (2, 1, 1));
+        let top = coverage.directories.iter().find(|d| d.directory == ".").unwrap();
+        assert_eq!((top.total_files, top.analyzed_files, top.stale_files), (1, 1, 0));
+    }
+}
@@ -0,0 +1,420 @@
+use crate::database::{CustomFieldRecord, Database, TicketRecord};
+use chrono::{NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+
+const MAX_NAME_CHARS: usize = 64;
+const MAX_TEXT_CHARS: usize = 2000;
+
+/// Prefix of ticket listing query parameters filtering by a custom field (`cf.severity=high`)
+pub const FILTER_PREFIX: &str = "cf.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FieldType {
+    Text,
+    Number,
+    Boolean,
+    /// `YYYY-MM-DD`
+    Date,
+    /// One of the field's options
+    Select,
+    /// A list of the field's options
+    MultiSelect,
+}
+
+impl FieldType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FieldType::Text => "text",
+            FieldType::Number => "number",
+            FieldType::Boolean => "boolean",
+            FieldType::Date => "date",
+            FieldType::Select => "select",
+            FieldType::MultiSelect => "multi-select",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        serde_json::from_value(Value::String(value.to_string())).ok()
+    }
+
+    fn has_options(&self) -> bool {
+        matches!(self, FieldType::Select | FieldType::MultiSelect)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FieldError {
+    #[error("{0}")]
+    InvalidDefinition(String),
+
+    #[error("A field named {0} already exists")]
+    Duplicate(String),
+
+    #[error("Options still used by tickets: {}", .0.join(", "))]
+    OptionsInUse(Vec<String>),
+
+    #[error("Unknown custom field {0}")]
+    UnknownField(String),
+
+    #[error("Invalid value for {0}: {1}")]
+    InvalidValue(String, String),
+
+    #[error(transparent)]
+    Database(#[from] anyhow::Error),
+}
+
+/// Custom field values of a ticket by field name
+pub type FieldValues = BTreeMap<String, Value>;
+
+/// A ticket as returned by listings, with its custom field values
+#[derive(Debug, Clone, Serialize)]
+pub struct TicketWithFields {
+    #[serde(flatten)]
+    pub ticket: TicketRecord,
+    pub custom_fields: FieldValues,
+    /// When the ticket entered its status (see `time_tracking::flag_overdue`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status_since: Option<String>,
+    /// Past the SLA of its status
+    pub overdue: bool,
+}
+
+/// Trimmed name and options, checked against the field type
+fn clean_definition(name: &str, field_type: FieldType, options: Vec<String>) -> Result<(String, Vec<String>), FieldError> {
+    let name = name.trim().to_string();
+    if name.is_empty() || name.chars().count() > MAX_NAME_CHARS {
+        return Err(FieldError::InvalidDefinition(format!(
+            "Field names must have 1 to {} characters",
+            MAX_NAME_CHARS
+        )));
+    }
+
+    let mut cleaned: Vec<String> = Vec::new();
+    for option in options.iter().map(|o| o.trim()).filter(|o| !o.is_empty()) {
+        if !cleaned.iter().any(|c| c == option) {
+            cleaned.push(option.to_string());
+        }
+    }
+    match (field_type.has_options(), cleaned.is_empty()) {
+        (true, true) => Err(FieldError::InvalidDefinition(format!(
+            "A {} field needs at least one option",
+            field_type.as_str()
+        ))),
+        (false, false) => Err(FieldError::InvalidDefinition(format!(
+            "A {} field takes no options",
+            field_type.as_str()
+        ))),
+        _ => Ok((name, cleaned)),
+    }
+}
+
+/// Check a value written to `field`, returning it as stored
+fn validate_value(field: &CustomFieldRecord, field_type: FieldType, value: Value) -> Result<Value, FieldError> {
+    let invalid = |reason: String| FieldError::InvalidValue(field.name.clone(), reason);
+    let option = |value: &Value| match value.as_str() {
+        Some(s) if field.options.iter().any(|o| o == s) => Ok(s.to_string()),
+        _ => Err(invalid(format!("expected one of {}", field.options.join(", ")))),
+    };
+
+    match field_type {
+        FieldType::Text => match value.as_str() {
+            Some(s) if s.chars().count() <= MAX_TEXT_CHARS => Ok(Value::String(s.to_string())),
+            Some(_) => Err(invalid(format!("at most {} characters", MAX_TEXT_CHARS))),
+            None => Err(invalid("expected a string".to_string())),
+        },
+        FieldType::Number if value.is_number() => Ok(value),
+        FieldType::Number => Err(invalid("expected a number".to_string())),
+        FieldType::Boolean if value.is_boolean() => Ok(value),
+        FieldType::Boolean => Err(invalid("expected true or false".to_string())),
+        FieldType::Date => match value.as_str().map(|s| NaiveDate::parse_from_str(s, "%Y-%m-%d")) {
+            Some(Ok(date)) => Ok(Value::String(date.format("%Y-%m-%d").to_string())),
+            _ => Err(invalid("expected a YYYY-MM-DD date".to_string())),
+        },
+        FieldType::Select => option(&value).map(Value::String),
+        FieldType::MultiSelect => {
+            let items = value.as_array().ok_or_else(|| invalid("expected a list of options".to_string()))?;
+            let mut selected: Vec<String> = Vec::new();
+            for item in items {
+                let item = option(item)?;
+                if !selected.contains(&item) {
+                    selected.push(item);
+                }
+            }
+            Ok(Value::from(selected))
+        }
+    }
+}
+
+fn field_type(field: &CustomFieldRecord) -> Result<FieldType, FieldError> {
+    FieldType::parse(&field.field_type)
+        .ok_or_else(|| FieldError::Database(anyhow::anyhow!("Unknown field type {}", field.field_type)))
+}
+
+pub async fn create_field(
+    database: &Database,
+    project_id: &str,
+    name: &str,
+    field_type: FieldType,
+    options: Vec<String>,
+) -> Result<CustomFieldRecord, FieldError> {
+    let (name, options) = clean_definition(name, field_type, options)?;
+    let existing = database.list_custom_fields(project_id).await?;
+    if existing.iter().any(|f| f.name.eq_ignore_ascii_case(&name)) {
+        return Err(FieldError::Duplicate(name));
+    }
+
+    let field = CustomFieldRecord {
+        id: uuid::Uuid::new_v4().to_string(),
+        project_id: project_id.to_string(),
+        name,
+        field_type: field_type.as_str().to_string(),
+        options,
+        created_at: Utc::now().to_rfc3339(),
+    };
+    database.create_custom_field(&field).await?;
+    Ok(field)
+}
+
+/// Rename a field or change its options; its type is fixed. Options still set on tickets can't be
+/// removed.
+pub async fn update_field(
+    database: &Database,
+    field: &CustomFieldRecord,
+    name: &str,
+    options: Vec<String>,
+) -> Result<CustomFieldRecord, FieldError> {
+    let (name, options) = clean_definition(name, field_type(field)?, options)?;
+    let existing = database.list_custom_fields(&field.project_id).await?;
+    if existing.iter().any(|f| f.id != field.id && f.name.eq_ignore_ascii_case(&name)) {
+        return Err(FieldError::Duplicate(name));
+    }
+
+    let mut in_use: Vec<String> = Vec::new();
+    for stored in database.list_field_values(&field.id).await? {
+        let value: Value = serde_json::from_str(&stored).map_err(anyhow::Error::from)?;
+        let used = match &value {
+            Value::Array(items) => items.iter().filter_map(Value::as_str).collect(),
+            value => value.as_str().into_iter().collect::<Vec<_>>(),
+        };
+        for option in used {
+            if !options.iter().any(|o| o == option) && !in_use.iter().any(|u| u == option) {
+                in_use.push(option.to_string());
+            }
+        }
+    }
+    if !in_use.is_empty() {
+        return Err(FieldError::OptionsInUse(in_use));
+    }
+
+    database.update_custom_field(&field.id, &name, &options).await?;
+    Ok(CustomFieldRecord {
+        name,
+        options,
+        ..field.clone()
+    })
+}
+
+/// Custom field values of each given ticket; tickets without any get an empty map
+pub async fn values_by_ticket(database: &Database, ticket_ids: &[String]) -> anyhow::Result<HashMap<String, FieldValues>> {
+    let mut by_ticket: HashMap<String, FieldValues> = HashMap::new();
+    for (ticket_id, name, value) in database.list_ticket_field_values(ticket_ids).await? {
+        by_ticket
+            .entry(ticket_id)
+            .or_default()
+            .insert(name, serde_json::from_str(&value)?);
+    }
+    Ok(by_ticket)
+}
+
+pub async fn ticket_values(database: &Database, ticket_id: &str) -> anyhow::Result<FieldValues> {
+    let mut by_ticket = values_by_ticket(database, &[ticket_id.to_string()]).await?;
+    Ok(by_ticket.remove(ticket_id).unwrap_or_default())
+}
+
+/// Set a ticket's values by field name after checking them against the project's definitions;
+/// null clears a value. Nothing is written when any value is invalid.
+pub async fn set_values(database: &Database, ticket: &TicketRecord, updates: FieldValues) -> Result<FieldValues, FieldError> {
+    let fields = database.list_custom_fields(&ticket.project_id).await?;
+    let mut writes: Vec<(String, Option<String>)> = Vec::new();
+    for (name, value) in updates {
+        let field = fields
+            .iter()
+            .find(|f| f.name == name)
+            .ok_or_else(|| FieldError::UnknownField(name.clone()))?;
+        let stored = match value {
+            Value::Null => None,
+            value => {
+                let value = validate_value(field, field_type(field)?, value)?;
+                Some(value.to_string())
+            }
+        };
+        writes.push((field.id.clone(), stored));
+    }
+
+    database.set_ticket_field_values(&ticket.id, &writes).await?;
+    Ok(ticket_values(database, &ticket.id).await?)
+}
+
+/// Whether a stored value matches a filter given as text: equal, or containing it for lists.
+/// Text compares case-insensitively.
+fn matches(value: &Value, wanted: &str) -> bool {
+    match value {
+        Value::String(s) => s.eq_ignore_ascii_case(wanted),
+        Value::Number(n) => wanted.parse::<f64>().ok() == n.as_f64(),
+        Value::Bool(b) => wanted.parse::<bool>().ok() == Some(*b),
+        Value::Array(items) => items.iter().any(|item| matches(item, wanted)),
+        _ => false,
+    }
+}
+
+/// Attach custom field values to tickets, keeping only those matching every `(name, value)`
+/// filter; `none` matches tickets without a value
+pub async fn with_fields(
+    database: &Database,
+    tickets: Vec<TicketRecord>,
+    filters: &[(String, String)],
+) -> anyhow::Result<Vec<TicketWithFields>> {
+    let ids: Vec<String> = tickets.iter().map(|t| t.id.clone()).collect();
+    let mut by_ticket = values_by_ticket(database, &ids).await?;
+
+    Ok(tickets
+        .into_iter()
+        .map(|ticket| {
+            let custom_fields = by_ticket.remove(&ticket.id).unwrap_or_default();
+            TicketWithFields {
+                ticket,
+                custom_fields,
+                status_since: None,
+                overdue: false,
+            }
+        })
+        .filter(|t| {
+            filters.iter().all(|(name, wanted)| match t.custom_fields.get(name) {
+                Some(value) => matches(value, wanted),
+                None => wanted == "none",
+            })
+        })
+        .collect())
+}
+
+/// `cf.<name>=<value>` pairs of a listing's query string
+pub fn filters_from_query(params: &HashMap<String, String>) -> Vec<(String, String)> {
+    params
+        .iter()
+        .filter_map(|(key, value)| Some((key.strip_prefix(FILTER_PREFIX)?.to_string(), value.clone())))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{project_fixture, test_db, ticket_fixture};
+    use serde_json::json;
+
+    async fn setup() -> (Database, TicketRecord) {
+        let db = test_db().await.unwrap();
+        db.create_project(&project_fixture("p1")).await.unwrap();
+        let ticket = TicketRecord {
+            title: "Checkout".to_string(),
+            ..ticket_fixture("t1", "p1")
+        };
+        db.create_ticket(&ticket).await.unwrap();
+        (db, ticket)
+    }
+
+    fn values(value: Value) -> FieldValues {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_clean_definition() {
+        let (name, options) =
+            clean_definition(" Severity ", FieldType::Select, vec!["high".into(), " low ".into(), "high".into(), "".into()])
+                .unwrap();
+        assert_eq!(name, "Severity");
+        assert_eq!(options, vec!["high", "low"]);
+        assert!(clean_definition("Severity", FieldType::Select, vec![]).is_err());
+        assert!(clean_definition("Sprint", FieldType::Number, vec!["1".into()]).is_err());
+        assert!(clean_definition("  ", FieldType::Text, vec![]).is_err());
+    }
+
+    #[test]
+    fn test_matches() {
+        assert!(matches(&json!("High"), "high"));
+        assert!(matches(&json!(12), "12.0"));
+        assert!(matches(&json!(true), "true"));
+        assert!(matches(&json!(["staging", "prod"]), "prod"));
+        assert!(!matches(&json!(["staging"]), "prod"));
+    }
+
+    #[tokio::test]
+    async fn test_values_are_validated_and_filtered() {
+        let (db, ticket) = setup().await;
+        create_field(&db, "p1", "severity", FieldType::Select, vec!["high".into(), "low".into()])
+            .await
+            .unwrap();
+        create_field(&db, "p1", "sprint", FieldType::Number, vec![]).await.unwrap();
+        create_field(&db, "p1", "envs", FieldType::MultiSelect, vec!["staging".into(), "prod".into()])
+            .await
+            .unwrap();
+        create_field(&db, "p1", "due", FieldType::Date, vec![]).await.unwrap();
+        assert!(matches!(
+            create_field(&db, "p1", "Severity", FieldType::Text, vec![]).await,
+            Err(FieldError::Duplicate(_))
+        ));
+
+        // One bad value rejects the whole write
+        let err = set_values(&db, &ticket, values(json!({ "severity": "high", "sprint": "twelve" })))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, FieldError::InvalidValue(name, _) if name == "sprint"));
+        assert!(ticket_values(&db, "t1").await.unwrap().is_empty());
+        assert!(matches!(
+            set_values(&db, &ticket, values(json!({ "owner": "x" }))).await,
+            Err(FieldError::UnknownField(_))
+        ));
+        assert!(set_values(&db, &ticket, values(json!({ "due": "2026-13-01" }))).await.is_err());
+
+        let stored = set_values(
+            &db,
+            &ticket,
+            values(json!({ "severity": "high", "sprint": 12, "envs": ["prod", "prod"], "due": "2026-11-01" })),
+        )
+        .await
+        .unwrap();
+        assert_eq!(stored["envs"], json!(["prod"]));
+
+        let stored = set_values(&db, &ticket, values(json!({ "sprint": null }))).await.unwrap();
+        assert!(!stored.contains_key("sprint"));
+
+        let tickets = db.list_tickets_by_project("p1").await.unwrap();
+        let listed = with_fields(&db, tickets.clone(), &[("envs".into(), "prod".into())]).await.unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].custom_fields["severity"], json!("high"));
+        assert!(with_fields(&db, tickets.clone(), &[("severity".into(), "low".into())]).await.unwrap().is_empty());
+        assert_eq!(with_fields(&db, tickets, &[("sprint".into(), "none".into())]).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_options_in_use_cannot_be_removed() {
+        let (db, ticket) = setup().await;
+        let field = create_field(&db, "p1", "severity", FieldType::Select, vec!["high".into(), "low".into()])
+            .await
+            .unwrap();
+        set_values(&db, &ticket, values(json!({ "severity": "high" }))).await.unwrap();
+
+        assert!(matches!(
+            update_field(&db, &field, "severity", vec!["low".into()]).await,
+            Err(FieldError::OptionsInUse(options)) if options == vec!["high"]
+        ));
+        let renamed = update_field(&db, &field, "Severity", vec!["high".into(), "medium".into()]).await.unwrap();
+        assert_eq!(renamed.options, vec!["high", "medium"]);
+        assert!(ticket_values(&db, "t1").await.unwrap().contains_key("Severity"));
+
+        db.delete_custom_field(&field.id).await.unwrap();
+        assert!(ticket_values(&db, "t1").await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,155 @@
+//! Everything a project's dashboard shows, in one payload (`GET /api/projects/:id/dashboard`):
+//! ticket counts, recent activity, running analyses, top contributors and this month's spend
+
+use crate::board;
+use crate::database::{ContributorRow, Database, FeedEntry};
+use anyhow::Result;
+use chrono::{DateTime, Datelike, TimeZone, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+const ACTIVITY_LIMIT: u32 = 10;
+const CONTRIBUTORS_LIMIT: u32 = 5;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RunningAnalysis {
+    pub session_id: String,
+    pub run_id: Option<String>,
+    pub ticket_id: String,
+    pub ticket_title: String,
+    pub agent_type: String,
+    pub started_at: String,
+    pub elapsed_seconds: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MonthSpend {
+    /// First day of the month, `YYYY-MM-DD` (UTC)
+    pub since: String,
+    /// Sum of the costs agents reported; runs without one count as free
+    pub cost_usd: f64,
+    pub runs: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProjectDashboard {
+    pub project_id: String,
+    /// Every board status, zero included
+    pub tickets_by_status: BTreeMap<String, i64>,
+    pub total_tickets: i64,
+    pub recent_activity: Vec<FeedEntry>,
+    pub running: Vec<RunningAnalysis>,
+    pub top_contributors: Vec<ContributorRow>,
+    pub spend_this_month: MonthSpend,
+}
+
+/// Midnight (UTC) of the first day of `now`'s month
+pub(crate) fn month_start(now: DateTime<Utc>) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(now.year(), now.month(), 1, 0, 0, 0)
+        .single()
+        .unwrap_or(now)
+}
+
+pub async fn build(database: &Database, project_id: &str, now: DateTime<Utc>) -> Result<ProjectDashboard> {
+    let since = month_start(now);
+    let since_rfc3339 = since.to_rfc3339();
+    let (counts, recent_activity, running, top_contributors, spend) = tokio::try_join!(
+        database.count_tickets_by_status(project_id),
+        database.list_project_activity(project_id, ACTIVITY_LIMIT),
+        database.list_running_sessions(project_id),
+        database.list_top_contributors(project_id, CONTRIBUTORS_LIMIT),
+        database.get_project_spend(project_id, &since_rfc3339),
+    )?;
+
+    let mut tickets_by_status: BTreeMap<String, i64> =
+        board::STATUSES.iter().map(|status| (status.to_string(), 0)).collect();
+    tickets_by_status.extend(counts);
+    let running = running
+        .into_iter()
+        .map(|session| {
+            let elapsed_seconds = DateTime::parse_from_rfc3339(&session.started_at)
+                .map(|started| (now - started.with_timezone(&Utc)).num_seconds().max(0))
+                .unwrap_or_default();
+            RunningAnalysis {
+                session_id: session.session_id,
+                run_id: session.run_id,
+                ticket_id: session.ticket_id,
+                ticket_title: session.ticket_title,
+                agent_type: session.agent_type,
+                started_at: session.started_at,
+                elapsed_seconds,
+            }
+        })
+        .collect();
+
+    Ok(ProjectDashboard {
+        project_id: project_id.to_string(),
+        total_tickets: tickets_by_status.values().sum(),
+        tickets_by_status,
+        recent_activity,
+        running,
+        top_contributors,
+        spend_this_month: MonthSpend {
+            since: since.format("%Y-%m-%d").to_string(),
+            cost_usd: spend.0,
+            runs: spend.1,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::activity;
+    use crate::database::{TicketRecord, UserRecord, DEFAULT_ORG_ID};
+    use crate::test_support::{project_fixture, test_db, ticket_fixture};
+    use crate::tool_policy::ToolPolicy;
+
+    #[tokio::test]
+    async fn test_build() {
+        let db = test_db().await.unwrap();
+        db.create_project(&project_fixture("p1")).await.unwrap();
+        db.create_user(&UserRecord {
+            id: "alice".to_string(),
+            org_id: DEFAULT_ORG_ID.to_string(),
+            email: "alice@example.com".to_string(),
+            name: "Alice".to_string(),
+            password_hash: None,
+            role: "member".to_string(),
+            created_at: Utc::now().to_rfc3339(),
+        })
+        .await
+        .unwrap();
+        for (id, status) in [("t1", "todo"), ("t2", "todo"), ("t3", "done")] {
+            db.create_ticket(&TicketRecord {
+                status: status.to_string(),
+                ..ticket_fixture(id, "p1")
+            })
+            .await
+            .unwrap();
+            activity::watch_created(&db, id, Some("alice")).await;
+        }
+        activity::status_changed(&db, "t3", Some("alice"), "todo", "done").await;
+        let finished = db.create_session("t3", "claude", Some("r1"), &ToolPolicy::default(), Default::default()).await.unwrap();
+        db.complete_session(&finished, "").await.unwrap();
+        db.create_session("t1", "claude", Some("r2"), &ToolPolicy::default(), Default::default()).await.unwrap();
+
+        let dashboard = build(&db, "p1", Utc::now()).await.unwrap();
+        assert_eq!(dashboard.total_tickets, 3);
+        assert_eq!(dashboard.tickets_by_status["todo"], 2);
+        assert_eq!(dashboard.tickets_by_status["in-progress"], 0);
+        assert_eq!(dashboard.recent_activity.len(), 1);
+        assert_eq!(dashboard.running.len(), 1);
+        assert_eq!(dashboard.running[0].ticket_id, "t1");
+        assert_eq!(
+            dashboard.top_contributors,
+            vec![ContributorRow {
+                user_id: "alice".to_string(),
+                name: "Alice".to_string(),
+                tickets_created: 3,
+                actions: 1,
+            }]
+        );
+        assert_eq!((dashboard.spend_this_month.runs, dashboard.spend_this_month.cost_usd), (2, 0.0));
+    }
+}
@@ -1,7 +1,23 @@
-use anyhow::Result;
+use crate::blob_store::{Blob, Blobs};
+use crate::budgets::{Budget, BudgetMetric, Enforcement};
+use crate::cache::{CacheConfig, CacheStats, TtlLruCache};
+use crate::code_agent::{AnalysisMode, CodeAnalysisRequest};
+use crate::config::DatabaseConfig;
+use crate::log_persistence::LogPersistence;
+use crate::preflight::PreflightCheck;
+use crate::project_files::PathRules;
+use crate::run_environment::RunEnvironment;
+use crate::tool_policy::ToolPolicy;
+use anyhow::{Context, Result};
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
-use sqlx::{sqlite::SqlitePool, FromRow, Row};
+use sqlx::migrate::{Migrate, Migrator};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqlitePoolOptions, SqliteRow};
+use sqlx::{FromRow, Row};
+use std::future::Future;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct ProjectRecord {
@@ -11,6 +27,318 @@ pub struct ProjectRecord {
     pub directory_path: String,
     pub created_at: String,
     pub updated_at: String,
+    /// Owning organization; exports from before multi-tenancy fall back to the default org
+    #[serde(default = "default_org_id")]
+    pub org_id: String,
+}
+
+/// A project in the trash, restorable until it is purged
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct TrashedProjectRecord {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub project: ProjectRecord,
+    pub deleted_at: String,
+    /// User who deleted it; None for anonymous access
+    pub deleted_by: Option<String>,
+}
+
+fn default_org_id() -> String {
+    DEFAULT_ORG_ID.to_string()
+}
+
+/// Built-in organization that owns pre-existing data and anonymous requests
+pub const DEFAULT_ORG_ID: &str = "default";
+
+/// Migrations embedded from `migrations/` at build time; adding one is a new `NNN_description.sql` file
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+/// Columns the queries in this file read or write, per table. `verify_schema` checks the
+/// migrated database against it at startup; keep it in step with the record structs.
+const EXPECTED_SCHEMA: &[(&str, &[&str])] = &[
+    ("organizations", &["id", "name", "created_at"]),
+    ("users", &["id", "org_id", "email", "name", "password_hash", "role", "created_at"]),
+    ("auth_tokens", &["token_hash", "user_id", "created_at", "expires_at"]),
+    (
+        "org_invites",
+        &["id", "org_id", "email", "role", "token_hash", "invited_by", "created_at", "expires_at", "accepted_at"],
+    ),
+    (
+        "projects",
+        &["id", "name", "description", "directory_path", "created_at", "updated_at", "org_id", "deleted_at", "deleted_by"],
+    ),
+    (
+        "tickets",
+        &[
+            "id",
+            "project_id",
+            "title",
+            "description",
+            "status",
+            "code_context",
+            "analysis_result",
+            "is_analyzing",
+            "created_at",
+            "updated_at",
+            "stale",
+            "analysis_result_blob",
+            "analysis_result_size",
+            "position",
+            "assignee_id",
+            "summary",
+            "mode",
+        ],
+    ),
+    (
+        "structured_logs",
+        &["id", "ticket_id", "message_type", "content", "raw_log", "metadata", "timestamp", "raw_log_blob"],
+    ),
+    (
+        "analysis_sessions",
+        &[
+            "id",
+            "ticket_id",
+            "started_at",
+            "completed_at",
+            "status",
+            "error_message",
+            "agent_type",
+            "run_id",
+            "tool_policy",
+            "cli_version",
+            "model",
+            "git_commit",
+            "prompt_hash",
+            "config_snapshot",
+            "request_json",
+            "replay_of",
+            "experiment_id",
+            "variant_id",
+            "cost_usd",
+            "hook_results",
+            "output_violations",
+            "changed_files",
+            "mode",
+            "tokens",
+        ],
+    ),
+    ("analysis_session_stages", &["id", "session_id", "stage", "entered_at"]),
+    (
+        "session_transcripts",
+        &[
+            "session_id",
+            "prompt",
+            "argv",
+            "env_names",
+            "transcript",
+            "transcript_blob",
+            "transcript_size",
+            "created_at",
+        ],
+    ),
+    (
+        "test_cases",
+        &[
+            "id",
+            "ticket_id",
+            "run_id",
+            "position",
+            "title",
+            "preconditions",
+            "steps",
+            "expected",
+            "priority",
+            "flow",
+            "created_at",
+        ],
+    ),
+    ("ticket_labels", &["ticket_id", "label"]),
+    ("ticket_links", &["id", "source_ticket_id", "target_ticket_id", "link_type", "created_by", "created_at"]),
+    ("ticket_files", &["ticket_id", "file_path", "run_id", "analyzed_at"]),
+    ("ticket_watchers", &["ticket_id", "user_id", "reason", "created_at"]),
+    ("ticket_activity", &["id", "ticket_id", "kind", "actor_id", "summary", "created_at"]),
+    ("activity_reads", &["user_id", "read_at"]),
+    (
+        "ticket_status_history",
+        &["id", "ticket_id", "from_status", "to_status", "actor_id", "changed_at"],
+    ),
+    ("custom_fields", &["id", "project_id", "name", "field_type", "options", "created_at"]),
+    ("ticket_field_values", &["ticket_id", "field_id", "value"]),
+    ("ticket_diagrams", &["ticket_id", "run_id", "kind", "source", "svg", "created_at"]),
+    (
+        "result_feedback",
+        &["id", "ticket_id", "session_id", "user_id", "rating", "comment", "created_at", "updated_at"],
+    ),
+    (
+        "analysis_jobs",
+        &[
+            "seq",
+            "ticket_id",
+            "run_id",
+            "priority",
+            "request_json",
+            "follow_ups_json",
+            "requested_by",
+            "status",
+            "worker_id",
+            "cancel_requested",
+            "enqueued_at",
+            "claimed_at",
+            "heartbeat_at",
+        ],
+    ),
+    ("stream_events", &["seq", "origin", "kind", "ticket_id", "org_id", "payload", "created_at"]),
+    ("prompt_experiments", &["id", "project_id", "name", "status", "created_by", "created_at", "stopped_at"]),
+    ("prompt_variants", &["id", "experiment_id", "position", "name", "template", "weight"]),
+    (
+        "project_agent_credentials",
+        &["project_id", "provider", "ciphertext", "masked_key", "updated_by", "updated_at"],
+    ),
+    (
+        "ticket_context_files",
+        &["ticket_id", "file_path", "position", "score", "reasons", "resolved_for", "resolved_at"],
+    ),
+    (
+        "ticket_shares",
+        &["id", "ticket_id", "token_hash", "password_hash", "created_by", "created_at", "expires_at", "revoked_at"],
+    ),
+    ("notification_preferences", &["user_id", "email_mode", "updated_at"]),
+    (
+        "user_preferences",
+        &["user_id", "default_agent", "response_language", "timezone", "default_project_id", "updated_at"],
+    ),
+    (
+        "pending_notifications",
+        &["id", "user_id", "ticket_id", "ticket_title", "succeeded", "summary", "link", "created_at"],
+    ),
+    ("slack_workspaces", &["team_id", "org_id", "team_name", "bot_token", "installed_by", "created_at"]),
+    ("redaction_patterns", &["id", "name", "pattern", "created_by", "created_at"]),
+    ("project_path_rules", &["project_id", "include_patterns", "exclude_patterns", "updated_at"]),
+    ("project_tool_policies", &["project_id", "allowed_tools", "updated_at"]),
+    ("project_log_persistence", &["project_id", "message_types", "raw_log_errors_only", "updated_at"]),
+    ("project_budgets", &["project_id", "metric", "monthly_limit", "enforcement", "updated_by", "updated_at"]),
+    ("org_budgets", &["org_id", "metric", "monthly_limit", "enforcement", "updated_by", "updated_at"]),
+    ("budget_warnings", &["scope", "scope_id", "period", "threshold", "created_at"]),
+    ("project_preflight_checks", &["project_id", "checks", "updated_at"]),
+    ("project_post_run_hooks", &["project_id", "hooks", "reopen_on_failure", "updated_at"]),
+    (
+        "alert_rules",
+        &[
+            "id",
+            "org_id",
+            "project_id",
+            "name",
+            "message_type",
+            "content_pattern",
+            "threshold",
+            "actions",
+            "enabled",
+            "created_by",
+            "created_at",
+            "updated_at",
+        ],
+    ),
+    ("alert_events", &["id", "rule_id", "ticket_id", "run_id", "match_count", "sample", "created_at"]),
+    (
+        "question_templates",
+        &[
+            "id",
+            "org_id",
+            "project_id",
+            "category",
+            "title",
+            "question",
+            "mode",
+            "code_context",
+            "usage_count",
+            "created_by",
+            "created_at",
+            "updated_at",
+        ],
+    ),
+    ("project_summary_settings", &["project_id", "enabled", "max_lines", "updated_at"]),
+    (
+        "idempotency_keys",
+        &["scope", "idempotency_key", "fingerprint", "status_code", "response", "created_at", "expires_at"],
+    ),
+    ("user_identities", &["issuer", "subject", "user_id", "created_at"]),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MigrationState {
+    Applied,
+    Pending,
+    /// The file was edited after it was applied
+    ChecksumMismatch,
+    /// Started but didn't finish; the database needs a manual look
+    Failed,
+    /// Applied by a newer build; this one refuses to start against the database
+    Unknown,
+}
+
+impl MigrationState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MigrationState::Applied => "applied",
+            MigrationState::Pending => "pending",
+            MigrationState::ChecksumMismatch => "checksum_mismatch",
+            MigrationState::Failed => "failed",
+            MigrationState::Unknown => "unknown",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub state: MigrationState,
+    pub installed_on: Option<String>,
+}
+
+#[derive(Debug, FromRow)]
+struct AppliedMigrationRow {
+    version: i64,
+    description: String,
+    installed_on: String,
+    success: i64,
+    /// None for rows read from the legacy table, which kept no checksums
+    checksum: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct OrganizationRecord {
+    pub id: String,
+    pub name: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct UserRecord {
+    pub id: String,
+    pub org_id: String,
+    pub email: String,
+    pub name: String,
+    #[serde(skip_serializing, default)]
+    pub password_hash: Option<String>,
+    /// owner, admin or member
+    pub role: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct OrgInviteRecord {
+    pub id: String,
+    pub org_id: String,
+    pub email: String,
+    pub role: String,
+    #[serde(skip_serializing, default)]
+    pub token_hash: String,
+    pub invited_by: Option<String>,
+    pub created_at: String,
+    pub expires_at: String,
+    pub accepted_at: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
@@ -25,9 +353,109 @@ pub struct TicketRecord {
     pub is_analyzing: bool,
     pub created_at: String,
     pub updated_at: String,
+    /// Files read by the last analysis changed after it ran (see `stale::spawn_checker`)
+    #[serde(default)]
+    pub stale: bool,
+    /// Blob holding the full `analysis_result` when it was too large to keep inline; the
+    /// column then only holds a preview (see `blob_store`)
+    #[serde(default)]
+    pub analysis_result_blob: Option<String>,
+    /// Size in bytes of the full result kept in `analysis_result_blob`
+    #[serde(default)]
+    pub analysis_result_size: Option<i64>,
+    /// Order within the project's column for `status`, lowest first (see `board`)
+    #[serde(default)]
+    pub position: f64,
+    /// User the ticket is assigned to, in the ticket's organization
+    #[serde(default)]
+    pub assignee_id: Option<String>,
+    /// Short TL;DR of `analysis_result` (see `summary`)
+    #[serde(default)]
+    pub summary: Option<String>,
+    /// Mode runs use unless they ask for another (see `analysis_runner::choose_mode`)
+    #[serde(default)]
+    pub mode: AnalysisMode,
+}
+
+/// Whether a project's results get a summary (see `summary`)
+#[derive(Debug, Clone, FromRow)]
+pub struct ProjectSummarySettingsRecord {
+    pub project_id: String,
+    pub enabled: bool,
+    /// Lines of the summary; the server's `SUMMARY_MAX_LINES` when None
+    pub max_lines: Option<i64>,
+    pub updated_at: String,
+}
+
+/// Commands run after a project's edit-capable analyses (see `post_run`)
+#[derive(Debug, Clone, FromRow)]
+pub struct PostRunHooksRecord {
+    pub project_id: String,
+    /// JSON array of `PostRunHook`
+    pub hooks: String,
+    /// Move the ticket back to in-progress when a hook fails
+    pub reopen_on_failure: bool,
+    pub updated_at: String,
+}
+
+/// A condition on a run's log entries and what to do when it is met (see `alerts`)
+#[derive(Debug, Clone, PartialEq, FromRow)]
+pub struct AlertRuleRecord {
+    pub id: String,
+    pub org_id: String,
+    /// None for rules on every project of the organization
+    pub project_id: Option<String>,
+    pub name: String,
+    /// Only entries of this type count (`error`, `tool_use`, ...)
+    pub message_type: Option<String>,
+    /// Only entries whose content matches this regex count
+    pub content_pattern: Option<String>,
+    /// Matching entries a run needs for the rule to fire
+    pub threshold: i64,
+    /// JSON array of `AlertAction`
+    pub actions: String,
+    pub enabled: bool,
+    pub created_by: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// A rule firing for one run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FromRow)]
+pub struct AlertEventRecord {
+    pub id: String,
+    pub rule_id: String,
+    pub ticket_id: String,
+    pub run_id: String,
+    pub match_count: i64,
+    /// Content of the entry that made the rule fire, shortened
+    pub sample: String,
+    pub created_at: String,
+}
+
+/// A reusable question of the library (see `question_templates`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FromRow)]
+pub struct QuestionTemplateRecord {
+    pub id: String,
+    pub org_id: String,
+    /// None for the organization's global templates
+    pub project_id: Option<String>,
+    pub category: String,
+    /// Title of the tickets created from it
+    pub title: String,
+    /// Description of those tickets, and the question asked to the agent
+    pub question: String,
+    /// `ask`, `testcases` or `diagram`
+    pub mode: String,
+    pub code_context: Option<String>,
+    /// Tickets created from it
+    pub usage_count: i64,
+    pub created_by: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct StructuredLogRecord {
     pub id: String,
     pub ticket_id: String,
@@ -36,6 +464,9 @@ pub struct StructuredLogRecord {
     pub raw_log: Option<String>,
     pub metadata: Option<String>, // JSON string
     pub timestamp: String,
+    /// Blob holding the full `raw_log` when it was too large to keep inline
+    #[serde(default)]
+    pub raw_log_blob: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
@@ -46,201 +477,4528 @@ pub struct AnalysisSession {
     pub completed_at: Option<String>,
     pub status: String,
     pub error_message: Option<String>,
+    pub agent_type: Option<String>,
+    pub run_id: Option<String>,
+    /// JSON `ToolPolicy` the run was started with
+    pub tool_policy: Option<String>,
+    /// `--version` output of the agent CLI (see `run_environment`)
+    #[serde(default)]
+    pub cli_version: Option<String>,
+    /// Model reported by the agent's init event, or configured for API agents
+    #[serde(default)]
+    pub model: Option<String>,
+    /// HEAD of the analyzed project directory when the run started
+    #[serde(default)]
+    pub git_commit: Option<String>,
+    /// SHA-256 of the prompt as sent to the agent
+    #[serde(default)]
+    pub prompt_hash: Option<String>,
+    /// JSON snapshot of the agent configuration, without credentials
+    #[serde(default)]
+    pub config_snapshot: Option<String>,
+    /// JSON `CodeAnalysisRequest` the agent received, for replays
+    #[serde(skip_serializing, default)]
+    pub request_json: Option<String>,
+    /// Session this one re-ran (`POST /api/sessions/:id/replay`)
+    #[serde(default)]
+    pub replay_of: Option<String>,
+    /// Prompt experiment and variant the run was assigned to (see `experiments`)
+    #[serde(default)]
+    pub experiment_id: Option<String>,
+    #[serde(default)]
+    pub variant_id: Option<String>,
+    /// Cost reported by the agent, when it reports one
+    #[serde(default)]
+    pub cost_usd: Option<f64>,
+    /// JSON `HookResult`s of the post-run hooks run after this session (see `post_run`)
+    #[serde(default)]
+    pub hook_results: Option<String>,
+    /// JSON `Violation`s of the mode's instructions found in its output (see `output_validation`)
+    #[serde(default)]
+    pub output_violations: Option<String>,
+    /// JSON `ChangedFile`s the session's write/edit tools produced (see `changed_files`)
+    #[serde(default)]
+    pub changed_files: Option<String>,
+    /// Mode the run used, which may differ from its ticket's
+    #[serde(default)]
+    pub mode: Option<String>,
+    /// Input plus output tokens reported by the agent, when it reports them
+    #[serde(default)]
+    pub tokens: Option<i64>,
 }
 
-#[derive(Debug)]
-pub struct Database {
-    pool: SqlitePool,
+/// What a CLI agent run exchanged with its process (see `transcript`); `argv` and `env_names`
+/// are JSON arrays
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct SessionTranscriptRecord {
+    pub session_id: String,
+    pub prompt: String,
+    pub argv: String,
+    pub env_names: String,
+    /// Gzipped JSON lines, when kept inline
+    #[serde(skip)]
+    pub transcript: Option<Vec<u8>>,
+    /// Blob holding the gzipped lines when they were too large to keep inline
+    pub transcript_blob: Option<String>,
+    /// Uncompressed size in bytes
+    pub transcript_size: i64,
+    pub created_at: String,
 }
 
-impl Database {
-    pub async fn new(database_url: &str) -> Result<Self> {
-        let pool = SqlitePool::connect(database_url).await?;
-        Ok(Self { pool })
-    }
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct SessionStageRecord {
+    pub session_id: String,
+    pub stage: String,
+    pub entered_at: String,
+}
 
-    pub async fn init_schema(&self) -> Result<()> {
-        // Create projects table
-        sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS projects (
-                id TEXT PRIMARY KEY,
-                name TEXT NOT NULL,
-                description TEXT,
-                directory_path TEXT NOT NULL,
-                created_at TEXT NOT NULL,
-                updated_at TEXT NOT NULL
-            )
-            "#,
-        )
-        .execute(&self.pool)
-        .await?;
+/// A generated test case; `preconditions` and `steps` are stored as JSON arrays
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TestCaseRecord {
+    pub id: String,
+    pub ticket_id: String,
+    /// Run of the testcases analysis that produced this case
+    pub run_id: Option<String>,
+    pub position: i64,
+    pub title: String,
+    pub preconditions: Vec<String>,
+    pub steps: Vec<String>,
+    pub expected: String,
+    pub priority: Option<String>,
+    /// Sub-flow the case belongs to, used to split Gherkin exports into features
+    pub flow: Option<String>,
+    pub created_at: String,
+}
 
-        // Create tickets table
-        sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS tickets (
-                id TEXT PRIMARY KEY,
-                project_id TEXT NOT NULL,
-                title TEXT NOT NULL,
-                description TEXT NOT NULL,
-                status TEXT NOT NULL CHECK(status IN ('todo', 'in-progress', 'done')),
-                code_context TEXT,
-                analysis_result TEXT,
-                is_analyzing BOOLEAN DEFAULT 0,
-                created_at TEXT NOT NULL,
-                updated_at TEXT NOT NULL,
-                FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
-            )
-            "#,
-        )
-        .execute(&self.pool)
-        .await?;
+/// A project file read by a ticket's analysis (path relative to the project directory)
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct TicketFileRecord {
+    pub ticket_id: String,
+    pub file_path: String,
+    pub run_id: Option<String>,
+    pub analyzed_at: String,
+}
 
-        // Add project_id column to existing tickets table if it doesn't exist
-        let _ = sqlx::query(
-            r#"
-            ALTER TABLE tickets ADD COLUMN project_id TEXT
-            "#
-        )
-        .execute(&self.pool)
-        .await;
+/// A project file chosen for a ticket's code context (see `context_resolver`); `reasons` is
+/// stored as a JSON array
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ContextFileRecord {
+    pub ticket_id: String,
+    pub file_path: String,
+    pub position: i64,
+    pub score: i64,
+    pub reasons: Vec<String>,
+    /// Code context text the file was resolved for
+    pub resolved_for: String,
+    pub resolved_at: String,
+}
 
-        // Create index for tickets by project
-        sqlx::query("CREATE INDEX IF NOT EXISTS idx_tickets_project_id ON tickets(project_id)")
-            .execute(&self.pool)
-            .await?;
+/// Typed relation between two tickets of the same project, read as "source <link_type> target"
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct TicketLinkRecord {
+    pub id: String,
+    pub source_ticket_id: String,
+    pub target_ticket_id: String,
+    /// `blocks`, `relates-to` or `duplicates`
+    pub link_type: String,
+    pub created_by: Option<String>,
+    pub created_at: String,
+}
+
+/// A project's custom ticket field; `options` is stored as a JSON array
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CustomFieldRecord {
+    pub id: String,
+    pub project_id: String,
+    pub name: String,
+    /// `text`, `number`, `boolean`, `date`, `select` or `multi-select`
+    pub field_type: String,
+    /// Allowed values of the select types
+    pub options: Vec<String>,
+    pub created_at: String,
+}
+
+/// A project's own API key for one provider's agents, encrypted by `agent_credentials`
+#[derive(Debug, Clone, FromRow)]
+pub struct ProjectCredentialRecord {
+    pub project_id: String,
+    /// `anthropic`, `gemini` or `cursor`
+    pub provider: String,
+    /// `v1:<nonce>:<ciphertext>`, hex encoded
+    pub ciphertext: String,
+    /// The key's last characters, for listing without decrypting
+    pub masked_key: String,
+    pub updated_by: Option<String>,
+    pub updated_at: String,
+}
+
+/// Mermaid diagram of a ticket's last `diagram` run (see `diagram`)
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct TicketDiagramRecord {
+    pub ticket_id: String,
+    pub run_id: Option<String>,
+    /// `sequence`, `flowchart`, `state`, `class` or `er`
+    pub kind: String,
+    pub source: String,
+    /// Rendered SVG, cached the first time it is requested
+    pub svg: Option<String>,
+    pub created_at: String,
+}
+
+/// Defaults applied to a user's requests that leave them out (see `preferences`)
+#[derive(Debug, Clone, Default, Serialize, Deserialize, FromRow)]
+pub struct UserPreferencesRecord {
+    pub user_id: String,
+    pub default_agent: Option<String>,
+    pub response_language: Option<String>,
+    pub timezone: Option<String>,
+    pub default_project_id: Option<String>,
+    pub updated_at: String,
+}
+
+/// A user's rating of the analysis result of one session (see `feedback`)
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ResultFeedbackRecord {
+    pub id: String,
+    pub ticket_id: String,
+    pub session_id: String,
+    /// None for anonymous ratings, or once the rater's account is deleted
+    pub user_id: Option<String>,
+    /// 1 for thumbs up, -1 for thumbs down
+    pub rating: i64,
+    pub comment: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// Ratings of the results of one agent in one analysis mode
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct FeedbackQuality {
+    /// `unknown` for sessions recorded before agent tracking existed
+    pub agent_type: String,
+    /// `ask` for sessions recorded before requests were kept
+    pub mode: String,
+    pub ratings: i64,
+    pub thumbs_up: i64,
+    pub thumbs_down: i64,
+    /// Share of thumbs up
+    pub approval_rate: f64,
+}
+
+/// A thumbs-down rating with the session it rated, for the prompt-tuning export
+#[derive(Debug, Clone, FromRow)]
+pub struct LowRatedFeedback {
+    pub feedback_id: String,
+    pub ticket_id: String,
+    pub project_id: String,
+    pub session_id: String,
+    pub agent_type: Option<String>,
+    pub model: Option<String>,
+    /// JSON `CodeAnalysisRequest` of the session
+    pub request_json: Option<String>,
+    pub comment: Option<String>,
+    pub rated_by: Option<String>,
+    pub rated_at: String,
+    /// The session is still the ticket's latest completed one, so its result is the ticket's
+    pub is_current: bool,
+}
+
+/// Analysis in the shared queue (`--role api|worker`); `status` is `queued` or `claimed`
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct AnalysisJobRecord {
+    pub seq: i64,
+    pub ticket_id: String,
+    pub run_id: String,
+    /// `AnalysisPriority::rank`, higher first
+    pub priority: i64,
+    /// JSON `CodeAnalysisRequest`
+    pub request_json: String,
+    /// JSON array of the requests to queue once this one ends
+    pub follow_ups_json: String,
+    pub requested_by: Option<String>,
+    pub status: String,
+    pub worker_id: Option<String>,
+    /// Set by an API process asked to stop the run; the claiming worker cancels it
+    pub cancel_requested: bool,
+    pub enqueued_at: String,
+    pub claimed_at: Option<String>,
+    pub heartbeat_at: Option<String>,
+}
+
+/// Log entry (`log`) or broadcast (`broadcast`) of one process, relayed to the others
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct StreamEventRecord {
+    pub seq: i64,
+    pub origin: String,
+    pub kind: String,
+    pub ticket_id: String,
+    pub org_id: Option<String>,
+    /// JSON `StructuredLogEntry` or `BroadcastMessage`
+    pub payload: String,
+    pub created_at: String,
+}
+
+/// A/B test of prompt templates within a project; `status` is `running` or `stopped`
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ExperimentRecord {
+    pub id: String,
+    pub project_id: String,
+    pub name: String,
+    pub status: String,
+    pub created_by: Option<String>,
+    pub created_at: String,
+    pub stopped_at: Option<String>,
+}
+
+/// One prompt template of an experiment, with `{question}` where the question goes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FromRow)]
+pub struct PromptVariantRecord {
+    pub id: String,
+    pub experiment_id: String,
+    pub position: i64,
+    pub name: String,
+    pub template: String,
+    /// Share of new runs relative to the other variants' weights
+    pub weight: i64,
+}
+
+/// Runs, ratings and cost of the sessions assigned to one variant
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct VariantStats {
+    pub variant_id: String,
+    pub runs: i64,
+    pub completed: i64,
+    pub failed: i64,
+    pub cancelled: i64,
+    pub avg_duration_ms: Option<f64>,
+    pub ratings: i64,
+    pub thumbs_up: i64,
+    pub thumbs_down: i64,
+    /// Share of thumbs up; None before the first rating
+    pub approval_rate: Option<f64>,
+    /// Over the runs whose agent reported a cost
+    pub total_cost_usd: Option<f64>,
+    pub avg_cost_usd: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct TicketWatcherRecord {
+    pub ticket_id: String,
+    pub user_id: String,
+    /// `creator`, `assignee` or `manual`
+    pub reason: String,
+    pub created_at: String,
+}
+
+/// A status transition of a ticket (see `time_tracking`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FromRow)]
+pub struct StatusChangeRecord {
+    pub id: String,
+    pub ticket_id: String,
+    /// None when not known
+    pub from_status: Option<String>,
+    pub to_status: String,
+    pub actor_id: Option<String>,
+    pub changed_at: String,
+}
+
+/// Something that happened to a ticket, as shown in its watchers' feeds
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ActivityRecord {
+    pub id: String,
+    pub ticket_id: String,
+    /// e.g. `status-changed`, `analysis-completed` (see `activity::ActivityKind`)
+    pub kind: String,
+    /// User who caused it; None for anonymous callers
+    pub actor_id: Option<String>,
+    pub summary: String,
+    pub created_at: String,
+}
+
+/// Activity entry of a user's feed, with the ticket it is about
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct FeedEntry {
+    pub id: String,
+    pub ticket_id: String,
+    pub ticket_title: String,
+    pub project_id: String,
+    pub kind: String,
+    pub actor_id: Option<String>,
+    pub summary: String,
+    pub created_at: String,
+}
+
+/// A session still running, with its ticket, for the project dashboard
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct RunningSessionRow {
+    pub session_id: String,
+    pub run_id: Option<String>,
+    pub ticket_id: String,
+    pub ticket_title: String,
+    pub project_id: String,
+    pub agent_type: String,
+    pub started_at: String,
+}
+
+/// A user's share of a project's work: tickets they created and actions in the activity feed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FromRow)]
+pub struct ContributorRow {
+    pub user_id: String,
+    pub name: String,
+    pub tickets_created: i64,
+    pub actions: i64,
+}
+
+/// Public read-only link to a ticket; the token itself is only shown once, at creation
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct TicketShareRecord {
+    pub id: String,
+    pub ticket_id: String,
+    #[serde(skip_serializing, default)]
+    pub token_hash: String,
+    #[serde(skip_serializing, default)]
+    pub password_hash: Option<String>,
+    pub created_by: Option<String>,
+    pub created_at: String,
+    /// Never expires when unset
+    pub expires_at: Option<String>,
+    pub revoked_at: Option<String>,
+}
+
+/// A finished analysis waiting for the user's next digest email
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct PendingNotificationRecord {
+    pub id: String,
+    pub user_id: String,
+    pub ticket_id: String,
+    pub ticket_title: String,
+    pub succeeded: bool,
+    pub summary: String,
+    pub link: String,
+    pub created_at: String,
+}
+
+/// Slack workspace installed for an organization; `bot_token` never leaves the server
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct SlackWorkspaceRecord {
+    pub team_id: String,
+    pub org_id: String,
+    pub team_name: String,
+    #[serde(skip_serializing, default)]
+    pub bot_token: String,
+    pub installed_by: Option<String>,
+    pub created_at: String,
+}
+
+/// Admin-defined secret pattern, applied by `redaction::Redactor` next to the built-in ones
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct RedactionPatternRecord {
+    pub id: String,
+    pub name: String,
+    pub pattern: String,
+    pub created_by: Option<String>,
+    pub created_at: String,
+}
+
+/// The request that first used an idempotency key; no status or response while it's in progress
+#[derive(Debug, Clone)]
+pub struct IdempotencyRecord {
+    pub fingerprint: String,
+    pub status_code: Option<u16>,
+    pub response: Option<String>,
+}
+
+/// Date-range and project filter shared by the analytics queries
+#[derive(Debug, Clone, Default)]
+pub struct AnalyticsFilter {
+    /// Inclusive start date (`YYYY-MM-DD` or RFC 3339)
+    pub from: Option<String>,
+    /// Inclusive end date (`YYYY-MM-DD` or RFC 3339)
+    pub to: Option<String>,
+    pub project_id: Option<String>,
+    pub org_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct SessionTotals {
+    pub total_runs: i64,
+    pub completed: i64,
+    pub failed: i64,
+    pub cancelled: i64,
+    pub running: i64,
+    /// Average wall-clock duration of completed sessions
+    pub avg_duration_ms: Option<f64>,
+    pub max_duration_ms: Option<f64>,
+}
+
+impl SessionTotals {
+    /// Share of all runs that `count` makes up, 0 without runs
+    pub fn rate(&self, count: i64) -> f64 {
+        if self.total_runs == 0 {
+            0.0
+        } else {
+            count as f64 / self.total_runs as f64
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct DailyRuns {
+    pub date: String,
+    pub runs: i64,
+    pub failed: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct AgentBreakdown {
+    /// `unknown` for sessions recorded before agent tracking existed
+    pub agent_type: String,
+    pub runs: i64,
+    pub completed: i64,
+    pub failed: i64,
+    pub cancelled: i64,
+    pub avg_duration_ms: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ProjectRuns {
+    pub project_id: String,
+    pub project_name: Option<String>,
+    pub runs: i64,
+    pub failed: i64,
+}
+
+// Bound as ?1 = from, ?2 = to, ?3 = project_id, ?4 = org_id; NULL disables the condition
+const ANALYTICS_WHERE: &str = "(?1 IS NULL OR date(s.started_at) >= date(?1))
+      AND (?2 IS NULL OR date(s.started_at) <= date(?2))
+      AND (?3 IS NULL OR t.project_id = ?3)
+      AND (?4 IS NULL OR t.project_id IN (SELECT id FROM projects WHERE org_id = ?4))";
+
+/// Filters of the project-wide log queries; None/empty disables a condition
+#[derive(Debug, Clone, Default)]
+pub struct LogFilter {
+    /// Any of these message types
+    pub message_types: Vec<String>,
+    pub ticket_id: Option<String>,
+    /// Inclusive start, RFC 3339 in UTC like the stored timestamps
+    pub from: Option<String>,
+    /// Exclusive end, RFC 3339 in UTC
+    pub until: Option<String>,
+    /// Case-insensitive substring of the content
+    pub search: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogOrder {
+    Asc,
+    /// Newest first
+    #[default]
+    Desc,
+}
+
+impl LogOrder {
+    fn as_sql(&self) -> &'static str {
+        match self {
+            LogOrder::Asc => "ASC",
+            LogOrder::Desc => "DESC",
+        }
+    }
+}
+
+// Bound as ?1 = project_id, ?2 = comma-separated message types, ?3 = ticket_id, ?4 = from,
+// ?5 = until, ?6 = search; NULL disables the condition
+const PROJECT_LOGS_WHERE: &str = "t.project_id = ?1
+      AND (?2 IS NULL OR instr(',' || ?2 || ',', ',' || l.message_type || ',') > 0)
+      AND (?3 IS NULL OR l.ticket_id = ?3)
+      AND (?4 IS NULL OR l.timestamp >= ?4)
+      AND (?5 IS NULL OR l.timestamp < ?5)
+      AND (?6 IS NULL OR instr(lower(l.content), lower(?6)) > 0)";
+
+// The conditions of `list_user_feed`, limited to entries after the user's read marker;
+// bound as ?1 = user_id, ?2 = org_id
+const UNREAD_FEED_FROM: &str = "FROM ticket_activity a
+      JOIN ticket_watchers w ON w.ticket_id = a.ticket_id AND w.user_id = ?1
+      JOIN tickets t ON t.id = a.ticket_id
+      JOIN projects p ON p.id = t.project_id
+      LEFT JOIN activity_reads r ON r.user_id = ?1
+      WHERE a.created_at >= w.created_at
+        AND (a.actor_id IS NULL OR a.actor_id != ?1)
+        AND p.org_id = ?2 AND p.deleted_at IS NULL
+        AND (r.read_at IS NULL OR a.created_at > r.read_at)";
+
+// Milliseconds between started_at and completed_at
+const DURATION_MS: &str = "(julianday(s.completed_at) - julianday(s.started_at)) * 86400000.0";
+
+/// Hit/miss counters of the lookup caches in front of `get_ticket`/`get_project`
+#[derive(Debug, Clone, Serialize)]
+pub struct DatabaseCacheStats {
+    pub tickets: CacheStats,
+    pub projects: CacheStats,
+}
+
+/// Likely causes of a failed connection, logged at startup
+pub fn diagnose_connection(config: &DatabaseConfig) -> Vec<String> {
+    if !config.url.starts_with("sqlite:") {
+        return vec![format!("DATABASE_URL must be a sqlite: URL, got {}", config.url)];
+    }
+    let Some(path) = config.file_path() else {
+        return Vec::new();
+    };
+
+    let mut hints = Vec::new();
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => std::path::PathBuf::from("."),
+    };
+    if path.exists() {
+        if let Err(e) = std::fs::OpenOptions::new().read(true).write(true).open(&path) {
+            hints.push(format!("{} exists but cannot be opened for writing: {}", path.display(), e));
+        }
+    } else if !config.create_if_missing {
+        hints.push(format!(
+            "{} does not exist and DB_CREATE_IF_MISSING=false; create it or enable creation",
+            path.display()
+        ));
+    } else if !dir.exists() {
+        hints.push(format!("Directory {} does not exist and could not be created", dir.display()));
+    } else {
+        let probe = dir.join(format!(".qa-chatbot-write-test-{}", std::process::id()));
+        match std::fs::write(&probe, b"") {
+            Ok(()) => {
+                let _ = std::fs::remove_file(&probe);
+            }
+            Err(e) => hints.push(format!("Directory {} is not writable: {}", dir.display(), e)),
+        }
+    }
+    if let Ok(cwd) = std::env::current_dir() {
+        if path.is_relative() {
+            hints.push(format!("Relative paths are resolved from {}", cwd.display()));
+        }
+    }
+    hints
+}
+
+/// How long a replica that failed a query is left out before it is tried again
+const REPLICA_RETRY_AFTER: Duration = Duration::from_secs(30);
+
+#[derive(Debug)]
+struct Replica {
+    url: String,
+    pool: SqlitePool,
+    down_until: std::sync::Mutex<Option<Instant>>,
+}
+
+impl Replica {
+    fn is_up(&self) -> bool {
+        self.down_until.lock().unwrap().is_none_or(|until| Instant::now() >= until)
+    }
+
+    fn mark_down(&self) {
+        *self.down_until.lock().unwrap() = Some(Instant::now() + REPLICA_RETRY_AFTER);
+    }
+}
+
+/// Read-only copies serving the heavy reports, in turn; empty without `DATABASE_REPLICA_URLS`
+#[derive(Debug, Default)]
+struct ReadReplicas {
+    replicas: Vec<Replica>,
+    next: AtomicUsize,
+}
+
+impl ReadReplicas {
+    /// Next replica that isn't marked down
+    fn pick(&self) -> Option<&Replica> {
+        let count = self.replicas.len();
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        (0..count)
+            .map(|offset| &self.replicas[(start + offset) % count])
+            .find(|replica| replica.is_up())
+    }
+}
+
+#[derive(Debug)]
+pub struct Database {
+    pool: SqlitePool,
+    replicas: ReadReplicas,
+    ticket_cache: TtlLruCache<TicketRecord>,
+    project_cache: TtlLruCache<ProjectRecord>,
+    /// Where oversized results and raw logs go; None keeps everything inline
+    blobs: Option<Blobs>,
+}
+
+impl Database {
+    /// Pool with default settings for `database_url`
+    pub async fn new(database_url: &str) -> Result<Self> {
+        Self::connect(&DatabaseConfig {
+            url: database_url.to_string(),
+            ..Default::default()
+        })
+        .await
+    }
+
+    pub async fn connect(config: &DatabaseConfig) -> Result<Self> {
+        let mut options = SqliteConnectOptions::from_str(&config.url)
+            .with_context(|| format!("Invalid DATABASE_URL {}", config.url))?
+            .create_if_missing(config.create_if_missing)
+            .busy_timeout(Duration::from_secs(config.busy_timeout_secs));
+        if let Some(mode) = &config.journal_mode {
+            let mode = SqliteJournalMode::from_str(mode).with_context(|| format!("Invalid DB_JOURNAL_MODE {}", mode))?;
+            options = options.journal_mode(mode);
+        }
+
+        // SQLite creates the file but not its directory
+        if config.create_if_missing {
+            let dir = config.file_path().and_then(|path| path.parent().map(|dir| dir.to_path_buf()));
+            if let Some(dir) = dir.filter(|dir| !dir.as_os_str().is_empty() && !dir.exists()) {
+                std::fs::create_dir_all(&dir)
+                    .with_context(|| format!("Cannot create database directory {}", dir.display()))?;
+            }
+        }
+
+        let pool = SqlitePoolOptions::new()
+            .max_connections(config.max_connections)
+            .acquire_timeout(Duration::from_secs(config.acquire_timeout_secs))
+            .connect_with(options)
+            .await?;
+
+        // Replicas connect on first use, so one that is down doesn't keep the server from starting
+        let mut replicas = ReadReplicas::default();
+        for url in &config.replica_urls {
+            let options = SqliteConnectOptions::from_str(url)
+                .with_context(|| format!("Invalid DATABASE_REPLICA_URLS entry {}", url))?
+                .read_only(true)
+                .create_if_missing(false)
+                .busy_timeout(Duration::from_secs(config.busy_timeout_secs));
+            replicas.replicas.push(Replica {
+                url: url.clone(),
+                pool: SqlitePoolOptions::new()
+                    .max_connections(config.max_connections)
+                    .acquire_timeout(Duration::from_secs(config.acquire_timeout_secs))
+                    .connect_lazy_with(options),
+                down_until: std::sync::Mutex::new(None),
+            });
+        }
+
+        let cache_config = CacheConfig::from_env();
+        Ok(Self {
+            pool,
+            replicas,
+            ticket_cache: TtlLruCache::new(cache_config),
+            project_cache: TtlLruCache::new(cache_config),
+            blobs: None,
+        })
+    }
+
+    /// Run a read-only query on the next replica, or on the primary when there is none or the
+    /// replica fails; a failing replica is left out for `REPLICA_RETRY_AFTER`. Replicas lag
+    /// behind the primary, so only reports that can be a little stale go through here.
+    async fn read_replica<T, F, Fut>(&self, query: F) -> Result<T>
+    where
+        F: Fn(SqlitePool) -> Fut,
+        Fut: Future<Output = sqlx::Result<T>>,
+    {
+        if let Some(replica) = self.replicas.pick() {
+            match query(replica.pool.clone()).await {
+                Ok(value) => return Ok(value),
+                Err(e) => {
+                    replica.mark_down();
+                    tracing::warn!("⚠️ Replica {} lỗi, chuyển sang database chính: {}", replica.url, e);
+                }
+            }
+        }
+        Ok(query(self.pool.clone()).await?)
+    }
+
+    /// Offload analysis results and raw logs above the configured size to `blobs`
+    pub fn with_blobs(mut self, blobs: Option<Blobs>) -> Self {
+        self.blobs = blobs;
+        self
+    }
+
+    /// `value` as stored in SQLite: itself, or a preview plus the key of the blob it was
+    /// written to under `key` when it is over the inline limit
+    async fn store_large(&self, key: impl FnOnce() -> String, value: &str) -> Result<(String, Option<String>)> {
+        match &self.blobs {
+            Some(blobs) if blobs.should_offload(value) => {
+                let key = key();
+                blobs.store.put(&key, value.as_bytes().to_vec()).await?;
+                Ok((blobs.preview(value), Some(key)))
+            }
+            _ => Ok((value.to_string(), None)),
+        }
+    }
+
+    /// Best effort: a blob left behind only wastes space
+    async fn delete_blobs(&self, keys: impl IntoIterator<Item = String>) {
+        let Some(blobs) = &self.blobs else {
+            return;
+        };
+        for key in keys {
+            if let Err(e) = blobs.store.delete(&key).await {
+                tracing::warn!("⚠️ Không xóa được blob {}: {}", key, e);
+            }
+        }
+    }
+
+    pub async fn open_blob(&self, key: &str) -> Result<Option<Blob>> {
+        match &self.blobs {
+            Some(blobs) => blobs.store.open(key).await,
+            None => Ok(None),
+        }
+    }
+
+    async fn read_blob(&self, key: &str) -> Result<String> {
+        let blobs = self.blobs.as_ref().context("Blob store is disabled")?;
+        let data = blobs
+            .store
+            .read(key)
+            .await?
+            .with_context(|| format!("Blob {} is missing", key))?;
+        Ok(String::from_utf8_lossy(&data).into_owned())
+    }
+
+    /// The ticket's whole analysis result, read back from its blob when it was offloaded
+    pub async fn full_result(&self, ticket: &TicketRecord) -> Result<Option<String>> {
+        match &ticket.analysis_result_blob {
+            Some(key) => self.read_blob(key).await.map(Some),
+            None => Ok(ticket.analysis_result.clone()),
+        }
+    }
+
+    /// The log's whole raw line, read back from its blob when it was offloaded
+    pub async fn full_raw_log(&self, log: &StructuredLogRecord) -> Result<Option<String>> {
+        match &log.raw_log_blob {
+            Some(key) => self.read_blob(key).await.map(Some),
+            None => Ok(log.raw_log.clone()),
+        }
+    }
+
+    pub fn cache_stats(&self) -> DatabaseCacheStats {
+        DatabaseCacheStats {
+            tickets: self.ticket_cache.stats(),
+            projects: self.project_cache.stats(),
+        }
+    }
+
+    /// Round trip to the database, for health checks
+    pub async fn ping(&self) -> Result<()> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+
+    pub async fn init_schema(&self) -> Result<()> {
+        // Create projects table
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS projects (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                description TEXT,
+                directory_path TEXT NOT NULL,
+                created_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Create tickets table
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS tickets (
+                id TEXT PRIMARY KEY,
+                project_id TEXT NOT NULL,
+                title TEXT NOT NULL,
+                description TEXT NOT NULL,
+                status TEXT NOT NULL CHECK(status IN ('todo', 'in-progress', 'done')),
+                code_context TEXT,
+                analysis_result TEXT,
+                is_analyzing BOOLEAN DEFAULT 0,
+                created_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL,
+                FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Add project_id column to existing tickets table if it doesn't exist
+        let _ = sqlx::query(
+            r#"
+            ALTER TABLE tickets ADD COLUMN project_id TEXT
+            "#
+        )
+        .execute(&self.pool)
+        .await;
+
+        // Create index for tickets by project
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_tickets_project_id ON tickets(project_id)")
+            .execute(&self.pool)
+            .await?;
+
+        // Create structured_logs table
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS structured_logs (
+                id TEXT PRIMARY KEY,
+                ticket_id TEXT NOT NULL,
+                message_type TEXT NOT NULL CHECK(message_type IN ('tool_use', 'assistant', 'error', 'system', 'result')),
+                content TEXT NOT NULL,
+                raw_log TEXT,
+                metadata TEXT,
+                timestamp TEXT NOT NULL,
+                FOREIGN KEY (ticket_id) REFERENCES tickets(id) ON DELETE CASCADE
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Create indexes
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_logs_ticket_id ON structured_logs(ticket_id)")
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_logs_timestamp ON structured_logs(timestamp)")
+            .execute(&self.pool)
+            .await?;
+
+        // Create analysis_sessions table
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS analysis_sessions (
+                id TEXT PRIMARY KEY,
+                ticket_id TEXT NOT NULL,
+                started_at TEXT NOT NULL,
+                completed_at TEXT,
+                status TEXT NOT NULL CHECK(status IN ('running', 'completed', 'failed', 'cancelled')),
+                error_message TEXT,
+                FOREIGN KEY (ticket_id) REFERENCES tickets(id) ON DELETE CASCADE
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Create analysis_session_stages table (one row per progress stage transition)
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS analysis_session_stages (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                session_id TEXT NOT NULL,
+                stage TEXT NOT NULL,
+                entered_at TEXT NOT NULL,
+                FOREIGN KEY (session_id) REFERENCES analysis_sessions(id) ON DELETE CASCADE
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_session_stages_session_id ON analysis_session_stages(session_id)")
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    // Clear all existing data (for migration)
+    pub async fn clear_all_tickets(&self) -> Result<()> {
+        sqlx::query("DELETE FROM analysis_sessions")
+            .execute(&self.pool)
+            .await?;
+        
+        sqlx::query("DELETE FROM structured_logs")
+            .execute(&self.pool)
+            .await?;
+        
+        sqlx::query("DELETE FROM tickets")
+            .execute(&self.pool)
+            .await?;
+        
+        sqlx::query("DELETE FROM projects")
+            .execute(&self.pool)
+            .await?;
+
+        self.ticket_cache.clear();
+        self.project_cache.clear();
+        Ok(())
+    }
+
+    // Project CRUD operations
+    pub async fn create_project(&self, project: &ProjectRecord) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO projects (id, name, description, directory_path, created_at, updated_at, org_id)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+            "#,
+        )
+        .bind(&project.id)
+        .bind(&project.name)
+        .bind(&project.description)
+        .bind(&project.directory_path)
+        .bind(&project.created_at)
+        .bind(&project.updated_at)
+        .bind(&project.org_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_project(&self, id: &str) -> Result<Option<ProjectRecord>> {
+        if let Some(project) = self.project_cache.get(id) {
+            return Ok(Some(project));
+        }
+
+        let project = sqlx::query_as::<_, ProjectRecord>(
+            "SELECT * FROM projects WHERE id = ?1 AND deleted_at IS NULL"
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        if let Some(project) = &project {
+            self.project_cache.insert(id, project.clone());
+        }
+        Ok(project)
+    }
+
+    pub async fn list_projects(&self) -> Result<Vec<ProjectRecord>> {
+        let projects = sqlx::query_as::<_, ProjectRecord>(
+            "SELECT * FROM projects WHERE deleted_at IS NULL ORDER BY created_at DESC"
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(projects)
+    }
+
+    pub async fn list_projects_by_org(&self, org_id: &str) -> Result<Vec<ProjectRecord>> {
+        let projects = sqlx::query_as::<_, ProjectRecord>(
+            "SELECT * FROM projects WHERE org_id = ?1 AND deleted_at IS NULL ORDER BY created_at DESC"
+        )
+        .bind(org_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(projects)
+    }
+
+    pub async fn update_project(&self, project: &ProjectRecord) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE projects
+            SET name = ?1, description = ?2, directory_path = ?3, updated_at = ?4
+            WHERE id = ?5
+            "#,
+        )
+        .bind(&project.name)
+        .bind(&project.description)
+        .bind(&project.directory_path)
+        .bind(&project.updated_at)
+        .bind(&project.id)
+        .execute(&self.pool)
+        .await?;
+
+        self.project_cache.invalidate(&project.id);
+        Ok(())
+    }
+
+    /// Move a project to the trash; its tickets stay but are hidden with it
+    pub async fn delete_project(&self, id: &str, deleted_by: Option<&str>) -> Result<()> {
+        sqlx::query("UPDATE projects SET deleted_at = ?2, deleted_by = ?3 WHERE id = ?1 AND deleted_at IS NULL")
+            .bind(id)
+            .bind(Utc::now().to_rfc3339())
+            .bind(deleted_by)
+            .execute(&self.pool)
+            .await?;
+
+        self.project_cache.invalidate(id);
+        Ok(())
+    }
+
+    pub async fn list_trashed_projects_by_org(&self, org_id: &str) -> Result<Vec<TrashedProjectRecord>> {
+        let projects = sqlx::query_as::<_, TrashedProjectRecord>(
+            "SELECT * FROM projects WHERE org_id = ?1 AND deleted_at IS NOT NULL ORDER BY deleted_at DESC"
+        )
+        .bind(org_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(projects)
+    }
+
+    pub async fn get_trashed_project(&self, id: &str) -> Result<Option<TrashedProjectRecord>> {
+        let project = sqlx::query_as::<_, TrashedProjectRecord>(
+            "SELECT * FROM projects WHERE id = ?1 AND deleted_at IS NOT NULL"
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(project)
+    }
+
+    /// Take a project out of the trash; false when it isn't there
+    pub async fn restore_project(&self, id: &str) -> Result<bool> {
+        let result = sqlx::query(
+            "UPDATE projects SET deleted_at = NULL, deleted_by = NULL, updated_at = ?2 WHERE id = ?1 AND deleted_at IS NOT NULL"
+        )
+        .bind(id)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        self.project_cache.invalidate(id);
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Permanently delete projects trashed before `deleted_before`, returning their IDs
+    pub async fn purge_trashed_projects(&self, deleted_before: &str) -> Result<Vec<String>> {
+        let blobs: Vec<String> = sqlx::query_scalar(
+            "SELECT t.analysis_result_blob FROM tickets t
+             JOIN projects p ON p.id = t.project_id
+             WHERE p.deleted_at < ?1 AND t.analysis_result_blob IS NOT NULL
+             UNION ALL
+             SELECT l.raw_log_blob FROM structured_logs l
+             JOIN tickets t ON t.id = l.ticket_id
+             JOIN projects p ON p.id = t.project_id
+             WHERE p.deleted_at < ?1 AND l.raw_log_blob IS NOT NULL
+             UNION ALL
+             SELECT st.transcript_blob FROM session_transcripts st
+             JOIN analysis_sessions s ON s.id = st.session_id
+             JOIN tickets t ON t.id = s.ticket_id
+             JOIN projects p ON p.id = t.project_id
+             WHERE p.deleted_at < ?1 AND st.transcript_blob IS NOT NULL"
+        )
+        .bind(deleted_before)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let ids: Vec<String> = sqlx::query_scalar(
+            "DELETE FROM projects WHERE deleted_at IS NOT NULL AND deleted_at < ?1 RETURNING id"
+        )
+        .bind(deleted_before)
+        .fetch_all(&self.pool)
+        .await?;
+
+        // Tickets go with the project (ON DELETE CASCADE)
+        if !ids.is_empty() {
+            self.ticket_cache.clear();
+        }
+        self.delete_blobs(blobs).await;
+        Ok(ids)
+    }
+
+    /// Insert a project with its tickets and logs atomically (used by project import)
+    pub async fn import_project(
+        &self,
+        project: &ProjectRecord,
+        tickets: &[TicketRecord],
+        logs: &[StructuredLogRecord],
+    ) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO projects (id, name, description, directory_path, created_at, updated_at, org_id)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+            "#,
+        )
+        .bind(&project.id)
+        .bind(&project.name)
+        .bind(&project.description)
+        .bind(&project.directory_path)
+        .bind(&project.created_at)
+        .bind(&project.updated_at)
+        .bind(&project.org_id)
+        .execute(&mut *tx)
+        .await?;
+
+        for ticket in tickets {
+            sqlx::query(
+                r#"
+                INSERT INTO tickets (id, project_id, title, description, status, code_context, analysis_result, is_analyzing, created_at, updated_at, position)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
+                "#,
+            )
+            .bind(&ticket.id)
+            .bind(&ticket.project_id)
+            .bind(&ticket.title)
+            .bind(&ticket.description)
+            .bind(&ticket.status)
+            .bind(&ticket.code_context)
+            .bind(&ticket.analysis_result)
+            .bind(ticket.is_analyzing)
+            .bind(&ticket.created_at)
+            .bind(&ticket.updated_at)
+            .bind(ticket.position)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        for log in logs {
+            sqlx::query(
+                r#"
+                INSERT INTO structured_logs (id, ticket_id, message_type, content, raw_log, metadata, timestamp)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+                "#,
+            )
+            .bind(&log.id)
+            .bind(&log.ticket_id)
+            .bind(&log.message_type)
+            .bind(&log.content)
+            .bind(&log.raw_log)
+            .bind(&log.metadata)
+            .bind(&log.timestamp)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    // Ticket CRUD operations
+
+    /// Insert a ticket at the top of its column; returns the position it got
+    pub async fn create_ticket(&self, ticket: &TicketRecord) -> Result<f64> {
+        // `fetch_all`: stopping at the first row of a RETURNING statement leaves the insert
+        // uncommitted until its connection runs another query, hiding it from other connections
+        let positions: Vec<f64> = sqlx::query_scalar(
+            r#"
+            INSERT INTO tickets (id, project_id, title, description, status, code_context, analysis_result, is_analyzing, created_at, updated_at, mode, position)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11,
+                    COALESCE((SELECT MIN(position) FROM tickets WHERE project_id = ?2 AND status = ?5), 0) - 1)
+            RETURNING position
+            "#,
+        )
+        .bind(&ticket.id)
+        .bind(&ticket.project_id)
+        .bind(&ticket.title)
+        .bind(&ticket.description)
+        .bind(&ticket.status)
+        .bind(&ticket.code_context)
+        .bind(&ticket.analysis_result)
+        .bind(ticket.is_analyzing)
+        .bind(&ticket.created_at)
+        .bind(&ticket.updated_at)
+        .bind(ticket.mode)
+        .fetch_all(&self.pool)
+        .await?;
+
+        positions.into_iter().next().context("INSERT returned no position")
+    }
+
+    /// Update a ticket's fields; a ticket changing status moves to the top of its new column
+    pub async fn update_ticket(&self, ticket: &TicketRecord) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE tickets
+            SET project_id = ?1, title = ?2, description = ?3, status = ?4, code_context = ?5,
+                analysis_result = ?6, is_analyzing = ?7, updated_at = ?8,
+                position = CASE WHEN status = ?4 THEN position ELSE
+                    COALESCE((SELECT MIN(t.position) FROM tickets t WHERE t.project_id = ?1 AND t.status = ?4), 0) - 1
+                END
+            WHERE id = ?9
+            "#,
+        )
+        .bind(&ticket.project_id)
+        .bind(&ticket.title)
+        .bind(&ticket.description)
+        .bind(&ticket.status)
+        .bind(&ticket.code_context)
+        .bind(&ticket.analysis_result)
+        .bind(ticket.is_analyzing)
+        .bind(&ticket.updated_at)
+        .bind(&ticket.id)
+        .execute(&self.pool)
+        .await?;
+
+        self.ticket_cache.invalidate(&ticket.id);
+        Ok(())
+    }
+
+    /// Change a ticket's status, moving it to the top of its new column
+    pub async fn update_ticket_status(&self, ticket_id: &str, status: &str) -> Result<()> {
+        let now = Utc::now().to_rfc3339();
+        sqlx::query(
+            r#"
+            UPDATE tickets
+            SET status = ?1, updated_at = ?2,
+                position = CASE WHEN status = ?1 THEN position ELSE
+                    COALESCE((SELECT MIN(t.position) FROM tickets t WHERE t.project_id = tickets.project_id AND t.status = ?1), 0) - 1
+                END
+            WHERE id = ?3
+            "#,
+        )
+        .bind(status)
+        .bind(now)
+        .bind(ticket_id)
+        .execute(&self.pool)
+        .await?;
+
+        self.ticket_cache.invalidate(ticket_id);
+        Ok(())
+    }
+
+    pub async fn update_ticket_analyzing(&self, ticket_id: &str, is_analyzing: bool) -> Result<()> {
+        let now = Utc::now().to_rfc3339();
+        sqlx::query(
+            r#"
+            UPDATE tickets
+            SET is_analyzing = ?1, updated_at = ?2
+            WHERE id = ?3
+            "#,
+        )
+        .bind(is_analyzing)
+        .bind(now)
+        .bind(ticket_id)
+        .execute(&self.pool)
+        .await?;
+
+        self.ticket_cache.invalidate(ticket_id);
+        Ok(())
+    }
+
+    pub async fn update_ticket_result(&self, ticket_id: &str, result: &str) -> Result<()> {
+        let now = Utc::now().to_rfc3339();
+        let previous_blob: Option<String> =
+            sqlx::query_scalar("SELECT analysis_result_blob FROM tickets WHERE id = ?1")
+                .bind(ticket_id)
+                .fetch_optional(&self.pool)
+                .await?
+                .flatten();
+        let (stored, blob) = self
+            .store_large(|| format!("results/{}/{}.md", ticket_id, uuid::Uuid::new_v4()), result)
+            .await?;
+
+        sqlx::query(
+            r#"
+            UPDATE tickets
+            SET analysis_result = ?1, is_analyzing = ?2, updated_at = ?3,
+                analysis_result_blob = ?4, analysis_result_size = ?5, summary = NULL
+            WHERE id = ?6
+            "#,
+        )
+        .bind(stored)
+        .bind(false)
+        .bind(now)
+        .bind(&blob)
+        .bind(blob.as_ref().map(|_| result.len() as i64))
+        .bind(ticket_id)
+        .execute(&self.pool)
+        .await?;
+
+        self.ticket_cache.invalidate(ticket_id);
+        self.delete_blobs(previous_blob).await;
+        Ok(())
+    }
+
+    /// Store the summary of the ticket's current result
+    pub async fn set_ticket_summary(&self, ticket_id: &str, summary: &str) -> Result<()> {
+        sqlx::query("UPDATE tickets SET summary = ?1 WHERE id = ?2")
+            .bind(summary)
+            .bind(ticket_id)
+            .execute(&self.pool)
+            .await?;
+
+        self.ticket_cache.invalidate(ticket_id);
+        Ok(())
+    }
+
+    /// Drop the cached copy of a ticket another process changed (see `worker::relay_event`)
+    pub fn invalidate_cached_ticket(&self, id: &str) {
+        self.ticket_cache.invalidate(id);
+    }
+
+    pub async fn get_ticket(&self, id: &str) -> Result<Option<TicketRecord>> {
+        if let Some(ticket) = self.ticket_cache.get(id) {
+            return Ok(Some(ticket));
+        }
+
+        let ticket = sqlx::query_as::<_, TicketRecord>(
+            "SELECT * FROM tickets WHERE id = ?1"
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        if let Some(ticket) = &ticket {
+            self.ticket_cache.insert(id, ticket.clone());
+        }
+        Ok(ticket)
+    }
+
+    pub async fn list_tickets(&self) -> Result<Vec<TicketRecord>> {
+        let tickets = sqlx::query_as::<_, TicketRecord>(
+            "SELECT * FROM tickets
+             WHERE project_id NOT IN (SELECT id FROM projects WHERE deleted_at IS NOT NULL)
+             ORDER BY position ASC, created_at DESC"
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(tickets)
+    }
+
+    pub async fn list_tickets_by_project(&self, project_id: &str) -> Result<Vec<TicketRecord>> {
+        let tickets = sqlx::query_as::<_, TicketRecord>(
+            "SELECT * FROM tickets WHERE project_id = ?1 ORDER BY position ASC, created_at DESC"
+        )
+        .bind(project_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(tickets)
+    }
+
+    /// Tickets of the given projects, in board order
+    pub async fn list_tickets_by_projects(&self, project_ids: &[String]) -> Result<Vec<TicketRecord>> {
+        let tickets = sqlx::query_as::<_, TicketRecord>(
+            "SELECT * FROM tickets
+             WHERE project_id IN (SELECT value FROM json_each(?1))
+             ORDER BY position ASC, created_at DESC"
+        )
+        .bind(serde_json::to_string(project_ids)?)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(tickets)
+    }
+
+    pub async fn list_tickets_by_org(&self, org_id: &str) -> Result<Vec<TicketRecord>> {
+        let tickets = sqlx::query_as::<_, TicketRecord>(
+            "SELECT t.* FROM tickets t
+             JOIN projects p ON p.id = t.project_id
+             WHERE p.org_id = ?1 AND p.deleted_at IS NULL
+             ORDER BY t.position ASC, t.created_at DESC"
+        )
+        .bind(org_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(tickets)
+    }
+
+    /// A project's tickets assigned to `assignee_id`, or the unassigned ones for None
+    pub async fn list_tickets_by_assignee(&self, project_id: &str, assignee_id: Option<&str>) -> Result<Vec<TicketRecord>> {
+        let tickets = sqlx::query_as::<_, TicketRecord>(
+            "SELECT * FROM tickets WHERE project_id = ?1 AND assignee_id IS ?2
+             ORDER BY position ASC, created_at DESC"
+        )
+        .bind(project_id)
+        .bind(assignee_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(tickets)
+    }
+
+    /// Tickets assigned to a user across an organization's projects, recently updated first
+    pub async fn list_user_tickets(&self, user_id: &str, org_id: &str, status: Option<&str>) -> Result<Vec<TicketRecord>> {
+        let tickets = sqlx::query_as::<_, TicketRecord>(
+            "SELECT t.* FROM tickets t
+             JOIN projects p ON p.id = t.project_id
+             WHERE t.assignee_id = ?1 AND p.org_id = ?2 AND p.deleted_at IS NULL
+               AND (?3 IS NULL OR t.status = ?3)
+             ORDER BY t.updated_at DESC"
+        )
+        .bind(user_id)
+        .bind(org_id)
+        .bind(status)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(tickets)
+    }
+
+    /// Tickets in the organization (or one of its projects) matching an FTS5 `match_query` (see
+    /// `search::match_query`), best first; title matches weigh most, then summaries, descriptions
+    /// and results
+    pub async fn search_tickets(
+        &self,
+        org_id: &str,
+        project_id: Option<&str>,
+        match_query: &str,
+        limit: u32,
+    ) -> Result<Vec<TicketRecord>> {
+        let tickets = self
+            .read_replica(|pool| async move {
+                sqlx::query_as::<_, TicketRecord>(
+                    "SELECT t.* FROM ticket_search
+                     JOIN tickets t ON t.rowid = ticket_search.rowid
+                     JOIN projects p ON p.id = t.project_id
+                     WHERE ticket_search MATCH ?1 AND p.org_id = ?2 AND p.deleted_at IS NULL
+                       AND (?3 IS NULL OR t.project_id = ?3)
+                     ORDER BY bm25(ticket_search, 0.0, 10.0, 2.0, 4.0, 1.0), t.updated_at DESC
+                     LIMIT ?4"
+                )
+                .bind(match_query)
+                .bind(org_id)
+                .bind(project_id)
+                .bind(limit as i64)
+                .fetch_all(&pool)
+                .await
+            })
+            .await?;
+
+        Ok(tickets)
+    }
+
+    pub async fn set_ticket_assignee(&self, ticket_id: &str, assignee_id: Option<&str>) -> Result<()> {
+        let now = Utc::now().to_rfc3339();
+        sqlx::query("UPDATE tickets SET assignee_id = ?1, updated_at = ?2 WHERE id = ?3")
+            .bind(assignee_id)
+            .bind(now)
+            .bind(ticket_id)
+            .execute(&self.pool)
+            .await?;
+
+        self.ticket_cache.invalidate(ticket_id);
+        Ok(())
+    }
+
+    pub async fn set_ticket_mode(&self, ticket_id: &str, mode: AnalysisMode) -> Result<()> {
+        let now = Utc::now().to_rfc3339();
+        sqlx::query("UPDATE tickets SET mode = ?1, updated_at = ?2 WHERE id = ?3")
+            .bind(mode)
+            .bind(now)
+            .bind(ticket_id)
+            .execute(&self.pool)
+            .await?;
+
+        self.ticket_cache.invalidate(ticket_id);
+        Ok(())
+    }
+
+    /// IDs and positions of a project's tickets with `status`, in board order
+    pub async fn list_ticket_column(&self, project_id: &str, status: &str) -> Result<Vec<(String, f64)>> {
+        let column = sqlx::query_as::<_, (String, f64)>(
+            "SELECT id, position FROM tickets WHERE project_id = ?1 AND status = ?2
+             ORDER BY position ASC, created_at DESC"
+        )
+        .bind(project_id)
+        .bind(status)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(column)
+    }
+
+    /// Put tickets into the `status` column at the given positions, in one transaction
+    pub async fn set_ticket_positions(&self, status: &str, positions: &[(String, f64)]) -> Result<()> {
+        let now = Utc::now().to_rfc3339();
+        let mut tx = self.pool.begin().await?;
+        for (ticket_id, position) in positions {
+            sqlx::query(
+                "UPDATE tickets
+                 SET position = ?1, updated_at = CASE WHEN status = ?2 THEN updated_at ELSE ?3 END, status = ?2
+                 WHERE id = ?4"
+            )
+            .bind(position)
+            .bind(status)
+            .bind(&now)
+            .bind(ticket_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        for (ticket_id, _) in positions {
+            self.ticket_cache.invalidate(ticket_id);
+        }
+        Ok(())
+    }
+
+    /// Organization owning a ticket (through its project), served from the lookup caches
+    pub async fn ticket_org_id(&self, ticket_id: &str) -> Result<Option<String>> {
+        let Some(ticket) = self.get_ticket(ticket_id).await? else {
+            return Ok(None);
+        };
+        Ok(self.get_project(&ticket.project_id).await?.map(|p| p.org_id))
+    }
+
+    pub async fn delete_ticket(&self, id: &str) -> Result<()> {
+        let blobs: Vec<String> = sqlx::query_scalar(
+            "SELECT analysis_result_blob FROM tickets WHERE id = ?1 AND analysis_result_blob IS NOT NULL
+             UNION ALL
+             SELECT raw_log_blob FROM structured_logs WHERE ticket_id = ?1 AND raw_log_blob IS NOT NULL
+             UNION ALL
+             SELECT st.transcript_blob FROM session_transcripts st
+             JOIN analysis_sessions s ON s.id = st.session_id
+             WHERE s.ticket_id = ?1 AND st.transcript_blob IS NOT NULL"
+        )
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        sqlx::query("DELETE FROM tickets WHERE id = ?1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        self.ticket_cache.invalidate(id);
+        self.delete_blobs(blobs).await;
+        Ok(())
+    }
+
+    // Log operations
+    /// The log as stored: an oversized `raw_log` moved to a blob, leaving its preview
+    async fn offload_raw_log(&self, log: &StructuredLogRecord) -> Result<StructuredLogRecord> {
+        let mut log = log.clone();
+        if let Some(raw_log) = &log.raw_log {
+            let (stored, blob) = self
+                .store_large(|| format!("logs/{}/{}.json", log.ticket_id, log.id), raw_log)
+                .await?;
+            if blob.is_some() {
+                log.raw_log = Some(stored);
+                log.raw_log_blob = blob;
+            }
+        }
+        Ok(log)
+    }
+
+    pub async fn save_log(&self, log: &StructuredLogRecord) -> Result<()> {
+        let log = self.offload_raw_log(log).await?;
+        sqlx::query(
+            r#"
+            INSERT INTO structured_logs (id, ticket_id, message_type, content, raw_log, metadata, timestamp, raw_log_blob)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+            ON CONFLICT(id) DO UPDATE SET
+                content = excluded.content,
+                raw_log = excluded.raw_log,
+                metadata = excluded.metadata,
+                raw_log_blob = excluded.raw_log_blob
+            "#,
+        )
+        .bind(&log.id)
+        .bind(&log.ticket_id)
+        .bind(&log.message_type)
+        .bind(&log.content)
+        .bind(&log.raw_log)
+        .bind(&log.metadata)
+        .bind(&log.timestamp)
+        .bind(&log.raw_log_blob)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Insert the logs; one already saved under the same ID (a compacted run growing) is updated
+    pub async fn save_logs_batch(&self, logs: &[StructuredLogRecord]) -> Result<()> {
+        if logs.is_empty() {
+            return Ok(());
+        }
+
+        // Blobs are written first so the transaction stays short
+        let mut stored = Vec::with_capacity(logs.len());
+        for log in logs {
+            stored.push(self.offload_raw_log(log).await?);
+        }
+
+        // Use a transaction for batch insert
+        let mut tx = self.pool.begin().await?;
+
+        for log in &stored {
+            sqlx::query(
+                r#"
+                INSERT INTO structured_logs (id, ticket_id, message_type, content, raw_log, metadata, timestamp, raw_log_blob)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+                ON CONFLICT(id) DO UPDATE SET
+                    content = excluded.content,
+                    raw_log = excluded.raw_log,
+                    metadata = excluded.metadata,
+                    raw_log_blob = excluded.raw_log_blob
+                "#,
+            )
+            .bind(&log.id)
+            .bind(&log.ticket_id)
+            .bind(&log.message_type)
+            .bind(&log.content)
+            .bind(&log.raw_log)
+            .bind(&log.metadata)
+            .bind(&log.timestamp)
+            .bind(&log.raw_log_blob)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    pub async fn count_logs_for_ticket(&self, ticket_id: &str) -> Result<u64> {
+        let count: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM structured_logs WHERE ticket_id = ?1"
+        )
+        .bind(ticket_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(count as u64)
+    }
+
+    /// `(ticket_id, log count)` of the given tickets that have logs
+    pub async fn count_logs_by_tickets(&self, ticket_ids: &[String]) -> Result<Vec<(String, i64)>> {
+        let counts = sqlx::query_as::<_, (String, i64)>(
+            "SELECT ticket_id, COUNT(*) FROM structured_logs
+             WHERE ticket_id IN (SELECT value FROM json_each(?1))
+             GROUP BY ticket_id"
+        )
+        .bind(serde_json::to_string(ticket_ids)?)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(counts)
+    }
+
+    /// Logs of a ticket with `from <= timestamp < until` (either bound optional), oldest first
+    pub async fn get_logs_in_range(
+        &self,
+        ticket_id: &str,
+        from: Option<&str>,
+        until: Option<&str>,
+        limit: u64,
+    ) -> Result<Vec<StructuredLogRecord>> {
+        let logs = sqlx::query_as::<_, StructuredLogRecord>(
+            "SELECT id, ticket_id, message_type, content, raw_log, metadata, timestamp, raw_log_blob
+             FROM structured_logs
+             WHERE ticket_id = ?1 AND (?2 IS NULL OR timestamp >= ?2) AND (?3 IS NULL OR timestamp < ?3)
+             ORDER BY timestamp ASC
+             LIMIT ?4"
+        )
+        .bind(ticket_id)
+        .bind(from)
+        .bind(until)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(logs)
+    }
+
+    pub async fn get_logs_for_ticket(
+        &self,
+        ticket_id: &str,
+        limit: Option<u64>,
+        offset: Option<u64>,
+    ) -> Result<Vec<StructuredLogRecord>> {
+        // Ensure limit is always valid: minimum 1, maximum 1000, default 100
+        let limit = limit.unwrap_or(100).clamp(1, 1000);
+        let offset = offset.unwrap_or(0);
+
+        tracing::debug!(
+            "get_logs_for_ticket: ticket_id={}, limit={}, offset={}",
+            ticket_id,
+            limit,
+            offset
+        );
+
+        let logs = sqlx::query(
+            "SELECT id, ticket_id, message_type, content, raw_log, metadata, timestamp, raw_log_blob
+             FROM structured_logs 
+             WHERE ticket_id = ?1 
+             ORDER BY timestamp ASC 
+             LIMIT ?2 OFFSET ?3"
+        )
+        .bind(ticket_id)
+        .bind(limit as i64)
+        .bind(offset as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut result = Vec::new();
+        for row in logs {
+            result.push(StructuredLogRecord {
+                id: row.get("id"),
+                ticket_id: row.get("ticket_id"),
+                message_type: row.get("message_type"),
+                content: row.get("content"),
+                raw_log: row.get("raw_log"),
+                metadata: row.get("metadata"),
+                timestamp: row.get("timestamp"),
+                raw_log_blob: row.get("raw_log_blob"),
+            });
+        }
+
+        Ok(result)
+    }
+
+    /// Logs of all tickets of a project matching `filter`, by timestamp in `order`
+    pub async fn query_project_logs(
+        &self,
+        project_id: &str,
+        filter: &LogFilter,
+        order: LogOrder,
+        limit: u64,
+        offset: u64,
+    ) -> Result<Vec<StructuredLogRecord>> {
+        let sql = &format!(
+            "SELECT l.id, l.ticket_id, l.message_type, l.content, l.raw_log, l.metadata, l.timestamp, l.raw_log_blob
+             FROM structured_logs l
+             JOIN tickets t ON t.id = l.ticket_id
+             WHERE {filter}
+             ORDER BY l.timestamp {order}, l.id {order}
+             LIMIT ?7 OFFSET ?8",
+            filter = PROJECT_LOGS_WHERE,
+            order = order.as_sql(),
+        );
+        let message_types = &(!filter.message_types.is_empty()).then(|| filter.message_types.join(","));
+
+        let logs = self
+            .read_replica(|pool| async move {
+                sqlx::query_as::<_, StructuredLogRecord>(sql)
+                    .bind(project_id)
+                    .bind(message_types)
+                    .bind(&filter.ticket_id)
+                    .bind(&filter.from)
+                    .bind(&filter.until)
+                    .bind(&filter.search)
+                    .bind(limit as i64)
+                    .bind(offset as i64)
+                    .fetch_all(&pool)
+                    .await
+            })
+            .await?;
+
+        Ok(logs)
+    }
+
+    /// Number of a project's logs matching `filter`, per message type
+    pub async fn count_project_logs_by_type(&self, project_id: &str, filter: &LogFilter) -> Result<Vec<(String, u64)>> {
+        let sql = &format!(
+            "SELECT l.message_type, COUNT(*)
+             FROM structured_logs l
+             JOIN tickets t ON t.id = l.ticket_id
+             WHERE {filter}
+             GROUP BY l.message_type
+             ORDER BY l.message_type",
+            filter = PROJECT_LOGS_WHERE,
+        );
+        let message_types = &(!filter.message_types.is_empty()).then(|| filter.message_types.join(","));
+
+        let counts = self
+            .read_replica(|pool| async move {
+                sqlx::query_as::<_, (String, i64)>(sql)
+                    .bind(project_id)
+                    .bind(message_types)
+                    .bind(&filter.ticket_id)
+                    .bind(&filter.from)
+                    .bind(&filter.until)
+                    .bind(&filter.search)
+                    .fetch_all(&pool)
+                    .await
+            })
+            .await?;
+
+        Ok(counts.into_iter().map(|(message_type, count)| (message_type, count as u64)).collect())
+    }
+
+    pub async fn get_log(&self, id: &str) -> Result<Option<StructuredLogRecord>> {
+        let log = sqlx::query_as::<_, StructuredLogRecord>(
+            "SELECT id, ticket_id, message_type, content, raw_log, metadata, timestamp, raw_log_blob
+             FROM structured_logs
+             WHERE id = ?1"
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(log)
+    }
+
+    /// Up to `limit` of the ticket's logs stamped after `after_timestamp`, oldest first
+    pub async fn get_logs_after(
+        &self,
+        ticket_id: &str,
+        after_timestamp: &str,
+        limit: u64,
+    ) -> Result<Vec<StructuredLogRecord>> {
+        let logs = sqlx::query_as::<_, StructuredLogRecord>(
+            "SELECT id, ticket_id, message_type, content, raw_log, metadata, timestamp, raw_log_blob
+             FROM structured_logs
+             WHERE ticket_id = ?1 AND timestamp > ?2
+             ORDER BY timestamp ASC
+             LIMIT ?3"
+        )
+        .bind(ticket_id)
+        .bind(after_timestamp)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(logs)
+    }
+
+    pub async fn clear_logs_for_ticket(&self, ticket_id: &str) -> Result<()> {
+        let blobs: Vec<Option<String>> = sqlx::query_scalar(
+            "DELETE FROM structured_logs WHERE ticket_id = ?1 RETURNING raw_log_blob"
+        )
+        .bind(ticket_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        self.delete_blobs(blobs.into_iter().flatten()).await;
+        Ok(())
+    }
+
+    // Analysis session operations
+    pub async fn create_session(
+        &self,
+        ticket_id: &str,
+        agent_type: &str,
+        run_id: Option<&str>,
+        tool_policy: &ToolPolicy,
+        mode: AnalysisMode,
+    ) -> Result<String> {
+        let session_id = uuid::Uuid::new_v4().to_string();
+        let started_at = Utc::now().to_rfc3339();
+
+        sqlx::query(
+            r#"
+            INSERT INTO analysis_sessions (id, ticket_id, started_at, status, agent_type, run_id, tool_policy, mode)
+            VALUES (?1, ?2, ?3, 'running', ?4, ?5, ?6, ?7)
+            "#,
+        )
+        .bind(&session_id)
+        .bind(ticket_id)
+        .bind(started_at)
+        .bind(agent_type)
+        .bind(run_id)
+        .bind(serde_json::to_string(tool_policy)?)
+        .bind(mode)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(session_id)
+    }
+
+    pub async fn complete_session(&self, session_id: &str, _result: &str) -> Result<()> {
+        let completed_at = Utc::now().to_rfc3339();
+
+        sqlx::query(
+            r#"
+            UPDATE analysis_sessions
+            SET status = 'completed', completed_at = ?1
+            WHERE id = ?2
+            "#,
+        )
+        .bind(completed_at)
+        .bind(session_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn fail_session(&self, session_id: &str, error: &str) -> Result<()> {
+        let completed_at = Utc::now().to_rfc3339();
+
+        sqlx::query(
+            r#"
+            UPDATE analysis_sessions
+            SET status = 'failed', completed_at = ?1, error_message = ?2
+            WHERE id = ?3
+            "#,
+        )
+        .bind(completed_at)
+        .bind(error)
+        .bind(session_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn cancel_session(&self, session_id: &str, reason: &str) -> Result<()> {
+        let completed_at = Utc::now().to_rfc3339();
+
+        sqlx::query(
+            r#"
+            UPDATE analysis_sessions
+            SET status = 'cancelled', completed_at = ?1, error_message = ?2
+            WHERE id = ?3
+            "#,
+        )
+        .bind(completed_at)
+        .bind(reason)
+        .bind(session_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_active_session_by_ticket(&self, ticket_id: &str) -> Result<Option<AnalysisSession>> {
+        let session = sqlx::query_as::<_, AnalysisSession>(
+            "SELECT * FROM analysis_sessions 
+             WHERE ticket_id = ?1 AND status = 'running' 
+             ORDER BY started_at DESC LIMIT 1"
+        )
+        .bind(ticket_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(session)
+    }
+
+    pub async fn get_latest_session_by_ticket(&self, ticket_id: &str) -> Result<Option<AnalysisSession>> {
+        let session = sqlx::query_as::<_, AnalysisSession>(
+            "SELECT * FROM analysis_sessions
+             WHERE ticket_id = ?1
+             ORDER BY started_at DESC LIMIT 1"
+        )
+        .bind(ticket_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(session)
+    }
+
+    /// The most recent session of each of the given tickets that has one
+    pub async fn get_latest_sessions_by_tickets(&self, ticket_ids: &[String]) -> Result<Vec<AnalysisSession>> {
+        let sessions = sqlx::query_as::<_, AnalysisSession>(
+            "SELECT * FROM (
+                 SELECT *, ROW_NUMBER() OVER (PARTITION BY ticket_id ORDER BY started_at DESC) AS latest
+                 FROM analysis_sessions
+                 WHERE ticket_id IN (SELECT value FROM json_each(?1))
+             )
+             WHERE latest = 1"
+        )
+        .bind(serde_json::to_string(ticket_ids)?)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(sessions)
+    }
+
+    /// The ticket's most recent completed session, the one its analysis result comes from
+    pub async fn get_latest_completed_session(&self, ticket_id: &str) -> Result<Option<AnalysisSession>> {
+        let session = sqlx::query_as::<_, AnalysisSession>(
+            "SELECT * FROM analysis_sessions
+             WHERE ticket_id = ?1 AND status = 'completed'
+             ORDER BY started_at DESC LIMIT 1",
+        )
+        .bind(ticket_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(session)
+    }
+
+    pub async fn get_session_by_run_id(&self, ticket_id: &str, run_id: &str) -> Result<Option<AnalysisSession>> {
+        let session = sqlx::query_as::<_, AnalysisSession>(
+            "SELECT * FROM analysis_sessions WHERE ticket_id = ?1 AND run_id = ?2"
+        )
+        .bind(ticket_id)
+        .bind(run_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(session)
+    }
+
+    pub async fn get_session(&self, session_id: &str) -> Result<Option<AnalysisSession>> {
+        let session = sqlx::query_as::<_, AnalysisSession>("SELECT * FROM analysis_sessions WHERE id = ?1")
+            .bind(session_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(session)
+    }
+
+    /// Store the session's transcript, replacing any earlier one; the gzipped lines go to the
+    /// blob store when they are over the inline limit
+    pub async fn save_session_transcript(&self, record: &SessionTranscriptRecord) -> Result<()> {
+        let mut record = record.clone();
+        if let (Some(blobs), Some(transcript)) = (&self.blobs, &record.transcript) {
+            if transcript.len() > blobs.config.inline_max_bytes {
+                let key = format!("transcripts/{}.jsonl.gz", record.session_id);
+                blobs.store.put(&key, transcript.clone()).await?;
+                record.transcript = None;
+                record.transcript_blob = Some(key);
+            }
+        }
+
+        sqlx::query(
+            r#"
+            INSERT OR REPLACE INTO session_transcripts
+                (session_id, prompt, argv, env_names, transcript, transcript_blob, transcript_size, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+            "#,
+        )
+        .bind(&record.session_id)
+        .bind(&record.prompt)
+        .bind(&record.argv)
+        .bind(&record.env_names)
+        .bind(&record.transcript)
+        .bind(&record.transcript_blob)
+        .bind(record.transcript_size)
+        .bind(&record.created_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_session_transcript(&self, session_id: &str) -> Result<Option<SessionTranscriptRecord>> {
+        let record = sqlx::query_as::<_, SessionTranscriptRecord>(
+            "SELECT * FROM session_transcripts WHERE session_id = ?1"
+        )
+        .bind(session_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(record)
+    }
+
+    /// The gzipped transcript lines, read back from their blob when they were offloaded
+    pub async fn read_session_transcript(&self, record: &SessionTranscriptRecord) -> Result<Vec<u8>> {
+        match (&record.transcript, &record.transcript_blob) {
+            (Some(transcript), _) => Ok(transcript.clone()),
+            (None, Some(key)) => {
+                let blobs = self.blobs.as_ref().context("Blob store is disabled")?;
+                blobs.store.read(key).await?.with_context(|| format!("Blob {} is missing", key))
+            }
+            (None, None) => Ok(Vec::new()),
+        }
+    }
+
+    /// The ticket's sessions, newest first
+    pub async fn list_sessions_by_ticket(&self, ticket_id: &str) -> Result<Vec<AnalysisSession>> {
+        let sessions = sqlx::query_as::<_, AnalysisSession>(
+            "SELECT * FROM analysis_sessions WHERE ticket_id = ?1 ORDER BY started_at DESC",
+        )
+        .bind(ticket_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(sessions)
+    }
+
+    pub async fn record_session_environment(&self, session_id: &str, environment: &RunEnvironment) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE analysis_sessions
+            SET cli_version = ?1, model = ?2, git_commit = ?3, prompt_hash = ?4, config_snapshot = ?5,
+                cost_usd = ?7, tokens = ?8
+            WHERE id = ?6
+            "#,
+        )
+        .bind(&environment.cli_version)
+        .bind(&environment.model)
+        .bind(&environment.git_commit)
+        .bind(&environment.prompt_hash)
+        .bind(serde_json::to_string(&environment.config)?)
+        .bind(session_id)
+        .bind(environment.cost_usd)
+        .bind(environment.tokens)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Keep the request as the agent received it, which session it replays and the experiment
+    /// variant it ran
+    pub async fn record_session_request(
+        &self,
+        session_id: &str,
+        request: &CodeAnalysisRequest,
+        replay_of: Option<&str>,
+    ) -> Result<()> {
+        // Replays reuse their variant's prompt but don't count toward the experiment
+        let variant = request.prompt_variant.as_ref().filter(|_| replay_of.is_none());
+        sqlx::query(
+            "UPDATE analysis_sessions SET request_json = ?1, replay_of = ?2, experiment_id = ?3, variant_id = ?4
+             WHERE id = ?5"
+        )
+        .bind(serde_json::to_string(request)?)
+        .bind(replay_of)
+        .bind(variant.map(|v| &v.experiment_id))
+        .bind(variant.map(|v| &v.variant_id))
+        .bind(session_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Start of the ticket's first session after `started_at`, i.e. where that run's logs end
+    pub async fn get_next_session_start(&self, ticket_id: &str, started_at: &str) -> Result<Option<String>> {
+        let next = sqlx::query_scalar::<_, Option<String>>(
+            "SELECT MIN(started_at) FROM analysis_sessions WHERE ticket_id = ?1 AND started_at > ?2"
+        )
+        .bind(ticket_id)
+        .bind(started_at)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(next)
+    }
+
+    pub async fn record_session_stage(&self, session_id: &str, stage: &str) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO analysis_session_stages (session_id, stage, entered_at) VALUES (?1, ?2, ?3)"
+        )
+        .bind(session_id)
+        .bind(stage)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_session_stages(&self, session_id: &str) -> Result<Vec<SessionStageRecord>> {
+        let stages = sqlx::query_as::<_, SessionStageRecord>(
+            "SELECT session_id, stage, entered_at FROM analysis_session_stages
+             WHERE session_id = ?1
+             ORDER BY id ASC"
+        )
+        .bind(session_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(stages)
+    }
+
+    // Test case operations
+    /// Replace all test cases of a ticket with a freshly generated set
+    pub async fn replace_test_cases(&self, ticket_id: &str, cases: &[TestCaseRecord]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM test_cases WHERE ticket_id = ?1")
+            .bind(ticket_id)
+            .execute(&mut *tx)
+            .await?;
+
+        for case in cases {
+            sqlx::query(
+                r#"
+                INSERT INTO test_cases (id, ticket_id, run_id, position, title, preconditions, steps, expected, priority, flow, created_at)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
+                "#,
+            )
+            .bind(&case.id)
+            .bind(&case.ticket_id)
+            .bind(&case.run_id)
+            .bind(case.position)
+            .bind(&case.title)
+            .bind(serde_json::to_string(&case.preconditions)?)
+            .bind(serde_json::to_string(&case.steps)?)
+            .bind(&case.expected)
+            .bind(&case.priority)
+            .bind(&case.flow)
+            .bind(&case.created_at)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    pub async fn list_test_cases(&self, ticket_id: &str) -> Result<Vec<TestCaseRecord>> {
+        let rows = sqlx::query(
+            "SELECT id, ticket_id, run_id, position, title, preconditions, steps, expected, priority, flow, created_at
+             FROM test_cases
+             WHERE ticket_id = ?1
+             ORDER BY position ASC",
+        )
+        .bind(ticket_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(TestCaseRecord {
+                    id: row.get("id"),
+                    ticket_id: row.get("ticket_id"),
+                    run_id: row.get("run_id"),
+                    position: row.get("position"),
+                    title: row.get("title"),
+                    preconditions: serde_json::from_str(row.get("preconditions"))?,
+                    steps: serde_json::from_str(row.get("steps"))?,
+                    expected: row.get("expected"),
+                    priority: row.get("priority"),
+                    flow: row.get("flow"),
+                    created_at: row.get("created_at"),
+                })
+            })
+            .collect()
+    }
+
+    /// Replace the ticket's diagram with a new run's
+    pub async fn replace_ticket_diagram(&self, diagram: &TicketDiagramRecord) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO ticket_diagrams (ticket_id, run_id, kind, source, svg, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+            ON CONFLICT(ticket_id) DO UPDATE SET
+                run_id = excluded.run_id,
+                kind = excluded.kind,
+                source = excluded.source,
+                svg = excluded.svg,
+                created_at = excluded.created_at
+            "#,
+        )
+        .bind(&diagram.ticket_id)
+        .bind(&diagram.run_id)
+        .bind(&diagram.kind)
+        .bind(&diagram.source)
+        .bind(&diagram.svg)
+        .bind(&diagram.created_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_ticket_diagram(&self, ticket_id: &str) -> Result<Option<TicketDiagramRecord>> {
+        let diagram = sqlx::query_as::<_, TicketDiagramRecord>("SELECT * FROM ticket_diagrams WHERE ticket_id = ?1")
+            .bind(ticket_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(diagram)
+    }
+
+    /// Cache the SVG of the diagram, unless a newer run replaced it meanwhile
+    pub async fn set_ticket_diagram_svg(&self, ticket_id: &str, created_at: &str, svg: &str) -> Result<()> {
+        sqlx::query("UPDATE ticket_diagrams SET svg = ?1 WHERE ticket_id = ?2 AND created_at = ?3")
+            .bind(svg)
+            .bind(ticket_id)
+            .bind(created_at)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Store a rating; a signed-in user rating the same session again replaces their rating
+    pub async fn upsert_result_feedback(&self, feedback: &ResultFeedbackRecord) -> Result<ResultFeedbackRecord> {
+        let stored: Vec<ResultFeedbackRecord> = sqlx::query_as(
+            r#"
+            INSERT INTO result_feedback (id, ticket_id, session_id, user_id, rating, comment, created_at, updated_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+            ON CONFLICT(session_id, user_id) DO UPDATE SET
+                rating = excluded.rating,
+                comment = excluded.comment,
+                updated_at = excluded.updated_at
+            RETURNING *
+            "#,
+        )
+        .bind(&feedback.id)
+        .bind(&feedback.ticket_id)
+        .bind(&feedback.session_id)
+        .bind(&feedback.user_id)
+        .bind(feedback.rating)
+        .bind(&feedback.comment)
+        .bind(&feedback.created_at)
+        .bind(&feedback.updated_at)
+        .fetch_all(&self.pool)
+        .await?;
+
+        stored.into_iter().next().context("INSERT returned no feedback")
+    }
+
+    /// The ticket's ratings, newest first
+    pub async fn list_result_feedback(&self, ticket_id: &str) -> Result<Vec<ResultFeedbackRecord>> {
+        let feedback = sqlx::query_as::<_, ResultFeedbackRecord>(
+            "SELECT * FROM result_feedback WHERE ticket_id = ?1 ORDER BY updated_at DESC",
+        )
+        .bind(ticket_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(feedback)
+    }
+
+    /// Add a job to the shared queue, replacing one still waiting for the same ticket
+    pub async fn enqueue_job(&self, job: &AnalysisJobRecord) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM analysis_jobs WHERE ticket_id = ?1 AND status = 'queued'")
+            .bind(&job.ticket_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            "INSERT INTO analysis_jobs
+                (ticket_id, run_id, priority, request_json, follow_ups_json, requested_by, status, enqueued_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, 'queued', ?7)"
+        )
+        .bind(&job.ticket_id)
+        .bind(&job.run_id)
+        .bind(job.priority)
+        .bind(&job.request_json)
+        .bind(&job.follow_ups_json)
+        .bind(&job.requested_by)
+        .bind(&job.enqueued_at)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Claim the highest-priority, longest-waiting job for `worker_id`, or a claimed one whose
+    /// worker last heartbeated before `stale_before`. A single UPDATE, so concurrent workers
+    /// never claim the same job.
+    pub async fn claim_job(&self, worker_id: &str, stale_before: &str) -> Result<Option<AnalysisJobRecord>> {
+        let now = Utc::now().to_rfc3339();
+        // `fetch_all` throughout for RETURNING statements, see `create_ticket`
+        let job: Vec<AnalysisJobRecord> = sqlx::query_as(
+            r#"
+            UPDATE analysis_jobs
+            SET status = 'claimed', worker_id = ?1, claimed_at = ?2, heartbeat_at = ?2
+            WHERE seq = (
+                SELECT seq FROM analysis_jobs
+                WHERE status = 'queued' OR (status = 'claimed' AND heartbeat_at < ?3)
+                ORDER BY priority DESC, seq ASC
+                LIMIT 1
+            )
+            RETURNING *
+            "#,
+        )
+        .bind(worker_id)
+        .bind(&now)
+        .bind(stale_before)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(job.into_iter().next())
+    }
+
+    /// The ticket's latest job in the shared queue, and its 1-based place while queued
+    pub async fn find_job(&self, ticket_id: &str) -> Result<Option<(AnalysisJobRecord, Option<i64>)>> {
+        let Some(job) = sqlx::query_as::<_, AnalysisJobRecord>(
+            "SELECT * FROM analysis_jobs WHERE ticket_id = ?1 ORDER BY seq DESC LIMIT 1",
+        )
+        .bind(ticket_id)
+        .fetch_optional(&self.pool)
+        .await?
+        else {
+            return Ok(None);
+        };
+        if job.status != "queued" {
+            return Ok(Some((job, None)));
+        }
+
+        let ahead = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM analysis_jobs
+             WHERE status = 'queued' AND (priority > ?1 OR (priority = ?1 AND seq < ?2))",
+        )
+        .bind(job.priority)
+        .bind(job.seq)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(Some((job, Some(ahead + 1))))
+    }
+
+    /// Drop the ticket's job if no worker has claimed it yet
+    pub async fn remove_queued_job(&self, ticket_id: &str) -> Result<Option<AnalysisJobRecord>> {
+        let job: Vec<AnalysisJobRecord> = sqlx::query_as(
+            "DELETE FROM analysis_jobs WHERE ticket_id = ?1 AND status = 'queued' RETURNING *",
+        )
+        .bind(ticket_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(job.into_iter().next())
+    }
+
+    /// Ask the worker running the ticket's job to stop it; returns the run ID
+    pub async fn request_job_cancel(&self, ticket_id: &str) -> Result<Option<String>> {
+        let run_id: Option<String> = sqlx::query_scalar(
+            "UPDATE analysis_jobs SET cancel_requested = 1 WHERE ticket_id = ?1 AND status = 'claimed' RETURNING run_id",
+        )
+        .bind(ticket_id)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .next();
+
+        Ok(run_id)
+    }
+
+    pub async fn clear_job_cancel(&self, seq: i64) -> Result<()> {
+        sqlx::query("UPDATE analysis_jobs SET cancel_requested = 0 WHERE seq = ?1")
+            .bind(seq)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Jobs claimed by `worker_id`, with their heartbeat renewed
+    pub async fn heartbeat_jobs(&self, worker_id: &str) -> Result<Vec<AnalysisJobRecord>> {
+        let jobs = sqlx::query_as::<_, AnalysisJobRecord>(
+            "UPDATE analysis_jobs SET heartbeat_at = ?1 WHERE worker_id = ?2 AND status = 'claimed' RETURNING *",
+        )
+        .bind(Utc::now().to_rfc3339())
+        .bind(worker_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(jobs)
+    }
+
+    pub async fn delete_job(&self, seq: i64) -> Result<()> {
+        sqlx::query("DELETE FROM analysis_jobs WHERE seq = ?1")
+            .bind(seq)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn append_stream_events(&self, events: &[StreamEventRecord]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for event in events {
+            sqlx::query(
+                "INSERT INTO stream_events (origin, kind, ticket_id, org_id, payload, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)"
+            )
+            .bind(&event.origin)
+            .bind(&event.kind)
+            .bind(&event.ticket_id)
+            .bind(&event.org_id)
+            .bind(&event.payload)
+            .bind(&event.created_at)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Events after `seq` from other processes than `origin`, oldest first
+    pub async fn get_stream_events_after(&self, seq: i64, origin: &str, limit: u32) -> Result<Vec<StreamEventRecord>> {
+        let events = sqlx::query_as::<_, StreamEventRecord>(
+            "SELECT * FROM stream_events WHERE seq > ?1 AND origin != ?2 ORDER BY seq ASC LIMIT ?3",
+        )
+        .bind(seq)
+        .bind(origin)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(events)
+    }
+
+    pub async fn latest_stream_event_seq(&self) -> Result<i64> {
+        let seq = sqlx::query_scalar::<_, Option<i64>>("SELECT MAX(seq) FROM stream_events")
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(seq.unwrap_or(0))
+    }
+
+    /// Drop relayed events older than `before`; returns how many
+    pub async fn prune_stream_events(&self, before: &str) -> Result<u64> {
+        let result = sqlx::query("DELETE FROM stream_events WHERE created_at < ?1")
+            .bind(before)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Store an experiment with its variants
+    pub async fn create_experiment(
+        &self,
+        experiment: &ExperimentRecord,
+        variants: &[PromptVariantRecord],
+    ) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "INSERT INTO prompt_experiments (id, project_id, name, status, created_by, created_at, stopped_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)"
+        )
+        .bind(&experiment.id)
+        .bind(&experiment.project_id)
+        .bind(&experiment.name)
+        .bind(&experiment.status)
+        .bind(&experiment.created_by)
+        .bind(&experiment.created_at)
+        .bind(&experiment.stopped_at)
+        .execute(&mut *tx)
+        .await?;
+
+        for variant in variants {
+            sqlx::query(
+                "INSERT INTO prompt_variants (id, experiment_id, position, name, template, weight)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)"
+            )
+            .bind(&variant.id)
+            .bind(&variant.experiment_id)
+            .bind(variant.position)
+            .bind(&variant.name)
+            .bind(&variant.template)
+            .bind(variant.weight)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    pub async fn get_experiment(&self, id: &str) -> Result<Option<ExperimentRecord>> {
+        let experiment = sqlx::query_as::<_, ExperimentRecord>("SELECT * FROM prompt_experiments WHERE id = ?1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(experiment)
+    }
+
+    /// A project's experiments, newest first
+    pub async fn list_experiments(&self, project_id: &str) -> Result<Vec<ExperimentRecord>> {
+        let experiments = sqlx::query_as::<_, ExperimentRecord>(
+            "SELECT * FROM prompt_experiments WHERE project_id = ?1 ORDER BY created_at DESC",
+        )
+        .bind(project_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(experiments)
+    }
+
+    pub async fn get_running_experiment(&self, project_id: &str) -> Result<Option<ExperimentRecord>> {
+        let experiment = sqlx::query_as::<_, ExperimentRecord>(
+            "SELECT * FROM prompt_experiments WHERE project_id = ?1 AND status = 'running'",
+        )
+        .bind(project_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(experiment)
+    }
+
+    pub async fn list_prompt_variants(&self, experiment_id: &str) -> Result<Vec<PromptVariantRecord>> {
+        let variants = sqlx::query_as::<_, PromptVariantRecord>(
+            "SELECT * FROM prompt_variants WHERE experiment_id = ?1 ORDER BY position ASC",
+        )
+        .bind(experiment_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(variants)
+    }
+
+    /// Stop a running experiment; false when it was already stopped
+    pub async fn stop_experiment(&self, id: &str, stopped_at: &str) -> Result<bool> {
+        let result = sqlx::query(
+            "UPDATE prompt_experiments SET status = 'stopped', stopped_at = ?1 WHERE id = ?2 AND status = 'running'",
+        )
+        .bind(stopped_at)
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn get_ticket_labels(&self, ticket_id: &str) -> Result<Vec<String>> {
+        let labels = sqlx::query_scalar::<_, String>(
+            "SELECT label FROM ticket_labels WHERE ticket_id = ?1 ORDER BY label ASC",
+        )
+        .bind(ticket_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(labels)
+    }
+
+    pub async fn set_ticket_labels(&self, ticket_id: &str, labels: &[String]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM ticket_labels WHERE ticket_id = ?1")
+            .bind(ticket_id)
+            .execute(&mut *tx)
+            .await?;
+
+        for label in labels {
+            sqlx::query("INSERT OR IGNORE INTO ticket_labels (ticket_id, label) VALUES (?1, ?2)")
+                .bind(ticket_id)
+                .bind(label)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    // Resolved code context operations
+    /// Replace the files resolved for a ticket's code context
+    pub async fn set_ticket_context_files(&self, ticket_id: &str, files: &[ContextFileRecord]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM ticket_context_files WHERE ticket_id = ?1")
+            .bind(ticket_id)
+            .execute(&mut *tx)
+            .await?;
+
+        for file in files {
+            sqlx::query(
+                r#"
+                INSERT OR IGNORE INTO ticket_context_files (ticket_id, file_path, position, score, reasons, resolved_for, resolved_at)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+                "#,
+            )
+            .bind(ticket_id)
+            .bind(&file.file_path)
+            .bind(file.position)
+            .bind(file.score)
+            .bind(serde_json::to_string(&file.reasons)?)
+            .bind(&file.resolved_for)
+            .bind(&file.resolved_at)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    pub async fn get_ticket_context_files(&self, ticket_id: &str) -> Result<Vec<ContextFileRecord>> {
+        let rows = sqlx::query("SELECT * FROM ticket_context_files WHERE ticket_id = ?1 ORDER BY position ASC")
+            .bind(ticket_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(ContextFileRecord {
+                    ticket_id: row.get("ticket_id"),
+                    file_path: row.get("file_path"),
+                    position: row.get("position"),
+                    score: row.get("score"),
+                    reasons: serde_json::from_str(row.get("reasons"))?,
+                    resolved_for: row.get("resolved_for"),
+                    resolved_at: row.get("resolved_at"),
+                })
+            })
+            .collect()
+    }
+
+    // Ticket link operations
+    pub async fn create_ticket_link(&self, link: &TicketLinkRecord) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO ticket_links (id, source_ticket_id, target_ticket_id, link_type, created_by, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+            "#,
+        )
+        .bind(&link.id)
+        .bind(&link.source_ticket_id)
+        .bind(&link.target_ticket_id)
+        .bind(&link.link_type)
+        .bind(&link.created_by)
+        .bind(&link.created_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_ticket_link(&self, id: &str) -> Result<Option<TicketLinkRecord>> {
+        let link = sqlx::query_as::<_, TicketLinkRecord>("SELECT * FROM ticket_links WHERE id = ?1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(link)
+    }
+
+    /// Links where the ticket is either end, oldest first
+    pub async fn list_ticket_links(&self, ticket_id: &str) -> Result<Vec<TicketLinkRecord>> {
+        let links = sqlx::query_as::<_, TicketLinkRecord>(
+            "SELECT * FROM ticket_links WHERE source_ticket_id = ?1 OR target_ticket_id = ?1 ORDER BY created_at ASC",
+        )
+        .bind(ticket_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(links)
+    }
+
+    /// Every link between the project's tickets (both ends always share the project)
+    pub async fn list_project_ticket_links(&self, project_id: &str) -> Result<Vec<TicketLinkRecord>> {
+        let links = sqlx::query_as::<_, TicketLinkRecord>(
+            r#"
+            SELECT l.* FROM ticket_links l
+            JOIN tickets t ON t.id = l.source_ticket_id
+            WHERE t.project_id = ?1
+            ORDER BY l.created_at ASC
+            "#,
+        )
+        .bind(project_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(links)
+    }
+
+    pub async fn delete_ticket_link(&self, id: &str) -> Result<()> {
+        sqlx::query("DELETE FROM ticket_links WHERE id = ?1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    // Custom fields
+
+    fn custom_field_from_row(row: &SqliteRow) -> Result<CustomFieldRecord> {
+        Ok(CustomFieldRecord {
+            id: row.get("id"),
+            project_id: row.get("project_id"),
+            name: row.get("name"),
+            field_type: row.get("field_type"),
+            options: serde_json::from_str(row.get("options"))?,
+            created_at: row.get("created_at"),
+        })
+    }
+
+    pub async fn list_custom_fields(&self, project_id: &str) -> Result<Vec<CustomFieldRecord>> {
+        let rows = sqlx::query(
+            "SELECT * FROM custom_fields WHERE project_id = ?1 ORDER BY created_at ASC, name ASC"
+        )
+        .bind(project_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(Self::custom_field_from_row).collect()
+    }
+
+    pub async fn get_custom_field(&self, id: &str) -> Result<Option<CustomFieldRecord>> {
+        let row = sqlx::query("SELECT * FROM custom_fields WHERE id = ?1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        row.as_ref().map(Self::custom_field_from_row).transpose()
+    }
+
+    pub async fn create_custom_field(&self, field: &CustomFieldRecord) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO custom_fields (id, project_id, name, field_type, options, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)"
+        )
+        .bind(&field.id)
+        .bind(&field.project_id)
+        .bind(&field.name)
+        .bind(&field.field_type)
+        .bind(serde_json::to_string(&field.options)?)
+        .bind(&field.created_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn update_custom_field(&self, id: &str, name: &str, options: &[String]) -> Result<()> {
+        sqlx::query("UPDATE custom_fields SET name = ?1, options = ?2 WHERE id = ?3")
+            .bind(name)
+            .bind(serde_json::to_string(options)?)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Delete a field definition along with every ticket's value for it
+    pub async fn delete_custom_field(&self, id: &str) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM ticket_field_values WHERE field_id = ?1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM custom_fields WHERE id = ?1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Stored JSON values of one field across all tickets
+    pub async fn list_field_values(&self, field_id: &str) -> Result<Vec<String>> {
+        let values = sqlx::query_scalar::<_, String>("SELECT value FROM ticket_field_values WHERE field_id = ?1")
+            .bind(field_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(values)
+    }
+
+    /// `(ticket_id, field name, JSON value)` of the given tickets
+    pub async fn list_ticket_field_values(&self, ticket_ids: &[String]) -> Result<Vec<(String, String, String)>> {
+        let values = sqlx::query_as::<_, (String, String, String)>(
+            "SELECT v.ticket_id, f.name, v.value
+             FROM ticket_field_values v
+             JOIN custom_fields f ON f.id = v.field_id
+             WHERE v.ticket_id IN (SELECT value FROM json_each(?1))"
+        )
+        .bind(serde_json::to_string(ticket_ids)?)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(values)
+    }
+
+    /// Set (Some) or clear (None) a ticket's values, keyed by field ID, in one transaction
+    pub async fn set_ticket_field_values(&self, ticket_id: &str, values: &[(String, Option<String>)]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for (field_id, value) in values {
+            match value {
+                Some(value) => {
+                    sqlx::query(
+                        "INSERT INTO ticket_field_values (ticket_id, field_id, value) VALUES (?1, ?2, ?3)
+                         ON CONFLICT(ticket_id, field_id) DO UPDATE SET value = excluded.value"
+                    )
+                    .bind(ticket_id)
+                    .bind(field_id)
+                    .bind(value)
+                    .execute(&mut *tx)
+                    .await?;
+                }
+                None => {
+                    sqlx::query("DELETE FROM ticket_field_values WHERE ticket_id = ?1 AND field_id = ?2")
+                        .bind(ticket_id)
+                        .bind(field_id)
+                        .execute(&mut *tx)
+                        .await?;
+                }
+            }
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    // Project agent credentials
+
+    /// Store a project's encrypted key, replacing the provider's previous one
+    pub async fn upsert_project_credential(&self, credential: &ProjectCredentialRecord) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO project_agent_credentials (project_id, provider, ciphertext, masked_key, updated_by, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT(project_id, provider) DO UPDATE SET
+                ciphertext = excluded.ciphertext,
+                masked_key = excluded.masked_key,
+                updated_by = excluded.updated_by,
+                updated_at = excluded.updated_at"
+        )
+        .bind(&credential.project_id)
+        .bind(&credential.provider)
+        .bind(&credential.ciphertext)
+        .bind(&credential.masked_key)
+        .bind(&credential.updated_by)
+        .bind(&credential.updated_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_project_credential(&self, project_id: &str, provider: &str) -> Result<Option<ProjectCredentialRecord>> {
+        let credential = sqlx::query_as::<_, ProjectCredentialRecord>(
+            "SELECT * FROM project_agent_credentials WHERE project_id = ?1 AND provider = ?2"
+        )
+        .bind(project_id)
+        .bind(provider)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(credential)
+    }
+
+    pub async fn list_project_credentials(&self, project_id: &str) -> Result<Vec<ProjectCredentialRecord>> {
+        let credentials = sqlx::query_as::<_, ProjectCredentialRecord>(
+            "SELECT * FROM project_agent_credentials WHERE project_id = ?1 ORDER BY provider ASC"
+        )
+        .bind(project_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(credentials)
+    }
+
+    pub async fn delete_project_credential(&self, project_id: &str, provider: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM project_agent_credentials WHERE project_id = ?1 AND provider = ?2")
+            .bind(project_id)
+            .bind(provider)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    // Ticket watchers and activity
+
+    /// Start watching a ticket; a user already watching keeps their original reason
+    pub async fn add_ticket_watcher(&self, watcher: &TicketWatcherRecord) -> Result<bool> {
+        let result = sqlx::query(
+            "INSERT OR IGNORE INTO ticket_watchers (ticket_id, user_id, reason, created_at) VALUES (?1, ?2, ?3, ?4)"
+        )
+        .bind(&watcher.ticket_id)
+        .bind(&watcher.user_id)
+        .bind(&watcher.reason)
+        .bind(&watcher.created_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn remove_ticket_watcher(&self, ticket_id: &str, user_id: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM ticket_watchers WHERE ticket_id = ?1 AND user_id = ?2")
+            .bind(ticket_id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn list_ticket_watchers(&self, ticket_id: &str) -> Result<Vec<TicketWatcherRecord>> {
+        let watchers = sqlx::query_as::<_, TicketWatcherRecord>(
+            "SELECT * FROM ticket_watchers WHERE ticket_id = ?1 ORDER BY created_at ASC"
+        )
+        .bind(ticket_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(watchers)
+    }
+
+    pub async fn create_activity(&self, activity: &ActivityRecord) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO ticket_activity (id, ticket_id, kind, actor_id, summary, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)"
+        )
+        .bind(&activity.id)
+        .bind(&activity.ticket_id)
+        .bind(&activity.kind)
+        .bind(&activity.actor_id)
+        .bind(&activity.summary)
+        .bind(&activity.created_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn create_status_change(&self, change: &StatusChangeRecord) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO ticket_status_history (id, ticket_id, from_status, to_status, actor_id, changed_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)"
+        )
+        .bind(&change.id)
+        .bind(&change.ticket_id)
+        .bind(&change.from_status)
+        .bind(&change.to_status)
+        .bind(&change.actor_id)
+        .bind(&change.changed_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Status transitions of the given tickets, oldest first
+    pub async fn list_status_changes(&self, ticket_ids: &[String]) -> Result<Vec<StatusChangeRecord>> {
+        let changes = sqlx::query_as::<_, StatusChangeRecord>(
+            "SELECT * FROM ticket_status_history
+             WHERE ticket_id IN (SELECT value FROM json_each(?1))
+             ORDER BY changed_at ASC"
+        )
+        .bind(serde_json::to_string(ticket_ids)?)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(changes)
+    }
+
+    /// Tickets of the organization (or one project) created in the date range, for analytics
+    pub async fn list_tickets_created_in(&self, filter: &AnalyticsFilter) -> Result<Vec<TicketRecord>> {
+        let tickets = self
+            .read_replica(|pool| async move {
+                sqlx::query_as::<_, TicketRecord>(
+                    "SELECT t.* FROM tickets t
+                     WHERE (?1 IS NULL OR date(t.created_at) >= date(?1))
+                       AND (?2 IS NULL OR date(t.created_at) <= date(?2))
+                       AND (?3 IS NULL OR t.project_id = ?3)
+                       AND (?4 IS NULL OR t.project_id IN (SELECT id FROM projects WHERE org_id = ?4))"
+                )
+                .bind(&filter.from)
+                .bind(&filter.to)
+                .bind(&filter.project_id)
+                .bind(&filter.org_id)
+                .fetch_all(&pool)
+                .await
+            })
+            .await?;
+
+        Ok(tickets)
+    }
+
+    /// Activity of the tickets a user watches in an organization since they started watching,
+    /// leaving out their own actions, newest first; `before` pages back from an entry's `created_at`
+    pub async fn list_user_feed(
+        &self,
+        user_id: &str,
+        org_id: &str,
+        before: Option<&str>,
+        limit: u32,
+    ) -> Result<Vec<FeedEntry>> {
+        let entries = sqlx::query_as::<_, FeedEntry>(
+            "SELECT a.id, a.ticket_id, t.title AS ticket_title, t.project_id, a.kind, a.actor_id, a.summary, a.created_at
+             FROM ticket_activity a
+             JOIN ticket_watchers w ON w.ticket_id = a.ticket_id AND w.user_id = ?1
+             JOIN tickets t ON t.id = a.ticket_id
+             JOIN projects p ON p.id = t.project_id
+             WHERE a.created_at >= w.created_at
+               AND (a.actor_id IS NULL OR a.actor_id != ?1)
+               AND p.org_id = ?2 AND p.deleted_at IS NULL
+               AND (?3 IS NULL OR a.created_at < ?3)
+             ORDER BY a.created_at DESC, a.id DESC
+             LIMIT ?4"
+        )
+        .bind(user_id)
+        .bind(org_id)
+        .bind(before)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(entries)
+    }
+
+    /// When the user last marked their feed read; None if they never did
+    pub async fn get_activity_read_at(&self, user_id: &str) -> Result<Option<String>> {
+        let read_at = sqlx::query_scalar::<_, String>("SELECT read_at FROM activity_reads WHERE user_id = ?1")
+            .bind(user_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(read_at)
+    }
+
+    pub async fn mark_activity_read(&self, user_id: &str, read_at: &str) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO activity_reads (user_id, read_at)
+            VALUES (?1, ?2)
+            ON CONFLICT(user_id) DO UPDATE SET read_at = max(read_at, excluded.read_at)
+            "#,
+        )
+        .bind(user_id)
+        .bind(read_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Feed entries (as `list_user_feed`) newer than the user's read marker, newest first, and
+    /// how many there are in all
+    pub async fn list_unread_feed(&self, user_id: &str, org_id: &str, limit: u32) -> Result<(Vec<FeedEntry>, i64)> {
+        let entries_sql = &format!(
+            "SELECT a.id, a.ticket_id, t.title AS ticket_title, t.project_id, a.kind, a.actor_id, a.summary, a.created_at
+             {from}
+             ORDER BY a.created_at DESC, a.id DESC
+             LIMIT ?3",
+            from = UNREAD_FEED_FROM,
+        );
+        let entries = sqlx::query_as::<_, FeedEntry>(entries_sql)
+            .bind(user_id)
+            .bind(org_id)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?;
+        let total = sqlx::query_scalar::<_, i64>(&format!("SELECT COUNT(*) {}", UNREAD_FEED_FROM))
+            .bind(user_id)
+            .bind(org_id)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok((entries, total))
+    }
+
+    // Project dashboard aggregates
+    pub async fn count_tickets_by_status(&self, project_id: &str) -> Result<Vec<(String, i64)>> {
+        let counts = self
+            .read_replica(|pool| async move {
+                sqlx::query_as::<_, (String, i64)>(
+                    "SELECT status, COUNT(*) FROM tickets WHERE project_id = ?1 GROUP BY status"
+                )
+                .bind(project_id)
+                .fetch_all(&pool)
+                .await
+            })
+            .await?;
+
+        Ok(counts)
+    }
+
+    /// Latest activity on the project's tickets, newest first
+    pub async fn list_project_activity(&self, project_id: &str, limit: u32) -> Result<Vec<FeedEntry>> {
+        let entries = self
+            .read_replica(|pool| async move {
+                sqlx::query_as::<_, FeedEntry>(
+                    "SELECT a.id, a.ticket_id, t.title AS ticket_title, t.project_id, a.kind, a.actor_id, a.summary, a.created_at
+                     FROM ticket_activity a
+                     JOIN tickets t ON t.id = a.ticket_id
+                     WHERE t.project_id = ?1
+                     ORDER BY a.created_at DESC, a.id DESC
+                     LIMIT ?2"
+                )
+                .bind(project_id)
+                .bind(limit)
+                .fetch_all(&pool)
+                .await
+            })
+            .await?;
+
+        Ok(entries)
+    }
+
+    /// Sessions of the project still running, oldest first; read from the primary, which
+    /// replicas may lag behind
+    pub async fn list_running_sessions(&self, project_id: &str) -> Result<Vec<RunningSessionRow>> {
+        let sessions = sqlx::query_as::<_, RunningSessionRow>(
+            "SELECT s.id AS session_id, s.run_id, s.ticket_id, t.title AS ticket_title, t.project_id, s.agent_type, s.started_at
+             FROM analysis_sessions s
+             JOIN tickets t ON t.id = s.ticket_id
+             WHERE t.project_id = ?1 AND s.status = 'running'
+             ORDER BY s.started_at ASC"
+        )
+        .bind(project_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(sessions)
+    }
+
+    /// Sessions still running on any live project of the org, oldest first
+    pub async fn list_org_running_sessions(&self, org_id: &str) -> Result<Vec<RunningSessionRow>> {
+        let sessions = sqlx::query_as::<_, RunningSessionRow>(
+            "SELECT s.id AS session_id, s.run_id, s.ticket_id, t.title AS ticket_title, t.project_id, s.agent_type, s.started_at
+             FROM analysis_sessions s
+             JOIN tickets t ON t.id = s.ticket_id
+             JOIN projects p ON p.id = t.project_id
+             WHERE p.org_id = ?1 AND p.deleted_at IS NULL AND s.status = 'running'
+             ORDER BY s.started_at ASC"
+        )
+        .bind(org_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(sessions)
+    }
+
+    /// Users who created the most tickets of the project and acted the most on them
+    pub async fn list_top_contributors(&self, project_id: &str, limit: u32) -> Result<Vec<ContributorRow>> {
+        let contributors = self
+            .read_replica(|pool| async move {
+                sqlx::query_as::<_, ContributorRow>(
+                    "WITH contributions AS (
+                         SELECT w.user_id, 1 AS created, 0 AS acted
+                         FROM ticket_watchers w
+                         JOIN tickets t ON t.id = w.ticket_id
+                         WHERE t.project_id = ?1 AND w.reason = 'creator'
+                         UNION ALL
+                         SELECT a.actor_id, 0, 1
+                         FROM ticket_activity a
+                         JOIN tickets t ON t.id = a.ticket_id
+                         WHERE t.project_id = ?1 AND a.actor_id IS NOT NULL
+                     )
+                     SELECT c.user_id, u.name, SUM(c.created) AS tickets_created, SUM(c.acted) AS actions
+                     FROM contributions c
+                     JOIN users u ON u.id = c.user_id
+                     GROUP BY c.user_id, u.name
+                     ORDER BY SUM(c.created) + SUM(c.acted) DESC, u.name ASC
+                     LIMIT ?2"
+                )
+                .bind(project_id)
+                .bind(limit)
+                .fetch_all(&pool)
+                .await
+            })
+            .await?;
+
+        Ok(contributors)
+    }
+
+    /// Reported cost and number of the project's runs started since `since` (RFC 3339)
+    pub async fn get_project_spend(&self, project_id: &str, since: &str) -> Result<(f64, i64)> {
+        let spend = self
+            .read_replica(|pool| async move {
+                sqlx::query_as::<_, (f64, i64)>(
+                    "SELECT COALESCE(SUM(s.cost_usd), 0.0), COUNT(*)
+                     FROM analysis_sessions s
+                     JOIN tickets t ON t.id = s.ticket_id
+                     WHERE t.project_id = ?1 AND s.started_at >= ?2"
+                )
+                .bind(project_id)
+                .bind(since)
+                .fetch_one(&pool)
+                .await
+            })
+            .await?;
+
+        Ok(spend)
+    }
+
+    // Coverage: files touched by analyses
+    pub async fn record_ticket_files(
+        &self,
+        ticket_id: &str,
+        run_id: Option<&str>,
+        file_paths: &[String],
+        analyzed_at: &str,
+    ) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        // The new run's files replace the previous set, and the ticket is fresh again
+        sqlx::query("DELETE FROM ticket_files WHERE ticket_id = ?1")
+            .bind(ticket_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("UPDATE tickets SET stale = 0 WHERE id = ?1")
+            .bind(ticket_id)
+            .execute(&mut *tx)
+            .await?;
+
+        for file_path in file_paths {
+            sqlx::query(
+                r#"
+                INSERT INTO ticket_files (ticket_id, file_path, run_id, analyzed_at)
+                VALUES (?1, ?2, ?3, ?4)
+                ON CONFLICT(ticket_id, file_path) DO UPDATE SET
+                    run_id = excluded.run_id,
+                    analyzed_at = excluded.analyzed_at
+                "#,
+            )
+            .bind(ticket_id)
+            .bind(file_path)
+            .bind(run_id)
+            .bind(analyzed_at)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        self.ticket_cache.invalidate(ticket_id);
+        Ok(())
+    }
+
+    /// Set a ticket's stale flag; returns whether it changed
+    pub async fn set_ticket_stale(&self, ticket_id: &str, stale: bool) -> Result<bool> {
+        let result = sqlx::query("UPDATE tickets SET stale = ?1 WHERE id = ?2 AND stale != ?1")
+            .bind(stale)
+            .bind(ticket_id)
+            .execute(&self.pool)
+            .await?;
+
+        self.ticket_cache.invalidate(ticket_id);
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn list_ticket_files(&self, ticket_id: &str) -> Result<Vec<TicketFileRecord>> {
+        let files = sqlx::query_as::<_, TicketFileRecord>(
+            "SELECT ticket_id, file_path, run_id, analyzed_at FROM ticket_files
+             WHERE ticket_id = ?1
+             ORDER BY file_path ASC",
+        )
+        .bind(ticket_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(files)
+    }
+
+    pub async fn list_ticket_files_by_project(&self, project_id: &str) -> Result<Vec<TicketFileRecord>> {
+        let files = sqlx::query_as::<_, TicketFileRecord>(
+            "SELECT f.ticket_id, f.file_path, f.run_id, f.analyzed_at
+             FROM ticket_files f
+             JOIN tickets t ON t.id = f.ticket_id
+             WHERE t.project_id = ?1
+             ORDER BY f.file_path ASC, f.ticket_id ASC",
+        )
+        .bind(project_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(files)
+    }
+
+    // Share link operations
+    pub async fn create_ticket_share(&self, share: &TicketShareRecord) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO ticket_shares (id, ticket_id, token_hash, password_hash, created_by, created_at, expires_at, revoked_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+            "#,
+        )
+        .bind(&share.id)
+        .bind(&share.ticket_id)
+        .bind(&share.token_hash)
+        .bind(&share.password_hash)
+        .bind(&share.created_by)
+        .bind(&share.created_at)
+        .bind(&share.expires_at)
+        .bind(&share.revoked_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Share for a link token hash, including revoked and expired ones
+    pub async fn get_ticket_share_by_token(&self, token_hash: &str) -> Result<Option<TicketShareRecord>> {
+        let share = sqlx::query_as::<_, TicketShareRecord>("SELECT * FROM ticket_shares WHERE token_hash = ?1")
+            .bind(token_hash)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(share)
+    }
+
+    pub async fn get_ticket_share(&self, ticket_id: &str, share_id: &str) -> Result<Option<TicketShareRecord>> {
+        let share = sqlx::query_as::<_, TicketShareRecord>(
+            "SELECT * FROM ticket_shares WHERE id = ?1 AND ticket_id = ?2",
+        )
+        .bind(share_id)
+        .bind(ticket_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(share)
+    }
+
+    pub async fn list_ticket_shares(&self, ticket_id: &str) -> Result<Vec<TicketShareRecord>> {
+        let shares = sqlx::query_as::<_, TicketShareRecord>(
+            "SELECT * FROM ticket_shares WHERE ticket_id = ?1 ORDER BY created_at DESC",
+        )
+        .bind(ticket_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(shares)
+    }
+
+    pub async fn revoke_ticket_share(&self, share_id: &str) -> Result<()> {
+        sqlx::query("UPDATE ticket_shares SET revoked_at = ?1 WHERE id = ?2 AND revoked_at IS NULL")
+            .bind(Utc::now().to_rfc3339())
+            .bind(share_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    // Notification operations
+    /// A user's email mode, None when never set
+    pub async fn get_email_mode(&self, user_id: &str) -> Result<Option<String>> {
+        let mode = sqlx::query_scalar::<_, String>(
+            "SELECT email_mode FROM notification_preferences WHERE user_id = ?1",
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(mode)
+    }
+
+    pub async fn set_email_mode(&self, user_id: &str, email_mode: &str) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO notification_preferences (user_id, email_mode, updated_at)
+            VALUES (?1, ?2, ?3)
+            ON CONFLICT(user_id) DO UPDATE SET
+                email_mode = excluded.email_mode,
+                updated_at = excluded.updated_at
+            "#,
+        )
+        .bind(user_id)
+        .bind(email_mode)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    // User preference operations
+    pub async fn get_user_preferences(&self, user_id: &str) -> Result<Option<UserPreferencesRecord>> {
+        let preferences = sqlx::query_as::<_, UserPreferencesRecord>(
+            "SELECT * FROM user_preferences WHERE user_id = ?1",
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(preferences)
+    }
+
+    pub async fn set_user_preferences(&self, preferences: &UserPreferencesRecord) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO user_preferences
+                (user_id, default_agent, response_language, timezone, default_project_id, updated_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+            ON CONFLICT(user_id) DO UPDATE SET
+                default_agent = excluded.default_agent,
+                response_language = excluded.response_language,
+                timezone = excluded.timezone,
+                default_project_id = excluded.default_project_id,
+                updated_at = excluded.updated_at
+            "#,
+        )
+        .bind(&preferences.user_id)
+        .bind(&preferences.default_agent)
+        .bind(&preferences.response_language)
+        .bind(&preferences.timezone)
+        .bind(&preferences.default_project_id)
+        .bind(&preferences.updated_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn enqueue_notification(&self, notification: &PendingNotificationRecord) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO pending_notifications (id, user_id, ticket_id, ticket_title, succeeded, summary, link, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+            "#,
+        )
+        .bind(&notification.id)
+        .bind(&notification.user_id)
+        .bind(&notification.ticket_id)
+        .bind(&notification.ticket_title)
+        .bind(notification.succeeded)
+        .bind(&notification.summary)
+        .bind(&notification.link)
+        .bind(&notification.created_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Every queued notification, oldest first
+    pub async fn list_pending_notifications(&self) -> Result<Vec<PendingNotificationRecord>> {
+        let notifications = sqlx::query_as::<_, PendingNotificationRecord>(
+            "SELECT * FROM pending_notifications ORDER BY user_id ASC, created_at ASC",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(notifications)
+    }
+
+    pub async fn delete_pending_notifications(&self, ids: &[String]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for id in ids {
+            sqlx::query("DELETE FROM pending_notifications WHERE id = ?1")
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    // Slack workspace operations
+    /// Link a workspace to an organization, replacing the token of a re-installed one
+    pub async fn upsert_slack_workspace(&self, workspace: &SlackWorkspaceRecord) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO slack_workspaces (team_id, org_id, team_name, bot_token, installed_by, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+            ON CONFLICT(team_id) DO UPDATE SET
+                team_name = excluded.team_name,
+                bot_token = excluded.bot_token,
+                installed_by = excluded.installed_by
+            "#,
+        )
+        .bind(&workspace.team_id)
+        .bind(&workspace.org_id)
+        .bind(&workspace.team_name)
+        .bind(&workspace.bot_token)
+        .bind(&workspace.installed_by)
+        .bind(&workspace.created_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_slack_workspace(&self, team_id: &str) -> Result<Option<SlackWorkspaceRecord>> {
+        let workspace = sqlx::query_as::<_, SlackWorkspaceRecord>("SELECT * FROM slack_workspaces WHERE team_id = ?1")
+            .bind(team_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(workspace)
+    }
+
+    pub async fn list_slack_workspaces(&self, org_id: &str) -> Result<Vec<SlackWorkspaceRecord>> {
+        let workspaces = sqlx::query_as::<_, SlackWorkspaceRecord>(
+            "SELECT * FROM slack_workspaces WHERE org_id = ?1 ORDER BY created_at ASC",
+        )
+        .bind(org_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(workspaces)
+    }
+
+    /// Returns whether the organization had that workspace
+    pub async fn delete_slack_workspace(&self, org_id: &str, team_id: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM slack_workspaces WHERE team_id = ?1 AND org_id = ?2")
+            .bind(team_id)
+            .bind(org_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    // Redaction pattern operations
+    pub async fn insert_redaction_pattern(&self, pattern: &RedactionPatternRecord) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO redaction_patterns (id, name, pattern, created_by, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+        )
+        .bind(&pattern.id)
+        .bind(&pattern.name)
+        .bind(&pattern.pattern)
+        .bind(&pattern.created_by)
+        .bind(&pattern.created_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn list_redaction_patterns(&self) -> Result<Vec<RedactionPatternRecord>> {
+        let patterns =
+            sqlx::query_as::<_, RedactionPatternRecord>("SELECT * FROM redaction_patterns ORDER BY created_at ASC")
+                .fetch_all(&self.pool)
+                .await?;
+
+        Ok(patterns)
+    }
+
+    /// Returns whether the pattern existed
+    pub async fn delete_redaction_pattern(&self, id: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM redaction_patterns WHERE id = ?1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    // Project path rule operations
+    /// The project's include/exclude globs; empty rules when none were set
+    pub async fn get_project_path_rules(&self, project_id: &str) -> Result<PathRules> {
+        let row = sqlx::query("SELECT include_patterns, exclude_patterns FROM project_path_rules WHERE project_id = ?1")
+            .bind(project_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        let Some(row) = row else {
+            return Ok(PathRules::default());
+        };
+        Ok(PathRules {
+            include: serde_json::from_str(&row.get::<String, _>("include_patterns"))?,
+            exclude: serde_json::from_str(&row.get::<String, _>("exclude_patterns"))?,
+        })
+    }
+
+    pub async fn set_project_path_rules(&self, project_id: &str, rules: &PathRules) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO project_path_rules (project_id, include_patterns, exclude_patterns, updated_at)
+            VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT(project_id) DO UPDATE SET
+                include_patterns = excluded.include_patterns,
+                exclude_patterns = excluded.exclude_patterns,
+                updated_at = excluded.updated_at
+            "#,
+        )
+        .bind(project_id)
+        .bind(serde_json::to_string(&rules.include)?)
+        .bind(serde_json::to_string(&rules.exclude)?)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    fn log_persistence_from_row(row: &SqliteRow) -> Result<LogPersistence> {
+        let message_types: Option<String> = row.get("message_types");
+        Ok(LogPersistence {
+            message_types: message_types.as_deref().map(serde_json::from_str).transpose()?,
+            raw_log_errors_only: row.get("raw_log_errors_only"),
+        })
+    }
+
+    /// The project's log persistence; None when it uses the server's
+    pub async fn get_project_log_persistence(&self, project_id: &str) -> Result<Option<LogPersistence>> {
+        let row = sqlx::query("SELECT message_types, raw_log_errors_only FROM project_log_persistence WHERE project_id = ?1")
+            .bind(project_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        row.as_ref().map(Self::log_persistence_from_row).transpose()
+    }
+
+    /// The log persistence of the project `ticket_id` belongs to; None when it uses the server's
+    pub async fn get_ticket_log_persistence(&self, ticket_id: &str) -> Result<Option<LogPersistence>> {
+        let row = sqlx::query(
+            r#"
+            SELECT lp.message_types, lp.raw_log_errors_only
+            FROM project_log_persistence lp
+            JOIN tickets t ON t.project_id = lp.project_id
+            WHERE t.id = ?1
+            "#,
+        )
+        .bind(ticket_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.as_ref().map(Self::log_persistence_from_row).transpose()
+    }
+
+    /// Store the project's log persistence, or go back to the server's with None
+    pub async fn set_project_log_persistence(&self, project_id: &str, persistence: Option<&LogPersistence>) -> Result<()> {
+        let Some(persistence) = persistence else {
+            sqlx::query("DELETE FROM project_log_persistence WHERE project_id = ?1")
+                .bind(project_id)
+                .execute(&self.pool)
+                .await?;
+            return Ok(());
+        };
+
+        let message_types = persistence.message_types.as_ref().map(serde_json::to_string).transpose()?;
+        sqlx::query(
+            r#"
+            INSERT INTO project_log_persistence (project_id, message_types, raw_log_errors_only, updated_at)
+            VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT(project_id) DO UPDATE SET
+                message_types = excluded.message_types,
+                raw_log_errors_only = excluded.raw_log_errors_only,
+                updated_at = excluded.updated_at
+            "#,
+        )
+        .bind(project_id)
+        .bind(message_types)
+        .bind(persistence.raw_log_errors_only)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    fn budget_from_row(row: &SqliteRow) -> Result<Budget> {
+        let metric: String = row.get("metric");
+        let enforcement: String = row.get("enforcement");
+        Ok(Budget {
+            metric: BudgetMetric::parse(&metric).with_context(|| format!("Unknown budget metric {}", metric))?,
+            monthly_limit: row.get("monthly_limit"),
+            enforcement: Enforcement::parse(&enforcement)
+                .with_context(|| format!("Unknown budget enforcement {}", enforcement))?,
+        })
+    }
+
+    pub async fn get_project_budget(&self, project_id: &str) -> Result<Option<Budget>> {
+        let row = sqlx::query("SELECT metric, monthly_limit, enforcement FROM project_budgets WHERE project_id = ?1")
+            .bind(project_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        row.as_ref().map(Self::budget_from_row).transpose()
+    }
+
+    pub async fn get_org_budget(&self, org_id: &str) -> Result<Option<Budget>> {
+        let row = sqlx::query("SELECT metric, monthly_limit, enforcement FROM org_budgets WHERE org_id = ?1")
+            .bind(org_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        row.as_ref().map(Self::budget_from_row).transpose()
+    }
+
+    /// Store the project's budget, or remove it with None; the warnings already sent for it
+    /// are forgotten either way
+    pub async fn set_project_budget(&self, project_id: &str, budget: Option<&Budget>, updated_by: Option<&str>) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM budget_warnings WHERE scope = 'project' AND scope_id = ?1")
+            .bind(project_id)
+            .execute(&mut *tx)
+            .await?;
+        match budget {
+            Some(budget) => {
+                sqlx::query(
+                    r#"
+                    INSERT INTO project_budgets (project_id, metric, monthly_limit, enforcement, updated_by, updated_at)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                    ON CONFLICT(project_id) DO UPDATE SET
+                        metric = excluded.metric,
+                        monthly_limit = excluded.monthly_limit,
+                        enforcement = excluded.enforcement,
+                        updated_by = excluded.updated_by,
+                        updated_at = excluded.updated_at
+                    "#,
+                )
+                .bind(project_id)
+                .bind(budget.metric.as_str())
+                .bind(budget.monthly_limit)
+                .bind(budget.enforcement.as_str())
+                .bind(updated_by)
+                .bind(Utc::now().to_rfc3339())
+                .execute(&mut *tx)
+                .await?;
+            }
+            None => {
+                sqlx::query("DELETE FROM project_budgets WHERE project_id = ?1")
+                    .bind(project_id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Store the organization's budget, or remove it with None; like `set_project_budget`
+    pub async fn set_org_budget(&self, org_id: &str, budget: Option<&Budget>, updated_by: Option<&str>) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM budget_warnings WHERE scope = 'org' AND scope_id = ?1")
+            .bind(org_id)
+            .execute(&mut *tx)
+            .await?;
+        match budget {
+            Some(budget) => {
+                sqlx::query(
+                    r#"
+                    INSERT INTO org_budgets (org_id, metric, monthly_limit, enforcement, updated_by, updated_at)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                    ON CONFLICT(org_id) DO UPDATE SET
+                        metric = excluded.metric,
+                        monthly_limit = excluded.monthly_limit,
+                        enforcement = excluded.enforcement,
+                        updated_by = excluded.updated_by,
+                        updated_at = excluded.updated_at
+                    "#,
+                )
+                .bind(org_id)
+                .bind(budget.metric.as_str())
+                .bind(budget.monthly_limit)
+                .bind(budget.enforcement.as_str())
+                .bind(updated_by)
+                .bind(Utc::now().to_rfc3339())
+                .execute(&mut *tx)
+                .await?;
+            }
+            None => {
+                sqlx::query("DELETE FROM org_budgets WHERE org_id = ?1")
+                    .bind(org_id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Reported cost and tokens of the project's runs started since `since` (RFC 3339)
+    pub async fn get_project_usage(&self, project_id: &str, since: &str) -> Result<(f64, i64)> {
+        let usage = sqlx::query_as::<_, (f64, i64)>(
+            "SELECT COALESCE(SUM(s.cost_usd), 0.0), COALESCE(SUM(s.tokens), 0)
+             FROM analysis_sessions s
+             JOIN tickets t ON t.id = s.ticket_id
+             WHERE t.project_id = ?1 AND s.started_at >= ?2",
+        )
+        .bind(project_id)
+        .bind(since)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(usage)
+    }
+
+    /// Like `get_project_usage`, over every project of the organization, trashed ones included
+    pub async fn get_org_usage(&self, org_id: &str, since: &str) -> Result<(f64, i64)> {
+        let usage = sqlx::query_as::<_, (f64, i64)>(
+            "SELECT COALESCE(SUM(s.cost_usd), 0.0), COALESCE(SUM(s.tokens), 0)
+             FROM analysis_sessions s
+             JOIN tickets t ON t.id = s.ticket_id
+             JOIN projects p ON p.id = t.project_id
+             WHERE p.org_id = ?1 AND s.started_at >= ?2",
+        )
+        .bind(org_id)
+        .bind(since)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(usage)
+    }
+
+    /// Note that `threshold` of a budget was warned about for the month starting `period`;
+    /// false when it already was
+    pub async fn record_budget_warning(&self, scope: &str, scope_id: &str, period: &str, threshold: i64) -> Result<bool> {
+        let result = sqlx::query(
+            "INSERT OR IGNORE INTO budget_warnings (scope, scope_id, period, threshold, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+        )
+        .bind(scope)
+        .bind(scope_id)
+        .bind(period)
+        .bind(threshold)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// The project's allowed tools; None when it uses the mode defaults
+    pub async fn get_project_tool_policy(&self, project_id: &str) -> Result<Option<Vec<String>>> {
+        let row = sqlx::query("SELECT allowed_tools FROM project_tool_policies WHERE project_id = ?1")
+            .bind(project_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        match row {
+            Some(row) => Ok(Some(serde_json::from_str(&row.get::<String, _>("allowed_tools"))?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Store the project's allowed tools, or go back to the mode defaults with None
+    pub async fn set_project_tool_policy(&self, project_id: &str, allowed_tools: Option<&[String]>) -> Result<()> {
+        let Some(allowed_tools) = allowed_tools else {
+            sqlx::query("DELETE FROM project_tool_policies WHERE project_id = ?1")
+                .bind(project_id)
+                .execute(&self.pool)
+                .await?;
+            return Ok(());
+        };
+
+        sqlx::query(
+            r#"
+            INSERT INTO project_tool_policies (project_id, allowed_tools, updated_at)
+            VALUES (?1, ?2, ?3)
+            ON CONFLICT(project_id) DO UPDATE SET
+                allowed_tools = excluded.allowed_tools,
+                updated_at = excluded.updated_at
+            "#,
+        )
+        .bind(project_id)
+        .bind(serde_json::to_string(allowed_tools)?)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// The project's pre-flight checks, empty when it has none
+    pub async fn get_project_preflight_checks(&self, project_id: &str) -> Result<Vec<PreflightCheck>> {
+        let row = sqlx::query("SELECT checks FROM project_preflight_checks WHERE project_id = ?1")
+            .bind(project_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        match row {
+            Some(row) => Ok(serde_json::from_str(&row.get::<String, _>("checks"))?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Replace the project's pre-flight checks; an empty list removes them
+    pub async fn set_project_preflight_checks(&self, project_id: &str, checks: &[PreflightCheck]) -> Result<()> {
+        if checks.is_empty() {
+            sqlx::query("DELETE FROM project_preflight_checks WHERE project_id = ?1")
+                .bind(project_id)
+                .execute(&self.pool)
+                .await?;
+            return Ok(());
+        }
+
+        sqlx::query(
+            r#"
+            INSERT INTO project_preflight_checks (project_id, checks, updated_at)
+            VALUES (?1, ?2, ?3)
+            ON CONFLICT(project_id) DO UPDATE SET
+                checks = excluded.checks,
+                updated_at = excluded.updated_at
+            "#,
+        )
+        .bind(project_id)
+        .bind(serde_json::to_string(checks)?)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_project_post_run_hooks(&self, project_id: &str) -> Result<Option<PostRunHooksRecord>> {
+        let hooks = sqlx::query_as::<_, PostRunHooksRecord>("SELECT * FROM project_post_run_hooks WHERE project_id = ?1")
+            .bind(project_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(hooks)
+    }
+
+    /// Replace the project's post-run hooks; None removes them
+    pub async fn set_project_post_run_hooks(&self, project_id: &str, hooks: Option<&PostRunHooksRecord>) -> Result<()> {
+        let Some(hooks) = hooks else {
+            sqlx::query("DELETE FROM project_post_run_hooks WHERE project_id = ?1")
+                .bind(project_id)
+                .execute(&self.pool)
+                .await?;
+            return Ok(());
+        };
+
+        sqlx::query(
+            r#"
+            INSERT INTO project_post_run_hooks (project_id, hooks, reopen_on_failure, updated_at)
+            VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT(project_id) DO UPDATE SET
+                hooks = excluded.hooks,
+                reopen_on_failure = excluded.reopen_on_failure,
+                updated_at = excluded.updated_at
+            "#,
+        )
+        .bind(project_id)
+        .bind(&hooks.hooks)
+        .bind(hooks.reopen_on_failure)
+        .bind(&hooks.updated_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn set_session_hook_results(&self, session_id: &str, hook_results: &str) -> Result<()> {
+        sqlx::query("UPDATE analysis_sessions SET hook_results = ?1 WHERE id = ?2")
+            .bind(hook_results)
+            .bind(session_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn set_session_output_violations(&self, session_id: &str, violations: &str) -> Result<()> {
+        sqlx::query("UPDATE analysis_sessions SET output_violations = ?1 WHERE id = ?2")
+            .bind(violations)
+            .bind(session_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn set_session_changed_files(&self, session_id: &str, changed_files: &str) -> Result<()> {
+        sqlx::query("UPDATE analysis_sessions SET changed_files = ?1 WHERE id = ?2")
+            .bind(changed_files)
+            .bind(session_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    // Alert rule operations
+    pub async fn list_alert_rules(&self, org_id: &str) -> Result<Vec<AlertRuleRecord>> {
+        let rules = sqlx::query_as::<_, AlertRuleRecord>(
+            "SELECT * FROM alert_rules WHERE org_id = ?1 ORDER BY created_at ASC, name ASC"
+        )
+        .bind(org_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rules)
+    }
+
+    /// Enabled rules of every organization, for the evaluator
+    pub async fn list_enabled_alert_rules(&self) -> Result<Vec<AlertRuleRecord>> {
+        let rules = sqlx::query_as::<_, AlertRuleRecord>("SELECT * FROM alert_rules WHERE enabled = 1")
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rules)
+    }
+
+    pub async fn get_alert_rule(&self, id: &str) -> Result<Option<AlertRuleRecord>> {
+        let rule = sqlx::query_as::<_, AlertRuleRecord>("SELECT * FROM alert_rules WHERE id = ?1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(rule)
+    }
+
+    /// Insert the rule, or replace it when its ID exists
+    pub async fn save_alert_rule(&self, rule: &AlertRuleRecord) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO alert_rules
+                (id, org_id, project_id, name, message_type, content_pattern, threshold, actions, enabled,
+                 created_by, created_at, updated_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
+            ON CONFLICT(id) DO UPDATE SET
+                project_id = excluded.project_id,
+                name = excluded.name,
+                message_type = excluded.message_type,
+                content_pattern = excluded.content_pattern,
+                threshold = excluded.threshold,
+                actions = excluded.actions,
+                enabled = excluded.enabled,
+                updated_at = excluded.updated_at
+            "#,
+        )
+        .bind(&rule.id)
+        .bind(&rule.org_id)
+        .bind(&rule.project_id)
+        .bind(&rule.name)
+        .bind(&rule.message_type)
+        .bind(&rule.content_pattern)
+        .bind(rule.threshold)
+        .bind(&rule.actions)
+        .bind(rule.enabled)
+        .bind(&rule.created_by)
+        .bind(&rule.created_at)
+        .bind(&rule.updated_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete_alert_rule(&self, id: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM alert_rules WHERE id = ?1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Record that a rule fired for a run; false when it already had, here or on another instance
+    pub async fn record_alert_event(&self, event: &AlertEventRecord) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            INSERT OR IGNORE INTO alert_events (id, rule_id, ticket_id, run_id, match_count, sample, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+            "#,
+        )
+        .bind(&event.id)
+        .bind(&event.rule_id)
+        .bind(&event.ticket_id)
+        .bind(&event.run_id)
+        .bind(event.match_count)
+        .bind(&event.sample)
+        .bind(&event.created_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// A rule's latest firings, newest first
+    pub async fn list_alert_events(&self, rule_id: &str, limit: i64) -> Result<Vec<AlertEventRecord>> {
+        let events = sqlx::query_as::<_, AlertEventRecord>(
+            "SELECT * FROM alert_events WHERE rule_id = ?1 ORDER BY created_at DESC LIMIT ?2"
+        )
+        .bind(rule_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(events)
+    }
+
+    /// Templates of the organization: the global ones, plus those of `project_id` when given,
+    /// optionally of one category. Most used first.
+    pub async fn list_question_templates(
+        &self,
+        org_id: &str,
+        project_id: Option<&str>,
+        category: Option<&str>,
+    ) -> Result<Vec<QuestionTemplateRecord>> {
+        let templates = sqlx::query_as::<_, QuestionTemplateRecord>(
+            r#"
+            SELECT * FROM question_templates
+            WHERE org_id = ?1
+              AND (project_id IS NULL OR project_id = ?2)
+              AND (?3 IS NULL OR category = ?3)
+            ORDER BY category ASC, usage_count DESC, title ASC
+            "#,
+        )
+        .bind(org_id)
+        .bind(project_id)
+        .bind(category)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(templates)
+    }
+
+    pub async fn get_question_template(&self, id: &str) -> Result<Option<QuestionTemplateRecord>> {
+        let template = sqlx::query_as::<_, QuestionTemplateRecord>("SELECT * FROM question_templates WHERE id = ?1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(template)
+    }
+
+    pub async fn create_question_template(&self, template: &QuestionTemplateRecord) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO question_templates
+                (id, org_id, project_id, category, title, question, mode, code_context, usage_count,
+                 created_by, created_at, updated_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
+            "#,
+        )
+        .bind(&template.id)
+        .bind(&template.org_id)
+        .bind(&template.project_id)
+        .bind(&template.category)
+        .bind(&template.title)
+        .bind(&template.question)
+        .bind(&template.mode)
+        .bind(&template.code_context)
+        .bind(template.usage_count)
+        .bind(&template.created_by)
+        .bind(&template.created_at)
+        .bind(&template.updated_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Replace the editable fields of a template
+    pub async fn update_question_template(&self, template: &QuestionTemplateRecord) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE question_templates
+            SET category = ?1, title = ?2, question = ?3, mode = ?4, code_context = ?5, updated_at = ?6
+            WHERE id = ?7
+            "#,
+        )
+        .bind(&template.category)
+        .bind(&template.title)
+        .bind(&template.question)
+        .bind(&template.mode)
+        .bind(&template.code_context)
+        .bind(&template.updated_at)
+        .bind(&template.id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete_question_template(&self, id: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM question_templates WHERE id = ?1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn increment_question_template_usage(&self, id: &str) -> Result<()> {
+        sqlx::query("UPDATE question_templates SET usage_count = usage_count + 1 WHERE id = ?1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_project_summary_settings(&self, project_id: &str) -> Result<Option<ProjectSummarySettingsRecord>> {
+        let settings = sqlx::query_as::<_, ProjectSummarySettingsRecord>(
+            "SELECT * FROM project_summary_settings WHERE project_id = ?1"
+        )
+        .bind(project_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(settings)
+    }
 
-        // Create structured_logs table
+    pub async fn set_project_summary_settings(&self, settings: &ProjectSummarySettingsRecord) -> Result<()> {
         sqlx::query(
             r#"
-            CREATE TABLE IF NOT EXISTS structured_logs (
-                id TEXT PRIMARY KEY,
-                ticket_id TEXT NOT NULL,
-                message_type TEXT NOT NULL CHECK(message_type IN ('tool_use', 'assistant', 'error', 'system', 'result')),
-                content TEXT NOT NULL,
-                raw_log TEXT,
-                metadata TEXT,
-                timestamp TEXT NOT NULL,
-                FOREIGN KEY (ticket_id) REFERENCES tickets(id) ON DELETE CASCADE
-            )
+            INSERT INTO project_summary_settings (project_id, enabled, max_lines, updated_at)
+            VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT(project_id) DO UPDATE SET
+                enabled = excluded.enabled,
+                max_lines = excluded.max_lines,
+                updated_at = excluded.updated_at
             "#,
         )
+        .bind(&settings.project_id)
+        .bind(settings.enabled)
+        .bind(settings.max_lines)
+        .bind(&settings.updated_at)
         .execute(&self.pool)
         .await?;
 
-        // Create indexes
-        sqlx::query("CREATE INDEX IF NOT EXISTS idx_logs_ticket_id ON structured_logs(ticket_id)")
+        Ok(())
+    }
+
+    /// Claim `key` for a new request, returning the row that already holds it instead when
+    /// one does. Expired rows, and claims left in progress longer than `stale_after`, are
+    /// dropped first so their keys can be reused.
+    pub async fn claim_idempotency_key(
+        &self,
+        scope: &str,
+        key: &str,
+        fingerprint: &str,
+        ttl: chrono::Duration,
+        stale_after: chrono::Duration,
+    ) -> Result<Option<IdempotencyRecord>> {
+        let now = Utc::now();
+        sqlx::query(
+            "DELETE FROM idempotency_keys WHERE expires_at < ?1 OR (response IS NULL AND created_at < ?2)",
+        )
+        .bind(now.to_rfc3339())
+        .bind((now - stale_after).to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        // The holder can release its claim between our insert and read; claim again then
+        for _ in 0..3 {
+            let inserted = sqlx::query(
+                r#"
+                INSERT OR IGNORE INTO idempotency_keys (scope, idempotency_key, fingerprint, created_at, expires_at)
+                VALUES (?1, ?2, ?3, ?4, ?5)
+                "#,
+            )
+            .bind(scope)
+            .bind(key)
+            .bind(fingerprint)
+            .bind(now.to_rfc3339())
+            .bind((now + ttl).to_rfc3339())
             .execute(&self.pool)
             .await?;
 
-        sqlx::query("CREATE INDEX IF NOT EXISTS idx_logs_timestamp ON structured_logs(timestamp)")
-            .execute(&self.pool)
+            if inserted.rows_affected() == 1 {
+                return Ok(None);
+            }
+
+            let row = sqlx::query(
+                "SELECT fingerprint, status_code, response FROM idempotency_keys WHERE scope = ?1 AND idempotency_key = ?2",
+            )
+            .bind(scope)
+            .bind(key)
+            .fetch_optional(&self.pool)
             .await?;
 
-        // Create analysis_sessions table
+            if let Some(row) = row {
+                return Ok(Some(IdempotencyRecord {
+                    fingerprint: row.get("fingerprint"),
+                    status_code: row.get::<Option<i64>, _>("status_code").map(|code| code as u16),
+                    response: row.get("response"),
+                }));
+            }
+        }
+
+        anyhow::bail!("Idempotency key {} kept changing hands", key)
+    }
+
+    /// Store the response of the request that claimed `key`
+    pub async fn complete_idempotency_key(&self, scope: &str, key: &str, status_code: u16, response: &str) -> Result<()> {
         sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS analysis_sessions (
-                id TEXT PRIMARY KEY,
-                ticket_id TEXT NOT NULL,
-                started_at TEXT NOT NULL,
-                completed_at TEXT,
-                status TEXT NOT NULL CHECK(status IN ('running', 'completed', 'failed', 'cancelled')),
-                error_message TEXT,
-                FOREIGN KEY (ticket_id) REFERENCES tickets(id) ON DELETE CASCADE
-            )
-            "#,
+            "UPDATE idempotency_keys SET status_code = ?3, response = ?4 WHERE scope = ?1 AND idempotency_key = ?2",
         )
+        .bind(scope)
+        .bind(key)
+        .bind(status_code as i64)
+        .bind(response)
         .execute(&self.pool)
         .await?;
 
         Ok(())
     }
 
-    // Clear all existing data (for migration)
-    pub async fn clear_all_tickets(&self) -> Result<()> {
-        sqlx::query("DELETE FROM analysis_sessions")
+    /// Give up a claim whose request failed, so a retry runs it again
+    pub async fn release_idempotency_key(&self, scope: &str, key: &str) -> Result<()> {
+        sqlx::query("DELETE FROM idempotency_keys WHERE scope = ?1 AND idempotency_key = ?2")
+            .bind(scope)
+            .bind(key)
             .execute(&self.pool)
             .await?;
-        
-        sqlx::query("DELETE FROM structured_logs")
-            .execute(&self.pool)
-            .await?;
-        
-        sqlx::query("DELETE FROM tickets")
-            .execute(&self.pool)
-            .await?;
-        
-        sqlx::query("DELETE FROM projects")
+
+        Ok(())
+    }
+
+    // Organization, user and invite operations
+    pub async fn create_organization(&self, org: &OrganizationRecord) -> Result<()> {
+        sqlx::query("INSERT INTO organizations (id, name, created_at) VALUES (?1, ?2, ?3)")
+            .bind(&org.id)
+            .bind(&org.name)
+            .bind(&org.created_at)
             .execute(&self.pool)
             .await?;
 
         Ok(())
     }
 
-    // Project CRUD operations
-    pub async fn create_project(&self, project: &ProjectRecord) -> Result<()> {
+    pub async fn get_organization(&self, id: &str) -> Result<Option<OrganizationRecord>> {
+        let org = sqlx::query_as::<_, OrganizationRecord>(
+            "SELECT * FROM organizations WHERE id = ?1"
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(org)
+    }
+
+    pub async fn list_organizations(&self) -> Result<Vec<OrganizationRecord>> {
+        let orgs = sqlx::query_as::<_, OrganizationRecord>(
+            "SELECT * FROM organizations ORDER BY created_at ASC"
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(orgs)
+    }
+
+    pub async fn create_user(&self, user: &UserRecord) -> Result<()> {
         sqlx::query(
             r#"
-            INSERT INTO projects (id, name, description, directory_path, created_at, updated_at)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+            INSERT INTO users (id, org_id, email, name, password_hash, role, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
             "#,
         )
-        .bind(&project.id)
-        .bind(&project.name)
-        .bind(&project.description)
-        .bind(&project.directory_path)
-        .bind(&project.created_at)
-        .bind(&project.updated_at)
+        .bind(&user.id)
+        .bind(&user.org_id)
+        .bind(&user.email)
+        .bind(&user.name)
+        .bind(&user.password_hash)
+        .bind(&user.role)
+        .bind(&user.created_at)
         .execute(&self.pool)
         .await?;
 
         Ok(())
     }
 
-    pub async fn get_project(&self, id: &str) -> Result<Option<ProjectRecord>> {
-        let project = sqlx::query_as::<_, ProjectRecord>(
-            "SELECT * FROM projects WHERE id = ?1"
+    pub async fn get_user(&self, id: &str) -> Result<Option<UserRecord>> {
+        let user = sqlx::query_as::<_, UserRecord>("SELECT * FROM users WHERE id = ?1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(user)
+    }
+
+    pub async fn get_user_by_email(&self, email: &str) -> Result<Option<UserRecord>> {
+        let user = sqlx::query_as::<_, UserRecord>(
+            "SELECT * FROM users WHERE email = ?1 COLLATE NOCASE"
         )
-        .bind(id)
+        .bind(email)
         .fetch_optional(&self.pool)
         .await?;
 
-        Ok(project)
+        Ok(user)
     }
 
-    pub async fn list_projects(&self) -> Result<Vec<ProjectRecord>> {
-        let projects = sqlx::query_as::<_, ProjectRecord>(
-            "SELECT * FROM projects ORDER BY created_at DESC"
+    /// The user an SSO identity signs in as
+    pub async fn get_user_by_identity(&self, issuer: &str, subject: &str) -> Result<Option<UserRecord>> {
+        let user = sqlx::query_as::<_, UserRecord>(
+            "SELECT u.* FROM users u
+             JOIN user_identities i ON i.user_id = u.id
+             WHERE i.issuer = ?1 AND i.subject = ?2"
         )
-        .fetch_all(&self.pool)
+        .bind(issuer)
+        .bind(subject)
+        .fetch_optional(&self.pool)
         .await?;
 
-        Ok(projects)
+        Ok(user)
     }
 
-    pub async fn update_project(&self, project: &ProjectRecord) -> Result<()> {
+    pub async fn link_user_identity(&self, issuer: &str, subject: &str, user_id: &str) -> Result<()> {
         sqlx::query(
-            r#"
-            UPDATE projects
-            SET name = ?1, description = ?2, directory_path = ?3, updated_at = ?4
-            WHERE id = ?5
-            "#,
+            "INSERT INTO user_identities (issuer, subject, user_id, created_at) VALUES (?1, ?2, ?3, ?4)"
         )
-        .bind(&project.name)
-        .bind(&project.description)
-        .bind(&project.directory_path)
-        .bind(&project.updated_at)
-        .bind(&project.id)
+        .bind(issuer)
+        .bind(subject)
+        .bind(user_id)
+        .bind(Utc::now().to_rfc3339())
         .execute(&self.pool)
         .await?;
 
         Ok(())
     }
 
-    pub async fn delete_project(&self, id: &str) -> Result<()> {
-        sqlx::query("DELETE FROM projects WHERE id = ?1")
+    pub async fn update_user_role(&self, user_id: &str, role: &str) -> Result<()> {
+        sqlx::query("UPDATE users SET role = ?2 WHERE id = ?1")
+            .bind(user_id)
+            .bind(role)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn list_users_by_org(&self, org_id: &str) -> Result<Vec<UserRecord>> {
+        let users = sqlx::query_as::<_, UserRecord>(
+            "SELECT * FROM users WHERE org_id = ?1 ORDER BY created_at ASC"
+        )
+        .bind(org_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(users)
+    }
+
+    pub async fn count_users(&self) -> Result<u64> {
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users")
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(count as u64)
+    }
+
+    pub async fn delete_user(&self, id: &str) -> Result<()> {
+        sqlx::query("DELETE FROM users WHERE id = ?1")
             .bind(id)
             .execute(&self.pool)
             .await?;
@@ -248,421 +5006,915 @@ impl Database {
         Ok(())
     }
 
-    // Ticket CRUD operations
-    pub async fn create_ticket(&self, ticket: &TicketRecord) -> Result<()> {
+    pub async fn create_auth_token(&self, token_hash: &str, user_id: &str, expires_at: &str) -> Result<()> {
         sqlx::query(
-            r#"
-            INSERT INTO tickets (id, project_id, title, description, status, code_context, analysis_result, is_analyzing, created_at, updated_at)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
-            "#,
+            "INSERT INTO auth_tokens (token_hash, user_id, created_at, expires_at) VALUES (?1, ?2, ?3, ?4)"
         )
-        .bind(&ticket.id)
-        .bind(&ticket.project_id)
-        .bind(&ticket.title)
-        .bind(&ticket.description)
-        .bind(&ticket.status)
-        .bind(&ticket.code_context)
-        .bind(&ticket.analysis_result)
-        .bind(ticket.is_analyzing)
-        .bind(&ticket.created_at)
-        .bind(&ticket.updated_at)
+        .bind(token_hash)
+        .bind(user_id)
+        .bind(Utc::now().to_rfc3339())
+        .bind(expires_at)
         .execute(&self.pool)
         .await?;
 
         Ok(())
     }
 
-    pub async fn update_ticket(&self, ticket: &TicketRecord) -> Result<()> {
-        sqlx::query(
-            r#"
-            UPDATE tickets
-            SET project_id = ?1, title = ?2, description = ?3, status = ?4, code_context = ?5,
-                analysis_result = ?6, is_analyzing = ?7, updated_at = ?8
-            WHERE id = ?9
-            "#,
+    /// User owning an unexpired token
+    pub async fn get_user_by_token(&self, token_hash: &str) -> Result<Option<UserRecord>> {
+        let user = sqlx::query_as::<_, UserRecord>(
+            "SELECT u.* FROM auth_tokens a
+             JOIN users u ON u.id = a.user_id
+             WHERE a.token_hash = ?1 AND a.expires_at > ?2"
         )
-        .bind(&ticket.project_id)
-        .bind(&ticket.title)
-        .bind(&ticket.description)
-        .bind(&ticket.status)
-        .bind(&ticket.code_context)
-        .bind(&ticket.analysis_result)
-        .bind(ticket.is_analyzing)
-        .bind(&ticket.updated_at)
-        .bind(&ticket.id)
-        .execute(&self.pool)
+        .bind(token_hash)
+        .bind(Utc::now().to_rfc3339())
+        .fetch_optional(&self.pool)
         .await?;
 
+        Ok(user)
+    }
+
+    pub async fn delete_auth_token(&self, token_hash: &str) -> Result<()> {
+        sqlx::query("DELETE FROM auth_tokens WHERE token_hash = ?1")
+            .bind(token_hash)
+            .execute(&self.pool)
+            .await?;
+
         Ok(())
     }
 
-    pub async fn update_ticket_status(&self, ticket_id: &str, status: &str) -> Result<()> {
-        let now = Utc::now().to_rfc3339();
+    pub async fn create_invite(&self, invite: &OrgInviteRecord) -> Result<()> {
         sqlx::query(
             r#"
-            UPDATE tickets
-            SET status = ?1, updated_at = ?2
-            WHERE id = ?3
+            INSERT INTO org_invites (id, org_id, email, role, token_hash, invited_by, created_at, expires_at, accepted_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
             "#,
         )
-        .bind(status)
-        .bind(now)
-        .bind(ticket_id)
+        .bind(&invite.id)
+        .bind(&invite.org_id)
+        .bind(&invite.email)
+        .bind(&invite.role)
+        .bind(&invite.token_hash)
+        .bind(&invite.invited_by)
+        .bind(&invite.created_at)
+        .bind(&invite.expires_at)
+        .bind(&invite.accepted_at)
         .execute(&self.pool)
         .await?;
 
         Ok(())
     }
 
-    pub async fn update_ticket_analyzing(&self, ticket_id: &str, is_analyzing: bool) -> Result<()> {
-        let now = Utc::now().to_rfc3339();
+    /// Pending (unaccepted, unexpired) invite for a token
+    pub async fn get_pending_invite(&self, token_hash: &str) -> Result<Option<OrgInviteRecord>> {
+        let invite = sqlx::query_as::<_, OrgInviteRecord>(
+            "SELECT * FROM org_invites
+             WHERE token_hash = ?1 AND accepted_at IS NULL AND expires_at > ?2"
+        )
+        .bind(token_hash)
+        .bind(Utc::now().to_rfc3339())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(invite)
+    }
+
+    pub async fn list_invites_by_org(&self, org_id: &str) -> Result<Vec<OrgInviteRecord>> {
+        let invites = sqlx::query_as::<_, OrgInviteRecord>(
+            "SELECT * FROM org_invites WHERE org_id = ?1 ORDER BY created_at DESC"
+        )
+        .bind(org_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(invites)
+    }
+
+    /// Create the invited user and consume the invite in one transaction
+    pub async fn accept_invite(&self, invite_id: &str, user: &UserRecord) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        let consumed = sqlx::query(
+            "UPDATE org_invites SET accepted_at = ?1 WHERE id = ?2 AND accepted_at IS NULL"
+        )
+        .bind(Utc::now().to_rfc3339())
+        .bind(invite_id)
+        .execute(&mut *tx)
+        .await?;
+        if consumed.rows_affected() == 0 {
+            return Err(anyhow::anyhow!("Invite {} was already accepted", invite_id));
+        }
+
         sqlx::query(
             r#"
-            UPDATE tickets
-            SET is_analyzing = ?1, updated_at = ?2
-            WHERE id = ?3
+            INSERT INTO users (id, org_id, email, name, password_hash, role, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
             "#,
         )
-        .bind(is_analyzing)
-        .bind(now)
-        .bind(ticket_id)
-        .execute(&self.pool)
+        .bind(&user.id)
+        .bind(&user.org_id)
+        .bind(&user.email)
+        .bind(&user.name)
+        .bind(&user.password_hash)
+        .bind(&user.role)
+        .bind(&user.created_at)
+        .execute(&mut *tx)
         .await?;
 
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    // Analytics aggregates over analysis_sessions
+    pub async fn get_session_totals(&self, filter: &AnalyticsFilter) -> Result<SessionTotals> {
+        let sql = &format!(
+            "SELECT
+                COUNT(*) AS total_runs,
+                COALESCE(SUM(s.status = 'completed'), 0) AS completed,
+                COALESCE(SUM(s.status = 'failed'), 0) AS failed,
+                COALESCE(SUM(s.status = 'cancelled'), 0) AS cancelled,
+                COALESCE(SUM(s.status = 'running'), 0) AS running,
+                ROUND(AVG(CASE WHEN s.status = 'completed' THEN {duration} END)) AS avg_duration_ms,
+                ROUND(MAX(CASE WHEN s.status = 'completed' THEN {duration} END)) AS max_duration_ms
+             FROM analysis_sessions s
+             JOIN tickets t ON t.id = s.ticket_id
+             WHERE {filter}",
+            duration = DURATION_MS,
+            filter = ANALYTICS_WHERE,
+        );
+
+        let totals = self
+            .read_replica(|pool| async move {
+                sqlx::query_as::<_, SessionTotals>(sql)
+                    .bind(&filter.from)
+                    .bind(&filter.to)
+                    .bind(&filter.project_id)
+                    .bind(&filter.org_id)
+                    .fetch_one(&pool)
+                    .await
+            })
+            .await?;
+
+        Ok(totals)
+    }
+
+    pub async fn get_runs_per_day(&self, filter: &AnalyticsFilter) -> Result<Vec<DailyRuns>> {
+        let sql = &format!(
+            "SELECT
+                date(s.started_at) AS date,
+                COUNT(*) AS runs,
+                COALESCE(SUM(s.status = 'failed'), 0) AS failed
+             FROM analysis_sessions s
+             JOIN tickets t ON t.id = s.ticket_id
+             WHERE {filter}
+             GROUP BY date(s.started_at)
+             ORDER BY date ASC",
+            filter = ANALYTICS_WHERE,
+        );
+
+        let days = self
+            .read_replica(|pool| async move {
+                sqlx::query_as::<_, DailyRuns>(sql)
+                    .bind(&filter.from)
+                    .bind(&filter.to)
+                    .bind(&filter.project_id)
+                    .bind(&filter.org_id)
+                    .fetch_all(&pool)
+                    .await
+            })
+            .await?;
+
+        Ok(days)
+    }
+
+    pub async fn get_agent_breakdown(&self, filter: &AnalyticsFilter) -> Result<Vec<AgentBreakdown>> {
+        let sql = &format!(
+            "SELECT
+                COALESCE(s.agent_type, 'unknown') AS agent_type,
+                COUNT(*) AS runs,
+                COALESCE(SUM(s.status = 'completed'), 0) AS completed,
+                COALESCE(SUM(s.status = 'failed'), 0) AS failed,
+                COALESCE(SUM(s.status = 'cancelled'), 0) AS cancelled,
+                ROUND(AVG(CASE WHEN s.status = 'completed' THEN {duration} END)) AS avg_duration_ms
+             FROM analysis_sessions s
+             JOIN tickets t ON t.id = s.ticket_id
+             WHERE {filter}
+             GROUP BY COALESCE(s.agent_type, 'unknown')
+             ORDER BY runs DESC",
+            duration = DURATION_MS,
+            filter = ANALYTICS_WHERE,
+        );
+
+        let agents = self
+            .read_replica(|pool| async move {
+                sqlx::query_as::<_, AgentBreakdown>(sql)
+                    .bind(&filter.from)
+                    .bind(&filter.to)
+                    .bind(&filter.project_id)
+                    .bind(&filter.org_id)
+                    .fetch_all(&pool)
+                    .await
+            })
+            .await?;
+
+        Ok(agents)
+    }
+
+    pub async fn get_busiest_projects(&self, filter: &AnalyticsFilter, limit: u32) -> Result<Vec<ProjectRuns>> {
+        let sql = &format!(
+            "SELECT
+                t.project_id AS project_id,
+                p.name AS project_name,
+                COUNT(*) AS runs,
+                COALESCE(SUM(s.status = 'failed'), 0) AS failed
+             FROM analysis_sessions s
+             JOIN tickets t ON t.id = s.ticket_id
+             LEFT JOIN projects p ON p.id = t.project_id
+             WHERE {filter}
+             GROUP BY t.project_id
+             ORDER BY runs DESC
+             LIMIT ?5",
+            filter = ANALYTICS_WHERE,
+        );
+
+        let projects = self
+            .read_replica(|pool| async move {
+                sqlx::query_as::<_, ProjectRuns>(sql)
+                    .bind(&filter.from)
+                    .bind(&filter.to)
+                    .bind(&filter.project_id)
+                    .bind(&filter.org_id)
+                    .bind(limit)
+                    .fetch_all(&pool)
+                    .await
+            })
+            .await?;
+
+        Ok(projects)
+    }
+
+    /// Ratings per agent and mode of the sessions matching `filter`
+    pub async fn get_feedback_quality(&self, filter: &AnalyticsFilter) -> Result<Vec<FeedbackQuality>> {
+        let sql = &format!(
+            "SELECT
+                COALESCE(s.agent_type, 'unknown') AS agent_type,
+                COALESCE(s.mode, 'ask') AS mode,
+                COUNT(*) AS ratings,
+                COALESCE(SUM(f.rating = 1), 0) AS thumbs_up,
+                COALESCE(SUM(f.rating = -1), 0) AS thumbs_down,
+                AVG(f.rating = 1) AS approval_rate
+             FROM result_feedback f
+             JOIN analysis_sessions s ON s.id = f.session_id
+             JOIN tickets t ON t.id = s.ticket_id
+             WHERE {filter}
+             GROUP BY 1, 2
+             ORDER BY ratings DESC, agent_type, mode",
+            filter = ANALYTICS_WHERE,
+        );
+
+        let quality = self
+            .read_replica(|pool| async move {
+                sqlx::query_as::<_, FeedbackQuality>(sql)
+                    .bind(&filter.from)
+                    .bind(&filter.to)
+                    .bind(&filter.project_id)
+                    .bind(&filter.org_id)
+                    .fetch_all(&pool)
+                    .await
+            })
+            .await?;
+
+        Ok(quality)
+    }
+
+    /// Thumbs-down ratings of the sessions matching `filter`, most recently rated first
+    pub async fn list_low_rated_feedback(&self, filter: &AnalyticsFilter, limit: u32) -> Result<Vec<LowRatedFeedback>> {
+        let sql = &format!(
+            "SELECT
+                f.id AS feedback_id,
+                f.ticket_id,
+                t.project_id,
+                f.session_id,
+                s.agent_type,
+                s.model,
+                s.request_json,
+                f.comment,
+                f.user_id AS rated_by,
+                f.updated_at AS rated_at,
+                s.id = (
+                    SELECT latest.id FROM analysis_sessions latest
+                    WHERE latest.ticket_id = t.id AND latest.status = 'completed'
+                    ORDER BY latest.started_at DESC LIMIT 1
+                ) AS is_current
+             FROM result_feedback f
+             JOIN analysis_sessions s ON s.id = f.session_id
+             JOIN tickets t ON t.id = s.ticket_id
+             WHERE f.rating = -1 AND {filter}
+             ORDER BY f.updated_at DESC
+             LIMIT ?5",
+            filter = ANALYTICS_WHERE,
+        );
+
+        let feedback = self
+            .read_replica(|pool| async move {
+                sqlx::query_as::<_, LowRatedFeedback>(sql)
+                    .bind(&filter.from)
+                    .bind(&filter.to)
+                    .bind(&filter.project_id)
+                    .bind(&filter.org_id)
+                    .bind(limit)
+                    .fetch_all(&pool)
+                    .await
+            })
+            .await?;
+
+        Ok(feedback)
+    }
+
+    /// Per variant of the experiment, in order: its sessions' outcomes, ratings and cost
+    pub async fn get_variant_stats(&self, experiment_id: &str) -> Result<Vec<VariantStats>> {
+        let sql = &format!(
+            "SELECT
+                v.id AS variant_id,
+                COUNT(s.id) AS runs,
+                COALESCE(SUM(s.status = 'completed'), 0) AS completed,
+                COALESCE(SUM(s.status = 'failed'), 0) AS failed,
+                COALESCE(SUM(s.status = 'cancelled'), 0) AS cancelled,
+                ROUND(AVG(CASE WHEN s.status = 'completed' THEN {duration} END)) AS avg_duration_ms,
+                COALESCE(MAX(r.ratings), 0) AS ratings,
+                COALESCE(MAX(r.thumbs_up), 0) AS thumbs_up,
+                COALESCE(MAX(r.ratings) - MAX(r.thumbs_up), 0) AS thumbs_down,
+                CAST(MAX(r.thumbs_up) AS REAL) / MAX(r.ratings) AS approval_rate,
+                SUM(s.cost_usd) AS total_cost_usd,
+                AVG(s.cost_usd) AS avg_cost_usd
+             FROM prompt_variants v
+             LEFT JOIN analysis_sessions s ON s.variant_id = v.id
+             LEFT JOIN (
+                SELECT rated.variant_id, COUNT(*) AS ratings, SUM(f.rating = 1) AS thumbs_up
+                FROM result_feedback f
+                JOIN analysis_sessions rated ON rated.id = f.session_id
+                WHERE rated.experiment_id = ?1
+                GROUP BY rated.variant_id
+             ) r ON r.variant_id = v.id
+             WHERE v.experiment_id = ?1
+             GROUP BY v.id
+             ORDER BY v.position ASC",
+            duration = DURATION_MS,
+        );
+
+        let stats = self
+            .read_replica(|pool| async move {
+                sqlx::query_as::<_, VariantStats>(sql)
+                    .bind(experiment_id)
+                    .fetch_all(&pool)
+                    .await
+            })
+            .await?;
+
+        Ok(stats)
+    }
+
+    /// Write a consistent copy of the live database to `path` (which must not exist)
+    pub async fn vacuum_into(&self, path: &str) -> Result<()> {
+        sqlx::query("VACUUM INTO ?1")
+            .bind(path)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// `PRAGMA integrity_check`: `["ok"]` when the file is sound, otherwise up to `max_errors`
+    /// problems
+    pub async fn integrity_check(&self, max_errors: u32) -> Result<Vec<String>> {
+        let lines = sqlx::query_scalar::<_, String>(&format!("PRAGMA integrity_check({})", max_errors))
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(lines)
+    }
+
+    /// Rebuild the file without its free pages; blocks writers while it runs
+    pub async fn vacuum(&self) -> Result<()> {
+        sqlx::query("VACUUM").execute(&self.pool).await?;
         Ok(())
     }
 
-    pub async fn update_ticket_result(&self, ticket_id: &str, result: &str) -> Result<()> {
-        let now = Utc::now().to_rfc3339();
-        sqlx::query(
-            r#"
-            UPDATE tickets
-            SET analysis_result = ?1, is_analyzing = ?2, updated_at = ?3
-            WHERE id = ?4
-            "#,
-        )
-        .bind(result)
-        .bind(false)
-        .bind(now)
-        .bind(ticket_id)
-        .execute(&self.pool)
-        .await?;
-
+    /// Refresh the query planner's statistics (`sqlite_stat1`)
+    pub async fn analyze(&self) -> Result<()> {
+        sqlx::query("ANALYZE").execute(&self.pool).await?;
         Ok(())
     }
 
-    pub async fn get_ticket(&self, id: &str) -> Result<Option<TicketRecord>> {
-        let ticket = sqlx::query_as::<_, TicketRecord>(
-            "SELECT * FROM tickets WHERE id = ?1"
-        )
-        .bind(id)
-        .fetch_optional(&self.pool)
-        .await?;
-
-        Ok(ticket)
+    /// Page size, pages in the file and free pages among them
+    pub async fn page_stats(&self) -> Result<(i64, i64, i64)> {
+        let pragma = |name: &'static str| async move {
+            sqlx::query_scalar::<_, i64>(&format!("PRAGMA {}", name))
+                .fetch_one(&self.pool)
+                .await
+        };
+        Ok((pragma("page_size").await?, pragma("page_count").await?, pragma("freelist_count").await?))
     }
 
-    pub async fn list_tickets(&self) -> Result<Vec<TicketRecord>> {
-        let tickets = sqlx::query_as::<_, TicketRecord>(
-            "SELECT * FROM tickets ORDER BY created_at DESC"
+    /// Tables and indexes as `(type, name, table, sql)`, internal `sqlite_` ones left out;
+    /// `sql` is None for indexes created by a UNIQUE or PRIMARY KEY constraint
+    pub async fn list_schema_objects(&self) -> Result<Vec<(String, String, String, Option<String>)>> {
+        let objects = sqlx::query_as(
+            "SELECT type, name, tbl_name, sql FROM sqlite_master
+             WHERE type IN ('table', 'index') AND name NOT LIKE 'sqlite_%'
+             ORDER BY type DESC, name",
         )
         .fetch_all(&self.pool)
         .await?;
 
-        Ok(tickets)
+        Ok(objects)
     }
 
-    pub async fn list_tickets_by_project(&self, project_id: &str) -> Result<Vec<TicketRecord>> {
-        let tickets = sqlx::query_as::<_, TicketRecord>(
-            "SELECT * FROM tickets WHERE project_id = ?1 ORDER BY created_at DESC"
-        )
-        .bind(project_id)
-        .fetch_all(&self.pool)
-        .await?;
+    pub async fn count_rows(&self, table: &str) -> Result<i64> {
+        let count = sqlx::query_scalar::<_, i64>(&format!("SELECT COUNT(*) FROM \"{}\"", table.replace('"', "\"\"")))
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(count)
+    }
 
-        Ok(tickets)
+    /// Bytes each table and index takes, from the `dbstat` table; None when SQLite was built
+    /// without it
+    pub async fn object_sizes(&self) -> Result<Option<Vec<(String, i64)>>> {
+        match sqlx::query_as::<_, (String, i64)>("SELECT name, SUM(pgsize) FROM dbstat GROUP BY name")
+            .fetch_all(&self.pool)
+            .await
+        {
+            Ok(sizes) => Ok(Some(sizes)),
+            Err(sqlx::Error::Database(e)) if e.message().contains("no such table") => Ok(None),
+            Err(e) => Err(e.into()),
+        }
     }
 
-    pub async fn delete_ticket(&self, id: &str) -> Result<()> {
-        sqlx::query("DELETE FROM tickets WHERE id = ?1")
-            .bind(id)
-            .execute(&self.pool)
+    /// The planner's statistics per index from the last `ANALYZE` (`sqlite_stat1`: rows, then
+    /// average rows per distinct key prefix); empty before the first one
+    pub async fn index_stats(&self) -> Result<Vec<(String, String)>> {
+        if !self.table_exists("sqlite_stat1").await? {
+            return Ok(Vec::new());
+        }
+        let stats = sqlx::query_as("SELECT idx, stat FROM sqlite_stat1 WHERE idx IS NOT NULL")
+            .fetch_all(&self.pool)
             .await?;
+        Ok(stats)
+    }
 
+    /// Apply every pending migration in `MIGRATOR`, in version order. Fails when an applied
+    /// migration's file has changed since (checksum mismatch) or the database has migrations
+    /// this build doesn't know about.
+    pub async fn run_migrations(&self) -> Result<()> {
+        self.adopt_legacy_migrations().await?;
+        MIGRATOR.run(&self.pool).await.context("Failed to apply migrations")?;
         Ok(())
     }
 
-    // Log operations
-    pub async fn save_log(&self, log: &StructuredLogRecord) -> Result<()> {
-        sqlx::query(
-            r#"
-            INSERT INTO structured_logs (id, ticket_id, message_type, content, raw_log, metadata, timestamp)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
-            "#,
-        )
-        .bind(&log.id)
-        .bind(&log.ticket_id)
-        .bind(&log.message_type)
-        .bind(&log.content)
-        .bind(&log.raw_log)
-        .bind(&log.metadata)
-        .bind(&log.timestamp)
-        .execute(&self.pool)
-        .await?;
-
-        Ok(())
+    async fn table_exists(&self, name: &str) -> Result<bool> {
+        let count = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?1")
+            .bind(name)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(count > 0)
     }
 
-    pub async fn save_logs_batch(&self, logs: &[StructuredLogRecord]) -> Result<()> {
-        if logs.is_empty() {
+    /// Move history from the hand-rolled `migrations` table that predates `MIGRATOR` into
+    /// `_sqlx_migrations`, so databases created by older builds don't re-run 001–012
+    async fn adopt_legacy_migrations(&self) -> Result<()> {
+        if !self.table_exists("migrations").await? {
             return Ok(());
         }
 
-        // Use a transaction for batch insert
         let mut tx = self.pool.begin().await?;
-
-        for log in logs {
+        tx.ensure_migrations_table().await?;
+        let names: Vec<String> = sqlx::query_scalar("SELECT name FROM migrations")
+            .fetch_all(&mut *tx)
+            .await?;
+        for name in &names {
+            let Some(migration) = legacy_version(name).and_then(|version| MIGRATOR.iter().find(|m| m.version == version))
+            else {
+                continue;
+            };
             sqlx::query(
-                r#"
-                INSERT INTO structured_logs (id, ticket_id, message_type, content, raw_log, metadata, timestamp)
-                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
-                "#,
+                "INSERT OR IGNORE INTO _sqlx_migrations (version, description, success, checksum, execution_time)
+                 VALUES (?1, ?2, TRUE, ?3, 0)",
             )
-            .bind(&log.id)
-            .bind(&log.ticket_id)
-            .bind(&log.message_type)
-            .bind(&log.content)
-            .bind(&log.raw_log)
-            .bind(&log.metadata)
-            .bind(&log.timestamp)
+            .bind(migration.version)
+            .bind(&*migration.description)
+            .bind(&*migration.checksum)
             .execute(&mut *tx)
             .await?;
         }
-
+        sqlx::query("DROP TABLE migrations").execute(&mut *tx).await?;
         tx.commit().await?;
 
+        tracing::info!("📦 Đã chuyển {} migration cũ sang _sqlx_migrations", names.len());
         Ok(())
     }
 
-    pub async fn count_logs_for_ticket(&self, ticket_id: &str) -> Result<u64> {
-        let count: i64 = sqlx::query_scalar(
-            "SELECT COUNT(*) FROM structured_logs WHERE ticket_id = ?1"
-        )
-        .bind(ticket_id)
-        .fetch_one(&self.pool)
-        .await?;
+    /// Every migration of this build with its state in the database, plus any applied
+    /// migration this build doesn't know about. Read-only: nothing is applied or adopted.
+    pub async fn migration_status(&self) -> Result<Vec<MigrationStatus>> {
+        let mut applied: Vec<AppliedMigrationRow> = Vec::new();
+        if self.table_exists("_sqlx_migrations").await? {
+            applied = sqlx::query_as(
+                "SELECT version, description, CAST(installed_on AS TEXT) AS installed_on,
+                        CAST(success AS INTEGER) AS success, checksum
+                 FROM _sqlx_migrations ORDER BY version",
+            )
+            .fetch_all(&self.pool)
+            .await?;
+        }
+        if self.table_exists("migrations").await? {
+            let legacy: Vec<(String, String)> = sqlx::query_as("SELECT name, applied_at FROM migrations")
+                .fetch_all(&self.pool)
+                .await?;
+            for (name, applied_at) in legacy {
+                let Some(version) = legacy_version(&name) else { continue };
+                if !applied.iter().any(|row| row.version == version) {
+                    applied.push(AppliedMigrationRow {
+                        version,
+                        description: name,
+                        installed_on: applied_at,
+                        success: 1,
+                        checksum: None,
+                    });
+                }
+            }
+        }
 
-        Ok(count as u64)
+        let mut statuses: Vec<MigrationStatus> = MIGRATOR
+            .iter()
+            .map(|migration| {
+                let row = applied.iter().find(|row| row.version == migration.version);
+                let state = match row {
+                    None => MigrationState::Pending,
+                    Some(row) if row.success == 0 => MigrationState::Failed,
+                    Some(row) if row.checksum.as_deref().is_some_and(|c| c != &*migration.checksum) => {
+                        MigrationState::ChecksumMismatch
+                    }
+                    Some(_) => MigrationState::Applied,
+                };
+                MigrationStatus {
+                    version: migration.version,
+                    description: migration.description.to_string(),
+                    state,
+                    installed_on: row.map(|row| row.installed_on.clone()),
+                }
+            })
+            .collect();
+        statuses.extend(
+            applied
+                .into_iter()
+                .filter(|row| !MIGRATOR.version_exists(row.version))
+                .map(|row| MigrationStatus {
+                    version: row.version,
+                    description: row.description,
+                    state: MigrationState::Unknown,
+                    installed_on: Some(row.installed_on),
+                }),
+        );
+        statuses.sort_by_key(|status| status.version);
+        Ok(statuses)
     }
 
-    pub async fn get_logs_for_ticket(
-        &self,
-        ticket_id: &str,
-        limit: Option<u64>,
-        offset: Option<u64>,
-    ) -> Result<Vec<StructuredLogRecord>> {
-        // Ensure limit is always valid: minimum 1, maximum 1000, default 100
-        let limit = limit.unwrap_or(100).clamp(1, 1000);
-        let offset = offset.unwrap_or(0);
+    /// Check every table and column in `EXPECTED_SCHEMA` exists, so a database the migrations
+    /// didn't bring up to date fails at startup instead of on the first query that needs it
+    pub async fn verify_schema(&self) -> Result<()> {
+        let mut missing = Vec::new();
+        for (table, columns) in EXPECTED_SCHEMA {
+            let existing: Vec<String> = sqlx::query_scalar("SELECT name FROM pragma_table_info(?1)")
+                .bind(table)
+                .fetch_all(&self.pool)
+                .await?;
+            if existing.is_empty() {
+                missing.push(format!("table {}", table));
+                continue;
+            }
+            missing.extend(
+                columns
+                    .iter()
+                    .filter(|column| !existing.iter().any(|e| e == *column))
+                    .map(|column| format!("{}.{}", table, column)),
+            );
+        }
 
-        tracing::debug!(
-            "get_logs_for_ticket: ticket_id={}, limit={}, offset={}",
-            ticket_id,
-            limit,
-            offset
-        );
+        if !missing.is_empty() {
+            anyhow::bail!("Database schema doesn't match this build, missing: {}", missing.join(", "));
+        }
+        Ok(())
+    }
+}
 
-        let logs = sqlx::query(
-            "SELECT id, ticket_id, message_type, content, raw_log, metadata, timestamp 
-             FROM structured_logs 
-             WHERE ticket_id = ?1 
-             ORDER BY timestamp ASC 
-             LIMIT ?2 OFFSET ?3"
-        )
-        .bind(ticket_id)
-        .bind(limit as i64)
-        .bind(offset as i64)
-        .fetch_all(&self.pool)
-        .await?;
+/// `001_add_result_message_type` → 1
+fn legacy_version(name: &str) -> Option<i64> {
+    name.split('_').next()?.parse().ok()
+}
 
-        let mut result = Vec::new();
-        for row in logs {
-            result.push(StructuredLogRecord {
-                id: row.get("id"),
-                ticket_id: row.get("ticket_id"),
-                message_type: row.get("message_type"),
-                content: row.get("content"),
-                raw_log: row.get("raw_log"),
-                metadata: row.get("metadata"),
-                timestamp: row.get("timestamp"),
-            });
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{project_fixture, test_db, ticket_fixture};
 
-        Ok(result)
-    }
+    #[tokio::test]
+    async fn test_connect_creates_missing_directory() {
+        let dir = std::env::temp_dir().join(format!("qa-db-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("nested").join("qa.db");
+        let config = DatabaseConfig {
+            url: format!("sqlite:{}", path.display()),
+            journal_mode: Some("wal".to_string()),
+            max_connections: 2,
+            ..Default::default()
+        };
 
-    pub async fn clear_logs_for_ticket(&self, ticket_id: &str) -> Result<()> {
-        sqlx::query("DELETE FROM structured_logs WHERE ticket_id = ?1")
-            .bind(ticket_id)
-            .execute(&self.pool)
-            .await?;
+        let db = Database::connect(&config).await.unwrap();
+        db.init_schema().await.unwrap();
+        assert!(path.is_file());
+        assert!(diagnose_connection(&config).is_empty());
 
-        Ok(())
+        let missing = DatabaseConfig {
+            url: format!("sqlite:{}", dir.join("other.db").display()),
+            create_if_missing: false,
+            ..Default::default()
+        };
+        assert!(Database::connect(&missing).await.is_err());
+        assert!(diagnose_connection(&missing)[0].contains("DB_CREATE_IF_MISSING=false"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 
-    // Analysis session operations
-    pub async fn create_session(&self, ticket_id: &str) -> Result<String> {
-        let session_id = uuid::Uuid::new_v4().to_string();
-        let started_at = Utc::now().to_rfc3339();
+    #[tokio::test]
+    async fn test_reports_read_from_replicas() {
+        let dir = std::env::temp_dir().join(format!("qa-db-{}", uuid::Uuid::new_v4()));
+        let primary_url = format!("sqlite:{}", dir.join("primary.db").display());
+        let replica_path = dir.join("replica.db");
+        let db = Database::connect(&DatabaseConfig {
+            url: primary_url.clone(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        db.init_schema().await.unwrap();
+        db.run_migrations().await.unwrap();
 
-        sqlx::query(
-            r#"
-            INSERT INTO analysis_sessions (id, ticket_id, started_at, status)
-            VALUES (?1, ?2, ?3, 'running')
-            "#,
-        )
-        .bind(&session_id)
-        .bind(ticket_id)
-        .bind(started_at)
-        .execute(&self.pool)
-        .await?;
+        db.create_project(&project_fixture("p1")).await.unwrap();
+        db.create_ticket(&ticket_fixture("a", "p1")).await.unwrap();
+        // The replica is a copy from before the run, like one lagging behind
+        db.vacuum_into(&replica_path.to_string_lossy()).await.unwrap();
+        db.create_session("a", "claude", None, &ToolPolicy::default(), Default::default()).await.unwrap();
 
-        Ok(session_id)
+        let with_replica = |replica_url: String| DatabaseConfig {
+            url: primary_url.clone(),
+            replica_urls: vec![replica_url],
+            ..Default::default()
+        };
+        let filter = AnalyticsFilter::default();
+        let replicated = Database::connect(&with_replica(format!("sqlite:{}", replica_path.display())))
+            .await
+            .unwrap();
+        assert_eq!(replicated.get_session_totals(&filter).await.unwrap().total_runs, 0);
+
+        // A replica that is down falls back to the primary
+        let missing = Database::connect(&with_replica(format!("sqlite:{}", dir.join("gone.db").display())))
+            .await
+            .unwrap();
+        assert_eq!(missing.get_session_totals(&filter).await.unwrap().total_runs, 1);
+        assert!(!missing.replicas.replicas[0].is_up());
+
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 
-    pub async fn complete_session(&self, session_id: &str, _result: &str) -> Result<()> {
-        let completed_at = Utc::now().to_rfc3339();
+    #[tokio::test]
+    async fn test_legacy_migrations_are_adopted() {
+        let db = test_db().await.unwrap();
+        assert!(db.migration_status().await.unwrap().iter().all(|m| m.state == MigrationState::Applied));
 
-        sqlx::query(
-            r#"
-            UPDATE analysis_sessions
-            SET status = 'completed', completed_at = ?1
-            WHERE id = ?2
-            "#,
-        )
-        .bind(completed_at)
-        .bind(session_id)
-        .execute(&self.pool)
-        .await?;
+        // Rewind to the bookkeeping of older builds, which had applied 001–012
+        sqlx::query("DROP TABLE _sqlx_migrations").execute(&db.pool).await.unwrap();
+        sqlx::query("CREATE TABLE migrations (id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE, applied_at TEXT NOT NULL)")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        for migration in MIGRATOR.iter() {
+            sqlx::query("INSERT INTO migrations (name, applied_at) VALUES (?1, '2026-01-01T00:00:00Z')")
+                .bind(format!("{:03}_{}", migration.version, migration.description.replace(' ', "_")))
+                .execute(&db.pool)
+                .await
+                .unwrap();
+        }
+        assert!(db.migration_status().await.unwrap().iter().all(|m| m.state == MigrationState::Applied));
 
-        Ok(())
+        // Adopted rather than re-run: 003's ADD COLUMN would fail on the existing column
+        db.run_migrations().await.unwrap();
+        assert!(!db.table_exists("migrations").await.unwrap());
+        let status = db.migration_status().await.unwrap();
+        assert!(status.iter().all(|m| m.state == MigrationState::Applied));
+
+        // An edited migration is reported rather than silently re-applied
+        sqlx::query("UPDATE _sqlx_migrations SET checksum = x'00' WHERE version = 3")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        let status = db.migration_status().await.unwrap();
+        assert_eq!(status[2].state, MigrationState::ChecksumMismatch);
+        assert!(db.run_migrations().await.is_err());
     }
 
-    pub async fn fail_session(&self, session_id: &str, error: &str) -> Result<()> {
-        let completed_at = Utc::now().to_rfc3339();
+    #[tokio::test]
+    async fn test_verify_schema() {
+        let db = Database::new("sqlite::memory:").await.unwrap();
+        db.init_schema().await.unwrap();
+        assert!(db.verify_schema().await.is_err());
 
-        sqlx::query(
-            r#"
-            UPDATE analysis_sessions
-            SET status = 'failed', completed_at = ?1, error_message = ?2
-            WHERE id = ?3
-            "#,
-        )
-        .bind(completed_at)
-        .bind(error)
-        .bind(session_id)
-        .execute(&self.pool)
-        .await?;
+        db.run_migrations().await.unwrap();
+        db.verify_schema().await.unwrap();
 
-        Ok(())
+        sqlx::query("ALTER TABLE tickets DROP COLUMN stale").execute(&db.pool).await.unwrap();
+        sqlx::query("DROP TABLE slack_workspaces").execute(&db.pool).await.unwrap();
+        let error = db.verify_schema().await.unwrap_err().to_string();
+        assert!(error.ends_with("missing: tickets.stale, table slack_workspaces"), "{}", error);
     }
 
-    pub async fn cancel_session(&self, session_id: &str, reason: &str) -> Result<()> {
-        let completed_at = Utc::now().to_rfc3339();
+    #[tokio::test]
+    async fn test_query_project_logs() {
+        let db = test_db().await.unwrap();
 
-        sqlx::query(
-            r#"
-            UPDATE analysis_sessions
-            SET status = 'cancelled', completed_at = ?1, error_message = ?2
-            WHERE id = ?3
-            "#,
-        )
-        .bind(completed_at)
-        .bind(reason)
-        .bind(session_id)
-        .execute(&self.pool)
-        .await?;
+        for (project_id, ticket_ids) in [("p1", ["a", "b"]), ("p2", ["c", "d"])] {
+            db.create_project(&project_fixture(project_id)).await.unwrap();
+            for ticket_id in ticket_ids {
+                db.create_ticket(&ticket_fixture(ticket_id, project_id)).await.unwrap();
+            }
+        }
 
-        Ok(())
-    }
+        let log = |id: &str, ticket_id: &str, message_type: &str, content: &str, second: u32| StructuredLogRecord {
+            id: id.to_string(),
+            ticket_id: ticket_id.to_string(),
+            message_type: message_type.to_string(),
+            content: content.to_string(),
+            raw_log: None,
+            metadata: None,
+            timestamp: format!("2026-10-17T10:00:{:02}+00:00", second),
+            raw_log_blob: None,
+        };
+        db.save_logs_batch(&[
+            log("1", "a", "error", "Timeout calling tool", 1),
+            log("2", "a", "assistant", "Reading auth.rs", 2),
+            log("3", "b", "error", "Permission denied", 3),
+            log("4", "c", "error", "TIMEOUT elsewhere", 4),
+        ])
+        .await
+        .unwrap();
 
-    pub async fn get_active_session_by_ticket(&self, ticket_id: &str) -> Result<Option<AnalysisSession>> {
-        let session = sqlx::query_as::<_, AnalysisSession>(
-            "SELECT * FROM analysis_sessions 
-             WHERE ticket_id = ?1 AND status = 'running' 
-             ORDER BY started_at DESC LIMIT 1"
-        )
-        .bind(ticket_id)
-        .fetch_optional(&self.pool)
-        .await?;
+        let ids = |logs: Vec<StructuredLogRecord>| logs.into_iter().map(|l| l.id).collect::<Vec<_>>();
+        let all = LogFilter::default();
+        assert_eq!(ids(db.query_project_logs("p1", &all, LogOrder::Desc, 10, 0).await.unwrap()), ["3", "2", "1"]);
+        assert_eq!(ids(db.query_project_logs("p1", &all, LogOrder::Asc, 1, 1).await.unwrap()), ["2"]);
 
-        Ok(session)
+        let errors = LogFilter {
+            message_types: vec!["error".to_string(), "tool_use".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(ids(db.query_project_logs("p1", &errors, LogOrder::Asc, 10, 0).await.unwrap()), ["1", "3"]);
+
+        let search = LogFilter {
+            search: Some("timeout".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(ids(db.query_project_logs("p1", &search, LogOrder::Asc, 10, 0).await.unwrap()), ["1"]);
+
+        let window = LogFilter {
+            ticket_id: Some("a".to_string()),
+            from: Some("2026-10-17T10:00:02+00:00".to_string()),
+            until: Some("2026-10-17T10:00:03+00:00".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(ids(db.query_project_logs("p1", &window, LogOrder::Asc, 10, 0).await.unwrap()), ["2"]);
+
+        assert_eq!(
+            db.count_project_logs_by_type("p1", &all).await.unwrap(),
+            vec![("assistant".to_string(), 1), ("error".to_string(), 2)]
+        );
     }
 
-    pub async fn run_migrations(&self) -> Result<()> {
-        // Check migrations table exists
-        sqlx::query(
-            "CREATE TABLE IF NOT EXISTS migrations (
-                id INTEGER PRIMARY KEY,
-                name TEXT NOT NULL UNIQUE,
-                applied_at TEXT NOT NULL
-            )"
-        )
-        .execute(&self.pool)
-        .await?;
+    #[tokio::test]
+    async fn test_analytics_aggregates() {
+        let db = test_db().await.unwrap();
 
-        // Run 001_add_result_message_type if not applied
-        let migration_name = "001_add_result_message_type";
-        let exists = sqlx::query_scalar::<_, i64>(
-            "SELECT COUNT(*) FROM migrations WHERE name = ?1"
-        )
-        .bind(migration_name)
-        .fetch_one(&self.pool)
-        .await?;
+        let now = Utc::now().to_rfc3339();
+        db.create_organization(&OrganizationRecord {
+            id: "acme".to_string(),
+            name: "Acme".to_string(),
+            created_at: now.clone(),
+        })
+        .await
+        .unwrap();
+        for (project_id, org_id, ticket_id) in [("p1", DEFAULT_ORG_ID, "a"), ("p2", DEFAULT_ORG_ID, "b"), ("p3", "acme", "c")] {
+            db.create_project(&ProjectRecord {
+                name: format!("Project {}", project_id),
+                org_id: org_id.to_string(),
+                ..project_fixture(project_id)
+            })
+            .await
+            .unwrap();
+            db.create_ticket(&ticket_fixture(ticket_id, project_id)).await.unwrap();
+        }
 
-        if exists == 0 {
-            // Read migration SQL file
-            let migration_sql = include_str!("../migrations/001_add_result_message_type.sql");
-            
-            // Execute migration SQL
-            sqlx::query(migration_sql)
-                .execute(&self.pool)
-                .await?;
-            
-            // Mark as applied
-            sqlx::query("INSERT INTO migrations (name, applied_at) VALUES (?1, ?2)")
-                .bind(migration_name)
-                .bind(chrono::Utc::now().to_rfc3339())
-                .execute(&self.pool)
-                .await?;
+        // (ticket, agent, status, started_at, seconds until completed_at)
+        let sessions = [
+            ("a", Some("claude"), "completed", "2026-03-01T10:00:00+00:00", Some(60)),
+            ("a", Some("claude"), "failed", "2026-03-01T23:59:00+00:00", Some(30)),
+            ("a", Some("claude"), "running", "2026-03-02T00:00:00+00:00", None),
+            ("b", Some("gemini"), "completed", "2026-03-02T08:00:00+00:00", Some(120)),
+            ("b", None, "cancelled", "2026-03-03T12:00:00+00:00", Some(5)),
+            ("c", Some("claude"), "completed", "2026-03-02T09:00:00+00:00", Some(10)),
+        ];
+        for (i, (ticket_id, agent_type, status, started_at, seconds)) in sessions.into_iter().enumerate() {
+            let completed_at = seconds.map(|seconds| {
+                (chrono::DateTime::parse_from_rfc3339(started_at).unwrap() + chrono::Duration::seconds(seconds)).to_rfc3339()
+            });
+            sqlx::query(
+                "INSERT INTO analysis_sessions (id, ticket_id, started_at, completed_at, status, agent_type)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )
+            .bind(format!("s{}", i))
+            .bind(ticket_id)
+            .bind(started_at)
+            .bind(completed_at)
+            .bind(status)
+            .bind(agent_type)
+            .execute(&db.pool)
+            .await
+            .unwrap();
         }
 
-        // Run 002_add_cancelled_status if not applied
-        let migration_name_002 = "002_add_cancelled_status";
-        let exists_002 = sqlx::query_scalar::<_, i64>(
-            "SELECT COUNT(*) FROM migrations WHERE name = ?1"
-        )
-        .bind(migration_name_002)
-        .fetch_one(&self.pool)
-        .await?;
+        let filter = |from: Option<&str>, to: Option<&str>, project_id: Option<&str>, org_id: Option<&str>| AnalyticsFilter {
+            from: from.map(str::to_string),
+            to: to.map(str::to_string),
+            project_id: project_id.map(str::to_string),
+            org_id: org_id.map(str::to_string),
+        };
+        let default_org = filter(None, None, None, Some(DEFAULT_ORG_ID));
 
-        if exists_002 == 0 {
-            // Read migration SQL file
-            let migration_sql = include_str!("../migrations/002_add_cancelled_status.sql");
-            
-            // Execute migration SQL
-            sqlx::query(migration_sql)
-                .execute(&self.pool)
-                .await?;
-            
-            // Mark as applied
-            sqlx::query("INSERT INTO migrations (name, applied_at) VALUES (?1, ?2)")
-                .bind(migration_name_002)
-                .bind(chrono::Utc::now().to_rfc3339())
-                .execute(&self.pool)
-                .await?;
-        }
+        let totals = db.get_session_totals(&default_org).await.unwrap();
+        assert_eq!(
+            (totals.total_runs, totals.completed, totals.failed, totals.cancelled, totals.running),
+            (5, 2, 1, 1, 1)
+        );
+        // Durations count completed sessions only
+        assert_eq!((totals.avg_duration_ms, totals.max_duration_ms), (Some(90_000.0), Some(120_000.0)));
+        assert_eq!((totals.rate(totals.failed), totals.rate(totals.cancelled)), (0.2, 0.2));
+        let empty = db.get_session_totals(&filter(Some("2027-01-01"), None, None, None)).await.unwrap();
+        assert_eq!((empty.total_runs, empty.avg_duration_ms, empty.rate(empty.failed)), (0, None, 0.0));
 
-        Ok(())
+        let days: Vec<(String, i64, i64)> = db
+            .get_runs_per_day(&default_org)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|day| (day.date, day.runs, day.failed))
+            .collect();
+        assert_eq!(
+            days,
+            [
+                ("2026-03-01".to_string(), 2, 1),
+                ("2026-03-02".to_string(), 2, 0),
+                ("2026-03-03".to_string(), 1, 0),
+            ]
+        );
+
+        // Both bounds are inclusive and compare calendar days, also when given as timestamps
+        let one_day = filter(Some("2026-03-02"), Some("2026-03-02"), None, Some(DEFAULT_ORG_ID));
+        assert_eq!(db.get_session_totals(&one_day).await.unwrap().total_runs, 2);
+        let timestamps = filter(Some("2026-03-01T23:59:30+00:00"), Some("2026-03-02T00:00:00+00:00"), None, Some(DEFAULT_ORG_ID));
+        assert_eq!(db.get_session_totals(&timestamps).await.unwrap().total_runs, 4);
+
+        let agents: Vec<(String, i64, i64, i64, i64, Option<f64>)> = db
+            .get_agent_breakdown(&default_org)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|a| (a.agent_type, a.runs, a.completed, a.failed, a.cancelled, a.avg_duration_ms))
+            .collect();
+        assert_eq!(agents[0], ("claude".to_string(), 3, 1, 1, 0, Some(60_000.0)));
+        assert!(agents.contains(&("gemini".to_string(), 1, 1, 0, 0, Some(120_000.0))));
+        assert!(agents.contains(&("unknown".to_string(), 1, 0, 0, 1, None)));
+
+        let busiest = db.get_busiest_projects(&default_org, 10).await.unwrap();
+        let busiest: Vec<(&str, Option<&str>, i64, i64)> = busiest
+            .iter()
+            .map(|p| (p.project_id.as_str(), p.project_name.as_deref(), p.runs, p.failed))
+            .collect();
+        assert_eq!(busiest, [("p1", Some("Project p1"), 3, 1), ("p2", Some("Project p2"), 2, 0)]);
+        assert_eq!(db.get_busiest_projects(&default_org, 1).await.unwrap().len(), 1);
+
+        // Project and organization filters
+        assert_eq!(db.get_session_totals(&filter(None, None, Some("p2"), None)).await.unwrap().total_runs, 2);
+        let acme = db.get_session_totals(&filter(None, None, None, Some("acme"))).await.unwrap();
+        assert_eq!((acme.total_runs, acme.avg_duration_ms), (1, Some(10_000.0)));
+        assert_eq!(db.get_session_totals(&filter(None, None, Some("p1"), Some("acme"))).await.unwrap().total_runs, 0);
+        assert_eq!(db.get_session_totals(&filter(None, None, None, None)).await.unwrap().total_runs, 6);
     }
 }
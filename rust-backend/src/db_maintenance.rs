@@ -0,0 +1,276 @@
+//! Upkeep of the SQLite file (`/api/admin/database/*`): what it holds (size, free pages, rows
+//! per table, indexes with their size and planner statistics), and `PRAGMA integrity_check`,
+//! `VACUUM` and `ANALYZE`, on demand or every `DB_MAINTENANCE_INTERVAL_HOURS`.
+
+use crate::database::Database;
+use crate::tasks::TaskSupervisor;
+use anyhow::Result;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::time::{Duration, Instant};
+use tracing::{error, info, warn};
+
+/// Problems `integrity_check` reports at most
+const MAX_INTEGRITY_ERRORS: u32 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MaintenanceTask {
+    IntegrityCheck,
+    Vacuum,
+    /// After `Vacuum` when both run, so the statistics describe the rebuilt file
+    Analyze,
+}
+
+impl MaintenanceTask {
+    fn parse(value: &str) -> Option<Self> {
+        serde_json::from_value(serde_json::Value::String(value.to_string())).ok()
+    }
+}
+
+/// Scheduled maintenance, from `DB_MAINTENANCE_INTERVAL_HOURS` and `DB_MAINTENANCE_TASKS`
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaintenanceConfig {
+    /// 0 disables the schedule (the admin routes still work)
+    pub interval_hours: u64,
+    pub tasks: Vec<MaintenanceTask>,
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> Self {
+        Self {
+            interval_hours: 24,
+            tasks: vec![MaintenanceTask::IntegrityCheck, MaintenanceTask::Analyze],
+        }
+    }
+}
+
+impl MaintenanceConfig {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let tasks = std::env::var("DB_MAINTENANCE_TASKS")
+            .ok()
+            .map(|v| {
+                v.split(',')
+                    .map(|name| name.trim().to_lowercase())
+                    .filter(|name| !name.is_empty())
+                    .filter_map(|name| match MaintenanceTask::parse(&name) {
+                        Some(task) => Some(task),
+                        None => {
+                            warn!("⚠️ DB_MAINTENANCE_TASKS: bỏ qua tác vụ không hợp lệ '{}'", name);
+                            None
+                        }
+                    })
+                    .collect()
+            })
+            .unwrap_or(defaults.tasks);
+        Self {
+            interval_hours: std::env::var("DB_MAINTENANCE_INTERVAL_HOURS")
+                .ok()
+                .and_then(|s| s.trim().parse().ok())
+                .unwrap_or(defaults.interval_hours),
+            tasks,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TableStats {
+    pub name: String,
+    pub rows: i64,
+    /// None when SQLite was built without `dbstat`
+    pub size_bytes: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexStats {
+    pub name: String,
+    pub table: String,
+    /// Created by a UNIQUE or PRIMARY KEY constraint rather than `CREATE INDEX`
+    pub automatic: bool,
+    pub size_bytes: Option<i64>,
+    /// `sqlite_stat1` from the last `ANALYZE`: rows, then average rows per distinct key
+    /// prefix. SQLite doesn't count index lookups; this is what the planner goes by.
+    pub stat: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DatabaseStats {
+    /// Pages in the file times the page size
+    pub size_bytes: i64,
+    /// Of `size_bytes`, what free pages take up (given back by `VACUUM`)
+    pub free_bytes: i64,
+    pub page_size: i64,
+    pub tables: Vec<TableStats>,
+    pub indexes: Vec<IndexStats>,
+    /// Whether `ANALYZE` ever ran, so the indexes have a `stat`
+    pub analyzed: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskResult {
+    pub task: MaintenanceTask,
+    pub duration_ms: u64,
+    /// `integrity-check` only: empty when the file is sound
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub problems: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MaintenanceReport {
+    pub started_at: String,
+    pub size_before: i64,
+    pub size_after: i64,
+    pub tasks: Vec<TaskResult>,
+    /// False when the integrity check found problems
+    pub ok: bool,
+}
+
+pub async fn stats(database: &Database) -> Result<DatabaseStats> {
+    let (page_size, page_count, free_pages) = database.page_stats().await?;
+    let sizes: Option<HashMap<String, i64>> = database.object_sizes().await?.map(|sizes| sizes.into_iter().collect());
+    let index_stats: HashMap<String, String> = database.index_stats().await?.into_iter().collect();
+    let size = |name: &str| sizes.as_ref().map(|sizes| sizes.get(name).copied().unwrap_or(0));
+
+    let mut tables = Vec::new();
+    let mut indexes = Vec::new();
+    for (kind, name, table, sql) in database.list_schema_objects().await? {
+        if kind == "table" {
+            tables.push(TableStats {
+                rows: database.count_rows(&name).await?,
+                size_bytes: size(&name),
+                name,
+            });
+        } else {
+            indexes.push(IndexStats {
+                table,
+                automatic: sql.is_none(),
+                size_bytes: size(&name),
+                stat: index_stats.get(&name).cloned(),
+                name,
+            });
+        }
+    }
+
+    Ok(DatabaseStats {
+        size_bytes: page_size * page_count,
+        free_bytes: page_size * free_pages,
+        page_size,
+        tables,
+        indexes,
+        analyzed: !index_stats.is_empty(),
+    })
+}
+
+/// Run `tasks` once each: integrity check first, then vacuum, then analyze
+pub async fn run(database: &Database, tasks: &[MaintenanceTask]) -> Result<MaintenanceReport> {
+    let mut tasks = tasks.to_vec();
+    tasks.sort();
+    tasks.dedup();
+
+    let started_at = Utc::now().to_rfc3339();
+    let size = || async { database.page_stats().await.map(|(page_size, pages, _)| page_size * pages) };
+    let size_before = size().await?;
+    let mut results = Vec::new();
+    let mut ok = true;
+    for task in tasks {
+        let started = Instant::now();
+        let mut problems = None;
+        match task {
+            MaintenanceTask::IntegrityCheck => {
+                let lines = database.integrity_check(MAX_INTEGRITY_ERRORS).await?;
+                let found: Vec<String> = lines.into_iter().filter(|line| line != "ok").collect();
+                ok = found.is_empty();
+                problems = Some(found);
+            }
+            MaintenanceTask::Vacuum => database.vacuum().await?,
+            MaintenanceTask::Analyze => database.analyze().await?,
+        }
+        results.push(TaskResult {
+            task,
+            duration_ms: started.elapsed().as_millis() as u64,
+            problems,
+        });
+    }
+
+    Ok(MaintenanceReport {
+        started_at,
+        size_before,
+        size_after: size().await?,
+        tasks: results,
+        ok,
+    })
+}
+
+/// Run the configured tasks every `interval_hours`; no-op when the interval is 0 or there are
+/// no tasks
+pub fn spawn_scheduler(database: Arc<Database>, config: MaintenanceConfig, tasks: &TaskSupervisor) {
+    if config.interval_hours == 0 || config.tasks.is_empty() {
+        info!("🧹 Scheduled database maintenance disabled");
+        return;
+    }
+    info!("🧹 Scheduled database maintenance every {}h: {:?}", config.interval_hours, config.tasks);
+
+    tasks.spawn("db-maintenance", async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(config.interval_hours * 3600));
+        // The first tick fires immediately; skip it so startup isn't slowed down
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            match run(&database, &config.tasks).await {
+                Ok(report) if report.ok => info!(
+                    "🧹 Bảo trì database xong: {} → {} bytes",
+                    report.size_before, report.size_after
+                ),
+                Ok(report) => {
+                    let problems = report.tasks.iter().filter_map(|task| task.problems.as_ref()).flatten();
+                    for problem in problems {
+                        error!("❌ Database integrity check: {}", problem);
+                    }
+                }
+                Err(e) => error!("❌ Bảo trì database định kỳ thất bại: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{project_fixture, test_db};
+
+    #[tokio::test]
+    async fn test_stats_and_maintenance() {
+        let db = test_db().await.unwrap();
+
+        let before = stats(&db).await.unwrap();
+        assert!(before.size_bytes > 0);
+        assert!(!before.analyzed);
+        let tickets = before.tables.iter().find(|table| table.name == "tickets").unwrap();
+        assert_eq!(tickets.rows, 0);
+        assert!(before.indexes.iter().any(|index| index.table == "tickets" && !index.automatic));
+
+        db.create_project(&project_fixture("p1")).await.unwrap();
+
+        let report = run(
+            &db,
+            &[MaintenanceTask::Analyze, MaintenanceTask::IntegrityCheck, MaintenanceTask::Vacuum, MaintenanceTask::Analyze],
+        )
+        .await
+        .unwrap();
+        assert!(report.ok);
+        let order: Vec<MaintenanceTask> = report.tasks.iter().map(|task| task.task).collect();
+        assert_eq!(order, [MaintenanceTask::IntegrityCheck, MaintenanceTask::Vacuum, MaintenanceTask::Analyze]);
+        assert_eq!(report.tasks[0].problems, Some(Vec::new()));
+
+        let after = stats(&db).await.unwrap();
+        assert!(after.analyzed);
+        assert_eq!(after.tables.iter().find(|table| table.name == "projects").unwrap().rows, 1);
+        assert!(after.indexes.iter().any(|index| index.table == "projects" && index.stat.is_some()));
+
+        assert_eq!(MaintenanceTask::parse("integrity-check"), Some(MaintenanceTask::IntegrityCheck));
+        assert_eq!(MaintenanceTask::parse("reindex"), None);
+    }
+}
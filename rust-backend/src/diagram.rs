@@ -0,0 +1,226 @@
+use crate::database::TicketDiagramRecord;
+use anyhow::{anyhow, Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+use std::time::Duration;
+use thiserror::Error;
+
+/// Instructions appended to the QA's question in `diagram` mode
+const PROMPT_TEMPLATE: &str = "Draw the flow described below as a Mermaid diagram, based on the actual code.
+Use a sequenceDiagram when the flow is about components, services or actors calling each other,
+and a flowchart TD when it is about business rules, decisions and branches.
+Name participants and steps after the real classes, endpoints and functions, keep labels short,
+and put labels containing punctuation in double quotes.
+
+Answer with a single ```mermaid fenced block containing the diagram. You may add a short
+explanation after the block.
+
+Flow to draw: ";
+
+/// Largest diagram source kept; anything bigger is not a readable diagram
+const MAX_SOURCE_BYTES: usize = 100 * 1024;
+
+/// Wrap a question in the diagram prompt of `diagram` mode
+pub fn build_prompt(question: &str) -> String {
+    format!("{}{}", PROMPT_TEMPLATE, question.trim())
+}
+
+/// Mermaid diagram type, from the source's first statement
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiagramKind {
+    Sequence,
+    Flowchart,
+    State,
+    Class,
+    Er,
+}
+
+impl DiagramKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Sequence => "sequence",
+            Self::Flowchart => "flowchart",
+            Self::State => "state",
+            Self::Class => "class",
+            Self::Er => "er",
+        }
+    }
+
+    /// Type declared by a Mermaid header line (`sequenceDiagram`, `flowchart LR`, `graph TD`, ...)
+    fn from_header(line: &str) -> Option<Self> {
+        match line.split_whitespace().next()? {
+            "sequenceDiagram" => Some(Self::Sequence),
+            "flowchart" | "graph" => Some(Self::Flowchart),
+            "stateDiagram" | "stateDiagram-v2" => Some(Self::State),
+            "classDiagram" => Some(Self::Class),
+            "erDiagram" => Some(Self::Er),
+            _ => None,
+        }
+    }
+}
+
+/// A diagram as extracted from an agent's answer
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagram {
+    pub kind: DiagramKind,
+    pub source: String,
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum DiagramError {
+    #[error("No Mermaid diagram found in the result")]
+    NotFound,
+
+    #[error("Unsupported Mermaid diagram type: {0}")]
+    Unsupported(String),
+
+    #[error("The Mermaid diagram has no statements")]
+    Empty,
+
+    #[error("The Mermaid diagram is larger than {0} bytes")]
+    TooLarge(usize),
+}
+
+/// Extract the diagram from an agent's answer: the ```mermaid block the prompt asks for, or
+/// another fenced block holding Mermaid when the model labelled it differently
+pub fn extract(output: &str) -> Result<Diagram, DiagramError> {
+    static FENCED: OnceLock<Regex> = OnceLock::new();
+    let fenced = FENCED.get_or_init(|| Regex::new(r"(?s)```[ \t]*([A-Za-z]*)[^\n]*\n(.*?)```").unwrap());
+
+    let blocks: Vec<(&str, &str)> = fenced
+        .captures_iter(output)
+        .filter_map(|c| Some((c.get(1)?.as_str(), c.get(2)?.as_str())))
+        .collect();
+    let source = blocks
+        .iter()
+        .find(|(language, _)| language.eq_ignore_ascii_case("mermaid"))
+        .or_else(|| blocks.iter().find(|(_, body)| header(body).and_then(DiagramKind::from_header).is_some()))
+        .map(|(_, body)| *body)
+        .ok_or(DiagramError::NotFound)?;
+
+    validate(source)
+}
+
+/// First line that isn't blank, a `%%` comment or a `---` front matter fence
+fn header(source: &str) -> Option<&str> {
+    statements(source).next()
+}
+
+fn statements(source: &str) -> impl Iterator<Item = &str> {
+    source
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with("%%") && *line != "---")
+}
+
+fn validate(source: &str) -> Result<Diagram, DiagramError> {
+    let source = source.trim();
+    if source.len() > MAX_SOURCE_BYTES {
+        return Err(DiagramError::TooLarge(MAX_SOURCE_BYTES));
+    }
+    let first = header(source).ok_or(DiagramError::Empty)?;
+    let kind = DiagramKind::from_header(first)
+        .ok_or_else(|| DiagramError::Unsupported(first.split_whitespace().next().unwrap_or_default().to_string()))?;
+    if statements(source).count() < 2 {
+        return Err(DiagramError::Empty);
+    }
+
+    Ok(Diagram {
+        kind,
+        source: source.to_string(),
+    })
+}
+
+pub fn to_record(ticket_id: &str, run_id: Option<&str>, diagram: Diagram) -> TicketDiagramRecord {
+    TicketDiagramRecord {
+        ticket_id: ticket_id.to_string(),
+        run_id: run_id.map(str::to_string),
+        kind: diagram.kind.as_str().to_string(),
+        source: diagram.source,
+        svg: None,
+        created_at: chrono::Utc::now().to_rfc3339(),
+    }
+}
+
+/// Renders diagrams to SVG with the Mermaid CLI (`mmdc`); None when `MERMAID_CLI_PATH` isn't set
+#[derive(Debug, Clone)]
+pub struct MermaidRenderer {
+    pub executable_path: String,
+    pub timeout: Duration,
+}
+
+impl MermaidRenderer {
+    pub fn from_env() -> Option<Self> {
+        let executable_path = std::env::var("MERMAID_CLI_PATH").ok().filter(|path| !path.trim().is_empty())?;
+        Some(Self {
+            executable_path,
+            timeout: Duration::from_secs(
+                std::env::var("MERMAID_RENDER_TIMEOUT_SECS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .filter(|n| *n > 0)
+                    .unwrap_or(30),
+            ),
+        })
+    }
+
+    pub async fn render(&self, source: &str) -> Result<String> {
+        let dir = std::env::temp_dir();
+        let name = uuid::Uuid::new_v4();
+        let input = dir.join(format!("diagram-{}.mmd", name));
+        let output = dir.join(format!("diagram-{}.svg", name));
+        tokio::fs::write(&input, source).await.context("Cannot write diagram source")?;
+
+        let run = tokio::process::Command::new(&self.executable_path)
+            .arg("-i")
+            .arg(&input)
+            .arg("-o")
+            .arg(&output)
+            .args(["-b", "transparent", "--quiet"])
+            .kill_on_drop(true)
+            .output();
+        let result = match tokio::time::timeout(self.timeout, run).await {
+            Err(_) => Err(anyhow!("Mermaid CLI timed out after {}s", self.timeout.as_secs())),
+            Ok(Err(e)) => Err(anyhow!("Cannot run {}: {}", self.executable_path, e)),
+            Ok(Ok(run)) if !run.status.success() => Err(anyhow!(
+                "Mermaid CLI failed: {}",
+                String::from_utf8_lossy(&run.stderr).trim()
+            )),
+            Ok(Ok(_)) => tokio::fs::read_to_string(&output).await.context("Cannot read rendered SVG"),
+        };
+
+        let _ = tokio::fs::remove_file(&input).await;
+        let _ = tokio::fs::remove_file(&output).await;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_mermaid_block() {
+        let output = "Here is the flow:\n\n```mermaid\nsequenceDiagram\n    participant UI\n    UI->>AuthController: POST /login\n```\n\nThe controller validates the password.";
+        let diagram = extract(output).unwrap();
+        assert_eq!(diagram.kind, DiagramKind::Sequence);
+        assert!(diagram.source.starts_with("sequenceDiagram\n"));
+        assert!(diagram.source.ends_with("POST /login"));
+    }
+
+    #[test]
+    fn test_extract_unlabelled_and_invalid_blocks() {
+        let output = "```json\n{}\n```\n```\n%% checkout\nflowchart TD\n  A[Cart] --> B{Paid?}\n```";
+        assert_eq!(extract(output).unwrap().kind, DiagramKind::Flowchart);
+
+        assert_eq!(extract("No diagram, sorry."), Err(DiagramError::NotFound));
+        assert_eq!(
+            extract("```mermaid\npie title Pets\n  \"Dogs\" : 3\n```"),
+            Err(DiagramError::Unsupported("pie".to_string()))
+        );
+        assert_eq!(extract("```mermaid\ngraph TD\n```"), Err(DiagramError::Empty));
+        assert!(build_prompt(" checkout ").ends_with("Flow to draw: checkout"));
+    }
+}
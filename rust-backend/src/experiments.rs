@@ -0,0 +1,346 @@
+use crate::code_agent::CodeAnalysisRequest;
+use crate::database::{Database, ExperimentRecord, PromptVariantRecord, VariantStats};
+use chrono::Utc;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+const MAX_NAME_CHARS: usize = 64;
+const MAX_TEMPLATE_CHARS: usize = 4000;
+const MAX_VARIANTS: usize = 5;
+const MAX_WEIGHT: i64 = 100;
+
+/// Marks where a variant's template puts the question
+pub const QUESTION_PLACEHOLDER: &str = "{question}";
+
+/// The variant a run was assigned to, carried on its request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PromptVariant {
+    pub experiment_id: String,
+    pub variant_id: String,
+    pub template: String,
+}
+
+impl PromptVariant {
+    /// The template with the question (already in its mode's template) in place
+    pub fn apply(&self, question: &str) -> String {
+        self.template.replace(QUESTION_PLACEHOLDER, question)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ExperimentError {
+    #[error("{0}")]
+    InvalidDefinition(String),
+
+    #[error("Experiment {0} is still running in this project")]
+    AlreadyRunning(String),
+
+    #[error("Experiment is already stopped")]
+    AlreadyStopped,
+
+    #[error(transparent)]
+    Database(#[from] anyhow::Error),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewVariant {
+    pub name: String,
+    pub template: String,
+    #[serde(default = "default_weight")]
+    pub weight: i64,
+}
+
+fn default_weight() -> i64 {
+    1
+}
+
+/// An experiment with its variants
+#[derive(Debug, Clone, Serialize)]
+pub struct Experiment {
+    #[serde(flatten)]
+    pub experiment: ExperimentRecord,
+    pub variants: Vec<PromptVariantRecord>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VariantReport {
+    #[serde(flatten)]
+    pub variant: PromptVariantRecord,
+    #[serde(flatten)]
+    pub stats: VariantStats,
+}
+
+/// Per-variant results of an experiment, in the order the variants were defined
+#[derive(Debug, Clone, Serialize)]
+pub struct ExperimentReport {
+    #[serde(flatten)]
+    pub experiment: ExperimentRecord,
+    pub variants: Vec<VariantReport>,
+}
+
+fn check_name(name: &str, what: &str) -> Result<String, ExperimentError> {
+    let name = name.trim().to_string();
+    if name.is_empty() || name.chars().count() > MAX_NAME_CHARS {
+        return Err(ExperimentError::InvalidDefinition(format!(
+            "{} names must have 1 to {} characters",
+            what, MAX_NAME_CHARS
+        )));
+    }
+    Ok(name)
+}
+
+/// Start an experiment in a project that has none running
+pub async fn create_experiment(
+    database: &Database,
+    project_id: &str,
+    name: &str,
+    variants: Vec<NewVariant>,
+    created_by: Option<String>,
+) -> Result<Experiment, ExperimentError> {
+    let name = check_name(name, "Experiment")?;
+    if !(2..=MAX_VARIANTS).contains(&variants.len()) {
+        return Err(ExperimentError::InvalidDefinition(format!(
+            "An experiment needs 2 to {} variants",
+            MAX_VARIANTS
+        )));
+    }
+
+    let experiment_id = uuid::Uuid::new_v4().to_string();
+    let mut records: Vec<PromptVariantRecord> = Vec::new();
+    for (position, variant) in variants.into_iter().enumerate() {
+        let variant_name = check_name(&variant.name, "Variant")?;
+        if records.iter().any(|r| r.name.eq_ignore_ascii_case(&variant_name)) {
+            return Err(ExperimentError::InvalidDefinition(format!(
+                "Two variants are named {}",
+                variant_name
+            )));
+        }
+        let template = variant.template.trim().to_string();
+        if !template.contains(QUESTION_PLACEHOLDER) || template.chars().count() > MAX_TEMPLATE_CHARS {
+            return Err(ExperimentError::InvalidDefinition(format!(
+                "The template of {} must contain {} and have at most {} characters",
+                variant_name, QUESTION_PLACEHOLDER, MAX_TEMPLATE_CHARS
+            )));
+        }
+        if !(1..=MAX_WEIGHT).contains(&variant.weight) {
+            return Err(ExperimentError::InvalidDefinition(format!(
+                "Variant weights must be between 1 and {}",
+                MAX_WEIGHT
+            )));
+        }
+        records.push(PromptVariantRecord {
+            id: uuid::Uuid::new_v4().to_string(),
+            experiment_id: experiment_id.clone(),
+            position: position as i64,
+            name: variant_name,
+            template,
+            weight: variant.weight,
+        });
+    }
+
+    if let Some(running) = database.get_running_experiment(project_id).await? {
+        return Err(ExperimentError::AlreadyRunning(running.name));
+    }
+    let experiment = ExperimentRecord {
+        id: experiment_id,
+        project_id: project_id.to_string(),
+        name,
+        status: "running".to_string(),
+        created_by,
+        created_at: Utc::now().to_rfc3339(),
+        stopped_at: None,
+    };
+    database.create_experiment(&experiment, &records).await?;
+    Ok(Experiment {
+        experiment,
+        variants: records,
+    })
+}
+
+/// Stop assigning new runs to the experiment; its results stay available
+pub async fn stop_experiment(
+    database: &Database,
+    experiment: &ExperimentRecord,
+) -> Result<ExperimentRecord, ExperimentError> {
+    let stopped_at = Utc::now().to_rfc3339();
+    if !database.stop_experiment(&experiment.id, &stopped_at).await? {
+        return Err(ExperimentError::AlreadyStopped);
+    }
+    Ok(ExperimentRecord {
+        status: "stopped".to_string(),
+        stopped_at: Some(stopped_at),
+        ..experiment.clone()
+    })
+}
+
+/// Variant whose share of the total weight `roll` (0 until the total) falls in
+fn pick(variants: &[PromptVariantRecord], mut roll: i64) -> Option<&PromptVariantRecord> {
+    variants.iter().find(|variant| {
+        roll -= variant.weight;
+        roll < 0
+    })
+}
+
+/// Give a new run one of the variants of its project's running experiment, at random by weight.
+/// Replays keep the variant they were recorded with. Returns a note for the ticket's log.
+pub async fn assign(database: &Database, request: &mut CodeAnalysisRequest) -> anyhow::Result<Option<String>> {
+    if request.replay.is_some() {
+        return Ok(None);
+    }
+    request.prompt_variant = None;
+    let Some(experiment) = database.get_running_experiment(&request.project_id).await? else {
+        return Ok(None);
+    };
+    let variants = database.list_prompt_variants(&experiment.id).await?;
+    let total: i64 = variants.iter().map(|v| v.weight).sum();
+    if total <= 0 {
+        return Ok(None);
+    }
+    let roll = rand::thread_rng().gen_range(0..total);
+    let Some(variant) = pick(&variants, roll) else {
+        return Ok(None);
+    };
+
+    request.prompt_variant = Some(PromptVariant {
+        experiment_id: experiment.id.clone(),
+        variant_id: variant.id.clone(),
+        template: variant.template.clone(),
+    });
+    Ok(Some(format!(
+        "🧪 Thử nghiệm prompt \"{}\": dùng biến thể \"{}\"",
+        experiment.name, variant.name
+    )))
+}
+
+pub async fn report(database: &Database, experiment: ExperimentRecord) -> anyhow::Result<ExperimentReport> {
+    let mut stats = database.get_variant_stats(&experiment.id).await?;
+    let variants = database
+        .list_prompt_variants(&experiment.id)
+        .await?
+        .into_iter()
+        .filter_map(|variant| {
+            let index = stats.iter().position(|s| s.variant_id == variant.id)?;
+            Some(VariantReport {
+                variant,
+                stats: stats.swap_remove(index),
+            })
+        })
+        .collect();
+    Ok(ExperimentReport { experiment, variants })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::TicketRecord;
+    use crate::test_support::{project_fixture, test_db, ticket_fixture};
+    use crate::tool_policy::ToolPolicy;
+
+    fn variant(name: &str, template: &str) -> NewVariant {
+        NewVariant {
+            name: name.to_string(),
+            template: template.to_string(),
+            weight: 1,
+        }
+    }
+
+    #[test]
+    fn test_pick_by_weight() {
+        let record = |name: &str, weight| PromptVariantRecord {
+            id: name.to_string(),
+            experiment_id: "e1".to_string(),
+            position: 0,
+            name: name.to_string(),
+            template: QUESTION_PLACEHOLDER.to_string(),
+            weight,
+        };
+        let variants = [record("a", 1), record("b", 3)];
+        let picked: Vec<&str> = (0..4).map(|roll| pick(&variants, roll).unwrap().name.as_str()).collect();
+        assert_eq!(picked, ["a", "b", "b", "b"]);
+        assert_eq!(pick(&variants, 4), None);
+    }
+
+    #[tokio::test]
+    async fn test_runs_are_assigned_and_reported_per_variant() {
+        let db = test_db().await.unwrap();
+        db.create_project(&project_fixture("p1")).await.unwrap();
+        db.create_ticket(&TicketRecord {
+            title: "Login".to_string(),
+            ..ticket_fixture("t1", "p1")
+        })
+        .await
+        .unwrap();
+
+        assert!(matches!(
+            create_experiment(&db, "p1", "Tone", vec![variant("a", "{question}")], None).await,
+            Err(ExperimentError::InvalidDefinition(_))
+        ));
+        assert!(matches!(
+            create_experiment(&db, "p1", "Tone", vec![variant("a", "{question}"), variant("b", "Explain")], None).await,
+            Err(ExperimentError::InvalidDefinition(_))
+        ));
+        let experiment = create_experiment(
+            &db,
+            "p1",
+            "Tone",
+            vec![variant("plain", "{question}"), variant("steps", "Answer step by step. {question}")],
+            None,
+        )
+        .await
+        .unwrap();
+        assert!(matches!(
+            create_experiment(&db, "p1", "Again", vec![variant("a", "{question}"), variant("b", "{question}")], None)
+                .await,
+            Err(ExperimentError::AlreadyRunning(name)) if name == "Tone"
+        ));
+
+        let mut request = CodeAnalysisRequest {
+            ticket_id: "t1".to_string(),
+            code_context: String::new(),
+            question: "How does login work?".to_string(),
+            project_id: "p1".to_string(),
+            run_id: None,
+            agent_type: Some("claude".to_string()),
+            mode: Default::default(),
+            path_rules: Default::default(),
+            tool_policy: Default::default(),
+            priority: Default::default(),
+            include_linked_results: false,
+            budget_override: false,
+            replay: None,
+            working_dir: None,
+            prompt_variant: None,
+            response_language: None,
+            api_key: None,
+            then: Vec::new(),
+        };
+        assert!(assign(&db, &mut request).await.unwrap().is_some());
+        let assigned = request.prompt_variant.clone().unwrap();
+        assert_eq!(assigned.experiment_id, experiment.experiment.id);
+        let template = &experiment.variants.iter().find(|v| v.id == assigned.variant_id).unwrap().template;
+        assert_eq!(request.prompt_question(), template.replace(QUESTION_PLACEHOLDER, "How does login work?"));
+
+        let session_id = db.create_session("t1", "claude", None, &ToolPolicy::default(), Default::default()).await.unwrap();
+        db.record_session_request(&session_id, &request, None).await.unwrap();
+        db.complete_session(&session_id, "done").await.unwrap();
+
+        let report = report(&db, experiment.experiment.clone()).await.unwrap();
+        assert_eq!(report.variants.len(), 2);
+        let runs: Vec<(bool, i64)> = report
+            .variants
+            .iter()
+            .map(|v| (v.variant.id == assigned.variant_id, v.stats.runs))
+            .collect();
+        assert!(runs.iter().all(|(is_assigned, count)| *count == i64::from(*is_assigned)));
+        assert!(report.variants.iter().all(|v| v.stats.approval_rate.is_none()));
+
+        // Stopped experiments assign nothing
+        stop_experiment(&db, &experiment.experiment).await.unwrap();
+        assert!(matches!(
+            stop_experiment(&db, &experiment.experiment).await,
+            Err(ExperimentError::AlreadyStopped)
+        ));
+        assert_eq!(assign(&db, &mut request).await.unwrap(), None);
+        assert_eq!(request.prompt_variant, None);
+    }
+}
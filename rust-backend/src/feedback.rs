@@ -0,0 +1,264 @@
+use crate::code_agent::CodeAnalysisRequest;
+use crate::database::{AnalyticsFilter, Database, LowRatedFeedback, ResultFeedbackRecord, TicketRecord};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+/// Longest comment kept with a rating
+pub const MAX_COMMENT_CHARS: usize = 2000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Rating {
+    Up,
+    Down,
+}
+
+impl Rating {
+    /// Stored value: 1 for thumbs up, -1 for thumbs down
+    pub fn value(&self) -> i64 {
+        match self {
+            Rating::Up => 1,
+            Rating::Down => -1,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FeedbackError {
+    #[error("Ticket has no analysis result to rate")]
+    NoResult,
+
+    #[error("Comment is longer than {} characters", MAX_COMMENT_CHARS)]
+    CommentTooLong,
+
+    #[error(transparent)]
+    Database(#[from] anyhow::Error),
+}
+
+/// Rate the ticket's current result, i.e. its latest completed session
+pub async fn rate(
+    database: &Database,
+    ticket: &TicketRecord,
+    user_id: Option<String>,
+    rating: Rating,
+    comment: Option<String>,
+) -> Result<ResultFeedbackRecord, FeedbackError> {
+    let comment = comment.map(|c| c.trim().to_string()).filter(|c| !c.is_empty());
+    if comment.as_ref().is_some_and(|c| c.chars().count() > MAX_COMMENT_CHARS) {
+        return Err(FeedbackError::CommentTooLong);
+    }
+    if ticket.analysis_result.is_none() {
+        return Err(FeedbackError::NoResult);
+    }
+    let session = database
+        .get_latest_completed_session(&ticket.id)
+        .await?
+        .ok_or(FeedbackError::NoResult)?;
+
+    let now = Utc::now().to_rfc3339();
+    let feedback = ResultFeedbackRecord {
+        id: uuid::Uuid::new_v4().to_string(),
+        ticket_id: ticket.id.clone(),
+        session_id: session.id,
+        user_id,
+        rating: rating.value(),
+        comment,
+        created_at: now.clone(),
+        updated_at: now,
+    };
+    Ok(database.upsert_result_feedback(&feedback).await?)
+}
+
+/// One line of the prompt-tuning export: what the agent was asked, what it answered and why
+/// the rater disliked it
+#[derive(Debug, Clone, Serialize)]
+pub struct FeedbackExample {
+    pub feedback_id: String,
+    pub ticket_id: String,
+    pub project_id: String,
+    pub session_id: String,
+    pub agent_type: Option<String>,
+    pub model: Option<String>,
+    pub mode: Option<String>,
+    pub question: Option<String>,
+    pub code_context: Option<String>,
+    /// None once a newer run replaced the rated result
+    pub result: Option<String>,
+    pub comment: Option<String>,
+    pub rated_by: Option<String>,
+    pub rated_at: String,
+}
+
+/// Thumbs-down rated results matching `filter`, with their requests and (while still current)
+/// their results
+pub async fn low_rated_examples(
+    database: &Database,
+    filter: &AnalyticsFilter,
+    limit: u32,
+) -> anyhow::Result<Vec<FeedbackExample>> {
+    let mut examples = Vec::new();
+    for feedback in database.list_low_rated_feedback(filter, limit).await? {
+        let result = match feedback.is_current {
+            true => match database.get_ticket(&feedback.ticket_id).await? {
+                Some(ticket) => database.full_result(&ticket).await?,
+                None => None,
+            },
+            false => None,
+        };
+        examples.push(example(feedback, result));
+    }
+    Ok(examples)
+}
+
+fn example(feedback: LowRatedFeedback, result: Option<String>) -> FeedbackExample {
+    let request = feedback
+        .request_json
+        .as_deref()
+        .and_then(|json| serde_json::from_str::<CodeAnalysisRequest>(json).ok());
+    let mode = request
+        .as_ref()
+        .and_then(|r| serde_json::to_value(r.mode).ok())
+        .and_then(|mode| mode.as_str().map(str::to_string));
+
+    FeedbackExample {
+        feedback_id: feedback.feedback_id,
+        ticket_id: feedback.ticket_id,
+        project_id: feedback.project_id,
+        session_id: feedback.session_id,
+        agent_type: feedback.agent_type,
+        model: feedback.model,
+        mode,
+        question: request.as_ref().map(|r| r.question.clone()),
+        code_context: request.map(|r| r.code_context),
+        result,
+        comment: feedback.comment,
+        rated_by: feedback.rated_by,
+        rated_at: feedback.rated_at,
+    }
+}
+
+/// JSON Lines, one example per line
+pub fn to_jsonl(examples: &[FeedbackExample]) -> String {
+    examples
+        .iter()
+        .filter_map(|example| serde_json::to_string(example).ok())
+        .map(|line| line + "\n")
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::code_agent::AnalysisMode;
+    use crate::database::UserRecord;
+    use crate::test_support::{project_fixture, test_db, ticket_fixture};
+    use crate::tool_policy::ToolPolicy;
+
+    async fn setup() -> (Database, TicketRecord) {
+        let db = test_db().await.unwrap();
+        db.create_project(&project_fixture("p1")).await.unwrap();
+        let ticket = TicketRecord {
+            title: "Login".to_string(),
+            status: "done".to_string(),
+            ..ticket_fixture("t1", "p1")
+        };
+        db.create_ticket(&ticket).await.unwrap();
+        (db, ticket)
+    }
+
+    async fn run(db: &Database, ticket: &mut TicketRecord, mode: AnalysisMode, result: &str) {
+        let session_id = db.create_session(&ticket.id, "claude", None, &ToolPolicy::default(), Default::default()).await.unwrap();
+        let request = CodeAnalysisRequest {
+            ticket_id: ticket.id.clone(),
+            code_context: "src/auth".to_string(),
+            question: "How does login work?".to_string(),
+            project_id: ticket.project_id.clone(),
+            run_id: None,
+            agent_type: Some("claude".to_string()),
+            mode,
+            path_rules: Default::default(),
+            tool_policy: Default::default(),
+            priority: Default::default(),
+            include_linked_results: false,
+            budget_override: false,
+            replay: None,
+            working_dir: None,
+            prompt_variant: None,
+            response_language: None,
+            api_key: None,
+            then: Vec::new(),
+        };
+        db.record_session_request(&session_id, &request, None).await.unwrap();
+        db.complete_session(&session_id, result).await.unwrap();
+        db.update_ticket_result(&ticket.id, result).await.unwrap();
+        ticket.analysis_result = Some(result.to_string());
+    }
+
+    #[tokio::test]
+    async fn test_rating_replaces_the_users_earlier_rating() {
+        let (db, mut ticket) = setup().await;
+        assert!(matches!(
+            rate(&db, &ticket, None, Rating::Up, None).await,
+            Err(FeedbackError::NoResult)
+        ));
+
+        run(&db, &mut ticket, AnalysisMode::Ask, "Login checks a JWT").await;
+        db.create_user(&UserRecord {
+            id: "u1".to_string(),
+            org_id: "default".to_string(),
+            email: "qa@example.com".to_string(),
+            name: "QA".to_string(),
+            password_hash: None,
+            role: "member".to_string(),
+            created_at: Utc::now().to_rfc3339(),
+        })
+        .await
+        .unwrap();
+        let user = || Some("u1".to_string());
+        let first = rate(&db, &ticket, user(), Rating::Down, Some("  ".to_string())).await.unwrap();
+        assert_eq!(first.comment, None);
+        let second = rate(&db, &ticket, user(), Rating::Up, Some("Clear".to_string())).await.unwrap();
+        assert_eq!((second.id.as_str(), second.rating), (first.id.as_str(), 1));
+
+        // Anonymous ratings can't be told apart, so each one counts
+        rate(&db, &ticket, None, Rating::Down, None).await.unwrap();
+        rate(&db, &ticket, None, Rating::Down, None).await.unwrap();
+        assert_eq!(db.list_result_feedback("t1").await.unwrap().len(), 3);
+
+        let too_long = "x".repeat(MAX_COMMENT_CHARS + 1);
+        assert!(matches!(
+            rate(&db, &ticket, None, Rating::Down, Some(too_long)).await,
+            Err(FeedbackError::CommentTooLong)
+        ));
+
+        let quality = db.get_feedback_quality(&AnalyticsFilter::default()).await.unwrap();
+        assert_eq!(quality.len(), 1);
+        assert_eq!((quality[0].agent_type.as_str(), quality[0].mode.as_str()), ("claude", "ask"));
+        assert_eq!((quality[0].thumbs_up, quality[0].thumbs_down), (1, 2));
+        assert!((quality[0].approval_rate - 1.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_export_holds_low_rated_results() {
+        let (db, mut ticket) = setup().await;
+        run(&db, &mut ticket, AnalysisMode::Diagram, "graph TD; A-->B").await;
+        rate(&db, &ticket, None, Rating::Down, Some("Misses the refresh flow".to_string()))
+            .await
+            .unwrap();
+        rate(&db, &ticket, None, Rating::Up, None).await.unwrap();
+
+        let examples = low_rated_examples(&db, &AnalyticsFilter::default(), 10).await.unwrap();
+        assert_eq!(examples.len(), 1);
+        assert_eq!(examples[0].mode.as_deref(), Some("diagram"));
+        assert_eq!(examples[0].question.as_deref(), Some("How does login work?"));
+        assert_eq!(examples[0].result.as_deref(), Some("graph TD; A-->B"));
+        assert_eq!(examples[0].comment.as_deref(), Some("Misses the refresh flow"));
+        assert_eq!(to_jsonl(&examples).lines().count(), 1);
+
+        // A newer run replaces the result the rating was about
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        run(&db, &mut ticket, AnalysisMode::Ask, "Login checks a JWT").await;
+        let examples = low_rated_examples(&db, &AnalyticsFilter::default(), 10).await.unwrap();
+        assert_eq!(examples[0].result, None);
+    }
+}
@@ -1,14 +1,22 @@
-use crate::code_agent::{CodeAgent, CodeAnalysisRequest, CodeAnalysisResponse};
+use crate::agent_credentials::ApiKey;
+use crate::code_agent::{until_cancelled, AnalysisCancelled, CodeAgent, CodeAnalysisRequest, CodeAnalysisResponse};
+use crate::container::{self, ContainerConfig};
 use crate::database::Database;
+use crate::json_frames::JsonFrames;
 use crate::log_normalizer::LogNormalizer;
 use crate::message_store::MsgStore;
+use crate::prompt::{PromptDelivery, PromptLimits};
+use crate::resource_limits::ResourceLimits;
+use crate::retry::{self, Retryable, RetryPolicy};
+use crate::stall::StallPolicy;
+use crate::transcript::Transcript;
 use anyhow::Result;
 use async_trait::async_trait;
 use serde_json::Value;
 use std::sync::Arc;
 use tokio::io::{AsyncBufReadExt, BufReader};
-use tokio::process::Command;
 use tokio::time::{timeout, Duration};
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info, warn};
 
 #[derive(Debug, thiserror::Error)]
@@ -27,14 +35,33 @@ pub enum GeminiAgentError {
     AuthenticationRequired(String),
 }
 
+impl Retryable for GeminiAgentError {
+    /// A crash or hang may be a one-off; a missing binary, directory or login will not fix itself
+    fn is_retryable(&self) -> bool {
+        match self {
+            Self::Timeout(_) | Self::ProcessFailed(_) | Self::SpawnFailed(_) => true,
+            Self::ExecutableNotFound(_)
+            | Self::DirectoryNotAccessible(_)
+            | Self::AuthenticationRequired(_) => false,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct GeminiAgentConfig {
     pub executable_path: String,
     pub timeout_seconds: u64,
-    pub max_retries: u32,
+    pub retry: RetryPolicy,
+    pub prompt_limits: PromptLimits,
     pub working_dir: Option<String>,
     pub output_format: OutputFormat,
     pub api_key: Option<String>,
+    /// Run the CLI in a Docker container instead of on the host
+    pub container: Option<ContainerConfig>,
+    /// Memory, CPU, file and wall-clock limits on the CLI process
+    pub limits: ResourceLimits,
+    /// Killing the CLI once it has gone silent for too long
+    pub stall: StallPolicy,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -50,10 +77,14 @@ impl Default for GeminiAgentConfig {
         Self {
             executable_path: "gemini".to_string(),
             timeout_seconds: 300, // 5 minutes
-            max_retries: 2,
+            retry: RetryPolicy::default(),
+            prompt_limits: PromptLimits::default(),
             working_dir: None,
             output_format: OutputFormat::StreamJson,
             api_key: std::env::var("GEMINI_API_KEY").ok(),
+            container: None,
+            limits: ResourceLimits::default(),
+            stall: StallPolicy::default(),
         }
     }
 }
@@ -78,13 +109,14 @@ impl GeminiAgentConfig {
                 .ok()
                 .and_then(|s| s.parse().ok())
                 .unwrap_or(300),
-            max_retries: std::env::var("GEMINI_AGENT_MAX_RETRIES")
-                .ok()
-                .and_then(|s| s.parse().ok())
-                .unwrap_or(2),
+            retry: RetryPolicy::from_env("GEMINI_AGENT"),
+            prompt_limits: PromptLimits::from_env(),
             working_dir: std::env::var("GEMINI_AGENT_WORKING_DIR").ok(),
             output_format,
             api_key: std::env::var("GEMINI_API_KEY").ok(),
+            container: ContainerConfig::from_env(),
+            limits: ResourceLimits::from_env(),
+            stall: StallPolicy::from_env(),
         }
     }
 }
@@ -105,6 +137,7 @@ impl GeminiAgent {
         working_directory: Option<String>,
         msg_store: &Arc<MsgStore>,
         normalizer: &LogNormalizer,
+        transcript: &Transcript,
     ) -> Result<String> {
         info!("🎯 Executing Gemini analysis for: {}", request.code_context);
         
@@ -119,8 +152,10 @@ impl GeminiAgent {
             }
         }
 
-        // Validate executable exists
-        if self.config.executable_path.contains('/') || self.config.executable_path.contains('\\') {
+        // Validate executable exists (in a container, a path inside the image)
+        if self.config.container.is_some() {
+            debug!("Skipping executable check: runs in container");
+        } else if self.config.executable_path.contains('/') || self.config.executable_path.contains('\\') {
             if let Err(_e) = tokio::fs::metadata(&self.config.executable_path).await {
                 error!(
                     "⚠️ Gemini executable không tồn tại: {}",
@@ -157,34 +192,10 @@ impl GeminiAgent {
         }
 
         // Execute with retry logic
-        let mut last_error = None;
-        for attempt in 1..=self.config.max_retries {
-            info!(
-                "🔄 Attempt {}/{} for Gemini analysis",
-                attempt, self.config.max_retries
-            );
-
-            match self
-                .spawn_gemini_process(request, analysis_dir.clone(), msg_store, normalizer)
-                .await
-            {
-                Ok(result) => {
-                    info!("✅ Gemini analysis completed successfully on attempt {}", attempt);
-                    return Ok(result);
-                }
-                Err(e) => {
-                    warn!("❌ Attempt {} failed: {}", attempt, e);
-                    last_error = Some(e);
-
-                    if attempt < self.config.max_retries {
-                        info!("⏳ Waiting before retry...");
-                        tokio::time::sleep(Duration::from_secs(2)).await;
-                    }
-                }
-            }
-        }
-
-        Err(last_error.unwrap_or_else(|| anyhow::anyhow!("All retry attempts failed")))
+        retry::run(&self.config.retry, "analysis", retry::classify::<GeminiAgentError>, |_| {
+            self.spawn_gemini_process(request, analysis_dir.clone(), msg_store, normalizer, transcript)
+        })
+        .await
     }
 
     async fn spawn_gemini_process(
@@ -193,22 +204,40 @@ impl GeminiAgent {
         working_directory: Option<String>,
         msg_store: &Arc<MsgStore>,
         _normalizer: &LogNormalizer,
+        transcript: &Transcript,
     ) -> Result<String> {
-        let prompt = self.create_analysis_prompt(request);
+        let mut prompt = self.create_analysis_prompt(request);
+        if let Some(notice) = request.path_rules.prompt_notice() {
+            prompt.push_str(&notice);
+        }
         let ticket_id = request.ticket_id.clone();
 
         info!("🚀 Spawning Gemini CLI process: {}", self.config.executable_path);
         debug!("Prompt: {}", prompt);
+        let delivery = PromptDelivery::for_prompt(&prompt, &self.config.prompt_limits)?;
 
         // Build Gemini CLI command
         // Format: gemini -p "prompt" (non-interactive mode)
         // Note: Gemini CLI does not support --output-format flag
         // Output will be parsed automatically based on actual format returned
         // Reference: https://github.com/google-gemini/gemini-cli
-        let mut cmd = Command::new(&self.config.executable_path);
+        // The project is mounted read-write only when the policy allows changing it
+        let (mut cmd, _container) = container::command(
+            self.config.container.as_ref(),
+            &self.config.executable_path,
+            working_directory.as_deref(),
+            !request.tool_policy.read_only(),
+            &["GEMINI_API_KEY"],
+        );
+
+        // Add -p flag with prompt for non-interactive mode; without -p the CLI reads a
+        // long prompt from stdin and still runs non-interactively
+        if delivery == PromptDelivery::Argv {
+            cmd.arg("-p").arg(&prompt);
+        }
 
-        // Add -p flag with prompt for non-interactive mode
-        cmd.arg("-p").arg(&prompt);
+        // Only the policy's tools run without confirmation, which a non-interactive run can't give
+        cmd.arg("--allowed-tools").arg(request.tool_policy.gemini_allowed_tools());
 
         // Set working directory với absolute path đã được normalize
         if let Some(ref dir) = working_directory {
@@ -219,22 +248,31 @@ impl GeminiAgent {
         }
 
         // Set API key if available
-        if let Some(ref api_key) = self.config.api_key {
+        // The project's own key wins over the server's
+        let api_key = request.api_key.as_ref().map(ApiKey::expose).or(self.config.api_key.as_deref());
+        if let Some(api_key) = api_key {
             cmd.env("GEMINI_API_KEY", api_key);
         }
 
         cmd.stdin(std::process::Stdio::piped());
+
+        // Dropping the run (cancel, timeout) must not leave the CLI running
+
+        cmd.kill_on_drop(true);
         cmd.stdout(std::process::Stdio::piped());
         cmd.stderr(std::process::Stdio::piped());
 
         // Spawn the process
+        let mut limits = self.config.limits.apply(&mut cmd);
+        transcript.spawned(&cmd, &prompt);
         let mut child = cmd
             .spawn()
             .map_err(|e| GeminiAgentError::SpawnFailed(e.to_string()))?;
+        limits.watch(child.id());
+        let mut stall = self.config.stall.watch(child.id(), &ticket_id, msg_store.clone());
 
-        // Close stdin immediately
-        let _stdin = child.stdin.take();
-        drop(_stdin);
+        // Close stdin immediately (after writing a long prompt)
+        delivery.feed_stdin(&mut child, &prompt);
         info!("🔒 Closed stdin to signal EOF to Gemini CLI");
 
         let stdout = child.stdout.take().ok_or_else(|| {
@@ -247,11 +285,17 @@ impl GeminiAgent {
         // Clone for async tasks
         let msg_store_clone = msg_store.clone();
         let ticket_id_clone = ticket_id.clone();
+        let stdout_transcript = transcript.clone();
+        let stdout_heartbeat = stall.heartbeat();
 
         // Spawn task to capture stdout and process JSON lines
         let stdout_handle = tokio::spawn(async move {
-            let reader = BufReader::new(stdout);
-            let mut lines = reader.lines();
+            // Events split over several lines arrive reassembled
+            let mut frames = JsonFrames::new(stdout).on_line(move |line| {
+                info!("📤 GEMINI STDOUT: {}", line);
+                stdout_transcript.stdout(line);
+                stdout_heartbeat.beat();
+            });
             let mut output_lines = Vec::new();
             let normalizer = LogNormalizer::new();
 
@@ -259,8 +303,7 @@ impl GeminiAgent {
             let mut current_content = String::new();
             let mut last_timestamp: Option<String> = None;
 
-            while let Ok(Some(line)) = lines.next_line().await {
-                info!("📤 GEMINI STDOUT: {}", line);
+            while let Some(line) = frames.next_frame().await {
                 output_lines.push(line.clone());
 
                 // Try to parse as JSON
@@ -364,6 +407,8 @@ impl GeminiAgent {
         // Spawn task to capture stderr
         let stderr_ticket_id = request.ticket_id.clone();
         let stderr_msg_store = msg_store.clone();
+        let stderr_transcript = transcript.clone();
+        let stderr_heartbeat = stall.heartbeat();
 
         let stderr_handle = tokio::spawn(async move {
             let reader = BufReader::new(stderr);
@@ -373,6 +418,8 @@ impl GeminiAgent {
 
             while let Ok(Some(line)) = lines.next_line().await {
                 info!("⚠️ GEMINI STDERR: {}", line);
+                stderr_transcript.stderr(&line);
+                stderr_heartbeat.beat();
 
                 // Check for authentication errors
                 if line.contains("not logged in")
@@ -409,6 +456,18 @@ impl GeminiAgent {
 
                 // Wait for log capture to complete
                 let (stdout_result, stderr_result) = tokio::join!(stdout_handle, stderr_handle);
+                transcript.exited(status.code());
+
+                if let Some(stalled) = stall.stalled() {
+                    error!("⛔ {}", stalled);
+                    msg_store.push(stalled.log_entry(&ticket_id)).await;
+                    return Err(stalled.into());
+                }
+                if let Some(exceeded) = limits.exceeded(&status) {
+                    error!("⛔ {}", exceeded);
+                    msg_store.push(exceeded.log_entry(&ticket_id)).await;
+                    return Err(exceeded.into());
+                }
 
                 let output_lines = stdout_result
                     .map_err(|e| GeminiAgentError::SpawnFailed(format!("Stdout task failed: {}", e)))?;
@@ -460,12 +519,12 @@ impl GeminiAgent {
         if request.code_context.is_empty() {
             format!(
                 "Phân tích code để giúp QA hiểu business flow. Câu hỏi: {}",
-                request.question
+                request.prompt_question()
             )
         } else {
             format!(
                 "Analyze the code in {} to help QA understand the business flow. Question: {}",
-                request.code_context, request.question
+                request.code_context, request.prompt_question()
             )
         }
     }
@@ -478,6 +537,7 @@ impl CodeAgent for GeminiAgent {
         request: CodeAnalysisRequest,
         msg_store: Arc<MsgStore>,
         database: Arc<Database>,
+        cancel: CancellationToken,
     ) -> Result<CodeAnalysisResponse> {
         info!("🚀 Bắt đầu phân tích code với Gemini cho ticket: {}", request.ticket_id);
 
@@ -500,6 +560,13 @@ impl CodeAgent for GeminiAgent {
                 is_analyzing: true,
                 created_at: chrono::Utc::now().to_rfc3339(),
                 updated_at: chrono::Utc::now().to_rfc3339(),
+                stale: false,
+                analysis_result_blob: None,
+                analysis_result_size: None,
+                position: 0.0,
+                assignee_id: None,
+                summary: None,
+                mode: request.mode,
             };
 
             database.create_ticket(&auto_ticket).await?;
@@ -507,7 +574,9 @@ impl CodeAgent for GeminiAgent {
         }
 
         // Create analysis session
-        let session_id = database.create_session(&request.ticket_id).await?;
+        let session_id = database
+            .create_session(&request.ticket_id, "gemini", request.run_id.as_deref(), &request.tool_policy, request.mode)
+            .await?;
 
         // Update ticket status to analyzing
         database
@@ -524,23 +593,17 @@ impl CodeAgent for GeminiAgent {
         logs.push(start_log.to_string());
 
         // Get project directory for analysis scope
-        let working_directory = if !request.project_id.is_empty() {
-            if let Ok(Some(project)) = database.get_project(&request.project_id).await {
-                info!("📂 Working directory: {}", project.directory_path);
-                Some(project.directory_path)
-            } else {
-                error!("⚠️ Không tìm thấy project {}", request.project_id);
-                None
-            }
-        } else {
-            None
-        };
+        let working_directory = crate::code_agent::working_directory(&request, &database).await;
 
         // Execute Gemini CLI analysis
-        let result = match self
-            .execute_gemini_agent(&request, working_directory, &msg_store, &normalizer)
-            .await
-        {
+        let transcript = Transcript::new();
+        let outcome = until_cancelled(
+            &cancel,
+            self.execute_gemini_agent(&request, working_directory, &msg_store, &normalizer, &transcript),
+        )
+        .await;
+        transcript.save(&database, &session_id, msg_store.redactor()).await;
+        let result = match outcome {
             Ok(output) => {
                 info!("✅ Gemini CLI hoàn thành phân tích");
 
@@ -552,7 +615,8 @@ impl CodeAgent for GeminiAgent {
                 msg_store.push(entry).await;
                 logs.push(completion_log.to_string());
 
-                // Update database with success
+                // Update database with success, secrets masked like the logs
+                let output = msg_store.redactor().redact_text(output);
                 database.complete_session(&session_id, "Success").await?;
                 database
                     .update_ticket_result(&request.ticket_id, &output)
@@ -560,6 +624,10 @@ impl CodeAgent for GeminiAgent {
 
                 output
             }
+            Err(e) if e.is::<AnalysisCancelled>() => {
+                info!("⛔ Đã dừng Gemini CLI cho ticket {}", request.ticket_id);
+                return Err(e);
+            }
             Err(e) => {
                 error!("❌ Lỗi khi thực thi Gemini CLI: {}", e);
 
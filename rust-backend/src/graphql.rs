@@ -0,0 +1,551 @@
+use crate::auth::AuthContext;
+use crate::custom_fields::{self, FieldValues};
+use crate::database::{
+    AnalysisSession, Database, ProjectRecord, SessionStageRecord, StructuredLogRecord, TicketRecord,
+};
+use crate::message_store::StructuredLogEntry;
+use crate::AppState;
+use async_graphql::{
+    ComplexObject, Context, EmptyMutation, Json, Object, Result, Schema, SimpleObject, Subscription, ID,
+};
+use async_graphql::dataloader::{DataLoader, Loader};
+use async_graphql::http::ALL_WEBSOCKET_PROTOCOLS;
+use async_graphql_axum::{GraphQLProtocol, GraphQLRequest, GraphQLResponse, GraphQLWebSocket};
+use axum::extract::ws::WebSocketUpgrade;
+use axum::response::{IntoResponse, Response};
+use axum::Extension;
+use futures_util::Stream;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+
+/// GraphQL schema served at `/graphql` (queries) and `/graphql/ws` (subscriptions)
+pub type AppSchema = Schema<QueryRoot, EmptyMutation, SubscriptionRoot>;
+
+pub fn build_schema(state: AppState) -> AppSchema {
+    let loader = DataLoader::new(BatchLoader { database: state.database.clone() }, tokio::spawn);
+    Schema::build(QueryRoot, EmptyMutation, SubscriptionRoot)
+        .data(state)
+        .data(loader)
+        .finish()
+}
+
+/// Loads what nested queries ask of every project or ticket in a list (`tickets`,
+/// `latestSession`, `logCount`) with one query per field instead of one per parent
+pub struct BatchLoader {
+    database: Arc<Database>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ProjectTickets(String);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct LatestSession(String);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct LogCount(String);
+
+fn ids<K>(keys: &[K], id: impl Fn(&K) -> &String) -> Vec<String> {
+    keys.iter().map(|key| id(key).clone()).collect()
+}
+
+impl Loader<ProjectTickets> for BatchLoader {
+    type Value = Vec<TicketRecord>;
+    type Error = Arc<anyhow::Error>;
+
+    async fn load(&self, keys: &[ProjectTickets]) -> std::result::Result<HashMap<ProjectTickets, Self::Value>, Self::Error> {
+        let tickets = self.database.list_tickets_by_projects(&ids(keys, |key| &key.0)).await?;
+        let mut by_project: HashMap<ProjectTickets, Vec<TicketRecord>> = HashMap::new();
+        for ticket in tickets {
+            by_project.entry(ProjectTickets(ticket.project_id.clone())).or_default().push(ticket);
+        }
+        Ok(by_project)
+    }
+}
+
+impl Loader<LatestSession> for BatchLoader {
+    type Value = AnalysisSession;
+    type Error = Arc<anyhow::Error>;
+
+    async fn load(&self, keys: &[LatestSession]) -> std::result::Result<HashMap<LatestSession, Self::Value>, Self::Error> {
+        let sessions = self.database.get_latest_sessions_by_tickets(&ids(keys, |key| &key.0)).await?;
+        Ok(sessions.into_iter().map(|session| (LatestSession(session.ticket_id.clone()), session)).collect())
+    }
+}
+
+impl Loader<LogCount> for BatchLoader {
+    type Value = u64;
+    type Error = Arc<anyhow::Error>;
+
+    async fn load(&self, keys: &[LogCount]) -> std::result::Result<HashMap<LogCount, Self::Value>, Self::Error> {
+        let counts = self.database.count_logs_by_tickets(&ids(keys, |key| &key.0)).await?;
+        Ok(counts.into_iter().map(|(ticket_id, count)| (LogCount(ticket_id), count as u64)).collect())
+    }
+}
+
+// GET/POST /graphql
+pub async fn graphql_handler(
+    Extension(schema): Extension<AppSchema>,
+    auth: AuthContext,
+    request: GraphQLRequest,
+) -> GraphQLResponse {
+    schema.execute(request.into_inner().data(auth)).await.into()
+}
+
+// GET /graphql/ws
+pub async fn graphql_ws_handler(
+    Extension(schema): Extension<AppSchema>,
+    auth: AuthContext,
+    protocol: GraphQLProtocol,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    upgrade
+        .protocols(ALL_WEBSOCKET_PROTOCOLS)
+        .on_upgrade(move |stream| {
+            let mut data = async_graphql::Data::default();
+            data.insert(auth);
+            GraphQLWebSocket::new(stream, schema, protocol)
+                .with_data(data)
+                .serve()
+        })
+        .into_response()
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    async fn projects(&self, ctx: &Context<'_>) -> Result<Vec<Project>> {
+        let state = ctx.data::<AppState>()?;
+        let auth = ctx.data::<AuthContext>()?;
+        let projects = state.database.list_projects_by_org(&auth.org_id).await?;
+        Ok(projects.into_iter().map(Project).collect())
+    }
+
+    async fn project(&self, ctx: &Context<'_>, id: ID) -> Result<Option<Project>> {
+        let state = ctx.data::<AppState>()?;
+        let auth = ctx.data::<AuthContext>()?;
+        Ok(auth.project(&state.database, &id).await?.map(Project))
+    }
+
+    async fn ticket(&self, ctx: &Context<'_>, id: ID) -> Result<Option<Ticket>> {
+        let state = ctx.data::<AppState>()?;
+        let auth = ctx.data::<AuthContext>()?;
+        Ok(auth.ticket(&state.database, &id).await?.map(Ticket))
+    }
+
+    /// Logs for a ticket, paginated the same way as `GET /api/tickets/:id/logs`
+    async fn logs(
+        &self,
+        ctx: &Context<'_>,
+        ticket_id: ID,
+        limit: Option<u64>,
+        offset: Option<u64>,
+    ) -> Result<Vec<LogEntry>> {
+        let state = ctx.data::<AppState>()?;
+        let auth = ctx.data::<AuthContext>()?;
+        if auth.ticket(&state.database, &ticket_id).await?.is_none() {
+            return Ok(Vec::new());
+        }
+        let logs = state.database.get_logs_for_ticket(&ticket_id, limit, offset).await?;
+        Ok(logs.into_iter().map(LogEntry::from).collect())
+    }
+}
+
+pub struct Project(ProjectRecord);
+
+#[Object]
+impl Project {
+    async fn id(&self) -> ID {
+        ID(self.0.id.clone())
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    async fn description(&self) -> Option<&str> {
+        self.0.description.as_deref()
+    }
+
+    async fn directory_path(&self) -> &str {
+        &self.0.directory_path
+    }
+
+    async fn created_at(&self) -> &str {
+        &self.0.created_at
+    }
+
+    async fn updated_at(&self) -> &str {
+        &self.0.updated_at
+    }
+
+    async fn tickets(&self, ctx: &Context<'_>) -> Result<Vec<Ticket>> {
+        let loader = ctx.data::<DataLoader<BatchLoader>>()?;
+        let tickets = loader.load_one(ProjectTickets(self.0.id.clone())).await?;
+        Ok(tickets.unwrap_or_default().into_iter().map(Ticket).collect())
+    }
+}
+
+pub struct Ticket(TicketRecord);
+
+#[Object]
+impl Ticket {
+    async fn id(&self) -> ID {
+        ID(self.0.id.clone())
+    }
+
+    async fn project_id(&self) -> ID {
+        ID(self.0.project_id.clone())
+    }
+
+    async fn title(&self) -> &str {
+        &self.0.title
+    }
+
+    async fn description(&self) -> &str {
+        &self.0.description
+    }
+
+    async fn status(&self) -> &str {
+        &self.0.status
+    }
+
+    async fn code_context(&self) -> Option<&str> {
+        self.0.code_context.as_deref()
+    }
+
+    /// The whole result, also when it was offloaded to the blob store
+    async fn analysis_result(&self, ctx: &Context<'_>) -> Result<Option<String>> {
+        let state = ctx.data::<AppState>()?;
+        Ok(state.database.full_result(&self.0).await?)
+    }
+
+    async fn is_analyzing(&self) -> bool {
+        self.0.is_analyzing
+    }
+
+    /// Files read by the last analysis changed since it ran
+    async fn stale(&self) -> bool {
+        self.0.stale
+    }
+
+    /// Order within its status column, lowest first
+    async fn position(&self) -> f64 {
+        self.0.position
+    }
+
+    /// User the ticket is assigned to
+    async fn assignee_id(&self) -> Option<&str> {
+        self.0.assignee_id.as_deref()
+    }
+
+    /// Short TL;DR of the analysis result, when the project summarizes results
+    async fn summary(&self) -> Option<&str> {
+        self.0.summary.as_deref()
+    }
+
+    /// Custom field values by field name
+    async fn custom_fields(&self, ctx: &Context<'_>) -> Result<Json<FieldValues>> {
+        let state = ctx.data::<AppState>()?;
+        Ok(Json(custom_fields::ticket_values(&state.database, &self.0.id).await?))
+    }
+
+    async fn created_at(&self) -> &str {
+        &self.0.created_at
+    }
+
+    async fn updated_at(&self) -> &str {
+        &self.0.updated_at
+    }
+
+    async fn latest_session(&self, ctx: &Context<'_>) -> Result<Option<Session>> {
+        let loader = ctx.data::<DataLoader<BatchLoader>>()?;
+        let session = loader.load_one(LatestSession(self.0.id.clone())).await?;
+        Ok(session.map(Session::from))
+    }
+
+    async fn log_count(&self, ctx: &Context<'_>) -> Result<u64> {
+        let loader = ctx.data::<DataLoader<BatchLoader>>()?;
+        Ok(loader.load_one(LogCount(self.0.id.clone())).await?.unwrap_or(0))
+    }
+
+    async fn logs(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<u64>,
+        offset: Option<u64>,
+    ) -> Result<Vec<LogEntry>> {
+        let state = ctx.data::<AppState>()?;
+        let logs = state.database.get_logs_for_ticket(&self.0.id, limit, offset).await?;
+        Ok(logs.into_iter().map(LogEntry::from).collect())
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(complex)]
+pub struct Session {
+    id: ID,
+    ticket_id: ID,
+    started_at: String,
+    completed_at: Option<String>,
+    status: String,
+    error_message: Option<String>,
+    agent_type: Option<String>,
+    /// Correlation ID stamped on every log entry of this run
+    run_id: Option<String>,
+    /// JSON of the tools the run could use without asking, and whether they came from the project
+    tool_policy: Option<String>,
+    /// `--version` output of the agent CLI
+    cli_version: Option<String>,
+    /// Model reported by the agent's init event, or configured for API agents
+    model: Option<String>,
+    /// Commit of the analyzed repository when the run started
+    git_commit: Option<String>,
+    /// SHA-256 of the question and code context sent to the agent
+    prompt_hash: Option<String>,
+    /// JSON snapshot of the agent configuration, without credentials
+    config_snapshot: Option<String>,
+    usage: Usage,
+}
+
+/// What the agent reported the run cost; both null when it reports neither
+#[derive(SimpleObject)]
+pub struct Usage {
+    cost_usd: Option<f64>,
+    /// Input plus output tokens
+    tokens: Option<i64>,
+}
+
+#[ComplexObject]
+impl Session {
+    /// Progress stage transitions in order (init, exploring_files, reasoning, writing_answer)
+    async fn stages(&self, ctx: &Context<'_>) -> Result<Vec<SessionStage>> {
+        let state = ctx.data::<AppState>()?;
+        let stages = state.database.get_session_stages(&self.id).await?;
+        Ok(stages.into_iter().map(SessionStage::from).collect())
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct SessionStage {
+    stage: String,
+    entered_at: String,
+}
+
+impl From<SessionStageRecord> for SessionStage {
+    fn from(record: SessionStageRecord) -> Self {
+        Self {
+            stage: record.stage,
+            entered_at: record.entered_at,
+        }
+    }
+}
+
+impl From<AnalysisSession> for Session {
+    fn from(session: AnalysisSession) -> Self {
+        Self {
+            id: ID(session.id),
+            ticket_id: ID(session.ticket_id),
+            started_at: session.started_at,
+            completed_at: session.completed_at,
+            status: session.status,
+            error_message: session.error_message,
+            agent_type: session.agent_type,
+            run_id: session.run_id,
+            tool_policy: session.tool_policy,
+            cli_version: session.cli_version,
+            model: session.model,
+            git_commit: session.git_commit,
+            prompt_hash: session.prompt_hash,
+            config_snapshot: session.config_snapshot,
+            usage: Usage {
+                cost_usd: session.cost_usd,
+                tokens: session.tokens,
+            },
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct LogEntry {
+    id: ID,
+    ticket_id: ID,
+    message_type: String,
+    content: String,
+    raw_log: Option<String>,
+    /// Metadata as a JSON object string
+    metadata: Option<String>,
+    timestamp: String,
+}
+
+impl From<StructuredLogRecord> for LogEntry {
+    fn from(record: StructuredLogRecord) -> Self {
+        Self {
+            id: ID(record.id),
+            ticket_id: ID(record.ticket_id),
+            message_type: record.message_type,
+            content: record.content,
+            raw_log: record.raw_log,
+            metadata: record.metadata,
+            timestamp: record.timestamp,
+        }
+    }
+}
+
+impl From<StructuredLogEntry> for LogEntry {
+    fn from(entry: StructuredLogEntry) -> Self {
+        entry.to_record().into()
+    }
+}
+
+pub struct SubscriptionRoot;
+
+#[Subscription]
+impl SubscriptionRoot {
+    /// Live structured logs from the MsgStore broadcast channel, optionally filtered by ticket.
+    /// Only tickets of the subscriber's organization are streamed.
+    async fn logs(&self, ctx: &Context<'_>, ticket_id: Option<ID>) -> Result<impl Stream<Item = LogEntry>> {
+        let state = ctx.data::<AppState>()?;
+        let org_id = ctx.data::<AuthContext>()?.org_id.clone();
+        let database = state.database.clone();
+        let receiver = state.msg_store.subscribe();
+        let ticket_id = ticket_id.map(|id| id.0);
+
+        Ok(futures_util::stream::unfold((receiver, HashSet::new()), move |(mut receiver, mut visible)| {
+            let ticket_id = ticket_id.clone();
+            let database = database.clone();
+            let org_id = org_id.clone();
+            async move {
+                loop {
+                    match receiver.recv().await {
+                        Ok(entry) => {
+                            if ticket_id.as_deref().is_some_and(|id| id != entry.ticket_id) {
+                                continue;
+                            }
+                            if !visible.contains(&entry.ticket_id) {
+                                match database.ticket_org_id(&entry.ticket_id).await {
+                                    Ok(Some(ticket_org)) if ticket_org == org_id => {
+                                        visible.insert(entry.ticket_id.clone());
+                                    }
+                                    _ => continue,
+                                }
+                            }
+                            return Some((LogEntry::from(entry), (receiver, visible)));
+                        }
+                        // A slow subscriber only misses entries, it stays subscribed
+                        Err(RecvError::Lagged(_)) => continue,
+                        Err(RecvError::Closed) => return None,
+                    }
+                }
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::OrgRole;
+    use crate::database::{OrganizationRecord, DEFAULT_ORG_ID};
+    use crate::mock_agent::{MockAgent, MockAgentConfig};
+    use crate::run_environment::RunEnvironment;
+    use crate::test_support::{project_fixture, temp_dir, test_database, test_state, ticket_fixture};
+    use chrono::Utc;
+    use serde_json::{json, Value};
+
+    async fn query(schema: &AppSchema, auth: &AuthContext, query: &str) -> Value {
+        let response = schema.execute(async_graphql::Request::new(query).data(auth.clone())).await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        response.data.into_json().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_nested_query_and_org_scoping() {
+        let dir = temp_dir("graphql").unwrap();
+        let database = test_database(&dir).await.unwrap();
+        database
+            .create_organization(&OrganizationRecord {
+                id: "acme".to_string(),
+                name: "Acme".to_string(),
+                created_at: Utc::now().to_rfc3339(),
+            })
+            .await
+            .unwrap();
+        for (id, org_id) in [("p1", DEFAULT_ORG_ID), ("p2", "acme")] {
+            database
+                .create_project(&ProjectRecord {
+                    org_id: org_id.to_string(),
+                    ..project_fixture(id)
+                })
+                .await
+                .unwrap();
+        }
+        for (id, project_id) in [("t1", "p1"), ("t2", "p1"), ("t3", "p2")] {
+            database.create_ticket(&ticket_fixture(id, project_id)).await.unwrap();
+        }
+
+        let policy = Default::default();
+        database.create_session("t1", "claude", Some("run-1"), &policy, Default::default()).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        let latest = database.create_session("t1", "claude", Some("run-2"), &policy, Default::default()).await.unwrap();
+        let environment = RunEnvironment {
+            cost_usd: Some(0.25),
+            tokens: Some(1500),
+            ..Default::default()
+        };
+        database.record_session_environment(&latest, &environment).await.unwrap();
+        for (i, content) in ["Reading file: a.rs", "Done"].iter().enumerate() {
+            database
+                .save_log(&StructuredLogRecord {
+                    id: format!("log-{}", i),
+                    ticket_id: "t1".to_string(),
+                    message_type: "system".to_string(),
+                    content: content.to_string(),
+                    raw_log: None,
+                    metadata: None,
+                    timestamp: Utc::now().to_rfc3339(),
+                    raw_log_blob: None,
+                })
+                .await
+                .unwrap();
+        }
+
+        let agent = Arc::new(MockAgent::with_config(MockAgentConfig::from_env()));
+        let schema = build_schema(test_state(database.clone(), agent).unwrap());
+        let default_org = AuthContext::anonymous();
+        let data = query(
+            &schema,
+            &default_org,
+            "{ projects { id tickets { id logCount latestSession { id usage { costUsd tokens } } } } }",
+        )
+        .await;
+        let projects = data["projects"].as_array().unwrap();
+        assert_eq!(projects.len(), 1);
+        assert_eq!(projects[0]["id"], "p1");
+        let tickets = projects[0]["tickets"].as_array().unwrap();
+        let t1 = tickets.iter().find(|ticket| ticket["id"] == "t1").unwrap();
+        assert_eq!(t1["logCount"], 2);
+        assert_eq!(t1["latestSession"]["id"], latest.as_str());
+        assert_eq!(t1["latestSession"]["usage"], json!({ "costUsd": 0.25, "tokens": 1500 }));
+        let t2 = tickets.iter().find(|ticket| ticket["id"] == "t2").unwrap();
+        assert_eq!((&t2["logCount"], &t2["latestSession"]), (&json!(0), &Value::Null));
+
+        // Another organization sees only its own project, and none of the default one's tickets
+        let acme = AuthContext {
+            user_id: Some("u1".to_string()),
+            org_id: "acme".to_string(),
+            role: OrgRole::Member,
+        };
+        let data = query(
+            &schema,
+            &acme,
+            r#"{ projects { id tickets { id } } ticket(id: "t1") { id } logs(ticketId: "t1") { id } }"#,
+        )
+        .await;
+        assert_eq!(data["projects"], json!([{ "id": "p2", "tickets": [{ "id": "t3" }] }]));
+        assert_eq!(data["ticket"], Value::Null);
+        assert_eq!(data["logs"], json!([]));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,282 @@
+use crate::auth::{self, AuthContext};
+use crate::database::TicketRecord;
+use crate::message_store::StructuredLogEntry;
+use crate::{AppState, CodeAnalysisRequest};
+use chrono::Utc;
+use futures_util::Stream;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use tokio::sync::broadcast::error::RecvError;
+use tonic::{Request, Response, Status};
+use tracing::{error, info};
+
+pub mod proto {
+    tonic::include_proto!("explain_source.v1");
+}
+
+use proto::analysis_service_server::{AnalysisService, AnalysisServiceServer};
+
+/// gRPC facade over the same AppState the axum server uses
+pub struct GrpcAnalysisService {
+    state: AppState,
+}
+
+impl GrpcAnalysisService {
+    pub fn new(state: AppState) -> Self {
+        Self { state }
+    }
+
+    /// Same rules as the HTTP extractor, reading the `authorization: Bearer <token>` metadata
+    async fn authenticate<T>(&self, request: &Request<T>) -> Result<AuthContext, Status> {
+        let token = request
+            .metadata()
+            .get("authorization")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .map(|v| v.trim().to_string());
+
+        match token {
+            Some(token) => match self.state.database.get_user_by_token(&auth::hash_token(&token)).await {
+                Ok(Some(user)) => Ok(AuthContext::from_user(&user)),
+                Ok(None) => Err(Status::unauthenticated("Invalid or expired token")),
+                Err(e) => Err(internal(e)),
+            },
+            None if self.state.auth.required => Err(Status::unauthenticated("Missing bearer token")),
+            None => Ok(AuthContext::anonymous()),
+        }
+    }
+}
+
+/// Serve the gRPC API on its own port until the process exits
+pub async fn serve(state: AppState, addr: SocketAddr) {
+    info!("🛰️ gRPC server đang chạy trên {}", addr);
+
+    if let Err(e) = tonic::transport::Server::builder()
+        .add_service(AnalysisServiceServer::new(GrpcAnalysisService::new(state)))
+        .serve(addr)
+        .await
+    {
+        error!("❌ gRPC server stopped: {}", e);
+    }
+}
+
+fn internal(e: anyhow::Error) -> Status {
+    error!("gRPC request failed: {}", e);
+    Status::internal(e.to_string())
+}
+
+impl From<TicketRecord> for proto::Ticket {
+    fn from(ticket: TicketRecord) -> Self {
+        Self {
+            id: ticket.id,
+            project_id: ticket.project_id,
+            title: ticket.title,
+            description: ticket.description,
+            status: ticket.status,
+            code_context: ticket.code_context,
+            analysis_result: ticket.analysis_result,
+            is_analyzing: ticket.is_analyzing,
+            created_at: ticket.created_at,
+            updated_at: ticket.updated_at,
+            stale: ticket.stale,
+            position: ticket.position,
+            assignee_id: ticket.assignee_id,
+            summary: ticket.summary,
+        }
+    }
+}
+
+impl From<StructuredLogEntry> for proto::LogEntry {
+    fn from(entry: StructuredLogEntry) -> Self {
+        Self {
+            id: entry.id,
+            ticket_id: entry.ticket_id,
+            message_type: entry.message_type.as_str().to_string(),
+            content: entry.content,
+            raw_log: entry.raw_log,
+            metadata: entry.metadata,
+            timestamp: entry.timestamp.to_rfc3339(),
+        }
+    }
+}
+
+type LogStream = Pin<Box<dyn Stream<Item = Result<proto::LogEntry, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl AnalysisService for GrpcAnalysisService {
+    async fn create_ticket(
+        &self,
+        request: Request<proto::CreateTicketRequest>,
+    ) -> Result<Response<proto::Ticket>, Status> {
+        let auth = self.authenticate(&request).await?;
+        let data = request.into_inner();
+
+        match auth.project(&self.state.database, &data.project_id).await {
+            Ok(Some(_)) => {}
+            Ok(None) => return Err(Status::not_found(format!("Project {} not found", data.project_id))),
+            Err(e) => return Err(internal(e)),
+        }
+
+        let ticket = TicketRecord {
+            id: uuid::Uuid::new_v4().to_string(),
+            project_id: data.project_id,
+            title: data.title,
+            description: data.description,
+            status: data.status.unwrap_or_else(|| "todo".to_string()),
+            code_context: data.code_context,
+            analysis_result: None,
+            is_analyzing: false,
+            created_at: Utc::now().to_rfc3339(),
+            updated_at: Utc::now().to_rfc3339(),
+            stale: false,
+            analysis_result_blob: None,
+            analysis_result_size: None,
+            position: 0.0,
+            assignee_id: None,
+            summary: None,
+            mode: Default::default(),
+        };
+
+        let position = self.state.database.create_ticket(&ticket).await.map_err(internal)?;
+        info!("✅ gRPC tạo ticket thành công: {}", ticket.id);
+        crate::activity::watch_created(&self.state.database, &ticket.id, auth.user_id.as_deref()).await;
+
+        Ok(Response::new(TicketRecord { position, ..ticket }.into()))
+    }
+
+    async fn start_analysis(
+        &self,
+        request: Request<proto::StartAnalysisRequest>,
+    ) -> Result<Response<proto::StartAnalysisResponse>, Status> {
+        let auth = self.authenticate(&request).await?;
+        let data = request.into_inner();
+
+        let ticket = match auth.ticket(&self.state.database, &data.ticket_id).await {
+            Ok(Some(ticket)) => ticket,
+            Ok(None) => return Err(Status::not_found(format!("Ticket {} not found", data.ticket_id))),
+            Err(e) => return Err(internal(e)),
+        };
+
+        if ticket.is_analyzing {
+            return Ok(Response::new(proto::StartAnalysisResponse {
+                ticket_id: ticket.id,
+                started: false,
+                message: "Ticket is already being analyzed".to_string(),
+                run_id: None,
+            }));
+        }
+
+        let request = CodeAnalysisRequest {
+            ticket_id: ticket.id.clone(),
+            code_context: data
+                .code_context
+                .or(ticket.code_context)
+                .unwrap_or_default(),
+            question: data.question.unwrap_or(ticket.description),
+            project_id: ticket.project_id,
+            run_id: None,
+            agent_type: data.agent_type,
+            mode: ticket.mode,
+            path_rules: Default::default(),
+            tool_policy: Default::default(),
+            priority: Default::default(),
+            include_linked_results: false,
+            budget_override: false,
+            replay: None,
+            working_dir: None,
+            prompt_variant: None,
+            response_language: None,
+            api_key: None,
+            then: Vec::new(),
+        };
+
+        info!("🚀 gRPC bắt đầu phân tích code cho ticket {}", request.ticket_id);
+        let run_id = crate::analysis_runner::start_analysis(&self.state, request, auth.user_id.clone()).await;
+
+        Ok(Response::new(proto::StartAnalysisResponse {
+            ticket_id: ticket.id,
+            started: true,
+            message: "Analysis started".to_string(),
+            run_id: Some(run_id),
+        }))
+    }
+
+    type StreamLogsStream = LogStream;
+
+    async fn stream_logs(
+        &self,
+        request: Request<proto::StreamLogsRequest>,
+    ) -> Result<Response<Self::StreamLogsStream>, Status> {
+        let auth = self.authenticate(&request).await?;
+        let data = request.into_inner();
+        let ticket_id = data.ticket_id;
+
+        match auth.ticket(&self.state.database, &ticket_id).await {
+            Ok(Some(_)) => {}
+            Ok(None) => return Err(Status::not_found(format!("Ticket {} not found", ticket_id))),
+            Err(e) => return Err(internal(e)),
+        }
+
+        // Subscribe before loading history so no entry falls between the two
+        let receiver = self.state.msg_store.subscribe();
+        let history = if data.include_history {
+            self.state.msg_store.get_logs(&ticket_id).await
+        } else {
+            Vec::new()
+        };
+
+        let history = futures_util::stream::iter(
+            history
+                .into_iter()
+                .map(|entry| Ok(proto::LogEntry::from(entry))),
+        );
+
+        let live = futures_util::stream::unfold(receiver, move |mut receiver| {
+            let ticket_id = ticket_id.clone();
+            async move {
+                loop {
+                    match receiver.recv().await {
+                        Ok(entry) if entry.ticket_id == ticket_id => {
+                            return Some((Ok(proto::LogEntry::from(entry)), receiver));
+                        }
+                        Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                        Err(RecvError::Closed) => return None,
+                    }
+                }
+            }
+        });
+
+        use futures_util::StreamExt;
+        Ok(Response::new(Box::pin(history.chain(live))))
+    }
+
+    async fn get_result(
+        &self,
+        request: Request<proto::GetResultRequest>,
+    ) -> Result<Response<proto::GetResultResponse>, Status> {
+        let auth = self.authenticate(&request).await?;
+        let ticket_id = request.into_inner().ticket_id;
+
+        let ticket = match auth.ticket(&self.state.database, &ticket_id).await {
+            Ok(Some(ticket)) => ticket,
+            Ok(None) => return Err(Status::not_found(format!("Ticket {} not found", ticket_id))),
+            Err(e) => return Err(internal(e)),
+        };
+
+        let session = self
+            .state
+            .database
+            .get_latest_session_by_ticket(&ticket_id)
+            .await
+            .map_err(internal)?;
+
+        Ok(Response::new(proto::GetResultResponse {
+            ticket_id: ticket.id,
+            is_analyzing: ticket.is_analyzing,
+            analysis_result: ticket.analysis_result,
+            session_status: session.as_ref().map(|s| s.status.clone()),
+            run_id: session.as_ref().and_then(|s| s.run_id.clone()),
+            error_message: session.and_then(|s| s.error_message),
+        }))
+    }
+}
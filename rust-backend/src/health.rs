@@ -0,0 +1,218 @@
+//! Liveness (`GET /healthz`) and readiness (`GET /readyz`) probes for orchestrators
+//!
+//! Liveness only says the process serves HTTP, so a restart is never triggered by a
+//! dependency outage. Readiness answers 503 until the database is reachable, every
+//! migration is applied, log writes keep up and the default agent can run.
+
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use serde::Serialize;
+use tokio::time::{timeout, Duration, Instant};
+
+use crate::agent_factory::{AgentInfo, AgentType};
+use crate::database::{Database, MigrationState, MigrationStatus};
+use crate::AppState;
+
+/// Log entries waiting for the database beyond which the server stops taking traffic
+const MAX_LOG_QUEUE_DEPTH: usize = 10_000;
+/// A database that doesn't answer within this is treated as down
+const DATABASE_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Serialize)]
+pub struct Liveness {
+    pub status: &'static str,
+    pub version: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DatabaseCheck {
+    pub ok: bool,
+    pub latency_ms: u64,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MigrationsCheck {
+    pub ok: bool,
+    /// Migrations not in the `applied` state, with what's wrong with each
+    pub pending: Vec<MigrationStatus>,
+    /// Set when the migration history couldn't be read
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LogQueueCheck {
+    pub ok: bool,
+    /// Log entries not yet written to the database
+    pub depth: usize,
+    pub max: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AgentsCheck {
+    /// Whether the default agent can run; other agents are informational
+    pub ok: bool,
+    pub default: &'static str,
+    pub available: Vec<&'static str>,
+    pub unavailable: Vec<&'static str>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    pub database: DatabaseCheck,
+    pub migrations: MigrationsCheck,
+    pub log_queue: LogQueueCheck,
+    pub agents: AgentsCheck,
+}
+
+impl Readiness {
+    fn new(database: DatabaseCheck, migrations: MigrationsCheck, log_queue: LogQueueCheck, agents: AgentsCheck) -> Self {
+        Self {
+            ready: database.ok && migrations.ok && log_queue.ok && agents.ok,
+            database,
+            migrations,
+            log_queue,
+            agents,
+        }
+    }
+}
+
+async fn check_database(database: &Database) -> DatabaseCheck {
+    let started = Instant::now();
+    let error = match timeout(DATABASE_TIMEOUT, database.ping()).await {
+        Ok(Ok(())) => None,
+        Ok(Err(e)) => Some(e.to_string()),
+        Err(_) => Some(format!("No answer within {}s", DATABASE_TIMEOUT.as_secs())),
+    };
+
+    DatabaseCheck {
+        ok: error.is_none(),
+        latency_ms: started.elapsed().as_millis() as u64,
+        error,
+    }
+}
+
+async fn check_migrations(database: &Database) -> MigrationsCheck {
+    match timeout(DATABASE_TIMEOUT, database.migration_status()).await {
+        Ok(Ok(statuses)) => {
+            let pending: Vec<MigrationStatus> = statuses
+                .into_iter()
+                .filter(|m| m.state != MigrationState::Applied)
+                .collect();
+            MigrationsCheck {
+                ok: pending.is_empty(),
+                pending,
+                error: None,
+            }
+        }
+        Ok(Err(e)) => MigrationsCheck {
+            ok: false,
+            pending: Vec::new(),
+            error: Some(e.to_string()),
+        },
+        Err(_) => MigrationsCheck {
+            ok: false,
+            pending: Vec::new(),
+            error: Some(format!("No answer within {}s", DATABASE_TIMEOUT.as_secs())),
+        },
+    }
+}
+
+/// Re-checked on every probe: executables can be installed or removed while the server runs
+fn check_agents(default_type: AgentType) -> AgentsCheck {
+    let (available, unavailable): (Vec<AgentInfo>, Vec<AgentInfo>) = AgentType::ALL
+        .iter()
+        .map(|agent_type| AgentInfo::from_env(*agent_type, *agent_type == default_type))
+        .partition(|info| info.available);
+
+    AgentsCheck {
+        ok: available.iter().any(|info| info.is_default),
+        default: default_type.id(),
+        available: available.iter().map(|info| info.id).collect(),
+        unavailable: unavailable.iter().map(|info| info.id).collect(),
+    }
+}
+
+// GET /healthz
+pub async fn liveness() -> Json<Liveness> {
+    Json(Liveness {
+        status: "ok",
+        version: env!("CARGO_PKG_VERSION"),
+    })
+}
+
+// GET /readyz
+pub async fn readiness(State(state): State<AppState>) -> Response {
+    let depth = state.msg_store.queue_depth();
+    let report = Readiness::new(
+        check_database(&state.database).await,
+        check_migrations(&state.database).await,
+        LogQueueCheck {
+            ok: depth <= MAX_LOG_QUEUE_DEPTH,
+            depth,
+            max: MAX_LOG_QUEUE_DEPTH,
+        },
+        check_agents(state.agents.default_type()),
+    );
+
+    if !report.ready {
+        tracing::warn!("Readiness check failed: {}", serde_json::to_string(&report).unwrap_or_default());
+    }
+    let status = if report.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(report)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_migration_check() {
+        let db = Database::new("sqlite::memory:").await.unwrap();
+        db.init_schema().await.unwrap();
+
+        // Nothing applied yet
+        let check = check_migrations(&db).await;
+        assert!(!check.ok);
+        assert_eq!(check.pending.len(), crate::database::MIGRATOR.iter().count());
+        assert!(check.pending.iter().all(|m| m.state == MigrationState::Pending));
+
+        db.run_migrations().await.unwrap();
+        let check = check_migrations(&db).await;
+        assert!(check.ok, "pending: {:?}", check.pending);
+        assert!(check_database(&db).await.ok);
+    }
+
+    #[test]
+    fn test_ready_only_when_every_check_passes() {
+        let report = |queue_depth: usize, agent_ok: bool| {
+            Readiness::new(
+                DatabaseCheck { ok: true, latency_ms: 1, error: None },
+                MigrationsCheck { ok: true, pending: Vec::new(), error: None },
+                LogQueueCheck {
+                    ok: queue_depth <= MAX_LOG_QUEUE_DEPTH,
+                    depth: queue_depth,
+                    max: MAX_LOG_QUEUE_DEPTH,
+                },
+                AgentsCheck {
+                    ok: agent_ok,
+                    default: "claude",
+                    available: Vec::new(),
+                    unavailable: Vec::new(),
+                },
+            )
+        };
+
+        assert!(report(0, true).ready);
+        assert!(!report(MAX_LOG_QUEUE_DEPTH + 1, true).ready);
+        assert!(!report(0, false).ready);
+    }
+}
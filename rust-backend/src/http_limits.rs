@@ -0,0 +1,166 @@
+//! Load shedding for the HTTP layer: past `HTTP_MAX_CONCURRENT_REQUESTS` requests are turned
+//! away with 503, slow ones end with 408, and oversized bodies with 413, so a slow database
+//! makes clients retry later instead of piling up requests until the server runs out of memory
+
+use crate::config::HttpLimitsConfig;
+use axum::{
+    body::Body,
+    extract::{DefaultBodyLimit, MatchedPath, Request},
+    http::{header, HeaderValue, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    Json, Router,
+};
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tracing::warn;
+
+struct Limits {
+    config: HttpLimitsConfig,
+    /// None without a concurrency limit
+    permits: Option<Arc<Semaphore>>,
+}
+
+/// `router` with the limits of `config` applied to every request, fallback included
+pub fn apply(router: Router, config: HttpLimitsConfig) -> Router {
+    let permits = (config.max_concurrent_requests > 0).then(|| Arc::new(Semaphore::new(config.max_concurrent_requests)));
+    let limits = Arc::new(Limits { config, permits });
+
+    router
+        .layer(middleware::from_fn(move |request: Request, next: Next| {
+            guard(limits.clone(), request, next)
+        }))
+        // Bodies are limited per route below, in place of the extractors' fixed 2 MiB
+        .layer(DefaultBodyLimit::disable())
+}
+
+fn rejection(status: StatusCode, error: &str, retry_after_secs: Option<u64>) -> Response {
+    let mut response = (status, Json(json!({ "error": error }))).into_response();
+    if let Some(secs) = retry_after_secs {
+        response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(secs));
+    }
+    response
+}
+
+async fn guard(limits: Arc<Limits>, request: Request, next: Next) -> Response {
+    let route = match request.extensions().get::<MatchedPath>() {
+        Some(path) => path.as_str().to_string(),
+        None => request.uri().path().to_string(),
+    };
+    let (timeout_secs, max_body_bytes) = limits.config.for_route(&route);
+    let retry_after = Some(limits.config.retry_after_secs);
+
+    let content_length = request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+    if content_length.is_some_and(|length| length > max_body_bytes) {
+        return rejection(StatusCode::PAYLOAD_TOO_LARGE, "Request body too large", None);
+    }
+    // Bodies without a length (chunked) are cut off while they are read; extractors then answer 413
+    let request = request.map(|body| Body::new(http_body_util::Limited::new(body, max_body_bytes)));
+
+    let _permit = match &limits.permits {
+        Some(permits) => match permits.clone().try_acquire_owned() {
+            Ok(permit) => Some(permit),
+            Err(_) => {
+                warn!("🚦 Quá tải ({} request đang xử lý), từ chối {}", limits.config.max_concurrent_requests, route);
+                return rejection(StatusCode::SERVICE_UNAVAILABLE, "Server is busy, retry later", retry_after);
+            }
+        },
+        None => None,
+    };
+
+    let Some(timeout_secs) = timeout_secs else {
+        return next.run(request).await;
+    };
+    match tokio::time::timeout(Duration::from_secs(timeout_secs), next.run(request)).await {
+        Ok(response) => response,
+        Err(_) => {
+            warn!("⏰ {} không phản hồi trong {}s", route, timeout_secs);
+            rejection(StatusCode::REQUEST_TIMEOUT, "Request timed out", retry_after)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RouteLimit;
+    use axum::routing::{get, post};
+    use tokio::sync::Notify;
+
+    async fn serve(router: Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        format!("http://{}", addr)
+    }
+
+    fn config() -> HttpLimitsConfig {
+        HttpLimitsConfig {
+            max_concurrent_requests: 1,
+            request_timeout_secs: 1,
+            max_body_bytes: 16,
+            retry_after_secs: 7,
+            routes: vec![RouteLimit {
+                pattern: "/upload/*".to_string(),
+                timeout_secs: None,
+                max_body_bytes: Some(1024),
+            }],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_overload_and_timeout() {
+        let release = Arc::new(Notify::new());
+        let blocked = release.clone();
+        let router = Router::new()
+            .route("/slow", get(move || async move { blocked.notified().await }))
+            .route("/hang", get(std::future::pending::<()>))
+            .route("/fast", get(|| async { "ok" }));
+        let url = serve(apply(router, config())).await;
+        let client = reqwest::Client::new();
+
+        let slow = tokio::spawn(client.get(format!("{}/slow", url)).send());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let shed = client.get(format!("{}/fast", url)).send().await.unwrap();
+        assert_eq!(shed.status(), 503);
+        assert_eq!(shed.headers()[header::RETRY_AFTER], "7");
+
+        release.notify_one();
+        assert_eq!(slow.await.unwrap().unwrap().status(), 200);
+        assert_eq!(client.get(format!("{}/fast", url)).send().await.unwrap().status(), 200);
+
+        let hung = client.get(format!("{}/hang", url)).send().await.unwrap();
+        assert_eq!(hung.status(), 408);
+        assert_eq!(hung.headers()[header::RETRY_AFTER], "7");
+    }
+
+    #[tokio::test]
+    async fn test_body_limits_per_route() {
+        let echo = || post(|body: String| async move { body.len().to_string() });
+        let router = Router::new().route("/small", echo()).route("/upload/big", echo());
+        let url = serve(apply(router, config())).await;
+        let client = reqwest::Client::new();
+        let body = "x".repeat(100);
+
+        let small = client.post(format!("{}/small", url)).body(body.clone()).send().await.unwrap();
+        assert_eq!(small.status(), 413);
+        let upload = client.post(format!("{}/upload/big", url)).body(body.clone()).send().await.unwrap();
+        assert_eq!(upload.text().await.unwrap(), "100");
+
+        // Without a Content-Length the limit applies while reading
+        let chunks = futures_util::stream::iter(vec![Ok::<_, std::io::Error>(body)]);
+        let chunked = client
+            .post(format!("{}/small", url))
+            .body(reqwest::Body::wrap_stream(chunks))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(chunked.status(), 413);
+    }
+}
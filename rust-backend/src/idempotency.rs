@@ -0,0 +1,265 @@
+use axum::{
+    http::{HeaderMap, HeaderName, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::future::Future;
+
+use crate::auth::AuthContext;
+use crate::AppState;
+
+pub const IDEMPOTENCY_KEY_HEADER: HeaderName = HeaderName::from_static("idempotency-key");
+/// Set on responses replayed from an earlier request with the same key
+pub const IDEMPOTENT_REPLAYED_HEADER: HeaderName = HeaderName::from_static("idempotent-replayed");
+
+const MAX_KEY_LEN: usize = 255;
+
+/// A claim still without a response after this long belongs to a request that died
+/// (e.g. a restart mid-request) and may be taken over by a retry
+const STALE_CLAIM_SECS: i64 = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdempotencyConfig {
+    /// How long a key keeps returning its original response
+    pub ttl_hours: i64,
+}
+
+impl Default for IdempotencyConfig {
+    fn default() -> Self {
+        Self { ttl_hours: 24 }
+    }
+}
+
+impl IdempotencyConfig {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+
+        Self {
+            ttl_hours: std::env::var("IDEMPOTENCY_KEY_TTL_HOURS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|hours| *hours > 0)
+                .unwrap_or(defaults.ttl_hours),
+        }
+    }
+}
+
+/// A client's `Idempotency-Key`, scoped to the caller so keys can't collide across users
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdempotencyKey {
+    scope: String,
+    key: String,
+    /// Endpoint and body of the request; reusing a key for a different request is an error
+    fingerprint: String,
+}
+
+impl IdempotencyKey {
+    /// The request's key, None without the header, 400 when the header isn't a usable key
+    pub fn from_headers(
+        headers: &HeaderMap,
+        auth: &AuthContext,
+        endpoint: &str,
+        body: &impl Serialize,
+    ) -> Result<Option<Self>, StatusCode> {
+        let Some(value) = headers.get(IDEMPOTENCY_KEY_HEADER) else {
+            return Ok(None);
+        };
+        let key = value.to_str().map_err(|_| StatusCode::BAD_REQUEST)?.trim();
+        if key.is_empty() || key.len() > MAX_KEY_LEN {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+
+        let scope = match &auth.user_id {
+            Some(user_id) => format!("user:{}", user_id),
+            None => format!("org:{}", auth.org_id),
+        };
+        let body = serde_json::to_string(body).map_err(|_| StatusCode::BAD_REQUEST)?;
+        let fingerprint = hex::encode(Sha256::digest(format!("{}\n{}", endpoint, body).as_bytes()));
+
+        Ok(Some(Self {
+            scope,
+            key: key.to_string(),
+            fingerprint,
+        }))
+    }
+}
+
+/// Run `handler` once per key: a retry gets the stored response of the first request that
+/// succeeded, a retry racing the first request gets 409, and a key reused for a different
+/// request gets 422. Failed requests release their key. Without a key `handler` just runs.
+pub async fn run_once<T, F, Fut>(state: &AppState, key: Option<IdempotencyKey>, handler: F) -> Result<Response, StatusCode>
+where
+    T: Serialize,
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<Json<T>, StatusCode>>,
+{
+    let Some(key) = key else {
+        return handler().await.map(IntoResponse::into_response);
+    };
+
+    let existing = state
+        .database
+        .claim_idempotency_key(
+            &key.scope,
+            &key.key,
+            &key.fingerprint,
+            chrono::Duration::hours(state.idempotency.ttl_hours),
+            chrono::Duration::seconds(STALE_CLAIM_SECS),
+        )
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to claim idempotency key: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    if let Some(existing) = existing {
+        if existing.fingerprint != key.fingerprint {
+            return Err(StatusCode::UNPROCESSABLE_ENTITY);
+        }
+        let (Some(status_code), Some(response)) = (existing.status_code, existing.response) else {
+            return Err(StatusCode::CONFLICT);
+        };
+        tracing::info!("Replaying response for idempotency key {}", key.key);
+        let status = StatusCode::from_u16(status_code).unwrap_or(StatusCode::OK);
+        let body: Value = serde_json::from_str(&response).map_err(|e| {
+            tracing::error!("Failed to parse stored idempotent response: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        return Ok((status, [(IDEMPOTENT_REPLAYED_HEADER, "true")], Json(body)).into_response());
+    }
+
+    match handler().await {
+        Ok(Json(body)) => {
+            let stored = serde_json::to_string(&body).map_err(|e| {
+                tracing::error!("Failed to serialize idempotent response: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+            if let Err(e) = state
+                .database
+                .complete_idempotency_key(&key.scope, &key.key, StatusCode::OK.as_u16(), &stored)
+                .await
+            {
+                // The request itself succeeded; a retry will just see it as in progress
+                tracing::error!("Failed to store idempotent response: {}", e);
+            }
+            Ok(Json(body).into_response())
+        }
+        Err(status) => {
+            if let Err(e) = state.database.release_idempotency_key(&key.scope, &key.key).await {
+                tracing::error!("Failed to release idempotency key: {}", e);
+            }
+            Err(status)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_agent::{MockAgent, MockAgentConfig};
+    use crate::test_support::{test_db, test_state};
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn headers(key: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(IDEMPOTENCY_KEY_HEADER, key.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_key_is_scoped_and_fingerprinted() {
+        let auth = AuthContext::anonymous();
+        let body = json!({"title": "Login fails"});
+
+        assert_eq!(IdempotencyKey::from_headers(&HeaderMap::new(), &auth, "POST /a", &body), Ok(None));
+        assert_eq!(
+            IdempotencyKey::from_headers(&headers("  "), &auth, "POST /a", &body),
+            Err(StatusCode::BAD_REQUEST)
+        );
+        assert_eq!(
+            IdempotencyKey::from_headers(&headers(&"k".repeat(MAX_KEY_LEN + 1)), &auth, "POST /a", &body),
+            Err(StatusCode::BAD_REQUEST)
+        );
+
+        let key = IdempotencyKey::from_headers(&headers("abc"), &auth, "POST /a", &body).unwrap().unwrap();
+        assert_eq!(key.key, "abc");
+        assert!(key.scope.starts_with("org:"));
+
+        let same = IdempotencyKey::from_headers(&headers("abc"), &auth, "POST /a", &body).unwrap().unwrap();
+        let other_endpoint = IdempotencyKey::from_headers(&headers("abc"), &auth, "POST /b", &body).unwrap().unwrap();
+        let other_body =
+            IdempotencyKey::from_headers(&headers("abc"), &auth, "POST /a", &json!({"title": "x"})).unwrap().unwrap();
+        assert_eq!(key.fingerprint, same.fingerprint);
+        assert_ne!(key.fingerprint, other_endpoint.fingerprint);
+        assert_ne!(key.fingerprint, other_body.fingerprint);
+    }
+
+    async fn state() -> AppState {
+        let agent = Arc::new(MockAgent::with_config(MockAgentConfig::from_env()));
+        test_state(Arc::new(test_db().await.unwrap()), agent).unwrap()
+    }
+
+    fn key(body: &Value) -> Option<IdempotencyKey> {
+        IdempotencyKey::from_headers(&headers("k1"), &AuthContext::anonymous(), "POST /api/x", body).unwrap()
+    }
+
+    async fn body(response: Response) -> Value {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_run_once() {
+        let state = state().await;
+        let runs = AtomicUsize::new(0);
+        let created = || async {
+            let run = runs.fetch_add(1, Ordering::SeqCst) + 1;
+            Ok(Json(json!({ "run": run })))
+        };
+        let request = json!({ "title": "Login fails" });
+
+        // A failed request releases its key, so the retry runs
+        let failed = run_once(&state, key(&request), || async {
+            runs.fetch_add(1, Ordering::SeqCst);
+            Err::<Json<Value>, _>(StatusCode::BAD_GATEWAY)
+        })
+        .await;
+        assert_eq!(failed.unwrap_err(), StatusCode::BAD_GATEWAY);
+
+        let first = run_once(&state, key(&request), created).await.unwrap();
+        assert!(first.headers().get(IDEMPOTENT_REPLAYED_HEADER).is_none());
+        assert_eq!(body(first).await, json!({ "run": 2 }));
+
+        // The retry gets the stored response without running again
+        let replayed = run_once(&state, key(&request), created).await.unwrap();
+        assert_eq!(replayed.status(), StatusCode::OK);
+        assert_eq!(replayed.headers()[IDEMPOTENT_REPLAYED_HEADER], "true");
+        assert_eq!(body(replayed).await, json!({ "run": 2 }));
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+
+        // The same key for another request
+        let mismatch = run_once(&state, key(&json!({ "title": "Other" })), created).await;
+        assert_eq!(mismatch.unwrap_err(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_run_once_conflicts_while_in_flight() {
+        let state = state().await;
+        let request = json!({ "title": "Login fails" });
+
+        let outer = run_once(&state, key(&request), || async {
+            // A retry arriving while the first request is still running
+            let retry = run_once(&state, key(&request), || async { Ok(Json(json!({ "run": "retry" }))) }).await;
+            assert_eq!(retry.unwrap_err(), StatusCode::CONFLICT);
+            Ok(Json(json!({ "run": "first" })))
+        })
+        .await
+        .unwrap();
+        assert_eq!(body(outer).await, json!({ "run": "first" }));
+    }
+}
@@ -0,0 +1,327 @@
+use crate::log_normalizer::LogNormalizer;
+use crate::message_store::StructuredLogEntry;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::io::AsyncWriteExt;
+use tokio::process::ChildStdin;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+/// Metadata key on the log entry announcing a paused agent; `progress::track_progress`
+/// relays such entries as `agent-awaiting-input` broadcasts
+pub const AWAITING_INPUT_KEY: &str = "awaiting_input";
+
+/// `<PREFIX>_INTERACTIVE=true` keeps the CLI's stdin open so permission prompts can be
+/// answered from the UI. Off by default: a prompt nobody answers holds the run until its timeout.
+pub fn interactive_from_env(prefix: &str) -> bool {
+    std::env::var(format!("{}_INTERACTIVE", prefix))
+        .map(|v| matches!(v.trim().to_lowercase().as_str(), "true" | "1" | "yes" | "on"))
+        .unwrap_or(false)
+}
+
+/// How a CLI asks for permission and expects the answer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputProtocol {
+    /// Claude's stream-json control messages (`--permission-prompt-tool stdio`)
+    ClaudeControl,
+    /// Plain-text questions such as `Run this command? (y/n)`, answered with a line of text
+    Text,
+}
+
+/// A question the agent is waiting on
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct PermissionRequest {
+    /// Control request ID to echo back (Claude); None for text prompts
+    pub request_id: Option<String>,
+    pub tool: Option<String>,
+    /// Human-readable question shown in the UI
+    pub prompt: String,
+    /// Tool input the CLI proposed, returned unchanged when the user allows it
+    #[serde(skip)]
+    pub input: Value,
+}
+
+/// The user's answer, from an `agent-input` WebSocket message
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AgentInput {
+    #[serde(default)]
+    pub allow: Option<bool>,
+    /// Free text: typed as-is for text prompts, the denial reason for Claude
+    #[serde(default)]
+    pub text: Option<String>,
+}
+
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub enum InputError {
+    #[error("No interactive agent is running for this ticket")]
+    NotInteractive,
+    #[error("The agent is not waiting for input")]
+    NothingPending,
+    #[error("The agent's stdin is already closed")]
+    Closed,
+}
+
+impl InputProtocol {
+    /// The permission request `line` carries, if any
+    pub fn detect(self, line: &str) -> Option<PermissionRequest> {
+        match self {
+            Self::ClaudeControl => {
+                let event: Value = serde_json::from_str(line).ok()?;
+                if event["type"] != "control_request" || event["request"]["subtype"] != "can_use_tool" {
+                    return None;
+                }
+                let tool = event["request"]["tool_name"].as_str().unwrap_or("tool").to_string();
+                let input = event["request"]["input"].clone();
+                let detail = ["command", "file_path", "path", "pattern", "url"]
+                    .iter()
+                    .find_map(|key| input[*key].as_str())
+                    .map(str::to_string)
+                    .unwrap_or_else(|| input.to_string());
+                Some(PermissionRequest {
+                    request_id: event["request_id"].as_str().map(str::to_string),
+                    prompt: format!("{}: {}", tool, detail),
+                    tool: Some(tool),
+                    input,
+                })
+            }
+            Self::Text => {
+                let trimmed = line.trim();
+                let lower = trimmed.to_lowercase();
+                let asks = ["(y/n)", "[y/n]", "(yes/no)", "[yes/no]"]
+                    .iter()
+                    .any(|suffix| lower.trim_end_matches([':', ' ']).ends_with(suffix));
+                asks.then(|| PermissionRequest {
+                    request_id: None,
+                    tool: None,
+                    prompt: trimmed.to_string(),
+                    input: Value::Null,
+                })
+            }
+        }
+    }
+
+    /// Whether `line` is the CLI's final event, after which stdin is closed so it can exit
+    fn is_final(self, line: &str) -> bool {
+        line.starts_with('{')
+            && serde_json::from_str::<Value>(line).is_ok_and(|event| event["type"] == "result")
+    }
+
+    /// What to write to stdin for `answer`
+    pub fn encode(self, request: &PermissionRequest, answer: &AgentInput) -> String {
+        match self {
+            Self::ClaudeControl => {
+                let response = if answer.allow == Some(true) {
+                    json!({ "behavior": "allow", "updatedInput": request.input })
+                } else {
+                    let reason = answer.text.as_deref().unwrap_or("Denied by the user");
+                    json!({ "behavior": "deny", "message": reason })
+                };
+                let message = json!({
+                    "type": "control_response",
+                    "response": {
+                        "subtype": "success",
+                        "request_id": request.request_id,
+                        "response": response,
+                    },
+                });
+                format!("{}\n", message)
+            }
+            Self::Text => match (&answer.text, answer.allow) {
+                (Some(text), _) => format!("{}\n", text),
+                (None, Some(true)) => "y\n".to_string(),
+                (None, _) => "n\n".to_string(),
+            },
+        }
+    }
+}
+
+/// The first stdin message of a Claude run in `--input-format stream-json`
+pub fn claude_user_message(prompt: &str) -> String {
+    let message = json!({
+        "type": "user",
+        "message": { "role": "user", "content": prompt },
+    });
+    format!("{}\n", message)
+}
+
+#[derive(Debug)]
+struct Channel {
+    id: u64,
+    protocol: InputProtocol,
+    tx: Option<mpsc::UnboundedSender<String>>,
+    pending: Option<PermissionRequest>,
+}
+
+/// Stdin of the interactive agent processes, keyed by ticket ID
+#[derive(Debug, Default)]
+pub struct AgentInputs {
+    channels: Mutex<HashMap<String, Channel>>,
+    next_id: AtomicU64,
+}
+
+impl AgentInputs {
+    /// Take over `stdin` of a freshly spawned agent, writing `initial` first. Stdin stays
+    /// open until the returned session sees the final event or is dropped.
+    pub fn open(
+        self: &Arc<Self>,
+        ticket_id: &str,
+        protocol: InputProtocol,
+        mut stdin: ChildStdin,
+        initial: Option<String>,
+    ) -> InputSession {
+        let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+        if let Some(initial) = initial {
+            let _ = tx.send(initial);
+        }
+        tokio::spawn(async move {
+            while let Some(message) = rx.recv().await {
+                if let Err(e) = async {
+                    stdin.write_all(message.as_bytes()).await?;
+                    stdin.flush().await
+                }
+                .await
+                {
+                    warn!("⚠️ Không ghi được vào stdin của agent: {}", e);
+                    break;
+                }
+            }
+        });
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.channels.lock().unwrap().insert(
+            ticket_id.to_string(),
+            Channel {
+                id,
+                protocol,
+                tx: Some(tx),
+                pending: None,
+            },
+        );
+        info!("💬 Agent cho ticket {} chạy ở chế độ tương tác", ticket_id);
+
+        InputSession {
+            inputs: self.clone(),
+            ticket_id: ticket_id.to_string(),
+            id,
+        }
+    }
+
+    /// The question the ticket's agent is waiting on
+    pub fn pending(&self, ticket_id: &str) -> Option<PermissionRequest> {
+        self.channels.lock().unwrap().get(ticket_id)?.pending.clone()
+    }
+
+    /// Write the user's answer to the waiting agent; returns the question it answered
+    pub fn answer(&self, ticket_id: &str, answer: &AgentInput) -> Result<PermissionRequest, InputError> {
+        let mut channels = self.channels.lock().unwrap();
+        let channel = channels.get_mut(ticket_id).ok_or(InputError::NotInteractive)?;
+        let tx = channel.tx.as_ref().ok_or(InputError::Closed)?;
+        let request = channel.pending.take().ok_or(InputError::NothingPending)?;
+        if tx.send(channel.protocol.encode(&request, answer)).is_err() {
+            return Err(InputError::Closed);
+        }
+        Ok(request)
+    }
+}
+
+/// An agent's side of its stdin channel
+#[derive(Debug)]
+pub struct InputSession {
+    inputs: Arc<AgentInputs>,
+    ticket_id: String,
+    id: u64,
+}
+
+impl InputSession {
+    /// Feed a stdout line. Returns the log entry to push when the agent starts waiting for
+    /// input; the final event closes stdin instead.
+    pub fn observe(&self, line: &str) -> Option<StructuredLogEntry> {
+        let mut channels = self.inputs.channels.lock().unwrap();
+        let channel = channels.get_mut(&self.ticket_id).filter(|c| c.id == self.id)?;
+        if channel.protocol.is_final(line) {
+            channel.tx = None;
+            channel.pending = None;
+            return None;
+        }
+        let request = channel.protocol.detect(line)?;
+        channel.pending = Some(request.clone());
+        drop(channels);
+
+        info!("⏸️ Agent cho ticket {} đang chờ xác nhận: {}", self.ticket_id, request.prompt);
+        let mut entry = LogNormalizer::new().normalize(
+            format!("⏸️ Agent đang chờ xác nhận: {}", request.prompt),
+            self.ticket_id.clone(),
+        );
+        entry.metadata.insert(
+            AWAITING_INPUT_KEY.to_string(),
+            serde_json::to_string(&request).unwrap_or_default(),
+        );
+        Some(entry)
+    }
+}
+
+impl Drop for InputSession {
+    fn drop(&mut self) {
+        let mut channels = self.inputs.channels.lock().unwrap();
+        if channels.get(&self.ticket_id).is_some_and(|c| c.id == self.id) {
+            channels.remove(&self.ticket_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detects_and_answers_permission_requests() {
+        let line = r#"{"type":"control_request","request_id":"req-1","request":{"subtype":"can_use_tool","tool_name":"Bash","input":{"command":"npm test"}}}"#;
+        let request = InputProtocol::ClaudeControl.detect(line).unwrap();
+        assert_eq!(request.prompt, "Bash: npm test");
+        assert_eq!(request.request_id.as_deref(), Some("req-1"));
+        assert!(InputProtocol::ClaudeControl.detect(r#"{"type":"assistant"}"#).is_none());
+
+        let allow = InputProtocol::ClaudeControl.encode(&request, &AgentInput { allow: Some(true), text: None });
+        let allow: Value = serde_json::from_str(&allow).unwrap();
+        assert_eq!(allow["response"]["request_id"], "req-1");
+        assert_eq!(allow["response"]["response"]["behavior"], "allow");
+        assert_eq!(allow["response"]["response"]["updatedInput"]["command"], "npm test");
+        let deny = InputProtocol::ClaudeControl.encode(&request, &AgentInput::default());
+        assert!(deny.contains(r#""behavior":"deny""#));
+
+        let prompt = InputProtocol::Text.detect("Run `rm -rf build`? (y/n): ").unwrap();
+        assert_eq!(prompt.prompt, "Run `rm -rf build`? (y/n):");
+        assert!(InputProtocol::Text.detect("Reading file: src/main.rs").is_none());
+        assert_eq!(InputProtocol::Text.encode(&prompt, &AgentInput { allow: Some(true), text: None }), "y\n");
+        assert!(InputProtocol::Text.is_final(r#"{"type":"result","result":"done"}"#));
+    }
+
+    #[tokio::test]
+    async fn test_answer_reaches_child_stdin() {
+        let mut child = tokio::process::Command::new("cat")
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .unwrap();
+        let inputs = Arc::new(AgentInputs::default());
+        let session = inputs.open("t1", InputProtocol::Text, child.stdin.take().unwrap(), Some("hello\n".to_string()));
+
+        assert_eq!(inputs.answer("t1", &AgentInput::default()), Err(InputError::NothingPending));
+        let entry = session.observe("Continue? [y/N]").unwrap();
+        assert!(entry.metadata.contains_key(AWAITING_INPUT_KEY));
+        assert!(inputs.pending("t1").is_some());
+        inputs.answer("t1", &AgentInput { allow: Some(true), text: None }).unwrap();
+
+        // The final event closes stdin, so `cat` sees EOF and exits
+        session.observe(r#"{"type":"result"}"#);
+        let output = child.wait_with_output().await.unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "hello\ny\n");
+
+        drop(session);
+        assert_eq!(inputs.answer("t1", &AgentInput::default()), Err(InputError::NotInteractive));
+    }
+}
@@ -0,0 +1,232 @@
+use crate::code_agent::CodeAnalysisRequest;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+
+/// How urgently an analysis should run; higher priorities leave the queue first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum AnalysisPriority {
+    /// Background work such as stale re-runs
+    Low,
+    #[default]
+    Normal,
+    High,
+    /// Production incidents; may preempt running work when `ANALYSIS_PREEMPTION` is on
+    Urgent,
+}
+
+impl AnalysisPriority {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Low => "low",
+            Self::Normal => "normal",
+            Self::High => "high",
+            Self::Urgent => "urgent",
+        }
+    }
+
+    /// Order in the shared queue (`worker`), higher first
+    pub fn rank(&self) -> i64 {
+        *self as i64
+    }
+
+    pub fn from_rank(rank: i64) -> Self {
+        match rank {
+            i64::MIN..=0 => Self::Low,
+            1 => Self::Normal,
+            2 => Self::High,
+            _ => Self::Urgent,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueConfig {
+    /// Analyses running at once; 0 means no limit
+    pub max_concurrent: usize,
+    /// Let an urgent job that finds every slot busy pause (cancel and requeue) the
+    /// lowest-priority running one
+    pub preemption: bool,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent: 4,
+            preemption: false,
+        }
+    }
+}
+
+impl QueueConfig {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+
+        Self {
+            max_concurrent: std::env::var("ANALYSIS_MAX_CONCURRENT")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(defaults.max_concurrent),
+            preemption: std::env::var("ANALYSIS_PREEMPTION")
+                .map(|v| matches!(v.trim().to_lowercase().as_str(), "true" | "1" | "yes" | "on"))
+                .unwrap_or(defaults.preemption),
+        }
+    }
+}
+
+/// Analysis waiting for a free slot
+#[derive(Debug, Clone)]
+pub struct QueuedJob {
+    pub request: CodeAnalysisRequest,
+    /// User to notify when the run ends
+    pub requested_by: Option<String>,
+    /// Arrival order, kept when a preempted job is requeued so it resumes ahead of later arrivals
+    seq: u64,
+}
+
+/// Pending analyses, ordered by priority then arrival
+#[derive(Debug)]
+pub struct JobQueue {
+    config: QueueConfig,
+    jobs: BTreeMap<(Reverse<AnalysisPriority>, u64), QueuedJob>,
+    next_seq: u64,
+}
+
+impl JobQueue {
+    pub fn new(config: QueueConfig) -> Self {
+        Self {
+            config,
+            jobs: BTreeMap::new(),
+            next_seq: 0,
+        }
+    }
+
+    pub fn config(&self) -> QueueConfig {
+        self.config
+    }
+
+    /// Whether another job may start while `running` are in progress
+    pub fn has_capacity(&self, running: usize) -> bool {
+        self.config.max_concurrent == 0 || running < self.config.max_concurrent
+    }
+
+    /// Queue a request, replacing one already waiting for the same ticket
+    pub fn push(&mut self, request: CodeAnalysisRequest, requested_by: Option<String>) {
+        self.remove(&request.ticket_id);
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.requeue(QueuedJob {
+            request,
+            requested_by,
+            seq,
+        });
+    }
+
+    /// Put back a job taken with `pop`, in its original place
+    pub fn requeue(&mut self, job: QueuedJob) {
+        self.jobs.insert((Reverse(job.request.priority), job.seq), job);
+    }
+
+    /// The highest-priority, longest-waiting job
+    pub fn pop(&mut self) -> Option<QueuedJob> {
+        self.jobs.pop_first().map(|(_, job)| job)
+    }
+
+    pub fn remove(&mut self, ticket_id: &str) -> Option<QueuedJob> {
+        let key = *self
+            .jobs
+            .iter()
+            .find(|(_, job)| job.request.ticket_id == ticket_id)?
+            .0;
+        self.jobs.remove(&key)
+    }
+
+    /// 1-based place of the ticket's job in the queue
+    pub fn position(&self, ticket_id: &str) -> Option<usize> {
+        self.find(ticket_id).map(|(position, _)| position)
+    }
+
+    /// The ticket's waiting job and its 1-based place in the queue
+    pub fn find(&self, ticket_id: &str) -> Option<(usize, &QueuedJob)> {
+        self.jobs
+            .values()
+            .enumerate()
+            .find(|(_, job)| job.request.ticket_id == ticket_id)
+            .map(|(index, job)| (index + 1, job))
+    }
+
+    pub fn count_at(&self, priority: AnalysisPriority) -> usize {
+        self.jobs.keys().filter(|(p, _)| p.0 == priority).count()
+    }
+
+    pub fn len(&self) -> usize {
+        self.jobs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(ticket_id: &str, priority: AnalysisPriority) -> CodeAnalysisRequest {
+        CodeAnalysisRequest {
+            ticket_id: ticket_id.to_string(),
+            code_context: String::new(),
+            question: "q".to_string(),
+            project_id: "p".to_string(),
+            run_id: None,
+            agent_type: None,
+            mode: Default::default(),
+            path_rules: Default::default(),
+            tool_policy: Default::default(),
+            priority,
+            include_linked_results: false,
+            budget_override: false,
+            replay: None,
+            working_dir: None,
+            prompt_variant: None,
+            response_language: None,
+            api_key: None,
+            then: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_dequeues_by_priority_then_arrival() {
+        let mut queue = JobQueue::new(QueueConfig {
+            max_concurrent: 2,
+            preemption: true,
+        });
+        queue.push(request("docs", AnalysisPriority::Low), None);
+        queue.push(request("a", AnalysisPriority::Normal), None);
+        queue.push(request("b", AnalysisPriority::Normal), None);
+        queue.push(request("incident", AnalysisPriority::Urgent), None);
+
+        assert_eq!(queue.position("incident"), Some(1));
+        assert_eq!(queue.position("docs"), Some(4));
+        assert_eq!(queue.count_at(AnalysisPriority::Urgent), 1);
+        assert!(queue.has_capacity(1) && !queue.has_capacity(2));
+
+        let order: Vec<String> = std::iter::from_fn(|| queue.pop()).map(|job| job.request.ticket_id).collect();
+        assert_eq!(order, ["incident", "a", "b", "docs"]);
+
+        // A requeued (preempted) job goes back ahead of later arrivals of its priority
+        queue.push(request("a", AnalysisPriority::Normal), None);
+        let first = queue.pop().unwrap();
+        queue.push(request("c", AnalysisPriority::Normal), None);
+        queue.requeue(first);
+        assert_eq!(queue.position("a"), Some(1));
+
+        // A newer request for a queued ticket replaces it
+        queue.push(request("c", AnalysisPriority::High), None);
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.position("c"), Some(1));
+        assert!(queue.remove("c").is_some());
+        assert_eq!(queue.position("c"), None);
+    }
+}
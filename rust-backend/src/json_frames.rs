@@ -0,0 +1,215 @@
+//! Reassembles JSON events a CLI split over several stdout lines. Agents are meant to print one
+//! event per line, but Gemini sometimes pretty-prints an event or breaks it up, and each piece
+//! then showed up as a plain text log. Lines go in as read; a complete event comes out on one
+//! line, anything that is not one comes out as it was.
+
+use serde_json::Value;
+use std::collections::VecDeque;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader, Lines};
+
+/// An event still open past this many bytes is given up on and its lines passed on as text
+const MAX_FRAME_BYTES: usize = 1024 * 1024;
+
+type OnLine = Box<dyn FnMut(&str) + Send>;
+
+/// The framing state between lines
+#[derive(Debug, Default)]
+pub struct JsonFramer {
+    /// Lines of the event still open
+    pending: Vec<String>,
+    pending_bytes: usize,
+    depth: i64,
+    in_string: bool,
+    escaped: bool,
+}
+
+impl JsonFramer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The frames `line` completes, in order
+    pub fn push(&mut self, line: String) -> Vec<String> {
+        if self.pending.is_empty() {
+            if !line.trim_start().starts_with('{') {
+                return vec![line];
+            }
+        } else if line.starts_with('{') {
+            // Nested objects of a pretty-printed event are indented: this is a new event and
+            // the open one was cut off
+            let mut frames = self.finish();
+            frames.extend(self.push(line));
+            return frames;
+        }
+
+        self.scan(&line);
+        self.pending_bytes += line.len() + 1;
+        self.pending.push(line);
+
+        if self.depth <= 0 {
+            self.complete()
+        } else if self.pending_bytes > MAX_FRAME_BYTES {
+            self.finish()
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// At the end of output: what is still open, as it was
+    pub fn finish(&mut self) -> Vec<String> {
+        let lines = std::mem::take(&mut self.pending);
+        *self = Self::default();
+        lines
+    }
+
+    fn scan(&mut self, line: &str) {
+        for c in line.chars() {
+            if self.in_string {
+                match c {
+                    _ if self.escaped => self.escaped = false,
+                    '\\' => self.escaped = true,
+                    '"' => self.in_string = false,
+                    _ => {}
+                }
+                continue;
+            }
+            match c {
+                '"' => self.in_string = true,
+                '{' | '[' => self.depth += 1,
+                '}' | ']' => self.depth -= 1,
+                _ => {}
+            }
+        }
+    }
+
+    fn complete(&mut self) -> Vec<String> {
+        if self.pending.len() == 1 {
+            return self.finish();
+        }
+        match serde_json::from_str::<Value>(&self.pending.join("\n")) {
+            Ok(event) => {
+                self.finish();
+                vec![event.to_string()]
+            }
+            Err(_) => self.finish(),
+        }
+    }
+}
+
+/// The lines of `reader` framed by a [`JsonFramer`]
+pub struct JsonFrames<R> {
+    lines: Lines<BufReader<R>>,
+    framer: JsonFramer,
+    ready: VecDeque<String>,
+    on_line: Option<OnLine>,
+    done: bool,
+}
+
+impl<R: AsyncRead + Unpin> JsonFrames<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            lines: BufReader::new(reader).lines(),
+            framer: JsonFramer::new(),
+            ready: VecDeque::new(),
+            on_line: None,
+            done: false,
+        }
+    }
+
+    /// Call `on_line` with every raw line as it is read, before framing
+    pub fn on_line(mut self, on_line: impl FnMut(&str) + Send + 'static) -> Self {
+        self.on_line = Some(Box::new(on_line));
+        self
+    }
+
+    /// The next frame; None once the output has ended
+    pub async fn next_frame(&mut self) -> Option<String> {
+        while self.ready.is_empty() && !self.done {
+            let frames = match self.lines.next_line().await {
+                Ok(Some(line)) => {
+                    if let Some(on_line) = self.on_line.as_mut() {
+                        on_line(&line);
+                    }
+                    self.framer.push(line)
+                }
+                _ => {
+                    self.done = true;
+                    self.framer.finish()
+                }
+            };
+            self.ready.extend(frames);
+        }
+        self.ready.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(lines: &[&str]) -> Vec<String> {
+        let mut framer = JsonFramer::new();
+        let mut frames: Vec<String> = lines.iter().flat_map(|line| framer.push(line.to_string())).collect();
+        frames.extend(framer.finish());
+        frames
+    }
+
+    #[test]
+    fn test_single_lines_pass_through() {
+        let lines = [r#"{"type":"init", "model":"gemini"}"#, "Loaded cached credentials.", "[1/3] { reading"];
+        assert_eq!(frame(&lines), lines);
+    }
+
+    #[test]
+    fn test_reassembles_split_events() {
+        let frames = frame(&[
+            "{",
+            r#"  "type": "message","#,
+            r#"  "content": "a } in a string {","#,
+            r#"  "nested": {"role": "assistant"}"#,
+            "}",
+            "done",
+        ]);
+        assert_eq!(frames.len(), 2);
+        let event: Value = serde_json::from_str(&frames[0]).unwrap();
+        assert_eq!(event["content"], "a } in a string {");
+        assert_eq!(event["nested"]["role"], "assistant");
+        assert_eq!(frames[1], "done");
+    }
+
+    #[test]
+    fn test_truncated_events_are_passed_on() {
+        // Cut off by the next event
+        let frames = frame(&[r#"{"type":"message","content":"half"#, r#"{"type":"result"}"#]);
+        assert_eq!(frames, [r#"{"type":"message","content":"half"#, r#"{"type":"result"}"#]);
+
+        // Cut off by the end of output
+        assert_eq!(frame(&["{", r#"  "type": "mess"#]), ["{", r#"  "type": "mess"#]);
+
+        // Too large to be an event
+        let mut framer = JsonFramer::new();
+        assert!(framer.push("{".to_string()).is_empty());
+        let frames = framer.push(format!(r#"  "content": "{}","#, "x".repeat(MAX_FRAME_BYTES)));
+        assert_eq!(frames.len(), 2);
+        assert_eq!(framer.push("plain".to_string()), ["plain"]);
+    }
+
+    #[tokio::test]
+    async fn test_frames_from_reader() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let output: &[u8] = b"{\n  \"type\": \"result\"\n}\ntext\n{\"type\":";
+        let read = Arc::new(AtomicUsize::new(0));
+        let counter = read.clone();
+        let mut frames = JsonFrames::new(output).on_line(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        let mut received = Vec::new();
+        while let Some(frame) = frames.next_frame().await {
+            received.push(frame);
+        }
+        assert_eq!(received, [r#"{"type":"result"}"#, "text", r#"{"type":"#]);
+        assert_eq!(read.load(Ordering::SeqCst), 5);
+    }
+}
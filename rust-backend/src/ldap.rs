@@ -0,0 +1,210 @@
+use crate::auth::{ExternalIdentity, Provisioning};
+use anyhow::{anyhow, Result};
+use ldap3::{ldap_escape, LdapConnAsync, LdapConnSettings, Scope, SearchEntry};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// LDAP result code of a bind with a wrong password (or a disabled account)
+const INVALID_CREDENTIALS: u32 = 49;
+
+/// LDAP / Active Directory settings, read from `LDAP_*`; None when `LDAP_URL` isn't set
+#[derive(Debug, Clone)]
+pub struct LdapConfig {
+    /// `ldap://dc1.corp.example.com` or `ldaps://...`
+    pub url: String,
+    /// Upgrade `ldap://` connections with StartTLS
+    pub starttls: bool,
+    /// Service account searching for users; anonymous search when unset
+    pub bind_dn: Option<String>,
+    pub bind_password: Option<String>,
+    pub base_dn: String,
+    /// Search filter with `{login}` standing for what the user typed
+    pub user_filter: String,
+    pub email_attribute: String,
+    pub name_attribute: String,
+    /// Attribute listing the user's group DNs (`memberOf` on AD and OpenLDAP with the overlay)
+    pub group_attribute: String,
+    pub timeout: Duration,
+    /// `LDAP_ORG_ID`, `LDAP_AUTO_PROVISION`, `LDAP_ROLE_MAPPING`, `LDAP_DEFAULT_ROLE`
+    pub provisioning: Provisioning,
+}
+
+impl LdapConfig {
+    pub fn from_env() -> Option<Self> {
+        let url = std::env::var("LDAP_URL").ok().filter(|url| !url.trim().is_empty())?;
+        let Some(base_dn) = std::env::var("LDAP_BASE_DN").ok().filter(|dn| !dn.trim().is_empty()) else {
+            tracing::warn!("LDAP_URL is set without LDAP_BASE_DN; LDAP login disabled");
+            return None;
+        };
+
+        Some(Self {
+            url: url.trim().to_string(),
+            starttls: std::env::var("LDAP_STARTTLS")
+                .map(|v| matches!(v.trim().to_lowercase().as_str(), "true" | "1" | "yes" | "on"))
+                .unwrap_or(false),
+            bind_dn: std::env::var("LDAP_BIND_DN").ok().filter(|dn| !dn.is_empty()),
+            bind_password: std::env::var("LDAP_BIND_PASSWORD").ok().filter(|p| !p.is_empty()),
+            base_dn: base_dn.trim().to_string(),
+            user_filter: std::env::var("LDAP_USER_FILTER")
+                .ok()
+                .filter(|filter| filter.contains("{login}"))
+                .unwrap_or_else(|| {
+                    "(|(sAMAccountName={login})(userPrincipalName={login})(uid={login})(mail={login}))".to_string()
+                }),
+            email_attribute: std::env::var("LDAP_EMAIL_ATTR").unwrap_or_else(|_| "mail".to_string()),
+            name_attribute: std::env::var("LDAP_NAME_ATTR").unwrap_or_else(|_| "displayName".to_string()),
+            group_attribute: std::env::var("LDAP_GROUP_ATTR").unwrap_or_else(|_| "memberOf".to_string()),
+            timeout: Duration::from_secs(
+                std::env::var("LDAP_TIMEOUT_SECS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .filter(|n| *n > 0)
+                    .unwrap_or(10),
+            ),
+            provisioning: Provisioning::from_env("LDAP"),
+        })
+    }
+
+    fn filter_for(&self, login: &str) -> String {
+        self.user_filter.replace("{login}", &ldap_escape(login))
+    }
+}
+
+/// Names a group DN can be mapped by: the DN itself and its leading `CN`
+fn group_names(dns: &[String]) -> Vec<String> {
+    let mut names = Vec::new();
+    for dn in dns {
+        names.push(dn.clone());
+        let first = dn.split(',').next().unwrap_or_default();
+        if let Some((attribute, value)) = first.split_once('=') {
+            if attribute.trim().eq_ignore_ascii_case("cn") {
+                names.push(value.trim().to_string());
+            }
+        }
+    }
+    names
+}
+
+/// First value of an attribute, matching its name case-insensitively as directories differ in case
+fn attribute<'a>(attrs: &'a HashMap<String, Vec<String>>, name: &str) -> Option<&'a Vec<String>> {
+    attrs
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, values)| values)
+}
+
+/// Verifies passwords by binding as the user found for a login
+pub struct LdapAuthenticator {
+    config: LdapConfig,
+}
+
+impl LdapAuthenticator {
+    pub fn new(config: LdapConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn config(&self) -> &LdapConfig {
+        &self.config
+    }
+
+    /// The directory user `login` names, when `password` is theirs. None for unknown users, wrong
+    /// passwords and logins matching several entries; errors only when the directory failed.
+    pub async fn authenticate(&self, login: &str, password: &str) -> Result<Option<ExternalIdentity>> {
+        // An empty password would be an unauthenticated bind, which most servers accept
+        if login.is_empty() || password.is_empty() {
+            return Ok(None);
+        }
+
+        let settings = LdapConnSettings::new()
+            .set_conn_timeout(self.config.timeout)
+            .set_starttls(self.config.starttls);
+        let (conn, mut ldap) = LdapConnAsync::with_settings(settings, &self.config.url).await?;
+        ldap3::drive!(conn);
+        ldap.with_timeout(self.config.timeout);
+
+        if let (Some(dn), Some(bind_password)) = (&self.config.bind_dn, &self.config.bind_password) {
+            ldap.simple_bind(dn, bind_password)
+                .await?
+                .success()
+                .map_err(|e| anyhow!("LDAP service account bind failed: {}", e))?;
+        }
+
+        let attributes = [
+            self.config.email_attribute.as_str(),
+            self.config.name_attribute.as_str(),
+            self.config.group_attribute.as_str(),
+        ];
+        ldap.with_timeout(self.config.timeout);
+        let (entries, _) = ldap
+            .search(&self.config.base_dn, Scope::Subtree, &self.config.filter_for(login), attributes)
+            .await?
+            .success()?;
+        let [entry] = entries.as_slice() else {
+            if entries.len() > 1 {
+                tracing::warn!("LDAP login {} matches {} entries; refused", login, entries.len());
+            }
+            let _ = ldap.unbind().await;
+            return Ok(None);
+        };
+        let entry = SearchEntry::construct(entry.clone());
+
+        ldap.with_timeout(self.config.timeout);
+        let bind = ldap.simple_bind(&entry.dn, password).await?;
+        let _ = ldap.unbind().await;
+        if bind.rc == INVALID_CREDENTIALS {
+            return Ok(None);
+        }
+        bind.success()?;
+
+        let first = |name: &str| attribute(&entry.attrs, name).and_then(|values| values.first()).cloned();
+        Ok(Some(ExternalIdentity {
+            issuer: self.config.url.clone(),
+            subject: entry.dn.clone(),
+            email: first(&self.config.email_attribute).map(|email| email.trim().to_lowercase()),
+            // The directory is authoritative for its users' addresses
+            email_verified: true,
+            name: first(&self.config.name_attribute),
+            groups: attribute(&entry.attrs, &self.config.group_attribute).map(|dns| group_names(dns)),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_login_is_escaped_in_filter() {
+        let config = LdapConfig {
+            url: "ldap://dc1.corp.example.com".to_string(),
+            starttls: false,
+            bind_dn: None,
+            bind_password: None,
+            base_dn: "DC=corp,DC=example,DC=com".to_string(),
+            user_filter: "(sAMAccountName={login})".to_string(),
+            email_attribute: "mail".to_string(),
+            name_attribute: "displayName".to_string(),
+            group_attribute: "memberOf".to_string(),
+            timeout: Duration::from_secs(10),
+            provisioning: Provisioning::from_env("LDAP_TEST_UNSET"),
+        };
+        assert_eq!(config.filter_for("jdoe"), "(sAMAccountName=jdoe)");
+        assert_eq!(config.filter_for("*)(uid=*"), "(sAMAccountName=\\2a\\29\\28uid=\\2a)");
+    }
+
+    #[test]
+    fn test_group_names_and_attributes() {
+        let dns = vec![
+            "CN=QA Admins,OU=Groups,DC=corp,DC=example,DC=com".to_string(),
+            "OU=Everyone,DC=corp".to_string(),
+        ];
+        assert_eq!(
+            group_names(&dns),
+            vec![dns[0].clone(), "QA Admins".to_string(), dns[1].clone()]
+        );
+
+        let attrs = HashMap::from([("MemberOf".to_string(), dns.clone())]);
+        assert_eq!(attribute(&attrs, "memberOf"), Some(&dns));
+        assert_eq!(attribute(&attrs, "mail"), None);
+    }
+}
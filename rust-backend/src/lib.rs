@@ -0,0 +1,379 @@
+//! QA Chatbot backend: the HTTP/WebSocket API, analysis pipeline and storage, as a library so
+//! the server binary and integration tests (`test-support` feature) share them
+
+// `from_str` on enums returns an Option or falls back to a default, unlike `FromStr`
+#![allow(clippy::should_implement_trait)]
+
+use axum::{
+    extract::{ws::WebSocketUpgrade, State},
+    response::Response,
+    routing::{delete, get, put, post},
+    Extension, Router,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+pub mod activity;
+pub mod agent_credentials;
+pub mod agent_factory;
+pub mod alerts;
+pub mod analysis_runner;
+pub mod api_agent;
+pub mod api_handlers;
+pub mod auth;
+pub mod backup;
+pub mod blob_store;
+pub mod board;
+pub mod bootstrap;
+pub mod budgets;
+pub mod cache;
+pub mod changed_files;
+pub mod claude_agent;
+pub mod code_agent;
+pub mod config;
+pub mod container;
+pub mod context_resolver;
+pub mod coverage;
+pub mod custom_fields;
+pub mod cursor_agent;
+pub mod dashboard;
+pub mod db_maintenance;
+pub mod database;
+pub mod diagram;
+pub mod experiments;
+pub mod feedback;
+pub mod gemini_agent;
+pub mod graphql;
+pub mod health;
+pub mod http_limits;
+pub mod idempotency;
+pub mod job_queue;
+pub mod json_frames;
+pub mod interaction;
+#[cfg(feature = "grpc")]
+pub mod grpc_service;
+pub mod ldap;
+pub mod log_bridge;
+pub mod log_compaction;
+pub mod log_normalizer;
+pub mod log_persistence;
+pub mod log_replay;
+pub mod logging;
+pub mod markdown;
+pub mod message_store;
+pub mod mock_agent;
+pub mod notifications;
+pub mod oidc;
+pub mod ollama_agent;
+pub mod org_handlers;
+pub mod output_validation;
+pub mod preferences;
+pub mod post_run;
+pub mod preflight;
+pub mod presence;
+pub mod progress;
+pub mod project_files;
+pub mod project_roots;
+pub mod project_transfer;
+pub mod prompt;
+pub mod question_templates;
+pub mod redaction;
+pub mod replay;
+pub mod resource_limits;
+pub mod retry;
+pub mod run_environment;
+pub mod s3;
+pub mod search;
+pub mod session_report;
+pub mod share_handlers;
+pub mod slack;
+pub mod slack_handlers;
+pub mod stale;
+pub mod stall;
+pub mod static_files;
+pub mod summary;
+pub mod tasks;
+pub mod test_cases;
+pub mod ticket_links;
+pub mod ticket_title;
+pub mod time_tracking;
+pub mod timeline;
+pub mod tool_policy;
+pub mod transcript;
+pub mod trash;
+pub mod tls;
+pub mod websocket_handler;
+pub mod worker;
+pub mod ws_hello;
+pub mod ws_limits;
+pub mod ws_stream;
+
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
+
+use agent_factory::AgentRegistry;
+use database::Database;
+use message_store::MsgStore;
+
+#[derive(Clone)]
+pub struct AppState {
+    pub agents: Arc<AgentRegistry>,
+    pub broadcast_tx: broadcast::Sender<BroadcastMessage>,
+    pub database: Arc<Database>,
+    pub msg_store: Arc<MsgStore>,
+    /// Named background tasks, listed by `GET /api/admin/tasks`
+    pub tasks: tasks::TaskSupervisor,
+    pub running_tasks: analysis_runner::RunningTasks,
+    /// Analyses waiting for a free slot (`ANALYSIS_MAX_CONCURRENT`), highest priority first
+    pub job_queue: analysis_runner::AnalysisQueue,
+    pub log_level: logging::LogLevelHandle,
+    pub backups: Arc<backup::BackupConfig>,
+    pub auth: Arc<auth::AuthConfig>,
+    /// Completion emails; None when SMTP isn't configured
+    pub notifier: Option<Arc<notifications::Notifier>>,
+    /// Slack slash command and result relay; None when SLACK_SIGNING_SECRET isn't set
+    pub slack: Option<Arc<slack::SlackClient>>,
+    /// SSO login; None when OIDC_ISSUER_URL isn't set
+    pub oidc: Option<Arc<oidc::OidcClient>>,
+    /// Password login against LDAP / Active Directory; None when `LDAP_URL` isn't set
+    pub ldap: Option<Arc<ldap::LdapAuthenticator>>,
+    /// Encrypts per-project agent keys; None when `AGENT_CREDENTIALS_KEY` isn't set
+    pub credentials: Option<Arc<agent_credentials::CredentialCipher>>,
+    /// Post-analysis TL;DR of results, for projects that enable it
+    pub summarizer: Arc<summary::Summarizer>,
+    /// SVG rendering of ticket diagrams; None when `MERMAID_CLI_PATH` isn't set
+    pub mermaid: Option<diagram::MermaidRenderer>,
+    /// Standard tickets `POST /api/projects/:id/bootstrap` creates
+    pub bootstrap: bootstrap::BootstrapConfig,
+    /// Question/context truncation applied before a request reaches its agent
+    pub prompt_limits: prompt::PromptLimits,
+    /// How many files a fuzzy code context resolves to
+    pub context_resolver: context_resolver::ResolverConfig,
+    /// How long `Idempotency-Key` responses are kept for replay
+    pub idempotency: idempotency::IdempotencyConfig,
+    /// How long deleted projects stay restorable
+    pub trash: trash::TrashConfig,
+    /// Where `?link=true` project exports are uploaded; None unless OBJECT_STORE=s3
+    pub exports: Option<Arc<dyn blob_store::BlobStore>>,
+    /// Rate and size limits on `/ws` client messages
+    pub ws_limits: ws_limits::WsLimits,
+    /// Whether projects' pre-flight checks may run shell commands, and for how long
+    pub preflight: preflight::PreflightConfig,
+    /// Directories project paths must be under; any when PROJECT_ROOTS is unset
+    pub project_roots: project_roots::ProjectRoots,
+    /// Whether analysis output is checked against its mode, and retried when it isn't
+    pub output_validation: output_validation::OutputValidationConfig,
+    /// Log alert rules and their per-run match counts, on this instance
+    pub alerts: Arc<alerts::AlertEngine>,
+    /// Most hours tickets may stay in each status (`TICKET_SLA_HOURS`)
+    pub sla: time_tracking::SlaConfig,
+    /// How tickets created without a title get one
+    pub ticket_titles: ticket_title::TitleConfig,
+    /// Who views which ticket over `/ws`, on this instance
+    pub presence: Arc<presence::Presence>,
+    /// `--role`: API, worker or both in one process
+    pub role: worker::Role,
+    /// Shared-queue settings of `api` and `worker` processes
+    pub worker: worker::WorkerConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BroadcastMessage {
+    pub ticket_id: String,
+    pub message_type: String,
+    pub content: String,
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    /// Restricts delivery to one organization's clients; when unset, delivery follows the
+    /// ticket's organization (`system` messages go to everyone)
+    #[serde(skip)]
+    pub org_id: Option<String>,
+    /// Files the run changed, on `code-analysis-complete` only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub changed_files: Option<Vec<changed_files::ChangedFile>>,
+}
+
+// Re-export for backward compatibility
+pub use code_agent::{CodeAnalysisRequest, CodeAnalysisResponse};
+
+/// Every API, WebSocket and GraphQL route, without the frontend fallback and HTTP layers `main`
+/// adds
+pub fn router(app_state: AppState) -> Router {
+    let schema = graphql::build_schema(app_state.clone());
+
+    Router::new()
+        .route("/healthz", get(health::liveness))
+        .route("/readyz", get(health::readiness))
+        .route("/ws", get(websocket_handler))
+        .route("/graphql", get(graphql::graphql_handler).post(graphql::graphql_handler))
+        .route("/graphql/ws", get(graphql::graphql_ws_handler))
+        .route("/api/auth/login", post(org_handlers::login))
+        .route("/api/auth/logout", post(org_handlers::logout))
+        .route("/api/auth/oidc/login", get(org_handlers::oidc_login))
+        .route("/api/auth/oidc/callback", get(org_handlers::oidc_callback))
+        .route("/api/me", get(org_handlers::get_me))
+        .route("/api/me/notifications", get(org_handlers::get_notification_settings).put(org_handlers::update_notification_settings))
+        .route("/api/me/preferences", get(org_handlers::get_preferences).put(org_handlers::update_preferences))
+        .route("/api/me/activity", get(org_handlers::get_activity_feed))
+        .route("/api/me/activity/read", post(org_handlers::mark_activity_read))
+        .route("/api/me/tickets", get(org_handlers::list_my_tickets))
+        .route("/api/orgs", get(org_handlers::list_organizations).post(org_handlers::create_organization))
+        .route("/api/orgs/current/members", get(org_handlers::list_members))
+        .route("/api/orgs/current/members/:user_id", delete(org_handlers::remove_member))
+        .route("/api/orgs/current/invites", get(org_handlers::list_invites).post(org_handlers::create_invite))
+        .route("/api/orgs/current/budget", get(org_handlers::get_budget).put(org_handlers::set_budget))
+        .route("/api/invites/:token/accept", post(org_handlers::accept_invite))
+        .route("/api/projects", get(api_handlers::list_projects).post(api_handlers::create_project))
+        .route("/api/projects/import", post(api_handlers::import_project))
+        .route("/api/projects/:id/export", get(api_handlers::export_project))
+        .route("/api/projects/:id/ticket-graph", get(api_handlers::get_ticket_graph))
+        .route("/api/projects/:id/logs", get(api_handlers::get_project_logs))
+        .route("/api/projects/:id/resolve-context", post(api_handlers::resolve_context))
+        .route("/api/projects/:id", get(api_handlers::get_project).put(api_handlers::update_project).delete(api_handlers::delete_project))
+        .route("/api/trash", get(api_handlers::list_trash))
+        .route("/api/trash/projects/:id/restore", post(api_handlers::restore_project))
+        .route("/api/projects/:project_id/tickets", get(api_handlers::list_tickets).post(api_handlers::create_ticket))
+        .route("/api/projects/:id/bootstrap", post(api_handlers::bootstrap_project))
+        .route("/api/tickets/:id/analyze", post(api_handlers::start_analysis))
+        .route("/api/tickets/:id/analysis-status", get(api_handlers::get_analysis_status))
+        .route("/api/tickets/:id/stop-analysis", post(api_handlers::stop_analysis))
+        .route("/api/tickets/:id/status", put(api_handlers::update_ticket_status))
+        .route("/api/tickets/:id/position", put(api_handlers::update_ticket_position))
+        .route("/api/tickets/:id/assignee", put(api_handlers::assign_ticket))
+        .route("/api/tickets/:id/watchers", get(api_handlers::list_ticket_watchers))
+        .route("/api/tickets/:id/watch", post(api_handlers::watch_ticket).delete(api_handlers::unwatch_ticket))
+        .route("/api/tickets/:id/logs", get(api_handlers::get_ticket_logs))
+        .route("/api/tickets/:id/logs/tail", get(api_handlers::tail_ticket_logs))
+        .route("/api/tickets/:id/logs/:log_id/raw", get(api_handlers::get_log_raw))
+        .route("/api/tickets/:id/result", get(api_handlers::get_ticket_result))
+        .route("/api/tickets/:id/result/html", get(api_handlers::get_ticket_result_html))
+        .route("/api/tickets/:id/test-cases", get(api_handlers::list_test_cases))
+        .route("/api/tickets/:id/labels", get(api_handlers::get_ticket_labels).put(api_handlers::set_ticket_labels))
+        .route(
+            "/api/tickets/:id/custom-fields",
+            get(api_handlers::get_ticket_custom_fields).put(api_handlers::set_ticket_custom_fields),
+        )
+        .route("/api/tickets/:id/feedback", get(api_handlers::list_ticket_feedback).post(api_handlers::rate_ticket_result))
+        .route("/api/tickets/:id/links", get(api_handlers::list_ticket_links).post(api_handlers::create_ticket_link))
+        .route("/api/tickets/:id/links/:link_id", delete(api_handlers::delete_ticket_link))
+        .route("/api/tickets/:id/test-cases/export", get(api_handlers::export_test_cases))
+        .route("/api/tickets/:id/diagram", get(api_handlers::get_ticket_diagram))
+        .route("/api/tickets/:id/files", get(api_handlers::list_ticket_files))
+        .route(
+            "/api/tickets/:id/context-files",
+            get(api_handlers::get_ticket_context_files).put(api_handlers::set_ticket_context_files),
+        )
+        .route("/api/tickets/:id/timeline", get(api_handlers::get_ticket_timeline))
+        .route("/api/tickets/:id/time", get(api_handlers::get_ticket_time))
+        .route("/api/tickets/:id/presence", get(api_handlers::get_ticket_presence))
+        .route("/api/tickets/:id/sessions", get(api_handlers::list_ticket_sessions))
+        .route("/api/sessions/:id", get(api_handlers::get_session))
+        .route("/api/sessions/:id/replay", post(api_handlers::replay_session))
+        .route("/api/sessions/:id/replay-stream", get(api_handlers::replay_session_stream))
+        .route("/api/sessions/:id/report", get(api_handlers::get_session_report))
+        .route("/api/sessions/:id/raw", get(api_handlers::get_session_raw))
+        .route("/api/sessions/:id/changed-files", get(api_handlers::get_session_changed_files))
+        .route("/api/tickets/:id/share", post(share_handlers::create_share))
+        .route("/api/tickets/:id/shares", get(share_handlers::list_shares))
+        .route("/api/tickets/:id/shares/:share_id", delete(share_handlers::revoke_share))
+        .route("/api/share/:token", get(share_handlers::get_shared_ticket))
+        .route("/share/:token", get(share_handlers::share_page).post(share_handlers::share_page_with_password))
+        .route("/api/integrations/slack/commands", post(slack_handlers::slash_command))
+        .route("/api/integrations/slack/workspaces", get(slack_handlers::list_workspaces).post(slack_handlers::link_workspace))
+        .route("/api/integrations/slack/workspaces/:team_id", delete(slack_handlers::unlink_workspace))
+        .route("/api/markdown/highlight.css", get(api_handlers::get_highlight_css))
+        .route("/api/projects/:id/analytics", get(api_handlers::get_project_analytics))
+        .route("/api/projects/:id/dashboard", get(api_handlers::get_project_dashboard))
+        .route("/api/search/tickets", get(api_handlers::search_tickets))
+        .route("/api/projects/:id/coverage", get(api_handlers::get_project_coverage))
+        .route(
+            "/api/projects/:id/path-policy",
+            get(api_handlers::get_project_path_policy).put(api_handlers::set_project_path_policy),
+        )
+        .route(
+            "/api/projects/:id/tool-policy",
+            get(api_handlers::get_project_tool_policy).put(api_handlers::set_project_tool_policy),
+        )
+        .route(
+            "/api/projects/:id/log-persistence",
+            get(api_handlers::get_project_log_persistence).put(api_handlers::set_project_log_persistence),
+        )
+        .route(
+            "/api/projects/:id/budget",
+            get(api_handlers::get_project_budget).put(api_handlers::set_project_budget),
+        )
+        .route(
+            "/api/projects/:id/preflight",
+            get(api_handlers::get_project_preflight).put(api_handlers::set_project_preflight),
+        )
+        .route(
+            "/api/projects/:id/post-run-hooks",
+            get(api_handlers::get_project_post_run_hooks).put(api_handlers::set_project_post_run_hooks),
+        )
+        .route("/api/projects/:id/agent-credentials", get(api_handlers::list_agent_credentials))
+        .route(
+            "/api/projects/:id/agent-credentials/:provider",
+            put(api_handlers::set_agent_credential).delete(api_handlers::delete_agent_credential),
+        )
+        .route(
+            "/api/projects/:id/summary-settings",
+            get(api_handlers::get_project_summary_settings).put(api_handlers::set_project_summary_settings),
+        )
+        .route(
+            "/api/projects/:id/custom-fields",
+            get(api_handlers::list_custom_fields).post(api_handlers::create_custom_field),
+        )
+        .route(
+            "/api/projects/:id/custom-fields/:field_id",
+            put(api_handlers::update_custom_field).delete(api_handlers::delete_custom_field),
+        )
+        .route(
+            "/api/projects/:id/experiments",
+            get(api_handlers::list_experiments).post(api_handlers::create_experiment),
+        )
+        .route("/api/projects/:id/experiments/:experiment_id", get(api_handlers::get_experiment_report))
+        .route("/api/projects/:id/experiments/:experiment_id/stop", post(api_handlers::stop_experiment))
+        .route("/api/analytics/summary", get(api_handlers::get_analytics_summary))
+        .route("/api/analytics/feedback/export", get(api_handlers::export_low_rated_feedback))
+        .route(
+            "/api/question-templates",
+            get(api_handlers::list_question_templates).post(api_handlers::create_question_template),
+        )
+        .route(
+            "/api/question-templates/:id",
+            put(api_handlers::update_question_template).delete(api_handlers::delete_question_template),
+        )
+        .route("/api/question-templates/:id/tickets", post(api_handlers::create_ticket_from_template))
+        .route("/api/alert-rules", get(api_handlers::list_alert_rules).post(api_handlers::create_alert_rule))
+        .route(
+            "/api/alert-rules/:id",
+            put(api_handlers::update_alert_rule).delete(api_handlers::delete_alert_rule),
+        )
+        .route("/api/alert-rules/:id/events", get(api_handlers::list_alert_events))
+        .route("/api/agents", get(api_handlers::list_agents))
+        .route("/api/metrics", get(api_handlers::get_metrics))
+        .route("/api/admin/backups", get(api_handlers::list_backups).post(api_handlers::create_backup))
+        .route("/api/admin/backups/:name", get(api_handlers::download_backup))
+        .route("/api/admin/database/stats", get(api_handlers::get_database_stats))
+        .route("/api/admin/database/integrity-check", post(api_handlers::check_database_integrity))
+        .route("/api/admin/database/vacuum", post(api_handlers::vacuum_database))
+        .route("/api/admin/database/analyze", post(api_handlers::analyze_database))
+        .route("/api/admin/migrations", get(api_handlers::list_migrations))
+        .route(
+            "/api/admin/redaction-patterns",
+            get(api_handlers::list_redaction_patterns).post(api_handlers::create_redaction_pattern),
+        )
+        .route("/api/admin/redaction-patterns/:id", delete(api_handlers::delete_redaction_pattern))
+        .route("/api/admin/log-level", get(api_handlers::get_log_level).put(api_handlers::set_log_level))
+        .route("/api/admin/tasks", get(api_handlers::list_tasks))
+        .layer(Extension(schema))
+        .with_state(app_state)
+}
+
+async fn websocket_handler(
+    ws: WebSocketUpgrade,
+    auth: auth::AuthContext,
+    State(state): State<AppState>,
+) -> Response {
+    ws.on_upgrade(|socket| websocket_handler::handle_websocket(socket, state, auth))
+}
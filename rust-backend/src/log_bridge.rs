@@ -0,0 +1,202 @@
+use crate::message_store::{MsgStore, StructuredLogEntry};
+use crate::tasks::TaskSupervisor;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tracing::warn;
+
+#[cfg(feature = "redis")]
+use tracing::info;
+
+/// Channel every instance publishes to and subscribes on, unless `REDIS_CHANNEL` is set
+const DEFAULT_CHANNEL: &str = "explain-source:logs";
+
+/// Pause before reconnecting after Redis dropped the subscription
+#[cfg(feature = "redis")]
+const RECONNECT_DELAY: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Log entry as published on the channel, tagged with the instance that pushed it
+#[derive(Debug, Serialize, Deserialize)]
+struct Envelope {
+    origin: String,
+    entry: StructuredLogEntry,
+}
+
+/// Redis pub/sub fan-out of agent logs (`REDIS_URL`), so a WebSocket client on any
+/// instance behind the load balancer sees the logs of analyses running on the others
+#[derive(Debug, Clone)]
+pub struct LogBridge {
+    origin: String,
+    channel: String,
+    publish_tx: mpsc::UnboundedSender<String>,
+    #[cfg(feature = "redis")]
+    client: redis::Client,
+}
+
+impl LogBridge {
+    /// None unless `REDIS_URL` is set and the build has the `redis` feature
+    pub fn from_env(tasks: &TaskSupervisor) -> Option<Self> {
+        let url = std::env::var("REDIS_URL").ok().filter(|url| !url.trim().is_empty())?;
+        let channel = std::env::var("REDIS_CHANNEL")
+            .ok()
+            .filter(|channel| !channel.trim().is_empty())
+            .unwrap_or_else(|| DEFAULT_CHANNEL.to_string());
+        Self::connect(url.trim(), channel, tasks)
+    }
+
+    #[cfg(feature = "redis")]
+    fn connect(url: &str, channel: String, tasks: &TaskSupervisor) -> Option<Self> {
+        let client = match redis::Client::open(url) {
+            Ok(client) => client,
+            Err(e) => {
+                warn!("⚠️ REDIS_URL không hợp lệ, log chỉ phát trong instance này: {}", e);
+                return None;
+            }
+        };
+        let (publish_tx, publish_rx) = mpsc::unbounded_channel();
+        tasks.spawn("log-bridge-publisher", publish_loop(client.clone(), channel.clone(), publish_rx));
+        info!("📡 Log được phát qua Redis pub/sub (kênh {})", channel);
+
+        Some(Self {
+            origin: uuid::Uuid::new_v4().to_string(),
+            channel,
+            publish_tx,
+            client,
+        })
+    }
+
+    #[cfg(not(feature = "redis"))]
+    fn connect(_url: &str, _channel: String, _tasks: &TaskSupervisor) -> Option<Self> {
+        warn!("⚠️ REDIS_URL được đặt nhưng bản build không có feature `redis`, bỏ qua");
+        None
+    }
+
+    /// Queue an entry for every other instance (non-blocking)
+    pub fn publish(&self, entry: &StructuredLogEntry) {
+        let envelope = Envelope {
+            origin: self.origin.clone(),
+            entry: entry.clone(),
+        };
+        match serde_json::to_string(&envelope) {
+            Ok(payload) => {
+                let _ = self.publish_tx.send(payload);
+            }
+            Err(e) => warn!("⚠️ Không tuần tự hóa được log {}: {}", entry.id, e),
+        }
+    }
+
+    /// Relay entries published by other instances to this instance's subscribers
+    pub fn spawn_subscriber(&self, store: Arc<MsgStore>, tasks: &TaskSupervisor) {
+        #[cfg(feature = "redis")]
+        tasks.spawn("log-bridge-subscriber", subscribe_loop(
+            self.client.clone(),
+            self.channel.clone(),
+            self.origin.clone(),
+            store,
+        ));
+        #[cfg(not(feature = "redis"))]
+        let _ = (store, &self.channel, tasks);
+    }
+}
+
+/// The entry in `payload`, unless this instance published it or it is malformed
+#[cfg_attr(not(feature = "redis"), allow(dead_code))]
+fn decode(origin: &str, payload: &str) -> Option<StructuredLogEntry> {
+    match serde_json::from_str::<Envelope>(payload) {
+        Ok(envelope) if envelope.origin != origin => Some(envelope.entry),
+        Ok(_) => None,
+        Err(e) => {
+            warn!("⚠️ Bỏ qua log không hợp lệ từ Redis: {}", e);
+            None
+        }
+    }
+}
+
+/// Entries are dropped while Redis is unreachable; they are still stored and reach
+/// clients of other instances through the database on reconnect or reload
+#[cfg(feature = "redis")]
+async fn publish_loop(client: redis::Client, channel: String, mut publish_rx: mpsc::UnboundedReceiver<String>) {
+    use redis::AsyncCommands;
+
+    let mut connection = None;
+    while let Some(payload) = publish_rx.recv().await {
+        if connection.is_none() {
+            match client.get_multiplexed_async_connection().await {
+                Ok(conn) => connection = Some(conn),
+                Err(e) => {
+                    warn!("⚠️ Không kết nối được Redis để phát log: {}", e);
+                    continue;
+                }
+            }
+        }
+        if let Some(conn) = connection.as_mut() {
+            if let Err(e) = conn.publish::<_, _, ()>(&channel, payload).await {
+                warn!("⚠️ Không phát được log qua Redis: {}", e);
+                connection = None;
+            }
+        }
+    }
+}
+
+#[cfg(feature = "redis")]
+async fn subscribe_loop(client: redis::Client, channel: String, origin: String, store: Arc<MsgStore>) {
+    use futures_util::StreamExt;
+
+    loop {
+        match client.get_async_pubsub().await {
+            Ok(mut pubsub) => match pubsub.subscribe(&channel).await {
+                Ok(()) => {
+                    info!("📡 Đã đăng ký kênh Redis {}", channel);
+                    let mut messages = pubsub.on_message();
+                    while let Some(message) = messages.next().await {
+                        let Ok(payload) = message.get_payload::<String>() else {
+                            continue;
+                        };
+                        if let Some(entry) = decode(&origin, &payload) {
+                            store.relay(entry).await;
+                        }
+                    }
+                    warn!("⚠️ Mất kết nối Redis pub/sub, đang kết nối lại");
+                }
+                Err(e) => warn!("⚠️ Không đăng ký được kênh Redis {}: {}", channel, e),
+            },
+            Err(e) => warn!("⚠️ Không kết nối được Redis pub/sub: {}", e),
+        }
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message_store::LogMessageType;
+    use std::collections::HashMap;
+
+    fn entry() -> StructuredLogEntry {
+        StructuredLogEntry {
+            id: "log-1".to_string(),
+            ticket_id: "t1".to_string(),
+            message_type: LogMessageType::Assistant,
+            content: "Reading src/main.rs".to_string(),
+            raw_log: None,
+            metadata: HashMap::new(),
+            timestamp: chrono::Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_decode_skips_own_entries() {
+        let payload = serde_json::to_string(&Envelope {
+            origin: "instance-a".to_string(),
+            entry: entry(),
+        })
+        .unwrap();
+
+        assert!(decode("instance-a", &payload).is_none());
+        let relayed = decode("instance-b", &payload).unwrap();
+        assert_eq!(relayed.id, "log-1");
+        assert_eq!(relayed.content, "Reading src/main.rs");
+
+        assert!(decode("instance-b", "not json").is_none());
+    }
+}
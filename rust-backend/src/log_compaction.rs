@@ -0,0 +1,174 @@
+use crate::message_store::{LogMessageType, StructuredLogEntry};
+use serde_json::Value;
+use std::time::Duration;
+
+/// Folds runs of the same tool call ("Reading file" x 200) into the entry that started the run.
+/// The merged entry keeps its ID and gets `compacted_count`, `compacted_files` (JSON array) and
+/// `compacted_until` metadata.
+#[derive(Debug, Clone)]
+pub struct LogCompactor {
+    /// Longest gap between two calls of a run; None disables compaction
+    window: Option<Duration>,
+    /// Append the merged entries' raw logs to the run's `raw_log`, one per line
+    keep_raw: bool,
+}
+
+impl LogCompactor {
+    pub fn new(window: Option<Duration>, keep_raw: bool) -> Self {
+        Self { window, keep_raw }
+    }
+
+    /// Off unless `LOG_COMPACTION_WINDOW_SECS` is set above 0; `LOG_COMPACTION_KEEP_RAW=false`
+    /// drops the merged entries' raw logs
+    pub fn from_env() -> Self {
+        let window = std::env::var("LOG_COMPACTION_WINDOW_SECS")
+            .ok()
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .filter(|secs| *secs > 0)
+            .map(Duration::from_secs);
+        let keep_raw = std::env::var("LOG_COMPACTION_KEEP_RAW")
+            .map(|v| !matches!(v.trim().to_lowercase().as_str(), "false" | "0" | "no" | "off"))
+            .unwrap_or(true);
+        Self::new(window, keep_raw)
+    }
+
+    /// Merge `entry` into `last` when both are calls of the same tool within the window.
+    /// Returns false, leaving `last` untouched, when `entry` starts a new entry.
+    pub fn merge(&self, last: &mut StructuredLogEntry, entry: &StructuredLogEntry) -> bool {
+        let Some(window) = self.window else {
+            return false;
+        };
+        if !matches!(last.message_type, LogMessageType::ToolUse)
+            || !matches!(entry.message_type, LogMessageType::ToolUse)
+            || last.metadata.get("run_id") != entry.metadata.get("run_id")
+        {
+            return false;
+        }
+        let (Some(tool), Some(last_tool)) = (tool_key(entry), tool_key(last)) else {
+            return false;
+        };
+        if tool != last_tool {
+            return false;
+        }
+        let run_end = last
+            .metadata
+            .get("compacted_until")
+            .and_then(|ts| chrono::DateTime::parse_from_rfc3339(ts).ok())
+            .map(|ts| ts.with_timezone(&chrono::Utc))
+            .unwrap_or(last.timestamp);
+        let gap = (entry.timestamp - run_end).to_std().unwrap_or_default();
+        if gap > window {
+            return false;
+        }
+
+        let count = last
+            .metadata
+            .get("compacted_count")
+            .and_then(|n| n.parse::<u64>().ok())
+            .unwrap_or(1);
+        let mut files: Vec<String> = match last.metadata.get("compacted_files") {
+            Some(files) => serde_json::from_str(files).unwrap_or_default(),
+            None => file_path(last).into_iter().collect(),
+        };
+        if let Some(file) = file_path(entry) {
+            if !files.contains(&file) {
+                files.push(file);
+            }
+        }
+
+        last.metadata.insert("compacted_count".to_string(), (count + 1).to_string());
+        last.metadata.insert(
+            "compacted_files".to_string(),
+            serde_json::to_string(&files).unwrap_or_default(),
+        );
+        last.metadata.insert("compacted_until".to_string(), entry.timestamp.to_rfc3339());
+        if self.keep_raw {
+            if let Some(raw) = &entry.raw_log {
+                last.raw_log = Some(match last.raw_log.take() {
+                    Some(previous) => format!("{}\n{}", previous, raw),
+                    None => raw.clone(),
+                });
+            }
+        }
+        true
+    }
+}
+
+/// What makes two tool calls "the same": the tool name, or for plain-text lines without one
+/// the text before the first colon ("Reading file")
+fn tool_key(entry: &StructuredLogEntry) -> Option<String> {
+    if let Some(tool) = entry.metadata.get("tool_name") {
+        return Some(tool.clone());
+    }
+    if entry.content.starts_with('{') {
+        return None;
+    }
+    entry
+        .content
+        .split_once(':')
+        .map(|(prefix, _)| prefix.trim().to_string())
+        .filter(|prefix| !prefix.is_empty())
+}
+
+/// File the call touched: the normalizer's `file_path`, or the path argument of a JSON tool call
+fn file_path(entry: &StructuredLogEntry) -> Option<String> {
+    if let Some(path) = entry.metadata.get("file_path") {
+        return Some(path.clone());
+    }
+    let json: Value = serde_json::from_str(&entry.content).ok()?;
+    let args = json.get("parameters").or_else(|| json.get("input"))?;
+    ["file_path", "path", "absolute_path"]
+        .iter()
+        .find_map(|key| args.get(key).and_then(Value::as_str))
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log_normalizer::LogNormalizer;
+
+    fn entry(raw: &str, secs: i64) -> StructuredLogEntry {
+        let mut entry = LogNormalizer::new().normalize(raw.to_string(), "t1".to_string());
+        entry.timestamp = chrono::DateTime::from_timestamp(1_700_000_000 + secs, 0).unwrap();
+        entry
+    }
+
+    #[test]
+    fn test_merges_repeated_tool_calls_within_window() {
+        let compactor = LogCompactor::new(Some(Duration::from_secs(5)), true);
+        let mut last = entry("Reading file: src/a.js", 0);
+
+        assert!(compactor.merge(&mut last, &entry("Reading file: src/b.js", 3)));
+        assert!(compactor.merge(&mut last, &entry("Reading file: src/a.js", 7)));
+        assert_eq!(last.metadata["compacted_count"], "3");
+        assert_eq!(last.metadata["compacted_files"], r#"["src/a.js","src/b.js"]"#);
+        assert_eq!(last.raw_log.as_deref().unwrap().lines().count(), 3);
+        assert_eq!(last.content, "Reading file: src/a.js");
+
+        // Too long after the last call, or a different tool, starts a new entry
+        assert!(!compactor.merge(&mut last, &entry("Reading file: src/c.js", 20)));
+        assert!(!compactor.merge(&mut last, &entry("Searching: login", 8)));
+        assert_eq!(last.metadata["compacted_count"], "3");
+    }
+
+    #[test]
+    fn test_json_tool_calls_and_disabled_compaction() {
+        let call = |path: &str, secs| {
+            entry(
+                &format!(r#"{{"type":"tool_use","tool_name":"read_file","parameters":{{"file_path":"{}"}}}}"#, path),
+                secs,
+            )
+        };
+        let compactor = LogCompactor::new(Some(Duration::from_secs(5)), false);
+        let mut last = call("a.rs", 0);
+        let first_raw = last.raw_log.clone();
+        assert!(compactor.merge(&mut last, &call("b.rs", 1)));
+        assert_eq!(last.metadata["compacted_files"], r#"["a.rs","b.rs"]"#);
+        assert_eq!(last.raw_log, first_raw);
+
+        let mut last = call("a.rs", 0);
+        assert!(!LogCompactor::new(None, true).merge(&mut last, &call("b.rs", 1)));
+        assert!(!last.metadata.contains_key("compacted_count"));
+    }
+}
@@ -16,7 +16,7 @@ impl LogNormalizer {
     pub fn new() -> Self {
         Self {
             // Match file paths like "path/to/file.js" or "/absolute/path.ts"
-            file_path_pattern: Regex::new(r#"(?:Reading|Analyzing|Processing|File:)\s+([^\s]+\.[a-zA-Z]{1,4})"#).unwrap(),
+            file_path_pattern: Regex::new(r#"(?:(?:Reading|Analyzing|Processing)(?:\s+file)?:?|File:)\s+([^\s]+\.[a-zA-Z]{1,4})"#).unwrap(),
 
             // Match error codes and severity levels
             error_pattern: Regex::new(r#"(ERROR|WARN|WARNING|CRITICAL|FATAL)(?::\s*)?(.*)?"#).unwrap(),
@@ -182,7 +182,7 @@ impl LogNormalizer {
                         metadata.insert("file_path".to_string(), file_path.as_str().to_string());
 
                         // Extract file extension
-                        if let Some(ext) = file_path.as_str().split('.').last() {
+                        if let Some(ext) = file_path.as_str().rsplit('.').next() {
                             metadata.insert("file_extension".to_string(), ext.to_string());
                         }
                     }
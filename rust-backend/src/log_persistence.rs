@@ -0,0 +1,99 @@
+use crate::message_store::{LogMessageType, StructuredLogEntry};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+/// Which log entries are written to `structured_logs`. Live broadcasts and the in-memory
+/// buffer always get every entry; this only trims what is kept for later.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LogPersistence {
+    /// Types written; None writes all of them
+    #[serde(default)]
+    pub message_types: Option<Vec<LogMessageType>>,
+    /// Drop `raw_log` from every stored entry but errors
+    #[serde(default)]
+    pub raw_log_errors_only: bool,
+}
+
+impl LogPersistence {
+    /// `LOG_PERSIST_TYPES` (comma-separated, e.g. `assistant,tool_use,error,result`; unset or
+    /// `all` keeps every type) and `LOG_PERSIST_RAW_ERRORS_ONLY`
+    pub fn from_env() -> Self {
+        let message_types = std::env::var("LOG_PERSIST_TYPES")
+            .ok()
+            .map(|v| v.trim().to_lowercase())
+            .filter(|v| !v.is_empty() && v != "all")
+            .map(|v| {
+                v.split(',')
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .filter_map(|name| match parse_type(name) {
+                        Some(message_type) => Some(message_type),
+                        None => {
+                            warn!("⚠️ LOG_PERSIST_TYPES: bỏ qua loại log không hợp lệ '{}'", name);
+                            None
+                        }
+                    })
+                    .collect()
+            });
+        let raw_log_errors_only = std::env::var("LOG_PERSIST_RAW_ERRORS_ONLY")
+            .map(|v| matches!(v.trim().to_lowercase().as_str(), "true" | "1" | "yes" | "on"))
+            .unwrap_or(false);
+        Self {
+            message_types,
+            raw_log_errors_only,
+        }
+    }
+
+    /// The entry as it is stored; None when it is not stored at all
+    pub fn apply(&self, entry: &StructuredLogEntry) -> Option<StructuredLogEntry> {
+        if let Some(types) = &self.message_types {
+            if !types.contains(&entry.message_type) {
+                return None;
+            }
+        }
+        let mut stored = entry.clone();
+        if self.raw_log_errors_only && stored.message_type != LogMessageType::Error {
+            stored.raw_log = None;
+        }
+        Some(stored)
+    }
+}
+
+fn parse_type(name: &str) -> Option<LogMessageType> {
+    serde_json::from_value(serde_json::Value::String(name.to_string())).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn entry(message_type: LogMessageType) -> StructuredLogEntry {
+        StructuredLogEntry {
+            id: "l1".to_string(),
+            ticket_id: "t1".to_string(),
+            message_type,
+            content: "content".to_string(),
+            raw_log: Some("raw".to_string()),
+            metadata: HashMap::new(),
+            timestamp: chrono::Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_apply() {
+        let all = LogPersistence::default();
+        assert_eq!(all.apply(&entry(LogMessageType::System)).unwrap().raw_log.as_deref(), Some("raw"));
+
+        let trimmed = LogPersistence {
+            message_types: Some(vec![LogMessageType::Assistant, LogMessageType::Error]),
+            raw_log_errors_only: true,
+        };
+        assert!(trimmed.apply(&entry(LogMessageType::System)).is_none());
+        assert_eq!(trimmed.apply(&entry(LogMessageType::Assistant)).unwrap().raw_log, None);
+        assert_eq!(trimmed.apply(&entry(LogMessageType::Error)).unwrap().raw_log.as_deref(), Some("raw"));
+
+        assert_eq!(parse_type("tool_use"), Some(LogMessageType::ToolUse));
+        assert_eq!(parse_type("debug"), None);
+    }
+}
@@ -0,0 +1,142 @@
+//! Replays a finished session's stored logs as if it were running, for working on the UI without
+//! an agent: `GET /api/sessions/:id/replay-stream?speed=1` answers with a server-sent event
+//! stream a browser can open with `EventSource`, the `replay-session` WebSocket message streams
+//! into the requesting connection. Frames are the live ones with `"replay": true` and the
+//! session's ID added:
+//!
+//! ```json
+//! {"message_type": "structured-log", "replay": true, "session_id": "...", "log": {...}}
+//! {"message_type": "replay-complete", "replay": true, "session_id": "...", "count": 42}
+//! ```
+//!
+//! Entries keep their original gaps, divided by `speed`; nothing is stored or broadcast.
+
+use crate::database::{AnalysisSession, Database};
+use crate::message_store::StructuredLogEntry;
+use crate::timeline;
+use crate::ws_stream;
+use anyhow::Result;
+use futures_util::Stream;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+pub const MAX_SPEED: f64 = 100.0;
+
+/// Longest wait between two entries, so a long tool call doesn't stall the replay
+const MAX_GAP: Duration = Duration::from_secs(10);
+
+pub struct Replay {
+    session_id: String,
+    entries: Vec<StructuredLogEntry>,
+    /// 1.0 replays at the original pace, 0 without waiting
+    speed: f64,
+}
+
+impl Replay {
+    /// The logs of `session`; `speed` defaults to 1.0 and is capped at `MAX_SPEED`
+    pub async fn load(database: &Database, session: &AnalysisSession, speed: Option<f64>) -> Result<Self> {
+        let entries = timeline::run_logs(database, &session.ticket_id, Some(session)).await?;
+        Ok(Self::new(&session.id, entries, speed))
+    }
+
+    fn new(session_id: &str, entries: Vec<StructuredLogEntry>, speed: Option<f64>) -> Self {
+        Self {
+            session_id: session_id.to_string(),
+            entries,
+            speed: speed.filter(|speed| speed.is_finite()).unwrap_or(1.0).clamp(0.0, MAX_SPEED),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Wait before the entry at `index`
+    fn gap(&self, index: usize) -> Duration {
+        if index == 0 || self.speed == 0.0 {
+            return Duration::ZERO;
+        }
+        let gap = (self.entries[index].timestamp - self.entries[index - 1].timestamp)
+            .to_std()
+            .unwrap_or_default();
+        gap.min(MAX_GAP).div_f64(self.speed)
+    }
+
+    fn log_message(&self, entry: &StructuredLogEntry) -> Value {
+        let mut message = ws_stream::single_log_message(entry);
+        message["replay"] = json!(true);
+        message["session_id"] = json!(self.session_id);
+        message
+    }
+
+    fn complete_message(&self) -> Value {
+        json!({
+            "message_type": "replay-complete",
+            "replay": true,
+            "session_id": self.session_id,
+            "count": self.entries.len(),
+        })
+    }
+
+    /// The frames, paced; the last one is `replay-complete`
+    pub fn frames(self) -> impl Stream<Item = Value> + Send {
+        let gaps: VecDeque<Duration> = (0..self.entries.len()).map(|index| self.gap(index)).collect();
+        let mut frames: VecDeque<Value> = self.entries.iter().map(|entry| self.log_message(entry)).collect();
+        frames.push_back(self.complete_message());
+
+        futures_util::stream::unfold((frames, gaps), |(mut frames, mut gaps)| async move {
+            let frame = frames.pop_front()?;
+            if let Some(gap) = gaps.pop_front() {
+                tokio::time::sleep(gap).await;
+            }
+            Some((frame, (frames, gaps)))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message_store::LogMessageType;
+    use futures_util::StreamExt;
+    use std::collections::HashMap;
+
+    fn entry(id: &str, ms: i64) -> StructuredLogEntry {
+        StructuredLogEntry {
+            id: id.to_string(),
+            ticket_id: "t1".to_string(),
+            message_type: LogMessageType::System,
+            content: id.to_string(),
+            raw_log: None,
+            metadata: HashMap::new(),
+            timestamp: chrono::DateTime::from_timestamp_millis(1_800_000_000_000 + ms).unwrap(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_frames_keep_pace() {
+        let entries = vec![entry("a", 0), entry("b", 2_000), entry("c", 600_000)];
+        let replay = Replay::new("s1", entries.clone(), Some(20.0));
+        assert_eq!(replay.gap(1), Duration::from_millis(100));
+        // Capped, then sped up
+        assert_eq!(replay.gap(2), Duration::from_millis(500));
+
+        let started = tokio::time::Instant::now();
+        let frames: Vec<Value> = replay.frames().collect().await;
+        assert!(started.elapsed() >= Duration::from_millis(600));
+        assert_eq!(frames.len(), 4);
+        assert!(frames.iter().all(|frame| frame["replay"] == true && frame["session_id"] == "s1"));
+        assert_eq!(frames[1]["log"]["id"], "b");
+        assert_eq!(frames[3]["message_type"], "replay-complete");
+        assert_eq!(frames[3]["count"], 3);
+
+        let instant = Replay::new("s1", entries, Some(0.0));
+        assert_eq!(instant.gap(2), Duration::ZERO);
+        assert_eq!(instant.frames().count().await, 4);
+    }
+}
@@ -0,0 +1,114 @@
+use axum::http::Request;
+use std::sync::{Arc, RwLock};
+use tracing::{info_span, Span};
+use tracing_subscriber::{
+    fmt, layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter, Layer, Registry,
+};
+
+/// Filter used when `RUST_LOG` is unset or invalid
+const DEFAULT_DIRECTIVES: &str = "info";
+
+/// Handle to the global `EnvFilter`, so directives can be changed without a restart
+#[derive(Clone)]
+pub struct LogLevelHandle {
+    handle: reload::Handle<EnvFilter, Registry>,
+    // EnvFilter's Display doesn't round-trip every directive, so keep the source string
+    directives: Arc<RwLock<String>>,
+}
+
+impl LogLevelHandle {
+    /// Directives currently in effect (RUST_LOG syntax)
+    pub fn current(&self) -> String {
+        self.directives.read().map(|d| d.clone()).unwrap_or_default()
+    }
+
+    /// Replace the active filter, e.g. `info,qa_chatbot_backend::claude_agent=debug`
+    pub fn set(&self, directives: &str) -> Result<(), String> {
+        let filter = parse_directives(directives)?;
+        self.handle.reload(filter).map_err(|e| e.to_string())?;
+
+        if let Ok(mut current) = self.directives.write() {
+            *current = directives.to_string();
+        }
+        Ok(())
+    }
+}
+
+fn parse_directives(directives: &str) -> Result<EnvFilter, String> {
+    if directives.trim().is_empty() {
+        return Err("directives must not be empty".to_string());
+    }
+    EnvFilter::try_new(directives).map_err(|e| e.to_string())
+}
+
+/// Install the global subscriber
+///
+/// - `RUST_LOG`: initial filter directives (default `info`)
+/// - `LOG_FORMAT=json`: one JSON object per line for log aggregators, otherwise human-readable text
+pub fn init() -> LogLevelHandle {
+    let directives = std::env::var("RUST_LOG")
+        .ok()
+        .filter(|d| parse_directives(d).is_ok())
+        .unwrap_or_else(|| DEFAULT_DIRECTIVES.to_string());
+    let filter = parse_directives(&directives).unwrap_or_else(|_| EnvFilter::new(DEFAULT_DIRECTIVES));
+    let (filter_layer, handle) = reload::Layer::new(filter);
+
+    let json_output = std::env::var("LOG_FORMAT")
+        .map(|f| f.eq_ignore_ascii_case("json"))
+        .unwrap_or(false);
+    let fmt_layer = if json_output {
+        fmt::layer().json().with_current_span(true).boxed()
+    } else {
+        fmt::layer().boxed()
+    };
+
+    tracing_subscriber::registry()
+        .with(filter_layer)
+        .with(fmt_layer)
+        .init();
+
+    LogLevelHandle {
+        handle,
+        directives: Arc::new(RwLock::new(directives)),
+    }
+}
+
+/// Handle on a filter no subscriber uses, for states built without `init` (tests); `set` fails
+#[cfg(any(test, feature = "test-support"))]
+pub fn detached() -> LogLevelHandle {
+    let (_layer, handle) = reload::Layer::<EnvFilter, Registry>::new(EnvFilter::new(DEFAULT_DIRECTIVES));
+    LogLevelHandle {
+        handle,
+        directives: Arc::new(RwLock::new(DEFAULT_DIRECTIVES.to_string())),
+    }
+}
+
+/// Span for one HTTP request, carrying the `x-request-id` set by `SetRequestIdLayer`
+pub fn http_span<B>(request: &Request<B>) -> Span {
+    let request_id = request
+        .headers()
+        .get("x-request-id")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("-");
+
+    info_span!(
+        "http_request",
+        method = %request.method(),
+        // Not the query: it may hold a `?token=`
+        path = %request.uri().path(),
+        request_id = %request_id,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_directives() {
+        assert!(parse_directives("info").is_ok());
+        assert!(parse_directives("warn,qa_chatbot_backend::gemini_agent=trace").is_ok());
+        assert!(parse_directives("").is_err());
+        assert!(parse_directives("qa_chatbot_backend=notalevel").is_err());
+    }
+}
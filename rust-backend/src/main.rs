@@ -1,49 +1,24 @@
-use axum::{
-    extract::{ws::WebSocketUpgrade, State},
-    response::Response,
-    routing::{get, put, post},
-    Router,
-};
-use serde::{Deserialize, Serialize};
+use axum::routing::get;
 use std::{collections::HashMap, net::SocketAddr, sync::Arc};
-use tokio::{sync::{broadcast, Mutex}, task::AbortHandle};
-use tower_http::cors::CorsLayer;
-use tracing::{info, warn};
-
-mod agent_factory;
-mod api_handlers;
-mod claude_agent;
-mod code_agent;
-mod cursor_agent;
-mod database;
-mod gemini_agent;
-mod log_normalizer;
-mod message_store;
-mod websocket_handler;
-
-use code_agent::CodeAgent;
-use database::Database;
-use message_store::MsgStore;
-
-#[derive(Clone)]
-pub struct AppState {
-    pub code_agent: Arc<dyn CodeAgent>,
-    pub broadcast_tx: broadcast::Sender<BroadcastMessage>,
-    pub database: Arc<Database>,
-    pub msg_store: Arc<MsgStore>,
-    pub running_tasks: Arc<Mutex<HashMap<String, AbortHandle>>>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct BroadcastMessage {
-    pub ticket_id: String,
-    pub message_type: String,
-    pub content: String,
-    pub timestamp: chrono::DateTime<chrono::Utc>,
-}
+use tokio::sync::{broadcast, Mutex};
+use tower_http::{
+    cors::CorsLayer,
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+    trace::TraceLayer,
+};
+use tracing::{error, info, warn};
 
-// Re-export for backward compatibility
-pub use code_agent::{CodeAnalysisRequest, CodeAnalysisResponse};
+#[cfg(feature = "grpc")]
+use qa_chatbot_backend::grpc_service;
+use qa_chatbot_backend::agent_factory::AgentRegistry;
+use qa_chatbot_backend::database::{self, Database};
+use qa_chatbot_backend::message_store::MsgStore;
+use qa_chatbot_backend::{
+    agent_credentials, alerts, auth, backup, blob_store, bootstrap, config, context_resolver, db_maintenance, diagram, http_limits,
+    idempotency, job_queue, ldap, log_bridge, logging, notifications, oidc, output_validation, preflight, presence, project_roots,
+    prompt, slack, stale, static_files, summary, tasks, ticket_title, time_tracking, tls, trash, worker, ws_limits,
+    AppState,
+};
 
 #[tokio::main]
 async fn main() {
@@ -88,23 +63,62 @@ async fn main() {
         }
     }
 
-    // Initialize tracing
-    tracing_subscriber::fmt::init();
+    // Initialize tracing (RUST_LOG filter, reloadable via /api/admin/log-level)
+    let log_level = logging::init();
 
     info!("🚀 Khởi động QA Chatbot Backend...");
 
-    // Initialize database
-    let database_url = std::env::var("DATABASE_URL")
-        .unwrap_or_else(|_| "sqlite:qa_chatbot.db".to_string());
+    // `--role api|worker|all`: split the API from the processes running agents
+    let role = match worker::Role::from_args(std::env::args()) {
+        Ok(role) => role,
+        Err(e) => {
+            error!("❌ {}", e);
+            std::process::exit(1);
+        }
+    };
+    info!("🧩 Role: {}", role.as_str());
+
+    let server_config = match config::ServerConfig::from_env() {
+        Ok(server_config) => server_config,
+        Err(e) => {
+            error!("❌ Cấu hình server không hợp lệ: {:#}", e);
+            std::process::exit(1);
+        }
+    };
 
-    info!("📊 Kết nối database: {}", database_url);
+    // Initialize database
+    let database_config = config::DatabaseConfig::from_env();
 
-    let database = Arc::new(
-        Database::new(&database_url)
-            .await
-            .expect("Failed to connect to database"),
+    info!(
+        "📊 Kết nối database: {} (max {} connections, acquire timeout {}s)",
+        database_config.url, database_config.max_connections, database_config.acquire_timeout_secs
     );
 
+    // Oversized results and raw logs are kept outside SQLite
+    let blobs = match blob_store::Blobs::from_env() {
+        Ok(blobs) => blobs,
+        Err(e) => {
+            error!("❌ Cấu hình blob store không hợp lệ: {:#}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let database = match Database::connect(&database_config).await {
+        Ok(database) => Arc::new(database.with_blobs(blobs)),
+        Err(e) => {
+            error!("❌ Không kết nối được database {}: {:#}", database_config.url, e);
+            for hint in database::diagnose_connection(&database_config) {
+                error!("   ↳ {}", hint);
+            }
+            std::process::exit(1);
+        }
+    };
+
+    // `--migration-status`: list applied/pending migrations and exit without touching the schema
+    if std::env::args().any(|arg| arg == "--migration-status") {
+        std::process::exit(print_migration_status(&database).await);
+    }
+
     // Initialize database schema
     database
         .init_schema()
@@ -117,51 +131,244 @@ async fn main() {
         .await
         .expect("Failed to run database migrations");
 
+    if let Err(e) = database.verify_schema().await {
+        error!("❌ {:#}", e);
+        error!("   ↳ Kiểm tra `--migration-status`: database có thể được tạo bởi một bản build khác");
+        std::process::exit(1);
+    }
+
     info!("✅ Database schema initialized and migrations applied");
 
     info!("📊 Database persistence enabled - keeping existing data");
 
+    // Background tasks are spawned through the supervisor so they can be listed
+    let tasks = tasks::TaskSupervisor::default();
+
     // Initialize message store
-    let msg_store = Arc::new(MsgStore::new(database.clone()));
+    let log_bridge = log_bridge::LogBridge::from_env(&tasks);
+    let msg_store = Arc::new(MsgStore::new(database.clone(), &tasks).with_bridge(log_bridge.clone()));
+    if let Some(bridge) = &log_bridge {
+        bridge.spawn_subscriber(msg_store.clone(), &tasks);
+    }
+    if let Err(e) = msg_store.redactor().reload(&database).await {
+        warn!("⚠️ Không nạp được redaction pattern tùy chỉnh: {}", e);
+    }
 
     info!("✅ Message store initialized");
 
     // Initialize broadcast channel for legacy messages
     let (broadcast_tx, _broadcast_rx) = broadcast::channel(1000);
 
-    // Initialize code analysis agent from environment
-    let code_agent = agent_factory::create_agent_from_env();
+    // Initialize code analysis agents (default from AGENT_TYPE, others on demand)
+    let agents = Arc::new(AgentRegistry::from_env());
 
     info!("✅ Code analysis agent initialized");
 
+    // Organizations and accounts; without AUTH_REQUIRED, anonymous requests use the default org
+    let auth_config = Arc::new(auth::AuthConfig::from_env());
+    if let Err(e) = auth::bootstrap_admin(&database).await {
+        warn!("⚠️ Không tạo được admin ban đầu: {}", e);
+    }
+    info!("🔐 Auth required: {}", auth_config.required);
+
+    // Scheduled database backups
+    let backups = match backup::BackupConfig::from_env() {
+        Ok(backups) => Arc::new(backups),
+        Err(e) => {
+            error!("❌ Cấu hình object storage không hợp lệ: {:#}", e);
+            std::process::exit(1);
+        }
+    };
+    // Scheduled jobs run on the API processes only, so workers don't repeat them
+    if role.serves_api() {
+        backup::spawn_scheduler(database.clone(), backups.clone(), &tasks);
+        db_maintenance::spawn_scheduler(database.clone(), db_maintenance::MaintenanceConfig::from_env(), &tasks);
+    }
+
+    // Exports handed out as links live in the shared bucket, so any replica can serve them
+    let exports = blob_store::object_store_from_env("exports/").unwrap_or_else(|e| {
+        error!("❌ Cấu hình object storage không hợp lệ: {:#}", e);
+        std::process::exit(1);
+    });
+    if exports.is_some() {
+        info!("🪣 Backups and export links use S3 object storage");
+    }
+
+    // Completion emails to whoever started an analysis
+    let notifier = match notifications::EmailConfig::from_env().map(notifications::Notifier::new) {
+        Some(Ok(notifier)) => {
+            info!("📧 Email notifications via {}", notifier.config().smtp_host);
+            let notifier = Arc::new(notifier);
+            if role.serves_api() {
+                notifications::spawn_digest(notifier.clone(), database.clone(), &tasks);
+            }
+            Some(notifier)
+        }
+        Some(Err(e)) => {
+            warn!("⚠️ Cấu hình SMTP không hợp lệ, tắt email thông báo: {}", e);
+            None
+        }
+        None => None,
+    };
+
+    // Slack app: `/explain <project> <question>` and results posted back to the channel
+    let slack = slack::SlackConfig::from_env().map(|config| Arc::new(slack::SlackClient::new(config)));
+    if slack.is_some() {
+        info!("💬 Slack integration enabled");
+    }
+
+    let oidc = oidc::OidcConfig::from_env().and_then(|config| match oidc::OidcClient::new(config) {
+        Ok(client) => Some(Arc::new(client)),
+        Err(e) => {
+            error!("❌ Failed to set up SSO: {}", e);
+            None
+        }
+    });
+    if let Some(oidc) = &oidc {
+        info!("🔐 SSO login enabled via {}", oidc.config().issuer_url);
+    }
+
+    let ldap = ldap::LdapConfig::from_env().map(|config| Arc::new(ldap::LdapAuthenticator::new(config)));
+    if let Some(ldap) = &ldap {
+        info!("🔐 LDAP login enabled via {}", ldap.config().url);
+    }
+
+    let credentials = match agent_credentials::CredentialCipher::from_env() {
+        Some(Ok(cipher)) => {
+            info!("🔑 Per-project agent API keys enabled");
+            Some(Arc::new(cipher))
+        }
+        Some(Err(e)) => {
+            error!("❌ Failed to load agent credentials key, per-project API keys disabled: {}", e);
+            None
+        }
+        None => None,
+    };
+
     // Create app state
     let app_state = AppState {
-        code_agent,
+        agents,
         broadcast_tx,
         database,
         msg_store,
+        tasks,
         running_tasks: Arc::new(Mutex::new(HashMap::new())),
+        job_queue: Arc::new(Mutex::new(job_queue::JobQueue::new(job_queue::QueueConfig::from_env()))),
+        log_level,
+        backups,
+        auth: auth_config,
+        notifier,
+        slack,
+        oidc,
+        ldap,
+        credentials,
+        summarizer: Arc::new(summary::Summarizer::new(summary::SummaryConfig::from_env())),
+        mermaid: diagram::MermaidRenderer::from_env(),
+        bootstrap: bootstrap::BootstrapConfig::from_env(),
+        prompt_limits: prompt::PromptLimits::from_env(),
+        context_resolver: context_resolver::ResolverConfig::from_env(),
+        idempotency: idempotency::IdempotencyConfig::from_env(),
+        trash: trash::TrashConfig::from_env(),
+        exports,
+        ws_limits: ws_limits::WsLimits::from_env(),
+        preflight: preflight::PreflightConfig::from_env(),
+        project_roots: project_roots::ProjectRoots::from_env(),
+        output_validation: output_validation::OutputValidationConfig::from_env(),
+        alerts: Arc::new(alerts::AlertEngine::default()),
+        sla: time_tracking::SlaConfig::from_env(),
+        ticket_titles: ticket_title::TitleConfig::from_env(),
+        presence: Arc::new(presence::Presence::default()),
+        role,
+        worker: worker::WorkerConfig::from_env(),
     };
 
     info!("✅ App state initialized");
 
-    // Build router
-    let app = Router::new()
-        .route("/", get(health_check))
-        .route("/ws", get(websocket_handler))
-        .route("/api/projects", get(api_handlers::list_projects).post(api_handlers::create_project))
-        .route("/api/projects/:id", get(api_handlers::get_project).put(api_handlers::update_project).delete(api_handlers::delete_project))
-        .route("/api/projects/:project_id/tickets", get(api_handlers::list_tickets).post(api_handlers::create_ticket))
-        .route("/api/tickets/:id/stop-analysis", post(api_handlers::stop_analysis))
-        .route("/api/tickets/:id/status", put(api_handlers::update_ticket_status))
-        .route("/api/tickets/:id/logs", get(api_handlers::get_ticket_logs))
-        .layer(CorsLayer::permissive())
-        .with_state(app_state);
+    match role {
+        // Workers only run analyses and stream their logs back through the database
+        worker::Role::Worker => {
+            worker::spawn_forwarder(&app_state);
+            worker::run(app_state).await;
+            return;
+        }
+        worker::Role::Api => worker::spawn_relay(&app_state),
+        worker::Role::All => {}
+    }
+
+    // Flag tickets whose analyzed files changed since, optionally re-running them
+    stale::spawn_checker(app_state.clone(), stale::StaleConfig::from_env());
+
+    // Alert rules are evaluated on the logs this instance sees, its own runs' and relayed ones
+    alerts::spawn(app_state.clone());
+
+    // Deleted projects are only purged for good once their retention has passed
+    trash::spawn_purger(app_state.database.clone(), app_state.trash, &app_state.tasks);
+
+    // Optional gRPC server sharing the same state, on its own port
+    #[cfg(feature = "grpc")]
+    {
+        let grpc_port = std::env::var("GRPC_PORT")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(50051);
+        let grpc_addr = SocketAddr::from(([0, 0, 0, 0], grpc_port));
+        app_state.tasks.spawn("grpc-server", grpc_service::serve(app_state.clone(), grpc_addr));
+    }
+
+    // Build router; the supervisor stays reachable for listeners started with the server
+    let tasks = app_state.tasks.clone();
+    let mut app = qa_chatbot_backend::router(app_state);
+
+    // Serve the frontend bundle from the same binary when configured,
+    // otherwise keep the plain-text health check on `/`
+    app = match &server_config.frontend_dist_dir {
+        Some(dist_dir) => {
+            if !dist_dir.join("index.html").is_file() {
+                warn!("⚠️ FRONTEND_DIST_DIR {} không chứa index.html", dist_dir.display());
+            }
+            info!("🖥️ Serving frontend từ {}", dist_dir.display());
+            app.fallback_service(static_files::frontend_router(
+                dist_dir,
+                server_config.static_max_age_seconds,
+            ))
+        }
+        None => app.route("/", get(health_check)),
+    };
+
+    // 503 when overloaded, 408 when slow, 413 for oversized bodies, instead of piling up
+    let http_limits_config = config::HttpLimitsConfig::from_env();
+    info!(
+        "🚦 HTTP limits: {} request đồng thời, timeout {}s, body {} bytes",
+        http_limits_config.max_concurrent_requests,
+        http_limits_config.request_timeout_secs,
+        http_limits_config.max_body_bytes
+    );
+    let app = http_limits::apply(app, http_limits_config);
+
+    // Every HTTP response carries an x-request-id (client-supplied or generated)
+    let app = app
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(TraceLayer::new_for_http().make_span_with(logging::http_span))
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+        .layer(CorsLayer::permissive());
 
     // Start server
-    let addr = SocketAddr::from(([0, 0, 0, 0], 9000));
+    let addr = SocketAddr::from(([0, 0, 0, 0], server_config.port));
+
+    if let Some(tls_config) = &server_config.tls {
+        info!("🔒 Server đang chạy trên https://{}", addr);
+        info!("📡 WebSocket endpoint: wss://{}/ws", addr);
+        info!("🔎 GraphQL endpoint: https://{}/graphql (subscriptions: wss://{}/graphql/ws)", addr, addr);
+
+        tls::serve_tls(app, addr, tls_config, &tasks)
+            .await
+            .expect("Failed to start TLS server");
+        return;
+    }
+
     info!("🌐 Server đang chạy trên {}", addr);
     info!("📡 WebSocket endpoint: ws://{}/ws", addr);
+    info!("🔎 GraphQL endpoint: http://{}/graphql (subscriptions: ws://{}/graphql/ws)", addr, addr);
 
     let listener = tokio::net::TcpListener::bind(&addr)
         .await
@@ -174,10 +381,29 @@ async fn main() {
         .expect("Failed to start server");
 }
 
-async fn health_check() -> &'static str {
-    "✅ QA Chatbot Backend đang hoạt động!"
+/// Print one line per migration; exit code 0 when all are applied, 1 otherwise
+async fn print_migration_status(database: &Database) -> i32 {
+    let statuses = match database.migration_status().await {
+        Ok(statuses) => statuses,
+        Err(e) => {
+            error!("❌ Không đọc được trạng thái migration: {:#}", e);
+            return 1;
+        }
+    };
+
+    for status in &statuses {
+        println!(
+            "{:03}  {:<17} {:<20} {}",
+            status.version,
+            status.state.as_str(),
+            status.installed_on.as_deref().unwrap_or("-"),
+            status.description
+        );
+    }
+    let all_applied = statuses.iter().all(|s| s.state == database::MigrationState::Applied);
+    if all_applied { 0 } else { 1 }
 }
 
-async fn websocket_handler(ws: WebSocketUpgrade, State(state): State<AppState>) -> Response {
-    ws.on_upgrade(|socket| websocket_handler::handle_websocket(socket, state))
+async fn health_check() -> &'static str {
+    "✅ QA Chatbot Backend đang hoạt động!"
 }
@@ -0,0 +1,131 @@
+use pulldown_cmark::{html, CodeBlockKind, Event, Options, Parser, Tag, TagEnd};
+use std::sync::OnceLock;
+use syntect::highlighting::ThemeSet;
+use syntect::html::{css_for_theme_with_class_style, ClassStyle, ClassedHTMLGenerator};
+use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+
+/// Highlighted tokens are emitted as `hl-*` classes; `highlight_css` styles them
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+const HIGHLIGHT_THEME: &str = "InspiredGitHub";
+
+fn syntax_set() -> &'static SyntaxSet {
+    static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+/// Render agent markdown to HTML that is safe to inject into the page: raw HTML in the
+/// markdown goes through the same allowlist as everything else, so scripts, event
+/// handlers and `javascript:` links are dropped. Fenced code is syntax highlighted and
+/// ```mermaid blocks become `<pre class="mermaid">` for mermaid.js to pick up.
+pub fn render_markdown(markdown: &str) -> String {
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+
+    let mut events = Vec::new();
+    // (language, source) of the fenced block being collected
+    let mut code_block: Option<(String, String)> = None;
+
+    for event in Parser::new_ext(markdown, options) {
+        match event {
+            Event::Start(Tag::CodeBlock(kind)) => {
+                let language = match kind {
+                    CodeBlockKind::Fenced(info) => info.split_whitespace().next().unwrap_or("").to_string(),
+                    CodeBlockKind::Indented => String::new(),
+                };
+                code_block = Some((language, String::new()));
+            }
+            Event::Text(text) if code_block.is_some() => {
+                if let Some((_, source)) = code_block.as_mut() {
+                    source.push_str(&text);
+                }
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                if let Some((language, source)) = code_block.take() {
+                    events.push(Event::Html(code_block_html(&language, &source).into()));
+                }
+            }
+            event => events.push(event),
+        }
+    }
+
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, events.into_iter());
+
+    ammonia::Builder::default()
+        .add_tag_attributes("pre", &["class"])
+        .add_tag_attributes("code", &["class"])
+        .add_tag_attributes("span", &["class"])
+        .clean(&unsafe_html)
+        .to_string()
+}
+
+/// Stylesheet for the `hl-*` classes produced by `render_markdown`
+pub fn highlight_css() -> String {
+    let themes = ThemeSet::load_defaults();
+    css_for_theme_with_class_style(&themes.themes[HIGHLIGHT_THEME], CLASS_STYLE).unwrap_or_default()
+}
+
+fn code_block_html(language: &str, source: &str) -> String {
+    if language.eq_ignore_ascii_case("mermaid") {
+        return format!("<pre class=\"mermaid\">{}</pre>", escape_html(source));
+    }
+
+    let syntaxes = syntax_set();
+    let plain = || format!("<pre><code>{}</code></pre>", escape_html(source));
+    let Some(syntax) = syntaxes.find_syntax_by_token(language).filter(|_| !language.is_empty()) else {
+        return plain();
+    };
+
+    let mut generator = ClassedHTMLGenerator::new_with_class_style(syntax, syntaxes, CLASS_STYLE);
+    for line in LinesWithEndings::from(source) {
+        if generator.parse_html_for_line_which_includes_newline(line).is_err() {
+            return plain();
+        }
+    }
+
+    format!(
+        "<pre class=\"hl-code\"><code class=\"language-{}\">{}</code></pre>",
+        escape_html(language),
+        generator.finalize()
+    )
+}
+
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_strips_unsafe_html() {
+        let html = render_markdown(
+            "# Result\n\n<script>alert(1)</script>\n\n<img src=x onerror=\"alert(2)\">\n\n[link](javascript:alert(3))",
+        );
+        assert!(html.contains("<h1>Result</h1>"));
+        assert!(!html.contains("<script"));
+        assert!(!html.contains("onerror"));
+        assert!(!html.contains("javascript:"));
+    }
+
+    #[test]
+    fn test_render_code_blocks() {
+        let html = render_markdown("```rust\nfn main() {}\n```\n\n```mermaid\ngraph TD\n  A-->B\n```\n");
+        assert!(html.contains("<code class=\"language-rust\">"));
+        assert!(html.contains("<span class=\"hl-"));
+        assert!(html.contains("<pre class=\"mermaid\">graph TD\n  A--&gt;B\n</pre>"));
+        assert!(highlight_css().contains(".hl-"));
+    }
+}
@@ -1,12 +1,20 @@
 use crate::database::{Database, StructuredLogRecord};
+use crate::interaction::AgentInputs;
+use crate::log_bridge::LogBridge;
+use crate::log_compaction::LogCompactor;
+use crate::log_persistence::LogPersistence;
+use crate::redaction::Redactor;
+use crate::tasks::TaskSupervisor;
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::{broadcast, mpsc, Mutex};
 use tracing::error;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum LogMessageType {
     ToolUse,
@@ -57,6 +65,7 @@ impl StructuredLogEntry {
             message_type: self.message_type.as_str().to_string(),
             content: self.content.clone(),
             raw_log: self.raw_log.clone(),
+            raw_log_blob: None,
             metadata: if self.metadata.is_empty() {
                 None
             } else {
@@ -104,50 +113,84 @@ pub struct MsgStore {
 
     // Queue for batch database inserts
     db_queue_tx: mpsc::UnboundedSender<StructuredLogEntry>,
+
+    // Entries queued or batched but not yet written to the database
+    pending_writes: Arc<AtomicUsize>,
+
+    // Run ID of the analysis currently running per ticket (ticket_id -> run_id)
+    active_runs: Arc<Mutex<HashMap<String, String>>>,
+
+    // Which entries are written to the database (LOG_PERSIST_*), and the project's own
+    // policy per ticket with a running analysis
+    persistence: LogPersistence,
+    run_persistence: Arc<Mutex<HashMap<String, LogPersistence>>>,
+
+    // Secret masking applied to every entry before it is buffered, stored or broadcast
+    redactor: Arc<Redactor>,
+
+    // Folds repeated tool calls into one entry (LOG_COMPACTION_WINDOW_SECS)
+    compactor: LogCompactor,
+
+    // Stdin of agents running in interactive mode, for answers sent from the UI
+    inputs: Arc<AgentInputs>,
+
+    // Publishes pushed entries to the other instances (REDIS_URL)
+    bridge: Option<LogBridge>,
 }
 
 impl MsgStore {
-    pub fn new(database: Arc<Database>) -> Self {
+    pub fn new(database: Arc<Database>, tasks: &TaskSupervisor) -> Self {
         let (broadcast_tx, _) = broadcast::channel(1000);
-        let (db_queue_tx, mut db_queue_rx) = mpsc::unbounded_channel::<StructuredLogEntry>();
-
-        // Spawn background task to batch insert logs
-        let db_clone = database.clone();
-        tokio::spawn(async move {
-            let mut batch: Vec<StructuredLogRecord> = Vec::with_capacity(BATCH_SIZE);
-            let mut interval = tokio::time::interval(tokio::time::Duration::from_millis(FLUSH_INTERVAL_MS));
-
-            loop {
-                tokio::select! {
-                    // Receive logs from queue
-                    Some(entry) = db_queue_rx.recv() => {
-                        batch.push(entry.to_record());
-
-                        // Flush when batch is full
-                        if batch.len() >= BATCH_SIZE {
-                            if let Err(e) = db_clone.save_logs_batch(&batch).await {
-                                error!("Failed to batch save logs: {}", e);
+        let (db_queue_tx, db_queue_rx) = mpsc::unbounded_channel::<StructuredLogEntry>();
+
+        let pending_writes = Arc::new(AtomicUsize::new(0));
+
+        // Batch insert logs in the background. The queue outlives a writer that panics, so the
+        // restarted one picks up where it left off (only the batch in hand is lost).
+        let db_queue_rx = Arc::new(Mutex::new(db_queue_rx));
+        let db = database.clone();
+        let pending = pending_writes.clone();
+        tasks.spawn_restarting("log-writer", move || {
+            let (db_queue_rx, db_clone, pending_writes_clone) = (db_queue_rx.clone(), db.clone(), pending.clone());
+            async move {
+                let mut db_queue_rx = db_queue_rx.lock().await;
+                let mut batch: Vec<StructuredLogRecord> = Vec::with_capacity(BATCH_SIZE);
+                let mut interval = tokio::time::interval(tokio::time::Duration::from_millis(FLUSH_INTERVAL_MS));
+
+                loop {
+                    tokio::select! {
+                        // Receive logs from queue
+                        Some(entry) = db_queue_rx.recv() => {
+                            batch.push(entry.to_record());
+
+                            // Flush when batch is full
+                            if batch.len() >= BATCH_SIZE {
+                                if let Err(e) = db_clone.save_logs_batch(&batch).await {
+                                    error!("Failed to batch save logs: {}", e);
+                                }
+                                pending_writes_clone.fetch_sub(batch.len(), Ordering::Relaxed);
+                                batch.clear();
                             }
-                            batch.clear();
                         }
-                    }
-                    // Flush on interval
-                    _ = interval.tick() => {
-                        if !batch.is_empty() {
-                            if let Err(e) = db_clone.save_logs_batch(&batch).await {
-                                error!("Failed to batch save logs: {}", e);
+                        // Flush on interval
+                        _ = interval.tick() => {
+                            if !batch.is_empty() {
+                                if let Err(e) = db_clone.save_logs_batch(&batch).await {
+                                    error!("Failed to batch save logs: {}", e);
+                                }
+                                pending_writes_clone.fetch_sub(batch.len(), Ordering::Relaxed);
+                                batch.clear();
                             }
-                            batch.clear();
                         }
-                    }
-                    // Channel closed, flush remaining and exit
-                    else => {
-                        if !batch.is_empty() {
-                            if let Err(e) = db_clone.save_logs_batch(&batch).await {
-                                error!("Failed to batch save logs: {}", e);
+                        // Channel closed, flush remaining and exit
+                        else => {
+                            if !batch.is_empty() {
+                                if let Err(e) = db_clone.save_logs_batch(&batch).await {
+                                    error!("Failed to batch save logs: {}", e);
+                                }
                             }
+                            break;
                         }
-                        break;
                     }
                 }
             }
@@ -158,6 +201,68 @@ impl MsgStore {
             database,
             broadcast_tx,
             db_queue_tx,
+            pending_writes,
+            active_runs: Arc::new(Mutex::new(HashMap::new())),
+            persistence: LogPersistence::from_env(),
+            run_persistence: Arc::new(Mutex::new(HashMap::new())),
+            redactor: Arc::new(Redactor::from_env()),
+            compactor: LogCompactor::from_env(),
+            inputs: Arc::new(AgentInputs::default()),
+            bridge: None,
+        }
+    }
+
+    /// Publish every pushed entry through `bridge` as well
+    pub fn with_bridge(mut self, bridge: Option<LogBridge>) -> Self {
+        self.bridge = bridge;
+        self
+    }
+
+    /// Whether pushed entries already reach the other instances through Redis
+    pub fn is_bridged(&self) -> bool {
+        self.bridge.is_some()
+    }
+
+    pub fn redactor(&self) -> &Redactor {
+        &self.redactor
+    }
+
+    pub fn inputs(&self) -> &Arc<AgentInputs> {
+        &self.inputs
+    }
+
+    /// The server's log persistence, used by projects without their own
+    pub fn log_persistence(&self) -> &LogPersistence {
+        &self.persistence
+    }
+
+    /// Stamp `run_id` into the metadata of every entry pushed for this ticket, and store them
+    /// as its project's log persistence says
+    pub async fn begin_run(&self, ticket_id: &str, run_id: &str) {
+        let persistence = match self.database.get_ticket_log_persistence(ticket_id).await {
+            Ok(persistence) => persistence,
+            Err(e) => {
+                error!("Failed to get log persistence of ticket {}: {}", ticket_id, e);
+                None
+            }
+        };
+        {
+            let mut policies = self.run_persistence.lock().await;
+            match persistence {
+                Some(persistence) => policies.insert(ticket_id.to_string(), persistence),
+                None => policies.remove(ticket_id),
+            };
+        }
+        let mut runs = self.active_runs.lock().await;
+        runs.insert(ticket_id.to_string(), run_id.to_string());
+    }
+
+    /// Stop stamping entries for this ticket, unless a newer run has replaced `run_id`
+    pub async fn end_run(&self, ticket_id: &str, run_id: &str) {
+        let mut runs = self.active_runs.lock().await;
+        if runs.get(ticket_id).map(String::as_str) == Some(run_id) {
+            runs.remove(ticket_id);
+            self.run_persistence.lock().await.remove(ticket_id);
         }
     }
 
@@ -165,7 +270,21 @@ impl MsgStore {
         self.broadcast_tx.subscribe()
     }
 
-    pub async fn push(&self, entry: StructuredLogEntry) {
+    pub async fn push(&self, mut entry: StructuredLogEntry) {
+        // 0. Attach the correlation ID of the ticket's running analysis
+        {
+            let runs = self.active_runs.lock().await;
+            if let Some(run_id) = runs.get(&entry.ticket_id) {
+                entry
+                    .metadata
+                    .entry("run_id".to_string())
+                    .or_insert_with(|| run_id.clone());
+            }
+        }
+
+        // 0b. Mask secrets the agent echoed (.env contents, keys found in the repo)
+        self.redactor.redact_entry(&mut entry);
+
         // 1. Add to in-memory buffer with circular buffer behavior
         {
             let mut buffer = self.buffer.lock().await;
@@ -173,23 +292,69 @@ impl MsgStore {
                 .entry(entry.ticket_id.clone())
                 .or_insert_with(VecDeque::new);
 
-            ticket_logs.push_back(entry.clone());
+            // 1b. A repeat of the previous tool call updates that entry instead: it is
+            // re-saved and re-broadcast under the same ID
+            let merged = ticket_logs
+                .back_mut()
+                .and_then(|last| self.compactor.merge(last, &entry).then(|| last.clone()));
+            if let Some(merged) = merged {
+                entry = merged;
+            } else {
+                ticket_logs.push_back(entry.clone());
 
-            // Keep buffer size limited (circular buffer)
-            if ticket_logs.len() > MAX_BUFFER_SIZE {
-                ticket_logs.pop_front();
+                // Keep buffer size limited (circular buffer)
+                if ticket_logs.len() > MAX_BUFFER_SIZE {
+                    ticket_logs.pop_front();
+                }
             }
         }
 
-        // 2. Enqueue for batch database insert (non-blocking)
-        // Ignore send errors (means background task has stopped)
-        let _ = self.db_queue_tx.send(entry.clone());
+        // 2. Enqueue for batch database insert (non-blocking), unless the persistence policy
+        // leaves the entry out
+        // Counted before sending so the writer never decrements first;
+        // send errors mean the background task has stopped
+        let stored = {
+            let policies = self.run_persistence.lock().await;
+            policies.get(&entry.ticket_id).unwrap_or(&self.persistence).apply(&entry)
+        };
+        if let Some(stored) = stored {
+            self.pending_writes.fetch_add(1, Ordering::Relaxed);
+            if self.db_queue_tx.send(stored).is_err() {
+                self.pending_writes.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
 
-        // 3. Broadcast to all WebSocket subscribers
+        // 3. Fan out to the subscribers of the other instances
+        if let Some(bridge) = &self.bridge {
+            bridge.publish(&entry);
+        }
+
+        // 4. Broadcast to all WebSocket subscribers
         // Ignore send errors (means no active subscribers)
         let _ = self.broadcast_tx.send(entry);
     }
 
+    /// Buffer and broadcast an entry another process already redacted and stored (see
+    /// `worker` and `log_bridge`). An entry with a buffered ID (a compacted tool call)
+    /// replaces it; relayed entries are never published again.
+    pub async fn relay(&self, entry: StructuredLogEntry) {
+        {
+            let mut buffer = self.buffer.lock().await;
+            let ticket_logs = buffer.entry(entry.ticket_id.clone()).or_default();
+            match ticket_logs.iter_mut().rev().find(|buffered| buffered.id == entry.id) {
+                Some(buffered) => *buffered = entry.clone(),
+                None => {
+                    ticket_logs.push_back(entry.clone());
+                    if ticket_logs.len() > MAX_BUFFER_SIZE {
+                        ticket_logs.pop_front();
+                    }
+                }
+            }
+        }
+
+        let _ = self.broadcast_tx.send(entry);
+    }
+
     pub async fn get_logs(&self, ticket_id: &str) -> Vec<StructuredLogEntry> {
         // Try in-memory buffer first (fast path)
         {
@@ -212,6 +377,71 @@ impl MsgStore {
         }
     }
 
+    /// The ticket's entries logged after `after_id`, or all of them without one. An ID older than
+    /// the buffer continues from the stored entry; an unknown ID starts over.
+    pub async fn logs_after(&self, ticket_id: &str, after_id: Option<&str>) -> Vec<StructuredLogEntry> {
+        let Some(after_id) = after_id else {
+            return self.get_logs(ticket_id).await;
+        };
+        {
+            let buffer = self.buffer.lock().await;
+            if let Some(position) = buffer
+                .get(ticket_id)
+                .and_then(|logs| logs.iter().position(|entry| entry.id == after_id))
+            {
+                return buffer[ticket_id].iter().skip(position + 1).cloned().collect();
+            }
+        }
+
+        match self.database.get_log(after_id).await {
+            Ok(Some(record)) if record.ticket_id == ticket_id => {
+                match self.database.get_logs_after(ticket_id, &record.timestamp, MAX_BUFFER_SIZE as u64).await {
+                    Ok(records) => records.into_iter().map(StructuredLogEntry::from_record).collect(),
+                    Err(e) => {
+                        error!("Failed to load logs from database: {}", e);
+                        Vec::new()
+                    }
+                }
+            }
+            Ok(_) => self.get_logs(ticket_id).await,
+            Err(e) => {
+                error!("Failed to load log {}: {}", after_id, e);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Long-poll for the ticket's entries after `after_id`: those already logged right away,
+    /// otherwise the first ones pushed within `wait` (empty when it expires)
+    pub async fn tail(&self, ticket_id: &str, after_id: Option<&str>, wait: Duration) -> Vec<StructuredLogEntry> {
+        // Subscribed before looking, so an entry pushed in between isn't missed
+        let mut rx = self.subscribe();
+        let logs = self.logs_after(ticket_id, after_id).await;
+        if !logs.is_empty() || wait.is_zero() {
+            return logs;
+        }
+
+        let deadline = tokio::time::Instant::now() + wait;
+        loop {
+            match tokio::time::timeout_at(deadline, rx.recv()).await {
+                Err(_) => return Vec::new(),
+                Ok(Ok(entry)) if entry.ticket_id == ticket_id => {
+                    let mut logs = vec![entry];
+                    // Entries of the same burst go out together
+                    while let Ok(entry) = rx.try_recv() {
+                        if entry.ticket_id == ticket_id {
+                            logs.push(entry);
+                        }
+                    }
+                    return logs;
+                }
+                Ok(Ok(_)) => {}
+                Ok(Err(broadcast::error::RecvError::Lagged(_))) => return self.logs_after(ticket_id, after_id).await,
+                Ok(Err(broadcast::error::RecvError::Closed)) => return Vec::new(),
+            }
+        }
+    }
+
     pub async fn clear_logs(&self, ticket_id: &str) -> Result<()> {
         // Clear from in-memory buffer
         {
@@ -225,6 +455,11 @@ impl MsgStore {
         Ok(())
     }
 
+    /// Log entries waiting to be written to the database
+    pub fn queue_depth(&self) -> usize {
+        self.pending_writes.load(Ordering::Relaxed)
+    }
+
     pub async fn get_buffer_stats(&self) -> HashMap<String, usize> {
         let buffer = self.buffer.lock().await;
         buffer
@@ -266,12 +501,12 @@ impl MsgStore {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_support::{project_fixture, test_db, ticket_fixture};
 
     #[tokio::test]
     async fn test_circular_buffer() {
-        let db = Arc::new(Database::new("sqlite::memory:").await.unwrap());
-        db.init_schema().await.unwrap();
-        let store = MsgStore::new(db);
+        let db = Arc::new(test_db().await.unwrap());
+        let store = MsgStore::new(db, &TaskSupervisor::default());
 
         // Push more than MAX_BUFFER_SIZE logs
         for i in 0..1500 {
@@ -295,4 +530,134 @@ mod tests {
         // Buffer should be limited to MAX_BUFFER_SIZE
         assert!(logs.len() <= MAX_BUFFER_SIZE);
     }
+
+    #[tokio::test]
+    async fn test_run_id_stamped_on_entries() {
+        let db = Arc::new(test_db().await.unwrap());
+        let store = MsgStore::new(db, &TaskSupervisor::default());
+
+        let entry = |id: &str, ticket_id: &str| StructuredLogEntry {
+            id: id.to_string(),
+            ticket_id: ticket_id.to_string(),
+            message_type: LogMessageType::System,
+            content: "line".to_string(),
+            raw_log: None,
+            metadata: HashMap::new(),
+            timestamp: chrono::Utc::now(),
+        };
+
+        store.begin_run("ticket-a", "run-1").await;
+        store.push(entry("1", "ticket-a")).await;
+        store.push(entry("2", "ticket-b")).await;
+        store.end_run("ticket-a", "run-1").await;
+        store.push(entry("3", "ticket-a")).await;
+
+        let logs = store.get_logs("ticket-a").await;
+        assert_eq!(logs[0].metadata.get("run_id").map(String::as_str), Some("run-1"));
+        assert!(!logs[1].metadata.contains_key("run_id"));
+        assert!(!store.get_logs("ticket-b").await[0].metadata.contains_key("run_id"));
+    }
+
+    #[tokio::test]
+    async fn test_repeated_tool_calls_are_compacted() {
+        let db = Arc::new(test_db().await.unwrap());
+        db.create_project(&project_fixture("p1")).await.unwrap();
+        db.create_ticket(&ticket_fixture("t1", "p1")).await.unwrap();
+        let mut store = MsgStore::new(db.clone(), &TaskSupervisor::default());
+        store.compactor = LogCompactor::new(Some(Duration::from_secs(5)), true);
+        let mut rx = store.subscribe();
+
+        let normalizer = crate::log_normalizer::LogNormalizer::new();
+        for file in ["a.js", "b.js", "c.js"] {
+            store.push(normalizer.normalize(format!("Reading file: {}", file), "t1".to_string())).await;
+        }
+        store.push(normalizer.normalize("Analysis: done".to_string(), "t1".to_string())).await;
+
+        let logs = store.get_logs("t1").await;
+        assert_eq!(logs.len(), 2);
+        assert_eq!(logs[0].metadata["compacted_count"], "3");
+        assert_eq!(logs[0].metadata["compacted_files"], r#"["a.js","b.js","c.js"]"#);
+
+        // Each merge goes out again under the first entry's ID
+        let broadcast: Vec<String> = (0..4).map(|_| rx.try_recv().unwrap().id).collect();
+        assert_eq!(broadcast[..3], [logs[0].id.clone(), logs[0].id.clone(), logs[0].id.clone()]);
+
+        tokio::time::sleep(Duration::from_millis(FLUSH_INTERVAL_MS * 3)).await;
+        let stored = db.get_logs_for_ticket("t1", None, None).await.unwrap();
+        assert_eq!(stored.len(), 2);
+        assert_eq!(stored[0].raw_log.as_deref().unwrap().lines().count(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_project_log_persistence() {
+        let db = Arc::new(test_db().await.unwrap());
+        db.create_project(&project_fixture("p1")).await.unwrap();
+        db.create_ticket(&ticket_fixture("t1", "p1")).await.unwrap();
+        let persistence = LogPersistence {
+            message_types: Some(vec![LogMessageType::Assistant, LogMessageType::Error]),
+            raw_log_errors_only: true,
+        };
+        db.set_project_log_persistence("p1", Some(&persistence)).await.unwrap();
+        let store = MsgStore::new(db.clone(), &TaskSupervisor::default());
+
+        let entry = |id: &str, message_type: LogMessageType| StructuredLogEntry {
+            id: id.to_string(),
+            ticket_id: "t1".to_string(),
+            message_type,
+            content: "line".to_string(),
+            raw_log: Some("raw".to_string()),
+            metadata: HashMap::new(),
+            timestamp: chrono::Utc::now(),
+        };
+        store.begin_run("t1", "run-1").await;
+        store.push(entry("1", LogMessageType::System)).await;
+        store.push(entry("2", LogMessageType::Assistant)).await;
+        store.push(entry("3", LogMessageType::Error)).await;
+        store.end_run("t1", "run-1").await;
+        // Outside the run the server's policy applies again
+        store.push(entry("4", LogMessageType::System)).await;
+        store.flush().await;
+
+        // Everything is still live
+        assert_eq!(store.get_logs("t1").await.len(), 4);
+        let stored = db.get_logs_for_ticket("t1", None, None).await.unwrap();
+        let stored: Vec<(&str, Option<&str>)> = stored.iter().map(|log| (log.id.as_str(), log.raw_log.as_deref())).collect();
+        assert_eq!(stored, [("2", None), ("3", Some("raw")), ("4", Some("raw"))]);
+    }
+
+    #[tokio::test]
+    async fn test_tail_waits_for_new_entries() {
+        let db = Arc::new(test_db().await.unwrap());
+        let store = Arc::new(MsgStore::new(db, &TaskSupervisor::default()));
+
+        let entry = |id: &str, ticket_id: &str| StructuredLogEntry {
+            id: id.to_string(),
+            ticket_id: ticket_id.to_string(),
+            message_type: LogMessageType::System,
+            content: "line".to_string(),
+            raw_log: None,
+            metadata: HashMap::new(),
+            timestamp: chrono::Utc::now(),
+        };
+        store.push(entry("1", "ticket-a")).await;
+        store.push(entry("2", "ticket-a")).await;
+
+        // Newer entries come back at once
+        let logs = store.tail("ticket-a", Some("1"), Duration::from_secs(30)).await;
+        assert_eq!(logs.iter().map(|e| e.id.as_str()).collect::<Vec<_>>(), vec!["2"]);
+        assert_eq!(store.tail("ticket-a", None, Duration::ZERO).await.len(), 2);
+
+        // Nothing newer: the wait expires empty
+        assert!(store.tail("ticket-a", Some("2"), Duration::from_millis(50)).await.is_empty());
+
+        // Other tickets' entries don't end the wait
+        let pusher = store.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            pusher.push(entry("3", "ticket-b")).await;
+            pusher.push(entry("4", "ticket-a")).await;
+        });
+        let logs = store.tail("ticket-a", Some("2"), Duration::from_secs(5)).await;
+        assert_eq!(logs.iter().map(|e| e.id.as_str()).collect::<Vec<_>>(), vec!["4"]);
+    }
 }
@@ -0,0 +1,399 @@
+use crate::code_agent::{
+    until_cancelled, AnalysisCancelled, AnalysisMode, CodeAgent, CodeAnalysisRequest, CodeAnalysisResponse,
+};
+use crate::database::Database;
+use crate::log_normalizer::LogNormalizer;
+use crate::message_store::MsgStore;
+use anyhow::Result;
+use async_trait::async_trait;
+use rand::Rng;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::time::{sleep, timeout, Duration};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+/// Canned runs used when `MOCK_AGENT_FIXTURES` has no file for the mode
+const BUILTIN_ASK: &str = include_str!("../fixtures/mock_agent/ask.jsonl");
+const BUILTIN_TESTCASES: &str = include_str!("../fixtures/mock_agent/testcases.jsonl");
+const BUILTIN_DIAGRAM: &str = include_str!("../fixtures/mock_agent/diagram.jsonl");
+
+#[derive(Debug, thiserror::Error)]
+pub enum MockAgentError {
+    #[error("Request timeout after {0}s")]
+    Timeout(u64),
+    #[error("Invalid fixture {0}: {1}")]
+    InvalidFixture(String, String),
+    /// An `{"type": "error", "error": ...}` event, for demoing failed runs
+    #[error("{0}")]
+    Scripted(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct MockAgentConfig {
+    /// Directory of `ask.jsonl`, `testcases.jsonl` and `diagram.jsonl` replacing the built-in runs
+    pub fixtures_dir: Option<PathBuf>,
+    /// Pause before each event
+    pub delay_ms: u64,
+    /// Share of each pause randomized away, 0.0–1.0, so concurrent runs don't move in lockstep
+    pub jitter: f64,
+    pub timeout_seconds: u64,
+}
+
+impl Default for MockAgentConfig {
+    fn default() -> Self {
+        Self {
+            fixtures_dir: None,
+            delay_ms: 400,
+            jitter: 0.5,
+            timeout_seconds: 300,
+        }
+    }
+}
+
+impl MockAgentConfig {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+
+        Self {
+            fixtures_dir: std::env::var("MOCK_AGENT_FIXTURES").ok().map(PathBuf::from),
+            delay_ms: std::env::var("MOCK_AGENT_DELAY_MS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(defaults.delay_ms),
+            jitter: std::env::var("MOCK_AGENT_JITTER")
+                .ok()
+                .and_then(|s| s.parse::<f64>().ok())
+                .map(|j| j.clamp(0.0, 1.0))
+                .unwrap_or(defaults.jitter),
+            timeout_seconds: std::env::var("MOCK_AGENT_TIMEOUT")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(defaults.timeout_seconds),
+        }
+    }
+
+    /// Pause before the next event
+    fn delay(&self) -> Duration {
+        self.delay_with(rand::thread_rng().gen())
+    }
+
+    /// `delay` with the random draw (in `[0, 1)`) supplied by the caller
+    fn delay_with(&self, draw: f64) -> Duration {
+        Duration::from_secs_f64(self.delay_ms as f64 / 1000.0 * (1.0 - self.jitter * draw))
+    }
+}
+
+/// One line of a fixture
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    /// `{"delay_ms": 1500}`: an extra pause, e.g. a slow tool call
+    Delay(u64),
+    /// Any other JSON object: an event in the stream-json shape the Ollama and API agents log
+    Event(Value),
+}
+
+/// Steps of a fixture; blank lines are skipped
+fn parse_fixture(name: &str, fixture: &str) -> Result<Vec<Step>, MockAgentError> {
+    fixture
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            let event: Value = serde_json::from_str(line)
+                .map_err(|e| MockAgentError::InvalidFixture(name.to_string(), format!("line {}: {}", index + 1, e)))?;
+            if !event.is_object() {
+                let message = format!("line {}: not a JSON object", index + 1);
+                return Err(MockAgentError::InvalidFixture(name.to_string(), message));
+            }
+            Ok(match event.get("delay_ms").and_then(Value::as_u64) {
+                Some(ms) if event.as_object().map(|o| o.len()) == Some(1) => Step::Delay(ms),
+                _ => Step::Event(event),
+            })
+        })
+        .collect()
+}
+
+fn fixture_name(mode: AnalysisMode) -> &'static str {
+    match mode {
+        AnalysisMode::Ask => "ask.jsonl",
+        AnalysisMode::TestCases => "testcases.jsonl",
+        AnalysisMode::Diagram => "diagram.jsonl",
+    }
+}
+
+fn builtin_fixture(mode: AnalysisMode) -> &'static str {
+    match mode {
+        AnalysisMode::Ask => BUILTIN_ASK,
+        AnalysisMode::TestCases => BUILTIN_TESTCASES,
+        AnalysisMode::Diagram => BUILTIN_DIAGRAM,
+    }
+}
+
+/// Steps for a run in `mode`: the fixture directory's file, read on every run so fixtures can
+/// be edited while the server runs, or the built-in one
+async fn load_fixture(fixtures_dir: Option<&Path>, mode: AnalysisMode) -> Result<Vec<Step>, MockAgentError> {
+    let name = fixture_name(mode);
+    if let Some(dir) = fixtures_dir {
+        let path = dir.join(name);
+        match tokio::fs::read_to_string(&path).await {
+            Ok(fixture) => return parse_fixture(&path.display().to_string(), &fixture),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                warn!("⚠️ Không có fixture {}, dùng fixture mặc định", path.display());
+            }
+            Err(e) => return Err(MockAgentError::InvalidFixture(path.display().to_string(), e.to_string())),
+        }
+    }
+    parse_fixture(name, builtin_fixture(mode))
+}
+
+/// Text of an assistant message event, which makes up the answer
+fn assistant_text(event: &Value) -> Option<&str> {
+    let is_assistant = event.get("type").and_then(Value::as_str) == Some("message")
+        && event.get("role").and_then(Value::as_str) == Some("assistant");
+    is_assistant.then(|| event.get("content").and_then(Value::as_str)).flatten()
+}
+
+/// Agent replaying canned runs with artificial delays: demos without API credits or a model,
+/// and a generator of realistic load. Never reads the project.
+#[derive(Debug)]
+pub struct MockAgent {
+    config: MockAgentConfig,
+}
+
+impl MockAgent {
+    pub fn with_config(config: MockAgentConfig) -> Self {
+        Self { config }
+    }
+
+    pub async fn analyze_code(
+        &self,
+        request: CodeAnalysisRequest,
+        msg_store: Arc<MsgStore>,
+        database: Arc<Database>,
+        cancel: CancellationToken,
+    ) -> Result<CodeAnalysisResponse> {
+        info!("🚀 Bắt đầu phân tích code (mock) cho ticket: {}", request.ticket_id);
+
+        // Check if ticket exists, auto-create if not to prevent FK constraint failure
+        let ticket = database.get_ticket(&request.ticket_id).await?;
+        if ticket.is_none() {
+            info!("🔧 Ticket {} chưa tồn tại, tự động tạo ticket", request.ticket_id);
+
+            let auto_ticket = crate::database::TicketRecord {
+                id: request.ticket_id.clone(),
+                project_id: request.project_id.clone(),
+                title: "Auto-created".to_string(),
+                description: request.question.clone(),
+                status: "in-progress".to_string(),
+                code_context: Some(request.code_context.clone()),
+                analysis_result: None,
+                is_analyzing: true,
+                created_at: chrono::Utc::now().to_rfc3339(),
+                updated_at: chrono::Utc::now().to_rfc3339(),
+                stale: false,
+                analysis_result_blob: None,
+                analysis_result_size: None,
+                position: 0.0,
+                assignee_id: None,
+                summary: None,
+                mode: request.mode,
+            };
+
+            database.create_ticket(&auto_ticket).await?;
+            info!("✅ Đã tự động tạo ticket: {}", request.ticket_id);
+        }
+
+        let session_id = database
+            .create_session(&request.ticket_id, "mock", request.run_id.as_deref(), &request.tool_policy, request.mode)
+            .await?;
+
+        database
+            .update_ticket_analyzing(&request.ticket_id, true)
+            .await?;
+
+        let mut logs = Vec::new();
+        let normalizer = LogNormalizer::new();
+
+        let start_log = "🔄 Khởi động Mock agent (demo, không gọi model)...";
+        msg_store
+            .push(normalizer.normalize(start_log.to_string(), request.ticket_id.clone()))
+            .await;
+        logs.push(start_log.to_string());
+
+        let outcome = until_cancelled(&cancel, self.replay(&request, &msg_store, &normalizer)).await;
+        let result = match outcome {
+            Ok(output) => {
+                info!("✅ Mock agent hoàn thành phân tích");
+
+                let completion_log = "✅ Phân tích hoàn tất!";
+                let mut entry = normalizer.normalize(
+                    completion_log.to_string(),
+                    request.ticket_id.clone(),
+                );
+                entry.message_type = crate::message_store::LogMessageType::Result;
+                msg_store.push(entry).await;
+                logs.push(completion_log.to_string());
+
+                database.complete_session(&session_id, "Success").await?;
+                database
+                    .update_ticket_result(&request.ticket_id, &output)
+                    .await?;
+
+                output
+            }
+            Err(e) if e.is::<AnalysisCancelled>() => {
+                info!("⛔ Đã dừng Mock agent cho ticket {}", request.ticket_id);
+                return Err(e);
+            }
+            Err(e) => {
+                error!("❌ Lỗi khi chạy Mock agent: {}", e);
+
+                let error_log = format!("❌ Lỗi: {}", e);
+                let entry = normalizer.normalize(error_log.clone(), request.ticket_id.clone());
+                msg_store.push(entry).await;
+                logs.push(error_log);
+
+                database.fail_session(&session_id, &e.to_string()).await?;
+                database
+                    .update_ticket_analyzing(&request.ticket_id, false)
+                    .await?;
+
+                format!("Không thể phân tích code do lỗi: {}", e)
+            }
+        };
+
+        Ok(CodeAnalysisResponse {
+            ticket_id: request.ticket_id,
+            result,
+            logs,
+            success: true,
+        })
+    }
+
+    /// Log the fixture's events one by one and return the assistant's text
+    async fn replay(
+        &self,
+        request: &CodeAnalysisRequest,
+        msg_store: &Arc<MsgStore>,
+        normalizer: &LogNormalizer,
+    ) -> Result<String> {
+        let steps = load_fixture(self.config.fixtures_dir.as_deref(), request.mode).await?;
+        info!("🎬 Phát lại {} bước ({:?})", steps.len(), request.mode);
+
+        let run = async {
+            let mut answer = String::new();
+            for step in steps {
+                let event = match step {
+                    Step::Delay(ms) => {
+                        sleep(Duration::from_millis(ms)).await;
+                        continue;
+                    }
+                    Step::Event(event) => event,
+                };
+                sleep(self.config.delay()).await;
+                msg_store
+                    .push(normalizer.normalize(event.to_string(), request.ticket_id.clone()))
+                    .await;
+
+                if let Some(message) = event.get("error").and_then(Value::as_str) {
+                    return Err(MockAgentError::Scripted(message.to_string()));
+                }
+                if let Some(text) = assistant_text(&event) {
+                    answer.push_str(text);
+                }
+            }
+            Ok::<_, MockAgentError>(answer)
+        };
+
+        let answer = match timeout(Duration::from_secs(self.config.timeout_seconds), run).await {
+            Ok(answer) => answer?,
+            Err(_) => return Err(MockAgentError::Timeout(self.config.timeout_seconds).into()),
+        };
+        if answer.trim().is_empty() {
+            warn!("⚠️ Mock fixture has no assistant output");
+            return Ok("Analysis completed but no output generated".to_string());
+        }
+        Ok(answer)
+    }
+}
+
+#[async_trait]
+impl CodeAgent for MockAgent {
+    async fn analyze_code(
+        &self,
+        request: CodeAnalysisRequest,
+        msg_store: Arc<MsgStore>,
+        database: Arc<Database>,
+        cancel: CancellationToken,
+    ) -> Result<CodeAnalysisResponse> {
+        self.analyze_code(request, msg_store, database, cancel).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn answer(fixture: &str) -> String {
+        parse_fixture("test", fixture)
+            .unwrap()
+            .iter()
+            .filter_map(|step| match step {
+                Step::Event(event) => assistant_text(event).map(str::to_string),
+                Step::Delay(_) => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_fixture() {
+        let fixture = r#"{"type": "init", "model": "mock-demo"}
+
+{"delay_ms": 1500}
+{"type": "message", "role": "assistant", "content": "Xin chào", "delta": true}
+{"delay_ms": 20, "type": "tool_use"}"#;
+        let steps = parse_fixture("test", fixture).unwrap();
+        assert_eq!(steps.len(), 4);
+        assert_eq!(steps[1], Step::Delay(1500));
+        // `delay_ms` only makes a pause on its own
+        assert!(matches!(steps[3], Step::Event(_)));
+
+        let err = parse_fixture("ask.jsonl", "{\"type\": \"init\"}\nnot json").unwrap_err();
+        assert!(err.to_string().starts_with("Invalid fixture ask.jsonl: line 2"));
+        assert!(parse_fixture("ask.jsonl", "[1, 2]").is_err());
+    }
+
+    #[test]
+    fn test_builtin_fixtures_parse_into_their_mode() {
+        assert!(answer(BUILTIN_ASK).contains("POST /api/checkout"));
+        assert_eq!(crate::test_cases::parse_test_cases(&answer(BUILTIN_TESTCASES)).unwrap().len(), 4);
+        assert!(crate::diagram::extract(&answer(BUILTIN_DIAGRAM)).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_fixture_dir_overrides_per_mode() {
+        let dir = std::env::temp_dir().join(format!("mock-fixtures-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("ask.jsonl"), r#"{"type": "message", "role": "assistant", "content": "custom"}"#).unwrap();
+
+        let ask = load_fixture(Some(&dir), AnalysisMode::Ask).await.unwrap();
+        assert_eq!(ask.len(), 1);
+        // No diagram.jsonl in the directory: the built-in one
+        let diagram = load_fixture(Some(&dir), AnalysisMode::Diagram).await.unwrap();
+        assert_eq!(diagram, parse_fixture("diagram.jsonl", BUILTIN_DIAGRAM).unwrap());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_delay_jitter() {
+        let config = MockAgentConfig {
+            delay_ms: 400,
+            jitter: 0.5,
+            ..Default::default()
+        };
+        assert_eq!(config.delay_with(0.0), Duration::from_millis(400));
+        assert_eq!(config.delay_with(0.5), Duration::from_millis(300));
+    }
+}
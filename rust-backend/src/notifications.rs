@@ -0,0 +1,480 @@
+use crate::budgets::BudgetStatus;
+use crate::database::{Database, PendingNotificationRecord, ProjectRecord, TicketRecord};
+use crate::tasks::TaskSupervisor;
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use lettre::message::{header::ContentType, Mailbox};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::time::Duration;
+use tracing::{error, info, warn};
+
+/// Characters of the result (or error) quoted in an email
+const SUMMARY_MAX_CHARS: usize = 600;
+
+/// How a user hears about their finished analyses
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailMode {
+    Off,
+    /// One email per finished analysis
+    #[default]
+    Immediate,
+    /// Finished analyses are collected into one email every `EMAIL_DIGEST_INTERVAL_MINUTES`
+    Digest,
+}
+
+impl EmailMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EmailMode::Off => "off",
+            EmailMode::Immediate => "immediate",
+            EmailMode::Digest => "digest",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "off" => Some(EmailMode::Off),
+            "immediate" => Some(EmailMode::Immediate),
+            "digest" => Some(EmailMode::Digest),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmtpSecurity {
+    /// Implicit TLS (usually port 465)
+    Tls,
+    /// STARTTLS upgrade (usually port 587)
+    StartTls,
+    /// Plain SMTP, for local relays and test servers
+    None,
+}
+
+/// Email settings, read from `SMTP_*`, `APP_PUBLIC_URL`, `NOTIFY_MIN_DURATION_SECS` and
+/// `EMAIL_DIGEST_INTERVAL_MINUTES`. Notifications are off unless `SMTP_HOST` is set.
+#[derive(Debug, Clone)]
+pub struct EmailConfig {
+    pub smtp_host: String,
+    /// The security mode's standard port when unset
+    pub smtp_port: Option<u16>,
+    pub smtp_username: Option<String>,
+    pub smtp_password: Option<String>,
+    pub smtp_security: SmtpSecurity,
+    pub from: String,
+    /// Base URL of the web app, for deep links to tickets
+    pub public_url: String,
+    /// Analyses finishing faster than this don't notify; the user is likely still watching
+    pub min_duration_secs: u64,
+    pub digest_interval_minutes: u64,
+}
+
+impl EmailConfig {
+    pub fn from_env() -> Option<Self> {
+        let smtp_host = std::env::var("SMTP_HOST").ok().filter(|h| !h.trim().is_empty())?;
+        let smtp_security = match std::env::var("SMTP_SECURITY").as_deref() {
+            Ok("tls") => SmtpSecurity::Tls,
+            Ok("none") => SmtpSecurity::None,
+            _ => SmtpSecurity::StartTls,
+        };
+
+        Some(Self {
+            smtp_port: std::env::var("SMTP_PORT").ok().and_then(|s| s.parse().ok()),
+            smtp_username: std::env::var("SMTP_USERNAME").ok(),
+            smtp_password: std::env::var("SMTP_PASSWORD").ok(),
+            smtp_security,
+            from: std::env::var("SMTP_FROM").unwrap_or_else(|_| format!("Explain Source <noreply@{}>", smtp_host)),
+            public_url: std::env::var("APP_PUBLIC_URL")
+                .unwrap_or_else(|_| "http://localhost:3000".to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            min_duration_secs: std::env::var("NOTIFY_MIN_DURATION_SECS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(60),
+            digest_interval_minutes: std::env::var("EMAIL_DIGEST_INTERVAL_MINUTES")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .map(|n: u64| n.max(1))
+                .unwrap_or(60),
+            smtp_host,
+        })
+    }
+}
+
+/// How an analysis ended, as reported to the user who started it
+#[derive(Debug, Clone)]
+pub struct AnalysisOutcome {
+    pub succeeded: bool,
+    /// The result markdown, or the error message
+    pub detail: String,
+    pub elapsed: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Email {
+    pub subject: String,
+    pub body: String,
+}
+
+pub struct Notifier {
+    config: EmailConfig,
+    from: Mailbox,
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl Notifier {
+    pub fn new(config: EmailConfig) -> Result<Self> {
+        let mut builder = match config.smtp_security {
+            SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.smtp_host)?,
+            SmtpSecurity::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.smtp_host)?,
+            SmtpSecurity::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.smtp_host),
+        };
+        if let Some(port) = config.smtp_port {
+            builder = builder.port(port);
+        }
+        if let (Some(username), Some(password)) = (&config.smtp_username, &config.smtp_password) {
+            builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+        let from = config
+            .from
+            .parse()
+            .map_err(|e| anyhow!("Invalid SMTP_FROM {}: {}", config.from, e))?;
+
+        Ok(Self {
+            transport: builder.build(),
+            from,
+            config,
+        })
+    }
+
+    pub fn config(&self) -> &EmailConfig {
+        &self.config
+    }
+
+    async fn send(&self, to: &str, email: Email) -> Result<()> {
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to.parse()?)
+            .subject(email.subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(email.body)?;
+        self.transport.send(message).await?;
+        Ok(())
+    }
+
+    fn ticket_link(&self, ticket: &TicketRecord) -> String {
+        format!(
+            "{}/projects/{}?ticket={}",
+            self.config.public_url, ticket.project_id, ticket.id
+        )
+    }
+
+    /// Tell the user who started an analysis how it ended, now or in their next digest
+    pub async fn analysis_finished(
+        &self,
+        db: &Database,
+        user_id: &str,
+        ticket_id: &str,
+        outcome: &AnalysisOutcome,
+    ) -> Result<()> {
+        if outcome.elapsed < Duration::from_secs(self.config.min_duration_secs) {
+            return Ok(());
+        }
+        let Some(user) = db.get_user(user_id).await? else {
+            return Ok(());
+        };
+        let mode = db
+            .get_email_mode(user_id)
+            .await?
+            .and_then(|mode| EmailMode::parse(&mode))
+            .unwrap_or_default();
+        if mode == EmailMode::Off {
+            return Ok(());
+        }
+        let Some(ticket) = db.get_ticket(ticket_id).await? else {
+            return Ok(());
+        };
+
+        let notice = PendingNotificationRecord {
+            id: uuid::Uuid::new_v4().to_string(),
+            user_id: user.id.clone(),
+            ticket_id: ticket.id.clone(),
+            link: self.ticket_link(&ticket),
+            ticket_title: ticket.title,
+            succeeded: outcome.succeeded,
+            summary: summarize(&outcome.detail, SUMMARY_MAX_CHARS),
+            created_at: Utc::now().to_rfc3339(),
+        };
+
+        if mode == EmailMode::Digest {
+            return db.enqueue_notification(&notice).await;
+        }
+        self.send(&user.email, completion_email(&notice)).await?;
+        info!("📧 Đã gửi email thông báo ticket {} tới {}", ticket_id, user.email);
+        Ok(())
+    }
+
+    /// Tell a user a ticket was assigned to them. Assignments aren't part of digests: they are
+    /// sent right away unless the user turned email off.
+    pub async fn ticket_assigned(
+        &self,
+        db: &Database,
+        user_id: &str,
+        ticket: &TicketRecord,
+        assigned_by: Option<&str>,
+    ) -> Result<()> {
+        let Some(user) = db.get_user(user_id).await? else {
+            return Ok(());
+        };
+        let mode = db
+            .get_email_mode(user_id)
+            .await?
+            .and_then(|mode| EmailMode::parse(&mode))
+            .unwrap_or_default();
+        if mode == EmailMode::Off {
+            return Ok(());
+        }
+        let assigner = match assigned_by {
+            Some(id) => db.get_user(id).await?.map(|u| u.name),
+            None => None,
+        };
+
+        let email = assignment_email(&ticket.title, assigner.as_deref(), &self.ticket_link(ticket));
+        self.send(&user.email, email).await?;
+        info!("📧 Đã gửi email giao ticket {} tới {}", ticket.id, user.email);
+        Ok(())
+    }
+
+    /// Tell the recipients of an alert rule it fired; alerts go out right away, to addresses
+    /// that needn't be users. One failed recipient doesn't stop the others.
+    pub async fn alert(&self, to: &[String], rule_name: &str, ticket: &TicketRecord, match_count: i64, sample: &str) {
+        let email = alert_email(rule_name, &ticket.title, match_count, sample, &self.ticket_link(ticket));
+        for address in to {
+            match self.send(address, email.clone()).await {
+                Ok(()) => info!("📧 Đã gửi email alert '{}' tới {}", rule_name, address),
+                Err(e) => warn!("⚠️ Không gửi được email alert tới {}: {}", address, e),
+            }
+        }
+    }
+
+    /// Tell the owners and admins of the project's organization a budget reached `threshold`
+    /// percent; sent right away unless they turned email off. `name` says whose budget it is.
+    pub async fn budget_warning(
+        &self,
+        db: &Database,
+        project: &ProjectRecord,
+        name: &str,
+        status: &BudgetStatus,
+        threshold: i64,
+    ) -> Result<()> {
+        let link = format!("{}/projects/{}", self.config.public_url, project.id);
+        let email = budget_email(name, status, threshold, &link);
+        for user in db.list_users_by_org(&project.org_id).await? {
+            if !matches!(user.role.as_str(), "owner" | "admin") {
+                continue;
+            }
+            let mode = db
+                .get_email_mode(&user.id)
+                .await?
+                .and_then(|mode| EmailMode::parse(&mode))
+                .unwrap_or_default();
+            if mode == EmailMode::Off {
+                continue;
+            }
+            match self.send(&user.email, email.clone()).await {
+                Ok(()) => info!("📧 Đã gửi email cảnh báo ngân sách {} tới {}", name, user.email),
+                Err(e) => warn!("⚠️ Không gửi được email cảnh báo ngân sách tới {}: {}", user.email, e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Send every user with queued notices one email listing them
+    pub async fn send_digests(&self, db: &Database) -> Result<()> {
+        let mut by_user: BTreeMap<String, Vec<PendingNotificationRecord>> = BTreeMap::new();
+        for notice in db.list_pending_notifications().await? {
+            by_user.entry(notice.user_id.clone()).or_default().push(notice);
+        }
+
+        for (user_id, notices) in by_user {
+            let ids: Vec<String> = notices.iter().map(|n| n.id.clone()).collect();
+            let Some(user) = db.get_user(&user_id).await? else {
+                db.delete_pending_notifications(&ids).await?;
+                continue;
+            };
+            // Unsent notices stay queued for the next run
+            match self.send(&user.email, digest_email(&notices)).await {
+                Ok(()) => {
+                    db.delete_pending_notifications(&ids).await?;
+                    info!("📧 Đã gửi email tổng hợp {} phân tích tới {}", notices.len(), user.email);
+                }
+                Err(e) => warn!("⚠️ Gửi email tổng hợp tới {} thất bại: {}", user.email, e),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Send digest emails every `digest_interval_minutes`
+pub fn spawn_digest(notifier: Arc<Notifier>, db: Arc<Database>, tasks: &TaskSupervisor) {
+    tasks.spawn("email-digest", async move {
+        let mut interval =
+            tokio::time::interval(Duration::from_secs(notifier.config.digest_interval_minutes * 60));
+        // The first tick fires immediately; wait a full interval before the first digest
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if let Err(e) = notifier.send_digests(&db).await {
+                error!("❌ Gửi email tổng hợp thất bại: {}", e);
+            }
+        }
+    });
+}
+
+fn status_label(succeeded: bool) -> &'static str {
+    if succeeded {
+        "completed"
+    } else {
+        "failed"
+    }
+}
+
+pub fn completion_email(notice: &PendingNotificationRecord) -> Email {
+    Email {
+        subject: format!("Analysis {}: {}", status_label(notice.succeeded), notice.ticket_title),
+        body: format!(
+            "The analysis of \"{}\" has {}.\n\n{}\n\nOpen the ticket: {}\n",
+            notice.ticket_title,
+            status_label(notice.succeeded),
+            notice.summary,
+            notice.link
+        ),
+    }
+}
+
+pub fn digest_email(notices: &[PendingNotificationRecord]) -> Email {
+    let failed = notices.iter().filter(|n| !n.succeeded).count();
+    let mut subject = format!("{} analyses finished", notices.len());
+    if failed > 0 {
+        subject.push_str(&format!(" ({} failed)", failed));
+    }
+
+    let mut body = String::new();
+    for notice in notices {
+        body.push_str(&format!(
+            "- {} [{}]\n  {}\n  {}\n\n",
+            notice.ticket_title,
+            status_label(notice.succeeded),
+            notice.summary.replace('\n', "\n  "),
+            notice.link
+        ));
+    }
+    Email { subject, body }
+}
+
+pub fn assignment_email(ticket_title: &str, assigned_by: Option<&str>, link: &str) -> Email {
+    let by = assigned_by.map(|name| format!(" by {}", name)).unwrap_or_default();
+    Email {
+        subject: format!("Assigned to you: {}", ticket_title),
+        body: format!("\"{}\" was assigned to you{}.\n\nOpen the ticket: {}\n", ticket_title, by, link),
+    }
+}
+
+pub fn alert_email(rule_name: &str, ticket_title: &str, match_count: i64, sample: &str, link: &str) -> Email {
+    Email {
+        subject: format!("Alert: {} on {}", rule_name, ticket_title),
+        body: format!(
+            "The alert rule \"{}\" fired on the analysis of \"{}\" after {} matching log entries.\n\n{}\n\nOpen the ticket: {}\n",
+            rule_name, ticket_title, match_count, sample, link
+        ),
+    }
+}
+
+pub fn budget_email(name: &str, status: &BudgetStatus, threshold: i64, link: &str) -> Email {
+    let metric = status.budget.metric;
+    let consequence = if threshold >= 100 {
+        "New analyses are refused until next month or until the limit is raised."
+    } else {
+        "Analyses will be refused once it is used up."
+    };
+    Email {
+        subject: format!("Budget {}% used: {}", threshold, name),
+        body: format!(
+            "The monthly budget of the {} is {}% used: {} of {} since {}.\n\n{}\n\nOpen the project: {}\n",
+            name,
+            threshold,
+            metric.format(status.used),
+            metric.format(status.budget.monthly_limit),
+            status.since,
+            consequence,
+            link
+        ),
+    }
+}
+
+/// First `max_chars` characters of the text with runs of blank lines collapsed
+pub fn summarize(text: &str, max_chars: usize) -> String {
+    let lines: Vec<&str> = text.lines().map(str::trim_end).collect();
+    let mut compact = String::new();
+    for (i, line) in lines.iter().enumerate() {
+        if line.is_empty() && (i == 0 || lines[i - 1].is_empty()) {
+            continue;
+        }
+        compact.push_str(line);
+        compact.push('\n');
+    }
+    let compact = compact.trim();
+
+    match compact.char_indices().nth(max_chars) {
+        Some((cut, _)) => format!("{}…", compact[..cut].trim_end()),
+        None => compact.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notice(title: &str, succeeded: bool, summary: &str) -> PendingNotificationRecord {
+        PendingNotificationRecord {
+            id: "n1".to_string(),
+            user_id: "u1".to_string(),
+            ticket_id: "t1".to_string(),
+            ticket_title: title.to_string(),
+            succeeded,
+            summary: summary.to_string(),
+            link: "http://localhost:3000/projects/p1?ticket=t1".to_string(),
+            created_at: Utc::now().to_rfc3339(),
+        }
+    }
+
+    #[test]
+    fn test_summarize() {
+        assert_eq!(summarize("\n\n# Flow\n\n\n\nStep one\n", 100), "# Flow\n\nStep one");
+        assert_eq!(summarize("héllo wörld", 5), "héllo…");
+        assert_eq!(summarize("short", 5), "short");
+    }
+
+    #[test]
+    fn test_emails() {
+        let email = completion_email(&notice("Checkout", false, "Agent timed out"));
+        assert_eq!(email.subject, "Analysis failed: Checkout");
+        assert!(email.body.contains("Agent timed out"));
+        assert!(email.body.contains("?ticket=t1"));
+
+        let digest = digest_email(&[notice("Checkout", true, "Line 1\nLine 2"), notice("Refunds", false, "Error")]);
+        assert_eq!(digest.subject, "2 analyses finished (1 failed)");
+        assert!(digest.body.contains("- Checkout [completed]\n  Line 1\n  Line 2\n"));
+        assert!(digest.body.contains("- Refunds [failed]"));
+
+        let email = assignment_email("Checkout", Some("Alice"), "http://localhost:3000/projects/p1?ticket=t1");
+        assert_eq!(email.subject, "Assigned to you: Checkout");
+        assert!(email.body.starts_with("\"Checkout\" was assigned to you by Alice."));
+    }
+}
@@ -0,0 +1,275 @@
+use crate::auth::{ExternalIdentity, Provisioning};
+use anyhow::{anyhow, Context, Result};
+use openidconnect::core::{
+    CoreAuthDisplay, CoreAuthPrompt, CoreErrorResponseType, CoreGenderClaim, CoreJsonWebKey,
+    CoreJweContentEncryptionAlgorithm, CoreJwsSigningAlgorithm, CoreProviderMetadata, CoreResponseType,
+    CoreRevocableToken, CoreRevocationErrorResponse, CoreTokenIntrospectionResponse, CoreTokenType,
+};
+use openidconnect::{
+    AdditionalClaims, AuthenticationFlow, AuthorizationCode, Client, ClientId, ClientSecret, CsrfToken,
+    EmptyExtraTokenFields, EndpointMaybeSet, EndpointNotSet, EndpointSet, IdTokenFields, IssuerUrl, Nonce,
+    PkceCodeChallenge, PkceCodeVerifier, RedirectUrl, Scope, StandardErrorResponse, StandardTokenResponse,
+    TokenResponse,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use tokio::sync::{Mutex, RwLock};
+use tokio::time::{Duration, Instant};
+
+/// How long a user may take at the identity provider before the login must be restarted
+const PENDING_LOGIN_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Provider metadata (and its signing keys) is fetched again after this long, picking up key rotation
+const METADATA_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// OIDC settings, read from `OIDC_*`; None when `OIDC_ISSUER_URL` isn't set
+#[derive(Debug, Clone)]
+pub struct OidcConfig {
+    pub issuer_url: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    /// This server's `/api/auth/oidc/callback`, as registered with the provider
+    pub redirect_url: String,
+    /// Where the browser lands after login, with `#token=...` (or `#error=...`) appended
+    pub post_login_url: String,
+    pub scopes: Vec<String>,
+    /// ID token claim listing the user's groups (`groups` for Azure AD, Okta, Keycloak)
+    pub groups_claim: String,
+    /// `OIDC_ORG_ID`, `OIDC_AUTO_PROVISION`, `OIDC_ROLE_MAPPING`, `OIDC_DEFAULT_ROLE`
+    pub provisioning: Provisioning,
+}
+
+impl OidcConfig {
+    pub fn from_env() -> Option<Self> {
+        let issuer_url = std::env::var("OIDC_ISSUER_URL")
+            .ok()
+            .filter(|url| !url.trim().is_empty())?;
+        let Some(client_id) = std::env::var("OIDC_CLIENT_ID").ok().filter(|id| !id.trim().is_empty()) else {
+            tracing::warn!("OIDC_ISSUER_URL is set without OIDC_CLIENT_ID; SSO disabled");
+            return None;
+        };
+        let public_url = std::env::var("APP_PUBLIC_URL")
+            .unwrap_or_else(|_| "http://localhost:3000".to_string())
+            .trim_end_matches('/')
+            .to_string();
+
+        Some(Self {
+            issuer_url: issuer_url.trim().trim_end_matches('/').to_string(),
+            client_id,
+            client_secret: std::env::var("OIDC_CLIENT_SECRET").ok().filter(|s| !s.is_empty()),
+            redirect_url: std::env::var("OIDC_REDIRECT_URL")
+                .unwrap_or_else(|_| format!("{}/api/auth/oidc/callback", public_url)),
+            post_login_url: std::env::var("OIDC_POST_LOGIN_URL").unwrap_or_else(|_| format!("{}/", public_url)),
+            scopes: std::env::var("OIDC_SCOPES")
+                .unwrap_or_else(|_| "email profile".to_string())
+                .split([' ', ','])
+                .filter(|scope| !scope.is_empty() && *scope != "openid")
+                .map(str::to_string)
+                .collect(),
+            groups_claim: std::env::var("OIDC_GROUPS_CLAIM").unwrap_or_else(|_| "groups".to_string()),
+            provisioning: Provisioning::from_env("OIDC"),
+        })
+    }
+}
+
+/// ID token claims beyond the standard ones, kept so the configured groups claim can be read
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExtraClaims {
+    #[serde(flatten)]
+    claims: HashMap<String, Value>,
+}
+
+impl AdditionalClaims for ExtraClaims {}
+
+type IdTokenFieldsWithGroups = IdTokenFields<
+    ExtraClaims,
+    EmptyExtraTokenFields,
+    CoreGenderClaim,
+    CoreJweContentEncryptionAlgorithm,
+    CoreJwsSigningAlgorithm,
+>;
+
+type DiscoveredClient = Client<
+    ExtraClaims,
+    CoreAuthDisplay,
+    CoreGenderClaim,
+    CoreJweContentEncryptionAlgorithm,
+    CoreJsonWebKey,
+    CoreAuthPrompt,
+    StandardErrorResponse<CoreErrorResponseType>,
+    StandardTokenResponse<IdTokenFieldsWithGroups, CoreTokenType>,
+    CoreTokenIntrospectionResponse,
+    CoreRevocableToken,
+    CoreRevocationErrorResponse,
+    EndpointSet,
+    EndpointNotSet,
+    EndpointNotSet,
+    EndpointNotSet,
+    EndpointMaybeSet,
+    EndpointMaybeSet,
+>;
+
+struct PendingLogin {
+    nonce: Nonce,
+    pkce_verifier: PkceCodeVerifier,
+    created: Instant,
+}
+
+/// Authorization code flow against one provider. Logins in progress are held in memory, so
+/// with several replicas the callback must reach the one that started the login.
+pub struct OidcClient {
+    config: OidcConfig,
+    http: reqwest::Client,
+    metadata: RwLock<Option<(Instant, CoreProviderMetadata)>>,
+    pending: Mutex<HashMap<String, PendingLogin>>,
+}
+
+impl OidcClient {
+    pub fn new(config: OidcConfig) -> Result<Self> {
+        let http = reqwest::Client::builder()
+            // Following redirects would expose the token endpoint to SSRF
+            .redirect(reqwest::redirect::Policy::none())
+            .build()?;
+
+        Ok(Self {
+            config,
+            http,
+            metadata: RwLock::new(None),
+            pending: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn config(&self) -> &OidcConfig {
+        &self.config
+    }
+
+    /// Client for the provider's current metadata; `refresh` skips the cache
+    async fn client(&self, refresh: bool) -> Result<DiscoveredClient> {
+        let cached = self
+            .metadata
+            .read()
+            .await
+            .as_ref()
+            .filter(|(fetched, _)| !refresh && fetched.elapsed() < METADATA_TTL)
+            .map(|(_, metadata)| metadata.clone());
+        let metadata = match cached {
+            Some(metadata) => metadata,
+            None => {
+                let issuer = IssuerUrl::new(self.config.issuer_url.clone())?;
+                let metadata = CoreProviderMetadata::discover_async(issuer, &self.http)
+                    .await
+                    .map_err(|e| anyhow!("OIDC discovery failed for {}: {}", self.config.issuer_url, e))?;
+                *self.metadata.write().await = Some((Instant::now(), metadata.clone()));
+                metadata
+            }
+        };
+
+        Ok(DiscoveredClient::from_provider_metadata(
+            metadata,
+            ClientId::new(self.config.client_id.clone()),
+            self.config.client_secret.clone().map(ClientSecret::new),
+        )
+        .set_redirect_uri(RedirectUrl::new(self.config.redirect_url.clone())?))
+    }
+
+    /// The provider URL that starts a login
+    pub async fn authorize_url(&self) -> Result<String> {
+        let client = self.client(false).await?;
+        let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+        let mut request = client
+            .authorize_url(
+                AuthenticationFlow::<CoreResponseType>::AuthorizationCode,
+                CsrfToken::new_random,
+                Nonce::new_random,
+            )
+            .set_pkce_challenge(pkce_challenge);
+        for scope in &self.config.scopes {
+            request = request.add_scope(Scope::new(scope.clone()));
+        }
+        let (url, csrf_state, nonce) = request.url();
+
+        let mut pending = self.pending.lock().await;
+        pending.retain(|_, login| login.created.elapsed() < PENDING_LOGIN_TTL);
+        pending.insert(
+            csrf_state.secret().clone(),
+            PendingLogin {
+                nonce,
+                pkce_verifier,
+                created: Instant::now(),
+            },
+        );
+
+        Ok(url.to_string())
+    }
+
+    /// Finish the login started with `state`: redeem the code and verify the ID token
+    pub async fn complete(&self, state: &str, code: String) -> Result<ExternalIdentity> {
+        let login = self
+            .pending
+            .lock()
+            .await
+            .remove(state)
+            .filter(|login| login.created.elapsed() < PENDING_LOGIN_TTL)
+            .context("Unknown or expired login state")?;
+
+        let client = self.client(false).await?;
+        let token = client
+            .exchange_code(AuthorizationCode::new(code))?
+            .set_pkce_verifier(login.pkce_verifier)
+            .request_async(&self.http)
+            .await
+            .map_err(|e| anyhow!("OIDC code exchange failed: {}", e))?;
+        let id_token = token.id_token().context("Provider returned no ID token")?;
+        let claims = match id_token.claims(&client.id_token_verifier(), &login.nonce) {
+            Ok(claims) => claims.clone(),
+            // Signed with a key published after our metadata was cached
+            Err(e) => {
+                tracing::debug!("ID token verification failed, refreshing provider keys: {}", e);
+                let client = self.client(true).await?;
+                let verifier = client.id_token_verifier();
+                let claims = id_token.claims(&verifier, &login.nonce)?.clone();
+                claims
+            }
+        };
+
+        Ok(ExternalIdentity {
+            issuer: claims.issuer().to_string(),
+            subject: claims.subject().to_string(),
+            email: claims.email().map(|email| email.trim().to_lowercase()),
+            // Providers that omit the claim haven't vouched for the address
+            email_verified: claims.email_verified() == Some(true),
+            name: claims
+                .name()
+                .and_then(|name| name.get(None))
+                .map(|name| name.to_string())
+                .or_else(|| claims.preferred_username().map(|name| name.to_string())),
+            groups: groups_claim(&claims.additional_claims().claims, &self.config.groups_claim),
+        })
+    }
+}
+
+/// A claim holding a list of groups, or a single group as a string
+fn groups_claim(claims: &HashMap<String, Value>, name: &str) -> Option<Vec<String>> {
+    match claims.get(name)? {
+        Value::Array(groups) => Some(groups.iter().filter_map(|g| g.as_str().map(str::to_string)).collect()),
+        Value::String(group) => Some(vec![group.clone()]),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_groups_claim() {
+        let groups = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        let claims: HashMap<String, Value> =
+            serde_json::from_value(json!({"groups": ["qa-admins", 7], "roles": "qa-owners"})).unwrap();
+        assert_eq!(groups_claim(&claims, "groups"), Some(groups(&["qa-admins"])));
+        assert_eq!(groups_claim(&claims, "roles"), Some(groups(&["qa-owners"])));
+        assert_eq!(groups_claim(&claims, "missing"), None);
+    }
+}
@@ -0,0 +1,412 @@
+use crate::code_agent::{until_cancelled, AnalysisCancelled, CodeAgent, CodeAnalysisRequest, CodeAnalysisResponse};
+use crate::database::Database;
+use crate::log_normalizer::LogNormalizer;
+use crate::message_store::MsgStore;
+use crate::project_files::{self, ContextFile, ContextLimits};
+use crate::retry::{self, Retryable, RetryPolicy};
+use anyhow::Result;
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::time::{timeout, Duration, Instant};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
+
+/// Flush buffered tokens to MsgStore at least this often
+const DELTA_FLUSH_INTERVAL_MS: u64 = 250;
+/// ... or as soon as this many characters are buffered
+const DELTA_FLUSH_CHARS: usize = 200;
+
+#[derive(Debug, thiserror::Error)]
+pub enum OllamaAgentError {
+    #[error("Request timeout after {0}s")]
+    Timeout(u64),
+    #[error("Ollama endpoint unreachable: {0}")]
+    Unreachable(String),
+    #[error("Ollama returned HTTP {0}: {1}")]
+    HttpStatus(u16, String),
+    #[error("Ollama error: {0}")]
+    Model(String),
+    #[error("Working directory not accessible: {0}")]
+    DirectoryNotAccessible(String),
+}
+
+impl Retryable for OllamaAgentError {
+    /// The server may be starting up or busy; model errors (e.g. not pulled) need an operator
+    fn is_retryable(&self) -> bool {
+        match self {
+            Self::Timeout(_) | Self::Unreachable(_) => true,
+            Self::HttpStatus(status, _) => *status == 429 || *status >= 500,
+            Self::Model(_) | Self::DirectoryNotAccessible(_) => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct OllamaAgentConfig {
+    /// Base URL of the Ollama HTTP API
+    pub endpoint: String,
+    pub model: String,
+    pub timeout_seconds: u64,
+    pub retry: RetryPolicy,
+    pub working_dir: Option<String>,
+    pub context_limits: ContextLimits,
+}
+
+impl Default for OllamaAgentConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: "http://localhost:11434".to_string(),
+            model: "qwen2.5-coder".to_string(),
+            timeout_seconds: 600, // local models are slower than hosted ones
+            retry: RetryPolicy::default(),
+            working_dir: None,
+            context_limits: ContextLimits::default(),
+        }
+    }
+}
+
+impl OllamaAgentConfig {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+
+        Self {
+            endpoint: std::env::var("OLLAMA_ENDPOINT")
+                .map(|s| s.trim_end_matches('/').to_string())
+                .unwrap_or(defaults.endpoint),
+            model: std::env::var("OLLAMA_MODEL").unwrap_or(defaults.model),
+            timeout_seconds: std::env::var("OLLAMA_AGENT_TIMEOUT")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(defaults.timeout_seconds),
+            retry: RetryPolicy::from_env("OLLAMA_AGENT"),
+            working_dir: std::env::var("OLLAMA_AGENT_WORKING_DIR").ok(),
+            context_limits: ContextLimits {
+                max_files: std::env::var("OLLAMA_MAX_CONTEXT_FILES")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(defaults.context_limits.max_files),
+                max_total_bytes: std::env::var("OLLAMA_MAX_CONTEXT_BYTES")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(defaults.context_limits.max_total_bytes),
+                ..defaults.context_limits
+            },
+        }
+    }
+}
+
+/// Code agent backed by a local Ollama server; source code never leaves the host
+#[derive(Debug)]
+pub struct OllamaAgent {
+    config: OllamaAgentConfig,
+    client: reqwest::Client,
+}
+
+impl OllamaAgent {
+    pub fn with_config(config: OllamaAgentConfig) -> Self {
+        Self {
+            config,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub async fn analyze_code(
+        &self,
+        request: CodeAnalysisRequest,
+        msg_store: Arc<MsgStore>,
+        database: Arc<Database>,
+        cancel: CancellationToken,
+    ) -> Result<CodeAnalysisResponse> {
+        info!("🚀 Bắt đầu phân tích code cho ticket: {}", request.ticket_id);
+
+        // Check if ticket exists, auto-create if not to prevent FK constraint failure
+        let ticket = database.get_ticket(&request.ticket_id).await?;
+        if ticket.is_none() {
+            info!("🔧 Ticket {} chưa tồn tại, tự động tạo ticket", request.ticket_id);
+
+            let auto_ticket = crate::database::TicketRecord {
+                id: request.ticket_id.clone(),
+                project_id: request.project_id.clone(),
+                title: "Auto-created".to_string(),
+                description: request.question.clone(),
+                status: "in-progress".to_string(),
+                code_context: Some(request.code_context.clone()),
+                analysis_result: None,
+                is_analyzing: true,
+                created_at: chrono::Utc::now().to_rfc3339(),
+                updated_at: chrono::Utc::now().to_rfc3339(),
+                stale: false,
+                analysis_result_blob: None,
+                analysis_result_size: None,
+                position: 0.0,
+                assignee_id: None,
+                summary: None,
+                mode: request.mode,
+            };
+
+            database.create_ticket(&auto_ticket).await?;
+            info!("✅ Đã tự động tạo ticket: {}", request.ticket_id);
+        }
+
+        let session_id = database
+            .create_session(&request.ticket_id, "ollama", request.run_id.as_deref(), &request.tool_policy, request.mode)
+            .await?;
+
+        database
+            .update_ticket_analyzing(&request.ticket_id, true)
+            .await?;
+
+        let mut logs = Vec::new();
+        let normalizer = LogNormalizer::new();
+
+        let start_log = format!("🔄 Khởi động Ollama agent (model: {})...", self.config.model);
+        msg_store
+            .push(normalizer.normalize(start_log.clone(), request.ticket_id.clone()))
+            .await;
+        logs.push(start_log);
+
+        let working_directory = crate::code_agent::working_directory(&request, &database).await;
+
+        let outcome = until_cancelled(
+            &cancel,
+            self.execute_ollama(&request, working_directory, &msg_store, &normalizer),
+        )
+        .await;
+        let result = match outcome {
+            Ok(output) => {
+                info!("✅ Ollama agent hoàn thành phân tích");
+
+                let completion_log = "✅ Phân tích hoàn tất!";
+                let mut entry = normalizer.normalize(
+                    completion_log.to_string(),
+                    request.ticket_id.clone(),
+                );
+                entry.message_type = crate::message_store::LogMessageType::Result;
+                msg_store.push(entry).await;
+                logs.push(completion_log.to_string());
+
+                let output = msg_store.redactor().redact_text(output);
+                database.complete_session(&session_id, "Success").await?;
+                database
+                    .update_ticket_result(&request.ticket_id, &output)
+                    .await?;
+
+                output
+            }
+            Err(e) if e.is::<AnalysisCancelled>() => {
+                info!("⛔ Đã dừng Ollama agent cho ticket {}", request.ticket_id);
+                return Err(e);
+            }
+            Err(e) => {
+                error!("❌ Lỗi khi thực thi Ollama agent: {}", e);
+
+                let error_log = format!("❌ Lỗi: {}", e);
+                let entry = normalizer.normalize(error_log.clone(), request.ticket_id.clone());
+                msg_store.push(entry).await;
+                logs.push(error_log);
+
+                database.fail_session(&session_id, &e.to_string()).await?;
+                database
+                    .update_ticket_analyzing(&request.ticket_id, false)
+                    .await?;
+
+                format!("Không thể phân tích code do lỗi: {}", e)
+            }
+        };
+
+        Ok(CodeAnalysisResponse {
+            ticket_id: request.ticket_id,
+            result,
+            logs,
+            success: true,
+        })
+    }
+
+    async fn execute_ollama(
+        &self,
+        request: &CodeAnalysisRequest,
+        working_directory: Option<String>,
+        msg_store: &Arc<MsgStore>,
+        normalizer: &LogNormalizer,
+    ) -> Result<String> {
+        let analysis_dir = working_directory.or(self.config.working_dir.clone());
+
+        // Assemble context ourselves: the model has no tools to read files
+        let context_files = match analysis_dir {
+            Some(_) if !request.tool_policy.allows("Read") => {
+                info!("🚫 Tool policy không cho phép Read, bỏ qua nạp file vào context");
+                Vec::new()
+            }
+            Some(dir) => {
+                if let Err(e) = tokio::fs::metadata(&dir).await {
+                    error!("⚠️ Không thể access directory {}: {}", dir, e);
+                    return Err(OllamaAgentError::DirectoryNotAccessible(dir).into());
+                }
+                let root = PathBuf::from(&dir);
+                let code_context = request.code_context.clone();
+                let question = request.question.clone();
+                let limits = self.config.context_limits;
+                let policy = request.path_rules.compile()?;
+                tokio::task::spawn_blocking(move || {
+                    project_files::collect_context(&root, &code_context, &question, limits, &policy)
+                })
+                .await??
+            }
+            None => Vec::new(),
+        };
+
+        for file in &context_files {
+            let event = json!({
+                "type": "tool_use",
+                "tool_name": "read_file",
+                "parameters": { "file_path": file.path },
+            });
+            msg_store
+                .push(normalizer.normalize(event.to_string(), request.ticket_id.clone()))
+                .await;
+        }
+        info!("📚 Đã nạp {} file vào context", context_files.len());
+
+        retry::run(&self.config.retry, "analysis", retry::classify::<OllamaAgentError>, |_| async {
+            let chat = self.stream_chat(request, &context_files, msg_store, normalizer);
+            match timeout(Duration::from_secs(self.config.timeout_seconds), chat).await {
+                Ok(result) => result,
+                Err(_) => {
+                    error!("⏰ Ollama timeout after {} seconds", self.config.timeout_seconds);
+                    Err(OllamaAgentError::Timeout(self.config.timeout_seconds).into())
+                }
+            }
+        })
+        .await
+    }
+
+    /// POST /api/chat with `stream: true` and forward tokens as assistant deltas
+    async fn stream_chat(
+        &self,
+        request: &CodeAnalysisRequest,
+        context_files: &[ContextFile],
+        msg_store: &Arc<MsgStore>,
+        normalizer: &LogNormalizer,
+    ) -> Result<String> {
+        let body = json!({
+            "model": self.config.model,
+            "stream": true,
+            "messages": [
+                { "role": "system", "content": SYSTEM_PROMPT },
+                { "role": "user", "content": create_analysis_prompt(request, context_files) },
+            ],
+        });
+
+        let url = format!("{}/api/chat", self.config.endpoint);
+        debug!("POST {} (model {})", url, self.config.model);
+
+        let response = self
+            .client
+            .post(&url)
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| OllamaAgentError::Unreachable(e.to_string()))?;
+
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let text = response.text().await.unwrap_or_default();
+            return Err(OllamaAgentError::HttpStatus(status, text).into());
+        }
+
+        let mut answer = String::new();
+        let mut pending = String::new();
+        let mut line_buf = Vec::new();
+        let mut last_flush = Instant::now();
+        let mut stream = response.bytes_stream();
+
+        let flush = |pending: &mut String| {
+            let event = json!({
+                "type": "message",
+                "role": "assistant",
+                "content": std::mem::take(pending),
+                "delta": true,
+            });
+            normalizer.normalize(event.to_string(), request.ticket_id.clone())
+        };
+
+        // Response body is NDJSON: one chat chunk per line
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|e| OllamaAgentError::Unreachable(e.to_string()))?;
+            line_buf.extend_from_slice(&chunk);
+
+            while let Some(pos) = line_buf.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = line_buf.drain(..=pos).collect();
+                let Ok(event) = serde_json::from_slice::<Value>(&line) else { continue };
+
+                if let Some(err) = event.get("error").and_then(|v| v.as_str()) {
+                    return Err(OllamaAgentError::Model(err.to_string()).into());
+                }
+                if let Some(token) = event.pointer("/message/content").and_then(|v| v.as_str()) {
+                    answer.push_str(token);
+                    pending.push_str(token);
+                }
+
+                let done = event.get("done").and_then(|v| v.as_bool()).unwrap_or(false);
+                if !pending.is_empty()
+                    && (done
+                        || pending.len() >= DELTA_FLUSH_CHARS
+                        || last_flush.elapsed() >= Duration::from_millis(DELTA_FLUSH_INTERVAL_MS))
+                {
+                    msg_store.push(flush(&mut pending)).await;
+                    last_flush = Instant::now();
+                }
+            }
+        }
+
+        if !pending.is_empty() {
+            msg_store.push(flush(&mut pending)).await;
+        }
+
+        if answer.trim().is_empty() {
+            warn!("⚠️ Ollama produced no output");
+            return Ok("Analysis completed but no output generated".to_string());
+        }
+        Ok(answer)
+    }
+}
+
+const SYSTEM_PROMPT: &str = "You are a senior engineer helping QA testers understand a codebase. \
+Explain business flows in plain language, reference file paths from the provided context, \
+and say so when the context is not enough to answer.";
+
+fn create_analysis_prompt(request: &CodeAnalysisRequest, context_files: &[ContextFile]) -> String {
+    let mut prompt = String::new();
+    if context_files.is_empty() {
+        prompt.push_str("No source files were found for this question.\n\n");
+    } else {
+        prompt.push_str("Relevant source files:\n\n");
+        for file in context_files {
+            prompt.push_str(&format!("=== {} ===\n{}\n\n", file.path, file.content));
+        }
+    }
+    if !request.code_context.is_empty() {
+        prompt.push_str(&format!("Code context: {}\n", request.code_context));
+    }
+    prompt.push_str(&format!(
+        "Analyze the code to help QA understand the business flow. Question: {}",
+        request.prompt_question()
+    ));
+    prompt
+}
+
+#[async_trait]
+impl CodeAgent for OllamaAgent {
+    async fn analyze_code(
+        &self,
+        request: CodeAnalysisRequest,
+        msg_store: Arc<MsgStore>,
+        database: Arc<Database>,
+        cancel: CancellationToken,
+    ) -> Result<CodeAnalysisResponse> {
+        self.analyze_code(request, msg_store, database, cancel).await
+    }
+}
@@ -0,0 +1,826 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{Json, Redirect},
+};
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+use crate::api_handlers::{BudgetRequest, BudgetResponse};
+use crate::auth::{self, AuthContext, ExternalIdentity, OrgRole, Provisioning};
+use crate::budgets::{self, Budget};
+use crate::custom_fields::{self, TicketWithFields};
+use crate::database::{FeedEntry, OrgInviteRecord, OrganizationRecord, UserRecord};
+use crate::notifications::EmailMode;
+use crate::oidc::{OidcClient, OidcConfig};
+use crate::preferences::{self, PreferencesError, UserPreferences};
+use crate::time_tracking;
+use crate::AppState;
+
+const MIN_PASSWORD_LEN: usize = 8;
+const FEED_DEFAULT_LIMIT: u32 = 50;
+const FEED_MAX_LIMIT: u32 = 200;
+
+#[derive(Debug, Deserialize)]
+pub struct LoginRequest {
+    /// Email of a local account, or any login the LDAP user filter accepts
+    #[serde(alias = "username")]
+    pub email: String,
+    pub password: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LoginResponse {
+    pub token: String,
+    pub expires_at: String,
+    pub user: UserRecord,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MeResponse {
+    /// None for anonymous access (AUTH_REQUIRED=false)
+    pub user: Option<UserRecord>,
+    pub organization: OrganizationRecord,
+    pub role: OrgRole,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NotificationSettings {
+    pub email_mode: EmailMode,
+    /// Whether the server can send email at all (SMTP configured)
+    pub email_available: bool,
+    /// Analyses shorter than this don't notify
+    pub min_duration_secs: Option<u64>,
+    pub digest_interval_minutes: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateNotificationSettingsRequest {
+    pub email_mode: EmailMode,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ActivityFeedQuery {
+    /// `next_before` of the previous page
+    pub before: Option<String>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ActivityFeedResponse {
+    /// Newest first
+    pub entries: Vec<FeedEntry>,
+    /// Pass as `before` for older entries; None on the last page
+    pub next_before: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MyTicketsQuery {
+    pub status: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateOrganizationRequest {
+    pub name: String,
+    pub owner_email: String,
+    pub owner_name: String,
+    pub owner_password: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreateOrganizationResponse {
+    pub organization: OrganizationRecord,
+    pub owner: UserRecord,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateInviteRequest {
+    pub email: String,
+    #[serde(default = "default_invite_role")]
+    pub role: OrgRole,
+}
+
+fn default_invite_role() -> OrgRole {
+    OrgRole::Member
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreateInviteResponse {
+    pub invite: OrgInviteRecord,
+    /// Shown once; the invitee accepts with `POST /api/invites/:token/accept`
+    pub token: String,
+}
+
+/// What the identity provider sends back to `/api/auth/oidc/callback`
+#[derive(Debug, Deserialize)]
+pub struct OidcCallbackQuery {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+    pub error_description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AcceptInviteRequest {
+    pub name: String,
+    pub password: String,
+}
+
+fn internal(context: &str) -> impl Fn(anyhow::Error) -> StatusCode + '_ {
+    move |e| {
+        tracing::error!("{}: {}", context, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
+
+pub async fn issue_token(state: &AppState, user: UserRecord) -> Result<LoginResponse, StatusCode> {
+    let token = auth::generate_token();
+    let expires_at = (Utc::now() + Duration::hours(state.auth.token_ttl_hours)).to_rfc3339();
+    state
+        .database
+        .create_auth_token(&auth::hash_token(&token), &user.id, &expires_at)
+        .await
+        .map_err(internal("Failed to create auth token"))?;
+
+    Ok(LoginResponse {
+        token,
+        expires_at,
+        user,
+    })
+}
+
+fn new_user(org_id: &str, email: &str, name: &str, password: &str, role: OrgRole) -> Result<UserRecord, StatusCode> {
+    if password.len() < MIN_PASSWORD_LEN || !email.contains('@') || name.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    Ok(UserRecord {
+        id: uuid::Uuid::new_v4().to_string(),
+        org_id: org_id.to_string(),
+        email: email.trim().to_lowercase(),
+        name: name.trim().to_string(),
+        password_hash: Some(auth::hash_password(password).map_err(internal("Failed to hash password"))?),
+        role: role.as_str().to_string(),
+        created_at: Utc::now().to_rfc3339(),
+    })
+}
+
+async fn ensure_email_free(state: &AppState, email: &str) -> Result<(), StatusCode> {
+    match state.database.get_user_by_email(email.trim()).await {
+        Ok(None) => Ok(()),
+        Ok(Some(_)) => Err(StatusCode::CONFLICT),
+        Err(e) => Err(internal("Failed to look up user")(e)),
+    }
+}
+
+// POST /api/auth/login
+pub async fn login(
+    State(state): State<AppState>,
+    Json(data): Json<LoginRequest>,
+) -> Result<Json<LoginResponse>, StatusCode> {
+    let user = state
+        .database
+        .get_user_by_email(data.email.trim())
+        .await
+        .map_err(internal("Failed to look up user"))?;
+
+    if let Some(user) = user.filter(|u| {
+        u.password_hash
+            .as_deref()
+            .is_some_and(|hash| auth::verify_password(&data.password, hash))
+    }) {
+        info!("User {} logged in", user.id);
+        return Ok(Json(issue_token(&state, user).await?));
+    }
+
+    // Not a local account (or not its password): try the directory
+    if let Some(ldap) = &state.ldap {
+        let identity = ldap
+            .authenticate(data.email.trim(), &data.password)
+            .await
+            .map_err(|e| {
+                tracing::error!("LDAP login of {} failed: {}", data.email, e);
+                StatusCode::BAD_GATEWAY
+            })?;
+        if let Some(identity) = identity {
+            if let Some(user) = external_user(&state, &ldap.config().provisioning, &identity).await? {
+                info!("User {} logged in via LDAP", user.id);
+                return Ok(Json(issue_token(&state, user).await?));
+            }
+        }
+    }
+
+    warn!("Failed login for {}", data.email);
+    Err(StatusCode::UNAUTHORIZED)
+}
+
+fn oidc_client(state: &AppState) -> Result<Arc<OidcClient>, StatusCode> {
+    state.oidc.clone().ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Back to the frontend with the outcome in the fragment, which never reaches server logs
+fn post_login_redirect(config: &OidcConfig, params: &[(&str, &str)]) -> Redirect {
+    let fragment = serde_urlencoded::to_string(params).unwrap_or_default();
+    Redirect::to(&format!("{}#{}", config.post_login_url, fragment))
+}
+
+/// The local user an SSO or LDAP identity signs in as: the one it was linked to before, an
+/// existing account with the same verified email (only with `*_LINK_BY_EMAIL`), or (with
+/// auto-provisioning) a new member of the provider's organization. None when the identity may
+/// not sign in.
+async fn external_user(
+    state: &AppState,
+    config: &Provisioning,
+    identity: &ExternalIdentity,
+) -> Result<Option<UserRecord>, StatusCode> {
+    let linked = state
+        .database
+        .get_user_by_identity(&identity.issuer, &identity.subject)
+        .await
+        .map_err(internal("Failed to look up external identity"))?;
+
+    let mut user = match linked {
+        Some(user) => user,
+        None => {
+            let Some(email) = identity.email.as_deref().filter(|_| identity.email_verified) else {
+                warn!("External login of {} has no verified email", identity.subject);
+                return Ok(None);
+            };
+            let existing = state
+                .database
+                .get_user_by_email(email)
+                .await
+                .map_err(internal("Failed to look up user"))?;
+            let user = match existing {
+                Some(user) if config.link_by_email => user,
+                Some(_) => {
+                    warn!("External login of {} refused: the email belongs to an unlinked account", email);
+                    return Ok(None);
+                }
+                None if config.auto_provision => {
+                    let role = identity
+                        .groups
+                        .as_deref()
+                        .and_then(|groups| config.mapped_role(groups))
+                        .unwrap_or(config.default_role);
+                    let user = UserRecord {
+                        id: uuid::Uuid::new_v4().to_string(),
+                        org_id: config.org_id.clone(),
+                        email: email.to_string(),
+                        name: identity.name.clone().unwrap_or_else(|| email.to_string()),
+                        password_hash: None,
+                        role: role.as_str().to_string(),
+                        created_at: Utc::now().to_rfc3339(),
+                    };
+                    state
+                        .database
+                        .create_user(&user)
+                        .await
+                        .map_err(internal("Failed to provision external user"))?;
+                    info!("Provisioned external user {} in organization {}", user.id, user.org_id);
+                    user
+                }
+                None => {
+                    warn!("External login of {} refused: no account and auto-provisioning is off", email);
+                    return Ok(None);
+                }
+            };
+            state
+                .database
+                .link_user_identity(&identity.issuer, &identity.subject, &user.id)
+                .await
+                .map_err(internal("Failed to link external identity"))?;
+            user
+        }
+    };
+
+    // Roles of SSO/LDAP-only accounts follow their groups; password accounts linked by email keep theirs
+    if let Some(groups) = &identity.groups {
+        if !config.role_mapping.is_empty() && user.password_hash.is_none() && user.org_id == config.org_id {
+            let role = config.mapped_role(groups).unwrap_or(config.default_role);
+            if role.as_str() != user.role {
+                state
+                    .database
+                    .update_user_role(&user.id, role.as_str())
+                    .await
+                    .map_err(internal("Failed to update user role"))?;
+                info!("Role of user {} set to {} from {} groups", user.id, role.as_str(), identity.issuer);
+                user.role = role.as_str().to_string();
+            }
+        }
+    }
+
+    Ok(Some(user))
+}
+
+// GET /api/auth/oidc/login
+pub async fn oidc_login(State(state): State<AppState>) -> Result<Redirect, StatusCode> {
+    let oidc = oidc_client(&state)?;
+    match oidc.authorize_url().await {
+        Ok(url) => Ok(Redirect::to(&url)),
+        Err(e) => {
+            tracing::error!("Failed to start SSO login: {}", e);
+            Err(StatusCode::BAD_GATEWAY)
+        }
+    }
+}
+
+// GET /api/auth/oidc/callback
+pub async fn oidc_callback(
+    State(state): State<AppState>,
+    Query(query): Query<OidcCallbackQuery>,
+) -> Result<Redirect, StatusCode> {
+    let oidc = oidc_client(&state)?;
+    let config = oidc.config();
+
+    if let Some(error) = &query.error {
+        warn!(
+            "SSO login rejected by the identity provider: {} {}",
+            error,
+            query.error_description.as_deref().unwrap_or("")
+        );
+        return Ok(post_login_redirect(config, &[("error", error)]));
+    }
+    let (Some(code), Some(login_state)) = (query.code, query.state) else {
+        return Err(StatusCode::BAD_REQUEST);
+    };
+
+    let identity = match oidc.complete(&login_state, code).await {
+        Ok(identity) => identity,
+        Err(e) => {
+            warn!("SSO login failed: {}", e);
+            return Ok(post_login_redirect(config, &[("error", "login_failed")]));
+        }
+    };
+    let Some(user) = external_user(&state, &config.provisioning, &identity).await? else {
+        return Ok(post_login_redirect(config, &[("error", "access_denied")]));
+    };
+
+    info!("User {} logged in via SSO", user.id);
+    let login = issue_token(&state, user).await?;
+    Ok(post_login_redirect(
+        config,
+        &[("token", &login.token), ("expires_at", &login.expires_at)],
+    ))
+}
+
+// POST /api/auth/logout
+pub async fn logout(
+    State(state): State<AppState>,
+    request: axum::extract::Request,
+) -> Result<StatusCode, StatusCode> {
+    let (parts, _) = request.into_parts();
+    let Some(token) = auth::request_token(&parts) else {
+        return Err(StatusCode::UNAUTHORIZED);
+    };
+
+    state
+        .database
+        .delete_auth_token(&auth::hash_token(&token))
+        .await
+        .map_err(internal("Failed to revoke auth token"))?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// GET /api/me
+pub async fn get_me(auth: AuthContext, State(state): State<AppState>) -> Result<Json<MeResponse>, StatusCode> {
+    let user = match &auth.user_id {
+        Some(id) => state.database.get_user(id).await.map_err(internal("Failed to get user"))?,
+        None => None,
+    };
+    let organization = state
+        .database
+        .get_organization(&auth.org_id)
+        .await
+        .map_err(internal("Failed to get organization"))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(MeResponse {
+        user,
+        organization,
+        role: auth.role,
+    }))
+}
+
+// GET /api/me/notifications
+pub async fn get_notification_settings(
+    auth: AuthContext,
+    State(state): State<AppState>,
+) -> Result<Json<NotificationSettings>, StatusCode> {
+    // Anonymous access has no mailbox to notify
+    let user_id = auth.user_id.as_deref().ok_or(StatusCode::BAD_REQUEST)?;
+    let email_mode = state
+        .database
+        .get_email_mode(user_id)
+        .await
+        .map_err(internal("Failed to get notification settings"))?
+        .and_then(|mode| EmailMode::parse(&mode))
+        .unwrap_or_default();
+    let config = state.notifier.as_ref().map(|notifier| notifier.config());
+
+    Ok(Json(NotificationSettings {
+        email_mode,
+        email_available: config.is_some(),
+        min_duration_secs: config.map(|c| c.min_duration_secs),
+        digest_interval_minutes: config.map(|c| c.digest_interval_minutes),
+    }))
+}
+
+// PUT /api/me/notifications
+pub async fn update_notification_settings(
+    auth: AuthContext,
+    State(state): State<AppState>,
+    Json(data): Json<UpdateNotificationSettingsRequest>,
+) -> Result<Json<NotificationSettings>, StatusCode> {
+    let user_id = auth.user_id.as_deref().ok_or(StatusCode::BAD_REQUEST)?;
+    state
+        .database
+        .set_email_mode(user_id, data.email_mode.as_str())
+        .await
+        .map_err(internal("Failed to update notification settings"))?;
+
+    get_notification_settings(auth, State(state)).await
+}
+
+fn preferences_error(e: PreferencesError) -> (StatusCode, Json<Value>) {
+    match e {
+        PreferencesError::Database(e) => {
+            tracing::error!("Failed to save preferences: {}", e);
+            let status = StatusCode::INTERNAL_SERVER_ERROR;
+            (status, Json(json!({ "error": status.canonical_reason() })))
+        }
+        e => (StatusCode::BAD_REQUEST, Json(json!({ "error": e.to_string() }))),
+    }
+}
+
+// GET /api/me/preferences
+pub async fn get_preferences(
+    auth: AuthContext,
+    State(state): State<AppState>,
+) -> Result<Json<UserPreferences>, StatusCode> {
+    // Anonymous access has nowhere to keep preferences
+    let user_id = auth.user_id.as_deref().ok_or(StatusCode::BAD_REQUEST)?;
+    let preferences = preferences::load(&state.database, user_id)
+        .await
+        .map_err(internal("Failed to get preferences"))?;
+
+    Ok(Json(preferences))
+}
+
+// PUT /api/me/preferences
+pub async fn update_preferences(
+    auth: AuthContext,
+    State(state): State<AppState>,
+    Json(data): Json<UserPreferences>,
+) -> Result<Json<UserPreferences>, (StatusCode, Json<Value>)> {
+    let status_only = |status: StatusCode| (status, Json(json!({ "error": status.canonical_reason() })));
+    let user_id = auth.user_id.as_deref().ok_or(status_only(StatusCode::BAD_REQUEST))?;
+    if let Some(project_id) = data.default_project_id.as_deref().filter(|id| !id.trim().is_empty()) {
+        let project = auth
+            .project(&state.database, project_id.trim())
+            .await
+            .map_err(|e| preferences_error(e.into()))?;
+        if project.is_none() {
+            return Err(preferences_error(PreferencesError::UnknownProject(project_id.to_string())));
+        }
+    }
+
+    let saved = preferences::save(&state.database, user_id, data)
+        .await
+        .map_err(preferences_error)?;
+    info!("⚙️ Đã cập nhật preferences của user {}", user_id);
+    Ok(Json(saved))
+}
+
+// GET /api/me/tickets
+pub async fn list_my_tickets(
+    auth: AuthContext,
+    Query(params): Query<MyTicketsQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<TicketWithFields>>, StatusCode> {
+    // Nothing can be assigned to anonymous access
+    let user_id = auth.user_id.as_deref().ok_or(StatusCode::BAD_REQUEST)?;
+    let tickets = state
+        .database
+        .list_user_tickets(user_id, &auth.org_id, params.status.as_deref())
+        .await
+        .map_err(internal("Failed to list assigned tickets"))?;
+    let mut tickets = custom_fields::with_fields(&state.database, tickets, &[])
+        .await
+        .map_err(internal("Failed to load custom field values"))?;
+    time_tracking::flag_overdue(&state.database, &state.sla, &mut tickets)
+        .await
+        .map_err(internal("Failed to load status history"))?;
+
+    Ok(Json(tickets))
+}
+
+// GET /api/me/activity
+pub async fn get_activity_feed(
+    auth: AuthContext,
+    Query(params): Query<ActivityFeedQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<ActivityFeedResponse>, StatusCode> {
+    // Anonymous access watches nothing
+    let user_id = auth.user_id.as_deref().ok_or(StatusCode::BAD_REQUEST)?;
+    let limit = params.limit.unwrap_or(FEED_DEFAULT_LIMIT).clamp(1, FEED_MAX_LIMIT);
+    let entries = state
+        .database
+        .list_user_feed(user_id, &auth.org_id, params.before.as_deref(), limit)
+        .await
+        .map_err(internal("Failed to load activity feed"))?;
+
+    let next_before = match entries.last() {
+        Some(last) if entries.len() == limit as usize => Some(last.created_at.clone()),
+        _ => None,
+    };
+    Ok(Json(ActivityFeedResponse { entries, next_before }))
+}
+
+// POST /api/me/activity/read
+pub async fn mark_activity_read(auth: AuthContext, State(state): State<AppState>) -> Result<Json<Value>, StatusCode> {
+    let user_id = auth.user_id.as_deref().ok_or(StatusCode::BAD_REQUEST)?;
+    let read_at = Utc::now().to_rfc3339();
+    state
+        .database
+        .mark_activity_read(user_id, &read_at)
+        .await
+        .map_err(internal("Failed to mark activity read"))?;
+    Ok(Json(json!({ "read_at": read_at })))
+}
+
+// GET /api/orgs
+pub async fn list_organizations(
+    auth: AuthContext,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<OrganizationRecord>>, StatusCode> {
+    auth.require_instance_admin()?;
+    let orgs = state
+        .database
+        .list_organizations()
+        .await
+        .map_err(internal("Failed to list organizations"))?;
+    Ok(Json(orgs))
+}
+
+// POST /api/orgs
+pub async fn create_organization(
+    auth: AuthContext,
+    State(state): State<AppState>,
+    Json(data): Json<CreateOrganizationRequest>,
+) -> Result<Json<CreateOrganizationResponse>, StatusCode> {
+    auth.require_instance_admin()?;
+    if data.name.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    ensure_email_free(&state, &data.owner_email).await?;
+
+    let organization = OrganizationRecord {
+        id: uuid::Uuid::new_v4().to_string(),
+        name: data.name.trim().to_string(),
+        created_at: Utc::now().to_rfc3339(),
+    };
+    let owner = new_user(
+        &organization.id,
+        &data.owner_email,
+        &data.owner_name,
+        &data.owner_password,
+        OrgRole::Owner,
+    )?;
+
+    state
+        .database
+        .create_organization(&organization)
+        .await
+        .map_err(internal("Failed to create organization"))?;
+    state
+        .database
+        .create_user(&owner)
+        .await
+        .map_err(internal("Failed to create organization owner"))?;
+
+    info!("Created organization {} ({})", organization.name, organization.id);
+    Ok(Json(CreateOrganizationResponse { organization, owner }))
+}
+
+// GET /api/orgs/current/members
+pub async fn list_members(
+    auth: AuthContext,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<UserRecord>>, StatusCode> {
+    let users = state
+        .database
+        .list_users_by_org(&auth.org_id)
+        .await
+        .map_err(internal("Failed to list members"))?;
+    Ok(Json(users))
+}
+
+// DELETE /api/orgs/current/members/:user_id
+pub async fn remove_member(
+    auth: AuthContext,
+    Path(user_id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, StatusCode> {
+    auth.require_org_admin()?;
+    if auth.user_id.as_deref() == Some(user_id.as_str()) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let user = state
+        .database
+        .get_user(&user_id)
+        .await
+        .map_err(internal("Failed to get user"))?
+        .filter(|u| u.org_id == auth.org_id)
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    state
+        .database
+        .delete_user(&user.id)
+        .await
+        .map_err(internal("Failed to remove member"))?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// GET /api/orgs/current/budget
+pub async fn get_budget(auth: AuthContext, State(state): State<AppState>) -> Result<Json<BudgetResponse>, StatusCode> {
+    let status = budgets::org_status(&state.database, &auth.org_id, Utc::now())
+        .await
+        .map_err(internal("Failed to get organization budget"))?;
+    Ok(Json(BudgetResponse {
+        budget: status.as_ref().map(|status| status.budget.clone()),
+        status: status.into_iter().collect(),
+    }))
+}
+
+// PUT /api/orgs/current/budget (organization admins)
+pub async fn set_budget(
+    auth: AuthContext,
+    State(state): State<AppState>,
+    Json(data): Json<BudgetRequest>,
+) -> Result<Json<BudgetResponse>, (StatusCode, Json<Value>)> {
+    let status_only = |status: StatusCode| (status, Json(json!({ "error": status.canonical_reason() })));
+    auth.require_org_admin().map_err(status_only)?;
+    if let Some(Err(e)) = data.budget.as_ref().map(Budget::validate) {
+        return Err((StatusCode::BAD_REQUEST, Json(json!({ "error": e }))));
+    }
+
+    state
+        .database
+        .set_org_budget(&auth.org_id, data.budget.as_ref(), auth.user_id.as_deref())
+        .await
+        .map_err(internal("Failed to save organization budget"))
+        .map_err(status_only)?;
+    info!("Budget of organization {} updated: {:?}", auth.org_id, data.budget);
+    get_budget(auth, State(state)).await.map_err(status_only)
+}
+
+// GET /api/orgs/current/invites
+pub async fn list_invites(
+    auth: AuthContext,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<OrgInviteRecord>>, StatusCode> {
+    auth.require_org_admin()?;
+    let invites = state
+        .database
+        .list_invites_by_org(&auth.org_id)
+        .await
+        .map_err(internal("Failed to list invites"))?;
+    Ok(Json(invites))
+}
+
+// POST /api/orgs/current/invites
+pub async fn create_invite(
+    auth: AuthContext,
+    State(state): State<AppState>,
+    Json(data): Json<CreateInviteRequest>,
+) -> Result<Json<CreateInviteResponse>, StatusCode> {
+    auth.require_org_admin()?;
+    // Only owners can hand out ownership
+    if data.role == OrgRole::Owner && auth.role != OrgRole::Owner {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    if !data.email.contains('@') {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    ensure_email_free(&state, &data.email).await?;
+
+    let token = auth::generate_token();
+    let now = Utc::now();
+    let invite = OrgInviteRecord {
+        id: uuid::Uuid::new_v4().to_string(),
+        org_id: auth.org_id.clone(),
+        email: data.email.trim().to_lowercase(),
+        role: data.role.as_str().to_string(),
+        token_hash: auth::hash_token(&token),
+        invited_by: auth.user_id.clone(),
+        created_at: now.to_rfc3339(),
+        expires_at: (now + Duration::hours(state.auth.invite_ttl_hours)).to_rfc3339(),
+        accepted_at: None,
+    };
+
+    state
+        .database
+        .create_invite(&invite)
+        .await
+        .map_err(internal("Failed to create invite"))?;
+
+    info!("Invited {} to organization {}", invite.email, invite.org_id);
+    Ok(Json(CreateInviteResponse { invite, token }))
+}
+
+// POST /api/invites/:token/accept
+pub async fn accept_invite(
+    Path(token): Path<String>,
+    State(state): State<AppState>,
+    Json(data): Json<AcceptInviteRequest>,
+) -> Result<Json<LoginResponse>, StatusCode> {
+    let invite = state
+        .database
+        .get_pending_invite(&auth::hash_token(&token))
+        .await
+        .map_err(internal("Failed to look up invite"))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    ensure_email_free(&state, &invite.email).await?;
+
+    let role = OrgRole::parse(&invite.role).unwrap_or(OrgRole::Member);
+    let user = new_user(&invite.org_id, &invite.email, &data.name, &data.password, role)?;
+    state
+        .database
+        .accept_invite(&invite.id, &user)
+        .await
+        .map_err(|e| {
+            warn!("Failed to accept invite {}: {}", invite.id, e);
+            StatusCode::CONFLICT
+        })?;
+
+    info!("User {} joined organization {}", user.id, user.org_id);
+    Ok(Json(issue_token(&state, user).await?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::DEFAULT_ORG_ID;
+    use crate::mock_agent::{MockAgent, MockAgentConfig};
+    use crate::test_support::{temp_dir, test_database, test_state};
+
+    fn identity(subject: &str, email_verified: bool) -> ExternalIdentity {
+        ExternalIdentity {
+            issuer: "https://idp.example.com".to_string(),
+            subject: subject.to_string(),
+            email: Some("dev@example.com".to_string()),
+            email_verified,
+            name: None,
+            groups: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_external_login_links_existing_account_only_when_enabled() {
+        let dir = temp_dir("external-user").unwrap();
+        let database = test_database(&dir).await.unwrap();
+        let agent = Arc::new(MockAgent::with_config(MockAgentConfig::from_env()));
+        let state = test_state(database.clone(), agent).unwrap();
+        let existing = UserRecord {
+            id: "u1".to_string(),
+            org_id: DEFAULT_ORG_ID.to_string(),
+            email: "dev@example.com".to_string(),
+            name: "Dev".to_string(),
+            password_hash: Some(auth::hash_password("correct horse").unwrap()),
+            role: OrgRole::Owner.as_str().to_string(),
+            created_at: Utc::now().to_rfc3339(),
+        };
+        database.create_user(&existing).await.unwrap();
+        let mut config = Provisioning {
+            org_id: DEFAULT_ORG_ID.to_string(),
+            auto_provision: true,
+            link_by_email: true,
+            role_mapping: Vec::new(),
+            default_role: OrgRole::Member,
+        };
+
+        // A provider that sent no email_verified claim hasn't vouched for the address
+        let user = external_user(&state, &config, &identity("no-claim", false)).await.unwrap();
+        assert!(user.is_none());
+        assert!(database.get_user_by_identity("https://idp.example.com", "no-claim").await.unwrap().is_none());
+
+        // Verified, but linking by email is off: the account isn't taken over or duplicated
+        config.link_by_email = false;
+        let user = external_user(&state, &config, &identity("verified", true)).await.unwrap();
+        assert!(user.is_none());
+        assert!(database.get_user_by_identity("https://idp.example.com", "verified").await.unwrap().is_none());
+
+        config.link_by_email = true;
+        let user = external_user(&state, &config, &identity("verified", true)).await.unwrap().unwrap();
+        assert_eq!(user.id, "u1");
+        let linked = database.get_user_by_identity("https://idp.example.com", "verified").await.unwrap();
+        assert_eq!(linked.unwrap().id, "u1");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,402 @@
+//! Checks that an analysis did what its mode asked (`OUTPUT_VALIDATION`): no files written by
+//! a read-only run, test cases in testcases mode, a diagram in diagram mode. Violations are
+//! logged to the ticket and recorded on the session; in `retry` mode the agent gets one more
+//! attempt with a corrective prompt.
+
+use crate::code_agent::{AnalysisMode, CodeAgent, CodeAnalysisRequest, CodeAnalysisResponse};
+use crate::message_store::{LogMessageType, StructuredLogEntry};
+use crate::preflight;
+use crate::AppState;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+use tokio::sync::{broadcast, oneshot};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
+
+/// Broadcast when a completed run violated its mode
+pub const VIOLATION_EVENT: &str = "output-violation";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ValidationMode {
+    Off,
+    /// Record violations
+    Flag,
+    /// Record them and run the agent once more with a corrective prompt
+    Retry,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct OutputValidationConfig {
+    pub mode: ValidationMode,
+}
+
+impl OutputValidationConfig {
+    pub fn from_env() -> Self {
+        let mode = match std::env::var("OUTPUT_VALIDATION").map(|m| m.trim().to_lowercase()).as_deref() {
+            Ok("off") => ValidationMode::Off,
+            Ok("retry") => ValidationMode::Retry,
+            _ => ValidationMode::Flag,
+        };
+        Self { mode }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ViolationKind {
+    /// A run whose tool policy only allows reading changed files
+    FileWrite,
+    /// Testcases mode without test cases in a supported format
+    MissingTestCases,
+    /// Diagram mode without a usable Mermaid block
+    MissingDiagram,
+    EmptyResult,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Violation {
+    pub kind: ViolationKind,
+    pub detail: String,
+}
+
+impl Violation {
+    fn new(kind: ViolationKind, detail: impl Into<String>) -> Self {
+        Self {
+            kind,
+            detail: detail.into(),
+        }
+    }
+
+    /// What the agent is told on the retry
+    fn correction(&self) -> &'static str {
+        match self.kind {
+            ViolationKind::FileWrite => {
+                "Your previous attempt created or modified files. This is a read-only analysis: \
+                 do not write, edit or delete any file; only read the code and answer in text."
+            }
+            ViolationKind::MissingTestCases => {
+                "Your previous answer did not contain the test cases in the required format. \
+                 Follow the output format below exactly."
+            }
+            ViolationKind::MissingDiagram => {
+                "Your previous answer did not contain a valid Mermaid diagram. Answer with a single \
+                 ```mermaid block as described below."
+            }
+            ViolationKind::EmptyResult => "Your previous answer was empty. Answer the question below.",
+        }
+    }
+}
+
+/// Whether a tool name (`Write`, `MultiEdit`, `write_file`, `editToolCall`, ...) changes files
+pub fn is_write_tool(name: &str) -> bool {
+    let mut words = String::new();
+    for c in name.chars() {
+        if c.is_uppercase() {
+            words.push('_');
+        }
+        words.extend(c.to_lowercase());
+    }
+    words
+        .split(|c: char| !c.is_alphanumeric())
+        .any(|word| ["write", "edit", "replace", "delete", "patch"].contains(&word))
+}
+
+/// A call of a file-changing tool and its arguments
+#[derive(Debug, Clone, PartialEq)]
+pub struct WriteCall {
+    pub tool: String,
+    pub input: Value,
+}
+
+/// The file-changing tool calls of a log entry, in the event shapes of each agent
+pub fn write_calls(entry: &StructuredLogEntry) -> Vec<WriteCall> {
+    let mut calls: Vec<WriteCall> = Vec::new();
+    let Ok(json) = serde_json::from_str::<Value>(&entry.content) else {
+        let input = serde_json::json!({ "file_path": entry.metadata.get("file_path") });
+        calls.extend(entry.metadata.get("tool_name").map(|tool| WriteCall { tool: tool.clone(), input }));
+        return calls.into_iter().filter(|call| is_write_tool(&call.tool)).collect();
+    };
+    match json["type"].as_str().unwrap_or_default() {
+        // Claude stream-json: {"type":"assistant","message":{"content":[{"type":"tool_use","name":"Edit","input":{...}}]}}
+        "assistant" => {
+            let blocks = json.pointer("/message/content").and_then(Value::as_array);
+            for block in blocks.into_iter().flatten() {
+                if block["type"] == "tool_use" {
+                    calls.extend(block["name"].as_str().map(|tool| WriteCall {
+                        tool: tool.to_string(),
+                        input: block["input"].clone(),
+                    }));
+                }
+            }
+        }
+        // Gemini / Ollama: {"type":"tool_use","tool_name":"write_file","parameters":{...}}
+        "tool_use" => calls.extend(json["tool_name"].as_str().or(json["name"].as_str()).map(|tool| WriteCall {
+            tool: tool.to_string(),
+            input: json.get("parameters").or(json.get("input")).unwrap_or(&json).clone(),
+        })),
+        // Cursor: {"type":"tool_call","subtype":"started","tool_call":{"editToolCall":{"args":{...}}}}
+        "tool_call" if json["subtype"] != "completed" => {
+            let tools = json["tool_call"].as_object().into_iter().flatten();
+            calls.extend(tools.map(|(tool, input)| WriteCall {
+                tool: tool.clone(),
+                input: input.clone(),
+            }));
+        }
+        _ => {}
+    }
+    calls.into_iter().filter(|call| is_write_tool(&call.tool)).collect()
+}
+
+fn write_tools(entry: &StructuredLogEntry) -> Vec<String> {
+    write_calls(entry).into_iter().map(|call| call.tool).collect()
+}
+
+/// Collect the file-changing tools a ticket's run calls until `done` fires (or is dropped)
+async fn track_writes(
+    ticket_id: String,
+    mut log_rx: broadcast::Receiver<StructuredLogEntry>,
+    mut done: oneshot::Receiver<()>,
+) -> Vec<String> {
+    let mut writes = Vec::new();
+    loop {
+        tokio::select! {
+            received = log_rx.recv() => match received {
+                Ok(entry) if entry.ticket_id == ticket_id => writes.extend(write_tools(&entry)),
+                Ok(_) => {}
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    debug!("Write tracker for ticket {} skipped {} entries", ticket_id, skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            _ = &mut done => {
+                while let Ok(entry) = log_rx.try_recv() {
+                    if entry.ticket_id == ticket_id {
+                        writes.extend(write_tools(&entry));
+                    }
+                }
+                break;
+            }
+        }
+    }
+    writes
+}
+
+/// Ways the result of `request` ignored its mode; `writes` are the file-changing tools it called
+pub fn check(request: &CodeAnalysisRequest, result: &str, writes: &[String]) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    if !writes.is_empty() && request.tool_policy.read_only() {
+        let mut tools = writes.to_vec();
+        tools.sort();
+        tools.dedup();
+        violations.push(Violation::new(
+            ViolationKind::FileWrite,
+            format!("Called {} in a read-only run", tools.join(", ")),
+        ));
+    }
+    if result.trim().is_empty() {
+        violations.push(Violation::new(ViolationKind::EmptyResult, "The agent returned no answer"));
+        return violations;
+    }
+    match request.mode {
+        AnalysisMode::Ask => {}
+        AnalysisMode::TestCases => {
+            if let Err(e) = crate::test_cases::parse_test_cases(result) {
+                violations.push(Violation::new(ViolationKind::MissingTestCases, e.to_string()));
+            }
+        }
+        AnalysisMode::Diagram => {
+            if let Err(e) = crate::diagram::extract(result) {
+                violations.push(Violation::new(ViolationKind::MissingDiagram, e.to_string()));
+            }
+        }
+    }
+    violations
+}
+
+/// The question of the retry: what went wrong, then the original question
+fn corrective_question(question: &str, violations: &[Violation]) -> String {
+    let mut corrections: Vec<&str> = violations.iter().map(Violation::correction).collect();
+    corrections.dedup();
+    format!("{}\n\n{}", corrections.join("\n"), question)
+}
+
+/// Run the agent once, then check its output and record the violations on its session
+async fn run_checked(
+    state: &AppState,
+    code_agent: &Arc<dyn CodeAgent>,
+    request: &CodeAnalysisRequest,
+    cancel: CancellationToken,
+) -> (Result<CodeAnalysisResponse>, Vec<Violation>) {
+    let (done_tx, done_rx) = oneshot::channel();
+    let tracker = state.tasks.spawn(
+        format!("write-tracker {}", request.ticket_id),
+        track_writes(request.ticket_id.clone(), state.msg_store.subscribe(), done_rx),
+    );
+    let outcome = code_agent
+        .analyze_code(request.clone(), state.msg_store.clone(), state.database.clone(), cancel)
+        .await;
+    let _ = done_tx.send(());
+    let writes = tracker.await.unwrap_or_default();
+
+    let violations = match &outcome {
+        Ok(response) => check(request, &response.result, &writes),
+        Err(_) => Vec::new(),
+    };
+    if !violations.is_empty() {
+        record(state, request, &violations).await;
+    }
+    (outcome, violations)
+}
+
+async fn record(state: &AppState, request: &CodeAnalysisRequest, violations: &[Violation]) {
+    let ticket_id = &request.ticket_id;
+    for violation in violations {
+        let content = format!("⚠️ Kết quả vi phạm yêu cầu của mode: {}", violation.detail);
+        let kind = serde_json::to_value(violation.kind).ok();
+        let tag = ("output_violation", kind.as_ref().and_then(Value::as_str).unwrap_or_default());
+        state
+            .msg_store
+            .push(preflight::log_entry(ticket_id, LogMessageType::System, content, tag))
+            .await;
+    }
+
+    let json = serde_json::to_string(violations).unwrap_or_default();
+    match state.database.get_latest_session_by_ticket(ticket_id).await {
+        Ok(Some(session)) => {
+            if let Err(e) = state.database.set_session_output_violations(&session.id, &json).await {
+                error!("Failed to record output violations of session {}: {}", session.id, e);
+            }
+        }
+        Ok(None) => {}
+        Err(e) => error!("Failed to get the session of ticket {}: {}", ticket_id, e),
+    }
+
+    warn!("⚠️ Ticket {} có {} vi phạm trong kết quả", ticket_id, violations.len());
+    let _ = state.broadcast_tx.send(crate::BroadcastMessage {
+        ticket_id: ticket_id.clone(),
+        message_type: VIOLATION_EVENT.to_string(),
+        content: serde_json::json!({ "run_id": request.run_id, "violations": violations }).to_string(),
+        timestamp: chrono::Utc::now(),
+        org_id: None,
+        changed_files: None,
+    });
+}
+
+/// Run the agent on `request` and validate its output as configured. A retry that fails keeps
+/// the first result; replays are never retried, so they reproduce the original prompt.
+pub async fn analyze(
+    state: &AppState,
+    code_agent: &Arc<dyn CodeAgent>,
+    request: CodeAnalysisRequest,
+    cancel: CancellationToken,
+) -> Result<CodeAnalysisResponse> {
+    let config = state.output_validation;
+    if config.mode == ValidationMode::Off {
+        return code_agent
+            .analyze_code(request, state.msg_store.clone(), state.database.clone(), cancel)
+            .await;
+    }
+
+    let (outcome, violations) = run_checked(state, code_agent, &request, cancel.clone()).await;
+    let first = match outcome {
+        Ok(response) if !violations.is_empty() => response,
+        outcome => return outcome,
+    };
+    if config.mode != ValidationMode::Retry || request.replay.is_some() || cancel.is_cancelled() {
+        return Ok(first);
+    }
+
+    info!("🔁 Chạy lại phân tích ticket {} với lời nhắc sửa lỗi", request.ticket_id);
+    let retry = CodeAnalysisRequest {
+        question: corrective_question(&request.question, &violations),
+        ..request.clone()
+    };
+    match run_checked(state, code_agent, &retry, cancel).await.0 {
+        Err(e) if !e.is::<crate::code_agent::AnalysisCancelled>() => {
+            warn!("⚠️ Lần chạy lại của ticket {} lỗi, giữ kết quả đầu: {}", request.ticket_id, e);
+            Ok(first)
+        }
+        outcome => outcome,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tool_policy::{PolicySource, ToolPolicy};
+    use std::collections::HashMap;
+
+    fn request(mode: AnalysisMode) -> CodeAnalysisRequest {
+        serde_json::from_value(serde_json::json!({
+            "ticket_id": "t1",
+            "code_context": "",
+            "question": "How does checkout work?",
+            "project_id": "p1",
+            "mode": mode,
+        }))
+        .unwrap()
+    }
+
+    fn entry(content: &str) -> StructuredLogEntry {
+        StructuredLogEntry {
+            id: "1".to_string(),
+            ticket_id: "t1".to_string(),
+            message_type: LogMessageType::ToolUse,
+            content: content.to_string(),
+            raw_log: None,
+            metadata: HashMap::new(),
+            timestamp: chrono::Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_write_tools() {
+        let claude = entry(
+            r#"{"type":"assistant","message":{"content":[{"type":"tool_use","name":"Read","input":{}},{"type":"tool_use","name":"MultiEdit","input":{}}]}}"#,
+        );
+        assert_eq!(write_tools(&claude), vec!["MultiEdit"]);
+        assert_eq!(write_tools(&entry(r#"{"type":"tool_use","tool_name":"write_file","parameters":{}}"#)), vec!["write_file"]);
+        assert_eq!(
+            write_tools(&entry(r#"{"type":"tool_call","subtype":"started","tool_call":{"editToolCall":{"args":{}}}}"#)),
+            vec!["editToolCall"]
+        );
+        assert!(write_tools(&entry(r#"{"type":"tool_use","tool_name":"read_file","parameters":{}}"#)).is_empty());
+        assert!(!is_write_tool("Grep"));
+    }
+
+    #[test]
+    fn test_check() {
+        let ask = request(AnalysisMode::Ask);
+        assert!(check(&ask, "Checkout goes through PaymentService.", &[]).is_empty());
+
+        let writes = vec!["Edit".to_string(), "Edit".to_string()];
+        let violations = check(&ask, "Fixed it.", &writes);
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].kind, ViolationKind::FileWrite);
+        assert_eq!(violations[0].detail, "Called Edit in a read-only run");
+
+        // Projects allowing edits may edit
+        let editable = CodeAnalysisRequest {
+            tool_policy: ToolPolicy {
+                allowed_tools: vec!["Read".to_string(), "Edit".to_string()],
+                source: PolicySource::Project,
+            },
+            ..ask.clone()
+        };
+        assert!(check(&editable, "Fixed it.", &writes).is_empty());
+
+        let kinds = |mode: AnalysisMode, result: &str| -> Vec<ViolationKind> {
+            check(&request(mode), result, &[]).into_iter().map(|v| v.kind).collect()
+        };
+        assert_eq!(kinds(AnalysisMode::Ask, "  "), vec![ViolationKind::EmptyResult]);
+        assert_eq!(kinds(AnalysisMode::TestCases, "Here is how checkout works."), vec![ViolationKind::MissingTestCases]);
+        assert_eq!(kinds(AnalysisMode::Diagram, "Here is how checkout works."), vec![ViolationKind::MissingDiagram]);
+        assert!(kinds(AnalysisMode::Diagram, "```mermaid\nsequenceDiagram\n  A->>B: pay\n```").is_empty());
+
+        let question = corrective_question(&ask.question, &violations);
+        assert!(question.starts_with("Your previous attempt created or modified files."));
+        assert!(question.ends_with("How does checkout work?"));
+    }
+}
@@ -0,0 +1,246 @@
+//! Per-project commands run after an analysis that could edit the project (one whose tool
+//! policy allows more than reading), e.g. `npm test` or a notification script
+//! (`GET/PUT /api/projects/:id/post-run-hooks`)
+
+use crate::activity;
+use crate::code_agent::CodeAnalysisRequest;
+use crate::database::{Database, PostRunHooksRecord};
+use crate::message_store::LogMessageType;
+use crate::preflight::{self, PreflightConfig};
+use crate::AppState;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Instant;
+use tracing::{error, info, warn};
+
+/// Hooks a project may configure
+pub const MAX_HOOKS: usize = 10;
+
+/// Status a ticket goes back to when a hook fails and the project asks for it
+const REOPEN_STATUS: &str = "in-progress";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PostRunHook {
+    /// Run with `sh -c` in the project directory; `TICKET_ID`, `RUN_ID` and `PROJECT_ID` are set
+    pub command: String,
+}
+
+/// A project's hooks, as `GET/PUT /api/projects/:id/post-run-hooks` exchange them
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PostRunHooks {
+    pub hooks: Vec<PostRunHook>,
+    #[serde(default)]
+    pub reopen_on_failure: bool,
+}
+
+/// How one hook went, recorded on the session in `hook_results`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HookResult {
+    pub command: String,
+    /// None when it didn't finish: killed by a signal, timed out or couldn't start (`error`)
+    pub exit_code: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub duration_ms: u64,
+}
+
+impl HookResult {
+    pub fn succeeded(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+}
+
+/// Check an admin-supplied list before it is stored
+pub fn validate(hooks: &PostRunHooks, config: &PreflightConfig) -> Result<(), String> {
+    if hooks.hooks.len() > MAX_HOOKS {
+        return Err(format!("At most {} post-run hooks are allowed", MAX_HOOKS));
+    }
+    if hooks.hooks.iter().any(|hook| hook.command.trim().is_empty()) {
+        return Err("A hook needs a command".to_string());
+    }
+    if !hooks.hooks.is_empty() && !config.allow_commands {
+        return Err("Post-run hooks are disabled on this server (PREFLIGHT_ALLOW_COMMANDS)".to_string());
+    }
+    Ok(())
+}
+
+pub async fn load(database: &Database, project_id: &str) -> Result<PostRunHooks> {
+    match database.get_project_post_run_hooks(project_id).await? {
+        Some(record) => Ok(PostRunHooks {
+            hooks: serde_json::from_str(&record.hooks)?,
+            reopen_on_failure: record.reopen_on_failure,
+        }),
+        None => Ok(PostRunHooks::default()),
+    }
+}
+
+pub async fn save(database: &Database, project_id: &str, hooks: &PostRunHooks) -> Result<()> {
+    if hooks.hooks.is_empty() {
+        return database.set_project_post_run_hooks(project_id, None).await;
+    }
+    let record = PostRunHooksRecord {
+        project_id: project_id.to_string(),
+        hooks: serde_json::to_string(&hooks.hooks)?,
+        reopen_on_failure: hooks.reopen_on_failure,
+        updated_at: chrono::Utc::now().to_rfc3339(),
+    };
+    database.set_project_post_run_hooks(project_id, Some(&record)).await
+}
+
+/// Run the project's hooks after a successful run of `request`, when it could edit the
+/// project. Every hook runs, in order; their output goes to the ticket's log and their exit
+/// codes onto the run's session. Replays are skipped: their edits land in a throwaway checkout.
+pub async fn after_run(state: &AppState, request: &CodeAnalysisRequest) {
+    if request.tool_policy.read_only() || request.replay.is_some() {
+        return;
+    }
+    let database = &state.database;
+    let hooks = match load(database, &request.project_id).await {
+        Ok(hooks) if !hooks.hooks.is_empty() => hooks,
+        Ok(_) => return,
+        Err(e) => {
+            error!("Failed to load post-run hooks of project {}: {}", request.project_id, e);
+            return;
+        }
+    };
+    let ticket_id = &request.ticket_id;
+    let msg_store = &state.msg_store;
+    if !state.preflight.allow_commands {
+        warn!("⚠️ Bỏ qua post-run hook của project {}: PREFLIGHT_ALLOW_COMMANDS đang tắt", request.project_id);
+        return;
+    }
+    let Some(dir) = crate::code_agent::working_directory(request, database).await else {
+        return;
+    };
+
+    let mut results = Vec::new();
+    for hook in &hooks.hooks {
+        let label = format!("`{}`", hook.command);
+        let tag = ("post_run", hook.command.as_str());
+        msg_store
+            .push(preflight::log_entry(ticket_id, LogMessageType::System, format!("🪝 Chạy post-run hook {}", label), tag))
+            .await;
+
+        let started = Instant::now();
+        let env = [
+            ("TICKET_ID", ticket_id.as_str()),
+            ("RUN_ID", request.run_id.as_deref().unwrap_or_default()),
+            ("PROJECT_ID", request.project_id.as_str()),
+        ];
+        let outcome =
+            preflight::run_command(state.preflight.timeout, &hook.command, Path::new(&dir), &env, ticket_id, tag, msg_store)
+                .await;
+        let result = HookResult {
+            command: hook.command.clone(),
+            exit_code: outcome.as_ref().ok().copied().flatten(),
+            error: outcome.err(),
+            duration_ms: started.elapsed().as_millis() as u64,
+        };
+
+        let (message_type, content) = match (&result.exit_code, &result.error) {
+            (Some(0), _) => (LogMessageType::System, format!("✅ Post-run hook {} thành công", label)),
+            (Some(code), _) => (LogMessageType::Error, format!("❌ Post-run hook {} thoát với mã {}", label, code)),
+            (None, Some(error)) => (LogMessageType::Error, format!("❌ Post-run hook {}: {}", label, error)),
+            (None, None) => (LogMessageType::Error, format!("❌ Post-run hook {} bị dừng bởi signal", label)),
+        };
+        msg_store.push(preflight::log_entry(ticket_id, message_type, content, tag)).await;
+        results.push(result);
+    }
+
+    record_results(database, ticket_id, &results).await;
+
+    let failed = results.iter().filter(|result| !result.succeeded()).count();
+    info!("🪝 {} post-run hook của ticket {} đã chạy, {} lỗi", results.len(), ticket_id, failed);
+    if failed > 0 && hooks.reopen_on_failure {
+        reopen(state, ticket_id).await;
+    }
+}
+
+async fn record_results(database: &Database, ticket_id: &str, results: &[HookResult]) {
+    let session = match database.get_latest_session_by_ticket(ticket_id).await {
+        Ok(Some(session)) => session,
+        Ok(None) => return,
+        Err(e) => {
+            error!("Failed to get the session of ticket {}: {}", ticket_id, e);
+            return;
+        }
+    };
+    let recorded = match serde_json::to_string(results) {
+        Ok(json) => database.set_session_hook_results(&session.id, &json).await,
+        Err(e) => Err(e.into()),
+    };
+    if let Err(e) = recorded {
+        error!("Failed to record hook results of session {}: {}", session.id, e);
+    }
+}
+
+async fn reopen(state: &AppState, ticket_id: &str) {
+    let ticket = match state.database.get_ticket(ticket_id).await {
+        Ok(Some(ticket)) if ticket.status != REOPEN_STATUS => ticket,
+        Ok(_) => return,
+        Err(e) => {
+            error!("Failed to get ticket {}: {}", ticket_id, e);
+            return;
+        }
+    };
+    if let Err(e) = state.database.update_ticket_status(ticket_id, REOPEN_STATUS).await {
+        error!("Failed to reopen ticket {}: {}", ticket_id, e);
+        return;
+    }
+    activity::status_changed(&state.database, ticket_id, None, &ticket.status, REOPEN_STATUS).await;
+
+    info!("↩️ Ticket {} quay lại {} vì post-run hook lỗi", ticket_id, REOPEN_STATUS);
+    let _ = state.broadcast_tx.send(crate::BroadcastMessage {
+        ticket_id: ticket_id.to_string(),
+        message_type: "ticket-status-updated".to_string(),
+        content: REOPEN_STATUS.to_string(),
+        timestamp: chrono::Utc::now(),
+        org_id: None,
+        changed_files: None,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_validate() {
+        let config = PreflightConfig {
+            allow_commands: true,
+            timeout: Duration::from_secs(5),
+        };
+        let hooks: PostRunHooks =
+            serde_json::from_value(serde_json::json!({ "hooks": [{ "command": "npm test" }] })).unwrap();
+        assert!(!hooks.reopen_on_failure);
+        assert!(validate(&hooks, &config).is_ok());
+        assert!(validate(&hooks, &PreflightConfig { allow_commands: false, ..config }).is_err());
+        // Removing hooks is always allowed
+        assert!(validate(&PostRunHooks::default(), &PreflightConfig { allow_commands: false, ..config }).is_ok());
+
+        let blank = PostRunHooks {
+            hooks: vec![PostRunHook { command: " ".to_string() }],
+            reopen_on_failure: true,
+        };
+        assert!(validate(&blank, &config).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_save_and_load() {
+        let db = crate::test_support::test_db().await.unwrap();
+        db.create_project(&crate::test_support::project_fixture("p1")).await.unwrap();
+
+        assert_eq!(load(&db, "p1").await.unwrap(), PostRunHooks::default());
+        let hooks = PostRunHooks {
+            hooks: vec![PostRunHook { command: "npm test".to_string() }],
+            reopen_on_failure: true,
+        };
+        save(&db, "p1", &hooks).await.unwrap();
+        assert_eq!(load(&db, "p1").await.unwrap(), hooks);
+
+        save(&db, "p1", &PostRunHooks::default()).await.unwrap();
+        assert!(db.get_project_post_run_hooks("p1").await.unwrap().is_none());
+    }
+}
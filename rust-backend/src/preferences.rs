@@ -0,0 +1,186 @@
+use crate::agent_factory::AgentType;
+use crate::database::{Database, UserPreferencesRecord};
+use crate::notifications::EmailMode;
+use chrono::Utc;
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+/// Longest response language kept, e.g. `Vietnamese` or `pt-BR`
+pub const MAX_LANGUAGE_CHARS: usize = 50;
+
+/// Where a user is told about their analyses; only email can be switched off per user today
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NotificationChannels {
+    pub email: EmailMode,
+}
+
+/// A user's defaults, for `GET/PUT /api/me/preferences`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UserPreferences {
+    /// Agent id (`GET /api/agents`) for analyses started without one
+    pub default_agent: Option<String>,
+    /// Language the agent is asked to answer in, e.g. `Vietnamese`
+    pub response_language: Option<String>,
+    /// IANA name, e.g. `Asia/Ho_Chi_Minh`, for times in reports
+    pub timezone: Option<String>,
+    #[serde(default)]
+    pub notification_channels: NotificationChannels,
+    /// Project the CLI asks about when none is given
+    pub default_project_id: Option<String>,
+    #[serde(default, skip_deserializing)]
+    pub updated_at: Option<String>,
+}
+
+impl UserPreferences {
+    /// The timezone to show times in, None for UTC
+    pub fn tz(&self) -> Option<Tz> {
+        self.timezone.as_deref().and_then(|tz| tz.parse().ok())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PreferencesError {
+    #[error("Unknown agent '{0}'")]
+    UnknownAgent(String),
+
+    #[error("Unknown timezone '{0}'; expected an IANA name such as Asia/Ho_Chi_Minh")]
+    UnknownTimezone(String),
+
+    #[error("Response language is longer than {} characters", MAX_LANGUAGE_CHARS)]
+    LanguageTooLong,
+
+    #[error("Unknown project '{0}'")]
+    UnknownProject(String),
+
+    #[error(transparent)]
+    Database(#[from] anyhow::Error),
+}
+
+/// The user's preferences, defaults when never set
+pub async fn load(db: &Database, user_id: &str) -> anyhow::Result<UserPreferences> {
+    let record = db.get_user_preferences(user_id).await?;
+    let email = db
+        .get_email_mode(user_id)
+        .await?
+        .and_then(|mode| EmailMode::parse(&mode))
+        .unwrap_or_default();
+
+    let record = record.unwrap_or_default();
+    Ok(UserPreferences {
+        default_agent: record.default_agent,
+        response_language: record.response_language,
+        timezone: record.timezone,
+        notification_channels: NotificationChannels { email },
+        default_project_id: record.default_project_id,
+        updated_at: (!record.updated_at.is_empty()).then_some(record.updated_at),
+    })
+}
+
+/// Replace the user's preferences. `default_project_id` must already be checked to be a
+/// project the user can see.
+pub async fn save(db: &Database, user_id: &str, preferences: UserPreferences) -> Result<UserPreferences, PreferencesError> {
+    let trimmed = |value: Option<String>| value.map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+
+    let default_agent = match trimmed(preferences.default_agent) {
+        Some(agent) => match AgentType::from_str(&agent) {
+            Some(agent_type) => Some(agent_type.id().to_string()),
+            None => return Err(PreferencesError::UnknownAgent(agent)),
+        },
+        None => None,
+    };
+    let response_language = trimmed(preferences.response_language);
+    if response_language.as_ref().is_some_and(|l| l.chars().count() > MAX_LANGUAGE_CHARS) {
+        return Err(PreferencesError::LanguageTooLong);
+    }
+    let timezone = trimmed(preferences.timezone);
+    if let Some(tz) = &timezone {
+        if tz.parse::<Tz>().is_err() {
+            return Err(PreferencesError::UnknownTimezone(tz.clone()));
+        }
+    }
+
+    db.set_user_preferences(&UserPreferencesRecord {
+        user_id: user_id.to_string(),
+        default_agent,
+        response_language,
+        timezone,
+        default_project_id: trimmed(preferences.default_project_id),
+        updated_at: Utc::now().to_rfc3339(),
+    })
+    .await?;
+    db.set_email_mode(user_id, preferences.notification_channels.email.as_str())
+        .await?;
+
+    Ok(load(db, user_id).await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::UserRecord;
+    use crate::test_support::test_db;
+
+    #[tokio::test]
+    async fn test_save_and_load() {
+        let db = test_db().await.unwrap();
+        db.create_user(&UserRecord {
+            id: "u1".to_string(),
+            org_id: "default".to_string(),
+            email: "qa@example.com".to_string(),
+            name: "QA".to_string(),
+            password_hash: None,
+            role: "member".to_string(),
+            created_at: Utc::now().to_rfc3339(),
+        })
+        .await
+        .unwrap();
+
+        // Never set: all defaults
+        let defaults = load(&db, "u1").await.unwrap();
+        assert_eq!(defaults.default_agent, None);
+        assert_eq!(defaults.notification_channels.email, EmailMode::Immediate);
+        assert!(defaults.updated_at.is_none());
+
+        let saved = save(
+            &db,
+            "u1",
+            UserPreferences {
+                default_agent: Some(" Anthropic ".to_string()),
+                response_language: Some("Vietnamese".to_string()),
+                timezone: Some("Asia/Ho_Chi_Minh".to_string()),
+                notification_channels: NotificationChannels { email: EmailMode::Digest },
+                default_project_id: Some(String::new()),
+                updated_at: None,
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(saved.default_agent.as_deref(), Some("claude-api"));
+        assert_eq!(saved.response_language.as_deref(), Some("Vietnamese"));
+        assert_eq!(saved.tz(), Some(chrono_tz::Asia::Ho_Chi_Minh));
+        assert_eq!(saved.notification_channels.email, EmailMode::Digest);
+        assert_eq!(saved.default_project_id, None);
+        assert!(saved.updated_at.is_some());
+        assert_eq!(db.get_email_mode("u1").await.unwrap().as_deref(), Some("digest"));
+
+        let invalid = |preferences: UserPreferences| save(&db, "u1", preferences);
+        assert!(matches!(
+            invalid(UserPreferences {
+                default_agent: Some("copilot".to_string()),
+                ..Default::default()
+            })
+            .await,
+            Err(PreferencesError::UnknownAgent(_))
+        ));
+        assert!(matches!(
+            invalid(UserPreferences {
+                timezone: Some("Mars/Olympus".to_string()),
+                ..Default::default()
+            })
+            .await,
+            Err(PreferencesError::UnknownTimezone(_))
+        ));
+        // A rejected update changes nothing
+        assert_eq!(load(&db, "u1").await.unwrap().response_language.as_deref(), Some("Vietnamese"));
+    }
+}
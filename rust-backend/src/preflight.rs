@@ -0,0 +1,335 @@
+//! Per-project checks run before an agent spawns, so a run isn't paid for when the project
+//! isn't in the state it should be analyzed in (`GET/PUT /api/projects/:id/preflight`)
+
+use crate::message_store::{LogMessageType, MsgStore, StructuredLogEntry};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Component, Path};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::Command;
+use tracing::info;
+
+/// Checks a project may configure
+pub const MAX_CHECKS: usize = 20;
+
+/// Output lines of one command streamed to the ticket's log; the rest is counted only
+const MAX_OUTPUT_LINES: usize = 200;
+
+/// Changed files named in a failed `git-clean` check
+const MAX_LISTED_FILES: usize = 5;
+
+#[derive(Debug, Clone, Copy)]
+pub struct PreflightConfig {
+    /// `PREFLIGHT_ALLOW_COMMANDS`: whether projects may run shell commands on the server
+    pub allow_commands: bool,
+    /// Longest a `command` check may run
+    pub timeout: Duration,
+}
+
+impl PreflightConfig {
+    pub fn from_env() -> Self {
+        Self {
+            allow_commands: std::env::var("PREFLIGHT_ALLOW_COMMANDS")
+                .map(|v| matches!(v.trim().to_lowercase().as_str(), "1" | "true" | "yes"))
+                .unwrap_or(false),
+            timeout: Duration::from_secs(
+                std::env::var("PREFLIGHT_TIMEOUT_SECS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .filter(|n| *n > 0)
+                    .unwrap_or(300),
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum PreflightCheck {
+    /// No uncommitted changes or untracked files
+    GitClean,
+    /// The checked-out branch is `branch`
+    Branch { branch: String },
+    /// `path`, relative to the project directory, exists
+    PathExists { path: String },
+    /// `sh -c command` in the project directory exits with 0
+    Command { command: String },
+}
+
+impl PreflightCheck {
+    pub fn label(&self) -> String {
+        match self {
+            PreflightCheck::GitClean => "git-clean".to_string(),
+            PreflightCheck::Branch { branch } => format!("branch {}", branch),
+            PreflightCheck::PathExists { path } => format!("path-exists {}", path),
+            PreflightCheck::Command { command } => format!("command `{}`", command),
+        }
+    }
+}
+
+/// Returned when a check fails; the analysis ends without running the agent
+#[derive(Debug, thiserror::Error)]
+#[error("Pre-flight check {check} failed: {reason}")]
+pub struct PreflightFailed {
+    pub check: String,
+    pub reason: String,
+}
+
+/// Check an admin-supplied list before it is stored
+pub fn validate(checks: &[PreflightCheck], config: &PreflightConfig) -> Result<(), String> {
+    if checks.len() > MAX_CHECKS {
+        return Err(format!("At most {} pre-flight checks are allowed", MAX_CHECKS));
+    }
+    for check in checks {
+        match check {
+            PreflightCheck::GitClean => {}
+            PreflightCheck::Branch { branch } if branch.trim().is_empty() => {
+                return Err("A branch check needs a branch".to_string());
+            }
+            PreflightCheck::Branch { .. } => {}
+            PreflightCheck::PathExists { path } => {
+                let relative = Path::new(path.trim());
+                if path.trim().is_empty() || !relative.components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir)) {
+                    return Err(format!("'{}' must be a path inside the project directory", path));
+                }
+            }
+            PreflightCheck::Command { command } if command.trim().is_empty() => {
+                return Err("A command check needs a command".to_string());
+            }
+            PreflightCheck::Command { .. } if !config.allow_commands => {
+                return Err("Command checks are disabled on this server (PREFLIGHT_ALLOW_COMMANDS)".to_string());
+            }
+            PreflightCheck::Command { .. } => {}
+        }
+    }
+    Ok(())
+}
+
+/// Log entry tagged with the check or hook it belongs to, e.g. `("preflight", "git-clean")`
+pub(crate) fn log_entry(
+    ticket_id: &str,
+    message_type: LogMessageType,
+    content: String,
+    (key, label): (&str, &str),
+) -> StructuredLogEntry {
+    StructuredLogEntry {
+        id: uuid::Uuid::new_v4().to_string(),
+        ticket_id: ticket_id.to_string(),
+        message_type,
+        content,
+        raw_log: None,
+        metadata: HashMap::from([(key.to_string(), label.to_string())]),
+        timestamp: chrono::Utc::now(),
+    }
+}
+
+/// Run `checks` in order in `dir`, logging each to the ticket; stops at the first failure
+pub async fn run(
+    config: &PreflightConfig,
+    checks: &[PreflightCheck],
+    dir: &Path,
+    ticket_id: &str,
+    msg_store: &MsgStore,
+) -> Result<(), PreflightFailed> {
+    for check in checks {
+        let label = check.label();
+        let tag = ("preflight", label.as_str());
+        msg_store
+            .push(log_entry(ticket_id, LogMessageType::System, format!("🛫 Kiểm tra trước khi phân tích: {}", label), tag))
+            .await;
+
+        let outcome = match check {
+            PreflightCheck::GitClean => git_clean(dir).await,
+            PreflightCheck::Branch { branch } => on_branch(dir, branch).await,
+            PreflightCheck::PathExists { path } => match dir.join(path.trim()).exists() {
+                true => Ok(()),
+                false => Err(format!("{} does not exist", path)),
+            },
+            // Checked again here: the setting may have been turned off since the check was saved
+            PreflightCheck::Command { .. } if !config.allow_commands => {
+                Err("command checks are disabled on this server".to_string())
+            }
+            PreflightCheck::Command { command } => {
+                match run_command(config.timeout, command, dir, &[], ticket_id, tag, msg_store).await {
+                    Ok(Some(0)) => Ok(()),
+                    Ok(Some(code)) => Err(format!("exited with code {}", code)),
+                    Ok(None) => Err("killed by a signal".to_string()),
+                    Err(e) => Err(e),
+                }
+            }
+        };
+
+        match outcome {
+            Ok(()) => {
+                msg_store
+                    .push(log_entry(ticket_id, LogMessageType::System, format!("✅ Đạt: {}", label), tag))
+                    .await;
+            }
+            Err(reason) => {
+                msg_store
+                    .push(log_entry(ticket_id, LogMessageType::Error, format!("❌ Không đạt: {}: {}", label, reason), tag))
+                    .await;
+                return Err(PreflightFailed { check: label, reason });
+            }
+        }
+    }
+    if !checks.is_empty() {
+        info!("🛫 {} kiểm tra trước khi phân tích đều đạt cho ticket {}", checks.len(), ticket_id);
+    }
+    Ok(())
+}
+
+async fn git(dir: &Path, args: &[&str]) -> Result<String, String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["-c", "core.quotePath=false"])
+        .args(args)
+        .output()
+        .await
+        .map_err(|e| format!("cannot run git: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("git {} failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+async fn git_clean(dir: &Path) -> Result<(), String> {
+    let status = git(dir, &["status", "--porcelain"]).await?;
+    let changed: Vec<&str> = status.lines().map(|line| line.get(3..).unwrap_or(line)).collect();
+    if changed.is_empty() {
+        return Ok(());
+    }
+    let mut listed = changed.iter().take(MAX_LISTED_FILES).copied().collect::<Vec<_>>().join(", ");
+    if changed.len() > MAX_LISTED_FILES {
+        listed.push_str(&format!(" and {} more", changed.len() - MAX_LISTED_FILES));
+    }
+    Err(format!("{} uncommitted change(s): {}", changed.len(), listed))
+}
+
+async fn on_branch(dir: &Path, branch: &str) -> Result<(), String> {
+    let current = git(dir, &["rev-parse", "--abbrev-ref", "HEAD"]).await?;
+    match current.trim() {
+        current if current == branch.trim() => Ok(()),
+        "HEAD" => Err(format!("HEAD is detached, expected branch {}", branch.trim())),
+        current => Err(format!("on branch {}, expected {}", current, branch.trim())),
+    }
+}
+
+/// Run `sh -c command` in `dir` with `env` added, streaming its output to the ticket's log.
+/// The exit code, None when killed by a signal; Err when it couldn't start or ran longer
+/// than `timeout`.
+pub(crate) async fn run_command(
+    timeout: Duration,
+    command: &str,
+    dir: &Path,
+    env: &[(&str, &str)],
+    ticket_id: &str,
+    tag: (&str, &str),
+    msg_store: &MsgStore,
+) -> Result<Option<i32>, String> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .current_dir(dir)
+        .envs(env.iter().copied())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("cannot start: {}", e))?;
+
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+    let streamed = async {
+        tokio::join!(
+            stream_lines(stdout, ticket_id, tag, msg_store),
+            stream_lines(stderr, ticket_id, tag, msg_store),
+        );
+        child.wait().await
+    };
+
+    match tokio::time::timeout(timeout, streamed).await {
+        Ok(Ok(status)) => Ok(status.code()),
+        Ok(Err(e)) => Err(format!("cannot wait for the command: {}", e)),
+        Err(_) => Err(format!("timed out after {}s", timeout.as_secs())),
+    }
+}
+
+/// Push each line of `reader` to the ticket's log as system output of the command
+async fn stream_lines(reader: Option<impl AsyncRead + Unpin>, ticket_id: &str, tag: (&str, &str), msg_store: &MsgStore) {
+    let Some(reader) = reader else {
+        return;
+    };
+    let mut lines = BufReader::new(reader).lines();
+    let mut count = 0;
+    while let Ok(Some(line)) = lines.next_line().await {
+        count += 1;
+        if count <= MAX_OUTPUT_LINES {
+            msg_store.push(log_entry(ticket_id, LogMessageType::System, line, tag)).await;
+        } else if count == MAX_OUTPUT_LINES + 1 {
+            msg_store
+                .push(log_entry(ticket_id, LogMessageType::System, "… (output truncated)".to_string(), tag))
+                .await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let config = PreflightConfig {
+            allow_commands: false,
+            timeout: Duration::from_secs(5),
+        };
+        let checks: Vec<PreflightCheck> = serde_json::from_value(serde_json::json!([
+            { "type": "git-clean" },
+            { "type": "branch", "branch": "main" },
+            { "type": "path-exists", "path": "Cargo.toml" },
+        ]))
+        .unwrap();
+        assert!(validate(&checks, &config).is_ok());
+
+        let path = |path: &str| vec![PreflightCheck::PathExists { path: path.to_string() }];
+        assert!(validate(&path("../secrets"), &config).is_err());
+        assert!(validate(&path("/etc/passwd"), &config).is_err());
+
+        let command = vec![PreflightCheck::Command { command: "cargo check".to_string() }];
+        assert!(validate(&command, &config).unwrap_err().contains("PREFLIGHT_ALLOW_COMMANDS"));
+        assert!(validate(&command, &PreflightConfig { allow_commands: true, ..config }).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_run_stops_at_first_failure() {
+        let dir = std::env::temp_dir().join(format!("preflight-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("build.sh"), "").unwrap();
+        let db = std::sync::Arc::new(crate::test_support::test_db().await.unwrap());
+        let store = MsgStore::new(db, &crate::tasks::TaskSupervisor::default());
+        let config = PreflightConfig {
+            allow_commands: true,
+            timeout: Duration::from_secs(5),
+        };
+
+        let checks = vec![
+            PreflightCheck::PathExists { path: "build.sh".to_string() },
+            PreflightCheck::Command { command: "echo building; exit 3".to_string() },
+            PreflightCheck::PathExists { path: "never-checked".to_string() },
+        ];
+        let failed = run(&config, &checks, &dir, "t1", &store).await.unwrap_err();
+        assert_eq!(failed.check, "command `echo building; exit 3`");
+        assert_eq!(failed.reason, "exited with code 3");
+
+        let logs: Vec<String> = store.get_logs("t1").await.into_iter().map(|entry| entry.content).collect();
+        assert!(logs.iter().any(|line| line == "building"));
+        assert!(!logs.iter().any(|line| line.contains("never-checked")));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,207 @@
+//! Who is looking at which ticket right now, from the `view-ticket` / `editing` messages of
+//! `/ws` connections. Kept in memory per instance and gone on restart.
+
+use crate::BroadcastMessage;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// How long an editing indicator lasts without another `editing` message
+pub const EDITING_TTL_SECS: i64 = 8;
+
+/// Longest field name an `editing` message may carry
+const MAX_FIELD_CHARS: usize = 64;
+
+pub const JOINED: &str = "presence-joined";
+pub const LEFT: &str = "presence-left";
+pub const EDITING: &str = "presence-editing";
+
+/// One connection viewing a ticket
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Viewer {
+    pub client_id: String,
+    /// None for anonymous connections
+    pub user_id: Option<String>,
+    pub name: Option<String>,
+    pub since: DateTime<Utc>,
+    /// Field being edited, e.g. `plan` or `description`
+    pub editing: Option<String>,
+    pub editing_until: Option<DateTime<Utc>>,
+}
+
+impl Viewer {
+    pub fn new(client_id: &str, user_id: Option<String>, name: Option<String>, now: DateTime<Utc>) -> Self {
+        Self {
+            client_id: client_id.to_string(),
+            user_id,
+            name,
+            since: now,
+            editing: None,
+            editing_until: None,
+        }
+    }
+
+    fn expire_editing(&mut self, now: DateTime<Utc>) {
+        if self.editing_until.is_some_and(|until| until <= now) {
+            self.editing = None;
+            self.editing_until = None;
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    /// Ticket ID → client ID → viewer
+    tickets: HashMap<String, HashMap<String, Viewer>>,
+    /// Client ID → the one ticket it views
+    clients: HashMap<String, String>,
+}
+
+impl Inner {
+    fn remove(&mut self, client_id: &str) -> Option<(String, Viewer)> {
+        let ticket_id = self.clients.remove(client_id)?;
+        let viewers = self.tickets.get_mut(&ticket_id)?;
+        let viewer = viewers.remove(client_id)?;
+        if viewers.is_empty() {
+            self.tickets.remove(&ticket_id);
+        }
+        Some((ticket_id, viewer))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Presence {
+    inner: Mutex<Inner>,
+}
+
+impl Presence {
+    /// The client now views `ticket_id`, leaving the ticket it viewed before (returned)
+    pub fn join(&self, ticket_id: &str, viewer: Viewer) -> Option<(String, Viewer)> {
+        let mut inner = self.inner.lock().unwrap();
+        let left = match inner.clients.get(&viewer.client_id) {
+            Some(current) if current == ticket_id => return None,
+            Some(_) => inner.remove(&viewer.client_id),
+            None => None,
+        };
+        inner.clients.insert(viewer.client_id.clone(), ticket_id.to_string());
+        inner
+            .tickets
+            .entry(ticket_id.to_string())
+            .or_default()
+            .insert(viewer.client_id.clone(), viewer);
+        left
+    }
+
+    /// The client stopped viewing its ticket, or disconnected
+    pub fn leave(&self, client_id: &str) -> Option<(String, Viewer)> {
+        self.inner.lock().unwrap().remove(client_id)
+    }
+
+    /// Start (or renew) or stop the client's editing indicator. None unless it views `ticket_id`.
+    pub fn editing(&self, client_id: &str, ticket_id: &str, field: Option<&str>, now: DateTime<Utc>) -> Option<Viewer> {
+        let mut inner = self.inner.lock().unwrap();
+        let viewer = inner.tickets.get_mut(ticket_id)?.get_mut(client_id)?;
+        match field.map(str::trim).filter(|field| !field.is_empty()) {
+            Some(field) => {
+                viewer.editing = Some(field.chars().take(MAX_FIELD_CHARS).collect());
+                viewer.editing_until = Some(now + Duration::seconds(EDITING_TTL_SECS));
+            }
+            None => {
+                viewer.editing = None;
+                viewer.editing_until = None;
+            }
+        }
+        Some(viewer.clone())
+    }
+
+    /// Viewers of a ticket, longest-present first
+    pub fn snapshot(&self, ticket_id: &str, now: DateTime<Utc>) -> Vec<Viewer> {
+        let mut inner = self.inner.lock().unwrap();
+        let Some(viewers) = inner.tickets.get_mut(ticket_id) else {
+            return Vec::new();
+        };
+        let mut snapshot: Vec<Viewer> = viewers
+            .values_mut()
+            .map(|viewer| {
+                viewer.expire_editing(now);
+                viewer.clone()
+            })
+            .collect();
+        snapshot.sort_by(|a, b| a.since.cmp(&b.since).then_with(|| a.client_id.cmp(&b.client_id)));
+        snapshot
+    }
+}
+
+/// Presence event for the other viewers of `ticket_id`
+pub fn message(ticket_id: &str, event: &str, viewer: &Viewer) -> BroadcastMessage {
+    BroadcastMessage {
+        ticket_id: ticket_id.to_string(),
+        message_type: event.to_string(),
+        content: serde_json::to_string(viewer).unwrap_or_default(),
+        timestamp: Utc::now(),
+        org_id: None,
+        changed_files: None,
+    }
+}
+
+/// Whether a broadcast should reach the connection `client_id`, which views `viewing`:
+/// presence events go only to the other viewers of their ticket
+pub fn delivers_to(message: &BroadcastMessage, client_id: &str, viewing: Option<&str>) -> bool {
+    if ![JOINED, LEFT, EDITING].contains(&message.message_type.as_str()) {
+        return true;
+    }
+    if viewing != Some(message.ticket_id.as_str()) {
+        return false;
+    }
+    serde_json::from_str::<serde_json::Value>(&message.content)
+        .map(|viewer| viewer["client_id"] != client_id)
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_join_edit_leave() {
+        let presence = Presence::default();
+        let now = Utc::now();
+        let viewer = |client: &str| Viewer::new(client, Some(format!("user-{}", client)), None, now);
+
+        assert!(presence.join("t1", viewer("a")).is_none());
+        assert!(presence.join("t1", viewer("b")).is_none());
+        assert_eq!(presence.snapshot("t1", now).len(), 2);
+
+        // Editing needs the client to view the ticket, and expires unless renewed
+        assert!(presence.editing("a", "t2", Some("plan"), now).is_none());
+        let editing = presence.editing("a", "t1", Some("plan"), now).unwrap();
+        assert_eq!(editing.editing.as_deref(), Some("plan"));
+        let later = now + Duration::seconds(EDITING_TTL_SECS + 1);
+        assert!(presence.snapshot("t1", later).iter().all(|v| v.editing.is_none()));
+
+        // Viewing another ticket leaves the first
+        let (left, viewer_a) = presence.join("t2", viewer("a")).unwrap();
+        assert_eq!((left.as_str(), viewer_a.client_id.as_str()), ("t1", "a"));
+        assert_eq!(presence.snapshot("t1", now).len(), 1);
+
+        assert_eq!(presence.leave("b").map(|(ticket, _)| ticket).as_deref(), Some("t1"));
+        assert!(presence.snapshot("t1", now).is_empty());
+        assert!(presence.leave("b").is_none());
+    }
+
+    #[test]
+    fn test_delivers_to_other_viewers_only() {
+        let viewer = Viewer::new("a", None, None, Utc::now());
+        let joined = message("t1", JOINED, &viewer);
+
+        assert!(delivers_to(&joined, "b", Some("t1")));
+        assert!(!delivers_to(&joined, "a", Some("t1")));
+        assert!(!delivers_to(&joined, "b", Some("t2")));
+        assert!(!delivers_to(&joined, "b", None));
+
+        let mut status = joined.clone();
+        status.message_type = "ticket-status-updated".to_string();
+        assert!(delivers_to(&status, "b", None));
+    }
+}
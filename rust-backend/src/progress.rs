@@ -0,0 +1,284 @@
+use crate::database::Database;
+use crate::message_store::{LogMessageType, StructuredLogEntry};
+use crate::BroadcastMessage;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::sync::{broadcast, oneshot};
+use tokio::time::Instant;
+use tracing::{debug, error};
+
+/// Assistant text at least this long is treated as the final answer being written
+const ANSWER_MIN_CHARS: usize = 400;
+
+/// Coarse analysis stages derived from the normalized event stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnalysisStage {
+    Init,
+    ExploringFiles,
+    Reasoning,
+    WritingAnswer,
+}
+
+impl AnalysisStage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Init => "init",
+            Self::ExploringFiles => "exploring_files",
+            Self::Reasoning => "reasoning",
+            Self::WritingAnswer => "writing_answer",
+        }
+    }
+
+    /// Vietnamese label shown by the UI
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Init => "Đang khởi động",
+            Self::ExploringFiles => "Đang đọc source code",
+            Self::Reasoning => "Đang suy luận",
+            Self::WritingAnswer => "Đang viết câu trả lời",
+        }
+    }
+
+    /// Stage implied by a single log entry, if any
+    pub fn from_entry(entry: &StructuredLogEntry) -> Option<Self> {
+        if matches!(entry.message_type, LogMessageType::ToolUse) {
+            return Some(Self::ExploringFiles);
+        }
+
+        match serde_json::from_str::<Value>(&entry.content) {
+            Ok(json) => Self::from_json_event(&json),
+            Err(_) => None,
+        }
+    }
+
+    fn from_json_event(json: &Value) -> Option<Self> {
+        let event_type = json.get("type").and_then(|v| v.as_str()).unwrap_or("");
+
+        match event_type {
+            "init" => Some(Self::Init),
+            "system" if json.get("subtype").and_then(|v| v.as_str()) == Some("init") => Some(Self::Init),
+            "tool_use" | "tool_call" => Some(Self::ExploringFiles),
+            // Claude stream-json: {"type":"assistant","message":{"content":[...]}}
+            "assistant" => {
+                let blocks = json
+                    .pointer("/message/content")
+                    .and_then(|v| v.as_array())?;
+                let block_type = |b: &Value| b.get("type").and_then(|v| v.as_str()).unwrap_or("").to_string();
+
+                if blocks.iter().any(|b| block_type(b) == "tool_use") {
+                    return Some(Self::ExploringFiles);
+                }
+                if blocks.iter().any(|b| block_type(b) == "thinking") {
+                    return Some(Self::Reasoning);
+                }
+                let text_len: usize = blocks
+                    .iter()
+                    .filter_map(|b| b.get("text").and_then(|v| v.as_str()))
+                    .map(|t| t.chars().count())
+                    .sum();
+                Some(Self::from_text_len(text_len))
+            }
+            // Gemini/Cursor: {"type":"message","role":"assistant","content":"..."}
+            "message" if json.get("role").and_then(|v| v.as_str()) == Some("assistant") => {
+                if json.get("delta").and_then(|v| v.as_bool()) == Some(true) {
+                    return Some(Self::WritingAnswer);
+                }
+                let text_len = json
+                    .get("content")
+                    .and_then(|v| v.as_str())
+                    .map(|t| t.chars().count())
+                    .unwrap_or(0);
+                Some(Self::from_text_len(text_len))
+            }
+            _ => None,
+        }
+    }
+
+    fn from_text_len(text_len: usize) -> Self {
+        if text_len >= ANSWER_MIN_CHARS {
+            Self::WritingAnswer
+        } else {
+            Self::Reasoning
+        }
+    }
+}
+
+/// Tracks stage transitions for one ticket's analysis
+#[derive(Debug)]
+pub struct ProgressTracker {
+    current: AnalysisStage,
+    started_at: Instant,
+}
+
+impl ProgressTracker {
+    pub fn new() -> Self {
+        Self {
+            current: AnalysisStage::Init,
+            started_at: Instant::now(),
+        }
+    }
+
+    pub fn current(&self) -> AnalysisStage {
+        self.current
+    }
+
+    pub fn elapsed_ms(&self) -> u64 {
+        self.started_at.elapsed().as_millis() as u64
+    }
+
+    /// Feed an entry; returns the new stage when it differs from the current one
+    pub fn observe(&mut self, entry: &StructuredLogEntry) -> Option<AnalysisStage> {
+        let stage = AnalysisStage::from_entry(entry)?;
+        if stage == self.current {
+            return None;
+        }
+        self.current = stage;
+        Some(stage)
+    }
+}
+
+impl Default for ProgressTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Follow a ticket's log stream and emit `analysis-progress` broadcasts
+/// (plus persisted stage timestamps) until `done` fires or is dropped.
+/// An interactive agent pausing for the user is relayed as `agent-awaiting-input`.
+pub async fn track_progress(
+    ticket_id: String,
+    mut log_rx: broadcast::Receiver<StructuredLogEntry>,
+    broadcast_tx: broadcast::Sender<BroadcastMessage>,
+    database: Arc<Database>,
+    mut done: oneshot::Receiver<()>,
+) {
+    let mut tracker = ProgressTracker::new();
+    let mut session_id: Option<String> = None;
+
+    emit_stage(&ticket_id, &tracker, &mut session_id, &broadcast_tx, &database).await;
+
+    loop {
+        tokio::select! {
+            received = log_rx.recv() => match received {
+                Ok(entry) => {
+                    if entry.ticket_id == ticket_id {
+                        relay_awaiting_input(&entry, &broadcast_tx);
+                    }
+                    if entry.ticket_id == ticket_id && tracker.observe(&entry).is_some() {
+                        emit_stage(&ticket_id, &tracker, &mut session_id, &broadcast_tx, &database).await;
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    debug!("Progress tracker for ticket {} skipped {} entries", ticket_id, skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            _ = &mut done => {
+                // Drain entries pushed right before completion
+                while let Ok(entry) = log_rx.try_recv() {
+                    if entry.ticket_id == ticket_id && tracker.observe(&entry).is_some() {
+                        emit_stage(&ticket_id, &tracker, &mut session_id, &broadcast_tx, &database).await;
+                    }
+                }
+                break;
+            }
+        }
+    }
+}
+
+fn relay_awaiting_input(entry: &StructuredLogEntry, broadcast_tx: &broadcast::Sender<BroadcastMessage>) {
+    let Some(request) = entry.metadata.get(crate::interaction::AWAITING_INPUT_KEY) else {
+        return;
+    };
+    let _ = broadcast_tx.send(BroadcastMessage {
+        ticket_id: entry.ticket_id.clone(),
+        message_type: "agent-awaiting-input".to_string(),
+        content: request.clone(),
+        timestamp: chrono::Utc::now(),
+        org_id: None,
+        changed_files: None,
+    });
+}
+
+async fn emit_stage(
+    ticket_id: &str,
+    tracker: &ProgressTracker,
+    session_id: &mut Option<String>,
+    broadcast_tx: &broadcast::Sender<BroadcastMessage>,
+    database: &Database,
+) {
+    let stage = tracker.current();
+
+    // The agent creates the session before its first log line, so resolve lazily
+    if session_id.is_none() {
+        if let Ok(Some(session)) = database.get_active_session_by_ticket(ticket_id).await {
+            *session_id = Some(session.id);
+        }
+    }
+
+    if let Some(id) = session_id.as_deref() {
+        if let Err(e) = database.record_session_stage(id, stage.as_str()).await {
+            error!("Failed to record stage {} for session {}: {}", stage.as_str(), id, e);
+        }
+    }
+
+    let content = json!({
+        "stage": stage,
+        "label": stage.label(),
+        "elapsed_ms": tracker.elapsed_ms(),
+        "session_id": session_id,
+    });
+
+    let _ = broadcast_tx.send(BroadcastMessage {
+        ticket_id: ticket_id.to_string(),
+        message_type: "analysis-progress".to_string(),
+        content: content.to_string(),
+        timestamp: chrono::Utc::now(),
+        org_id: None,
+        changed_files: None,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log_normalizer::LogNormalizer;
+
+    fn entry(raw: &str) -> StructuredLogEntry {
+        LogNormalizer::new().normalize(raw.to_string(), "test-ticket".to_string())
+    }
+
+    #[test]
+    fn test_stage_from_claude_events() {
+        let init = entry(r#"{"type":"system","subtype":"init","model":"claude"}"#);
+        assert_eq!(AnalysisStage::from_entry(&init), Some(AnalysisStage::Init));
+
+        let tool = entry(r#"{"type":"assistant","message":{"content":[{"type":"tool_use","name":"Read","input":{"file_path":"src/a.rs"}}]}}"#);
+        assert_eq!(AnalysisStage::from_entry(&tool), Some(AnalysisStage::ExploringFiles));
+
+        let short = entry(r#"{"type":"assistant","message":{"content":[{"type":"text","text":"Let me check the router."}]}}"#);
+        assert_eq!(AnalysisStage::from_entry(&short), Some(AnalysisStage::Reasoning));
+
+        let answer = format!(
+            r#"{{"type":"assistant","message":{{"content":[{{"type":"text","text":"{}"}}]}}}}"#,
+            "x".repeat(ANSWER_MIN_CHARS)
+        );
+        assert_eq!(AnalysisStage::from_entry(&entry(&answer)), Some(AnalysisStage::WritingAnswer));
+    }
+
+    #[test]
+    fn test_tracker_reports_only_transitions() {
+        let mut tracker = ProgressTracker::new();
+
+        assert_eq!(tracker.observe(&entry("Reading file: src/main.rs")), Some(AnalysisStage::ExploringFiles));
+        assert_eq!(tracker.observe(&entry("Reading file: src/lib.rs")), None);
+        assert_eq!(
+            tracker.observe(&entry(r#"{"type":"message","role":"assistant","content":"ok","delta":true}"#)),
+            Some(AnalysisStage::WritingAnswer)
+        );
+        assert_eq!(tracker.observe(&entry("plain status line")), None);
+    }
+}
@@ -0,0 +1,446 @@
+use anyhow::{anyhow, Result};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+
+/// Directories never worth sending to a model
+const SKIPPED_DIRS: &[&str] = &[
+    ".git", "node_modules", "target", "dist", "build", ".next", "out", "vendor", "__pycache__",
+    ".venv", "venv", "coverage",
+];
+
+/// Extensions treated as readable source/text files
+const TEXT_EXTENSIONS: &[&str] = &[
+    "rs", "ts", "tsx", "js", "jsx", "mjs", "cjs", "py", "go", "java", "kt", "swift", "rb", "php",
+    "cs", "c", "h", "cpp", "hpp", "scala", "sql", "graphql", "proto", "vue", "svelte", "html",
+    "css", "scss", "json", "yaml", "yml", "toml", "md", "sh", "env", "xml",
+];
+
+/// Upper bound on files visited while walking a project
+const MAX_WALK_FILES: usize = 10_000;
+
+/// A file included in a model prompt
+#[derive(Debug, Clone)]
+pub struct ContextFile {
+    /// Path relative to the project root, with `/` separators
+    pub path: String,
+    pub content: String,
+}
+
+/// Budget for [`collect_context`]
+#[derive(Debug, Clone, Copy)]
+pub struct ContextLimits {
+    pub max_files: usize,
+    pub max_total_bytes: usize,
+    pub max_file_bytes: usize,
+}
+
+impl Default for ContextLimits {
+    fn default() -> Self {
+        Self {
+            max_files: 20,
+            max_total_bytes: 200_000,
+            max_file_bytes: 50_000,
+        }
+    }
+}
+
+/// A project's include/exclude globs, as stored and carried on each analysis request.
+/// Patterns are matched against root-relative paths, gitignore-style: a pattern without `/`
+/// matches at any depth (`*.pem`) and a trailing `/` covers a whole directory (`secrets/`).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PathRules {
+    /// When non-empty, only matching files may be analyzed
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// Files never analyzed, even when included
+    #[serde(default)]
+    pub exclude: Vec<String>,
+}
+
+impl PathRules {
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    pub fn compile(&self) -> Result<PathPolicy> {
+        Ok(PathPolicy {
+            include: build_globset(&self.include)?,
+            exclude: build_globset(&self.exclude)?,
+        })
+    }
+
+    /// Instructions for CLI agents, which read files on their own
+    pub fn prompt_notice(&self) -> Option<String> {
+        if self.is_empty() {
+            return None;
+        }
+        let mut notice = String::from("\n\nFile access policy for this project:");
+        if !self.include.is_empty() {
+            notice.push_str(&format!(" only read files matching {}.", self.include.join(", ")));
+        }
+        if !self.exclude.is_empty() {
+            notice.push_str(&format!(
+                " Never read, search or quote files matching {}.",
+                self.exclude.join(", ")
+            ));
+        }
+        Some(notice)
+    }
+}
+
+/// Compiled [`PathRules`]; the default allows every path
+#[derive(Debug, Clone, Default)]
+pub struct PathPolicy {
+    include: Option<GlobSet>,
+    exclude: Option<GlobSet>,
+}
+
+impl PathPolicy {
+    /// Whether the root-relative `path` may be analyzed
+    pub fn allows(&self, path: &str) -> bool {
+        let path = normalize_path(path);
+        if self.excludes(&path) {
+            return false;
+        }
+        self.include.as_ref().is_none_or(|include| include.is_match(&path))
+    }
+
+    /// Whether `path` is excluded explicitly, as opposed to just not included
+    pub fn excludes(&self, path: &str) -> bool {
+        self.exclude.as_ref().is_some_and(|exclude| exclude.is_match(normalize_path(path)))
+    }
+}
+
+fn normalize_path(path: &str) -> String {
+    let path = path.trim().replace('\\', "/");
+    path.trim_start_matches("./").trim_end_matches('/').to_string()
+}
+
+/// Gitignore-style pattern to glob: `*.pem` -> `**/*.pem`, `secrets/` -> `**/secrets/**`
+pub fn to_glob(pattern: &str) -> String {
+    let pattern = pattern.trim().trim_start_matches("./");
+    let (body, dir) = match pattern.strip_suffix('/') {
+        Some(body) => (body, true),
+        None => (pattern, false),
+    };
+    let body = match body.strip_prefix('/') {
+        Some(anchored) => anchored.to_string(),
+        None if !body.contains('/') && !body.starts_with("**") => format!("**/{}", body),
+        None => body.to_string(),
+    };
+    if dir {
+        format!("{}/**", body)
+    } else {
+        body
+    }
+}
+
+fn build_globset(patterns: &[String]) -> Result<Option<GlobSet>> {
+    let patterns: Vec<&String> = patterns.iter().filter(|p| !p.trim().is_empty()).collect();
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = to_glob(pattern);
+        builder.add(Glob::new(&glob).map_err(|e| anyhow!("Invalid pattern {}: {}", pattern, e))?);
+        // A directory pattern also matches everything below it (`vendor/*` hides `vendor/a/b.js`)
+        if !glob.ends_with("/**") {
+            builder.add(Glob::new(&format!("{}/**", glob)).map_err(|e| anyhow!("Invalid pattern {}: {}", pattern, e))?);
+        }
+    }
+    Ok(Some(builder.build()?))
+}
+
+/// Drop the tokens of `code_context` that name excluded paths. Returns the kept context and the
+/// removed tokens.
+pub fn strip_excluded_paths(code_context: &str, policy: &PathPolicy) -> (String, Vec<String>) {
+    let mut removed = Vec::new();
+    let kept: Vec<&str> = code_context
+        .split(' ')
+        .filter(|token| {
+            let path = token.trim_matches(|c: char| c == ',' || c.is_whitespace());
+            if !path.is_empty() && policy.excludes(path) {
+                removed.push(path.to_string());
+                return false;
+            }
+            true
+        })
+        .collect();
+    (kept.join(" "), removed)
+}
+
+/// Resolve `relative` inside `root`, rejecting anything that escapes the project directory
+/// (`..`, absolute paths, symlinks pointing outside)
+pub fn resolve_in_project(root: &Path, relative: &str) -> Result<PathBuf> {
+    let relative = Path::new(relative.trim());
+    if relative
+        .components()
+        .any(|c| matches!(c, Component::ParentDir | Component::RootDir | Component::Prefix(_)))
+    {
+        return Err(anyhow!("Path {} is outside the project directory", relative.display()));
+    }
+
+    let root = root.canonicalize()?;
+    let resolved = root.join(relative).canonicalize()?;
+    if !resolved.starts_with(&root) {
+        return Err(anyhow!("Path {} is outside the project directory", relative.display()));
+    }
+    Ok(resolved)
+}
+
+/// Read a text file inside the project, truncated to `max_bytes`
+pub fn read_project_file(root: &Path, relative: &str, max_bytes: usize, policy: &PathPolicy) -> Result<String> {
+    if !policy.allows(relative) {
+        return Err(anyhow!("{} is excluded by the project's path policy", relative));
+    }
+    let path = resolve_in_project(root, relative)?;
+    if !path.is_file() {
+        return Err(anyhow!("{} is not a file", relative));
+    }
+
+    let bytes = std::fs::read(&path)?;
+    if bytes.contains(&0) {
+        return Err(anyhow!("{} looks like a binary file", relative));
+    }
+
+    let mut content = String::from_utf8_lossy(&bytes).into_owned();
+    if content.len() > max_bytes {
+        let mut cut = max_bytes;
+        while !content.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        content.truncate(cut);
+        content.push_str("\n... [truncated]");
+    }
+    Ok(content)
+}
+
+/// List text files under `relative` (project root when empty) that `policy` allows, as
+/// root-relative paths
+pub fn list_project_files(root: &Path, relative: &str, max_files: usize, policy: &PathPolicy) -> Result<Vec<String>> {
+    let root = root.canonicalize()?;
+    let start = if relative.trim().is_empty() || relative.trim() == "." {
+        root.clone()
+    } else {
+        resolve_in_project(&root, relative)?
+    };
+
+    let mut files = Vec::new();
+    let mut stack = vec![start];
+    while let Some(dir) = stack.pop() {
+        if dir.is_file() {
+            if allowed(&root, &dir, policy) {
+                files.push(dir);
+            }
+            continue;
+        }
+        let Ok(entries) = std::fs::read_dir(&dir) else { continue };
+        let mut entries: Vec<_> = entries.flatten().map(|e| e.path()).collect();
+        entries.sort();
+
+        for path in entries.into_iter().rev() {
+            let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+            if path.is_dir() {
+                if !SKIPPED_DIRS.contains(&name) && !name.starts_with('.') {
+                    stack.push(path);
+                }
+            } else if is_text_file(&path) && allowed(&root, &path, policy) {
+                files.push(path);
+            }
+        }
+        if files.len() >= max_files.min(MAX_WALK_FILES) {
+            break;
+        }
+    }
+
+    files.truncate(max_files);
+    Ok(files
+        .iter()
+        .filter_map(|p| p.strip_prefix(&root).ok())
+        .map(|p| p.to_string_lossy().replace('\\', "/"))
+        .collect())
+}
+
+fn allowed(root: &Path, path: &Path, policy: &PathPolicy) -> bool {
+    path.strip_prefix(root)
+        .map(|relative| policy.allows(&relative.to_string_lossy()))
+        .unwrap_or(false)
+}
+
+/// Case-insensitive substring search over project text files, as `path:line: text` hits
+pub fn search_project(root: &Path, query: &str, max_results: usize, policy: &PathPolicy) -> Result<Vec<String>> {
+    let needle = query.trim().to_lowercase();
+    if needle.is_empty() {
+        return Err(anyhow!("Search query must not be empty"));
+    }
+
+    let mut hits = Vec::new();
+    for path in list_project_files(root, "", MAX_WALK_FILES, policy)? {
+        let Ok(content) = read_project_file(root, &path, usize::MAX, policy) else { continue };
+        for (index, line) in content.lines().enumerate() {
+            if line.to_lowercase().contains(&needle) {
+                hits.push(format!("{}:{}: {}", path, index + 1, line.trim()));
+                if hits.len() >= max_results {
+                    return Ok(hits);
+                }
+            }
+        }
+    }
+    Ok(hits)
+}
+
+/// Pick the files most relevant to a ticket without model help: everything under
+/// `code_context` when it names a project path, otherwise files whose path mentions a
+/// keyword from the question.
+pub fn collect_context(
+    root: &Path,
+    code_context: &str,
+    question: &str,
+    limits: ContextLimits,
+    policy: &PathPolicy,
+) -> Result<Vec<ContextFile>> {
+    let candidates = match code_context_paths(root, code_context, policy) {
+        paths if !paths.is_empty() => paths,
+        _ => {
+            let keywords = keywords(&format!("{} {}", code_context, question));
+            let mut scored: Vec<(usize, String)> = list_project_files(root, "", MAX_WALK_FILES, policy)?
+                .into_iter()
+                .map(|path| {
+                    let lower = path.to_lowercase();
+                    (keywords.iter().filter(|k| lower.contains(k.as_str())).count(), path)
+                })
+                .filter(|(score, _)| *score > 0)
+                .collect();
+            scored.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+            scored.into_iter().map(|(_, path)| path).collect()
+        }
+    };
+
+    let mut files = Vec::new();
+    let mut total = 0;
+    for path in candidates {
+        if files.len() >= limits.max_files || total >= limits.max_total_bytes {
+            break;
+        }
+        let budget = limits.max_file_bytes.min(limits.max_total_bytes - total);
+        if let Ok(content) = read_project_file(root, &path, budget, policy) {
+            total += content.len();
+            files.push(ContextFile { path, content });
+        }
+    }
+    Ok(files)
+}
+
+/// Files under every whitespace/comma separated token of `code_context` that resolves inside the project
+fn code_context_paths(root: &Path, code_context: &str, policy: &PathPolicy) -> Vec<String> {
+    let mut seen = HashSet::new();
+    code_context
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|token| !token.is_empty())
+        .filter(|token| resolve_in_project(root, token).is_ok())
+        .flat_map(|token| list_project_files(root, token, MAX_WALK_FILES, policy).unwrap_or_default())
+        .filter(|path| seen.insert(path.clone()))
+        .collect()
+}
+
+fn keywords(text: &str) -> Vec<String> {
+    let mut seen = HashSet::new();
+    text.split(|c: char| !c.is_alphanumeric() && c != '_')
+        .map(|w| w.to_lowercase())
+        .filter(|w| w.len() >= 4)
+        .filter(|w| seen.insert(w.clone()))
+        .collect()
+}
+
+fn is_text_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| TEXT_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_project() -> PathBuf {
+        let root = std::env::temp_dir().join(format!("project-files-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(root.join("src/checkout")).unwrap();
+        std::fs::create_dir_all(root.join("node_modules/dep")).unwrap();
+        std::fs::write(root.join("src/checkout/payment.ts"), "export const pay = () => {}").unwrap();
+        std::fs::write(root.join("src/cart.ts"), "export const cart = []").unwrap();
+        std::fs::write(root.join("node_modules/dep/payment.js"), "ignored").unwrap();
+        root
+    }
+
+    #[test]
+    fn test_resolve_rejects_escape() {
+        let root = temp_project();
+        assert!(resolve_in_project(&root, "src/cart.ts").is_ok());
+        assert!(resolve_in_project(&root, "../etc/passwd").is_err());
+        assert!(resolve_in_project(&root, "/etc/passwd").is_err());
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_collect_context() {
+        let root = temp_project();
+
+        let by_path = collect_context(&root, "src/checkout", "", ContextLimits::default(), &PathPolicy::default()).unwrap();
+        assert_eq!(by_path.len(), 1);
+        assert_eq!(by_path[0].path, "src/checkout/payment.ts");
+
+        let by_keyword =
+            collect_context(&root, "", "How does payment work?", ContextLimits::default(), &PathPolicy::default())
+                .unwrap();
+        let paths: Vec<_> = by_keyword.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(paths, vec!["src/checkout/payment.ts"]);
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_search_project() {
+        let root = temp_project();
+        let hits = search_project(&root, "PAY =", 10, &PathPolicy::default()).unwrap();
+        assert_eq!(hits, vec!["src/checkout/payment.ts:1: export const pay = () => {}"]);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_path_policy() {
+        let root = temp_project();
+        std::fs::create_dir_all(root.join("secrets")).unwrap();
+        std::fs::write(root.join("secrets/prod.env"), "PAY_KEY=x").unwrap();
+        std::fs::write(root.join("src/checkout/server.pem"), "pay").unwrap();
+
+        let rules = PathRules {
+            include: vec![],
+            exclude: vec!["secrets/".to_string(), "*.pem".to_string()],
+        };
+        let policy = rules.compile().unwrap();
+        assert!(!policy.allows("secrets/prod.env"));
+        assert!(!policy.allows("./src/checkout/server.pem"));
+        assert!(policy.allows("src/cart.ts"));
+
+        let files = list_project_files(&root, "", 100, &policy).unwrap();
+        assert_eq!(files, vec!["src/cart.ts", "src/checkout/payment.ts"]);
+        assert!(read_project_file(&root, "secrets/prod.env", 100, &policy).is_err());
+        assert!(search_project(&root, "pay", 10, &policy).unwrap().iter().all(|hit| hit.starts_with("src/checkout/payment.ts")));
+
+        let only_checkout = PathRules {
+            include: vec!["src/checkout/".to_string()],
+            exclude: vec![],
+        };
+        let policy = only_checkout.compile().unwrap();
+        assert!(policy.allows("src/checkout/payment.ts"));
+        assert!(!policy.allows("src/cart.ts"));
+
+        let (kept, removed) = strip_excluded_paths("src/checkout, secrets/prod.env", &rules.compile().unwrap());
+        assert_eq!((kept.as_str(), removed), ("src/checkout,", vec!["secrets/prod.env".to_string()]));
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
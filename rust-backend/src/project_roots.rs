@@ -0,0 +1,121 @@
+//! Directories project paths must live under (`PROJECT_ROOTS`), so nobody can register `/etc`
+//! or `/` as a project and have the agents read the host's files. Checked when a project is
+//! created, updated or imported, and again before each agent spawns.
+
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub enum RootError {
+    #[error("Directory {0} does not exist")]
+    NotFound(String),
+
+    #[error("{0} is not a directory")]
+    NotADirectory(String),
+
+    #[error("Directory {0} is outside the allowed project roots (PROJECT_ROOTS)")]
+    OutsideRoots(String),
+}
+
+/// Canonical allowed roots; any directory when unrestricted
+#[derive(Debug, Clone, Default)]
+pub struct ProjectRoots {
+    roots: Vec<PathBuf>,
+    restricted: bool,
+}
+
+impl ProjectRoots {
+    /// Roots that don't exist are dropped with a warning; if none is left, nothing is allowed
+    /// rather than everything
+    pub fn new(roots: &[&str]) -> Self {
+        let canonical: Vec<PathBuf> = roots
+            .iter()
+            .filter_map(|root| match Path::new(root).canonicalize() {
+                Ok(root) => Some(root),
+                Err(e) => {
+                    warn!("⚠️ Bỏ qua project root {}: {}", root, e);
+                    None
+                }
+            })
+            .collect();
+        if canonical.is_empty() && !roots.is_empty() {
+            warn!("⚠️ Không project root nào dùng được: mọi thư mục project đều bị từ chối");
+        }
+        Self {
+            roots: canonical,
+            restricted: !roots.is_empty(),
+        }
+    }
+
+    /// `PROJECT_ROOTS`, a comma-separated list of directories; unset allows any directory
+    pub fn from_env() -> Self {
+        let raw = std::env::var("PROJECT_ROOTS").unwrap_or_default();
+        let roots: Vec<&str> = raw.split(',').map(str::trim).filter(|root| !root.is_empty()).collect();
+        if roots.is_empty() {
+            warn!("⚠️ PROJECT_ROOTS chưa đặt: project có thể trỏ tới bất kỳ thư mục nào trên server");
+        }
+        Self::new(&roots)
+    }
+
+    pub fn is_restricted(&self) -> bool {
+        self.restricted
+    }
+
+    /// The canonical form of `directory` when it is a directory under one of the roots; the
+    /// path as given when no roots are configured
+    pub fn check(&self, directory: &str) -> Result<String, RootError> {
+        if !self.is_restricted() {
+            return Ok(directory.to_string());
+        }
+        let canonical = Path::new(directory.trim())
+            .canonicalize()
+            .map_err(|_| RootError::NotFound(directory.to_string()))?;
+        if !canonical.is_dir() {
+            return Err(RootError::NotADirectory(directory.to_string()));
+        }
+        // Canonical paths have no `..` or symlinks left, so a prefix match is a containment check
+        if !self.roots.iter().any(|root| canonical.starts_with(root)) {
+            return Err(RootError::OutsideRoots(directory.to_string()));
+        }
+        Ok(canonical.to_string_lossy().into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let base = std::env::temp_dir().join(format!("project-roots-{}", uuid::Uuid::new_v4()));
+        let root = base.join("projects");
+        std::fs::create_dir_all(root.join("shop/src")).unwrap();
+        std::fs::create_dir_all(base.join("secrets")).unwrap();
+        std::fs::write(root.join("notes.txt"), "").unwrap();
+        let path = |p: &Path| p.to_string_lossy().into_owned();
+
+        let roots = ProjectRoots::new(&[&path(&root), "/does/not/exist"]);
+        assert!(roots.is_restricted());
+        let canonical = root.canonicalize().unwrap();
+        assert_eq!(roots.check(&path(&root.join("shop"))), Ok(path(&canonical.join("shop"))));
+        assert_eq!(roots.check(&path(&root.join("shop/../shop/src"))), Ok(path(&canonical.join("shop/src"))));
+
+        // Escapes through `..` or a symlink resolve outside the root
+        let escape = path(&root.join("../secrets"));
+        assert_eq!(roots.check(&escape), Err(RootError::OutsideRoots(escape.clone())));
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(base.join("secrets"), root.join("link")).unwrap();
+            assert!(matches!(roots.check(&path(&root.join("link"))), Err(RootError::OutsideRoots(_))));
+        }
+        assert!(matches!(roots.check("/"), Err(RootError::OutsideRoots(_))));
+        assert!(matches!(roots.check(&path(&root.join("missing"))), Err(RootError::NotFound(_))));
+        assert!(matches!(roots.check(&path(&root.join("notes.txt"))), Err(RootError::NotADirectory(_))));
+
+        // No roots: anything goes, as given; only unusable roots: nothing does
+        assert_eq!(ProjectRoots::default().check("/etc"), Ok("/etc".to_string()));
+        assert!(ProjectRoots::new(&["/does/not/exist"]).check(&path(&root)).is_err());
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -0,0 +1,299 @@
+use crate::database::{Database, ProjectRecord, StructuredLogRecord, TicketRecord, DEFAULT_ORG_ID};
+use anyhow::Result;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use thiserror::Error;
+
+/// Bumped whenever the export layout changes incompatibly
+pub const EXPORT_FORMAT_VERSION: u32 = 1;
+
+const LOG_PAGE_SIZE: u64 = 1000;
+
+/// Self-contained snapshot of a project, as produced by `GET /api/projects/:id/export`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectExport {
+    pub format_version: u32,
+    pub exported_at: String,
+    pub project: ProjectRecord,
+    pub tickets: Vec<TicketRecord>,
+    /// Only present when exported with `include_logs=true`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logs: Option<Vec<StructuredLogRecord>>,
+}
+
+/// What to do when the target instance already has a project with the same name
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictStrategy {
+    /// Import as "<name> (imported)", "<name> (imported 2)", ...
+    #[default]
+    Rename,
+    Fail,
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportOptions {
+    pub on_conflict: ConflictStrategy,
+    /// Replaces the exported directory path, which rarely exists on the target machine
+    pub directory_path: Option<String>,
+    /// Organization receiving the project (the importer's, not the exporter's)
+    pub org_id: String,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        Self {
+            on_conflict: ConflictStrategy::default(),
+            directory_path: None,
+            org_id: DEFAULT_ORG_ID.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ImportError {
+    #[error("Unsupported export format version {0} (expected {EXPORT_FORMAT_VERSION})")]
+    UnsupportedVersion(u32),
+
+    #[error("A project named '{0}' already exists")]
+    NameConflict(String),
+
+    #[error("Invalid reference: {0}")]
+    InvalidReference(String),
+
+    #[error("Invalid ticket status '{0}'")]
+    InvalidStatus(String),
+}
+
+/// Records ready to insert, with fresh IDs and references rewritten
+#[derive(Debug)]
+pub struct PreparedImport {
+    pub project: ProjectRecord,
+    pub tickets: Vec<TicketRecord>,
+    pub logs: Vec<StructuredLogRecord>,
+}
+
+pub async fn export_project(
+    db: &Database,
+    project_id: &str,
+    include_logs: bool,
+) -> Result<Option<ProjectExport>> {
+    let Some(project) = db.get_project(project_id).await? else {
+        return Ok(None);
+    };
+    // Exports are self-contained: results and raw logs offloaded to blobs are inlined
+    let mut tickets = db.list_tickets_by_project(project_id).await?;
+    for ticket in tickets.iter_mut().filter(|ticket| ticket.analysis_result_blob.is_some()) {
+        ticket.analysis_result = db.full_result(ticket).await?;
+        ticket.analysis_result_blob = None;
+        ticket.analysis_result_size = None;
+    }
+
+    let logs = if include_logs {
+        let mut logs = Vec::new();
+        for ticket in &tickets {
+            let mut offset = 0;
+            loop {
+                let mut page = db
+                    .get_logs_for_ticket(&ticket.id, Some(LOG_PAGE_SIZE), Some(offset))
+                    .await?;
+                for log in page.iter_mut().filter(|log| log.raw_log_blob.is_some()) {
+                    log.raw_log = db.full_raw_log(log).await?;
+                    log.raw_log_blob = None;
+                }
+                let done = (page.len() as u64) < LOG_PAGE_SIZE;
+                offset += page.len() as u64;
+                logs.extend(page);
+                if done {
+                    break;
+                }
+            }
+        }
+        Some(logs)
+    } else {
+        None
+    };
+
+    Ok(Some(ProjectExport {
+        format_version: EXPORT_FORMAT_VERSION,
+        exported_at: Utc::now().to_rfc3339(),
+        project,
+        tickets,
+        logs,
+    }))
+}
+
+/// Validate an export and remap it onto new IDs. `existing_names` are the project names
+/// already present on this instance.
+pub fn prepare_import(
+    export: ProjectExport,
+    existing_names: &HashSet<String>,
+    options: &ImportOptions,
+) -> Result<PreparedImport, ImportError> {
+    if export.format_version != EXPORT_FORMAT_VERSION {
+        return Err(ImportError::UnsupportedVersion(export.format_version));
+    }
+
+    let name = match (existing_names.contains(&export.project.name), options.on_conflict) {
+        (false, _) => export.project.name.clone(),
+        (true, ConflictStrategy::Fail) => return Err(ImportError::NameConflict(export.project.name)),
+        (true, ConflictStrategy::Rename) => (1..)
+            .map(|n| match n {
+                1 => format!("{} (imported)", export.project.name),
+                n => format!("{} (imported {})", export.project.name, n),
+            })
+            .find(|candidate| !existing_names.contains(candidate))
+            .unwrap_or_default(),
+    };
+
+    let now = Utc::now().to_rfc3339();
+    let project = ProjectRecord {
+        id: uuid::Uuid::new_v4().to_string(),
+        name,
+        description: export.project.description,
+        directory_path: options
+            .directory_path
+            .clone()
+            .unwrap_or(export.project.directory_path),
+        created_at: export.project.created_at,
+        updated_at: now,
+        org_id: options.org_id.clone(),
+    };
+
+    let mut ticket_ids = HashMap::new();
+    let mut tickets = Vec::with_capacity(export.tickets.len());
+    for ticket in export.tickets {
+        if ticket.project_id != export.project.id {
+            return Err(ImportError::InvalidReference(format!(
+                "ticket {} belongs to project {}, not {}",
+                ticket.id, ticket.project_id, export.project.id
+            )));
+        }
+        if !crate::board::STATUSES.contains(&ticket.status.as_str()) {
+            return Err(ImportError::InvalidStatus(ticket.status));
+        }
+
+        let new_id = uuid::Uuid::new_v4().to_string();
+        if ticket_ids.insert(ticket.id.clone(), new_id.clone()).is_some() {
+            return Err(ImportError::InvalidReference(format!("duplicate ticket id {}", ticket.id)));
+        }
+        tickets.push(TicketRecord {
+            id: new_id,
+            project_id: project.id.clone(),
+            // An analysis can't still be running on a fresh instance, and the files
+            // behind the stale flag aren't exported
+            is_analyzing: false,
+            stale: false,
+            analysis_result_blob: None,
+            analysis_result_size: None,
+            ..ticket
+        });
+    }
+
+    let logs = export
+        .logs
+        .unwrap_or_default()
+        .into_iter()
+        .map(|log| match ticket_ids.get(&log.ticket_id) {
+            Some(ticket_id) => Ok(StructuredLogRecord {
+                id: uuid::Uuid::new_v4().to_string(),
+                ticket_id: ticket_id.clone(),
+                ..log
+            }),
+            None => Err(ImportError::InvalidReference(format!(
+                "log {} references unknown ticket {}",
+                log.id, log.ticket_id
+            ))),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(PreparedImport {
+        project,
+        tickets,
+        logs,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{project_fixture, test_db, ticket_fixture};
+
+    fn sample_export() -> ProjectExport {
+        let ticket = |id: &str| TicketRecord {
+            analysis_result: Some("answer".to_string()),
+            is_analyzing: true,
+            ..ticket_fixture(id, "p1")
+        };
+        ProjectExport {
+            format_version: EXPORT_FORMAT_VERSION,
+            exported_at: "2025-01-02T00:00:00Z".to_string(),
+            project: ProjectRecord {
+                name: "Shop".to_string(),
+                ..project_fixture("p1")
+            },
+            tickets: vec![ticket("t1"), ticket("t2")],
+            logs: Some(vec![StructuredLogRecord {
+                id: "l1".to_string(),
+                ticket_id: "t2".to_string(),
+                message_type: "system".to_string(),
+                content: "started".to_string(),
+                raw_log: None,
+                raw_log_blob: None,
+                metadata: None,
+                timestamp: "2025-01-01T00:00:01Z".to_string(),
+            }]),
+        }
+    }
+
+    #[test]
+    fn test_prepare_import_remaps_ids() {
+        let export = sample_export();
+        let names = HashSet::from(["Shop".to_string(), "Shop (imported)".to_string()]);
+        let prepared = prepare_import(export, &names, &ImportOptions::default()).unwrap();
+
+        assert_eq!(prepared.project.name, "Shop (imported 2)");
+        assert_ne!(prepared.project.id, "p1");
+        assert!(prepared.tickets.iter().all(|t| t.project_id == prepared.project.id && !t.is_analyzing));
+        assert_eq!(prepared.logs[0].ticket_id, prepared.tickets[1].id);
+
+        let fail = ImportOptions {
+            on_conflict: ConflictStrategy::Fail,
+            ..Default::default()
+        };
+        assert!(matches!(
+            prepare_import(sample_export(), &names, &fail),
+            Err(ImportError::NameConflict(_))
+        ));
+    }
+
+    #[test]
+    fn test_prepare_import_rejects_dangling_logs() {
+        let mut export = sample_export();
+        export.logs.as_mut().unwrap()[0].ticket_id = "missing".to_string();
+        assert!(matches!(
+            prepare_import(export, &HashSet::new(), &ImportOptions::default()),
+            Err(ImportError::InvalidReference(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_export_import_roundtrip() {
+        let db = test_db().await.unwrap();
+
+        let prepared = prepare_import(sample_export(), &HashSet::new(), &ImportOptions::default()).unwrap();
+        let project_id = prepared.project.id.clone();
+        db.import_project(&prepared.project, &prepared.tickets, &prepared.logs)
+            .await
+            .unwrap();
+
+        let export = export_project(&db, &project_id, true).await.unwrap().unwrap();
+        assert_eq!(export.project.name, "Shop");
+        assert_eq!(export.tickets.len(), 2);
+        assert_eq!(export.logs.unwrap().len(), 1);
+
+        let without_logs = export_project(&db, &project_id, false).await.unwrap().unwrap();
+        assert!(without_logs.logs.is_none());
+    }
+}
@@ -0,0 +1,198 @@
+use crate::code_agent::CodeAnalysisRequest;
+use tokio::io::AsyncWriteExt;
+use tokio::process::{Child, Command};
+use tracing::{info, warn};
+
+/// Size limits applied while assembling an agent prompt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PromptLimits {
+    /// `code_context` beyond this is cut, with a notice in the prompt
+    pub max_context_chars: usize,
+    /// Same for the question
+    pub max_question_chars: usize,
+    /// Final prompts larger than this fail the run with [`PromptTooLarge`]
+    pub max_prompt_bytes: usize,
+    /// CLI agents pass larger prompts on stdin instead of argv. Linux caps a single
+    /// argument at 128 KiB (MAX_ARG_STRLEN) and the whole argv + environment lower on some systems.
+    pub max_arg_bytes: usize,
+}
+
+impl Default for PromptLimits {
+    fn default() -> Self {
+        Self {
+            max_context_chars: 32_000,
+            max_question_chars: 16_000,
+            max_prompt_bytes: 512_000,
+            max_arg_bytes: 100_000,
+        }
+    }
+}
+
+impl PromptLimits {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+
+        Self {
+            max_context_chars: std::env::var("PROMPT_MAX_CONTEXT_CHARS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(defaults.max_context_chars),
+            max_question_chars: std::env::var("PROMPT_MAX_QUESTION_CHARS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(defaults.max_question_chars),
+            max_prompt_bytes: std::env::var("PROMPT_MAX_BYTES")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(defaults.max_prompt_bytes),
+            max_arg_bytes: std::env::var("PROMPT_MAX_ARG_BYTES")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(defaults.max_arg_bytes),
+        }
+    }
+}
+
+/// The assembled prompt exceeds `PROMPT_MAX_BYTES`; retrying can't help
+#[derive(Debug, thiserror::Error)]
+#[error("Prompt is {size} bytes, over the {limit}-byte limit (PROMPT_MAX_BYTES); shorten the question or code context")]
+pub struct PromptTooLarge {
+    pub size: usize,
+    pub limit: usize,
+}
+
+/// How a CLI agent hands its prompt to the process
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PromptDelivery {
+    /// As a command-line argument
+    Argv,
+    /// Written to stdin, for prompts too long for argv
+    Stdin,
+}
+
+impl PromptDelivery {
+    /// Check the final prompt against the limits and pick how to pass it
+    pub fn for_prompt(prompt: &str, limits: &PromptLimits) -> Result<Self, PromptTooLarge> {
+        if prompt.len() > limits.max_prompt_bytes {
+            return Err(PromptTooLarge {
+                size: prompt.len(),
+                limit: limits.max_prompt_bytes,
+            });
+        }
+        if prompt.len() > limits.max_arg_bytes {
+            info!("📨 Prompt {} bytes, vượt giới hạn argv, gửi qua stdin", prompt.len());
+            return Ok(Self::Stdin);
+        }
+        Ok(Self::Argv)
+    }
+
+    /// Append the prompt to `cmd` when it travels as an argument
+    pub fn push_arg(self, cmd: &mut Command, prompt: &str) {
+        if self == Self::Argv {
+            cmd.arg(prompt);
+        }
+    }
+
+    /// Write the prompt to the child's stdin when it travels there, then close stdin so the CLI
+    /// sees EOF. The write runs in its own task so a CLI that talks before reading can't deadlock.
+    pub fn feed_stdin(self, child: &mut Child, prompt: &str) {
+        let Some(mut stdin) = child.stdin.take() else {
+            return;
+        };
+        if self == Self::Argv {
+            return;
+        }
+        let prompt = prompt.to_string();
+        tokio::spawn(async move {
+            if let Err(e) = stdin.write_all(prompt.as_bytes()).await {
+                warn!("⚠️ Không ghi được prompt vào stdin: {}", e);
+            }
+        });
+    }
+}
+
+/// Cut `text` to `max_chars`, ending it with a notice so the agent knows content is missing
+fn truncate(text: &str, max_chars: usize, what: &str) -> Option<String> {
+    let total = text.chars().count();
+    if total <= max_chars {
+        return None;
+    }
+    let cut = text.char_indices().nth(max_chars).map_or(text.len(), |(i, _)| i);
+    Some(format!(
+        "{}\n\n[... {} đã bị cắt bớt: giữ {} / {} ký tự ...]",
+        &text[..cut],
+        what,
+        max_chars,
+        total
+    ))
+}
+
+/// Truncate an oversized question or code context in place before it reaches an agent.
+/// Returns one notice per field that was cut, for the ticket's log.
+pub fn fit_request(request: &mut CodeAnalysisRequest, limits: &PromptLimits) -> Vec<String> {
+    let mut notices = Vec::new();
+    if let Some(context) = truncate(&request.code_context, limits.max_context_chars, "code context") {
+        notices.push(format!(
+            "✂️ Code context dài {} ký tự, đã cắt còn {} (PROMPT_MAX_CONTEXT_CHARS)",
+            request.code_context.chars().count(),
+            limits.max_context_chars
+        ));
+        request.code_context = context;
+    }
+    if let Some(question) = truncate(&request.question, limits.max_question_chars, "câu hỏi") {
+        notices.push(format!(
+            "✂️ Câu hỏi dài {} ký tự, đã cắt còn {} (PROMPT_MAX_QUESTION_CHARS)",
+            request.question.chars().count(),
+            limits.max_question_chars
+        ));
+        request.question = question;
+    }
+    notices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fit_request_truncates_with_notice() {
+        let limits = PromptLimits {
+            max_context_chars: 10,
+            max_question_chars: 100,
+            max_prompt_bytes: 50,
+            max_arg_bytes: 20,
+        };
+        let mut request = CodeAnalysisRequest {
+            ticket_id: "t".to_string(),
+            code_context: "ạ".repeat(25),
+            question: "why?".to_string(),
+            project_id: "p".to_string(),
+            run_id: None,
+            agent_type: None,
+            mode: Default::default(),
+            path_rules: Default::default(),
+            tool_policy: Default::default(),
+            priority: Default::default(),
+            include_linked_results: false,
+            budget_override: false,
+            replay: None,
+            working_dir: None,
+            prompt_variant: None,
+            response_language: None,
+            api_key: None,
+            then: Vec::new(),
+        };
+
+        let notices = fit_request(&mut request, &limits);
+        assert_eq!(notices.len(), 1);
+        assert!(request.code_context.starts_with(&"ạ".repeat(10)));
+        assert!(!request.code_context.starts_with(&"ạ".repeat(11)));
+        assert!(request.code_context.contains("10 / 25"));
+        assert_eq!(request.question, "why?");
+
+        assert_eq!(PromptDelivery::for_prompt("short", &limits).unwrap(), PromptDelivery::Argv);
+        assert_eq!(PromptDelivery::for_prompt(&"x".repeat(30), &limits).unwrap(), PromptDelivery::Stdin);
+        let err = PromptDelivery::for_prompt(&"x".repeat(60), &limits).unwrap_err();
+        assert_eq!((err.size, err.limit), (60, 50));
+    }
+}
@@ -0,0 +1,264 @@
+//! Library of questions QA asks again and again, global to an organization or owned by one
+//! project, and tickets created from them (`/api/question-templates`)
+
+use crate::activity;
+use crate::analysis_runner;
+use crate::code_agent::AnalysisMode;
+use crate::database::{Database, QuestionTemplateRecord, TicketRecord};
+use crate::{AppState, CodeAnalysisRequest};
+use chrono::Utc;
+use serde::Deserialize;
+use tracing::info;
+
+/// Category of templates created without one
+pub const DEFAULT_CATEGORY: &str = "general";
+
+const MAX_CATEGORY_CHARS: usize = 64;
+const MAX_TITLE_CHARS: usize = 200;
+const MAX_QUESTION_CHARS: usize = 10_000;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TemplateError {
+    #[error("{0}")]
+    Invalid(String),
+
+    #[error(transparent)]
+    Database(#[from] anyhow::Error),
+}
+
+/// Body of `POST /api/question-templates` and `PUT /api/question-templates/:id`
+#[derive(Debug, Clone, Deserialize)]
+pub struct TemplateInput {
+    /// Owning project; a global template when unset. Ignored by updates.
+    pub project_id: Option<String>,
+    pub category: Option<String>,
+    pub title: String,
+    pub question: String,
+    #[serde(default)]
+    pub mode: AnalysisMode,
+    pub code_context: Option<String>,
+}
+
+/// The mode tickets created from `template` are analyzed in
+pub fn mode(template: &QuestionTemplateRecord) -> AnalysisMode {
+    serde_json::from_value(serde_json::Value::String(template.mode.clone())).unwrap_or_default()
+}
+
+/// Trimmed category, title, question and code context, checked
+fn normalize(input: &TemplateInput) -> Result<(String, String, String, Option<String>), TemplateError> {
+    let category = input
+        .category
+        .as_deref()
+        .map(|category| category.trim().to_lowercase())
+        .filter(|category| !category.is_empty())
+        .unwrap_or_else(|| DEFAULT_CATEGORY.to_string());
+    let title = input.title.trim().to_string();
+    let question = input.question.trim().to_string();
+    let code_context = input.code_context.clone().filter(|context| !context.trim().is_empty());
+
+    if title.is_empty() || question.is_empty() {
+        return Err(TemplateError::Invalid("A template needs a title and a question".to_string()));
+    }
+    if category.chars().count() > MAX_CATEGORY_CHARS {
+        return Err(TemplateError::Invalid(format!("Category is longer than {} characters", MAX_CATEGORY_CHARS)));
+    }
+    if title.chars().count() > MAX_TITLE_CHARS {
+        return Err(TemplateError::Invalid(format!("Title is longer than {} characters", MAX_TITLE_CHARS)));
+    }
+    if question.chars().count() > MAX_QUESTION_CHARS {
+        return Err(TemplateError::Invalid(format!("Question is longer than {} characters", MAX_QUESTION_CHARS)));
+    }
+    Ok((category, title, question, code_context))
+}
+
+/// Add a template to the organization's library, global unless `input.project_id` is set (the
+/// caller checks the project is the user's)
+pub async fn create(
+    db: &Database,
+    org_id: &str,
+    input: &TemplateInput,
+    created_by: Option<String>,
+) -> Result<QuestionTemplateRecord, TemplateError> {
+    let (category, title, question, code_context) = normalize(input)?;
+    let now = Utc::now().to_rfc3339();
+    let template = QuestionTemplateRecord {
+        id: uuid::Uuid::new_v4().to_string(),
+        org_id: org_id.to_string(),
+        project_id: input.project_id.clone().filter(|id| !id.trim().is_empty()),
+        category,
+        title,
+        question,
+        mode: input.mode.as_str().to_string(),
+        code_context,
+        usage_count: 0,
+        created_by,
+        created_at: now.clone(),
+        updated_at: now,
+    };
+    db.create_question_template(&template).await?;
+    Ok(template)
+}
+
+/// Replace the content of a template; it stays in its project (or global)
+pub async fn update(
+    db: &Database,
+    template: &QuestionTemplateRecord,
+    input: &TemplateInput,
+) -> Result<QuestionTemplateRecord, TemplateError> {
+    let (category, title, question, code_context) = normalize(input)?;
+    let template = QuestionTemplateRecord {
+        category,
+        title,
+        question,
+        mode: input.mode.as_str().to_string(),
+        code_context,
+        updated_at: Utc::now().to_rfc3339(),
+        ..template.clone()
+    };
+    db.update_question_template(&template).await?;
+    Ok(template)
+}
+
+/// Create a ticket of `project_id` from the template: its title, its question as description,
+/// its code context. Counts as a use of the template.
+pub async fn create_ticket(
+    state: &AppState,
+    template: &QuestionTemplateRecord,
+    project_id: &str,
+    status: &str,
+    requested_by: Option<&str>,
+) -> anyhow::Result<TicketRecord> {
+    let ticket = TicketRecord {
+        id: uuid::Uuid::new_v4().to_string(),
+        project_id: project_id.to_string(),
+        title: template.title.clone(),
+        description: template.question.clone(),
+        status: status.to_string(),
+        code_context: template.code_context.clone(),
+        analysis_result: None,
+        is_analyzing: false,
+        created_at: Utc::now().to_rfc3339(),
+        updated_at: Utc::now().to_rfc3339(),
+        stale: false,
+        analysis_result_blob: None,
+        analysis_result_size: None,
+        position: 0.0,
+        assignee_id: None,
+        summary: None,
+        mode: mode(template),
+    };
+    let position = state.database.create_ticket(&ticket).await?;
+    activity::watch_created(&state.database, &ticket.id, requested_by).await;
+    state.database.increment_question_template_usage(&template.id).await?;
+    let ticket = TicketRecord { position, ..ticket };
+
+    info!("📋 Tạo ticket {} từ template câu hỏi {}", ticket.id, template.title);
+    let _ = state.broadcast_tx.send(crate::BroadcastMessage {
+        ticket_id: ticket.id.clone(),
+        message_type: "ticket-created".to_string(),
+        content: serde_json::to_string(&ticket).unwrap_or_default(),
+        timestamp: Utc::now(),
+        org_id: None,
+        changed_files: None,
+    });
+    Ok(ticket)
+}
+
+/// Analyze a ticket created from `template` in the template's mode; returns the run ID
+pub async fn start(
+    state: &AppState,
+    template: &QuestionTemplateRecord,
+    ticket: &TicketRecord,
+    agent_type: Option<String>,
+    requested_by: Option<String>,
+) -> String {
+    let request = CodeAnalysisRequest {
+        ticket_id: ticket.id.clone(),
+        code_context: ticket.code_context.clone().unwrap_or_default(),
+        question: ticket.description.clone(),
+        project_id: ticket.project_id.clone(),
+        run_id: None,
+        agent_type,
+        mode: mode(template),
+        path_rules: Default::default(),
+        tool_policy: Default::default(),
+        priority: Default::default(),
+        include_linked_results: false,
+        budget_override: false,
+        replay: None,
+        working_dir: None,
+        prompt_variant: None,
+        response_language: None,
+        api_key: None,
+        then: Vec::new(),
+    };
+    let run_id = analysis_runner::start_analysis(state, request, requested_by).await;
+    analysis_runner::announce_started(state, &ticket.id, &run_id);
+    run_id
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::DEFAULT_ORG_ID;
+    use crate::test_support::{project_fixture, test_db};
+
+    fn input(title: &str, question: &str) -> TemplateInput {
+        TemplateInput {
+            project_id: None,
+            category: None,
+            title: title.to_string(),
+            question: question.to_string(),
+            mode: AnalysisMode::Ask,
+            code_context: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_library() {
+        let db = test_db().await.unwrap();
+        db.create_project(&project_fixture("p1")).await.unwrap();
+
+        let global = create(&db, DEFAULT_ORG_ID, &input(" Entry points ", "Which endpoints does it add?"), None)
+            .await
+            .unwrap();
+        assert_eq!((global.title.as_str(), global.category.as_str()), ("Entry points", DEFAULT_CATEGORY));
+
+        let owned = TemplateInput {
+            project_id: Some("p1".to_string()),
+            category: Some("Testing".to_string()),
+            mode: AnalysisMode::TestCases,
+            ..input("Test cases", "Write test cases for the module")
+        };
+        let owned = create(&db, DEFAULT_ORG_ID, &owned, Some("u1".to_string())).await.unwrap();
+        assert_eq!(owned.category, "testing");
+        assert_eq!(mode(&owned), AnalysisMode::TestCases);
+
+        // Other projects only see the global templates
+        assert_eq!(db.list_question_templates(DEFAULT_ORG_ID, Some("p1"), None).await.unwrap().len(), 2);
+        assert_eq!(db.list_question_templates(DEFAULT_ORG_ID, Some("p2"), None).await.unwrap(), vec![global.clone()]);
+        assert_eq!(
+            db.list_question_templates(DEFAULT_ORG_ID, Some("p1"), Some("testing")).await.unwrap(),
+            vec![owned.clone()]
+        );
+        assert!(db.list_question_templates("other", None, None).await.unwrap().is_empty());
+
+        assert!(matches!(
+            create(&db, DEFAULT_ORG_ID, &input("", "Why?"), None).await,
+            Err(TemplateError::Invalid(_))
+        ));
+
+        let updated = TemplateInput {
+            mode: AnalysisMode::Diagram,
+            ..input("Entry points", "Draw the request flow")
+        };
+        let updated = update(&db, &global, &updated).await.unwrap();
+        db.increment_question_template_usage(&global.id).await.unwrap();
+        let stored = db.get_question_template(&global.id).await.unwrap().unwrap();
+        assert_eq!((stored.question.as_str(), stored.mode.as_str()), ("Draw the request flow", "diagram"));
+        assert_eq!(stored.usage_count, updated.usage_count + 1);
+
+        assert!(db.delete_question_template(&global.id).await.unwrap());
+        assert!(!db.delete_question_template(&global.id).await.unwrap());
+    }
+}
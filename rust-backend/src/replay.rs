@@ -0,0 +1,209 @@
+use crate::code_agent::CodeAnalysisRequest;
+use crate::database::{AnalysisSession, Database};
+use crate::AppState;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::{info, warn};
+
+/// Where a replayed run comes from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Replay {
+    /// Session being re-run
+    pub session_id: String,
+    /// Commit the original run analyzed; None when the project wasn't a git repository
+    pub git_commit: Option<String>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReplayError {
+    #[error("Session was recorded before replays were supported")]
+    NotReplayable,
+
+    #[error("Ticket already has an analysis running or queued")]
+    Busy,
+
+    #[error("Commit {0} is no longer in the project repository")]
+    CommitMissing(String),
+
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+/// Queue a new run of the session's ticket with the request its agent received, against the
+/// same commit. Returns the new run ID.
+pub async fn replay_session(
+    state: &AppState,
+    session: &AnalysisSession,
+    requested_by: Option<String>,
+) -> Result<String, ReplayError> {
+    let request_json = session.request_json.as_deref().ok_or(ReplayError::NotReplayable)?;
+    let mut request: CodeAnalysisRequest = serde_json::from_str(request_json).context("Invalid stored request")?;
+
+    if crate::analysis_runner::is_active(state, &session.ticket_id).await {
+        return Err(ReplayError::Busy);
+    }
+    if let Some(commit) = &session.git_commit {
+        let project = state
+            .database
+            .get_project(&request.project_id)
+            .await?
+            .context("Project of the session no longer exists")?;
+        let (dir, rev) = (project.directory_path, format!("{}^{{commit}}", commit));
+        let found = tokio::task::spawn_blocking(move || {
+            crate::stale::git(Path::new(&dir), &["cat-file", "-e", &rev]).is_some()
+        })
+        .await
+        .context("Commit lookup panicked")?;
+        if !found {
+            return Err(ReplayError::CommitMissing(commit.clone()));
+        }
+    }
+
+    // The stored code context already holds any linked results
+    request.run_id = None;
+    request.include_linked_results = false;
+    request.working_dir = None;
+    request.agent_type = session.agent_type.clone().or(request.agent_type);
+    request.replay = Some(Replay {
+        session_id: session.id.clone(),
+        git_commit: session.git_commit.clone(),
+    });
+
+    info!("🔁 Phát lại session {} của ticket {}", session.id, session.ticket_id);
+    Ok(crate::analysis_runner::start_analysis(state, request, requested_by).await)
+}
+
+/// Temporary git worktree of the project at a replayed commit, removed on drop
+#[derive(Debug)]
+pub struct Checkout {
+    repo: PathBuf,
+    worktree: PathBuf,
+    /// The project directory inside the worktree (projects may sit in a monorepo subdirectory)
+    working_dir: PathBuf,
+}
+
+impl Checkout {
+    pub fn create(project_dir: &Path, commit: &str, run_id: &str) -> Result<Self> {
+        let prefix = crate::stale::git(project_dir, &["rev-parse", "--show-prefix"])
+            .context("Project directory is not a git repository")?;
+        let worktree = std::env::temp_dir().join("explain-source-replays").join(run_id);
+        if worktree.exists() {
+            // Left over by an earlier attempt of the same run
+            remove_worktree(project_dir, &worktree);
+        }
+
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(project_dir)
+            .args(["worktree", "add", "--detach"])
+            .arg(&worktree)
+            .arg(commit)
+            .output()
+            .context("Failed to run git worktree add")?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "git worktree add failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        Ok(Self {
+            repo: project_dir.to_path_buf(),
+            working_dir: worktree.join(prefix.trim()),
+            worktree,
+        })
+    }
+
+    pub fn working_dir(&self) -> &Path {
+        &self.working_dir
+    }
+}
+
+impl Drop for Checkout {
+    fn drop(&mut self) {
+        remove_worktree(&self.repo, &self.worktree);
+    }
+}
+
+fn remove_worktree(repo: &Path, worktree: &Path) {
+    let removed = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(["worktree", "remove", "--force"])
+        .arg(worktree)
+        .status()
+        .is_ok_and(|status| status.success());
+    if !removed {
+        if let Err(e) = std::fs::remove_dir_all(worktree) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                warn!("⚠️ Không xóa được worktree {}: {}", worktree.display(), e);
+            }
+        }
+        let _ = Command::new("git").arg("-C").arg(repo).args(["worktree", "prune"]).status();
+    }
+}
+
+/// Check out the commit a replayed request asks for; None for runs that aren't replays or
+/// whose original run had no commit
+pub async fn checkout(database: &Database, request: &CodeAnalysisRequest) -> Result<Option<Checkout>> {
+    let Some(commit) = request.replay.as_ref().and_then(|replay| replay.git_commit.clone()) else {
+        return Ok(None);
+    };
+    let project = database
+        .get_project(&request.project_id)
+        .await?
+        .with_context(|| format!("Project {} not found", request.project_id))?;
+    let run_id = request.run_id.clone().unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+    let checkout = tokio::task::spawn_blocking(move || {
+        Checkout::create(Path::new(&project.directory_path), &commit, &run_id)
+    })
+    .await
+    .context("Checkout panicked")??;
+    Ok(Some(checkout))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_git(dir: &Path, args: &[&str]) -> String {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    }
+
+    #[test]
+    fn test_checkout_is_at_commit_and_removed_on_drop() {
+        if crate::stale::git(&std::env::temp_dir(), &["--version"]).is_none() {
+            return;
+        }
+        let repo = std::env::temp_dir().join(format!("replay-git-{}", uuid::Uuid::new_v4()));
+        let project = repo.join("app");
+        std::fs::create_dir_all(&project).unwrap();
+        std::fs::write(project.join("auth.rs"), "// v1").unwrap();
+        run_git(&repo, &["init", "-q"]);
+        run_git(&repo, &["add", "."]);
+        run_git(&repo, &["commit", "-q", "-m", "v1"]);
+        let commit = run_git(&repo, &["rev-parse", "HEAD"]);
+        std::fs::write(project.join("auth.rs"), "// v2").unwrap();
+        run_git(&repo, &["commit", "-q", "-am", "v2"]);
+
+        let checkout = Checkout::create(&project, &commit, &uuid::Uuid::new_v4().to_string()).unwrap();
+        let working_dir = checkout.working_dir().to_path_buf();
+        assert_eq!(std::fs::read_to_string(working_dir.join("auth.rs")).unwrap(), "// v1");
+
+        drop(checkout);
+        assert!(!working_dir.exists());
+        assert_eq!(run_git(&repo, &["worktree", "list", "--porcelain"]).matches("worktree ").count(), 1);
+        std::fs::remove_dir_all(&repo).unwrap();
+    }
+}
@@ -0,0 +1,332 @@
+use crate::message_store::{LogMessageType, StructuredLogEntry};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+use tokio::process::Command;
+use tracing::warn;
+
+/// Grace between the CPU time soft limit (SIGXCPU) and the hard one (SIGKILL)
+const CPU_GRACE_SECS: u64 = 5;
+
+/// Limits on the CLI agent processes of one run, applied on unix
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ResourceLimits {
+    /// `memory.max` of the run's cgroup, or the address space limit (`RLIMIT_AS`) without one
+    pub memory_mb: Option<u64>,
+    /// `RLIMIT_CPU`
+    pub cpu_seconds: Option<u64>,
+    /// `cpu.weight` of the run's cgroup (1-10000, 100 is the kernel default)
+    pub cpu_weight: Option<u32>,
+    /// `RLIMIT_NOFILE`
+    pub open_files: Option<u64>,
+    /// Killed by a watchdog thread after this long, whatever the runtime is doing
+    pub wall_seconds: Option<u64>,
+    /// Delegated cgroup v2 directory each run gets a child cgroup in
+    pub cgroup_parent: Option<PathBuf>,
+}
+
+/// Why a run was killed, as reported to the user
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum LimitExceeded {
+    #[error("Killed due to resource limit: ran longer than {0}s")]
+    WallClock(u64),
+    #[error("Killed due to resource limit: used more than {0}s of CPU time")]
+    CpuTime(u64),
+    #[error("Killed due to resource limit: used more than {0} MB of memory")]
+    Memory(u64),
+}
+
+impl LimitExceeded {
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::WallClock(_) => "wall_clock",
+            Self::CpuTime(_) => "cpu_time",
+            Self::Memory(_) => "memory",
+        }
+    }
+
+    /// Error entry shown in the run's log
+    pub fn log_entry(&self, ticket_id: &str) -> StructuredLogEntry {
+        StructuredLogEntry {
+            id: uuid::Uuid::new_v4().to_string(),
+            ticket_id: ticket_id.to_string(),
+            message_type: LogMessageType::Error,
+            content: format!("⛔ Agent bị dừng do vượt giới hạn tài nguyên: {}", self),
+            raw_log: None,
+            metadata: HashMap::from([("resource_limit".to_string(), self.kind().to_string())]),
+            timestamp: chrono::Utc::now(),
+        }
+    }
+}
+
+impl ResourceLimits {
+    pub fn from_env() -> Self {
+        let number = |name: &str| std::env::var(name).ok().and_then(|v| v.trim().parse::<u64>().ok()).filter(|v| *v > 0);
+        let limits = Self {
+            memory_mb: number("AGENT_LIMIT_MEMORY_MB"),
+            cpu_seconds: number("AGENT_LIMIT_CPU_SECONDS"),
+            cpu_weight: number("AGENT_LIMIT_CPU_WEIGHT").map(|w| w.clamp(1, 10_000) as u32),
+            open_files: number("AGENT_LIMIT_OPEN_FILES"),
+            wall_seconds: number("AGENT_LIMIT_WALL_SECONDS"),
+            cgroup_parent: std::env::var("AGENT_CGROUP_PARENT")
+                .ok()
+                .filter(|v| !v.trim().is_empty())
+                .map(PathBuf::from),
+        };
+        if limits.cpu_weight.is_some() && limits.cgroup_parent.is_none() {
+            warn!("⚠️ AGENT_LIMIT_CPU_WEIGHT cần AGENT_CGROUP_PARENT, bỏ qua");
+        }
+        limits
+    }
+
+    /// Set up the limits for `cmd` before it is spawned
+    pub fn apply(&self, cmd: &mut Command) -> RunLimits {
+        let mut run = RunLimits {
+            limits: self.clone(),
+            cgroup: None,
+            procs: None,
+            fired: Arc::new(AtomicBool::new(false)),
+            stop_watchdog: None,
+        };
+        if self.cgroup_parent.is_some() && (self.memory_mb.is_some() || self.cpu_weight.is_some()) {
+            match self.create_cgroup() {
+                Ok((dir, procs)) => {
+                    run.cgroup = Some(dir);
+                    run.procs = Some(procs);
+                }
+                Err(e) => warn!("⚠️ Không tạo được cgroup cho agent, chỉ dùng rlimit: {}", e),
+            }
+        }
+
+        #[cfg(unix)]
+        {
+            use std::os::fd::AsRawFd;
+
+            let procs_fd = run.procs.as_ref().map(|file| file.as_raw_fd());
+            // The cgroup enforces memory when there is one; otherwise fall back to RLIMIT_AS
+            let address_space = match procs_fd {
+                Some(_) => None,
+                None => self.memory_mb.map(|mb| mb * 1024 * 1024),
+            };
+            let cpu_seconds = self.cpu_seconds;
+            let open_files = self.open_files;
+            let own_group = self.wall_seconds.is_some();
+
+            // SAFETY: the closure runs between fork and exec and only makes async-signal-safe
+            // calls (write, setrlimit, setpgid) without allocating
+            unsafe {
+                cmd.pre_exec(move || {
+                    if let Some(fd) = procs_fd {
+                        // "0" moves the writing process, i.e. the child, into the cgroup
+                        if libc::write(fd, b"0".as_ptr().cast(), 1) != 1 {
+                            return Err(std::io::Error::last_os_error());
+                        }
+                    }
+                    if let Some(bytes) = address_space {
+                        set_rlimit(libc::RLIMIT_AS, bytes, bytes)?;
+                    }
+                    if let Some(seconds) = cpu_seconds {
+                        set_rlimit(libc::RLIMIT_CPU, seconds, seconds + CPU_GRACE_SECS)?;
+                    }
+                    if let Some(files) = open_files {
+                        set_rlimit(libc::RLIMIT_NOFILE, files, files)?;
+                    }
+                    // The watchdog kills the whole group, tools the CLI started included
+                    if own_group && libc::setpgid(0, 0) != 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                    Ok(())
+                });
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = cmd;
+
+        run
+    }
+
+    fn create_cgroup(&self) -> std::io::Result<(PathBuf, std::fs::File)> {
+        let parent = self.cgroup_parent.as_deref().unwrap_or(Path::new("/"));
+        let dir = parent.join(format!("run-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir)?;
+        let configure = || -> std::io::Result<std::fs::File> {
+            if let Some(mb) = self.memory_mb {
+                std::fs::write(dir.join("memory.max"), (mb * 1024 * 1024).to_string())?;
+                // Without swap the limit would only move the pressure to disk
+                let _ = std::fs::write(dir.join("memory.swap.max"), "0");
+            }
+            if let Some(weight) = self.cpu_weight {
+                std::fs::write(dir.join("cpu.weight"), weight.to_string())?;
+            }
+            std::fs::OpenOptions::new().write(true).open(dir.join("cgroup.procs"))
+        };
+        match configure() {
+            Ok(procs) => Ok((dir, procs)),
+            Err(e) => {
+                let _ = std::fs::remove_dir(&dir);
+                Err(e)
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+#[cfg(target_os = "linux")]
+type Resource = libc::__rlimit_resource_t;
+#[cfg(unix)]
+#[cfg(not(target_os = "linux"))]
+type Resource = libc::c_int;
+
+#[cfg(unix)]
+fn set_rlimit(resource: Resource, soft: u64, hard: u64) -> std::io::Result<()> {
+    let limit = libc::rlimit {
+        rlim_cur: soft as libc::rlim_t,
+        rlim_max: hard as libc::rlim_t,
+    };
+    // SAFETY: plain syscall on a stack value
+    if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// The limits of one spawned process; the cgroup is removed and the watchdog stopped on drop
+#[derive(Debug)]
+pub struct RunLimits {
+    limits: ResourceLimits,
+    cgroup: Option<PathBuf>,
+    procs: Option<std::fs::File>,
+    fired: Arc<AtomicBool>,
+    stop_watchdog: Option<mpsc::Sender<()>>,
+}
+
+impl RunLimits {
+    /// Start the wall-clock watchdog for the spawned process
+    pub fn watch(&mut self, pid: Option<u32>) {
+        self.procs = None;
+        let (Some(seconds), Some(pid)) = (self.limits.wall_seconds, pid) else {
+            return;
+        };
+        let (stop_tx, stop_rx) = mpsc::channel::<()>();
+        self.stop_watchdog = Some(stop_tx);
+        let fired = self.fired.clone();
+        std::thread::spawn(move || {
+            // A stop message or a dropped sender means the run already ended
+            if let Err(mpsc::RecvTimeoutError::Timeout) = stop_rx.recv_timeout(Duration::from_secs(seconds)) {
+                fired.store(true, Ordering::SeqCst);
+                #[cfg(unix)]
+                // SAFETY: plain syscall; the child leads its own process group
+                unsafe {
+                    libc::kill(-(pid as libc::pid_t), libc::SIGKILL);
+                }
+            }
+        });
+    }
+
+    /// The limit that ended the run, judged from how it exited
+    pub fn exceeded(&mut self, status: &ExitStatus) -> Option<LimitExceeded> {
+        self.stop_watchdog = None;
+        if self.fired.load(Ordering::SeqCst) {
+            return self.limits.wall_seconds.map(LimitExceeded::WallClock);
+        }
+        if let (Some(dir), Some(mb)) = (&self.cgroup, self.limits.memory_mb) {
+            if oom_kills(dir) > 0 {
+                return Some(LimitExceeded::Memory(mb));
+            }
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::ExitStatusExt;
+            if let (Some(signal), Some(seconds)) = (status.signal(), self.limits.cpu_seconds) {
+                if signal == libc::SIGXCPU || signal == libc::SIGKILL {
+                    return Some(LimitExceeded::CpuTime(seconds));
+                }
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = status;
+        None
+    }
+}
+
+impl Drop for RunLimits {
+    fn drop(&mut self) {
+        if let Some(dir) = self.cgroup.take() {
+            // Only possible once the processes in it are gone; a killed run may take a moment
+            std::thread::spawn(move || {
+                for _ in 0..10 {
+                    if std::fs::remove_dir(&dir).is_ok() {
+                        return;
+                    }
+                    std::thread::sleep(Duration::from_millis(500));
+                }
+            });
+        }
+    }
+}
+
+/// `oom_kill` count from the cgroup's `memory.events`
+fn oom_kills(dir: &Path) -> u64 {
+    std::fs::read_to_string(dir.join("memory.events"))
+        .ok()
+        .and_then(|events| {
+            events.lines().find_map(|line| {
+                line.strip_prefix("oom_kill ").and_then(|count| count.trim().parse().ok())
+            })
+        })
+        .unwrap_or(0)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_wall_clock_limit() {
+        let limits = ResourceLimits {
+            wall_seconds: Some(1),
+            ..Default::default()
+        };
+        let mut cmd = Command::new("sleep");
+        cmd.arg("30");
+        let mut run = limits.apply(&mut cmd);
+        let mut child = cmd.spawn().unwrap();
+        run.watch(child.id());
+
+        let started = std::time::Instant::now();
+        let status = child.wait().await.unwrap();
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert_eq!(run.exceeded(&status), Some(LimitExceeded::WallClock(1)));
+    }
+
+    #[tokio::test]
+    async fn test_rlimits_and_clean_exit() {
+        let limits = ResourceLimits {
+            open_files: Some(64),
+            wall_seconds: Some(30),
+            ..Default::default()
+        };
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", "ulimit -n"]).stdout(std::process::Stdio::piped());
+        let mut run = limits.apply(&mut cmd);
+        let child = cmd.spawn().unwrap();
+        run.watch(child.id());
+
+        let output = child.wait_with_output().await.unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "64");
+        assert_eq!(run.exceeded(&output.status), None);
+    }
+
+    #[test]
+    fn test_oom_kills() {
+        let dir = std::env::temp_dir().join(format!("cgroup-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(dir.join("memory.events"), "low 0\nhigh 0\nmax 3\noom 1\noom_kill 1\n").unwrap();
+        assert_eq!(oom_kills(&dir), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(oom_kills(&dir), 0);
+    }
+}
@@ -0,0 +1,249 @@
+use crate::code_agent::AnalysisCancelled;
+use crate::prompt::PromptTooLarge;
+use crate::resource_limits::LimitExceeded;
+use anyhow::Result;
+use rand::Rng;
+use std::future::Future;
+use tokio::time::Duration;
+use tracing::{debug, info, warn};
+
+/// How an agent retries a failed attempt
+///
+/// Delays grow exponentially from `initial_delay` up to `max_delay`; each one is shortened by a
+/// random share of up to `jitter` so parallel runs hitting the same limit don't retry in lockstep.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// Attempts in total, the first one included
+    pub max_attempts: u32,
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    pub multiplier: f64,
+    /// Between 0.0 (fixed delays) and 1.0
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 2,
+            initial_delay: Duration::from_secs(2),
+            max_delay: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: 0.2,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Policy for the agent whose variables start with `prefix` (e.g. `CLAUDE_AGENT`)
+    ///
+    /// `<PREFIX>_MAX_RETRIES` keeps its meaning of attempts in total. The backoff reads
+    /// `<PREFIX>_RETRY_INITIAL_DELAY_MS`, `_RETRY_MAX_DELAY_MS`, `_RETRY_MULTIPLIER` and
+    /// `_RETRY_JITTER`, falling back to the same `AGENT_RETRY_*` variables shared by all agents.
+    pub fn from_env(prefix: &str) -> Self {
+        fn var<T: std::str::FromStr>(prefix: &str, name: &str) -> Option<T> {
+            std::env::var(format!("{}_{}", prefix, name))
+                .or_else(|_| std::env::var(format!("AGENT_{}", name)))
+                .ok()
+                .and_then(|s| s.parse().ok())
+        }
+        let defaults = Self::default();
+
+        Self {
+            max_attempts: std::env::var(format!("{}_MAX_RETRIES", prefix))
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(defaults.max_attempts)
+                .max(1),
+            initial_delay: var(prefix, "RETRY_INITIAL_DELAY_MS")
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.initial_delay),
+            max_delay: var(prefix, "RETRY_MAX_DELAY_MS")
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.max_delay),
+            multiplier: var(prefix, "RETRY_MULTIPLIER")
+                .filter(|m: &f64| *m >= 1.0)
+                .unwrap_or(defaults.multiplier),
+            jitter: var(prefix, "RETRY_JITTER")
+                .map(|j: f64| j.clamp(0.0, 1.0))
+                .unwrap_or(defaults.jitter),
+        }
+    }
+
+    /// Wait after failed attempt `attempt` (1-based)
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.delay_with(attempt, rand::thread_rng().gen())
+    }
+
+    /// `delay` with the random draw (in `[0, 1)`) supplied by the caller
+    fn delay_with(&self, attempt: u32, draw: f64) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(32) as i32;
+        let backoff = self.initial_delay.as_secs_f64() * self.multiplier.powi(exponent);
+        let capped = backoff.min(self.max_delay.as_secs_f64());
+        Duration::from_secs_f64(capped * (1.0 - self.jitter * draw))
+    }
+
+    /// One-line summary for the startup log
+    pub fn describe(&self) -> String {
+        format!(
+            "{} attempts, backoff {}ms → {}ms (x{}, jitter {})",
+            self.max_attempts,
+            self.initial_delay.as_millis(),
+            self.max_delay.as_millis(),
+            self.multiplier,
+            self.jitter
+        )
+    }
+}
+
+/// Whether an agent error may go away on a second try
+pub trait Retryable {
+    fn is_retryable(&self) -> bool;
+}
+
+/// Classify `error` through the agent error type `E`
+///
+/// Cancellation and oversized prompts never retry. Errors that are not an `E` (I/O, task
+/// join, ...) keep the old behaviour and do.
+pub fn classify<E>(error: &anyhow::Error) -> bool
+where
+    E: Retryable + std::error::Error + Send + Sync + 'static,
+{
+    // A run that hit a resource limit would hit it again
+    if error.is::<AnalysisCancelled>() || error.is::<PromptTooLarge>() || error.is::<LimitExceeded>() {
+        return false;
+    }
+    error.downcast_ref::<E>().is_none_or(E::is_retryable)
+}
+
+/// Run `attempt` until it succeeds, fails with an error `is_retryable` rejects, or the policy
+/// runs out of attempts; `what` names the operation in logs
+pub async fn run<T, F, Fut>(
+    policy: &RetryPolicy,
+    what: &str,
+    is_retryable: impl Fn(&anyhow::Error) -> bool,
+    mut attempt: F,
+) -> Result<T>
+where
+    F: FnMut(u32) -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let max_attempts = policy.max_attempts.max(1);
+    let mut n = 1;
+    loop {
+        if n == 1 {
+            debug!("🔄 Attempt {}/{} for {}", n, max_attempts, what);
+        } else {
+            info!("🔄 Attempt {}/{} for {}", n, max_attempts, what);
+        }
+        match attempt(n).await {
+            Ok(value) => {
+                if n > 1 {
+                    info!("✅ {} succeeded on attempt {}", what, n);
+                }
+                return Ok(value);
+            }
+            Err(e) => {
+                warn!("❌ Attempt {}/{} for {} failed: {}", n, max_attempts, what, e);
+                if !is_retryable(&e) {
+                    if !e.is::<AnalysisCancelled>() {
+                        warn!("🚫 Lỗi không thể retry, bỏ qua các lần thử còn lại");
+                    }
+                    return Err(e);
+                }
+                if n >= max_attempts {
+                    return Err(e);
+                }
+                let delay = policy.delay(n);
+                info!("⏳ Waiting {}ms before retry...", delay.as_millis());
+                tokio::time::sleep(delay).await;
+                n += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, thiserror::Error)]
+    enum TestError {
+        #[error("timeout")]
+        Timeout,
+        #[error("not found")]
+        NotFound,
+    }
+
+    impl Retryable for TestError {
+        fn is_retryable(&self) -> bool {
+            matches!(self, TestError::Timeout)
+        }
+    }
+
+    #[test]
+    fn test_delay_backs_off_with_cap_and_jitter() {
+        let policy = RetryPolicy {
+            max_attempts: 5,
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(500),
+            multiplier: 2.0,
+            jitter: 0.5,
+        };
+
+        assert_eq!(policy.delay_with(1, 0.0), Duration::from_millis(100));
+        assert_eq!(policy.delay_with(2, 0.0), Duration::from_millis(200));
+        assert_eq!(policy.delay_with(3, 0.0), Duration::from_millis(400));
+        assert_eq!(policy.delay_with(4, 0.0), Duration::from_millis(500));
+        assert_eq!(policy.delay_with(40, 0.0), Duration::from_millis(500));
+        assert_eq!(policy.delay_with(2, 0.5), Duration::from_millis(150));
+        let random = policy.delay(3);
+        assert!(random > Duration::from_millis(200) && random <= Duration::from_millis(400));
+    }
+
+    #[tokio::test]
+    async fn test_run_stops_on_non_retryable_errors() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            initial_delay: Duration::ZERO,
+            ..RetryPolicy::default()
+        };
+
+        let mut calls = 0;
+        let result: Result<()> = run(&policy, "test", classify::<TestError>, |_| {
+            calls += 1;
+            async { Err(TestError::Timeout.into()) }
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(calls, 3);
+
+        let mut calls = 0;
+        let result: Result<()> = run(&policy, "test", classify::<TestError>, |_| {
+            calls += 1;
+            async { Err(TestError::NotFound.into()) }
+        })
+        .await;
+        assert!(result.unwrap_err().is::<TestError>());
+        assert_eq!(calls, 1);
+
+        let mut calls = 0;
+        let result: Result<()> = run(&policy, "test", classify::<TestError>, |_| {
+            calls += 1;
+            async { Err(AnalysisCancelled.into()) }
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+
+        let result = run(&policy, "test", classify::<TestError>, |n| async move {
+            if n < 2 {
+                Err(TestError::Timeout.into())
+            } else {
+                Ok(n)
+            }
+        })
+        .await;
+        assert_eq!(result.unwrap(), 2);
+    }
+}
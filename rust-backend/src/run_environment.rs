@@ -0,0 +1,321 @@
+use crate::agent_factory::{AgentSettings, AgentType};
+use crate::code_agent::CodeAnalysisRequest;
+use crate::database::Database;
+use crate::message_store::StructuredLogEntry;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex, OnceLock};
+use tokio::sync::{broadcast, oneshot};
+use tokio::time::{Duration, Instant};
+use tracing::{debug, error, warn};
+
+/// How long a CLI's `--version` output is reused before asking again
+const VERSION_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// `--version` must answer within this, or the run is recorded without one
+const VERSION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What produced a run, stored on its session so a surprising result can be traced back
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RunEnvironment {
+    pub cli_version: Option<String>,
+    pub model: Option<String>,
+    pub git_commit: Option<String>,
+    pub prompt_hash: Option<String>,
+    /// Cost the agent reported in its result event (Claude Code only)
+    pub cost_usd: Option<f64>,
+    /// Input plus output tokens the agent reported in its result event
+    #[serde(default)]
+    pub tokens: Option<i64>,
+    /// Agent settings plus the request's mode and path rules
+    pub config: Value,
+}
+
+impl RunEnvironment {
+    /// Everything known before the agent starts; the model of CLI agents comes later from
+    /// their init event
+    pub async fn capture(request: &CodeAnalysisRequest, agent_type: AgentType, project_dir: Option<&str>) -> Self {
+        let settings = AgentSettings::from_env(agent_type);
+        let cli_version = match &settings.executable {
+            Some(executable) => cli_version(executable).await,
+            None => None,
+        };
+        let replayed_commit = request.replay.as_ref().and_then(|replay| replay.git_commit.clone());
+        let git_commit = match project_dir {
+            _ if replayed_commit.is_some() => replayed_commit,
+            Some(dir) => {
+                let dir = dir.to_string();
+                tokio::task::spawn_blocking(move || git_commit(Path::new(&dir)))
+                    .await
+                    .ok()
+                    .flatten()
+            }
+            None => None,
+        };
+
+        Self {
+            cli_version,
+            model: settings.model,
+            git_commit,
+            prompt_hash: Some(prompt_hash(request)),
+            cost_usd: None,
+            tokens: None,
+            config: json!({
+                "agent": settings.config,
+                "mode": request.mode,
+                "path_rules": request.path_rules,
+            }),
+        }
+    }
+}
+
+/// SHA-256 of the question and code context as the agent receives them
+pub fn prompt_hash(request: &CodeAnalysisRequest) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(request.prompt_question().as_bytes());
+    hasher.update([0]);
+    hasher.update(request.code_context.as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+fn git_commit(dir: &Path) -> Option<String> {
+    let head = crate::stale::git(dir, &["rev-parse", "HEAD"])?;
+    let head = head.trim();
+    (!head.is_empty()).then(|| head.to_string())
+}
+
+/// `--version` output per executable, with when it was read
+type VersionCache = Mutex<HashMap<String, (Instant, Option<String>)>>;
+
+/// First line of `<executable> --version`, cached for `VERSION_TTL`
+async fn cli_version(executable: &str) -> Option<String> {
+    static CACHE: OnceLock<VersionCache> = OnceLock::new();
+    let cache = CACHE.get_or_init(Default::default);
+
+    if let Some((at, version)) = cache.lock().unwrap_or_else(|e| e.into_inner()).get(executable) {
+        if at.elapsed() < VERSION_TTL {
+            return version.clone();
+        }
+    }
+
+    let output = tokio::process::Command::new(executable)
+        .arg("--version")
+        .kill_on_drop(true)
+        .output();
+    let version = match tokio::time::timeout(VERSION_TIMEOUT, output).await {
+        Ok(Ok(output)) if output.status.success() => String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .map(str::to_string),
+        Ok(Ok(output)) => {
+            warn!("{} --version exited with {}", executable, output.status);
+            None
+        }
+        Ok(Err(e)) => {
+            warn!("Failed to run {} --version: {}", executable, e);
+            None
+        }
+        Err(_) => {
+            warn!("{} --version timed out", executable);
+            None
+        }
+    };
+
+    cache
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(executable.to_string(), (Instant::now(), version.clone()));
+    version
+}
+
+/// Model named by an agent's init event: Claude/Cursor `{"type":"system","subtype":"init"}`,
+/// Gemini `{"type":"init"}`
+fn init_model(entry: &StructuredLogEntry) -> Option<String> {
+    let json: Value = serde_json::from_str(&entry.content).ok()?;
+    let is_init = match json.get("type").and_then(|v| v.as_str()) {
+        Some("init") => true,
+        Some("system") => json.get("subtype").and_then(|v| v.as_str()) == Some("init"),
+        _ => false,
+    };
+    if !is_init {
+        return None;
+    }
+    json.get("model").and_then(|v| v.as_str()).map(str::to_string)
+}
+
+/// Cost in USD of Claude Code's `{"type":"result","total_cost_usd":...}` (`cost_usd` in older
+/// versions)
+fn result_cost(entry: &StructuredLogEntry) -> Option<f64> {
+    let json: Value = serde_json::from_str(&entry.content).ok()?;
+    if json.get("type").and_then(|v| v.as_str()) != Some("result") {
+        return None;
+    }
+    json.get("total_cost_usd")
+        .or_else(|| json.get("cost_usd"))
+        .and_then(Value::as_f64)
+}
+
+/// Input plus output tokens of a result event: Claude Code's `usage`, or Gemini's `stats`
+fn result_tokens(entry: &StructuredLogEntry) -> Option<i64> {
+    let json: Value = serde_json::from_str(&entry.content).ok()?;
+    if json.get("type").and_then(|v| v.as_str()) != Some("result") {
+        return None;
+    }
+    let counts = json.get("usage").or_else(|| json.get("stats"))?;
+    let count = |name: &str| counts.get(name).and_then(Value::as_i64);
+    match (count("input_tokens"), count("output_tokens")) {
+        (None, None) => count("total_tokens"),
+        (input, output) => Some(input.unwrap_or(0) + output.unwrap_or(0)),
+    }
+}
+
+/// Capture the run's environment, pick up the model from the agent's init event (and the cost
+/// and tokens from its result event), and record both on the run's session with its request (for replays) when it ends (`done` firing or
+/// being dropped)
+pub async fn track_environment(
+    request: CodeAnalysisRequest,
+    agent_type: AgentType,
+    mut log_rx: broadcast::Receiver<StructuredLogEntry>,
+    database: Arc<Database>,
+    mut done: oneshot::Receiver<()>,
+) {
+    let ticket_id = request.ticket_id.clone();
+    let Some(run_id) = request.run_id.clone() else {
+        return;
+    };
+    let project_dir = match database.get_project(&request.project_id).await {
+        Ok(project) => project.map(|p| p.directory_path),
+        Err(e) => {
+            error!("Failed to load project {}: {}", request.project_id, e);
+            None
+        }
+    };
+    let mut environment = RunEnvironment::capture(&request, agent_type, project_dir.as_deref()).await;
+
+    let observe = |entry: &StructuredLogEntry, environment: &mut RunEnvironment| {
+        if entry.ticket_id == ticket_id {
+            if let Some(model) = init_model(entry) {
+                environment.model = Some(model);
+            }
+            if let Some(cost) = result_cost(entry) {
+                environment.cost_usd = Some(cost);
+            }
+            if let Some(tokens) = result_tokens(entry) {
+                environment.tokens = Some(tokens);
+            }
+        }
+    };
+    loop {
+        tokio::select! {
+            received = log_rx.recv() => match received {
+                Ok(entry) => observe(&entry, &mut environment),
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    debug!("Environment tracker for ticket {} skipped {} entries", ticket_id, skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            _ = &mut done => {
+                while let Ok(entry) = log_rx.try_recv() {
+                    observe(&entry, &mut environment);
+                }
+                break;
+            }
+        }
+    }
+
+    match database.get_session_by_run_id(&ticket_id, &run_id).await {
+        Ok(Some(session)) => {
+            if let Err(e) = database.record_session_environment(&session.id, &environment).await {
+                error!("Failed to record environment of session {}: {}", session.id, e);
+            }
+            let replay_of = request.replay.as_ref().map(|replay| replay.session_id.as_str());
+            if let Err(e) = database.record_session_request(&session.id, &request, replay_of).await {
+                error!("Failed to record request of session {}: {}", session.id, e);
+            }
+        }
+        Ok(None) => debug!("No session for run {} of ticket {}", run_id, ticket_id),
+        Err(e) => error!("Failed to find session of run {}: {}", run_id, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log_normalizer::LogNormalizer;
+
+    fn request(question: &str, code_context: &str) -> CodeAnalysisRequest {
+        CodeAnalysisRequest {
+            ticket_id: "t1".to_string(),
+            code_context: code_context.to_string(),
+            question: question.to_string(),
+            project_id: "p1".to_string(),
+            run_id: Some("run-1".to_string()),
+            agent_type: None,
+            mode: Default::default(),
+            path_rules: Default::default(),
+            tool_policy: Default::default(),
+            priority: Default::default(),
+            include_linked_results: false,
+            budget_override: false,
+            replay: None,
+            working_dir: None,
+            prompt_variant: None,
+            response_language: None,
+            api_key: None,
+            then: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_prompt_hash_covers_question_and_context() {
+        let hash = prompt_hash(&request("How does auth work?", "src/auth.rs"));
+        assert_eq!(hash.len(), 64);
+        assert_eq!(hash, prompt_hash(&request("How does auth work?", "src/auth.rs")));
+        assert_ne!(hash, prompt_hash(&request("How does auth work?", "src/billing.rs")));
+        // The separator keeps "ab" + "c" apart from "a" + "bc"
+        assert_ne!(prompt_hash(&request("ab", "c")), prompt_hash(&request("a", "bc")));
+    }
+
+    #[test]
+    fn test_init_model() {
+        let entry = |raw: &str| LogNormalizer::new().normalize(raw.to_string(), "t1".to_string());
+        assert_eq!(
+            init_model(&entry(r#"{"type":"system","subtype":"init","model":"claude-sonnet-4-5"}"#)),
+            Some("claude-sonnet-4-5".to_string())
+        );
+        assert_eq!(
+            init_model(&entry(r#"{"type":"init","model":"gemini-2.5-pro"}"#)),
+            Some("gemini-2.5-pro".to_string())
+        );
+        assert_eq!(init_model(&entry(r#"{"type":"assistant","model":"x"}"#)), None);
+        assert_eq!(init_model(&entry("plain text")), None);
+    }
+
+    #[test]
+    fn test_result_cost() {
+        let entry = |raw: &str| LogNormalizer::new().normalize(raw.to_string(), "t1".to_string());
+        assert_eq!(
+            result_cost(&entry(r#"{"type":"result","result":"done","total_cost_usd":0.0421}"#)),
+            Some(0.0421)
+        );
+        assert_eq!(result_cost(&entry(r#"{"type":"result","cost_usd":0.5}"#)), Some(0.5));
+        assert_eq!(result_cost(&entry(r#"{"type":"result","result":"done"}"#)), None);
+        assert_eq!(result_cost(&entry(r#"{"type":"assistant","total_cost_usd":1.0}"#)), None);
+
+        assert_eq!(
+            result_tokens(&entry(r#"{"type":"result","usage":{"input_tokens":1200,"output_tokens":300}}"#)),
+            Some(1500)
+        );
+        assert_eq!(result_tokens(&entry(r#"{"type":"result","stats":{"total_tokens":900}}"#)), Some(900));
+        assert_eq!(result_tokens(&entry(r#"{"type":"result","result":"done"}"#)), None);
+    }
+
+    #[tokio::test]
+    async fn test_cli_version_of_missing_executable() {
+        assert_eq!(cli_version("definitely-not-an-agent-cli").await, None);
+    }
+}
@@ -0,0 +1,74 @@
+//! Case- and accent-insensitive ticket search (`GET /api/search/tickets`) over the `ticket_search`
+//! FTS5 index: "phan tich" finds "Phân tích" in titles, descriptions, summaries and results
+
+/// Words of a query used; the rest are ignored
+const MAX_TERMS: usize = 16;
+
+/// "đ" is a letter of its own, not "d" with a mark, so the tokenizer's diacritic stripping
+/// keeps it; the index folds it the same way (migration 042)
+pub fn fold(text: &str) -> String {
+    text.replace('đ', "d").replace('Đ', "D")
+}
+
+/// FTS5 query matching tickets that contain every word of `query`, each as a prefix so results
+/// show up while the user is still typing. None when there's nothing to search for.
+pub fn match_query(query: &str) -> Option<String> {
+    let folded = fold(query);
+    let terms: Vec<String> = folded
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .take(MAX_TERMS)
+        .map(|word| format!("\"{}\"*", word))
+        .collect();
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{Database, TicketRecord, DEFAULT_ORG_ID};
+    use crate::test_support::{project_fixture, test_db, ticket_fixture};
+
+    #[test]
+    fn test_match_query() {
+        assert_eq!(match_query("phân tích"), Some(r#""phân"* "tích"*"#.to_string()));
+        assert_eq!(match_query(r#"Đăng "nhập" OR x*"#), Some(r#""Dăng"* "nhập"* "OR"* "x"*"#.to_string()));
+        assert_eq!(match_query(" -- "), None);
+    }
+
+    async fn search(db: &Database, query: &str, project_id: Option<&str>) -> Vec<String> {
+        let query = match_query(query).unwrap();
+        let tickets = db.search_tickets(DEFAULT_ORG_ID, project_id, &query, 10).await.unwrap();
+        tickets.into_iter().map(|t| t.id).collect()
+    }
+
+    #[tokio::test]
+    async fn test_search_tickets() {
+        let db = test_db().await.unwrap();
+        db.create_project(&project_fixture("p1")).await.unwrap();
+        for (id, title, description) in [
+            ("t1", "Phân tích luồng đăng nhập", "Kiểm tra xác thực"),
+            ("t2", "Giỏ hàng", "Tính tổng tiền"),
+            ("t3", "Thanh toán", "Cần phân tích lỗi"),
+        ] {
+            db.create_ticket(&TicketRecord {
+                title: title.to_string(),
+                description: description.to_string(),
+                ..ticket_fixture(id, "p1")
+            })
+            .await
+            .unwrap();
+        }
+
+        // Title matches rank above description ones
+        assert_eq!(search(&db, "phan tich", None).await, ["t1", "t3"]);
+        assert_eq!(search(&db, "DANG NHAP", Some("p1")).await, ["t1"]);
+        assert_eq!(search(&db, "tong tie", None).await, ["t2"]);
+        assert!(search(&db, "phan tich", Some("p2")).await.is_empty());
+
+        db.update_ticket_result("t2", "Đề xuất: làm tròn số tiền").await.unwrap();
+        assert_eq!(search(&db, "de xuat", None).await, ["t2"]);
+        db.delete_ticket("t1").await.unwrap();
+        assert_eq!(search(&db, "phan tich", None).await, ["t3"]);
+    }
+}
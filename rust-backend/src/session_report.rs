@@ -0,0 +1,325 @@
+//! Standalone HTML report of one analysis session (ticket, prompt, tool timeline, answer,
+//! duration and cost) for `GET /api/sessions/:id/report`, rendered from `templates/session_report.html`
+
+use crate::code_agent::CodeAnalysisRequest;
+use crate::database::{AnalysisSession, Database, TicketRecord};
+use crate::markdown::{escape_html, highlight_css, render_markdown};
+use crate::timeline::{self, TimelineEvent, TimelineKind};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+
+const TEMPLATE: &str = include_str!("../templates/session_report.html");
+
+/// Everything the report shows, read before rendering
+#[derive(Debug)]
+pub struct SessionReport {
+    pub session: AnalysisSession,
+    pub ticket: TicketRecord,
+    pub project_name: Option<String>,
+    /// Request the agent received; None for sessions recorded before requests were kept
+    pub request: Option<CodeAnalysisRequest>,
+    pub events: Vec<TimelineEvent>,
+    /// Markdown answer; tickets keep only their latest run's
+    pub answer: Option<String>,
+    /// Whether a later run replaced this run's answer
+    pub superseded: bool,
+}
+
+impl SessionReport {
+    /// None when the session's ticket no longer exists
+    pub async fn build(db: &Database, session: AnalysisSession) -> Result<Option<Self>> {
+        let Some(ticket) = db.get_ticket(&session.ticket_id).await? else {
+            return Ok(None);
+        };
+        let project_name = db.get_project(&ticket.project_id).await?.map(|project| project.name);
+        let request = session
+            .request_json
+            .as_deref()
+            .and_then(|json| serde_json::from_str::<CodeAnalysisRequest>(json).ok());
+        let entries = timeline::run_logs(db, &ticket.id, Some(&session)).await?;
+        let events = timeline::run_timeline(&ticket.id, Some(&session), &entries).events;
+
+        let latest = db.get_latest_session_by_ticket(&ticket.id).await?;
+        let superseded = latest.is_some_and(|latest| latest.id != session.id);
+        let answer = match session.status.as_str() {
+            "completed" if !superseded => db.full_result(&ticket).await?,
+            _ => None,
+        };
+
+        Ok(Some(Self {
+            session,
+            ticket,
+            project_name,
+            request,
+            events,
+            answer,
+            superseded,
+        }))
+    }
+
+    /// `tz` is the reader's timezone preference; times are shown in UTC without one
+    pub fn render(&self, generated_at: DateTime<Utc>, tz: Option<Tz>) -> String {
+        let session = &self.session;
+        let started = parse_time(&session.started_at);
+        let local = |at: &str| local_time(at, tz);
+
+        let mut ticket_facts = String::new();
+        push_fact(&mut ticket_facts, "Status", Some(&self.ticket.status));
+        push_fact(&mut ticket_facts, "Created", Some(&local(&self.ticket.created_at)));
+        push_fact(&mut ticket_facts, "Code context", self.ticket.code_context.as_deref());
+
+        let duration = match (started, session.completed_at.as_deref().and_then(parse_time)) {
+            (Some(started), Some(completed)) => Some(format_duration((completed - started).num_milliseconds())),
+            _ => None,
+        };
+        let mut run_facts = format!(
+            "<dt>Status</dt><dd class=\"status-{0}\">{0}</dd>",
+            escape_html(&session.status)
+        );
+        push_fact(&mut run_facts, "Agent", session.agent_type.as_deref());
+        push_fact(&mut run_facts, "Model", session.model.as_deref());
+        push_fact(&mut run_facts, "CLI version", session.cli_version.as_deref());
+        push_fact(&mut run_facts, "Started", Some(&local(&session.started_at)));
+        push_fact(&mut run_facts, "Completed", session.completed_at.as_deref().map(local).as_deref());
+        push_fact(&mut run_facts, "Duration", duration.as_deref());
+        push_fact(&mut run_facts, "Cost", session.cost_usd.map(|cost| format!("${:.4}", cost)).as_deref());
+        push_fact(&mut run_facts, "Git commit", session.git_commit.as_deref());
+        push_fact(&mut run_facts, "Run ID", session.run_id.as_deref());
+        push_fact(&mut run_facts, "Replay of", session.replay_of.as_deref());
+        push_fact(&mut run_facts, "Error", session.error_message.as_deref());
+
+        let prompt = match &self.request {
+            Some(request) => format!(
+                "<p><strong>Question</strong></p><pre>{}</pre>\
+                 <p class=\"meta\">Mode: {} · code context: {}</p>\
+                 <p><strong>As sent to the agent</strong></p><pre>{}</pre>",
+                escape_html(&request.question),
+                escape_html(&format!("{:?}", request.mode).to_lowercase()),
+                escape_html(if request.code_context.is_empty() { "whole project" } else { &request.code_context }),
+                escape_html(&request.prompt_question()),
+            ),
+            None => "<p class=\"meta\">The request of this run wasn't recorded.</p>".to_string(),
+        };
+
+        let answer = match (&self.answer, self.superseded) {
+            (Some(answer), _) => render_markdown(answer),
+            (None, true) => "<p class=\"meta\">A later run of this ticket replaced this run's answer.</p>".to_string(),
+            (None, false) => "<p class=\"meta\">This run produced no answer.</p>".to_string(),
+        };
+
+        fill(
+            TEMPLATE,
+            &[
+                ("title", escape_html(&self.ticket.title)),
+                ("project", escape_html(self.project_name.as_deref().unwrap_or("Unknown project"))),
+                ("generated_at", escape_html(&local(&generated_at.to_rfc3339()))),
+                ("ticket_facts", ticket_facts),
+                ("description", escape_html(&self.ticket.description)),
+                ("run_facts", run_facts),
+                ("prompt", prompt),
+                ("tool_summary", escape_html(&tool_summary(&self.events))),
+                ("timeline", timeline_table(&self.events, started)),
+                ("answer", answer),
+                ("session_id", escape_html(&session.id)),
+                ("highlight_css", highlight_css()),
+            ],
+        )
+    }
+}
+
+/// Replace each `{{name}}` of `template` in one pass, so values can't inject placeholders
+fn fill(template: &str, values: &[(&str, String)]) -> String {
+    let mut output = String::with_capacity(template.len() * 2);
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        match after.find("}}") {
+            Some(end) => {
+                let name = &after[..end];
+                match values.iter().find(|(key, _)| *key == name) {
+                    Some((_, value)) => output.push_str(value),
+                    None => output.push_str(&rest[start..start + end + 4]),
+                }
+                rest = &after[end + 2..];
+            }
+            None => {
+                output.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+fn push_fact(facts: &mut String, label: &str, value: Option<&str>) {
+    if let Some(value) = value.filter(|value| !value.is_empty()) {
+        facts.push_str(&format!("<dt>{}</dt><dd>{}</dd>", label, escape_html(value)));
+    }
+}
+
+fn parse_time(at: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(at).ok().map(|at| at.with_timezone(&Utc))
+}
+
+/// `at` in `tz`, unchanged when either is missing or unparsable
+fn local_time(at: &str, tz: Option<Tz>) -> String {
+    match (tz, DateTime::parse_from_rfc3339(at)) {
+        (Some(tz), Ok(at)) => at.with_timezone(&tz).format("%Y-%m-%d %H:%M:%S %Z").to_string(),
+        _ => at.to_string(),
+    }
+}
+
+fn format_duration(ms: i64) -> String {
+    let seconds = ms.max(0) / 1000;
+    match seconds {
+        0 => format!("{}ms", ms.max(0)),
+        1..=59 => format!("{}s", seconds),
+        _ => format!("{}m {}s", seconds / 60, seconds % 60),
+    }
+}
+
+fn kind_label(kind: TimelineKind) -> &'static str {
+    match kind {
+        TimelineKind::FileRead => "file read",
+        TimelineKind::Search => "search",
+        TimelineKind::Command => "command",
+        TimelineKind::Tool => "tool",
+        TimelineKind::Summary => "summary",
+        TimelineKind::Error => "error",
+    }
+}
+
+/// e.g. `12 file reads · 3 searches · 1 command`
+fn tool_summary(events: &[TimelineEvent]) -> String {
+    let kinds = [TimelineKind::FileRead, TimelineKind::Search, TimelineKind::Command, TimelineKind::Tool, TimelineKind::Error];
+    let parts: Vec<String> = kinds
+        .iter()
+        .filter_map(|kind| {
+            let count = events.iter().filter(|event| event.kind == *kind).count();
+            let plural = match kind {
+                TimelineKind::Search => "es",
+                _ => "s",
+            };
+            (count > 0).then(|| format!("{} {}{}", count, kind_label(*kind), if count == 1 { "" } else { plural }))
+        })
+        .collect();
+    if parts.is_empty() {
+        "No tool calls recorded".to_string()
+    } else {
+        parts.join(" · ")
+    }
+}
+
+fn timeline_table(events: &[TimelineEvent], started: Option<DateTime<Utc>>) -> String {
+    if events.is_empty() {
+        return "<p class=\"meta\">No logs recorded for this run.</p>".to_string();
+    }
+
+    let mut table = String::from("<table><thead><tr><th>At</th><th>Kind</th><th>Detail</th><th>Took</th></tr></thead><tbody>");
+    for event in events {
+        let offset = match (started, parse_time(&event.started_at)) {
+            (Some(started), Some(at)) => format!("+{}", format_duration((at - started).num_milliseconds())),
+            _ => String::new(),
+        };
+        let detail = match (&event.tool, &event.target, &event.text) {
+            (Some(tool), Some(target), _) => format!("<code>{}</code> {}", escape_html(tool), escape_html(target)),
+            (Some(tool), None, _) => format!("<code>{}</code>", escape_html(tool)),
+            (None, _, Some(text)) => escape_html(text),
+            _ => String::new(),
+        };
+        let label = kind_label(event.kind);
+        table.push_str(&format!(
+            "<tr><td class=\"num\">{}</td><td><span class=\"kind kind-{}\">{}</span></td><td>{}</td><td class=\"num\">{}</td></tr>",
+            offset,
+            label.replace(' ', "-"),
+            label,
+            detail,
+            event.duration_ms.map(format_duration).unwrap_or_default(),
+        ));
+    }
+    table.push_str("</tbody></table>");
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::ticket_fixture;
+
+    fn event(kind: TimelineKind, at: &str, tool: Option<&str>, target: Option<&str>, text: Option<&str>) -> TimelineEvent {
+        TimelineEvent {
+            kind,
+            started_at: at.to_string(),
+            duration_ms: Some(1500),
+            tool: tool.map(str::to_string),
+            target: target.map(str::to_string),
+            text: text.map(str::to_string),
+            log_id: "log".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_fill_single_pass() {
+        let filled = fill(
+            "<h1>{{title}}</h1>{{body}}{{unknown}}{{",
+            &[("title", "{{body}}".to_string()), ("body", "<p>x</p>".to_string())],
+        );
+        assert_eq!(filled, "<h1>{{body}}</h1><p>x</p>{{unknown}}{{");
+    }
+
+    #[test]
+    fn test_render_report() {
+        let session: AnalysisSession = serde_json::from_value(serde_json::json!({
+            "id": "s1",
+            "ticket_id": "t1",
+            "started_at": "2026-10-17T10:00:00+00:00",
+            "completed_at": "2026-10-17T10:02:05+00:00",
+            "status": "completed",
+            "error_message": null,
+            "agent_type": "claude",
+            "run_id": "r1",
+            "tool_policy": null,
+            "cost_usd": 0.0421,
+        }))
+        .unwrap();
+        let ticket = TicketRecord {
+            title: "Login <flow>".to_string(),
+            description: "How does login work?".to_string(),
+            status: "done".to_string(),
+            created_at: "2026-10-16T09:00:00+00:00".to_string(),
+            updated_at: "2026-10-17T10:02:05+00:00".to_string(),
+            ..ticket_fixture("t1", "p1")
+        };
+        let report = SessionReport {
+            session,
+            ticket,
+            project_name: Some("Shop".to_string()),
+            request: None,
+            events: vec![
+                event(TimelineKind::FileRead, "2026-10-17T10:00:03+00:00", Some("Read"), Some("src/auth.rs"), None),
+                event(TimelineKind::Summary, "2026-10-17T10:01:00+00:00", None, None, Some("Login checks <b>passwords</b>")),
+            ],
+            answer: Some("## Flow\n\n<script>alert(1)</script>Checks the password.".to_string()),
+            superseded: false,
+        };
+
+        let html = report.render(chrono::Utc::now(), None);
+        assert!(html.contains("<title>Login &lt;flow&gt;</title>"));
+        assert!(html.contains("<dd>2m 5s</dd>"));
+        assert!(html.contains("<dd>$0.0421</dd>"));
+        assert!(html.contains("1 file read"));
+        assert!(html.contains("<code>Read</code> src/auth.rs"));
+        assert!(html.contains("+3s"));
+        assert!(html.contains("Login checks &lt;b&gt;passwords&lt;/b&gt;"));
+        assert!(html.contains("<h2>Flow</h2>"));
+        assert!(!html.contains("<script>"));
+        assert!(!html.contains("{{"));
+
+        // Times follow the reader's timezone
+        let local = report.render(chrono::Utc::now(), Some(chrono_tz::Asia::Ho_Chi_Minh));
+        assert!(local.contains("<dd>2026-10-17 17:00:00 +07</dd>"));
+    }
+}
@@ -0,0 +1,527 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+    Form,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::api_handlers::LogsQueryParams;
+use crate::auth::{self, AuthContext};
+use crate::database::{StructuredLogRecord, TicketRecord, TicketShareRecord};
+use crate::markdown::{self, escape_html};
+use crate::AppState;
+
+/// Header carrying the password of a protected link on `GET /api/share/:token`
+const SHARE_PASSWORD_HEADER: &str = "x-share-password";
+/// Logs shown on the HTML page; the JSON endpoint pages through all of them
+const PAGE_LOG_LIMIT: u64 = 1000;
+/// Longest lifetime a link can be given: a year
+const MAX_EXPIRES_IN_HOURS: i64 = 365 * 24;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct CreateShareRequest {
+    /// Lifetime of the link, at most a year; it never expires when unset
+    pub expires_in_hours: Option<i64>,
+    pub password: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ShareLink {
+    #[serde(flatten)]
+    pub share: TicketShareRecord,
+    pub has_password: bool,
+    /// Neither revoked nor expired
+    pub active: bool,
+}
+
+impl From<TicketShareRecord> for ShareLink {
+    fn from(share: TicketShareRecord) -> Self {
+        Self {
+            has_password: share.password_hash.is_some(),
+            active: share_status(&share, Utc::now()).is_ok(),
+            share,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreateShareResponse {
+    #[serde(flatten)]
+    pub link: ShareLink,
+    /// Shown once; only its hash is stored
+    pub token: String,
+    /// Read-only page for the link, relative to the server
+    pub url: String,
+}
+
+/// What a share link exposes: the ticket's question, result and logs, nothing else
+#[derive(Debug, Serialize)]
+pub struct SharedTicket {
+    pub title: String,
+    pub description: String,
+    pub status: String,
+    pub analysis_result: Option<String>,
+    /// `analysis_result` rendered like `GET /api/tickets/:id/result/html`
+    pub analysis_html: Option<String>,
+    pub logs: Vec<StructuredLogRecord>,
+    pub total_logs: u64,
+    pub has_more: bool,
+    pub expires_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SharePasswordForm {
+    pub password: String,
+}
+
+fn internal(context: &str) -> impl Fn(anyhow::Error) -> StatusCode + '_ {
+    move |e| {
+        tracing::error!("{}: {}", context, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
+
+async fn authorized_ticket(state: &AppState, auth: &AuthContext, id: &str) -> Result<TicketRecord, StatusCode> {
+    auth.ticket(&state.database, id)
+        .await
+        .map_err(internal("Failed to get ticket"))?
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// 410 for revoked or expired links
+fn share_status(share: &TicketShareRecord, now: DateTime<Utc>) -> Result<(), StatusCode> {
+    if share.revoked_at.is_some() {
+        return Err(StatusCode::GONE);
+    }
+    let expired = share
+        .expires_at
+        .as_deref()
+        .and_then(|expires_at| DateTime::parse_from_rfc3339(expires_at).ok())
+        .is_some_and(|expires_at| expires_at <= now);
+    if expired {
+        return Err(StatusCode::GONE);
+    }
+    Ok(())
+}
+
+/// When a link created at `now` for `hours` expires; 400 unless 1 to `MAX_EXPIRES_IN_HOURS`
+fn expiry(hours: Option<i64>, now: DateTime<Utc>) -> Result<Option<DateTime<Utc>>, StatusCode> {
+    let Some(hours) = hours else { return Ok(None) };
+    if hours <= 0 || hours > MAX_EXPIRES_IN_HOURS {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    Duration::try_hours(hours)
+        .and_then(|lifetime| now.checked_add_signed(lifetime))
+        .map(Some)
+        .ok_or(StatusCode::BAD_REQUEST)
+}
+
+/// Ticket behind a usable link token: 404 unknown or in the trash, 410 revoked/expired, 401
+/// missing or wrong password
+async fn resolve_share(
+    state: &AppState,
+    token: &str,
+    password: Option<&str>,
+) -> Result<(TicketShareRecord, TicketRecord), StatusCode> {
+    let share = state
+        .database
+        .get_ticket_share_by_token(&auth::hash_token(token))
+        .await
+        .map_err(internal("Failed to look up share link"))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    share_status(&share, Utc::now())?;
+
+    if let Some(password_hash) = &share.password_hash {
+        match password {
+            Some(password) if auth::verify_password(password, password_hash) => {}
+            _ => return Err(StatusCode::UNAUTHORIZED),
+        }
+    }
+
+    let ticket = state
+        .database
+        .get_ticket(&share.ticket_id)
+        .await
+        .map_err(internal("Failed to get shared ticket"))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    // Links of a trashed project stop working until it is restored
+    state
+        .database
+        .get_project(&ticket.project_id)
+        .await
+        .map_err(internal("Failed to get shared ticket's project"))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok((share, ticket))
+}
+
+// POST /api/tickets/:id/share
+pub async fn create_share(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    data: Option<Json<CreateShareRequest>>,
+) -> Result<Json<CreateShareResponse>, StatusCode> {
+    authorized_ticket(&state, &auth, &id).await?;
+    let Json(data) = data.unwrap_or_default();
+
+    let now = Utc::now();
+    let expires_at = expiry(data.expires_in_hours, now)?.map(|expires_at| expires_at.to_rfc3339());
+    // Hashed as given, like the password it is checked against; a blank one means none
+    let password_hash = match data.password.as_deref() {
+        Some(password) if !password.trim().is_empty() => {
+            Some(auth::hash_password(password).map_err(internal("Failed to hash share password"))?)
+        }
+        _ => None,
+    };
+
+    let token = auth::generate_token();
+    let share = TicketShareRecord {
+        id: uuid::Uuid::new_v4().to_string(),
+        ticket_id: id,
+        token_hash: auth::hash_token(&token),
+        password_hash,
+        created_by: auth.user_id.clone(),
+        created_at: now.to_rfc3339(),
+        expires_at,
+        revoked_at: None,
+    };
+
+    state
+        .database
+        .create_ticket_share(&share)
+        .await
+        .map_err(internal("Failed to create share link"))?;
+
+    info!("Created share link {} for ticket {}", share.id, share.ticket_id);
+    Ok(Json(CreateShareResponse {
+        link: share.into(),
+        url: format!("/share/{}", token),
+        token,
+    }))
+}
+
+// GET /api/tickets/:id/shares
+pub async fn list_shares(
+    auth: AuthContext,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<ShareLink>>, StatusCode> {
+    authorized_ticket(&state, &auth, &id).await?;
+
+    let shares = state
+        .database
+        .list_ticket_shares(&id)
+        .await
+        .map_err(internal("Failed to list share links"))?;
+    Ok(Json(shares.into_iter().map(ShareLink::from).collect()))
+}
+
+// DELETE /api/tickets/:id/shares/:share_id
+pub async fn revoke_share(
+    auth: AuthContext,
+    Path((id, share_id)): Path<(String, String)>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, StatusCode> {
+    authorized_ticket(&state, &auth, &id).await?;
+
+    let share = state
+        .database
+        .get_ticket_share(&id, &share_id)
+        .await
+        .map_err(internal("Failed to get share link"))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    // Links can be revoked by whoever created them and by organization admins
+    if share.created_by != auth.user_id && !auth.can_manage_org() {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    state
+        .database
+        .revoke_ticket_share(&share.id)
+        .await
+        .map_err(internal("Failed to revoke share link"))?;
+
+    info!("Revoked share link {} for ticket {}", share.id, share.ticket_id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// GET /api/share/:token (password in the X-Share-Password header)
+pub async fn get_shared_ticket(
+    Path(token): Path<String>,
+    Query(params): Query<LogsQueryParams>,
+    headers: HeaderMap,
+    State(state): State<AppState>,
+) -> Result<Response, StatusCode> {
+    let password = headers.get(SHARE_PASSWORD_HEADER).and_then(|v| v.to_str().ok());
+    let (share, ticket) = resolve_share(&state, &token, password).await?;
+    let shared = shared_ticket(&state, share, ticket, params).await?;
+
+    Ok((private_headers("application/json"), Json(shared)).into_response())
+}
+
+// GET /share/:token
+pub async fn share_page(Path(token): Path<String>, State(state): State<AppState>) -> Response {
+    render_share_page(&state, &token, None).await
+}
+
+// POST /share/:token (password form)
+pub async fn share_page_with_password(
+    Path(token): Path<String>,
+    State(state): State<AppState>,
+    Form(form): Form<SharePasswordForm>,
+) -> Response {
+    render_share_page(&state, &token, Some(&form.password)).await
+}
+
+async fn shared_ticket(
+    state: &AppState,
+    share: TicketShareRecord,
+    ticket: TicketRecord,
+    params: LogsQueryParams,
+) -> Result<SharedTicket, StatusCode> {
+    let total_logs = state
+        .database
+        .count_logs_for_ticket(&ticket.id)
+        .await
+        .map_err(internal("Failed to count shared ticket logs"))?;
+    let logs = state
+        .database
+        .get_logs_for_ticket(&ticket.id, params.limit, params.offset)
+        .await
+        .map_err(internal("Failed to get shared ticket logs"))?;
+    let has_more = params.offset.unwrap_or(0) + (logs.len() as u64) < total_logs;
+    let analysis_result = state
+        .database
+        .full_result(&ticket)
+        .await
+        .map_err(internal("Failed to read shared ticket result"))?;
+
+    Ok(SharedTicket {
+        analysis_html: analysis_result.as_deref().map(markdown::render_markdown),
+        title: ticket.title,
+        description: ticket.description,
+        status: ticket.status,
+        analysis_result,
+        logs,
+        total_logs,
+        has_more,
+        expires_at: share.expires_at,
+    })
+}
+
+/// Link tokens live in the URL: keep them out of caches, search engines and Referer headers
+fn private_headers(content_type: &'static str) -> [(header::HeaderName, &'static str); 4] {
+    [
+        (header::CONTENT_TYPE, content_type),
+        (header::CACHE_CONTROL, "no-store"),
+        (header::REFERRER_POLICY, "no-referrer"),
+        (header::HeaderName::from_static("x-robots-tag"), "noindex"),
+    ]
+}
+
+async fn render_share_page(state: &AppState, token: &str, password: Option<&str>) -> Response {
+    let html_response = |status: StatusCode, body: String| {
+        (status, private_headers("text/html; charset=utf-8"), body).into_response()
+    };
+
+    let shared = match resolve_share(state, token, password).await {
+        Ok((share, ticket)) => {
+            let params = LogsQueryParams {
+                limit: Some(PAGE_LOG_LIMIT),
+                offset: None,
+            };
+            shared_ticket(state, share, ticket, params).await
+        }
+        Err(status) => Err(status),
+    };
+
+    match shared {
+        Ok(shared) => html_response(StatusCode::OK, ticket_page(&shared)),
+        Err(StatusCode::UNAUTHORIZED) => {
+            html_response(StatusCode::UNAUTHORIZED, password_page(password.is_some()))
+        }
+        Err(StatusCode::GONE) => html_response(
+            StatusCode::GONE,
+            message_page("This link has expired or was revoked."),
+        ),
+        Err(StatusCode::NOT_FOUND) => {
+            html_response(StatusCode::NOT_FOUND, message_page("This link does not exist."))
+        }
+        Err(status) => html_response(status, message_page("Something went wrong.")),
+    }
+}
+
+const PAGE_STYLE: &str = "body{font-family:system-ui,sans-serif;max-width:960px;margin:2rem auto;padding:0 1rem;color:#1f2328}\
+pre{background:#f6f8fa;padding:.75rem;overflow-x:auto;border-radius:6px}\
+.meta{color:#59636e;font-size:.9rem}.log{border-bottom:1px solid #d1d9e0;padding:.25rem 0}\
+.log pre{margin:.25rem 0;white-space:pre-wrap}";
+
+fn page(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><meta name=\"robots\" content=\"noindex\">\
+         <title>{}</title><link rel=\"stylesheet\" href=\"/api/markdown/highlight.css\">\
+         <style>{}</style></head><body>{}</body></html>",
+        escape_html(title),
+        PAGE_STYLE,
+        body
+    )
+}
+
+fn ticket_page(shared: &SharedTicket) -> String {
+    let mut body = format!(
+        "<h1>{}</h1><p class=\"meta\">Status: {}{}</p><pre>{}</pre><h2>Analysis</h2>",
+        escape_html(&shared.title),
+        escape_html(&shared.status),
+        shared
+            .expires_at
+            .as_deref()
+            .map(|expires_at| format!(" · link expires {}", escape_html(expires_at)))
+            .unwrap_or_default(),
+        escape_html(&shared.description),
+    );
+    body.push_str(
+        shared
+            .analysis_html
+            .as_deref()
+            .unwrap_or("<p class=\"meta\">No analysis result yet.</p>"),
+    );
+
+    body.push_str(&format!("<h2>Logs ({})</h2>", shared.total_logs));
+    for log in &shared.logs {
+        body.push_str(&format!(
+            "<div class=\"log\"><span class=\"meta\">{} · {}</span><pre>{}</pre></div>",
+            escape_html(&log.timestamp),
+            escape_html(&log.message_type),
+            escape_html(&log.content)
+        ));
+    }
+    if shared.has_more {
+        body.push_str(&format!(
+            "<p class=\"meta\">Showing the first {} logs.</p>",
+            shared.logs.len()
+        ));
+    }
+
+    page(&shared.title, &body)
+}
+
+fn password_page(wrong_password: bool) -> String {
+    let error = if wrong_password {
+        "<p class=\"meta\">Wrong password, try again.</p>"
+    } else {
+        ""
+    };
+    page(
+        "Password required",
+        &format!(
+            "<h1>Password required</h1>{}<form method=\"post\">\
+             <input type=\"password\" name=\"password\" autofocus required> \
+             <button type=\"submit\">View</button></form>",
+            error
+        ),
+    )
+}
+
+fn message_page(message: &str) -> String {
+    page("Shared analysis", &format!("<h1>Shared analysis</h1><p>{}</p>", escape_html(message)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_agent::{MockAgent, MockAgentConfig};
+    use crate::test_support::{project_fixture, temp_dir, test_database, test_state, ticket_fixture};
+    use std::sync::Arc;
+
+    fn share(expires_at: Option<DateTime<Utc>>, revoked: bool) -> TicketShareRecord {
+        TicketShareRecord {
+            id: "s1".to_string(),
+            ticket_id: "t1".to_string(),
+            token_hash: auth::hash_token("token"),
+            password_hash: None,
+            created_by: None,
+            created_at: Utc::now().to_rfc3339(),
+            expires_at: expires_at.map(|t| t.to_rfc3339()),
+            revoked_at: revoked.then(|| Utc::now().to_rfc3339()),
+        }
+    }
+
+    #[test]
+    fn test_share_status() {
+        let now = Utc::now();
+        assert_eq!(share_status(&share(None, false), now), Ok(()));
+        assert_eq!(share_status(&share(Some(now + Duration::hours(1)), false), now), Ok(()));
+        assert_eq!(
+            share_status(&share(Some(now - Duration::hours(1)), false), now),
+            Err(StatusCode::GONE)
+        );
+        assert_eq!(share_status(&share(None, true), now), Err(StatusCode::GONE));
+    }
+
+    #[test]
+    fn test_expiry_bounds() {
+        let now = Utc::now();
+        assert_eq!(expiry(None, now), Ok(None));
+        assert_eq!(expiry(Some(24), now), Ok(Some(now + Duration::hours(24))));
+        assert_eq!(expiry(Some(MAX_EXPIRES_IN_HOURS), now), Ok(Some(now + Duration::days(365))));
+        assert_eq!(expiry(Some(0), now), Err(StatusCode::BAD_REQUEST));
+        assert_eq!(expiry(Some(MAX_EXPIRES_IN_HOURS + 1), now), Err(StatusCode::BAD_REQUEST));
+        assert_eq!(expiry(Some(i64::MAX), now), Err(StatusCode::BAD_REQUEST));
+        assert_eq!(expiry(Some(MAX_EXPIRES_IN_HOURS), DateTime::<Utc>::MAX_UTC), Err(StatusCode::BAD_REQUEST));
+    }
+
+    #[test]
+    fn test_ticket_page_escapes_content() {
+        let shared = SharedTicket {
+            title: "<b>Checkout</b>".to_string(),
+            description: "How does <script>x</script> work?".to_string(),
+            status: "done".to_string(),
+            analysis_result: Some("# Flow".to_string()),
+            analysis_html: Some(markdown::render_markdown("# Flow")),
+            logs: vec![StructuredLogRecord {
+                id: "l1".to_string(),
+                ticket_id: "t1".to_string(),
+                message_type: "assistant".to_string(),
+                content: "<img src=x onerror=alert(1)>".to_string(),
+                raw_log: None,
+                raw_log_blob: None,
+                metadata: None,
+                timestamp: "2026-01-01T00:00:00Z".to_string(),
+            }],
+            total_logs: 1,
+            has_more: false,
+            expires_at: None,
+        };
+
+        let html = ticket_page(&shared);
+        assert!(html.contains("<h1>&lt;b&gt;Checkout&lt;/b&gt;</h1>"));
+        assert!(html.contains("<h1>Flow</h1>"));
+        assert!(!html.contains("<script>"));
+        assert!(!html.contains("<img"));
+    }
+
+    #[tokio::test]
+    async fn test_trashed_project_hides_its_links() {
+        let dir = temp_dir("share-trash").unwrap();
+        let database = test_database(&dir).await.unwrap();
+        let agent = Arc::new(MockAgent::with_config(MockAgentConfig::from_env()));
+        let state = test_state(database.clone(), agent).unwrap();
+        database.create_project(&project_fixture("p1")).await.unwrap();
+        database.create_ticket(&ticket_fixture("t1", "p1")).await.unwrap();
+        database.create_ticket_share(&share(None, false)).await.unwrap();
+
+        let (_, ticket) = resolve_share(&state, "token", None).await.unwrap();
+        assert_eq!(ticket.id, "t1");
+
+        database.delete_project("p1", None).await.unwrap();
+        assert_eq!(resolve_share(&state, "token", None).await.unwrap_err(), StatusCode::NOT_FOUND);
+
+        database.restore_project("p1").await.unwrap();
+        assert!(resolve_share(&state, "token", None).await.is_ok());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,311 @@
+use crate::BroadcastMessage;
+use anyhow::{anyhow, Result};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::sync::Arc;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{info, warn};
+
+/// Slack's own replay window for signed requests
+const MAX_REQUEST_AGE_SECS: i64 = 5 * 60;
+/// Characters per message posted in a result thread; longer answers span several messages
+const MESSAGE_MAX_CHARS: usize = 3_000;
+
+/// Slack app settings, read from `SLACK_SIGNING_SECRET`, `SLACK_API_URL` and `APP_PUBLIC_URL`.
+/// The integration is off unless `SLACK_SIGNING_SECRET` is set.
+#[derive(Debug, Clone)]
+pub struct SlackConfig {
+    /// Verifies that slash commands really come from Slack
+    pub signing_secret: String,
+    /// Web API base, overridable for tests and proxies
+    pub api_url: String,
+    /// Base URL of the frontend, for ticket links
+    pub public_url: String,
+}
+
+impl SlackConfig {
+    pub fn from_env() -> Option<Self> {
+        let signing_secret = std::env::var("SLACK_SIGNING_SECRET")
+            .ok()
+            .filter(|secret| !secret.trim().is_empty())?;
+
+        Some(Self {
+            signing_secret,
+            api_url: std::env::var("SLACK_API_URL")
+                .unwrap_or_else(|_| "https://slack.com/api".to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            public_url: std::env::var("APP_PUBLIC_URL")
+                .unwrap_or_else(|_| "http://localhost:3000".to_string())
+                .trim_end_matches('/')
+                .to_string(),
+        })
+    }
+}
+
+/// Fields of a slash command payload (`application/x-www-form-urlencoded`) we use
+#[derive(Debug, Deserialize)]
+pub struct SlashCommand {
+    pub team_id: String,
+    pub channel_id: String,
+    #[serde(default)]
+    pub user_id: String,
+    #[serde(default)]
+    pub command: String,
+    #[serde(default)]
+    pub text: String,
+}
+
+/// Slack Web API calls made with a workspace's bot token
+pub struct SlackClient {
+    config: SlackConfig,
+    http: reqwest::Client,
+}
+
+impl SlackClient {
+    pub fn new(config: SlackConfig) -> Self {
+        Self {
+            config,
+            http: reqwest::Client::new(),
+        }
+    }
+
+    pub fn config(&self) -> &SlackConfig {
+        &self.config
+    }
+
+    pub fn ticket_link(&self, project_id: &str, ticket_id: &str) -> String {
+        format!("{}/projects/{}?ticket={}", self.config.public_url, project_id, ticket_id)
+    }
+
+    /// Slack reports failures as HTTP 200 with `"ok": false`
+    async fn call(&self, token: &str, method: &str, body: Value) -> Result<Value> {
+        let response: Value = self
+            .http
+            .post(format!("{}/{}", self.config.api_url, method))
+            .bearer_auth(token)
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        if response.get("ok").and_then(Value::as_bool) != Some(true) {
+            let error = response.get("error").and_then(Value::as_str).unwrap_or("unknown_error");
+            return Err(anyhow!("Slack {} failed: {}", method, error));
+        }
+        Ok(response)
+    }
+
+    /// `(team_id, team_name)` of the workspace a bot token belongs to
+    pub async fn team_of(&self, token: &str) -> Result<(String, String)> {
+        let response = self.call(token, "auth.test", json!({})).await?;
+        let field = |key: &str| response.get(key).and_then(Value::as_str).map(str::to_string);
+
+        let team_id = field("team_id").ok_or_else(|| anyhow!("auth.test returned no team_id"))?;
+        let team_name = field("team").unwrap_or_else(|| team_id.clone());
+        Ok((team_id, team_name))
+    }
+
+    /// Post to a channel, or into a thread when `thread_ts` is set; returns the message's `ts`
+    pub async fn post_message(&self, token: &str, channel: &str, thread_ts: Option<&str>, text: &str) -> Result<String> {
+        let mut body = json!({ "channel": channel, "text": text, "unfurl_links": false });
+        if let Some(thread_ts) = thread_ts {
+            body["thread_ts"] = json!(thread_ts);
+        }
+
+        let response = self.call(token, "chat.postMessage", body).await?;
+        response
+            .get("ts")
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .ok_or_else(|| anyhow!("chat.postMessage returned no ts"))
+    }
+
+    pub async fn update_message(&self, token: &str, channel: &str, ts: &str, text: &str) -> Result<()> {
+        self.call(token, "chat.update", json!({ "channel": channel, "ts": ts, "text": text }))
+            .await
+            .map(|_| ())
+    }
+}
+
+/// Where an analysis started from Slack reports back
+pub struct SlackRun {
+    pub token: String,
+    pub channel: String,
+    pub ticket_id: String,
+    /// First line of the root message: who asked what, about which project
+    pub header: String,
+    pub link: String,
+}
+
+/// Mirror an analysis into Slack: the root message follows the run's stage, the final answer
+/// (or error) goes into its thread. `receiver` must be subscribed before the run starts.
+pub async fn relay_analysis(client: Arc<SlackClient>, run: SlackRun, mut receiver: broadcast::Receiver<BroadcastMessage>) {
+    let root = format!("{}\n⏳ Starting…", run.header);
+    let ts = match client.post_message(&run.token, &run.channel, None, &root).await {
+        Ok(ts) => ts,
+        Err(e) => {
+            warn!("⚠️ Không gửi được tin nhắn Slack cho ticket {}: {}", run.ticket_id, e);
+            return;
+        }
+    };
+
+    loop {
+        let message = match receiver.recv().await {
+            Ok(message) if message.ticket_id == run.ticket_id => message,
+            Ok(_) | Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => return,
+        };
+
+        let (status, reply) = match message.message_type.as_str() {
+            "analysis-progress" => {
+                let label = serde_json::from_str::<Value>(&message.content)
+                    .ok()
+                    .and_then(|content| content.get("label")?.as_str().map(str::to_string));
+                if let Some(label) = label {
+                    let text = format!("{}\n⏳ {}", run.header, label);
+                    if let Err(e) = client.update_message(&run.token, &run.channel, &ts, &text).await {
+                        warn!("⚠️ Không cập nhật được tiến độ Slack cho ticket {}: {}", run.ticket_id, e);
+                    }
+                }
+                continue;
+            }
+            "code-analysis-complete" => ("✅ Done", Some(message.content)),
+            "code-analysis-error" => ("❌ Failed", Some(format!("Analysis failed: {}", message.content))),
+            "analysis-stopped" => ("⛔ Stopped", None),
+            _ => continue,
+        };
+
+        for chunk in split_message(&escape(reply.as_deref().unwrap_or_default()), MESSAGE_MAX_CHARS) {
+            if let Err(e) = client.post_message(&run.token, &run.channel, Some(&ts), &chunk).await {
+                warn!("⚠️ Không gửi được kết quả Slack cho ticket {}: {}", run.ticket_id, e);
+                break;
+            }
+        }
+        let text = format!("{}\n{} · <{}|Open ticket>", run.header, status, run.link);
+        if let Err(e) = client.update_message(&run.token, &run.channel, &ts, &text).await {
+            warn!("⚠️ Không cập nhật được tin nhắn Slack cho ticket {}: {}", run.ticket_id, e);
+        }
+        info!("💬 Đã gửi kết quả ticket {} về Slack", run.ticket_id);
+        return;
+    }
+}
+
+/// Check `X-Slack-Signature` (`v0=` + hex HMAC-SHA256 of `v0:{timestamp}:{body}`) and that the
+/// request is recent
+pub fn verify_signature(secret: &str, timestamp: &str, body: &[u8], signature: &str, now: i64) -> bool {
+    let Ok(sent_at) = timestamp.parse::<i64>() else {
+        return false;
+    };
+    if (now - sent_at).abs() > MAX_REQUEST_AGE_SECS {
+        return false;
+    }
+    let Some(Ok(expected)) = signature.strip_prefix("v0=").map(hex::decode) else {
+        return false;
+    };
+    let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(secret.as_bytes()) else {
+        return false;
+    };
+    mac.update(format!("v0:{}:", timestamp).as_bytes());
+    mac.update(body);
+    mac.verify_slice(&expected).is_ok()
+}
+
+/// Split `/explain` text into project and question; a project name with spaces is "quoted"
+pub fn parse_command_text(text: &str) -> Option<(String, String)> {
+    let text = text.trim();
+    let (project, question) = match text.strip_prefix('"') {
+        Some(quoted) => quoted.split_once('"')?,
+        None => text.split_once(char::is_whitespace)?,
+    };
+    let (project, question) = (project.trim(), question.trim());
+    if project.is_empty() || question.is_empty() {
+        return None;
+    }
+    Some((project.to_string(), question.to_string()))
+}
+
+/// Slack treats `&`, `<` and `>` in message text as markup
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// Break text into messages of at most `max_chars`, at line breaks where possible
+pub fn split_message(text: &str, max_chars: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let mut current_chars = 0;
+
+    for mut line in text.split_inclusive('\n') {
+        loop {
+            let line_chars = line.chars().count();
+            if current_chars + line_chars <= max_chars {
+                current.push_str(line);
+                current_chars += line_chars;
+                break;
+            }
+            if current_chars > 0 {
+                chunks.push(std::mem::take(&mut current));
+                current_chars = 0;
+                continue;
+            }
+            // A single line longer than a whole message
+            let (cut, _) = line.char_indices().nth(max_chars).unwrap_or((line.len(), ' '));
+            chunks.push(line[..cut].to_string());
+            line = &line[cut..];
+        }
+    }
+    chunks.push(current);
+
+    chunks
+        .into_iter()
+        .map(|chunk| chunk.trim().to_string())
+        .filter(|chunk| !chunk.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sign(secret: &str, timestamp: &str, body: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(format!("v0:{}:{}", timestamp, body).as_bytes());
+        format!("v0={}", hex::encode(mac.finalize().into_bytes()))
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        let body = "team_id=T1&channel_id=C1&text=shop+how+does+checkout+work";
+        let signature = sign("secret", "1700000000", body);
+
+        assert!(verify_signature("secret", "1700000000", body.as_bytes(), &signature, 1700000010));
+        assert!(!verify_signature("other", "1700000000", body.as_bytes(), &signature, 1700000010));
+        assert!(!verify_signature("secret", "1700000000", b"team_id=T2", &signature, 1700000010));
+        // Replayed long after it was signed
+        assert!(!verify_signature("secret", "1700000000", body.as_bytes(), &signature, 1700001000));
+        assert!(!verify_signature("secret", "1700000000", body.as_bytes(), "v0=zz", 1700000010));
+    }
+
+    #[test]
+    fn test_parse_command_text_and_split() {
+        assert_eq!(
+            parse_command_text(" shop  how does checkout work? "),
+            Some(("shop".to_string(), "how does checkout work?".to_string()))
+        );
+        assert_eq!(
+            parse_command_text(r#""Web Shop" where are prices computed"#),
+            Some(("Web Shop".to_string(), "where are prices computed".to_string()))
+        );
+        assert_eq!(parse_command_text("shop"), None);
+        assert_eq!(parse_command_text(r#""Web Shop"#), None);
+
+        let text = format!("{}\n{}\n{}", "a".repeat(6), "b".repeat(2), "c".repeat(12));
+        assert_eq!(split_message(&text, 10), vec!["aaaaaa\nbb", "cccccccccc", "cc"]);
+        assert_eq!(escape("a < b && c"), "a &lt; b &amp;&amp; c");
+    }
+}
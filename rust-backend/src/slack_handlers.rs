@@ -0,0 +1,236 @@
+use axum::{
+    body::Bytes,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+use crate::auth::AuthContext;
+use crate::database::{SlackWorkspaceRecord, TicketRecord};
+use crate::notifications::summarize;
+use crate::slack::{self, SlackClient, SlackRun, SlashCommand};
+use crate::{AppState, CodeAnalysisRequest};
+
+/// Longest ticket title derived from a Slack question
+const TITLE_MAX_CHARS: usize = 80;
+
+#[derive(Debug, Deserialize)]
+pub struct LinkSlackWorkspaceRequest {
+    /// Bot token (`xoxb-…`) of the app installed in the workspace
+    pub bot_token: String,
+}
+
+fn internal(context: &str) -> impl Fn(anyhow::Error) -> StatusCode + '_ {
+    move |e| {
+        tracing::error!("{}: {}", context, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
+
+fn slack_client(state: &AppState) -> Result<Arc<SlackClient>, StatusCode> {
+    state.slack.clone().ok_or(StatusCode::SERVICE_UNAVAILABLE)
+}
+
+/// Reply only the user who ran the command sees
+fn ephemeral(text: impl Into<String>) -> Json<Value> {
+    Json(json!({ "response_type": "ephemeral", "text": text.into() }))
+}
+
+// POST /api/integrations/slack/commands
+pub async fn slash_command(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<Value>, StatusCode> {
+    let client = slack_client(&state)?;
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).unwrap_or_default();
+    if !slack::verify_signature(
+        &client.config().signing_secret,
+        header("x-slack-request-timestamp"),
+        &body,
+        header("x-slack-signature"),
+        Utc::now().timestamp(),
+    ) {
+        warn!("Rejected Slack command with an invalid signature");
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    let command: SlashCommand = serde_urlencoded::from_bytes(&body).map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let Some(workspace) = state
+        .database
+        .get_slack_workspace(&command.team_id)
+        .await
+        .map_err(internal("Failed to get Slack workspace"))?
+    else {
+        return Ok(ephemeral("This Slack workspace isn't linked to an Explain Source organization yet."));
+    };
+    let Some((project_name, question)) = slack::parse_command_text(&command.text) else {
+        return Ok(ephemeral(format!("Usage: `{} <project> <question>`", command.command)));
+    };
+
+    let projects = state
+        .database
+        .list_projects_by_org(&workspace.org_id)
+        .await
+        .map_err(internal("Failed to list projects"))?;
+    let names: Vec<String> = projects.iter().map(|p| format!("`{}`", p.name)).collect();
+    let Some(project) = projects
+        .into_iter()
+        .find(|p| p.id == project_name || p.name.eq_ignore_ascii_case(&project_name))
+    else {
+        return Ok(ephemeral(format!(
+            "No project named `{}`. Available: {}",
+            project_name,
+            names.join(", ")
+        )));
+    };
+
+    let ticket = TicketRecord {
+        id: uuid::Uuid::new_v4().to_string(),
+        project_id: project.id.clone(),
+        title: summarize(&question, TITLE_MAX_CHARS),
+        description: question.clone(),
+        status: "in-progress".to_string(),
+        code_context: None,
+        analysis_result: None,
+        is_analyzing: false,
+        created_at: Utc::now().to_rfc3339(),
+        updated_at: Utc::now().to_rfc3339(),
+        stale: false,
+        analysis_result_blob: None,
+        analysis_result_size: None,
+        position: 0.0,
+        assignee_id: None,
+        summary: None,
+        mode: Default::default(),
+    };
+    state
+        .database
+        .create_ticket(&ticket)
+        .await
+        .map_err(internal("Failed to create ticket"))?;
+
+    // Subscribe before starting so the relay sees every event of the run
+    let receiver = state.broadcast_tx.subscribe();
+    let request = CodeAnalysisRequest {
+        ticket_id: ticket.id.clone(),
+        code_context: String::new(),
+        question: question.clone(),
+        project_id: project.id.clone(),
+        run_id: None,
+        agent_type: None,
+        mode: Default::default(),
+        path_rules: Default::default(),
+        tool_policy: Default::default(),
+        priority: Default::default(),
+        include_linked_results: false,
+        budget_override: false,
+        replay: None,
+        working_dir: None,
+        prompt_variant: None,
+        response_language: None,
+        api_key: None,
+        then: Vec::new(),
+    };
+    crate::analysis_runner::start_analysis(&state, request, None).await;
+
+    let run = SlackRun {
+        token: workspace.bot_token,
+        channel: command.channel_id,
+        header: format!(
+            "<@{}> asked about *{}*: {}",
+            command.user_id,
+            slack::escape(&project.name),
+            slack::escape(&question)
+        ),
+        link: client.ticket_link(&project.id, &ticket.id),
+        ticket_id: ticket.id.clone(),
+    };
+    state.tasks.spawn(format!("slack-relay {}", ticket.id), slack::relay_analysis(client, run, receiver));
+
+    info!("💬 Slack command started analysis of ticket {} in project {}", ticket.id, project.id);
+    Ok(ephemeral(format!(
+        "Analyzing in *{}*; progress and the answer will be posted in this channel.",
+        slack::escape(&project.name)
+    )))
+}
+
+// GET /api/integrations/slack/workspaces
+pub async fn list_workspaces(
+    auth: AuthContext,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<SlackWorkspaceRecord>>, StatusCode> {
+    let workspaces = state
+        .database
+        .list_slack_workspaces(&auth.org_id)
+        .await
+        .map_err(internal("Failed to list Slack workspaces"))?;
+    Ok(Json(workspaces))
+}
+
+// POST /api/integrations/slack/workspaces
+pub async fn link_workspace(
+    auth: AuthContext,
+    State(state): State<AppState>,
+    Json(data): Json<LinkSlackWorkspaceRequest>,
+) -> Result<Json<SlackWorkspaceRecord>, StatusCode> {
+    auth.require_org_admin()?;
+    let client = slack_client(&state)?;
+
+    let bot_token = data.bot_token.trim().to_string();
+    let (team_id, team_name) = client.team_of(&bot_token).await.map_err(|e| {
+        warn!("Slack rejected the bot token: {}", e);
+        StatusCode::BAD_REQUEST
+    })?;
+
+    let existing = state
+        .database
+        .get_slack_workspace(&team_id)
+        .await
+        .map_err(internal("Failed to get Slack workspace"))?;
+    if existing.as_ref().is_some_and(|w| w.org_id != auth.org_id) {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let workspace = SlackWorkspaceRecord {
+        team_id,
+        org_id: auth.org_id.clone(),
+        team_name,
+        bot_token,
+        installed_by: auth.user_id.clone(),
+        created_at: existing.map(|w| w.created_at).unwrap_or_else(|| Utc::now().to_rfc3339()),
+    };
+    state
+        .database
+        .upsert_slack_workspace(&workspace)
+        .await
+        .map_err(internal("Failed to save Slack workspace"))?;
+
+    info!("💬 Linked Slack workspace {} to organization {}", workspace.team_id, workspace.org_id);
+    Ok(Json(workspace))
+}
+
+// DELETE /api/integrations/slack/workspaces/:team_id
+pub async fn unlink_workspace(
+    auth: AuthContext,
+    Path(team_id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, StatusCode> {
+    auth.require_org_admin()?;
+
+    let deleted = state
+        .database
+        .delete_slack_workspace(&auth.org_id, &team_id)
+        .await
+        .map_err(internal("Failed to unlink Slack workspace"))?;
+    if deleted {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(StatusCode::NOT_FOUND)
+    }
+}
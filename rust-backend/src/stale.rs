@@ -0,0 +1,391 @@
+use crate::database::{ProjectRecord, TicketFileRecord};
+use crate::{AppState, BroadcastMessage, CodeAnalysisRequest};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tokio::time::Duration;
+use tracing::{debug, error, info, warn};
+
+/// Stale checker settings, read from `STALE_CHECK_INTERVAL_SECS` / `STALE_AUTO_REANALYZE`
+#[derive(Debug, Clone)]
+pub struct StaleConfig {
+    /// 0 disables the background checker
+    pub interval_secs: u64,
+    /// Re-run the analysis of tickets as soon as they turn stale
+    pub auto_reanalyze: bool,
+}
+
+impl Default for StaleConfig {
+    fn default() -> Self {
+        Self {
+            interval_secs: 300,
+            auto_reanalyze: false,
+        }
+    }
+}
+
+impl StaleConfig {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            interval_secs: std::env::var("STALE_CHECK_INTERVAL_SECS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(defaults.interval_secs),
+            auto_reanalyze: std::env::var("STALE_AUTO_REANALYZE")
+                .map(|v| v.eq_ignore_ascii_case("true") || v == "1")
+                .unwrap_or(defaults.auto_reanalyze),
+        }
+    }
+}
+
+/// Last known change of a file a ticket's analysis read
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FileChange {
+    pub exists: bool,
+    /// Last commit touching the file in a git checkout (mtime for uncommitted or untracked
+    /// files), mtime elsewhere. `None` when git shows no change since the window start.
+    pub changed_at: Option<DateTime<Utc>>,
+}
+
+impl FileChange {
+    /// Whether an analysis run at `analyzed_at` predates this change
+    pub fn is_newer_than(&self, analyzed_at: &str) -> bool {
+        if !self.exists {
+            return true;
+        }
+        match (self.changed_at, DateTime::parse_from_rfc3339(analyzed_at)) {
+            (Some(changed_at), Ok(analyzed_at)) => changed_at > analyzed_at,
+            _ => false,
+        }
+    }
+}
+
+/// Last change of each root-relative path, looking at git history no further back than `since`
+pub fn file_changes(root: &Path, paths: &[String], since: Option<DateTime<Utc>>) -> HashMap<String, FileChange> {
+    let git = GitHistory::load(root, since);
+
+    paths
+        .iter()
+        .map(|path| {
+            let mtime: Option<DateTime<Utc>> = std::fs::metadata(root.join(path))
+                .and_then(|m| m.modified())
+                .ok()
+                .map(Into::into);
+            let changed_at = match &git {
+                // Checkouts reset mtimes, so clean files go by their commits
+                Some(git) if mtime.is_some() && !git.dirty.contains(path) && git.tracked.contains(path) => {
+                    git.committed.get(path).copied()
+                }
+                _ => mtime,
+            };
+            let change = FileChange {
+                exists: mtime.is_some(),
+                changed_at,
+            };
+            (path.clone(), change)
+        })
+        .collect()
+}
+
+/// Tickets (among those with recorded files) whose analysis read a file changed since
+pub fn stale_tickets(root: &Path, records: &[TicketFileRecord]) -> HashMap<String, bool> {
+    let paths: Vec<String> = records
+        .iter()
+        .map(|r| r.file_path.clone())
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    let since = records
+        .iter()
+        .filter_map(|r| DateTime::parse_from_rfc3339(&r.analyzed_at).ok())
+        .min()
+        .map(|t| t.with_timezone(&Utc));
+    let changes = file_changes(root, &paths, since);
+
+    let mut stale = HashMap::new();
+    for record in records {
+        let changed = changes
+            .get(&record.file_path)
+            .is_some_and(|change| change.is_newer_than(&record.analyzed_at));
+        *stale.entry(record.ticket_id.clone()).or_insert(false) |= changed;
+    }
+    stale
+}
+
+/// Commit times and working tree state of the git checkout containing a project
+struct GitHistory {
+    /// Latest commit time per project-relative path, within the window
+    committed: HashMap<String, DateTime<Utc>>,
+    /// Modified, staged or untracked paths
+    dirty: HashSet<String>,
+    /// Every path git tracks under the project
+    tracked: HashSet<String>,
+}
+
+impl GitHistory {
+    fn load(root: &Path, since: Option<DateTime<Utc>>) -> Option<Self> {
+        // Project directories may be a subdirectory of the repository
+        let prefix = git(root, &["rev-parse", "--show-prefix"])?.trim().to_string();
+
+        let since_arg = since.map(|t| format!("--since={}", t.to_rfc3339()));
+        let mut log_args = vec!["log", "--relative", "--name-only", "--format=%x01%cI"];
+        log_args.extend(since_arg.as_deref());
+        let log = git(root, &log_args)?;
+
+        let mut committed: HashMap<String, DateTime<Utc>> = HashMap::new();
+        let mut commit_time = None;
+        for line in log.lines() {
+            if let Some(time) = line.strip_prefix('\u{1}') {
+                commit_time = DateTime::parse_from_rfc3339(time).ok().map(|t| t.with_timezone(&Utc));
+            } else if let (false, Some(time)) = (line.is_empty(), commit_time) {
+                let latest = committed.entry(line.to_string()).or_insert(time);
+                *latest = (*latest).max(time);
+            }
+        }
+
+        // Porcelain paths are relative to the repository root; NUL-separated, renames
+        // carry their source path as an extra field
+        let status = git(root, &["status", "--porcelain", "-z", "--untracked-files=all", "."])?;
+        let mut dirty = HashSet::new();
+        let mut fields = status.split('\0').filter(|f| !f.is_empty());
+        while let Some(field) = fields.next() {
+            let (code, path) = field.split_at(field.len().min(3));
+            if let Some(path) = path.strip_prefix(prefix.as_str()) {
+                dirty.insert(path.to_string());
+            }
+            if code.starts_with('R') || code.starts_with('C') {
+                fields.next();
+            }
+        }
+
+        let tracked = git(root, &["ls-files"])?.lines().map(str::to_string).collect();
+
+        Some(Self {
+            committed,
+            dirty,
+            tracked,
+        })
+    }
+}
+
+/// Output of a git command run in `dir`, or None outside a repository / without git
+pub(crate) fn git(dir: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["-c", "core.quotePath=false"])
+        .args(args)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Re-check every project's analyzed tickets every `interval_secs`; no-op when the interval is 0
+pub fn spawn_checker(state: AppState, config: StaleConfig) {
+    if config.interval_secs == 0 {
+        info!("🕰️ Stale analysis checker disabled (STALE_CHECK_INTERVAL_SECS=0)");
+        return;
+    }
+
+    info!(
+        "🕰️ Checking for stale analyses every {}s (auto re-analyze: {})",
+        config.interval_secs, config.auto_reanalyze
+    );
+
+    let tasks = state.tasks.clone();
+    tasks.spawn("stale-checker", async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(config.interval_secs));
+        loop {
+            interval.tick().await;
+            if let Err(e) = check_projects(&state, &config).await {
+                error!("❌ Kiểm tra phân tích cũ thất bại: {}", e);
+            }
+        }
+    });
+}
+
+async fn check_projects(state: &AppState, config: &StaleConfig) -> Result<()> {
+    for project in state.database.list_projects().await? {
+        check_project(state, config, &project).await?;
+    }
+    Ok(())
+}
+
+/// Update the stale flag of a project's tickets, announcing (and optionally re-running)
+/// those that just turned stale
+pub async fn check_project(state: &AppState, config: &StaleConfig, project: &ProjectRecord) -> Result<()> {
+    let records = state.database.list_ticket_files_by_project(&project.id).await?;
+    if records.is_empty() {
+        return Ok(());
+    }
+
+    let root = PathBuf::from(&project.directory_path);
+    let stale = tokio::task::spawn_blocking(move || stale_tickets(&root, &records)).await?;
+
+    for (ticket_id, is_stale) in stale {
+        // A running analysis records a fresh file set when it finishes
+        if crate::analysis_runner::is_active(state, &ticket_id).await {
+            continue;
+        }
+        if !state.database.set_ticket_stale(&ticket_id, is_stale).await? || !is_stale {
+            continue;
+        }
+
+        info!("🕰️ Ticket {} đã cũ: file liên quan thay đổi sau lần phân tích cuối", ticket_id);
+        let _ = state.broadcast_tx.send(BroadcastMessage {
+            ticket_id: ticket_id.clone(),
+            message_type: "ticket-stale".to_string(),
+            content: String::new(),
+            timestamp: Utc::now(),
+            org_id: None,
+            changed_files: None,
+        });
+
+        if config.auto_reanalyze {
+            if let Err(e) = reanalyze(state, &ticket_id).await {
+                warn!("⚠️ Không thể phân tích lại ticket {}: {}", ticket_id, e);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Start a new analysis of the ticket with the agent of its last run
+async fn reanalyze(state: &AppState, ticket_id: &str) -> Result<()> {
+    let Some(ticket) = state.database.get_ticket(ticket_id).await? else {
+        return Ok(());
+    };
+    if ticket.is_analyzing {
+        debug!("Ticket {} is already being analyzed", ticket_id);
+        return Ok(());
+    }
+    let agent_type = state
+        .database
+        .get_latest_session_by_ticket(ticket_id)
+        .await?
+        .and_then(|session| session.agent_type);
+
+    let request = CodeAnalysisRequest {
+        ticket_id: ticket.id,
+        code_context: ticket.code_context.unwrap_or_default(),
+        question: ticket.description,
+        project_id: ticket.project_id,
+        run_id: None,
+        agent_type,
+        mode: Default::default(),
+        path_rules: Default::default(),
+        tool_policy: Default::default(),
+        // Re-runs are housekeeping; questions people are waiting on go first
+        priority: crate::job_queue::AnalysisPriority::Low,
+        include_linked_results: false,
+        budget_override: false,
+        replay: None,
+        working_dir: None,
+        prompt_variant: None,
+        response_language: None,
+        api_key: None,
+        then: Vec::new(),
+    };
+    info!("🔁 Tự động phân tích lại ticket {}", ticket_id);
+    crate::analysis_runner::start_analysis(state, request, None).await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(ticket: &str, path: &str, analyzed_at: DateTime<Utc>) -> TicketFileRecord {
+        TicketFileRecord {
+            ticket_id: ticket.to_string(),
+            file_path: path.to_string(),
+            run_id: None,
+            analyzed_at: analyzed_at.to_rfc3339(),
+        }
+    }
+
+    fn run_git(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .env("GIT_AUTHOR_DATE", "2020-01-01T00:00:00Z")
+            .env("GIT_COMMITTER_DATE", "2020-01-01T00:00:00Z")
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    #[test]
+    fn test_stale_tickets_by_mtime() {
+        let root = std::env::temp_dir().join(format!("stale-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("pay.rs"), "// pay").unwrap();
+
+        let past = Utc::now() - chrono::Duration::days(1);
+        let future = Utc::now() + chrono::Duration::days(1);
+        let stale = stale_tickets(
+            &root,
+            &[
+                record("old", "pay.rs", past),
+                record("fresh", "pay.rs", future),
+                record("deleted", "gone.rs", future),
+            ],
+        );
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert!(stale["old"]);
+        assert!(!stale["fresh"]);
+        assert!(stale["deleted"]);
+    }
+
+    #[test]
+    fn test_file_changes_use_git_commits() {
+        if git(&std::env::temp_dir(), &["--version"]).is_none() {
+            return;
+        }
+        // Project in a subdirectory of the repository, as for monorepos
+        let repo = std::env::temp_dir().join(format!("stale-git-{}", uuid::Uuid::new_v4()));
+        let project = repo.join("app");
+        std::fs::create_dir_all(&project).unwrap();
+        std::fs::write(project.join("clean.rs"), "// clean").unwrap();
+        std::fs::write(project.join("edited.rs"), "// v1").unwrap();
+        run_git(&repo, &["init", "-q"]);
+        run_git(&repo, &["add", "."]);
+        run_git(&repo, &["commit", "-q", "-m", "init"]);
+        std::fs::write(project.join("edited.rs"), "// v2").unwrap();
+        std::fs::write(project.join("new.rs"), "// new").unwrap();
+
+        let analyzed_at = "2021-01-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let stale = stale_tickets(
+            &project,
+            &[
+                record("clean", "clean.rs", analyzed_at),
+                record("edited", "edited.rs", analyzed_at),
+                record("new", "new.rs", analyzed_at),
+            ],
+        );
+        let paths = ["clean.rs".to_string()];
+        let all_history = file_changes(&project, &paths, None);
+        let recent_history = file_changes(&project, &paths, Some(analyzed_at));
+        std::fs::remove_dir_all(&repo).unwrap();
+
+        // The clean file's fresh mtime is ignored in favor of its 2020 commit
+        assert!(!stale["clean"]);
+        assert_eq!(
+            all_history["clean.rs"].changed_at,
+            Some("2020-01-01T00:00:00Z".parse().unwrap())
+        );
+        assert_eq!(recent_history["clean.rs"].changed_at, None);
+        // Uncommitted edits and untracked files go by mtime
+        assert!(stale["edited"]);
+        assert!(stale["new"]);
+    }
+}
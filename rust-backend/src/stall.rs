@@ -0,0 +1,273 @@
+//! Stall detection for the CLI agents: a CLI that prints nothing on stdout or stderr for
+//! `AGENT_STALL_SECONDS` gets a warning in the ticket's log (and a SIGINT with
+//! `AGENT_STALL_INTERRUPT`); still silent after the grace period, it is killed and the run fails
+//! with [`Stalled`] instead of waiting out the whole timeout.
+
+use crate::message_store::{LogMessageType, MsgStore, StructuredLogEntry};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tracing::warn;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StallPolicy {
+    /// Silence after which the run counts as stalled; None disables detection
+    pub idle: Option<Duration>,
+    /// Send SIGINT when the run stalls, in case the CLI is waiting on something it can give up
+    pub interrupt: bool,
+    /// Between the warning and the kill; output in the meantime lets the run go on
+    pub grace: Duration,
+}
+
+impl Default for StallPolicy {
+    fn default() -> Self {
+        Self {
+            idle: None,
+            interrupt: false,
+            grace: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Why a run was killed for going silent, as reported to the user
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[error("Agent stalled: no output for {0}s")]
+pub struct Stalled(pub u64);
+
+impl Stalled {
+    /// Error entry shown in the run's log
+    pub fn log_entry(&self, ticket_id: &str) -> StructuredLogEntry {
+        StructuredLogEntry {
+            id: uuid::Uuid::new_v4().to_string(),
+            ticket_id: ticket_id.to_string(),
+            message_type: LogMessageType::Error,
+            content: format!("⛔ Agent bị dừng vì không có output trong {}s", self.0),
+            raw_log: None,
+            metadata: HashMap::from([("stalled".to_string(), self.0.to_string())]),
+            timestamp: chrono::Utc::now(),
+        }
+    }
+}
+
+/// Marks output from the process; clones share it, so the stdout and stderr readers can each
+/// hold one
+#[derive(Debug, Clone)]
+pub struct Heartbeat {
+    started: Instant,
+    /// Milliseconds from `started` to the latest output
+    last_ms: Arc<AtomicU64>,
+}
+
+impl Heartbeat {
+    fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            last_ms: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn beat(&self) {
+        self.last_ms.store(self.started.elapsed().as_millis() as u64, Ordering::SeqCst);
+    }
+
+    fn silent_for(&self) -> Duration {
+        self.started.elapsed().saturating_sub(Duration::from_millis(self.last_ms.load(Ordering::SeqCst)))
+    }
+}
+
+impl StallPolicy {
+    pub fn from_env() -> Self {
+        let number = |name: &str| std::env::var(name).ok().and_then(|v| v.trim().parse::<u64>().ok());
+        let defaults = Self::default();
+        Self {
+            idle: number("AGENT_STALL_SECONDS").filter(|secs| *secs > 0).map(Duration::from_secs),
+            interrupt: std::env::var("AGENT_STALL_INTERRUPT")
+                .map(|v| matches!(v.trim().to_lowercase().as_str(), "true" | "1" | "yes" | "on"))
+                .unwrap_or(false),
+            grace: number("AGENT_STALL_GRACE_SECONDS").map(Duration::from_secs).unwrap_or(defaults.grace),
+        }
+    }
+
+    /// Start watching the spawned process `pid` of `ticket_id`'s run
+    pub fn watch(&self, pid: Option<u32>, ticket_id: &str, msg_store: Arc<MsgStore>) -> StallWatch {
+        let heartbeat = Heartbeat::new();
+        let fired = Arc::new(AtomicBool::new(false));
+        let task = match (self.idle, pid) {
+            (Some(idle), Some(pid)) => Some(tokio::spawn(watchdog(
+                self.clone(),
+                idle,
+                pid,
+                ticket_id.to_string(),
+                msg_store,
+                heartbeat.clone(),
+                fired.clone(),
+            ))),
+            _ => None,
+        };
+        StallWatch {
+            idle: self.idle,
+            heartbeat,
+            fired,
+            task,
+        }
+    }
+}
+
+async fn watchdog(
+    policy: StallPolicy,
+    idle: Duration,
+    pid: u32,
+    ticket_id: String,
+    msg_store: Arc<MsgStore>,
+    heartbeat: Heartbeat,
+    fired: Arc<AtomicBool>,
+) {
+    loop {
+        let silent = heartbeat.silent_for();
+        if silent < idle {
+            tokio::time::sleep(idle - silent).await;
+            continue;
+        }
+
+        warn!("⏳ Agent của ticket {} không có output trong {}s", ticket_id, silent.as_secs());
+        let mut content = format!(
+            "⏳ Agent không có output trong {}s, sẽ dừng sau {}s nếu vẫn im lặng",
+            silent.as_secs(),
+            policy.grace.as_secs()
+        );
+        if policy.interrupt {
+            signal(pid, Signal::Interrupt);
+            content.push_str(" (đã gửi SIGINT)");
+        }
+        msg_store
+            .push(StructuredLogEntry {
+                id: uuid::Uuid::new_v4().to_string(),
+                ticket_id: ticket_id.clone(),
+                message_type: LogMessageType::System,
+                content,
+                raw_log: None,
+                metadata: HashMap::from([("stall_warning".to_string(), silent.as_secs().to_string())]),
+                timestamp: chrono::Utc::now(),
+            })
+            .await;
+
+        tokio::time::sleep(policy.grace).await;
+        if heartbeat.silent_for() < policy.grace {
+            continue;
+        }
+        fired.store(true, Ordering::SeqCst);
+        signal(pid, Signal::Kill);
+        return;
+    }
+}
+
+enum Signal {
+    Interrupt,
+    Kill,
+}
+
+fn signal(pid: u32, signal: Signal) {
+    #[cfg(unix)]
+    {
+        let signal = match signal {
+            Signal::Interrupt => libc::SIGINT,
+            Signal::Kill => libc::SIGKILL,
+        };
+        // SAFETY: plain syscall
+        unsafe {
+            libc::kill(pid as libc::pid_t, signal);
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = (pid, signal);
+}
+
+/// The stall watch on one spawned process; stopped on drop
+#[derive(Debug)]
+pub struct StallWatch {
+    idle: Option<Duration>,
+    heartbeat: Heartbeat,
+    fired: Arc<AtomicBool>,
+    task: Option<JoinHandle<()>>,
+}
+
+impl StallWatch {
+    pub fn heartbeat(&self) -> Heartbeat {
+        self.heartbeat.clone()
+    }
+
+    /// Whether the run ended because the watch killed it
+    pub fn stalled(&mut self) -> Option<Stalled> {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+        self.fired
+            .load(Ordering::SeqCst)
+            .then(|| Stalled(self.idle.unwrap_or_default().as_secs()))
+    }
+}
+
+impl Drop for StallWatch {
+    fn drop(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::tasks::TaskSupervisor;
+    use crate::test_support::test_db;
+    use tokio::process::Command;
+
+    async fn msg_store() -> Arc<MsgStore> {
+        let db = Arc::new(test_db().await.unwrap());
+        Arc::new(MsgStore::new(db, &TaskSupervisor::default()))
+    }
+
+    fn policy() -> StallPolicy {
+        StallPolicy {
+            idle: Some(Duration::from_millis(300)),
+            interrupt: false,
+            grace: Duration::from_millis(300),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_silent_process_is_killed() {
+        let msg_store = msg_store().await;
+        let mut child = Command::new("sleep").arg("30").spawn().unwrap();
+        let mut watch = policy().watch(child.id(), "t1", msg_store.clone());
+
+        let started = std::time::Instant::now();
+        child.wait().await.unwrap();
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert!(watch.stalled().is_some());
+        let logs = msg_store.get_logs("t1").await;
+        assert_eq!(logs.len(), 1);
+        assert!(logs[0].metadata.contains_key("stall_warning"));
+    }
+
+    #[tokio::test]
+    async fn test_output_keeps_the_run_alive() {
+        let msg_store = msg_store().await;
+        let mut child = Command::new("sleep").arg("1").spawn().unwrap();
+        let mut watch = policy().watch(child.id(), "t1", msg_store);
+        let heartbeat = watch.heartbeat();
+        let beating = tokio::spawn(async move {
+            loop {
+                heartbeat.beat();
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        });
+
+        assert!(child.wait().await.unwrap().success());
+        beating.abort();
+        assert_eq!(watch.stalled(), None);
+    }
+}
@@ -0,0 +1,119 @@
+use axum::{
+    extract::Request,
+    handler::HandlerWithoutStateExt,
+    http::{header, HeaderValue, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    Router,
+};
+use std::path::{Path, PathBuf};
+use tower::Service;
+use tower_http::{
+    compression::CompressionLayer,
+    services::{ServeDir, ServeFile},
+};
+
+/// Prefix Next.js uses for content-hashed build assets
+const FINGERPRINTED_PREFIX: &str = "/_next/static/";
+
+/// Paths that never fall back to `index.html`: unknown API routes, and build assets a stale
+/// client asks for after a deploy, which must not be answered (and cached) as HTML
+const NO_FALLBACK_PREFIXES: [&str; 3] = ["/api/", "/graphql/", FINGERPRINTED_PREFIX];
+
+/// Router serving the frontend bundle, falling back to `index.html` for SPA routes
+///
+/// Mounted as the app's fallback service so every API/WebSocket route keeps priority.
+pub fn frontend_router(dist_dir: &Path, max_age_seconds: u64) -> Router {
+    let index = dist_dir.join("index.html");
+    let spa_fallback = move |request: Request| spa_fallback(index.clone(), request);
+    let serve_dir = ServeDir::new(dist_dir)
+        .append_index_html_on_directories(true)
+        .fallback(spa_fallback.into_service());
+
+    Router::new()
+        .fallback_service(serve_dir)
+        .layer(middleware::from_fn(move |request: Request, next: Next| {
+            cache_headers(request, next, max_age_seconds)
+        }))
+        .layer(CompressionLayer::new())
+}
+
+async fn spa_fallback(index: PathBuf, request: Request) -> Response {
+    let path = request.uri().path();
+    if matches!(path, "/api" | "/graphql" | "/ws") || NO_FALLBACK_PREFIXES.iter().any(|prefix| path.starts_with(prefix)) {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    // ServeFile is always ready
+    match ServeFile::new(index).call(request).await {
+        Ok(response) => response.into_response(),
+        Err(never) => match never {},
+    }
+}
+
+/// Long-lived caching for fingerprinted assets, revalidation for everything else
+/// (HTML entry points must always pick up new deployments)
+async fn cache_headers(request: Request, next: Next, max_age_seconds: u64) -> Response {
+    let fingerprinted = request.uri().path().starts_with(FINGERPRINTED_PREFIX);
+    let mut response = next.run(request).await;
+
+    // Fingerprinted paths only succeed for files that exist (see `NO_FALLBACK_PREFIXES`)
+    if response.status().is_success() {
+        let value = if fingerprinted {
+            format!("public, max-age={}, immutable", max_age_seconds)
+        } else {
+            "no-cache".to_string()
+        };
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            response.headers_mut().insert(header::CACHE_CONTROL, value);
+        }
+    }
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+
+    async fn get(router: &Router, path: &str) -> (StatusCode, Option<String>, String) {
+        let response = router
+            .clone()
+            .call(Request::get(path).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let cache_control = response
+            .headers()
+            .get(header::CACHE_CONTROL)
+            .map(|value| value.to_str().unwrap().to_string());
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, cache_control, String::from_utf8_lossy(&body).to_string())
+    }
+
+    #[tokio::test]
+    async fn test_spa_fallback_and_cache_headers() {
+        let dir = crate::test_support::temp_dir("frontend").unwrap();
+        std::fs::create_dir_all(dir.join("_next/static/chunks")).unwrap();
+        std::fs::write(dir.join("index.html"), "<html>app</html>").unwrap();
+        std::fs::write(dir.join("_next/static/chunks/app-1a2b.js"), "console.log(1)").unwrap();
+        let router = frontend_router(&dir, 3600);
+
+        let (status, cache, body) = get(&router, "/_next/static/chunks/app-1a2b.js").await;
+        assert_eq!((status, body.as_str()), (StatusCode::OK, "console.log(1)"));
+        assert_eq!(cache.as_deref(), Some("public, max-age=3600, immutable"));
+
+        // Client-side routes get the app, revalidated on every load
+        let (status, cache, body) = get(&router, "/projects/p1").await;
+        assert_eq!((status, body.as_str()), (StatusCode::OK, "<html>app</html>"));
+        assert_eq!(cache.as_deref(), Some("no-cache"));
+
+        // An asset of an older build is missing, not the app, and isn't cached
+        let (status, cache, _) = get(&router, "/_next/static/chunks/app-0000.js").await;
+        assert_eq!((status, cache), (StatusCode::NOT_FOUND, None));
+        for path in ["/api", "/api/unknown", "/graphql/unknown"] {
+            assert_eq!(get(&router, path).await.0, StatusCode::NOT_FOUND, "{}", path);
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,257 @@
+//! TL;DR of analysis results
+//!
+//! After an ask run succeeds, projects that opted in get a few-line summary of the answer from
+//! a cheap hosted model (`SUMMARY_PROVIDER` / `SUMMARY_MODEL`), stored in `tickets.summary` and
+//! announced with a `ticket-summary` broadcast. The project's own API key for the provider is
+//! used when it has one (see `agent_credentials`).
+
+use crate::agent_credentials::{self, Provider};
+use crate::api_agent::{ApiAgent, ApiAgentConfig, ApiProvider};
+use crate::database::Database;
+use crate::AppState;
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use tracing::{error, info, warn};
+
+/// Characters of the answer sent to the summarizer; the rest rarely changes the gist
+const INPUT_MAX_CHARS: usize = 60_000;
+pub const MAX_LINES_LIMIT: u32 = 20;
+
+#[derive(Debug, Clone)]
+pub struct SummaryConfig {
+    pub provider: ApiProvider,
+    pub model: String,
+    /// Lines of a summary unless the project sets its own
+    pub max_lines: u32,
+    pub timeout_seconds: u64,
+}
+
+impl SummaryConfig {
+    pub fn from_env() -> Self {
+        let provider = match std::env::var("SUMMARY_PROVIDER").map(|p| p.trim().to_lowercase()).as_deref() {
+            Ok("gemini") => ApiProvider::Gemini,
+            _ => ApiProvider::Anthropic,
+        };
+        let default_model = match provider {
+            ApiProvider::Anthropic => "claude-3-5-haiku-latest",
+            ApiProvider::Gemini => "gemini-2.5-flash",
+        };
+
+        Self {
+            provider,
+            model: std::env::var("SUMMARY_MODEL")
+                .ok()
+                .filter(|m| !m.trim().is_empty())
+                .unwrap_or_else(|| default_model.to_string()),
+            max_lines: std::env::var("SUMMARY_MAX_LINES")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|n| (1..=MAX_LINES_LIMIT).contains(n))
+                .unwrap_or(5),
+            timeout_seconds: std::env::var("SUMMARY_TIMEOUT_SECS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|n| *n > 0)
+                .unwrap_or(60),
+        }
+    }
+}
+
+/// A project's summarization settings, as `GET /api/projects/:id/summary-settings` returns them
+#[derive(Debug, Clone, Serialize)]
+pub struct SummarySettings {
+    pub enabled: bool,
+    /// None when the project uses the server default
+    pub max_lines: Option<u32>,
+    pub default_max_lines: u32,
+    pub model: String,
+}
+
+pub struct Summarizer {
+    config: SummaryConfig,
+    agent: ApiAgent,
+    /// `ANTHROPIC_API_KEY` / `GEMINI_API_KEY`, for projects without their own key
+    server_key: Option<String>,
+}
+
+impl Summarizer {
+    pub fn new(config: SummaryConfig) -> Self {
+        let mut agent_config = ApiAgentConfig::from_env(config.provider);
+        agent_config.model = config.model.clone();
+        agent_config.timeout_seconds = config.timeout_seconds;
+        agent_config.max_output_tokens = 1024;
+        let server_key = agent_config.api_key.clone();
+
+        Self {
+            config,
+            agent: ApiAgent::with_config(agent_config),
+            server_key,
+        }
+    }
+
+    pub fn config(&self) -> &SummaryConfig {
+        &self.config
+    }
+
+    fn key_provider(&self) -> Provider {
+        match self.config.provider {
+            ApiProvider::Anthropic => Provider::Anthropic,
+            ApiProvider::Gemini => Provider::Gemini,
+        }
+    }
+
+    /// The project's settings merged with the server defaults; disabled without a row
+    pub async fn settings(&self, database: &Database, project_id: &str) -> Result<SummarySettings> {
+        let record = database.get_project_summary_settings(project_id).await?;
+        Ok(SummarySettings {
+            enabled: record.as_ref().is_some_and(|r| r.enabled),
+            max_lines: record.and_then(|r| r.max_lines).map(|n| n as u32),
+            default_max_lines: self.config.max_lines,
+            model: self.config.model.clone(),
+        })
+    }
+
+    /// The project's key for the summary provider, else the server's
+    pub async fn api_key(&self, state: &AppState, project_id: &str) -> Option<String> {
+        let project_key =
+            agent_credentials::provider_key(&state.database, state.credentials.as_deref(), project_id, self.key_provider())
+                .await;
+        project_key
+            .as_ref()
+            .map(|key| key.expose().to_string())
+            .or_else(|| self.server_key.clone())
+    }
+
+    /// One reply of the summary model, for other short texts such as ticket titles
+    pub async fn complete(&self, api_key: &str, system: &str, prompt: &str) -> Result<String> {
+        self.agent.complete(api_key, system, prompt).await
+    }
+
+    /// At most `max_lines` lines summing up `answer`
+    pub async fn summarize(&self, api_key: &str, question: &str, answer: &str, max_lines: u32) -> Result<String> {
+        let summary = self
+            .agent
+            .complete(api_key, &system_prompt(max_lines), &user_prompt(question, answer))
+            .await?;
+        let summary = clean(&summary, max_lines);
+        if summary.is_empty() {
+            return Err(anyhow!("Model returned an empty summary"));
+        }
+        Ok(summary)
+    }
+}
+
+fn system_prompt(max_lines: u32) -> String {
+    format!(
+        "You summarize answers about a codebase for managers who won't read the full text. Reply with \
+         at most {} short lines of plain text, one key point per line: the direct answer first, then \
+         risks or follow-ups. No headings, preamble or code blocks; answer in the language of the question.",
+        max_lines
+    )
+}
+
+fn user_prompt(question: &str, answer: &str) -> String {
+    let answer = match answer.char_indices().nth(INPUT_MAX_CHARS) {
+        Some((cut, _)) => format!("{}\n[...]", &answer[..cut]),
+        None => answer.to_string(),
+    };
+    format!("Question:\n{}\n\nAnswer to summarize:\n{}", question.trim(), answer.trim())
+}
+
+/// The model's reply cut to `max_lines` non-empty lines, without code fences
+fn clean(summary: &str, max_lines: u32) -> String {
+    summary
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with("```"))
+        .take(max_lines as usize)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Summarize a finished run's result in the background when its project opted in
+pub fn spawn(state: &AppState, ticket_id: &str, project_id: &str, question: &str, answer: &str) {
+    let name = format!("summary {}", ticket_id);
+    let supervisor = state.tasks.clone();
+    let state = state.clone();
+    let (ticket_id, project_id, question, answer) =
+        (ticket_id.to_string(), project_id.to_string(), question.to_string(), answer.to_string());
+
+    supervisor.spawn(name, async move {
+        let summarizer = &state.summarizer;
+        let settings = match summarizer.settings(&state.database, &project_id).await {
+            Ok(settings) if settings.enabled => settings,
+            Ok(_) => return,
+            Err(e) => {
+                error!("Failed to load summary settings of project {}: {}", project_id, e);
+                return;
+            }
+        };
+        let Some(api_key) = summarizer.api_key(&state, &project_id).await else {
+            warn!("⚠️ Không có API key {} để tóm tắt ticket {}", summarizer.key_provider().as_str(), ticket_id);
+            return;
+        };
+
+        let max_lines = settings.max_lines.unwrap_or(settings.default_max_lines);
+        let summary = match summarizer.summarize(&api_key, &question, &answer, max_lines).await {
+            Ok(summary) => summary,
+            Err(e) => {
+                error!("Failed to summarize result of ticket {}: {}", ticket_id, e);
+                return;
+            }
+        };
+        if let Err(e) = state.database.set_ticket_summary(&ticket_id, &summary).await {
+            error!("Failed to store summary of ticket {}: {}", ticket_id, e);
+            return;
+        }
+
+        info!("📝 Đã tóm tắt kết quả ticket {}", ticket_id);
+        let _ = state.broadcast_tx.send(crate::BroadcastMessage {
+            ticket_id: ticket_id.clone(),
+            message_type: "ticket-summary".to_string(),
+            content: summary,
+            timestamp: chrono::Utc::now(),
+            org_id: None,
+            changed_files: None,
+        });
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_db;
+
+    #[test]
+    fn test_clean_keeps_first_lines() {
+        let reply = "```\n- Login uses JWT\n\n- Tokens expire after 24h\n- Refresh is missing\n```";
+        assert_eq!(clean(reply, 2), "- Login uses JWT\n- Tokens expire after 24h");
+        assert_eq!(clean("  \n", 5), "");
+    }
+
+    #[test]
+    fn test_long_answers_are_cut() {
+        let answer = "é".repeat(INPUT_MAX_CHARS + 10);
+        let prompt = user_prompt(" Why? ", &answer);
+        assert!(prompt.starts_with("Question:\nWhy?\n"));
+        assert!(prompt.ends_with("[...]"));
+        assert_eq!(prompt.matches('é').count(), INPUT_MAX_CHARS);
+        assert!(system_prompt(3).contains("at most 3 short lines"));
+    }
+
+    #[tokio::test]
+    async fn test_settings_default_to_disabled() {
+        let db = test_db().await.unwrap();
+        let summarizer = Summarizer::new(SummaryConfig {
+            provider: ApiProvider::Anthropic,
+            model: "claude-3-5-haiku-latest".to_string(),
+            max_lines: 5,
+            timeout_seconds: 60,
+        });
+
+        let settings = summarizer.settings(&db, "p1").await.unwrap();
+        assert!(!settings.enabled);
+        assert_eq!(settings.max_lines, None);
+        assert_eq!(settings.default_max_lines, 5);
+    }
+}
@@ -0,0 +1,205 @@
+//! Named background tasks: each one is listed while it runs (`GET /api/admin/tasks`), panics are
+//! logged with the task's name instead of vanishing with it, and tasks that must keep running
+//! (the log writer) are restarted after one
+
+use chrono::{DateTime, Utc};
+use futures_util::FutureExt;
+use serde::Serialize;
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{error, warn};
+
+/// Pause before a restarted task runs again, so one that panics at once doesn't spin
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TaskInfo {
+    pub id: u64,
+    /// What it does and for which ticket/connection, e.g. `analysis <ticket> run <run>`
+    pub name: String,
+    pub started_at: DateTime<Utc>,
+    /// Times it was restarted after panicking
+    pub restarts: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskList {
+    pub tasks: Vec<TaskInfo>,
+    /// Tasks that panicked since startup, restarted ones included
+    pub panics: u64,
+}
+
+#[derive(Debug, Default)]
+struct Registry {
+    next_id: AtomicU64,
+    panics: AtomicU64,
+    tasks: Mutex<BTreeMap<u64, TaskInfo>>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TaskSupervisor {
+    registry: Arc<Registry>,
+}
+
+/// Unlists its task when dropped, which covers tasks that are aborted as well as finished ones
+struct Listed {
+    registry: Arc<Registry>,
+    id: u64,
+}
+
+impl Drop for Listed {
+    fn drop(&mut self) {
+        self.registry.tasks.lock().unwrap().remove(&self.id);
+    }
+}
+
+impl Listed {
+    fn restarted(&self) {
+        if let Some(task) = self.registry.tasks.lock().unwrap().get_mut(&self.id) {
+            task.restarts += 1;
+        }
+    }
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("(no message)")
+}
+
+impl TaskSupervisor {
+    fn list(&self, name: String) -> Listed {
+        let id = self.registry.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let task = TaskInfo {
+            id,
+            name,
+            started_at: Utc::now(),
+            restarts: 0,
+        };
+        self.registry.tasks.lock().unwrap().insert(id, task);
+        Listed {
+            registry: self.registry.clone(),
+            id,
+        }
+    }
+
+    fn panicked(&self, name: &str, panic: &(dyn Any + Send)) {
+        self.registry.panics.fetch_add(1, Ordering::Relaxed);
+        error!("💥 Task {} panicked: {}", name, panic_message(panic));
+    }
+
+    /// `tokio::spawn`, listed under `name` until it ends. A panic is logged, then passed on to
+    /// the handle as usual.
+    pub fn spawn<F>(&self, name: impl Into<String>, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let name = name.into();
+        let listed = self.list(name.clone());
+        let supervisor = self.clone();
+        tokio::spawn(async move {
+            let _listed = listed;
+            match AssertUnwindSafe(future).catch_unwind().await {
+                Ok(output) => output,
+                Err(panic) => {
+                    supervisor.panicked(&name, panic.as_ref());
+                    std::panic::resume_unwind(panic)
+                }
+            }
+        })
+    }
+
+    /// Run the future `make` returns until it finishes, starting a new one each time it panics.
+    /// State the task must not lose (e.g. its queue's receiver) belongs outside the future.
+    pub fn spawn_restarting<F, Fut>(&self, name: impl Into<String>, mut make: F) -> JoinHandle<()>
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let name = name.into();
+        let listed = self.list(name.clone());
+        let supervisor = self.clone();
+        tokio::spawn(async move {
+            while let Err(panic) = AssertUnwindSafe(make()).catch_unwind().await {
+                supervisor.panicked(&name, panic.as_ref());
+                warn!("🔁 Khởi động lại task {} sau {}s", name, RESTART_DELAY.as_secs());
+                tokio::time::sleep(RESTART_DELAY).await;
+                listed.restarted();
+            }
+        })
+    }
+
+    /// Running tasks, oldest first
+    pub fn snapshot(&self) -> TaskList {
+        TaskList {
+            tasks: self.registry.tasks.lock().unwrap().values().cloned().collect(),
+            panics: self.registry.panics.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicU32;
+    use tokio::sync::oneshot;
+
+    #[tokio::test]
+    async fn test_spawn_lists_until_done() {
+        let tasks = TaskSupervisor::default();
+        let (release, released) = oneshot::channel::<()>();
+        let handle = tasks.spawn("analysis t1", async move {
+            let _ = released.await;
+            7
+        });
+        let listed = tasks.snapshot().tasks;
+        assert_eq!(listed.iter().map(|t| t.name.as_str()).collect::<Vec<_>>(), ["analysis t1"]);
+
+        release.send(()).unwrap();
+        assert_eq!(handle.await.unwrap(), 7);
+        assert!(tasks.snapshot().tasks.is_empty());
+
+        // Aborted tasks are unlisted too
+        let handle = tasks.spawn("sleeper", tokio::time::sleep(Duration::from_secs(60)));
+        handle.abort();
+        assert!(handle.await.unwrap_err().is_cancelled());
+        assert!(tasks.snapshot().tasks.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_panics_are_counted() {
+        let tasks = TaskSupervisor::default();
+        let handle = tasks.spawn("doomed", async { panic!("boom") });
+        assert!(handle.await.unwrap_err().is_panic());
+        assert_eq!(tasks.snapshot().panics, 1);
+        assert!(tasks.snapshot().tasks.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_spawn_restarting() {
+        let tasks = TaskSupervisor::default();
+        let attempts = Arc::new(AtomicU32::new(0));
+        let counter = attempts.clone();
+        let handle = tasks.spawn_restarting("log-writer", move || {
+            let attempt = counter.fetch_add(1, Ordering::Relaxed);
+            async move {
+                if attempt < 2 {
+                    panic!("attempt {}", attempt);
+                }
+            }
+        });
+        handle.await.unwrap();
+        assert_eq!(attempts.load(Ordering::Relaxed), 3);
+        assert_eq!(tasks.snapshot().panics, 2);
+        assert!(tasks.snapshot().tasks.is_empty());
+    }
+}
@@ -0,0 +1,507 @@
+use crate::database::{TestCaseRecord, TicketRecord};
+use anyhow::Result;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::io::Write;
+use std::sync::OnceLock;
+use thiserror::Error;
+use zip::write::SimpleFileOptions;
+
+/// Instructions appended to the QA's question in `testcases` mode
+const PROMPT_TEMPLATE: &str = "Generate QA test cases for the flow described below, based on the actual code.
+Cover the main path, validation errors, edge cases and permission checks you can find in the code.
+
+Answer with a single ```json fenced block containing an array, one object per test case:
+[
+  {
+    \"title\": \"Short scenario name\",
+    \"preconditions\": [\"State that must hold before the test\"],
+    \"steps\": [\"Action the tester performs\", \"...\"],
+    \"expected\": \"Observable result\",
+    \"priority\": \"high | medium | low\",
+    \"flow\": \"Sub-flow the case covers, e.g. Guest checkout\"
+  }
+]
+Steps must be concrete user or API actions. You may add a short explanation after the JSON block.
+
+Flow to test: ";
+
+/// Wrap a question in the structured-output prompt of `testcases` mode
+pub fn build_prompt(question: &str) -> String {
+    format!("{}{}", PROMPT_TEMPLATE, question.trim())
+}
+
+/// One test case as produced by the agent, before it gets IDs and a ticket
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TestCase {
+    pub title: String,
+    #[serde(default, deserialize_with = "string_or_list")]
+    pub preconditions: Vec<String>,
+    #[serde(deserialize_with = "string_or_list")]
+    pub steps: Vec<String>,
+    #[serde(deserialize_with = "list_or_string")]
+    pub expected: String,
+    #[serde(default)]
+    pub priority: Option<String>,
+    #[serde(default)]
+    pub flow: Option<String>,
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum TestCaseParseError {
+    #[error("No JSON array or markdown table of test cases found in the result")]
+    NotFound,
+
+    #[error("Test case {0} is invalid: {1}")]
+    Invalid(usize, String),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StringOrList {
+    One(String),
+    Many(Vec<String>),
+}
+
+fn string_or_list<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    Ok(match StringOrList::deserialize(deserializer)? {
+        StringOrList::One(value) => split_items(&value),
+        StringOrList::Many(values) => values,
+    })
+}
+
+fn list_or_string<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    Ok(match StringOrList::deserialize(deserializer)? {
+        StringOrList::One(value) => value,
+        StringOrList::Many(values) => values.join("\n"),
+    })
+}
+
+/// Extract test cases from an agent's answer: the JSON block the prompt asks for, or a
+/// markdown table (Title | Preconditions | Steps | Expected) when the model ignored it
+pub fn parse_test_cases(output: &str) -> Result<Vec<TestCase>, TestCaseParseError> {
+    let cases = parse_json(output)
+        .or_else(|| parse_markdown_table(output))
+        .ok_or(TestCaseParseError::NotFound)?;
+    validate(cases)
+}
+
+fn parse_json(output: &str) -> Option<Vec<TestCase>> {
+    #[derive(Deserialize)]
+    struct Wrapped {
+        #[serde(alias = "testCases")]
+        test_cases: Vec<TestCase>,
+    }
+
+    static FENCED: OnceLock<Regex> = OnceLock::new();
+    let fenced = FENCED.get_or_init(|| Regex::new(r"(?s)```(?:json)?\s*\n(.*?)```").unwrap());
+
+    let mut candidates: Vec<&str> = fenced
+        .captures_iter(output)
+        .filter_map(|c| c.get(1).map(|m| m.as_str()))
+        .collect();
+    if let (Some(start), Some(end)) = (output.find('['), output.rfind(']')) {
+        if start < end {
+            candidates.push(&output[start..=end]);
+        }
+    }
+
+    candidates.into_iter().find_map(|candidate| {
+        serde_json::from_str::<Vec<TestCase>>(candidate.trim())
+            .ok()
+            .or_else(|| {
+                serde_json::from_str::<Wrapped>(candidate.trim())
+                    .ok()
+                    .map(|w| w.test_cases)
+            })
+            .filter(|cases| !cases.is_empty())
+    })
+}
+
+fn parse_markdown_table(output: &str) -> Option<Vec<TestCase>> {
+    let rows: Vec<Vec<String>> = output
+        .lines()
+        .map(str::trim)
+        .filter(|line| line.starts_with('|'))
+        .map(|line| {
+            line.trim_matches('|')
+                .split('|')
+                .map(|cell| cell.trim().to_string())
+                .collect()
+        })
+        .collect();
+
+    let (header, body) = rows.split_first()?;
+    let column = |names: &[&str]| {
+        header.iter().position(|h| {
+            let h = h.to_lowercase();
+            names.iter().any(|name| h.contains(name))
+        })
+    };
+    let title = column(&["title", "scenario", "name", "test case"])?;
+    let steps = column(&["step"])?;
+    let expected = column(&["expected"])?;
+    let preconditions = column(&["precondition"]);
+    let priority = column(&["priority"]);
+    let flow = column(&["flow"]);
+
+    let cell = |row: &[String], index: usize| row.get(index).cloned().unwrap_or_default();
+    let cases: Vec<TestCase> = body
+        .iter()
+        .filter(|row| !row.iter().all(|c| c.chars().all(|ch| matches!(ch, '-' | ':' | ' '))))
+        .map(|row| TestCase {
+            title: cell(row, title),
+            preconditions: preconditions.map(|i| split_items(&cell(row, i))).unwrap_or_default(),
+            steps: split_items(&cell(row, steps)),
+            expected: split_items(&cell(row, expected)).join("\n"),
+            priority: priority.map(|i| cell(row, i)).filter(|p| !p.is_empty()),
+            flow: flow.map(|i| cell(row, i)).filter(|f| !f.is_empty()),
+        })
+        .collect();
+
+    (!cases.is_empty()).then_some(cases)
+}
+
+/// Split a cell holding several items (`<br>` or newline separated, optionally numbered)
+fn split_items(value: &str) -> Vec<String> {
+    static SEPARATOR: OnceLock<Regex> = OnceLock::new();
+    static NUMBERING: OnceLock<Regex> = OnceLock::new();
+    let separator = SEPARATOR.get_or_init(|| Regex::new(r"(?i)<br\s*/?>|\n").unwrap());
+    let numbering = NUMBERING.get_or_init(|| Regex::new(r"^(?:\d+[.)]|[-*])\s*").unwrap());
+
+    separator
+        .split(value)
+        .map(|item| numbering.replace(item.trim(), "").trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+fn validate(cases: Vec<TestCase>) -> Result<Vec<TestCase>, TestCaseParseError> {
+    cases
+        .into_iter()
+        .enumerate()
+        .map(|(index, case)| {
+            let number = index + 1;
+            let clean = |items: Vec<String>| -> Vec<String> {
+                items
+                    .into_iter()
+                    .map(|item| item.trim().to_string())
+                    .filter(|item| !item.is_empty())
+                    .collect()
+            };
+            let case = TestCase {
+                title: case.title.trim().to_string(),
+                preconditions: clean(case.preconditions),
+                steps: clean(case.steps),
+                expected: case.expected.trim().to_string(),
+                priority: case
+                    .priority
+                    .map(|p| p.trim().to_lowercase())
+                    .filter(|p| !p.is_empty()),
+                flow: case.flow.map(|f| f.trim().to_string()).filter(|f| !f.is_empty()),
+            };
+
+            if case.title.is_empty() {
+                return Err(TestCaseParseError::Invalid(number, "missing title".to_string()));
+            }
+            if case.steps.is_empty() {
+                return Err(TestCaseParseError::Invalid(number, "no steps".to_string()));
+            }
+            if case.expected.is_empty() {
+                return Err(TestCaseParseError::Invalid(number, "missing expected result".to_string()));
+            }
+            Ok(case)
+        })
+        .collect()
+}
+
+/// Records for `Database::replace_test_cases`, in the order the agent listed them
+pub fn to_records(ticket_id: &str, run_id: Option<&str>, cases: Vec<TestCase>) -> Vec<TestCaseRecord> {
+    let created_at = chrono::Utc::now().to_rfc3339();
+    cases
+        .into_iter()
+        .enumerate()
+        .map(|(position, case)| TestCaseRecord {
+            id: uuid::Uuid::new_v4().to_string(),
+            ticket_id: ticket_id.to_string(),
+            run_id: run_id.map(str::to_string),
+            position: position as i64,
+            title: case.title,
+            preconditions: case.preconditions,
+            steps: case.steps,
+            expected: case.expected,
+            priority: case.priority,
+            flow: case.flow,
+            created_at: created_at.clone(),
+        })
+        .collect()
+}
+
+/// CSV with one row per case; multi-item cells are newline separated
+pub fn to_csv(cases: &[TestCaseRecord]) -> String {
+    let quote = |value: &str| format!("\"{}\"", value.replace('"', "\"\""));
+
+    let mut csv = String::from("id,title,preconditions,steps,expected,priority,flow\r\n");
+    for case in cases {
+        let numbered_steps: Vec<String> = case
+            .steps
+            .iter()
+            .enumerate()
+            .map(|(i, step)| format!("{}. {}", i + 1, step))
+            .collect();
+        let row = [
+            quote(&format!("TC-{:03}", case.position + 1)),
+            quote(&case.title),
+            quote(&case.preconditions.join("\n")),
+            quote(&numbered_steps.join("\n")),
+            quote(&case.expected),
+            quote(case.priority.as_deref().unwrap_or("")),
+            quote(case.flow.as_deref().unwrap_or("")),
+        ];
+        csv.push_str(&row.join(","));
+        csv.push_str("\r\n");
+    }
+    csv
+}
+
+/// How `gherkin_features` splits a ticket's cases into `.feature` files
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GherkinGrouping {
+    /// One feature with every case of the ticket
+    #[default]
+    Ticket,
+    /// One feature per flow; cases without a flow share the ticket's feature
+    Flow,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeatureFile {
+    pub name: String,
+    pub content: String,
+}
+
+/// Gherkin tag for a ticket label: whitespace becomes '-', a leading '@' is optional
+pub fn label_tag(label: &str) -> Option<String> {
+    let tag = label.trim().trim_start_matches('@').split_whitespace().collect::<Vec<_>>().join("-");
+    (!tag.is_empty()).then(|| format!("@{}", tag))
+}
+
+/// Feature files for a ticket's cases; `labels` become feature-level tags
+pub fn gherkin_features(
+    ticket: &TicketRecord,
+    labels: &[String],
+    cases: &[TestCaseRecord],
+    grouping: GherkinGrouping,
+) -> Vec<FeatureFile> {
+    let tags: Vec<String> = labels.iter().filter_map(|label| label_tag(label)).collect();
+
+    // Flows in the order the agent first listed them; None is the ticket-level group
+    let mut groups: Vec<(Option<&str>, Vec<TestCaseRecord>)> = Vec::new();
+    for case in cases {
+        let flow = match grouping {
+            GherkinGrouping::Ticket => None,
+            GherkinGrouping::Flow => case.flow.as_deref(),
+        };
+        match groups.iter_mut().find(|(f, _)| *f == flow) {
+            Some((_, group)) => group.push(case.clone()),
+            None => groups.push((flow, vec![case.clone()])),
+        }
+    }
+    if groups.is_empty() {
+        groups.push((None, Vec::new()));
+    }
+
+    let ticket_slug = Some(slug(&ticket.title))
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| format!("ticket-{}", slug(&ticket.id)));
+    let mut used_names = HashSet::new();
+
+    groups
+        .into_iter()
+        .map(|(flow, group)| {
+            let (title, base) = match flow {
+                Some(flow) => (
+                    format!("{}: {}", ticket.title, flow),
+                    format!("{}-{}", ticket_slug, slug(flow)),
+                ),
+                None => (ticket.title.clone(), ticket_slug.clone()),
+            };
+            let name = (1..)
+                .map(|n| match n {
+                    1 => format!("{}.feature", base),
+                    n => format!("{}-{}.feature", base, n),
+                })
+                .find(|candidate| used_names.insert(candidate.clone()))
+                .unwrap_or_default();
+
+            FeatureFile {
+                name,
+                content: feature_text(&title, &ticket.description, &tags, &group),
+            }
+        })
+        .collect()
+}
+
+/// Zip archive of feature files, as served by the Gherkin export
+pub fn zip_features(files: &[FeatureFile]) -> Result<Vec<u8>> {
+    let mut archive = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    for file in files {
+        archive.start_file(file.name.as_str(), SimpleFileOptions::default())?;
+        archive.write_all(file.content.as_bytes())?;
+    }
+    Ok(archive.finish()?.into_inner())
+}
+
+fn feature_text(title: &str, description: &str, tags: &[String], cases: &[TestCaseRecord]) -> String {
+    let mut feature = String::new();
+    if !tags.is_empty() {
+        feature.push_str(&format!("{}\n", tags.join(" ")));
+    }
+    feature.push_str(&format!("Feature: {}\n", single_line(title)));
+    for line in description.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        feature.push_str(&format!("  {}\n", line));
+    }
+
+    for case in cases {
+        feature.push('\n');
+        if let Some(priority) = &case.priority {
+            feature.push_str(&format!("  @priority-{}\n", single_line(priority).replace(' ', "-")));
+        }
+        feature.push_str(&format!("  Scenario: {}\n", single_line(&case.title)));
+        push_steps(&mut feature, "Given", &case.preconditions);
+        push_steps(&mut feature, "When", &case.steps);
+        let expected: Vec<String> = case.expected.lines().map(str::to_string).collect();
+        push_steps(&mut feature, "Then", &expected);
+    }
+    feature
+}
+
+fn push_steps(feature: &mut String, keyword: &str, items: &[String]) {
+    for (i, item) in items.iter().filter(|item| !item.trim().is_empty()).enumerate() {
+        let keyword = if i == 0 { keyword } else { "And" };
+        feature.push_str(&format!("    {} {}\n", keyword, single_line(item)));
+    }
+}
+
+fn slug(value: &str) -> String {
+    value
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+fn single_line(value: &str) -> String {
+    value.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::ticket_fixture;
+
+    #[test]
+    fn test_parse_json_block() {
+        let output = r#"Here are the cases:
+
+```json
+[
+  {"title": "Pay with valid card", "preconditions": ["Cart has 1 item"], "steps": ["Open checkout", "Pay"], "expected": "Order is created", "priority": "High"},
+  {"title": "Expired card", "steps": "1. Open checkout<br>2. Pay with expired card", "expected": ["Error shown", "No order"]}
+]
+```
+Notes: refunds are out of scope."#;
+
+        let cases = parse_test_cases(output).unwrap();
+        assert_eq!(cases.len(), 2);
+        assert_eq!(cases[0].priority.as_deref(), Some("high"));
+        assert_eq!(cases[1].steps, vec!["Open checkout", "Pay with expired card"]);
+        assert_eq!(cases[1].expected, "Error shown\nNo order");
+        assert!(cases[1].preconditions.is_empty());
+    }
+
+    #[test]
+    fn test_parse_markdown_table_and_errors() {
+        let output = "| # | Title | Preconditions | Steps | Expected |\n\
+                      |---|-------|---------------|-------|----------|\n\
+                      | 1 | Login | User exists | 1. Open /login<br>2. Submit | Dashboard shown |";
+        let cases = parse_test_cases(output).unwrap();
+        assert_eq!(cases[0].title, "Login");
+        assert_eq!(cases[0].steps, vec!["Open /login", "Submit"]);
+
+        assert_eq!(parse_test_cases("no cases here"), Err(TestCaseParseError::NotFound));
+        assert!(matches!(
+            parse_test_cases(r#"[{"title": "x", "steps": [], "expected": "y"}]"#),
+            Err(TestCaseParseError::Invalid(1, _))
+        ));
+    }
+
+    fn sample_ticket() -> TicketRecord {
+        TicketRecord {
+            title: "Checkout".to_string(),
+            description: "Card payments".to_string(),
+            ..ticket_fixture("t1", "p1")
+        }
+    }
+
+    fn sample_case(title: &str, flow: Option<&str>) -> TestCase {
+        TestCase {
+            title: title.to_string(),
+            preconditions: vec!["Cart has 1 item".to_string()],
+            steps: vec!["Open checkout".to_string(), "Pay".to_string()],
+            expected: "Order is created".to_string(),
+            priority: Some("high".to_string()),
+            flow: flow.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_exports() {
+        let records = to_records("t1", None, vec![sample_case("Pay \"now\"", None)]);
+
+        let csv = to_csv(&records);
+        assert!(csv.contains("\"TC-001\",\"Pay \"\"now\"\"\",\"Cart has 1 item\",\"1. Open checkout\n2. Pay\""));
+
+        let labels = vec!["smoke".to_string(), "@Payments EU".to_string()];
+        let features = gherkin_features(&sample_ticket(), &labels, &records, GherkinGrouping::Ticket);
+        assert_eq!(features.len(), 1);
+        assert_eq!(features[0].name, "checkout.feature");
+        assert!(features[0].content.starts_with("@smoke @Payments-EU\nFeature: Checkout\n  Card payments\n"));
+        assert!(features[0].content.contains(
+            "  @priority-high\n  Scenario: Pay \"now\"\n    Given Cart has 1 item\n    When Open checkout\n    And Pay\n    Then Order is created\n"
+        ));
+    }
+
+    #[test]
+    fn test_gherkin_grouping_by_flow() {
+        let records = to_records(
+            "t1",
+            None,
+            vec![
+                sample_case("Guest pays", Some("Guest checkout")),
+                sample_case("Member pays", Some("Member checkout")),
+                sample_case("Cart is empty", None),
+                sample_case("Guest cancels", Some("Guest checkout")),
+            ],
+        );
+
+        let features = gherkin_features(&sample_ticket(), &[], &records, GherkinGrouping::Flow);
+        let names: Vec<_> = features.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec!["checkout-guest-checkout.feature", "checkout-member-checkout.feature", "checkout.feature"]
+        );
+        assert!(features[0].content.starts_with("Feature: Checkout: Guest checkout\n"));
+        assert_eq!(features[0].content.matches("Scenario:").count(), 2);
+
+        let bytes = zip_features(&features).unwrap();
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(bytes)).unwrap();
+        assert_eq!(archive.len(), 3);
+        let mut content = String::new();
+        std::io::Read::read_to_string(&mut archive.by_name("checkout.feature").unwrap(), &mut content).unwrap();
+        assert_eq!(content, features[2].content);
+    }
+}
@@ -0,0 +1,318 @@
+//! Building blocks for tests that drive the whole pipeline: an `AppState` on a throwaway SQLite
+//! file, the real router served on a free port, and `FakeAgent`, the Claude agent running the
+//! scripted `fake-agent` binary instead of the CLI. Unit tests get an in-memory database and
+//! project/ticket fixtures to override with struct update syntax. Available to other crates'
+//! tests with the `test-support` feature.
+
+use crate::agent_factory::{AgentRegistry, AgentType};
+use crate::claude_agent::{ClaudeAgent, ClaudeAgentConfig};
+use crate::code_agent::{CodeAgent, CodeAnalysisRequest, CodeAnalysisResponse};
+use crate::config::DatabaseConfig;
+use crate::database::{Database, ProjectRecord, TicketRecord, DEFAULT_ORG_ID};
+use crate::message_store::MsgStore;
+use crate::tasks::TaskSupervisor;
+use crate::{
+    alerts, auth, backup, bootstrap, context_resolver, idempotency, job_queue, logging, output_validation, preflight,
+    presence, project_roots, prompt, summary, ticket_title, time_tracking, trash, worker, ws_limits, AppState,
+};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::{broadcast, Mutex};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+/// A fresh, unique directory under the system temp dir
+pub fn temp_dir(prefix: &str) -> Result<PathBuf> {
+    let dir = std::env::temp_dir().join(format!("{}-{}", prefix, uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).with_context(|| format!("Cannot create {}", dir.display()))?;
+    Ok(dir)
+}
+
+/// Migrated database in `dir`. A file rather than `sqlite::memory:`, whose connections would
+/// lock each other's tables while a run writes logs.
+pub async fn test_database(dir: &Path) -> Result<Arc<Database>> {
+    let database = Database::connect(&DatabaseConfig {
+        url: format!("sqlite://{}", dir.join("test.db").display()),
+        journal_mode: Some("wal".to_string()),
+        ..Default::default()
+    })
+    .await?;
+    database.init_schema().await?;
+    database.run_migrations().await?;
+    Ok(Arc::new(database))
+}
+
+/// Migrated in-memory database, for unit tests of a single module
+pub async fn test_db() -> Result<Database> {
+    let database = Database::new("sqlite::memory:").await?;
+    database.init_schema().await?;
+    database.run_migrations().await?;
+    Ok(database)
+}
+
+/// Project `id` in the default organization, named after its ID, on `/tmp`
+pub fn project_fixture(id: &str) -> ProjectRecord {
+    let now = chrono::Utc::now().to_rfc3339();
+    ProjectRecord {
+        id: id.to_string(),
+        name: id.to_string(),
+        description: None,
+        directory_path: "/tmp".to_string(),
+        created_at: now.clone(),
+        updated_at: now,
+        org_id: DEFAULT_ORG_ID.to_string(),
+    }
+}
+
+/// Todo ticket `id` of `project_id`, titled after its ID and never analyzed
+pub fn ticket_fixture(id: &str, project_id: &str) -> TicketRecord {
+    let now = chrono::Utc::now().to_rfc3339();
+    TicketRecord {
+        id: id.to_string(),
+        project_id: project_id.to_string(),
+        title: id.to_string(),
+        description: String::new(),
+        status: "todo".to_string(),
+        code_context: None,
+        analysis_result: None,
+        is_analyzing: false,
+        created_at: now.clone(),
+        updated_at: now,
+        stale: false,
+        analysis_result_blob: None,
+        analysis_result_size: None,
+        position: 0.0,
+        assignee_id: None,
+        summary: None,
+        mode: Default::default(),
+    }
+}
+
+/// The state `main` builds, with `agent` as the default agent and no email, Slack, SSO or
+/// object storage. Other settings come from the environment, as in production.
+pub fn test_state(database: Arc<Database>, agent: Arc<dyn CodeAgent>) -> Result<AppState> {
+    let tasks = TaskSupervisor::default();
+    let msg_store = Arc::new(MsgStore::new(database.clone(), &tasks));
+    let (broadcast_tx, _) = broadcast::channel(1000);
+
+    Ok(AppState {
+        agents: Arc::new(AgentRegistry::with_agent(AgentType::Claude, agent)),
+        broadcast_tx,
+        database,
+        msg_store,
+        tasks,
+        running_tasks: Arc::new(Mutex::new(HashMap::new())),
+        job_queue: Arc::new(Mutex::new(job_queue::JobQueue::new(job_queue::QueueConfig::from_env()))),
+        log_level: logging::detached(),
+        backups: Arc::new(backup::BackupConfig::from_env()?),
+        auth: Arc::new(auth::AuthConfig::from_env()),
+        notifier: None,
+        slack: None,
+        oidc: None,
+        ldap: None,
+        credentials: None,
+        summarizer: Arc::new(summary::Summarizer::new(summary::SummaryConfig::from_env())),
+        mermaid: None,
+        bootstrap: bootstrap::BootstrapConfig::from_env(),
+        prompt_limits: prompt::PromptLimits::from_env(),
+        context_resolver: context_resolver::ResolverConfig::from_env(),
+        idempotency: idempotency::IdempotencyConfig::from_env(),
+        trash: trash::TrashConfig::from_env(),
+        exports: None,
+        ws_limits: ws_limits::WsLimits::from_env(),
+        preflight: preflight::PreflightConfig::from_env(),
+        project_roots: project_roots::ProjectRoots::from_env(),
+        output_validation: output_validation::OutputValidationConfig::from_env(),
+        alerts: Arc::new(alerts::AlertEngine::default()),
+        sla: time_tracking::SlaConfig::from_env(),
+        ticket_titles: ticket_title::TitleConfig::from_env(),
+        presence: Arc::new(presence::Presence::default()),
+        role: worker::Role::All,
+        worker: worker::WorkerConfig::from_env(),
+    })
+}
+
+/// The router served on 127.0.0.1 on a free port, with its own database and an empty project
+/// directory; both are removed when it is dropped
+pub struct TestApp {
+    pub state: AppState,
+    pub dir: PathBuf,
+    addr: SocketAddr,
+    server: JoinHandle<()>,
+}
+
+impl TestApp {
+    pub async fn start(agent: Arc<dyn CodeAgent>) -> Result<Self> {
+        Self::start_with(agent, |_, _| {}).await
+    }
+
+    /// Like `start`, with `configure` adjusting the state before the server takes it; it gets
+    /// the app's directory
+    pub async fn start_with(agent: Arc<dyn CodeAgent>, configure: impl FnOnce(&mut AppState, &Path)) -> Result<Self> {
+        let dir = temp_dir("qa-chatbot-test")?;
+        std::fs::create_dir_all(dir.join("project"))?;
+        let mut state = test_state(test_database(&dir).await?, agent)?;
+        configure(&mut state, &dir);
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let app = crate::router(state.clone());
+        let server = tokio::spawn(async move {
+            let _ = axum::serve(listener, app).await;
+        });
+        Ok(Self { state, dir, addr, server })
+    }
+
+    /// Directory to create the project under test with
+    pub fn project_dir(&self) -> PathBuf {
+        self.dir.join("project")
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.addr, path)
+    }
+
+    pub fn ws_url(&self) -> String {
+        format!("ws://{}/ws", self.addr)
+    }
+}
+
+impl Drop for TestApp {
+    fn drop(&mut self) {
+        self.server.abort();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Claude stream-json output for `fake-agent` to print, one event per line
+#[derive(Debug, Clone, Default)]
+pub struct Script {
+    lines: Vec<String>,
+}
+
+impl Script {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Any event, printed as is
+    pub fn event(mut self, event: Value) -> Self {
+        self.lines.push(event.to_string());
+        self
+    }
+
+    pub fn init(self, model: &str) -> Self {
+        self.event(json!({ "type": "system", "subtype": "init", "model": model, "tools": ["Read", "Grep"] }))
+    }
+
+    pub fn assistant(self, text: &str) -> Self {
+        self.event(json!({
+            "type": "assistant",
+            "message": { "content": [{ "type": "text", "text": text }] }
+        }))
+    }
+
+    pub fn tool_use(self, name: &str, input: Value) -> Self {
+        let id = format!("toolu_{}", self.lines.len());
+        self.event(json!({
+            "type": "assistant",
+            "message": { "content": [{ "type": "tool_use", "id": id, "name": name, "input": input }] }
+        }))
+    }
+
+    pub fn result(self, text: &str) -> Self {
+        self.event(json!({
+            "type": "result",
+            "subtype": "success",
+            "is_error": false,
+            "result": text,
+            "total_cost_usd": 0.01
+        }))
+    }
+
+    /// Wait before printing the rest, e.g. to stop a run while it is going
+    pub fn sleep_ms(self, ms: u64) -> Self {
+        self.event(json!({ "fake": { "sleep_ms": ms } }))
+    }
+
+    pub fn stderr(self, line: &str) -> Self {
+        self.event(json!({ "fake": { "stderr": line } }))
+    }
+
+    /// End the process with `code` without printing the rest
+    pub fn exit(self, code: i32) -> Self {
+        self.event(json!({ "fake": { "exit": code } }))
+    }
+
+    fn to_jsonl(&self) -> String {
+        self.lines.iter().map(|line| format!("{}\n", line)).collect()
+    }
+}
+
+/// The Claude agent with `fake-agent` (the path of its binary, `CARGO_BIN_EXE_fake-agent` in
+/// this crate's integration tests) in place of the CLI, printing `script` on every run
+pub struct FakeAgent {
+    inner: ClaudeAgent,
+    dir: PathBuf,
+}
+
+impl FakeAgent {
+    pub fn new(binary: impl AsRef<Path>, script: Script) -> Result<Self> {
+        let dir = temp_dir("fake-agent")?;
+        let script_path = dir.join("script.jsonl");
+        std::fs::write(&script_path, script.to_jsonl())?;
+
+        // The agent runs one executable with the CLI's arguments; this one adds the script
+        let launcher = dir.join("claude");
+        std::fs::write(
+            &launcher,
+            format!("#!/bin/sh\nexec '{}' '{}' \"$@\"\n", binary.as_ref().display(), script_path.display()),
+        )?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&launcher, std::fs::Permissions::from_mode(0o755))?;
+        }
+
+        let config = ClaudeAgentConfig {
+            executable_path: launcher.to_string_lossy().into_owned(),
+            timeout_seconds: 30,
+            api_key: None,
+            ..Default::default()
+        };
+        Ok(Self {
+            inner: ClaudeAgent::with_config(config),
+            dir,
+        })
+    }
+
+    /// CLI arguments of the latest run, None before the first
+    pub fn last_args(&self) -> Option<Vec<String>> {
+        let args = std::fs::read_to_string(self.dir.join("script.jsonl.args.json")).ok()?;
+        serde_json::from_str(&args).ok()
+    }
+}
+
+impl Drop for FakeAgent {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[async_trait]
+impl CodeAgent for FakeAgent {
+    async fn analyze_code(
+        &self,
+        request: CodeAnalysisRequest,
+        msg_store: Arc<MsgStore>,
+        database: Arc<Database>,
+        cancel: CancellationToken,
+    ) -> Result<CodeAnalysisResponse> {
+        CodeAgent::analyze_code(&self.inner, request, msg_store, database, cancel).await
+    }
+}
@@ -0,0 +1,340 @@
+use crate::database::{Database, TicketLinkRecord, TicketRecord};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// How a link's source relates to its target
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LinkType {
+    /// The target should be analyzed after the source
+    Blocks,
+    RelatesTo,
+    /// The source asks the same thing as the target
+    Duplicates,
+}
+
+impl LinkType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LinkType::Blocks => "blocks",
+            LinkType::RelatesTo => "relates-to",
+            LinkType::Duplicates => "duplicates",
+        }
+    }
+
+    /// `relates-to` reads the same both ways; the other types must stay acyclic
+    fn is_directed(&self) -> bool {
+        !matches!(self, LinkType::RelatesTo)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LinkError {
+    #[error("A ticket cannot be linked to itself")]
+    SelfLink,
+
+    #[error("Target ticket not found")]
+    TargetNotFound,
+
+    #[error("Linked tickets must belong to the same project")]
+    OtherProject,
+
+    #[error("These tickets are already linked as {0}")]
+    Exists(&'static str),
+
+    #[error("Link would create a {0} cycle: {1}")]
+    Cycle(&'static str, String),
+
+    #[error(transparent)]
+    Database(#[from] anyhow::Error),
+}
+
+/// Tickets from `from` to `to` along the given edges, both ends included; None when `to`
+/// can't be reached
+fn find_path(edges: &[(String, String)], from: &str, to: &str) -> Option<Vec<String>> {
+    let mut next: HashMap<&str, Vec<&str>> = HashMap::new();
+    for (source, target) in edges {
+        next.entry(source.as_str()).or_default().push(target.as_str());
+    }
+
+    let mut parent: HashMap<&str, &str> = HashMap::new();
+    let mut seen = HashSet::from([from]);
+    let mut queue = VecDeque::from([from]);
+    while let Some(node) = queue.pop_front() {
+        if node == to {
+            let mut path = vec![to.to_string()];
+            let mut current = to;
+            while let Some(previous) = parent.get(current) {
+                path.push(previous.to_string());
+                current = previous;
+            }
+            path.reverse();
+            return Some(path);
+        }
+        for target in next.get(node).into_iter().flatten() {
+            if seen.insert(target) {
+                parent.insert(target, node);
+                queue.push_back(target);
+            }
+        }
+    }
+    None
+}
+
+/// Link `source` to another ticket of its project after checking the link keeps `blocks` and
+/// `duplicates` acyclic
+pub async fn create_link(
+    database: &Database,
+    source: &TicketRecord,
+    target_id: &str,
+    link_type: LinkType,
+    created_by: Option<String>,
+) -> Result<TicketLinkRecord, LinkError> {
+    if source.id == target_id {
+        return Err(LinkError::SelfLink);
+    }
+    let target = database.get_ticket(target_id).await?.ok_or(LinkError::TargetNotFound)?;
+    if target.project_id != source.project_id {
+        return Err(LinkError::OtherProject);
+    }
+
+    let links = database.list_project_ticket_links(&source.project_id).await?;
+    let same_type: Vec<(String, String)> = links
+        .iter()
+        .filter(|link| link.link_type == link_type.as_str())
+        .map(|link| (link.source_ticket_id.clone(), link.target_ticket_id.clone()))
+        .collect();
+
+    let exists = same_type.iter().any(|(s, t)| {
+        (s == &source.id && t == target_id) || (!link_type.is_directed() && s == target_id && t == &source.id)
+    });
+    if exists {
+        return Err(LinkError::Exists(link_type.as_str()));
+    }
+    // source -> target closes a cycle when target already reaches source
+    if link_type.is_directed() {
+        if let Some(path) = find_path(&same_type, target_id, &source.id) {
+            let cycle = std::iter::once(source.id.clone()).chain(path).collect::<Vec<_>>();
+            return Err(LinkError::Cycle(link_type.as_str(), cycle.join(" -> ")));
+        }
+    }
+
+    let link = TicketLinkRecord {
+        id: uuid::Uuid::new_v4().to_string(),
+        source_ticket_id: source.id.clone(),
+        target_ticket_id: target_id.to_string(),
+        link_type: link_type.as_str().to_string(),
+        created_by,
+        created_at: Utc::now().to_rfc3339(),
+    };
+    database.create_ticket_link(&link).await?;
+    Ok(link)
+}
+
+/// How the other end of `link` relates to `ticket_id`, for prompts
+fn relation_to(link: &TicketLinkRecord, ticket_id: &str) -> &'static str {
+    let outgoing = link.source_ticket_id == ticket_id;
+    match (link.link_type.as_str(), outgoing) {
+        ("blocks", true) => "is blocked by this ticket",
+        ("blocks", false) => "blocks this ticket",
+        ("duplicates", true) => "is duplicated by this ticket",
+        ("duplicates", false) => "duplicates this ticket",
+        _ => "relates to this ticket",
+    }
+}
+
+/// Results of the ticket's linked tickets as a context section, plus how many were included;
+/// None when no linked ticket has a result yet
+pub async fn linked_context(database: &Database, ticket_id: &str) -> anyhow::Result<Option<(String, usize)>> {
+    let mut sections = Vec::new();
+    let mut included = HashSet::new();
+    for link in database.list_ticket_links(ticket_id).await? {
+        let other_id = if link.source_ticket_id == ticket_id {
+            &link.target_ticket_id
+        } else {
+            &link.source_ticket_id
+        };
+        if !included.insert(other_id.clone()) {
+            continue;
+        }
+        let Some(other) = database.get_ticket(other_id).await? else {
+            continue;
+        };
+        let Some(result) = database.full_result(&other).await? else {
+            continue;
+        };
+        sections.push(format!(
+            "### {} ({})\n\n{}",
+            other.title,
+            relation_to(&link, ticket_id),
+            result.trim()
+        ));
+    }
+
+    if sections.is_empty() {
+        return Ok(None);
+    }
+    let count = sections.len();
+    Ok(Some((
+        format!("## Results of linked tickets\n\n{}", sections.join("\n\n")),
+        count,
+    )))
+}
+
+#[derive(Debug, Serialize)]
+pub struct TicketGraphNode {
+    pub id: String,
+    pub title: String,
+    pub status: String,
+    pub is_analyzing: bool,
+    pub has_result: bool,
+    /// Tickets blocking this one that aren't done yet
+    pub blocked_by: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TicketGraphEdge {
+    pub id: String,
+    pub source: String,
+    pub target: String,
+    pub link_type: String,
+}
+
+/// Tickets of a project and their links, ready for a graph view
+#[derive(Debug, Serialize)]
+pub struct TicketGraph {
+    pub nodes: Vec<TicketGraphNode>,
+    pub edges: Vec<TicketGraphEdge>,
+}
+
+pub fn build_graph(tickets: Vec<TicketRecord>, links: Vec<TicketLinkRecord>) -> TicketGraph {
+    let open: HashSet<&str> = tickets
+        .iter()
+        .filter(|t| t.status != "done")
+        .map(|t| t.id.as_str())
+        .collect();
+
+    let mut blocked_by: HashMap<&str, Vec<String>> = HashMap::new();
+    for link in &links {
+        if link.link_type == LinkType::Blocks.as_str() && open.contains(link.source_ticket_id.as_str()) {
+            blocked_by
+                .entry(link.target_ticket_id.as_str())
+                .or_default()
+                .push(link.source_ticket_id.clone());
+        }
+    }
+
+    let nodes = tickets
+        .iter()
+        .map(|t| TicketGraphNode {
+            id: t.id.clone(),
+            title: t.title.clone(),
+            status: t.status.clone(),
+            is_analyzing: t.is_analyzing,
+            has_result: t.analysis_result.is_some(),
+            blocked_by: blocked_by.remove(t.id.as_str()).unwrap_or_default(),
+        })
+        .collect();
+    let edges = links
+        .into_iter()
+        .map(|link| TicketGraphEdge {
+            id: link.id,
+            source: link.source_ticket_id,
+            target: link.target_ticket_id,
+            link_type: link.link_type,
+        })
+        .collect();
+
+    TicketGraph { nodes, edges }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{project_fixture, test_db, ticket_fixture};
+
+    async fn setup() -> Database {
+        let db = test_db().await.unwrap();
+        db.create_project(&project_fixture("p1")).await.unwrap();
+        for id in ["a", "b", "c"] {
+            db.create_ticket(&ticket(id, if id == "a" { Some("Auth uses JWT") } else { None }))
+                .await
+                .unwrap();
+        }
+        db
+    }
+
+    fn ticket(id: &str, result: Option<&str>) -> TicketRecord {
+        TicketRecord {
+            title: format!("Ticket {}", id),
+            analysis_result: result.map(str::to_string),
+            ..ticket_fixture(id, "p1")
+        }
+    }
+
+    #[test]
+    fn test_find_path() {
+        let edges = vec![
+            ("a".to_string(), "b".to_string()),
+            ("b".to_string(), "c".to_string()),
+        ];
+        assert_eq!(find_path(&edges, "a", "c"), Some(vec!["a".into(), "b".into(), "c".into()]));
+        assert_eq!(find_path(&edges, "c", "a"), None);
+    }
+
+    #[tokio::test]
+    async fn test_create_link_rejects_cycles_and_duplicates() {
+        let db = setup().await;
+        let a = db.get_ticket("a").await.unwrap().unwrap();
+        let b = db.get_ticket("b").await.unwrap().unwrap();
+        let c = db.get_ticket("c").await.unwrap().unwrap();
+
+        create_link(&db, &a, "b", LinkType::Blocks, None).await.unwrap();
+        create_link(&db, &b, "c", LinkType::Blocks, None).await.unwrap();
+
+        let err = create_link(&db, &c, "a", LinkType::Blocks, None).await.unwrap_err();
+        assert!(matches!(err, LinkError::Cycle("blocks", ref path) if path == "c -> a -> b -> c"));
+        assert!(matches!(
+            create_link(&db, &a, "b", LinkType::Blocks, None).await.unwrap_err(),
+            LinkError::Exists(_)
+        ));
+        assert!(matches!(
+            create_link(&db, &a, "a", LinkType::RelatesTo, None).await.unwrap_err(),
+            LinkError::SelfLink
+        ));
+
+        // Other types are independent, and relates-to is undirected
+        create_link(&db, &c, "a", LinkType::RelatesTo, None).await.unwrap();
+        assert!(matches!(
+            create_link(&db, &a, "c", LinkType::RelatesTo, None).await.unwrap_err(),
+            LinkError::Exists(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_linked_context_and_graph() {
+        let db = setup().await;
+        let a = db.get_ticket("a").await.unwrap().unwrap();
+        create_link(&db, &a, "b", LinkType::Blocks, None).await.unwrap();
+
+        let (context, count) = linked_context(&db, "b").await.unwrap().unwrap();
+        assert_eq!(count, 1);
+        assert!(context.contains("### Ticket a (blocks this ticket)"));
+        assert!(context.contains("Auth uses JWT"));
+        // b has no result to offer a
+        assert!(linked_context(&db, "a").await.unwrap().is_none());
+
+        let graph = build_graph(
+            db.list_tickets_by_project("p1").await.unwrap(),
+            db.list_project_ticket_links("p1").await.unwrap(),
+        );
+        assert_eq!(graph.edges.len(), 1);
+        let b = graph.nodes.iter().find(|n| n.id == "b").unwrap();
+        assert_eq!(b.blocked_by, vec!["a".to_string()]);
+
+        // Deleting a ticket drops its links
+        db.delete_ticket("a").await.unwrap();
+        assert!(db.list_ticket_links("b").await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,176 @@
+//! Titles for tickets created without one, from their question (`TICKET_TITLE_MODE`)
+
+use crate::AppState;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Title the agents give tickets they create for logs of an unknown ticket; treated as missing
+pub const PLACEHOLDER: &str = "Auto-created";
+
+/// Title when the question is empty too
+const UNTITLED: &str = "Untitled ticket";
+
+/// A model title taking longer than this falls back to the rule-based one
+const MODEL_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TitleMode {
+    /// Keep the empty title
+    Off,
+    /// First sentence of the question, cut at a word boundary
+    Rules,
+    /// Asked of the summary model (`SUMMARY_PROVIDER` / `SUMMARY_MODEL`), rules as a fallback
+    Model,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TitleConfig {
+    pub mode: TitleMode,
+    pub max_chars: usize,
+}
+
+impl TitleConfig {
+    pub fn from_env() -> Self {
+        let mode = match std::env::var("TICKET_TITLE_MODE").map(|m| m.trim().to_lowercase()).as_deref() {
+            Ok("off") => TitleMode::Off,
+            Ok("model") => TitleMode::Model,
+            _ => TitleMode::Rules,
+        };
+        Self {
+            mode,
+            max_chars: std::env::var("TICKET_TITLE_MAX_CHARS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|n| (20..=200).contains(n))
+                .unwrap_or(80),
+        }
+    }
+}
+
+/// Whether a ticket created with `title` should get a generated one
+pub fn missing(title: &str) -> bool {
+    let title = title.trim();
+    title.is_empty() || title == PLACEHOLDER
+}
+
+/// The first sentence (or line) of `question` without markdown markers, at most `max_chars`
+/// characters, cut at a word boundary
+pub fn from_rules(question: &str, max_chars: usize) -> String {
+    let line = question
+        .lines()
+        .map(|line| line.trim().trim_start_matches(['#', '>', '-', '*', ' ']).trim())
+        .find(|line| !line.is_empty() && !line.starts_with("```"))
+        .unwrap_or_default();
+    let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    // A first sentence that fits is a better title than a cut-off paragraph
+    let sentence = line
+        .char_indices()
+        .find(|&(i, c)| matches!(c, '.' | '?' | '!' | '。' | '？') && line[i + c.len_utf8()..].starts_with(' '))
+        .map(|(i, c)| &line[..i + c.len_utf8()])
+        .unwrap_or(&line);
+    let sentence = sentence.trim_end_matches('.');
+
+    if sentence.is_empty() {
+        return UNTITLED.to_string();
+    }
+    let Some((cut, _)) = sentence.char_indices().nth(max_chars.saturating_sub(1)) else {
+        return sentence.to_string();
+    };
+    let head = &sentence[..cut];
+    let head = match head.rfind(' ') {
+        Some(space) if space > cut / 2 => &head[..space],
+        _ => head,
+    };
+    format!("{}…", head.trim_end_matches([',', ';', ':', ' ']))
+}
+
+/// The model's reply as a single-line title, None when unusable
+fn clean_model_title(reply: &str, max_chars: usize) -> Option<String> {
+    let line = reply.lines().map(str::trim).find(|line| !line.is_empty())?;
+    let line = line
+        .trim_start_matches("Title:")
+        .trim()
+        .trim_matches(['"', '\'', '`', '*'])
+        .trim_end_matches('.')
+        .trim();
+    (!line.is_empty() && line.chars().count() <= max_chars).then(|| line.to_string())
+}
+
+fn system_prompt(max_chars: usize) -> String {
+    format!(
+        "You name QA tickets about a codebase. Reply with only a concise title of at most {} characters \
+         for the question: no quotes, no trailing period, in the language of the question.",
+        max_chars
+    )
+}
+
+/// `title` unless it is missing; then one made from `question` as configured
+pub async fn resolve(state: &AppState, project_id: &str, title: &str, question: &str) -> String {
+    let config = state.ticket_titles;
+    if !missing(title) || config.mode == TitleMode::Off {
+        return title.to_string();
+    }
+
+    if config.mode == TitleMode::Model && !question.trim().is_empty() {
+        let summarizer = &state.summarizer;
+        match summarizer.api_key(state, project_id).await {
+            Some(api_key) => {
+                let reply = tokio::time::timeout(
+                    MODEL_TIMEOUT,
+                    summarizer.complete(&api_key, &system_prompt(config.max_chars), question.trim()),
+                )
+                .await;
+                match reply {
+                    Ok(Ok(reply)) => match clean_model_title(&reply, config.max_chars) {
+                        Some(generated) => {
+                            info!("🏷️ Đặt tiêu đề ticket bằng model: {}", generated);
+                            return generated;
+                        }
+                        None => warn!("⚠️ Model trả về tiêu đề không dùng được, dùng quy tắc"),
+                    },
+                    Ok(Err(e)) => warn!("⚠️ Không tạo được tiêu đề bằng model, dùng quy tắc: {}", e),
+                    Err(_) => warn!("⚠️ Tạo tiêu đề bằng model quá {:?}, dùng quy tắc", MODEL_TIMEOUT),
+                }
+            }
+            None => warn!("⚠️ Không có API key để tạo tiêu đề ticket, dùng quy tắc"),
+        }
+    }
+
+    from_rules(question, config.max_chars)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_rules() {
+        assert_eq!(
+            from_rules("## How does checkout work? I see two payment paths.", 80),
+            "How does checkout work?"
+        );
+        assert_eq!(from_rules("\n\n- Explain   the login\nflow in detail", 80), "Explain the login");
+        assert_eq!(from_rules("Version 1.2 of the API. More text", 80), "Version 1.2 of the API");
+
+        let long = "Why does the nightly export job sometimes write duplicate rows into the warehouse tables";
+        let title = from_rules(long, 40);
+        assert_eq!(title, "Why does the nightly export job…");
+        assert!(title.chars().count() <= 40);
+
+        assert_eq!(from_rules("  \n```\n", 80), UNTITLED);
+        assert_eq!(from_rules("Giải thích luồng đăng nhập", 80), "Giải thích luồng đăng nhập");
+    }
+
+    #[test]
+    fn test_missing_and_model_reply() {
+        assert!(missing("  "));
+        assert!(missing(PLACEHOLDER));
+        assert!(!missing("Login flow"));
+
+        assert_eq!(clean_model_title("\"Checkout payment paths.\"\n", 80).as_deref(), Some("Checkout payment paths"));
+        assert_eq!(clean_model_title("Title: Login flow", 80).as_deref(), Some("Login flow"));
+        assert!(clean_model_title("", 80).is_none());
+        assert!(clean_model_title(&"x".repeat(81), 80).is_none());
+    }
+}
@@ -0,0 +1,298 @@
+//! How long tickets spend in each status, from their status history: time in status and cycle
+//! time (`GET /api/tickets/:id/time`, analytics), and the SLAs (`TICKET_SLA_HOURS`) flagging
+//! tickets that sit too long in one status in listings
+
+use crate::board;
+use crate::custom_fields::TicketWithFields;
+use crate::database::{Database, StatusChangeRecord, TicketRecord};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use tracing::warn;
+
+/// Status that ends a ticket's cycle
+const DONE_STATUS: &str = "done";
+
+/// Most hours a ticket may stay in a status, per status; statuses without one have no SLA
+#[derive(Debug, Clone, Default)]
+pub struct SlaConfig {
+    hours: BTreeMap<String, f64>,
+}
+
+impl SlaConfig {
+    /// `TICKET_SLA_HOURS`, e.g. `todo=48,in-progress=24`; unset means no SLA
+    pub fn from_env() -> Self {
+        Self::parse(&std::env::var("TICKET_SLA_HOURS").unwrap_or_default())
+    }
+
+    fn parse(raw: &str) -> Self {
+        let mut hours = BTreeMap::new();
+        for entry in raw.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            let parsed = entry.split_once('=').and_then(|(status, limit)| {
+                let limit = limit.trim().parse::<f64>().ok().filter(|limit| *limit > 0.0)?;
+                board::STATUSES.contains(&status.trim()).then(|| (status.trim().to_string(), limit))
+            });
+            match parsed {
+                Some((status, limit)) => {
+                    hours.insert(status, limit);
+                }
+                None => warn!("⚠️ Bỏ qua TICKET_SLA_HOURS không hợp lệ: {}", entry),
+            }
+        }
+        Self { hours }
+    }
+
+    /// SLA of a status, in hours
+    pub fn hours(&self, status: &str) -> Option<f64> {
+        self.hours.get(status).copied()
+    }
+
+    /// Whether a ticket that entered `status` at `since` has been in it longer than its SLA
+    pub fn overdue(&self, status: &str, since: DateTime<Utc>, now: DateTime<Utc>) -> bool {
+        self.hours(status)
+            .is_some_and(|hours| (now - since).num_seconds() as f64 > hours * 3600.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StatusTime {
+    pub status: String,
+    /// Total over every stay, the current one up to now included
+    pub seconds: i64,
+}
+
+/// `GET /api/tickets/:id/time`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TicketTime {
+    pub ticket_id: String,
+    pub status: String,
+    /// When the ticket entered its current status
+    pub status_since: String,
+    /// Board statuses first, in board order
+    pub time_in_status: Vec<StatusTime>,
+    /// From creation to its last move to `done`; None while not done
+    pub cycle_time_seconds: Option<i64>,
+    pub sla_hours: Option<f64>,
+    pub overdue: bool,
+    pub transitions: Vec<StatusChangeRecord>,
+}
+
+fn parse_time(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value).ok().map(|time| time.with_timezone(&Utc))
+}
+
+/// When the ticket entered its current status: its last transition, else its creation
+fn status_since(ticket: &TicketRecord, history: &[StatusChangeRecord]) -> String {
+    history
+        .last()
+        .map(|change| change.changed_at.clone())
+        .unwrap_or_else(|| ticket.created_at.clone())
+}
+
+/// Times of one ticket from its history (oldest first), as of `now`
+pub fn compute(ticket: &TicketRecord, history: &[StatusChangeRecord], sla: &SlaConfig, now: DateTime<Utc>) -> TicketTime {
+    let created_at = parse_time(&ticket.created_at).unwrap_or(now);
+    // Tickets start in the status their first transition left; without one, in the current one
+    let mut status = history
+        .first()
+        .and_then(|change| change.from_status.clone())
+        .unwrap_or_else(|| ticket.status.clone());
+    let mut entered = created_at;
+    let mut seconds: HashMap<String, i64> = HashMap::new();
+    let mut done_at = None;
+
+    for change in history {
+        let Some(at) = parse_time(&change.changed_at) else {
+            continue;
+        };
+        *seconds.entry(status.clone()).or_default() += (at - entered).num_seconds().max(0);
+        status = change.to_status.clone();
+        entered = at;
+        if status == DONE_STATUS {
+            done_at = Some(at);
+        }
+    }
+    *seconds.entry(status.clone()).or_default() += (now - entered).num_seconds().max(0);
+
+    let mut time_in_status: Vec<StatusTime> = seconds
+        .into_iter()
+        .map(|(status, seconds)| StatusTime { status, seconds })
+        .collect();
+    let order = |status: &str| board::STATUSES.iter().position(|s| *s == status).unwrap_or(board::STATUSES.len());
+    time_in_status.sort_by(|a, b| order(&a.status).cmp(&order(&b.status)).then(a.status.cmp(&b.status)));
+
+    let since = status_since(ticket, history);
+    TicketTime {
+        ticket_id: ticket.id.clone(),
+        status: ticket.status.clone(),
+        overdue: sla.overdue(&ticket.status, parse_time(&since).unwrap_or(now), now),
+        sla_hours: sla.hours(&ticket.status),
+        status_since: since,
+        time_in_status,
+        cycle_time_seconds: done_at
+            .filter(|_| ticket.status == DONE_STATUS)
+            .map(|at| (at - created_at).num_seconds().max(0)),
+        transitions: history.to_vec(),
+    }
+}
+
+pub async fn ticket_time(database: &Database, ticket: &TicketRecord, sla: &SlaConfig) -> Result<TicketTime> {
+    let history = database.list_status_changes(std::slice::from_ref(&ticket.id)).await?;
+    Ok(compute(ticket, &history, sla, Utc::now()))
+}
+
+/// Histories of the given tickets, oldest change first
+async fn histories(database: &Database, ticket_ids: &[String]) -> Result<HashMap<String, Vec<StatusChangeRecord>>> {
+    let mut by_ticket: HashMap<String, Vec<StatusChangeRecord>> = HashMap::new();
+    for change in database.list_status_changes(ticket_ids).await? {
+        by_ticket.entry(change.ticket_id.clone()).or_default().push(change);
+    }
+    Ok(by_ticket)
+}
+
+/// Set when each listed ticket entered its status, and whether it is past its SLA
+pub async fn flag_overdue(database: &Database, sla: &SlaConfig, tickets: &mut [TicketWithFields]) -> Result<()> {
+    let ids: Vec<String> = tickets.iter().map(|t| t.ticket.id.clone()).collect();
+    let by_ticket = histories(database, &ids).await?;
+    let now = Utc::now();
+    for listed in tickets {
+        let history = by_ticket.get(&listed.ticket.id).map(Vec::as_slice).unwrap_or_default();
+        let since = status_since(&listed.ticket, history);
+        listed.overdue = sla.overdue(&listed.ticket.status, parse_time(&since).unwrap_or(now), now);
+        listed.status_since = Some(since);
+    }
+    Ok(())
+}
+
+/// Time-in-status figures of `AnalyticsResponse`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TimeAnalytics {
+    pub tickets: usize,
+    /// Average seconds tickets spent in each status they went through, board order first
+    pub avg_time_in_status: Vec<StatusTime>,
+    pub done: usize,
+    pub avg_cycle_time_seconds: Option<i64>,
+    /// Tickets currently past the SLA of their status
+    pub overdue: usize,
+}
+
+pub fn summarize(times: &[TicketTime]) -> TimeAnalytics {
+    let mut totals: Vec<(String, i64, i64)> = Vec::new();
+    for time in times {
+        for status_time in &time.time_in_status {
+            match totals.iter_mut().find(|(status, _, _)| *status == status_time.status) {
+                Some((_, seconds, count)) => {
+                    *seconds += status_time.seconds;
+                    *count += 1;
+                }
+                None => totals.push((status_time.status.clone(), status_time.seconds, 1)),
+            }
+        }
+    }
+    let order = |status: &str| board::STATUSES.iter().position(|s| *s == status).unwrap_or(board::STATUSES.len());
+    totals.sort_by(|a, b| order(&a.0).cmp(&order(&b.0)).then(a.0.cmp(&b.0)));
+
+    let cycle_times: Vec<i64> = times.iter().filter_map(|time| time.cycle_time_seconds).collect();
+    TimeAnalytics {
+        tickets: times.len(),
+        avg_time_in_status: totals
+            .into_iter()
+            .map(|(status, seconds, count)| StatusTime { status, seconds: seconds / count })
+            .collect(),
+        done: cycle_times.len(),
+        avg_cycle_time_seconds: (!cycle_times.is_empty())
+            .then(|| cycle_times.iter().sum::<i64>() / cycle_times.len() as i64),
+        overdue: times.iter().filter(|time| time.overdue).count(),
+    }
+}
+
+/// Time analytics of the tickets a filter selects (by creation date)
+pub async fn analytics(
+    database: &Database,
+    filter: &crate::database::AnalyticsFilter,
+    sla: &SlaConfig,
+) -> Result<TimeAnalytics> {
+    let tickets = database.list_tickets_created_in(filter).await?;
+    let ids: Vec<String> = tickets.iter().map(|t| t.id.clone()).collect();
+    let by_ticket = histories(database, &ids).await?;
+    let now = Utc::now();
+    let times: Vec<TicketTime> = tickets
+        .iter()
+        .map(|ticket| {
+            let history = by_ticket.get(&ticket.id).map(Vec::as_slice).unwrap_or_default();
+            compute(ticket, history, sla, now)
+        })
+        .collect();
+    Ok(summarize(&times))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::ticket_fixture;
+    use chrono::Duration;
+
+    fn ticket(status: &str, created_at: DateTime<Utc>) -> TicketRecord {
+        TicketRecord {
+            status: status.to_string(),
+            created_at: created_at.to_rfc3339(),
+            updated_at: created_at.to_rfc3339(),
+            ..ticket_fixture("t1", "p1")
+        }
+    }
+
+    fn change(from: &str, to: &str, at: DateTime<Utc>) -> StatusChangeRecord {
+        StatusChangeRecord {
+            id: uuid::Uuid::new_v4().to_string(),
+            ticket_id: "t1".to_string(),
+            from_status: Some(from.to_string()),
+            to_status: to.to_string(),
+            actor_id: None,
+            changed_at: at.to_rfc3339(),
+        }
+    }
+
+    #[test]
+    fn test_sla_config() {
+        let sla = SlaConfig::parse("todo=48, in-progress=1.5, review=2, done=x");
+        assert_eq!((sla.hours("todo"), sla.hours("in-progress")), (Some(48.0), Some(1.5)));
+        assert_eq!((sla.hours("review"), sla.hours("done")), (None, None));
+        let now = Utc::now();
+        assert!(sla.overdue("in-progress", now - Duration::hours(2), now));
+        assert!(!sla.overdue("todo", now - Duration::hours(2), now));
+        assert!(!SlaConfig::default().overdue("todo", now - Duration::days(30), now));
+    }
+
+    #[test]
+    fn test_compute() {
+        let created = Utc::now() - Duration::hours(10);
+        let sla = SlaConfig::parse("in-progress=3");
+        let history = vec![
+            change("todo", "in-progress", created + Duration::hours(2)),
+            change("in-progress", "todo", created + Duration::hours(3)),
+            change("todo", "in-progress", created + Duration::hours(4)),
+        ];
+        let now = created + Duration::hours(10);
+        let time = compute(&ticket("in-progress", created), &history, &sla, now);
+        let seconds: Vec<(&str, i64)> = time.time_in_status.iter().map(|t| (t.status.as_str(), t.seconds)).collect();
+        assert_eq!(seconds, vec![("todo", 3 * 3600), ("in-progress", 7 * 3600)]);
+        assert!(time.overdue);
+        assert_eq!(time.cycle_time_seconds, None);
+
+        let mut history = history;
+        history.push(change("in-progress", "done", created + Duration::hours(5)));
+        let time = compute(&ticket("done", created), &history, &sla, now);
+        assert_eq!(time.cycle_time_seconds, Some(5 * 3600));
+        assert!(!time.overdue);
+
+        // Without history the whole life is in the current status
+        let time = compute(&ticket("todo", created), &[], &sla, now);
+        assert_eq!(time.time_in_status, vec![StatusTime { status: "todo".to_string(), seconds: 10 * 3600 }]);
+        assert_eq!(time.status_since, created.to_rfc3339());
+
+        let summary = summarize(&[compute(&ticket("done", created), &history, &sla, now), time]);
+        assert_eq!((summary.tickets, summary.done, summary.avg_cycle_time_seconds), (2, 1, Some(5 * 3600)));
+        assert_eq!(summary.avg_time_in_status[0], StatusTime { status: "todo".to_string(), seconds: 13 * 3600 / 2 });
+    }
+}
@@ -0,0 +1,365 @@
+//! Ordered, typed timeline of one analysis run (files read, searches, shell commands,
+//! assistant summaries) distilled from the ticket's structured logs, for `GET /api/tickets/:id/timeline`
+
+use crate::database::{AnalysisSession, Database};
+use crate::message_store::{LogMessageType, StructuredLogEntry};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+
+/// Upper bound on logs read for one run's timeline
+const MAX_TIMELINE_LOGS: u64 = 5_000;
+/// Longest assistant or error text kept on an event; the full text stays in the logs
+const TEXT_MAX_CHARS: usize = 500;
+
+const FILE_KEYS: &[&str] = &["file_path", "filePath", "absolute_path", "path", "notebook_path", "target_file"];
+const SEARCH_KEYS: &[&str] = &["pattern", "query", "regex", "glob", "search_term"];
+const COMMAND_KEYS: &[&str] = &["command", "cmd"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimelineKind {
+    FileRead,
+    Search,
+    Command,
+    /// Any other tool call (edits, web fetches, MCP tools, ...)
+    Tool,
+    Summary,
+    Error,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TimelineEvent {
+    pub kind: TimelineKind,
+    pub started_at: String,
+    /// Until the next event, or the end of the run; None for the last event of a run still going
+    pub duration_ms: Option<i64>,
+    /// Tool name as the agent reported it (`Read`, `read_file`, `grepToolCall`, ...)
+    pub tool: Option<String>,
+    /// File path, search pattern or command line
+    pub target: Option<String>,
+    /// Assistant text or error message, truncated
+    pub text: Option<String>,
+    /// Structured log the event was taken from
+    pub log_id: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TicketTimeline {
+    pub ticket_id: String,
+    /// None when the ticket has no recorded run and the timeline covers all its logs
+    pub run_id: Option<String>,
+    pub started_at: Option<String>,
+    pub ended_at: Option<String>,
+    pub events: Vec<TimelineEvent>,
+}
+
+/// An event before timing is known
+struct Step {
+    kind: TimelineKind,
+    tool: Option<String>,
+    target: Option<String>,
+    text: Option<String>,
+    /// Streaming fragment to append to the previous summary
+    delta: bool,
+}
+
+impl Step {
+    fn tool(name: &str, input: &Value) -> Self {
+        let kind = tool_kind(name);
+        let keys = match kind {
+            TimelineKind::FileRead => FILE_KEYS,
+            TimelineKind::Search => SEARCH_KEYS,
+            TimelineKind::Command => COMMAND_KEYS,
+            _ => FILE_KEYS,
+        };
+        Self {
+            kind,
+            tool: Some(name.to_string()),
+            target: find_string(input, keys),
+            text: None,
+            delta: false,
+        }
+    }
+
+    fn text(kind: TimelineKind, text: &str, delta: bool) -> Self {
+        Self {
+            kind,
+            tool: None,
+            target: None,
+            text: Some(text.to_string()),
+            delta,
+        }
+    }
+}
+
+/// Classify by the words of the tool name (`read_file`, `NotebookRead`, `grepToolCall`, ...)
+fn tool_kind(name: &str) -> TimelineKind {
+    let mut words = String::new();
+    for c in name.chars() {
+        if c.is_uppercase() {
+            words.push('_');
+        }
+        words.extend(c.to_lowercase());
+    }
+    let words: Vec<&str> = words.split(|c: char| !c.is_alphanumeric()).collect();
+    let has = |candidates: &[&str]| words.iter().any(|w| candidates.contains(w));
+
+    if has(&["grep", "glob", "search", "find", "list", "ls"]) {
+        TimelineKind::Search
+    } else if has(&["read", "view", "cat", "open"]) {
+        TimelineKind::FileRead
+    } else if has(&["bash", "shell", "command", "terminal", "exec"]) {
+        TimelineKind::Command
+    } else {
+        TimelineKind::Tool
+    }
+}
+
+/// First string argument under one of `keys`, searching nested objects (Cursor nests
+/// arguments as `{"readToolCall":{"args":{"path":...}}}`)
+fn find_string(value: &Value, keys: &[&str]) -> Option<String> {
+    let map = value.as_object()?;
+    for key in keys {
+        match map.get(*key) {
+            Some(Value::String(s)) => return Some(s.clone()),
+            Some(Value::Array(items)) => {
+                if let Some(s) = items.iter().find_map(Value::as_str) {
+                    return Some(s.to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+    map.values().find_map(|v| find_string(v, keys))
+}
+
+/// What one log entry contributes to the timeline; Claude assistant messages can carry several blocks
+fn steps(entry: &StructuredLogEntry) -> Vec<Step> {
+    let json = serde_json::from_str::<Value>(&entry.content).ok().filter(Value::is_object);
+    let Some(json) = json else {
+        // Plain-text agents: "Reading file: src/a.rs", answers, errors
+        let content = entry.content.trim();
+        return match entry.message_type {
+            _ if content.is_empty() => Vec::new(),
+            LogMessageType::Error => vec![Step::text(TimelineKind::Error, content, false)],
+            LogMessageType::Assistant => vec![Step::text(TimelineKind::Summary, content, false)],
+            LogMessageType::ToolUse => {
+                let mut step = Step::text(TimelineKind::Tool, content, false);
+                if let Some(path) = entry.metadata.get("file_path") {
+                    step.kind = TimelineKind::FileRead;
+                    step.target = Some(path.clone());
+                }
+                step.tool = entry.metadata.get("tool_name").cloned();
+                vec![step]
+            }
+            _ => Vec::new(),
+        };
+    };
+
+    if let Some(error) = json.get("error") {
+        let text = error.as_str().map(str::to_string).unwrap_or_else(|| error.to_string());
+        return vec![Step::text(TimelineKind::Error, &text, false)];
+    }
+
+    match json["type"].as_str().unwrap_or_default() {
+        // Claude stream-json: {"type":"assistant","message":{"content":[...]}}
+        "assistant" => json
+            .pointer("/message/content")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|block| match block["type"].as_str()? {
+                "tool_use" => Some(Step::tool(block["name"].as_str()?, &block["input"])),
+                "text" => Some(Step::text(TimelineKind::Summary, block["text"].as_str()?, false)),
+                _ => None,
+            })
+            .collect(),
+        // Gemini / Ollama: {"type":"tool_use","tool_name":"read_file","parameters":{...}}
+        "tool_use" => match json["tool_name"].as_str().or_else(|| json["name"].as_str()) {
+            Some(name) => vec![Step::tool(name, &json["parameters"])],
+            None => Vec::new(),
+        },
+        // Cursor: {"type":"tool_call","subtype":"started","tool_call":{"readToolCall":{"args":{...}}}}
+        "tool_call" if json["subtype"] != "completed" => {
+            let call = json["tool_call"].as_object().and_then(|calls| calls.iter().next());
+            match call {
+                Some((name, call)) => vec![Step::tool(name, call)],
+                None => Vec::new(),
+            }
+        }
+        "message" if json["role"] == "assistant" => match json["content"].as_str() {
+            Some(text) => vec![Step::text(TimelineKind::Summary, text, json["delta"] == true)],
+            None => Vec::new(),
+        },
+        _ => Vec::new(),
+    }
+}
+
+fn truncate(text: &str) -> String {
+    let text = text.trim();
+    match text.char_indices().nth(TEXT_MAX_CHARS) {
+        Some((cut, _)) => format!("{}…", &text[..cut]),
+        None => text.to_string(),
+    }
+}
+
+/// Timeline events of `entries` (oldest first). Each event lasts until the next one starts;
+/// the last until `ended_at`, or open-ended when the run hasn't ended.
+pub fn build_timeline(entries: &[StructuredLogEntry], ended_at: Option<DateTime<Utc>>) -> Vec<TimelineEvent> {
+    let mut timed: Vec<(DateTime<Utc>, TimelineEvent)> = Vec::new();
+
+    for entry in entries {
+        for step in steps(entry) {
+            if let Some((_, previous)) = timed.last_mut() {
+                if step.delta && previous.kind == TimelineKind::Summary {
+                    previous.text.get_or_insert_with(String::new).push_str(step.text.as_deref().unwrap_or_default());
+                    continue;
+                }
+            }
+            timed.push((
+                entry.timestamp,
+                TimelineEvent {
+                    kind: step.kind,
+                    started_at: entry.timestamp.to_rfc3339(),
+                    duration_ms: None,
+                    tool: step.tool,
+                    target: step.target,
+                    text: step.text,
+                    log_id: entry.id.clone(),
+                },
+            ));
+        }
+    }
+
+    let starts: Vec<DateTime<Utc>> = timed.iter().map(|(start, _)| *start).collect();
+    timed
+        .into_iter()
+        .enumerate()
+        .map(|(i, (start, mut event))| {
+            let end = starts.get(i + 1).copied().or(ended_at);
+            event.duration_ms = end.map(|end| (end - start).num_milliseconds().max(0));
+            event.text = event.text.as_deref().map(truncate);
+            event
+        })
+        .collect()
+}
+
+/// Timeline of the ticket's run `run_id`, or of its latest run. None when `run_id` isn't a run of the ticket.
+pub async fn ticket_timeline(db: &Database, ticket_id: &str, run_id: Option<&str>) -> Result<Option<TicketTimeline>> {
+    let session = match run_id {
+        Some(run_id) => match db.get_session_by_run_id(ticket_id, run_id).await? {
+            Some(session) => Some(session),
+            None => return Ok(None),
+        },
+        None => db.get_latest_session_by_ticket(ticket_id).await?,
+    };
+
+    let entries = run_logs(db, ticket_id, session.as_ref()).await?;
+    Ok(Some(run_timeline(ticket_id, session.as_ref(), &entries)))
+}
+
+/// Logs of `session`, oldest first, or all of the ticket's logs without one
+pub async fn run_logs(db: &Database, ticket_id: &str, session: Option<&AnalysisSession>) -> Result<Vec<StructuredLogEntry>> {
+    // A run's logs end where the ticket's next run starts; completion is logged a little after `completed_at`
+    let until = match session {
+        Some(session) => db.get_next_session_start(ticket_id, &session.started_at).await?,
+        None => None,
+    };
+    let from = session.map(|s| s.started_at.as_str());
+    Ok(db
+        .get_logs_in_range(ticket_id, from, until.as_deref(), MAX_TIMELINE_LOGS)
+        .await?
+        .into_iter()
+        .map(StructuredLogEntry::from_record)
+        .collect())
+}
+
+/// Timeline of `entries`, the logs `run_logs` read for `session`
+pub fn run_timeline(ticket_id: &str, session: Option<&AnalysisSession>, entries: &[StructuredLogEntry]) -> TicketTimeline {
+    let ended_at = match session {
+        Some(session) => session.completed_at.clone(),
+        None => entries.last().map(|entry| entry.timestamp.to_rfc3339()),
+    };
+    let end = ended_at
+        .as_deref()
+        .and_then(|at| DateTime::parse_from_rfc3339(at).ok())
+        .map(|at| at.with_timezone(&Utc));
+
+    TicketTimeline {
+        ticket_id: ticket_id.to_string(),
+        run_id: session.and_then(|s| s.run_id.clone()),
+        started_at: session.map(|s| s.started_at.clone()),
+        ended_at,
+        events: build_timeline(entries, end),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn entry(seconds: i64, message_type: LogMessageType, content: &str) -> StructuredLogEntry {
+        StructuredLogEntry {
+            id: format!("log-{}", seconds),
+            ticket_id: "t-1".to_string(),
+            message_type,
+            content: content.to_string(),
+            raw_log: None,
+            metadata: HashMap::new(),
+            timestamp: DateTime::from_timestamp(1_700_000_000 + seconds, 0).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_build_timeline() {
+        let entries = vec![
+            entry(0, LogMessageType::System, "🔄 Khởi động agent..."),
+            entry(
+                1,
+                LogMessageType::System,
+                r#"{"type":"assistant","message":{"content":[{"type":"text","text":"Looking at checkout"},{"type":"tool_use","name":"Read","input":{"file_path":"src/checkout.rs"}}]}}"#,
+            ),
+            entry(3, LogMessageType::ToolUse, r#"{"type":"tool_use","tool_name":"search_file_content","parameters":{"pattern":"fn pay"}}"#),
+            entry(
+                4,
+                LogMessageType::System,
+                r#"{"type":"tool_call","subtype":"started","tool_call":{"shellToolCall":{"args":{"command":"cargo tree"}}}}"#,
+            ),
+            entry(5, LogMessageType::System, r#"{"type":"tool_call","subtype":"completed","tool_call":{"shellToolCall":{}}}"#),
+            entry(6, LogMessageType::Assistant, r#"{"type":"message","role":"assistant","content":"Pay","delta":true}"#),
+            entry(7, LogMessageType::Assistant, r#"{"type":"message","role":"assistant","content":"ment flow","delta":true}"#),
+            entry(8, LogMessageType::Error, r#"{"type":"error","error":"quota exceeded"}"#),
+        ];
+        let events = build_timeline(&entries, DateTime::from_timestamp(1_700_000_010, 0));
+
+        let kinds: Vec<TimelineKind> = events.iter().map(|e| e.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TimelineKind::Summary,
+                TimelineKind::FileRead,
+                TimelineKind::Search,
+                TimelineKind::Command,
+                TimelineKind::Summary,
+                TimelineKind::Error,
+            ]
+        );
+        assert_eq!(events[1].target.as_deref(), Some("src/checkout.rs"));
+        assert_eq!(events[2].target.as_deref(), Some("fn pay"));
+        assert_eq!(events[3].target.as_deref(), Some("cargo tree"));
+        assert_eq!(events[4].text.as_deref(), Some("Payment flow"));
+        assert_eq!(events[5].text.as_deref(), Some("quota exceeded"));
+
+        // Blocks of the same message share its timestamp; the last event runs to the end of the run
+        let durations: Vec<Option<i64>> = events.iter().map(|e| e.duration_ms).collect();
+        assert_eq!(durations, vec![Some(0), Some(2000), Some(1000), Some(2000), Some(2000), Some(2000)]);
+        assert_eq!(build_timeline(&entries, None).last().unwrap().duration_ms, None);
+
+        assert_eq!(tool_kind("NotebookRead"), TimelineKind::FileRead);
+        assert_eq!(tool_kind("run_shell_command"), TimelineKind::Command);
+        assert_eq!(tool_kind("mcp__github__get_pulls"), TimelineKind::Tool);
+    }
+}
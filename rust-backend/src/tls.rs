@@ -0,0 +1,124 @@
+use crate::config::TlsConfig;
+use crate::tasks::TaskSupervisor;
+use anyhow::{Context, Result};
+use axum::{
+    extract::Request,
+    http::{header, StatusCode, Uri},
+    response::{IntoResponse, Redirect, Response},
+    Router,
+};
+use axum_server::tls_rustls::RustlsConfig;
+use std::net::SocketAddr;
+use tracing::{info, warn};
+
+/// Serve the app over HTTPS (HTTP/1.1 + HTTP/2 via ALPN); WebSockets become WSS
+pub async fn serve_tls(
+    app: Router,
+    addr: SocketAddr,
+    tls: &TlsConfig,
+    tasks: &TaskSupervisor,
+) -> Result<()> {
+    // Single crypto provider for the whole process (ring, no C toolchain needed)
+    let _ = rustls::crypto::ring::default_provider().install_default();
+
+    let rustls_config = RustlsConfig::from_pem_file(&tls.cert_path, &tls.key_path)
+        .await
+        .with_context(|| {
+            format!(
+                "Failed to load TLS certificate {} / key {}",
+                tls.cert_path.display(),
+                tls.key_path.display()
+            )
+        })?;
+
+    if let Some(http_port) = tls.http_redirect_port {
+        tasks.spawn("https-redirect", redirect_http_to_https(http_port, addr.port()));
+    }
+
+    axum_server::bind_rustls(addr, rustls_config)
+        .serve(app.into_make_service())
+        .await?;
+
+    Ok(())
+}
+
+/// Plain HTTP listener answering every request with a permanent redirect to HTTPS
+async fn redirect_http_to_https(http_port: u16, https_port: u16) {
+    let addr = SocketAddr::from(([0, 0, 0, 0], http_port));
+    let app = Router::new().fallback(move |request: Request| async move {
+        redirect_response(&request, https_port)
+    });
+
+    let listener = match tokio::net::TcpListener::bind(&addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            warn!("⚠️ Không thể bind HTTP redirect port {}: {}", http_port, e);
+            return;
+        }
+    };
+
+    info!("↪️ HTTP → HTTPS redirect đang chạy trên {}", addr);
+    if let Err(e) = axum::serve(listener, app).await {
+        warn!("⚠️ HTTP redirect server stopped: {}", e);
+    }
+}
+
+fn redirect_response(request: &Request, https_port: u16) -> Response {
+    let host = match request
+        .headers()
+        .get(header::HOST)
+        .and_then(|h| h.to_str().ok())
+    {
+        Some(host) => host,
+        None => return StatusCode::BAD_REQUEST.into_response(),
+    };
+
+    match https_uri(host, request.uri(), https_port) {
+        Some(uri) => Redirect::permanent(&uri).into_response(),
+        None => StatusCode::BAD_REQUEST.into_response(),
+    }
+}
+
+/// Build the HTTPS URI for `host` + the original path/query, keeping non-default ports
+fn https_uri(host: &str, uri: &Uri, https_port: u16) -> Option<String> {
+    // Strip any port from the Host header (bracketed IPv6 keeps its brackets)
+    let hostname = match host.rfind(':') {
+        Some(idx) if !host[idx..].contains(']') => &host[..idx],
+        _ => host,
+    };
+    if hostname.is_empty() {
+        return None;
+    }
+
+    let path_and_query = uri.path_and_query().map(|pq| pq.as_str()).unwrap_or("/");
+    let authority = if https_port == 443 {
+        hostname.to_string()
+    } else {
+        format!("{}:{}", hostname, https_port)
+    };
+
+    Some(format!("https://{}{}", authority, path_and_query))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_https_uri_keeps_path_and_strips_http_port() {
+        let uri: Uri = "/projects/abc?tab=logs".parse().unwrap();
+
+        assert_eq!(
+            https_uri("example.com:80", &uri, 443).as_deref(),
+            Some("https://example.com/projects/abc?tab=logs")
+        );
+        assert_eq!(
+            https_uri("example.com", &uri, 8443).as_deref(),
+            Some("https://example.com:8443/projects/abc?tab=logs")
+        );
+        assert_eq!(
+            https_uri("[::1]:8080", &"/".parse().unwrap(), 443).as_deref(),
+            Some("https://[::1]/")
+        );
+    }
+}
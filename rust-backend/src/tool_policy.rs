@@ -0,0 +1,155 @@
+use crate::code_agent::AnalysisMode;
+use serde::{Deserialize, Serialize};
+
+/// Tool names accepted in a project's policy, in Claude Code's naming. An entry may narrow a
+/// tool to a pattern, e.g. `Bash(git log:*)`.
+pub const KNOWN_TOOLS: &[&str] = &[
+    "Read", "Grep", "Glob", "LS", "Bash", "Edit", "Write", "WebFetch", "WebSearch",
+];
+
+/// Tools that never change the project or leave the machine
+pub const READ_ONLY_TOOLS: &[&str] = &["Read", "Grep", "Glob", "LS"];
+
+/// Tools a mode may use when the project configures none
+pub fn default_tools(mode: AnalysisMode) -> &'static [&'static str] {
+    match mode {
+        // All of them only explain code; none needs to run or change anything
+        AnalysisMode::Ask | AnalysisMode::TestCases | AnalysisMode::Diagram => READ_ONLY_TOOLS,
+    }
+}
+
+/// Where an effective policy came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicySource {
+    #[default]
+    Default,
+    Project,
+}
+
+/// Tools an agent may use without asking, resolved for one run and recorded on its session
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolPolicy {
+    pub allowed_tools: Vec<String>,
+    pub source: PolicySource,
+}
+
+impl Default for ToolPolicy {
+    fn default() -> Self {
+        Self::resolve(None, AnalysisMode::default())
+    }
+}
+
+/// `Bash` of `Bash(git log:*)`
+fn tool_name(entry: &str) -> &str {
+    entry.split_once('(').map_or(entry, |(name, _)| name).trim()
+}
+
+/// Check an admin-supplied tool list before it is stored
+pub fn validate(tools: &[String]) -> Result<(), String> {
+    for entry in tools {
+        let name = tool_name(entry);
+        if !KNOWN_TOOLS.contains(&name) {
+            return Err(format!("Unknown tool '{}'; expected one of {}", name, KNOWN_TOOLS.join(", ")));
+        }
+        if entry.contains('(') && !entry.trim_end().ends_with(')') {
+            return Err(format!("Unclosed pattern in '{}'", entry));
+        }
+    }
+    Ok(())
+}
+
+impl ToolPolicy {
+    /// The project's tool list, or the mode's default when the project has none
+    pub fn resolve(project_tools: Option<Vec<String>>, mode: AnalysisMode) -> Self {
+        match project_tools {
+            Some(allowed_tools) => Self {
+                allowed_tools,
+                source: PolicySource::Project,
+            },
+            None => Self {
+                allowed_tools: default_tools(mode).iter().map(|t| t.to_string()).collect(),
+                source: PolicySource::Default,
+            },
+        }
+    }
+
+    /// Whether `tool` may run, in at least some form
+    pub fn allows(&self, tool: &str) -> bool {
+        self.allowed_tools.iter().any(|entry| tool_name(entry) == tool)
+    }
+
+    /// Whether every allowed tool is read-only
+    pub fn read_only(&self) -> bool {
+        self.allowed_tools
+            .iter()
+            .all(|entry| READ_ONLY_TOOLS.contains(&tool_name(entry)))
+    }
+
+    /// Value of Claude's `--allowedTools`
+    pub fn claude_allowed_tools(&self) -> String {
+        self.allowed_tools.join(",")
+    }
+
+    /// Value of Gemini's `--allowed-tools`, translated to its tool names
+    pub fn gemini_allowed_tools(&self) -> String {
+        let mut tools: Vec<String> = Vec::new();
+        for entry in &self.allowed_tools {
+            let pattern = entry.split_once('(').map(|(_, rest)| rest.trim_end_matches(')'));
+            let names: &[&str] = match tool_name(entry) {
+                "Read" => &["read_file", "read_many_files"],
+                "Grep" => &["search_file_content"],
+                "Glob" => &["glob"],
+                "LS" => &["list_directory"],
+                "Bash" => &["run_shell_command"],
+                "Edit" => &["replace"],
+                "Write" => &["write_file"],
+                "WebFetch" => &["web_fetch"],
+                "WebSearch" => &["google_web_search"],
+                _ => &[],
+            };
+            for name in names {
+                let tool = match pattern {
+                    // Gemini narrows shell commands by prefix only
+                    Some(pattern) if *name == "run_shell_command" => {
+                        format!("{}({})", name, pattern.trim_end_matches(":*").trim_end_matches('*'))
+                    }
+                    _ => name.to_string(),
+                };
+                if !tools.contains(&tool) {
+                    tools.push(tool);
+                }
+            }
+        }
+        tools.join(",")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_resolution_and_cli_mapping() {
+        let default = ToolPolicy::resolve(None, AnalysisMode::Ask);
+        assert_eq!(default.source, PolicySource::Default);
+        assert!(default.read_only());
+        assert!(default.allows("Grep"));
+        assert!(!default.allows("Bash"));
+
+        let tools = vec!["Read".to_string(), "Grep".to_string(), "Bash(git log:*)".to_string()];
+        assert!(validate(&tools).is_ok());
+        assert!(validate(&["Shell".to_string()]).is_err());
+        assert!(validate(&["Bash(git".to_string()]).is_err());
+
+        let project = ToolPolicy::resolve(Some(tools), AnalysisMode::TestCases);
+        assert_eq!(project.source, PolicySource::Project);
+        assert!(project.allows("Bash"));
+        assert!(!project.read_only());
+        assert_eq!(project.claude_allowed_tools(), "Read,Grep,Bash(git log:*)");
+        assert_eq!(
+            project.gemini_allowed_tools(),
+            "read_file,read_many_files,search_file_content,run_shell_command(git log)"
+        );
+    }
+}
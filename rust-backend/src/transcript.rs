@@ -0,0 +1,165 @@
+//! What a CLI agent run exchanged with its process, kept per session for debugging
+//! (`GET /api/sessions/:id/raw`): the prompt as assembled, argv, the names (never the values)
+//! of the environment variables set for it, and every stdout/stderr line as gzipped JSON lines:
+//!
+//! ```json
+//! {"at_ms": 0, "stream": "spawn", "line": "claude --allowedTools Read,Grep,Glob,LS -p ..."}
+//! {"at_ms": 812, "stream": "stdout", "line": "{\"type\":\"system\",\"subtype\":\"init\",...}"}
+//! {"at_ms": 9120, "stream": "exit", "line": "0"}
+//! ```
+//!
+//! Secrets are masked like the logs before anything is stored.
+
+use crate::database::{Database, SessionTranscriptRecord};
+use crate::redaction::Redactor;
+use anyhow::Result;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde_json::json;
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::process::Command;
+use tracing::{error, info};
+
+#[derive(Debug, Default)]
+struct Recorded {
+    started: Option<Instant>,
+    prompt: String,
+    argv: Vec<String>,
+    env_names: Vec<String>,
+    /// JSON lines, uncompressed until saved
+    lines: String,
+}
+
+/// Collects a run's exchange while it happens; clones share it, so the stdout and stderr
+/// readers can each hold one
+#[derive(Debug, Clone, Default)]
+pub struct Transcript {
+    recorded: Arc<Mutex<Recorded>>,
+}
+
+impl Transcript {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn push(&self, stream: &str, line: &str) {
+        let mut recorded = self.recorded.lock().unwrap_or_else(|e| e.into_inner());
+        let at_ms = recorded.started.get_or_insert_with(Instant::now).elapsed().as_millis() as u64;
+        recorded.lines.push_str(&json!({ "at_ms": at_ms, "stream": stream, "line": line }).to_string());
+        recorded.lines.push('\n');
+    }
+
+    /// `cmd` is about to be spawned with `prompt`; a retried run's later attempts replace the
+    /// earlier prompt and argv, their lines follow on
+    pub fn spawned(&self, cmd: &Command, prompt: &str) {
+        let cmd = cmd.as_std();
+        let argv: Vec<String> = std::iter::once(cmd.get_program())
+            .chain(cmd.get_args())
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect();
+        let mut env_names: Vec<String> = cmd
+            .get_envs()
+            .filter(|(_, value)| value.is_some())
+            .map(|(name, _)| name.to_string_lossy().into_owned())
+            .collect();
+        env_names.sort();
+
+        self.push("spawn", &argv.join(" "));
+        let mut recorded = self.recorded.lock().unwrap_or_else(|e| e.into_inner());
+        recorded.prompt = prompt.to_string();
+        recorded.argv = argv;
+        recorded.env_names = env_names;
+    }
+
+    pub fn stdout(&self, line: &str) {
+        self.push("stdout", line);
+    }
+
+    pub fn stderr(&self, line: &str) {
+        self.push("stderr", line);
+    }
+
+    /// The process ended with `code` (None when killed by a signal)
+    pub fn exited(&self, code: Option<i32>) {
+        self.push("exit", &code.map(|code| code.to_string()).unwrap_or_else(|| "signal".to_string()));
+    }
+
+    /// The record to store for `session_id`, secrets masked; None when nothing was spawned
+    pub fn record(&self, session_id: &str, redactor: &Redactor) -> Result<Option<SessionTranscriptRecord>> {
+        let recorded = self.recorded.lock().unwrap_or_else(|e| e.into_inner());
+        if recorded.started.is_none() {
+            return Ok(None);
+        }
+
+        let argv: Vec<String> = recorded.argv.iter().map(|arg| redactor.redact_text(arg.clone())).collect();
+        let lines = redactor.redact_text(recorded.lines.clone());
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(lines.as_bytes())?;
+
+        Ok(Some(SessionTranscriptRecord {
+            session_id: session_id.to_string(),
+            prompt: redactor.redact_text(recorded.prompt.clone()),
+            argv: serde_json::to_string(&argv)?,
+            env_names: serde_json::to_string(&recorded.env_names)?,
+            transcript: Some(encoder.finish()?),
+            transcript_blob: None,
+            transcript_size: lines.len() as i64,
+            created_at: chrono::Utc::now().to_rfc3339(),
+        }))
+    }
+
+    /// Store the transcript on the session; failures are logged, the run goes on
+    pub async fn save(&self, database: &Database, session_id: &str, redactor: &Redactor) {
+        let saved = match self.record(session_id, redactor) {
+            Ok(Some(record)) => database.save_session_transcript(&record).await.map(|_| record.transcript_size),
+            Ok(None) => return,
+            Err(e) => Err(e),
+        };
+        match saved {
+            Ok(size) => info!("📼 Đã lưu transcript {} byte của session {}", size, session_id),
+            Err(e) => error!("Failed to save transcript of session {}: {}", session_id, e),
+        }
+    }
+}
+
+/// The JSON lines of a stored transcript
+pub fn decompress(gzipped: &[u8]) -> Result<String> {
+    let mut lines = String::new();
+    if !gzipped.is_empty() {
+        GzDecoder::new(gzipped).read_to_string(&mut lines)?;
+    }
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let transcript = Transcript::new();
+        let redactor = Redactor::from_env();
+        assert!(transcript.record("s1", &redactor).unwrap().is_none());
+
+        let mut cmd = Command::new("claude");
+        cmd.arg("-p").arg("Explain login").env("CLAUDE_API_KEY", "secret-value");
+        transcript.spawned(&cmd, "Explain login");
+        transcript.stdout(r#"{"type":"result","result":"ok"}"#);
+        transcript.stderr("warning: slow");
+        transcript.exited(Some(0));
+
+        let record = transcript.record("s1", &redactor).unwrap().unwrap();
+        assert_eq!(record.prompt, "Explain login");
+        assert_eq!(record.argv, r#"["claude","-p","Explain login"]"#);
+        assert_eq!(record.env_names, r#"["CLAUDE_API_KEY"]"#);
+        let lines = decompress(record.transcript.as_deref().unwrap()).unwrap();
+        assert_eq!(lines.len() as i64, record.transcript_size);
+        assert!(!lines.contains("secret-value"));
+        let streams: Vec<String> = lines
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["stream"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(streams, ["spawn", "stdout", "stderr", "exit"]);
+    }
+}
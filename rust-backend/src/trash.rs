@@ -0,0 +1,119 @@
+use crate::database::Database;
+use crate::tasks::TaskSupervisor;
+use crate::AppState;
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use std::sync::Arc;
+use tracing::{error, info};
+
+/// How often the purger looks for projects past their retention
+const PURGE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrashConfig {
+    /// Days a deleted project stays restorable; 0 keeps it until restored
+    pub retention_days: i64,
+}
+
+impl Default for TrashConfig {
+    fn default() -> Self {
+        Self { retention_days: 30 }
+    }
+}
+
+impl TrashConfig {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            retention_days: std::env::var("TRASH_RETENTION_DAYS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|days| *days >= 0)
+                .unwrap_or(defaults.retention_days),
+        }
+    }
+
+    /// When a project deleted at `deleted_at` is purged; None when it never is
+    pub fn purge_at(&self, deleted_at: &str) -> Option<String> {
+        if self.retention_days == 0 {
+            return None;
+        }
+        let deleted_at = DateTime::parse_from_rfc3339(deleted_at).ok()?;
+        Some((deleted_at.to_utc() + Duration::days(self.retention_days)).to_rfc3339())
+    }
+
+    /// Projects deleted before this are due for purging
+    fn cutoff(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        now - Duration::days(self.retention_days)
+    }
+}
+
+/// Move a project to the trash, stopping analyses of its tickets first since nothing would
+/// show their results
+pub async fn trash_project(state: &AppState, project_id: &str, deleted_by: Option<&str>) -> Result<()> {
+    for ticket in state.database.list_tickets_by_project(project_id).await? {
+        if ticket.is_analyzing {
+            crate::analysis_runner::cancel_analysis(state, &ticket.id).await;
+        }
+    }
+    state.database.delete_project(project_id, deleted_by).await
+}
+
+/// Permanently delete trashed projects past their retention, hourly; no-op when retention is 0
+pub fn spawn_purger(database: Arc<Database>, config: TrashConfig, tasks: &TaskSupervisor) {
+    if config.retention_days == 0 {
+        info!("🗑️ Trashed projects are kept until restored (TRASH_RETENTION_DAYS=0)");
+        return;
+    }
+
+    info!("🗑️ Trashed projects are purged after {} days", config.retention_days);
+
+    tasks.spawn("trash-purger", async move {
+        let mut interval = tokio::time::interval(PURGE_INTERVAL);
+        loop {
+            interval.tick().await;
+            match database.purge_trashed_projects(&config.cutoff(Utc::now()).to_rfc3339()).await {
+                Ok(ids) if !ids.is_empty() => info!("🗑️ Đã xóa vĩnh viễn {} project trong thùng rác: {}", ids.len(), ids.join(", ")),
+                Ok(_) => {}
+                Err(e) => error!("❌ Dọn thùng rác thất bại: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{project_fixture, test_db};
+
+    #[tokio::test]
+    async fn test_trash_restore_and_purge() {
+        let db = test_db().await.unwrap();
+
+        let now = Utc::now().to_rfc3339();
+        for id in ["kept", "restored", "purged"] {
+            db.create_project(&project_fixture(id)).await.unwrap();
+        }
+
+        db.delete_project("restored", Some("u1")).await.unwrap();
+        db.delete_project("purged", None).await.unwrap();
+        assert!(db.get_project("purged").await.unwrap().is_none());
+        assert_eq!(db.list_projects().await.unwrap().len(), 1);
+        let trashed = db.list_trashed_projects_by_org(crate::database::DEFAULT_ORG_ID).await.unwrap();
+        assert_eq!(trashed.len(), 2);
+
+        assert!(db.restore_project("restored").await.unwrap());
+        assert!(!db.restore_project("kept").await.unwrap());
+        assert!(db.get_project("restored").await.unwrap().is_some());
+
+        let config = TrashConfig { retention_days: 30 };
+        assert!(db.purge_trashed_projects(&config.cutoff(Utc::now()).to_rfc3339()).await.unwrap().is_empty());
+        let later = Utc::now() + Duration::days(31);
+        assert_eq!(db.purge_trashed_projects(&config.cutoff(later).to_rfc3339()).await.unwrap(), ["purged"]);
+        assert!(db.get_trashed_project("purged").await.unwrap().is_none());
+        assert_eq!(db.list_projects().await.unwrap().len(), 2);
+
+        assert!(TrashConfig { retention_days: 0 }.purge_at(&now).is_none());
+        assert!(config.purge_at(&now).is_some());
+    }
+}
@@ -1,50 +1,223 @@
-use crate::{AppState, CodeAnalysisRequest};
+use crate::activity;
+use crate::auth::AuthContext;
+use crate::database::Database;
+use crate::interaction::AgentInput;
+use crate::log_normalizer::LogNormalizer;
+use crate::presence::{self, Viewer};
+use crate::ws_limits::{self, MessageGuard, Violation};
+use crate::ws_stream::{self, Outbox, OutboxError, StreamSettings};
+use crate::{AppState, BroadcastMessage, CodeAnalysisRequest};
 use axum::extract::ws::{Message, WebSocket};
 use futures_util::{sink::SinkExt, stream::StreamExt};
 use serde_json::{json, Value};
-use tracing::{error, info};
+use std::collections::HashSet;
+use std::ops::ControlFlow;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{mpsc, watch};
+use tokio::time::{sleep_until, Duration, Instant};
+use tracing::{error, info, warn};
 use uuid::Uuid;
 
-pub async fn handle_websocket(socket: WebSocket, state: AppState) {
+/// How long a client dropped for flooding gets to receive its close frame
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Tracks which tickets a connection's organization may see, so only its own logs and
+/// events are forwarded
+struct OrgFilter {
+    database: std::sync::Arc<Database>,
+    org_id: String,
+    /// Tickets already confirmed to belong to the org; misses are re-checked since a
+    /// ticket may be auto-created after its first log line
+    visible_tickets: HashSet<String>,
+}
+
+impl OrgFilter {
+    async fn ticket_visible(&mut self, ticket_id: &str) -> bool {
+        if self.visible_tickets.contains(ticket_id) {
+            return true;
+        }
+        match self.database.ticket_org_id(ticket_id).await {
+            Ok(Some(org_id)) if org_id == self.org_id => {
+                self.visible_tickets.insert(ticket_id.to_string());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    async fn broadcast_visible(&mut self, message: &BroadcastMessage) -> bool {
+        match &message.org_id {
+            Some(org_id) => *org_id == self.org_id,
+            None if message.ticket_id == "system" => true,
+            None => self.ticket_visible(&message.ticket_id).await,
+        }
+    }
+}
+
+pub async fn handle_websocket(socket: WebSocket, state: AppState, auth: AuthContext) {
     let (mut sender, mut receiver) = socket.split();
     let mut log_receiver = state.msg_store.subscribe();
     let client_id = Uuid::new_v4().to_string();
     let client_id_clone = client_id.clone();
+    let client_id_for_send = client_id.clone();
+    let tasks = state.tasks.clone();
+
+    info!("🔌 Client mới kết nối: {} (org {})", client_id, auth.org_id);
 
-    info!("🔌 Client mới kết nối: {}", client_id);
+    let mut org_filter = OrgFilter {
+        database: state.database.clone(),
+        org_id: auth.org_id.clone(),
+        visible_tickets: HashSet::new(),
+    };
+
+    // Per-connection streaming options, changed by the client's `subscribe` message
+    let (settings_tx, mut settings_rx) = watch::channel(StreamSettings::default());
+    // Frames for this client only (warnings about its own messages, the closing frame)
+    let (direct_tx, mut direct_rx) = mpsc::channel::<Message>(16);
+    // Ticket the client views (`view-ticket`), for presence events
+    let (viewing_tx, viewing_rx) = watch::channel::<Option<String>>(None);
 
     // Spawn task to listen for broadcast messages and forward to client
-    let mut send_task = tokio::spawn(async move {
-        while let Ok(log_entry) = log_receiver.recv().await {
-            // Convert StructuredLogEntry to JSON and send to client
-            let message = json!({
-                "message_type": "structured-log",
-                "log": {
-                    "id": log_entry.id,
-                    "ticket_id": log_entry.ticket_id,
-                    "message_type": log_entry.message_type,
-                    "content": log_entry.content,
-                    "raw_log": log_entry.raw_log,
-                    "metadata": log_entry.metadata,
-                    "timestamp": log_entry.timestamp.to_rfc3339(),
+    let mut broadcast_receiver = state.broadcast_tx.subscribe();
+    let max_lags = state.ws_limits.max_lags;
+    let mut send_task = tasks.spawn(format!("ws-send {}", client_id), async move {
+        let (mut outbox, mut outbox_rx) = Outbox::new(max_lags);
+
+        // Written on its own, so a slow socket fills this client's outbox instead of holding up
+        // the broadcast for it
+        let writer = async move {
+            while let Some(frame) = outbox_rx.recv().await {
+                let closing = matches!(frame, Message::Close(_));
+                if sender.send(frame).await.is_err() || closing {
+                    return;
                 }
-            });
+            }
+        };
+
+        // Owns the outbox, so its end stops the writer once the queue is drained; the direct
+        // channel stays open until then, for `recv_task` waiting on a close frame
+        let direct_rx = &mut direct_rx;
+        let forwarder = async move {
+            let mut settings = *settings_rx.borrow();
+            let mut pending_logs: Vec<Value> = Vec::new();
+            let mut flush_at: Option<Instant> = None;
+
+            loop {
+                let mut frames = Vec::new();
+
+                let lagged = tokio::select! {
+                    log_entry = log_receiver.recv() => match log_entry {
+                        Ok(log_entry) => {
+                            if !org_filter.ticket_visible(&log_entry.ticket_id).await {
+                                continue;
+                            }
+
+                            if settings.batching() {
+                                pending_logs.push(ws_stream::log_json(&log_entry));
+                                flush_at.get_or_insert_with(|| Instant::now() + settings.batch_window);
+                                if pending_logs.len() >= settings.batch_max_entries {
+                                    frames.push(ws_stream::batch_message(std::mem::take(&mut pending_logs)));
+                                }
+                            } else {
+                                frames.push(ws_stream::single_log_message(&log_entry));
+                            }
+                            Ok(())
+                        }
+                        Err(RecvError::Lagged(skipped)) => outbox.lagged(skipped, 0, Instant::now()),
+                        Err(RecvError::Closed) => break,
+                    },
+                    // Completion/error/progress events
+                    broadcast_msg = broadcast_receiver.recv() => match broadcast_msg {
+                        Ok(broadcast_msg) => {
+                            if !org_filter.broadcast_visible(&broadcast_msg).await
+                                || !presence::delivers_to(&broadcast_msg, &client_id_for_send, viewing_rx.borrow().as_deref())
+                            {
+                                continue;
+                            }
+
+                            // Keep ordering: logs buffered so far go out before the event
+                            if !pending_logs.is_empty() {
+                                frames.push(ws_stream::batch_message(std::mem::take(&mut pending_logs)));
+                            }
+                            frames.push(serde_json::to_value(&broadcast_msg).unwrap_or_default());
+                            Ok(())
+                        }
+                        Err(RecvError::Lagged(skipped)) => outbox.lagged(0, skipped, Instant::now()),
+                        Err(RecvError::Closed) => break,
+                    },
+                    _ = sleep_until(flush_at.unwrap_or_else(Instant::now)), if flush_at.is_some() => {
+                        if !pending_logs.is_empty() {
+                            frames.push(ws_stream::batch_message(std::mem::take(&mut pending_logs)));
+                        }
+                        Ok(())
+                    }
+                    Some(frame) = direct_rx.recv() => {
+                        let closing = matches!(frame, Message::Close(_));
+                        if outbox.push_direct(frame).is_err() || closing {
+                            return;
+                        }
+                        Ok(())
+                    }
+                    changed = settings_rx.changed() => {
+                        if changed.is_err() {
+                            break;
+                        }
+                        settings = *settings_rx.borrow_and_update();
+                        if !settings.batching() && !pending_logs.is_empty() {
+                            frames.push(ws_stream::batch_message(std::mem::take(&mut pending_logs)));
+                        }
+                        Ok(())
+                    }
+                };
 
-            let json_msg = serde_json::to_string(&message).unwrap_or_else(|_| "{}".to_string());
+                if pending_logs.is_empty() {
+                    flush_at = None;
+                }
 
-            if sender.send(Message::Text(json_msg)).await.is_err() {
-                break;
+                let now = Instant::now();
+                let sent = lagged
+                    .and_then(|()| outbox.send_notice(&settings))
+                    .and_then(|()| frames.iter().try_for_each(|frame| outbox.push(frame, &settings, now)));
+                match sent {
+                    Ok(()) => {}
+                    Err(OutboxError::Closed) => return,
+                    Err(OutboxError::Evict) => {
+                        warn!("🐢 Ngắt kết nối client {}: không theo kịp log stream", client_id_for_send);
+                        let _ = outbox.push_direct(ws_stream::slow_consumer_close_frame());
+                        return;
+                    }
+                }
             }
-        }
+        };
+
+        tokio::join!(writer, forwarder);
     });
 
+    let presence_state = state.clone();
+
     // Handle incoming messages from client
-    let mut recv_task = tokio::spawn(async move {
+    let mut recv_task = tasks.spawn(format!("ws-receive {}", client_id), async move {
+        let mut guard = MessageGuard::new(state.ws_limits, Instant::now());
+        let connection = Connection {
+            client_id: &client_id_clone,
+            settings_tx: &settings_tx,
+            direct_tx: &direct_tx,
+            viewing_tx: &viewing_tx,
+        };
         while let Some(msg) = receiver.next().await {
             match msg {
                 Ok(Message::Text(text)) => {
-                    if let Err(e) = handle_client_message(&text, &state, &client_id_clone).await {
-                        error!("Lỗi xử lý message từ client {}: {}", client_id_clone, e);
+                    let flow = handle_client_message(&text, &state, &auth, &connection, &mut guard)
+                        .await
+                        .unwrap_or_else(|e| {
+                            error!("Lỗi xử lý message từ client {}: {}", client_id_clone, e);
+                            ControlFlow::Continue(())
+                        });
+                    if flow.is_break() {
+                        // The send task exits once the close frame is out; ending here first
+                        // would abort it
+                        let _ = tokio::time::timeout(CLOSE_TIMEOUT, direct_tx.closed()).await;
+                        break;
                     }
                 }
                 Ok(Message::Close(_)) => {
@@ -78,22 +251,203 @@ pub async fn handle_websocket(socket: WebSocket, state: AppState) {
         }
     }
 
+    if let Some((ticket_id, viewer)) = presence_state.presence.leave(&client_id) {
+        let _ = presence_state
+            .broadcast_tx
+            .send(presence::message(&ticket_id, presence::LEFT, &viewer));
+    }
+
     info!("Client {} đã ngắt kết nối", client_id);
 }
 
+/// What a client message handler needs of its connection
+struct Connection<'a> {
+    client_id: &'a str,
+    settings_tx: &'a watch::Sender<StreamSettings>,
+    direct_tx: &'a mpsc::Sender<Message>,
+    viewing_tx: &'a watch::Sender<Option<String>>,
+}
+
+/// Why a client operation failed, sent back to its connection as a `ws-error` reply:
+///
+/// ```json
+/// {"ticket_id": "system", "message_type": "ws-error", "content": "{\"request_id\":\"r1\",\"request_type\":\"create-ticket\",\"code\":\"not-found\",\"message\":\"Project p1 not found\"}", "timestamp": "..."}
+/// ```
+#[derive(Debug)]
+struct WsError {
+    /// `not-found`, `invalid-request`, `budget-exceeded` or `internal`
+    code: &'static str,
+    message: String,
+}
+
+impl WsError {
+    fn not_found(what: String) -> Self {
+        Self {
+            code: "not-found",
+            message: format!("{} not found", what),
+        }
+    }
+
+    fn invalid(message: String) -> Self {
+        Self {
+            code: "invalid-request",
+            message,
+        }
+    }
+
+    /// A start refused by a used-up budget (see `budgets`)
+    fn budget_exceeded(exceeded: crate::budgets::BudgetExceeded) -> Self {
+        Self {
+            code: "budget-exceeded",
+            message: exceeded.to_string(),
+        }
+    }
+
+    /// Logs the cause; clients only get `message`
+    fn internal(message: &str, cause: impl std::fmt::Display) -> Self {
+        error!("❌ {}: {}", message, cause);
+        Self {
+            code: "internal",
+            message: message.to_string(),
+        }
+    }
+
+    /// The reply for `message`, echoing its `requestId`
+    fn reply(&self, message: &Value) -> Message {
+        let content = json!({
+            "request_id": message["requestId"].as_str(),
+            "request_type": message["type"].as_str(),
+            "code": self.code,
+            "message": self.message,
+        });
+        let frame = json!({
+            "ticket_id": message["ticketId"].as_str().unwrap_or("system"),
+            "message_type": "ws-error",
+            "content": content.to_string(),
+            "timestamp": chrono::Utc::now(),
+        });
+        Message::Text(frame.to_string())
+    }
+}
+
+impl From<crate::project_roots::RootError> for WsError {
+    fn from(e: crate::project_roots::RootError) -> Self {
+        Self::invalid(e.to_string())
+    }
+}
+
+impl From<anyhow::Error> for WsError {
+    fn from(e: anyhow::Error) -> Self {
+        Self::internal("Internal error", e)
+    }
+}
+
+/// Status a `create-ticket`/`update-ticket-status` message may set
+fn valid_status(status: &str) -> Result<(), WsError> {
+    if crate::board::STATUSES.contains(&status) {
+        Ok(())
+    } else {
+        Err(WsError::invalid(format!("Unknown status {}", status)))
+    }
+}
+
+/// Resource IDs the message names (`projectId`, `ticketId`, and `id` for project/ticket
+/// messages) that exist but belong to another organization
+async fn foreign_reference(message: &Value, state: &AppState, auth: &AuthContext) -> anyhow::Result<Option<String>> {
+    let message_type = message["type"].as_str().unwrap_or("");
+    let id_names_project = matches!(message_type, "create-project" | "update-project");
+    let id_names_ticket = message_type == "create-ticket";
+
+    let project_ids = [Some(&message["projectId"]), id_names_project.then_some(&message["id"])];
+    for project_id in project_ids.into_iter().flatten().filter_map(Value::as_str) {
+        if let Some(project) = state.database.get_project(project_id).await? {
+            if project.org_id != auth.org_id {
+                return Ok(Some(format!("Project {}", project_id)));
+            }
+        }
+    }
+    let ticket_ids = [Some(&message["ticketId"]), id_names_ticket.then_some(&message["id"])];
+    for ticket_id in ticket_ids.into_iter().flatten().filter_map(Value::as_str) {
+        if let Some(org_id) = state.database.ticket_org_id(ticket_id).await? {
+            if org_id != auth.org_id {
+                return Ok(Some(format!("Ticket {}", ticket_id)));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Check a client message against the connection's limits and the caller's organization, then
+/// handle it. Dropped messages get a `ws-warning` frame; Break means the connection is closed.
 async fn handle_client_message(
     text: &str,
     state: &AppState,
-    client_id: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let message: Value = serde_json::from_str(text)?;
+    auth: &AuthContext,
+    connection: &Connection<'_>,
+    guard: &mut MessageGuard,
+) -> Result<ControlFlow<()>, Box<dyn std::error::Error>> {
+    let client_id = connection.client_id;
+    let checked = match guard.admit(text.len(), Instant::now()) {
+        Ok(()) => serde_json::from_str::<Value>(text).map_err(|e| Violation::Invalid(e.to_string())),
+        Err(violation) => Err(violation),
+    };
+    let mut request_id = None;
+    let checked = match checked {
+        Ok(message) => {
+            request_id = message["requestId"].as_str().map(str::to_string);
+            match foreign_reference(&message, state, auth).await? {
+                Some(resource) => Err(Violation::Forbidden(resource)),
+                None => Ok(message),
+            }
+        }
+        Err(violation) => Err(violation),
+    };
+
+    let message = match checked {
+        Ok(message) => message,
+        Err(violation) => {
+            let exhausted = guard.record_violation(Instant::now());
+            warn!("🚫 Bỏ qua message từ client {}: {}", client_id, violation);
+            if exhausted {
+                warn!("⛔ Ngắt kết nối client {}: quá nhiều message bị từ chối", client_id);
+                let _ = connection.direct_tx.send(ws_limits::close_frame(&violation)).await;
+                return Ok(ControlFlow::Break(()));
+            }
+            // A full queue means the client isn't reading its warnings anyway
+            let _ = connection.direct_tx.try_send(guard.warning_frame(&violation, request_id.as_deref()));
+            return Ok(ControlFlow::Continue(()));
+        }
+    };
+
+    if let Err(e) = dispatch_client_message(&message, state, auth, connection).await {
+        warn!(
+            "❌ {} từ client {} thất bại ({}): {}",
+            message["type"].as_str().unwrap_or("unknown"),
+            client_id,
+            e.code,
+            e.message
+        );
+        let _ = connection.direct_tx.try_send(e.reply(&message));
+    }
+    Ok(ControlFlow::Continue(()))
+}
+
+async fn dispatch_client_message(
+    message: &Value,
+    state: &AppState,
+    auth: &AuthContext,
+    connection: &Connection<'_>,
+) -> Result<(), WsError> {
+    let client_id = connection.client_id;
     let message_type = message["type"].as_str().unwrap_or("unknown");
 
     info!("📨 Nhận message từ client {}: {}", client_id, message_type);
 
     match message_type {
         "start-code-analysis" => {
-            let request = CodeAnalysisRequest {
+            // "ask", "testcases" or "diagram"; the ticket's mode when unset
+            let requested_mode = serde_json::from_value(message["mode"].clone()).ok();
+            let mut request = CodeAnalysisRequest {
                 ticket_id: message["ticketId"]
                     .as_str()
                     .unwrap_or("unknown")
@@ -107,6 +461,21 @@ async fn handle_client_message(
                     .as_str()
                     .unwrap_or("")
                     .to_string(),
+                run_id: None,
+                agent_type: message["agentType"].as_str().map(str::to_string),
+                mode: requested_mode.unwrap_or_default(),
+                path_rules: Default::default(),
+                tool_policy: Default::default(),
+                // "low", "normal" (default), "high" or "urgent"
+                priority: serde_json::from_value(message["priority"].clone()).unwrap_or_default(),
+                include_linked_results: message["includeLinkedResults"].as_bool().unwrap_or(false),
+                budget_override: message["overrideBudget"].as_bool().unwrap_or(false),
+                replay: None,
+                working_dir: None,
+                prompt_variant: None,
+                response_language: None,
+                api_key: None,
+                then: Vec::new(),
             };
 
             info!(
@@ -116,67 +485,74 @@ async fn handle_client_message(
 
             // Validate ticket exists before spawning analysis
             match state.database.get_ticket(&request.ticket_id).await {
-                Ok(Some(_)) => {
+                Ok(Some(ticket)) => {
+                    if auth.project(&state.database, &ticket.project_id).await?.is_none() {
+                        return Err(WsError::not_found(format!("Ticket {}", request.ticket_id)));
+                    }
                     // Ticket exists, proceed with analysis
                     info!("✅ Ticket {} tồn tại trong database", request.ticket_id);
+                    // `setTicketMode` makes the requested mode the ticket's, not just this run's
+                    let remember = message["setTicketMode"].as_bool().unwrap_or(false);
+                    request.mode = crate::analysis_runner::choose_mode(state, &ticket, requested_mode, remember).await?;
                 }
                 Ok(None) => {
+                    // The auto-created ticket lands in the requested project, which must be ours
+                    if auth.project(&state.database, &request.project_id).await?.is_none() {
+                        return Err(WsError::not_found(format!("Project {}", request.project_id)));
+                    }
                     error!("⚠️ Ticket {} không tồn tại trong database, sẽ được tự động tạo", request.ticket_id);
                     // Will be auto-created in cursor_agent
                 }
-                Err(e) => {
-                    error!("❌ Lỗi kiểm tra ticket {}: {}", request.ticket_id, e);
-                    // Will try to auto-create in cursor_agent
-                }
+                Err(e) => return Err(WsError::internal("Failed to load ticket", e)),
             }
 
-            // Spawn analysis in background
-            let code_agent = state.code_agent.clone();
-            let msg_store = state.msg_store.clone();
-            let database = state.database.clone();
-            let broadcast_tx = state.broadcast_tx.clone();
-            let running_tasks = state.running_tasks.clone();
-            let ticket_id = request.ticket_id.clone();
-            let ticket_id_for_cleanup = ticket_id.clone();
-
-            let handle = tokio::spawn(async move {
-                match code_agent
-                    .analyze_code(request.clone(), msg_store.clone(), database.clone())
-                    .await
-                {
-                    Ok(response) => {
-                        // Broadcast completion message
-                        let _ = broadcast_tx.send(crate::BroadcastMessage {
-                            ticket_id: response.ticket_id,
-                            message_type: "code-analysis-complete".to_string(),
-                            content: response.result,
-                            timestamp: chrono::Utc::now(),
-                        });
-
-                        info!("✅ Phân tích hoàn tất cho ticket {}", request.ticket_id);
-                    }
-                    Err(e) => {
-                        error!("❌ Lỗi phân tích code: {}", e);
-
-                        // Broadcast error message
-                        let _ = broadcast_tx.send(crate::BroadcastMessage {
-                            ticket_id: request.ticket_id,
-                            message_type: "code-analysis-error".to_string(),
-                            content: e.to_string(),
-                            timestamp: chrono::Utc::now(),
-                        });
-                    }
-                }
+            // `overrideBudget` starts it despite a used-up budget that allows it (organization admins)
+            if request.budget_override && !auth.can_manage_org() {
+                return Err(WsError::invalid("Only organization admins can override a budget".to_string()));
+            }
+            match crate::budgets::check(&state.database, &request.project_id, request.budget_override).await {
+                Ok(None) => {}
+                Ok(Some(exceeded)) => return Err(WsError::budget_exceeded(exceeded)),
+                Err(e) => return Err(WsError::internal("Failed to check budgets", e)),
+            }
 
-                // Clean up task handle when analysis completes
-                let mut tasks = running_tasks.lock().await;
-                tasks.remove(&ticket_id_for_cleanup);
+            let ticket_id = request.ticket_id.clone();
+            let run_id = crate::analysis_runner::start_analysis(state, request, auth.user_id.clone()).await;
+
+            // Let clients show the run ID so users can quote it in bug reports
+            let _ = state.broadcast_tx.send(crate::BroadcastMessage {
+                ticket_id,
+                message_type: "analysis-started".to_string(),
+                content: json!({ "run_id": run_id }).to_string(),
+                timestamp: chrono::Utc::now(),
+                org_id: None,
+                changed_files: None,
             });
+        }
 
-            // Store abort handle for cancellation
-            {
-                let mut tasks = state.running_tasks.lock().await;
-                tasks.insert(ticket_id, handle.abort_handle());
+        "agent-input" => {
+            // Answer to an `agent-awaiting-input` prompt: { ticketId, allow?, text? }
+            let ticket_id = message["ticketId"].as_str().unwrap_or("");
+            if auth.ticket(&state.database, ticket_id).await?.is_none() {
+                return Err(WsError::not_found(format!("Ticket {}", ticket_id)));
+            }
+
+            let input: AgentInput = serde_json::from_value(message.clone()).unwrap_or_default();
+            match state.msg_store.inputs().answer(ticket_id, &input) {
+                Ok(request) => {
+                    let verdict = match (&input.text, input.allow) {
+                        (Some(text), _) => format!("trả lời \"{}\"", text),
+                        (None, Some(true)) => "cho phép".to_string(),
+                        (None, _) => "từ chối".to_string(),
+                    };
+                    info!("▶️ Client {} đã {} cho ticket {}", client_id, verdict, ticket_id);
+                    let entry = LogNormalizer::new().normalize(
+                        format!("▶️ Người dùng đã {}: {}", verdict, request.prompt),
+                        ticket_id.to_string(),
+                    );
+                    state.msg_store.push(entry).await;
+                }
+                Err(e) => return Err(WsError::invalid(format!("No input can be sent to ticket {}: {}", ticket_id, e))),
             }
         }
 
@@ -189,6 +565,67 @@ async fn handle_client_message(
             // Not implemented in this handler but available via msg_store.get_logs()
         }
 
+        "hello" => {
+            // Bootstrap snapshot in one reply, in place of load-projects and friends on startup
+            let snapshot = crate::ws_hello::snapshot(&state.database, auth, client_id)
+                .await
+                .map_err(|e| WsError::internal("Failed to load snapshot", e))?;
+            info!(
+                "👋 Client {} hello: {} projects, {} phân tích đang chạy, {} thông báo chưa đọc",
+                client_id,
+                snapshot.projects.len(),
+                snapshot.active_analyses.len(),
+                snapshot.notifications.unread
+            );
+            let compression = connection.settings_tx.borrow().compression;
+            let _ = connection.direct_tx.try_send(ws_stream::encode_frame(&crate::ws_hello::message(&snapshot), compression));
+        }
+
+        "replay-session" => {
+            // A finished session's logs re-sent to this connection only: { sessionId, speed? }
+            let session_id = message["sessionId"].as_str().unwrap_or("");
+            let session = state
+                .database
+                .get_session(session_id)
+                .await?
+                .ok_or_else(|| WsError::not_found(format!("Session {}", session_id)))?;
+            if auth.ticket(&state.database, &session.ticket_id).await?.is_none() {
+                return Err(WsError::not_found(format!("Session {}", session_id)));
+            }
+            if session.status == "running" {
+                return Err(WsError::invalid(format!("Session {} is still running", session_id)));
+            }
+
+            let replay = crate::log_replay::Replay::load(&state.database, &session, message["speed"].as_f64()).await?;
+            info!("⏯️ Client {} phát lại {} log của session {}", client_id, replay.len(), session_id);
+            let direct_tx = connection.direct_tx.clone();
+            let settings = connection.settings_tx.subscribe();
+            state.tasks.spawn(format!("ws-replay {}", client_id), async move {
+                let mut frames = std::pin::pin!(replay.frames());
+                while let Some(frame) = frames.next().await {
+                    let compression = settings.borrow().compression;
+                    // Ends with the connection
+                    if direct_tx.send(ws_stream::encode_frame(&frame, compression)).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
+        "backfill" => {
+            // Entries missed after a `stream-lagged` notice: { ticketId, afterId? }
+            let ticket_id = message["ticketId"].as_str().unwrap_or("");
+            if auth.ticket(&state.database, ticket_id).await?.is_none() {
+                return Err(WsError::not_found(format!("Ticket {}", ticket_id)));
+            }
+
+            let logs = state.msg_store.logs_after(ticket_id, message["afterId"].as_str()).await;
+            info!("⏪ Client {} backfill {} log cho ticket {}", client_id, logs.len(), ticket_id);
+            let batch = ws_stream::batch_message(logs.iter().map(ws_stream::log_json).collect());
+            let compression = connection.settings_tx.borrow().compression;
+            let _ = connection.direct_tx.try_send(ws_stream::encode_frame(&batch, compression));
+        }
+
         "load-tickets" => {
             let project_id = message["projectId"].as_str();
             
@@ -196,9 +633,12 @@ async fn handle_client_message(
 
             // Load tickets from database
             let result = if let Some(pid) = project_id {
+                if auth.project(&state.database, pid).await?.is_none() {
+                    return Err(WsError::not_found(format!("Project {}", pid)));
+                }
                 state.database.list_tickets_by_project(pid).await
             } else {
-                state.database.list_tickets().await
+                state.database.list_tickets_by_org(&auth.org_id).await
             };
 
             match result {
@@ -212,11 +652,11 @@ async fn handle_client_message(
                         message_type: "tickets-loaded".to_string(),
                         content: tickets_json,
                         timestamp: chrono::Utc::now(),
+                        org_id: Some(auth.org_id.clone()),
+                        changed_files: None,
                     });
                 }
-                Err(e) => {
-                    error!("❌ Lỗi tải tickets: {}", e);
-                }
+                Err(e) => return Err(WsError::internal("Failed to load tickets", e)),
             }
         }
 
@@ -227,14 +667,16 @@ async fn handle_client_message(
                 .as_str()
                 .map(|s| s.to_string())
                 .unwrap_or_else(|| Uuid::new_v4().to_string());
+            let directory_path = state.project_roots.check(message["directoryPath"].as_str().unwrap_or(""))?;
 
             let project = crate::database::ProjectRecord {
                 id: project_id.clone(),
                 name: message["name"].as_str().unwrap_or("").to_string(),
                 description: message["description"].as_str().map(|s| s.to_string()),
-                directory_path: message["directoryPath"].as_str().unwrap_or("").to_string(),
+                directory_path,
                 created_at: chrono::Utc::now().to_rfc3339(),
                 updated_at: chrono::Utc::now().to_rfc3339(),
+                org_id: auth.org_id.clone(),
             };
 
             match state.database.create_project(&project).await {
@@ -247,16 +689,18 @@ async fn handle_client_message(
                         message_type: "project-created".to_string(),
                         content: serde_json::to_string(&project).unwrap_or_default(),
                         timestamp: chrono::Utc::now(),
+                        org_id: Some(auth.org_id.clone()),
+                        changed_files: None,
                     });
                 }
-                Err(e) => error!("❌ Lỗi tạo project: {}", e),
+                Err(e) => return Err(WsError::internal("Failed to create project", e)),
             }
         }
 
         "load-projects" => {
             info!("📂 Client {} yêu cầu tải danh sách projects", client_id);
 
-            match state.database.list_projects().await {
+            match state.database.list_projects_by_org(&auth.org_id).await {
                 Ok(projects) => {
                     info!("✅ Tải được {} projects từ database", projects.len());
                     
@@ -266,9 +710,11 @@ async fn handle_client_message(
                         message_type: "projects-loaded".to_string(),
                         content: projects_json,
                         timestamp: chrono::Utc::now(),
+                        org_id: Some(auth.org_id.clone()),
+                        changed_files: None,
                     });
                 }
-                Err(e) => error!("❌ Lỗi tải projects: {}", e),
+                Err(e) => return Err(WsError::internal("Failed to load projects", e)),
             }
         }
 
@@ -276,7 +722,7 @@ async fn handle_client_message(
             let project_id = message["projectId"].as_str().unwrap_or("");
             info!("📋 Client {} yêu cầu chi tiết project {}", client_id, project_id);
 
-            match state.database.get_project(project_id).await {
+            match auth.project(&state.database, project_id).await {
                 Ok(Some(project)) => {
                     let project_json = serde_json::to_string(&project).unwrap_or_default();
                     let _ = state.broadcast_tx.send(crate::BroadcastMessage {
@@ -284,10 +730,12 @@ async fn handle_client_message(
                         message_type: "project-detail-loaded".to_string(),
                         content: project_json,
                         timestamp: chrono::Utc::now(),
+                        org_id: Some(auth.org_id.clone()),
+                        changed_files: None,
                     });
                 }
-                Ok(None) => error!("❌ Không tìm thấy project {}", project_id),
-                Err(e) => error!("❌ Lỗi tải project: {}", e),
+                Ok(None) => return Err(WsError::not_found(format!("Project {}", project_id))),
+                Err(e) => return Err(WsError::internal("Failed to load project", e)),
             }
         }
 
@@ -295,13 +743,19 @@ async fn handle_client_message(
             let project_id = message["id"].as_str().unwrap_or("");
             info!("🔄 Client {} cập nhật project {}", client_id, project_id);
 
+            if auth.project(&state.database, project_id).await?.is_none() {
+                return Err(WsError::not_found(format!("Project {}", project_id)));
+            }
+            let directory_path = state.project_roots.check(message["directoryPath"].as_str().unwrap_or(""))?;
+
             let project = crate::database::ProjectRecord {
                 id: project_id.to_string(),
                 name: message["name"].as_str().unwrap_or("").to_string(),
                 description: message["description"].as_str().map(|s| s.to_string()),
-                directory_path: message["directoryPath"].as_str().unwrap_or("").to_string(),
+                directory_path,
                 created_at: chrono::Utc::now().to_rfc3339(),
                 updated_at: chrono::Utc::now().to_rfc3339(),
+                org_id: auth.org_id.clone(),
             };
 
             match state.database.update_project(&project).await {
@@ -312,9 +766,11 @@ async fn handle_client_message(
                         message_type: "project-updated".to_string(),
                         content: serde_json::to_string(&project).unwrap_or_default(),
                         timestamp: chrono::Utc::now(),
+                        org_id: Some(auth.org_id.clone()),
+                        changed_files: None,
                     });
                 }
-                Err(e) => error!("❌ Lỗi cập nhật project: {}", e),
+                Err(e) => return Err(WsError::internal("Failed to update project", e)),
             }
         }
 
@@ -322,17 +778,23 @@ async fn handle_client_message(
             let project_id = message["projectId"].as_str().unwrap_or("");
             info!("🗑️ Client {} xóa project {}", client_id, project_id);
 
-            match state.database.delete_project(project_id).await {
+            if auth.project(&state.database, project_id).await?.is_none() {
+                return Err(WsError::not_found(format!("Project {}", project_id)));
+            }
+
+            match crate::trash::trash_project(state, project_id, auth.user_id.as_deref()).await {
                 Ok(_) => {
-                    info!("✅ Đã xóa project {}", project_id);
+                    info!("✅ Đã chuyển project {} vào thùng rác", project_id);
                     let _ = state.broadcast_tx.send(crate::BroadcastMessage {
                         ticket_id: "system".to_string(),
                         message_type: "project-deleted".to_string(),
                         content: project_id.to_string(),
                         timestamp: chrono::Utc::now(),
+                        org_id: Some(auth.org_id.clone()),
+                        changed_files: None,
                     });
                 }
-                Err(e) => error!("❌ Lỗi xóa project: {}", e),
+                Err(e) => return Err(WsError::internal("Failed to delete project", e)),
             }
         }
 
@@ -345,23 +807,40 @@ async fn handle_client_message(
                 .unwrap_or_else(|| Uuid::new_v4().to_string());
 
             let project_id = message["projectId"].as_str().unwrap_or("");
+            let status = message["status"].as_str().unwrap_or("todo");
+            valid_status(status)?;
 
+            if auth.project(&state.database, project_id).await?.is_none() {
+                return Err(WsError::not_found(format!("Project {}", project_id)));
+            }
+
+            let description = message["description"].as_str().unwrap_or("");
+            let title = crate::ticket_title::resolve(state, project_id, message["title"].as_str().unwrap_or(""), description).await;
             let ticket = crate::database::TicketRecord {
                 id: ticket_id.clone(),
                 project_id: project_id.to_string(),
-                title: message["title"].as_str().unwrap_or("").to_string(),
-                description: message["description"].as_str().unwrap_or("").to_string(),
-                status: message["status"].as_str().unwrap_or("todo").to_string(),
+                title,
+                description: description.to_string(),
+                status: status.to_string(),
                 code_context: message["codeContext"].as_str().map(|s| s.to_string()),
                 analysis_result: None,
                 is_analyzing: false,
                 created_at: chrono::Utc::now().to_rfc3339(),
                 updated_at: chrono::Utc::now().to_rfc3339(),
+                stale: false,
+                analysis_result_blob: None,
+                analysis_result_size: None,
+                position: 0.0,
+                assignee_id: None,
+                summary: None,
+                mode: serde_json::from_value(message["mode"].clone()).unwrap_or_default(),
             };
 
             match state.database.create_ticket(&ticket).await {
-                Ok(_) => {
+                Ok(position) => {
                     info!("✅ Tạo ticket thành công: {}", ticket.id);
+                    activity::watch_created(&state.database, &ticket.id, auth.user_id.as_deref()).await;
+                    let ticket = crate::database::TicketRecord { position, ..ticket };
                     
                     // Broadcast ticket created event to all clients
                     let _ = state.broadcast_tx.send(crate::BroadcastMessage {
@@ -369,9 +848,11 @@ async fn handle_client_message(
                         message_type: "ticket-created".to_string(),
                         content: serde_json::to_string(&ticket).unwrap_or_default(),
                         timestamp: chrono::Utc::now(),
+                        org_id: None,
+                        changed_files: None,
                     });
                 }
-                Err(e) => error!("❌ Lỗi tạo ticket: {}", e),
+                Err(e) => return Err(WsError::internal("Failed to create ticket", e)),
             }
         }
 
@@ -384,9 +865,22 @@ async fn handle_client_message(
                 client_id, ticket_id, new_status
             );
 
+            valid_status(new_status)?;
+            let Some(ticket) = auth.ticket(&state.database, ticket_id).await? else {
+                return Err(WsError::not_found(format!("Ticket {}", ticket_id)));
+            };
+
             match state.database.update_ticket_status(ticket_id, new_status).await {
                 Ok(_) => {
                     info!("✅ Đã cập nhật ticket {} status sang {}", ticket_id, new_status);
+                    activity::status_changed(
+                        &state.database,
+                        ticket_id,
+                        auth.user_id.as_deref(),
+                        &ticket.status,
+                        new_status,
+                    )
+                    .await;
                     
                     // Broadcast status update to all clients
                     let _ = state.broadcast_tx.send(crate::BroadcastMessage {
@@ -394,12 +888,61 @@ async fn handle_client_message(
                         message_type: "ticket-status-updated".to_string(),
                         content: new_status.to_string(),
                         timestamp: chrono::Utc::now(),
+                        org_id: None,
+                        changed_files: None,
                     });
                 }
-                Err(e) => error!("❌ Lỗi cập nhật ticket status {}: {}", ticket_id, e),
+                Err(e) => return Err(WsError::internal("Failed to update ticket status", e)),
             }
         }
 
+        "subscribe" => {
+            let settings = StreamSettings::from_subscribe(message);
+            info!(
+                "📶 Client {} stream settings: batch {} entries / {:?}, compression {:?}, buffer {} frames",
+                client_id, settings.batch_max_entries, settings.batch_window, settings.compression, settings.buffer_size
+            );
+            let _ = connection.settings_tx.send(settings);
+        }
+
+        "view-ticket" => {
+            // { ticketId }: the other viewers get `presence-joined`; one ticket per connection
+            let ticket_id = message["ticketId"].as_str().unwrap_or("");
+            if auth.ticket(&state.database, ticket_id).await?.is_none() {
+                return Err(WsError::not_found(format!("Ticket {}", ticket_id)));
+            }
+
+            let name = match &auth.user_id {
+                Some(user_id) => state.database.get_user(user_id).await?.map(|user| user.name),
+                None => None,
+            };
+            let viewer = Viewer::new(client_id, auth.user_id.clone(), name, chrono::Utc::now());
+            let joined = viewer.clone();
+            if let Some((left_ticket, left)) = state.presence.join(ticket_id, viewer) {
+                let _ = state.broadcast_tx.send(presence::message(&left_ticket, presence::LEFT, &left));
+            }
+            connection.viewing_tx.send_replace(Some(ticket_id.to_string()));
+            let _ = state.broadcast_tx.send(presence::message(ticket_id, presence::JOINED, &joined));
+            info!("👀 Client {} đang xem ticket {}", client_id, ticket_id);
+        }
+
+        "leave-ticket" => {
+            connection.viewing_tx.send_replace(None);
+            if let Some((ticket_id, viewer)) = state.presence.leave(client_id) {
+                let _ = state.broadcast_tx.send(presence::message(&ticket_id, presence::LEFT, &viewer));
+            }
+        }
+
+        "editing" => {
+            // { ticketId, field }, repeated while typing; `field: null` stops the indicator
+            let ticket_id = message["ticketId"].as_str().unwrap_or("");
+            let field = message["field"].as_str();
+            let Some(viewer) = state.presence.editing(client_id, ticket_id, field, chrono::Utc::now()) else {
+                return Err(WsError::invalid(format!("Send view-ticket for ticket {} first", ticket_id)));
+            };
+            let _ = state.broadcast_tx.send(presence::message(ticket_id, presence::EDITING, &viewer));
+        }
+
         "ping" => {
             info!("🏓 Ping từ client {}", client_id);
             // Pong will be sent automatically
@@ -407,8 +950,38 @@ async fn handle_client_message(
 
         _ => {
             info!("❓ Unknown message type từ client {}: {}", client_id, message_type);
+            return Err(WsError::invalid(format!("Unknown message type {}", message_type)));
         }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_reply_echoes_request() {
+        let request = json!({"type": "create-ticket", "requestId": "r1", "projectId": "p1"});
+        let Message::Text(frame) = WsError::not_found("Project p1".to_string()).reply(&request) else {
+            panic!("expected a text frame");
+        };
+        let frame: Value = serde_json::from_str(&frame).unwrap();
+        assert_eq!(frame["message_type"], "ws-error");
+        assert_eq!(frame["ticket_id"], "system");
+        let content: Value = serde_json::from_str(frame["content"].as_str().unwrap()).unwrap();
+        assert_eq!(
+            content,
+            json!({
+                "request_id": "r1",
+                "request_type": "create-ticket",
+                "code": "not-found",
+                "message": "Project p1 not found",
+            })
+        );
+
+        assert!(valid_status("in-progress").is_ok());
+        assert_eq!(valid_status("doing").unwrap_err().code, "invalid-request");
+    }
+}
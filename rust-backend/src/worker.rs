@@ -0,0 +1,567 @@
+use crate::analysis_runner;
+use crate::code_agent::CodeAnalysisRequest;
+use crate::database::{AnalysisJobRecord, Database, StreamEventRecord};
+use crate::job_queue::JobQueue;
+use crate::message_store::StructuredLogEntry;
+use crate::{AppState, BroadcastMessage};
+use chrono::Utc;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+use tokio::time::Duration;
+use tracing::{debug, error, info, warn};
+
+/// How often workers write out the log entries and broadcasts of their runs
+const FORWARD_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How often API processes pick up the workers' events
+const RELAY_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Most events an API process relays per poll
+const RELAY_BATCH: u32 = 500;
+
+/// Relayed events are only needed until every API process has read them
+const STREAM_EVENT_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// What this process does, from `--role`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Role {
+    /// Serve the API; analyses go to the shared queue for workers
+    Api,
+    /// Run analyses claimed from the shared queue; serves no HTTP
+    Worker,
+    /// Serve the API and run analyses from an in-memory queue, as a single process
+    #[default]
+    All,
+}
+
+impl Role {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "api" => Some(Self::Api),
+            "worker" => Some(Self::Worker),
+            "all" => Some(Self::All),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Api => "api",
+            Self::Worker => "worker",
+            Self::All => "all",
+        }
+    }
+
+    /// `--role <role>` or `--role=<role>`, else `SERVER_ROLE`, else `all`
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut args = args.into_iter();
+        let mut value = None;
+        while let Some(arg) = args.next() {
+            if arg == "--role" {
+                value = Some(args.next().unwrap_or_default());
+            } else if let Some(role) = arg.strip_prefix("--role=") {
+                value = Some(role.to_string());
+            }
+        }
+        let value = value.or_else(|| std::env::var("SERVER_ROLE").ok());
+        match value {
+            Some(value) => Self::parse(&value).ok_or_else(|| format!("Unknown role {:?} (api, worker or all)", value)),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Whether analyses go through the shared queue instead of this process's own
+    pub fn shared_queue(&self) -> bool {
+        !matches!(self, Self::All)
+    }
+
+    pub fn serves_api(&self) -> bool {
+        !matches!(self, Self::Worker)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WorkerConfig {
+    /// Marks this process's claims and events
+    pub instance_id: String,
+    /// How often a worker looks for jobs and renews its claims
+    pub poll_interval: Duration,
+    /// A claim not renewed for this long goes to another worker
+    pub claim_timeout: Duration,
+}
+
+impl WorkerConfig {
+    pub fn from_env() -> Self {
+        let suffix = uuid::Uuid::new_v4().simple().to_string()[..8].to_string();
+        let instance_id = match std::env::var("HOSTNAME") {
+            Ok(host) if !host.trim().is_empty() => format!("{}-{}", host.trim(), suffix),
+            _ => suffix,
+        };
+        let millis = |name: &str, default: u64| {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.trim().parse::<u64>().ok())
+                .filter(|v| *v > 0)
+                .unwrap_or(default)
+        };
+
+        Self {
+            instance_id,
+            poll_interval: Duration::from_millis(millis("WORKER_POLL_INTERVAL_MS", 1000)),
+            claim_timeout: Duration::from_secs(millis("WORKER_CLAIM_TIMEOUT_SECS", 60)),
+        }
+    }
+}
+
+/// Put a prepared request in the shared queue; returns its 1-based place there
+pub async fn enqueue(
+    database: &Database,
+    mut request: CodeAnalysisRequest,
+    requested_by: Option<String>,
+) -> anyhow::Result<Option<usize>> {
+    let follow_ups = std::mem::take(&mut request.then);
+    let job = AnalysisJobRecord {
+        seq: 0,
+        ticket_id: request.ticket_id.clone(),
+        run_id: request.run_id.clone().unwrap_or_default(),
+        priority: request.priority.rank(),
+        request_json: serde_json::to_string(&request)?,
+        follow_ups_json: serde_json::to_string(&follow_ups)?,
+        requested_by,
+        status: "queued".to_string(),
+        worker_id: None,
+        cancel_requested: false,
+        enqueued_at: Utc::now().to_rfc3339(),
+        claimed_at: None,
+        heartbeat_at: None,
+    };
+    database.enqueue_job(&job).await?;
+
+    let position = database.find_job(&job.ticket_id).await?.and_then(|(_, position)| position);
+    Ok(position.map(|p| p as usize))
+}
+
+/// Claim one job from the shared queue into this worker's queue; false when none is waiting
+pub async fn claim(state: &AppState, queue: &mut JobQueue) -> anyhow::Result<bool> {
+    let stale_before = Utc::now() - chrono::Duration::from_std(state.worker.claim_timeout)?;
+    let Some(job) = state
+        .database
+        .claim_job(&state.worker.instance_id, &stale_before.to_rfc3339())
+        .await?
+    else {
+        return Ok(false);
+    };
+
+    let mut request: CodeAnalysisRequest = match serde_json::from_str(&job.request_json) {
+        Ok(request) => request,
+        Err(e) => {
+            error!("❌ Job {} của ticket {} không đọc được, bỏ qua: {}", job.seq, job.ticket_id, e);
+            state.database.delete_job(job.seq).await?;
+            return Ok(true);
+        }
+    };
+    request.then = serde_json::from_str(&job.follow_ups_json).unwrap_or_default();
+    // Keys never leave the process that holds them; the worker looks the project's up itself
+    let (agent_type, _) = state.agents.resolve(request.agent_type.as_deref());
+    request.api_key = crate::agent_credentials::project_key(
+        &state.database,
+        state.credentials.as_deref(),
+        &request.project_id,
+        agent_type,
+    )
+    .await;
+
+    info!("📥 Worker {} nhận phân tích ticket {} (run {})", state.worker.instance_id, job.ticket_id, job.run_id);
+    state.msg_store.begin_run(&job.ticket_id, &job.run_id).await;
+    queue.push(request, job.requested_by);
+    Ok(true)
+}
+
+/// Stop a ticket's analysis in the shared queue: drop it while it waits, otherwise flag it for
+/// the worker running it. Returns the run ID, or None when the queue has no job for the ticket.
+pub async fn cancel(state: &AppState, ticket_id: &str) -> Option<String> {
+    if !state.role.shared_queue() {
+        return None;
+    }
+    match state.database.remove_queued_job(ticket_id).await {
+        Ok(Some(job)) => {
+            info!("⛔ Đã bỏ phân tích ticket {} khỏi hàng đợi chung (run {})", ticket_id, job.run_id);
+            analysis_runner::record_stopped(&state.database, &state.msg_store, &state.broadcast_tx, ticket_id).await;
+            state.msg_store.end_run(ticket_id, &job.run_id).await;
+            return Some(job.run_id);
+        }
+        Ok(None) => {}
+        Err(e) => error!("Failed to remove queued job of ticket {}: {}", ticket_id, e),
+    }
+    match state.database.request_job_cancel(ticket_id).await {
+        Ok(Some(run_id)) => {
+            info!("⛔ Đã yêu cầu worker dừng phân tích ticket {} (run {})", ticket_id, run_id);
+            Some(run_id)
+        }
+        Ok(None) => None,
+        Err(e) => {
+            error!("Failed to request cancellation of ticket {}: {}", ticket_id, e);
+            None
+        }
+    }
+}
+
+/// Renew this worker's claims, stop the runs an API process asked to stop, and release the
+/// claims of runs that have ended
+async fn sync_claims(state: &AppState) -> anyhow::Result<()> {
+    let mut ended = Vec::new();
+    let mut stopping = Vec::new();
+    {
+        let tasks = state.running_tasks.lock().await;
+        let queue = state.job_queue.lock().await;
+        for job in state.database.heartbeat_jobs(&state.worker.instance_id).await? {
+            let running = tasks.get(&job.ticket_id).is_some_and(|running| running.run_id == job.run_id);
+            let waiting = queue
+                .find(&job.ticket_id)
+                .is_some_and(|(_, queued)| queued.request.run_id.as_deref() == Some(job.run_id.as_str()));
+            if !running && !waiting {
+                ended.push(job);
+            } else if job.cancel_requested {
+                stopping.push(job);
+            }
+        }
+    }
+
+    for job in ended {
+        debug!("Releasing job {} of ticket {}", job.seq, job.ticket_id);
+        state.database.delete_job(job.seq).await?;
+    }
+    for job in stopping {
+        state.database.clear_job_cancel(job.seq).await?;
+        analysis_runner::cancel_analysis(state, &job.ticket_id).await;
+    }
+    Ok(())
+}
+
+/// Worker loop: claim jobs while slots are free and keep the claims up to date
+pub async fn run(state: AppState) {
+    info!(
+        "🛠️ Worker {} nhận phân tích từ hàng đợi chung (mỗi {}ms)",
+        state.worker.instance_id,
+        state.worker.poll_interval.as_millis()
+    );
+    let mut tick = tokio::time::interval(state.worker.poll_interval);
+    loop {
+        tick.tick().await;
+        analysis_runner::dispatch(&state).await;
+        if let Err(e) = sync_claims(&state).await {
+            error!("Failed to sync claimed jobs: {}", e);
+        }
+    }
+}
+
+fn log_event(origin: &str, entry: &StructuredLogEntry) -> Option<StreamEventRecord> {
+    Some(StreamEventRecord {
+        seq: 0,
+        origin: origin.to_string(),
+        kind: "log".to_string(),
+        ticket_id: entry.ticket_id.clone(),
+        org_id: None,
+        payload: serde_json::to_string(entry).ok()?,
+        created_at: Utc::now().to_rfc3339(),
+    })
+}
+
+fn broadcast_event(origin: &str, message: &BroadcastMessage) -> Option<StreamEventRecord> {
+    Some(StreamEventRecord {
+        seq: 0,
+        origin: origin.to_string(),
+        kind: "broadcast".to_string(),
+        ticket_id: message.ticket_id.clone(),
+        org_id: message.org_id.clone(),
+        payload: serde_json::to_string(message).ok()?,
+        created_at: Utc::now().to_rfc3339(),
+    })
+}
+
+/// Write this worker's log entries and broadcasts to `stream_events` for the API processes
+pub fn spawn_forwarder(state: &AppState) {
+    let mut logs = state.msg_store.subscribe();
+    let mut broadcasts = state.broadcast_tx.subscribe();
+    let database = state.database.clone();
+    let origin = state.worker.instance_id.clone();
+    // With a Redis bridge the logs already reach every instance; only broadcasts go here
+    let forward_logs = !state.msg_store.is_bridged();
+
+    state.tasks.spawn("worker-forwarder", async move {
+        let mut batch: Vec<StreamEventRecord> = Vec::new();
+        let mut flush = tokio::time::interval(FORWARD_INTERVAL);
+        let mut prune = tokio::time::interval(Duration::from_secs(60));
+        loop {
+            tokio::select! {
+                received = logs.recv() => match received {
+                    Ok(entry) if forward_logs => batch.extend(log_event(&origin, &entry)),
+                    Ok(_) => {}
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!("⚠️ Bỏ lỡ {} log khi chuyển tiếp cho API", skipped);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                received = broadcasts.recv() => match received {
+                    Ok(message) => batch.extend(broadcast_event(&origin, &message)),
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!("⚠️ Bỏ lỡ {} sự kiện khi chuyển tiếp cho API", skipped);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                _ = flush.tick() => {
+                    if !batch.is_empty() {
+                        if let Err(e) = database.append_stream_events(&batch).await {
+                            error!("Failed to forward {} stream events: {}", batch.len(), e);
+                        }
+                        batch.clear();
+                    }
+                }
+                _ = prune.tick() => prune_events(&database).await,
+            }
+        }
+    });
+}
+
+async fn prune_events(database: &Database) {
+    let Ok(ttl) = chrono::Duration::from_std(STREAM_EVENT_TTL) else {
+        return;
+    };
+    match database.prune_stream_events(&(Utc::now() - ttl).to_rfc3339()).await {
+        Ok(0) => {}
+        Ok(pruned) => debug!("Pruned {} relayed stream events", pruned),
+        Err(e) => error!("Failed to prune stream events: {}", e),
+    }
+}
+
+/// Hand an event of another process to this one's WebSocket, GraphQL and gRPC subscribers
+async fn relay_event(state: &AppState, event: StreamEventRecord) {
+    match event.kind.as_str() {
+        "log" => match serde_json::from_str::<StructuredLogEntry>(&event.payload) {
+            Ok(entry) => state.msg_store.relay(entry).await,
+            Err(e) => warn!("Skipping unreadable log event {}: {}", event.seq, e),
+        },
+        "broadcast" => match serde_json::from_str::<BroadcastMessage>(&event.payload) {
+            Ok(mut message) => {
+                // The worker updated the ticket (analysis state, result, status) in its own
+                // process; this one's cached copy is out of date
+                if !message.ticket_id.is_empty() {
+                    state.database.invalidate_cached_ticket(&message.ticket_id);
+                }
+                message.org_id = event.org_id;
+                let _ = state.broadcast_tx.send(message);
+            }
+            Err(e) => warn!("Skipping unreadable broadcast event {}: {}", event.seq, e),
+        },
+        kind => warn!("Skipping stream event {} of unknown kind {}", event.seq, kind),
+    }
+}
+
+/// Relay the workers' events to this API process's clients, starting with those written from now
+pub fn spawn_relay(state: &AppState) {
+    let state = state.clone();
+    let database: Arc<Database> = state.database.clone();
+
+    let tasks = state.tasks.clone();
+    tasks.spawn("worker-relay", async move {
+        let mut last_seq = match database.latest_stream_event_seq().await {
+            Ok(seq) => seq,
+            Err(e) => {
+                error!("Failed to read stream events: {}", e);
+                0
+            }
+        };
+        let mut tick = tokio::time::interval(RELAY_INTERVAL);
+        loop {
+            tick.tick().await;
+            let events = match database
+                .get_stream_events_after(last_seq, &state.worker.instance_id, RELAY_BATCH)
+                .await
+            {
+                Ok(events) => events,
+                Err(e) => {
+                    warn!("⚠️ Không đọc được sự kiện từ worker: {}", e);
+                    continue;
+                }
+            };
+            for event in events {
+                last_seq = event.seq;
+                relay_event(&state, event).await;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::TicketRecord;
+    use crate::test_support::{project_fixture, test_db, ticket_fixture};
+    use crate::job_queue::AnalysisPriority;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_role_from_args() {
+        assert_eq!(Role::from_args(args("backend --role worker")), Ok(Role::Worker));
+        assert_eq!(Role::from_args(args("backend --role=API")), Ok(Role::Api));
+        assert!(Role::from_args(args("backend --role scheduler")).is_err());
+        assert!(Role::Api.shared_queue() && Role::Worker.shared_queue() && !Role::All.shared_queue());
+        assert!(!Role::Worker.serves_api());
+    }
+
+    fn request(ticket_id: &str, priority: AnalysisPriority) -> CodeAnalysisRequest {
+        CodeAnalysisRequest {
+            ticket_id: ticket_id.to_string(),
+            code_context: String::new(),
+            question: "How does login work?".to_string(),
+            project_id: "p1".to_string(),
+            run_id: Some(format!("run-{}", ticket_id)),
+            agent_type: None,
+            mode: Default::default(),
+            path_rules: Default::default(),
+            tool_policy: Default::default(),
+            priority,
+            include_linked_results: false,
+            budget_override: false,
+            replay: None,
+            working_dir: None,
+            prompt_variant: None,
+            response_language: None,
+            api_key: None,
+            then: Vec::new(),
+        }
+    }
+
+    async fn setup(tickets: &[&str]) -> Database {
+        let db = test_db().await.unwrap();
+        db.create_project(&project_fixture("p1")).await.unwrap();
+        for id in tickets {
+            db.create_ticket(&ticket_fixture(id, "p1")).await.unwrap();
+        }
+        db
+    }
+
+    #[tokio::test]
+    async fn test_shared_queue_claims_by_priority_and_reclaims_stale_jobs() {
+        let db = setup(&["t1", "t2", "t3"]).await;
+        let mut chained = request("t1", AnalysisPriority::Normal);
+        chained.then = vec![request("t3", AnalysisPriority::Normal)];
+        assert_eq!(enqueue(&db, chained, None).await.unwrap(), Some(1));
+        assert_eq!(enqueue(&db, request("t2", AnalysisPriority::Urgent), None).await.unwrap(), Some(1));
+        assert_eq!(db.find_job("t1").await.unwrap().unwrap().1, Some(2));
+
+        let past = "2000-01-01T00:00:00+00:00";
+        let first = db.claim_job("w1", past).await.unwrap().unwrap();
+        assert_eq!((first.ticket_id.as_str(), first.worker_id.as_deref()), ("t2", Some("w1")));
+        let second = db.claim_job("w2", past).await.unwrap().unwrap();
+        assert_eq!(second.ticket_id, "t1");
+        let follow_ups: Vec<CodeAnalysisRequest> = serde_json::from_str(&second.follow_ups_json).unwrap();
+        assert_eq!(follow_ups[0].ticket_id, "t3");
+        assert!(db.claim_job("w1", past).await.unwrap().is_none());
+
+        // w2 stops heartbeating: its job goes to the next worker that looks
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        let cutoff = Utc::now().to_rfc3339();
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        assert_eq!(db.heartbeat_jobs("w1").await.unwrap().len(), 1);
+        let reclaimed = db.claim_job("w3", &cutoff).await.unwrap().unwrap();
+        assert_eq!((reclaimed.seq, reclaimed.worker_id.as_deref()), (second.seq, Some("w3")));
+        assert!(db.claim_job("w4", &cutoff).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_cancelling_queued_and_claimed_jobs() {
+        let db = setup(&["t1", "t2"]).await;
+        enqueue(&db, request("t1", AnalysisPriority::Normal), None).await.unwrap();
+        enqueue(&db, request("t2", AnalysisPriority::Normal), None).await.unwrap();
+        let claimed = db.claim_job("w1", "2000-01-01T00:00:00+00:00").await.unwrap().unwrap();
+        assert_eq!(claimed.ticket_id, "t1");
+
+        assert!(db.remove_queued_job("t1").await.unwrap().is_none());
+        assert_eq!(db.request_job_cancel("t1").await.unwrap().as_deref(), Some("run-t1"));
+        assert!(db.heartbeat_jobs("w1").await.unwrap()[0].cancel_requested);
+
+        assert_eq!(db.remove_queued_job("t2").await.unwrap().unwrap().run_id, "run-t2");
+        assert!(db.find_job("t2").await.unwrap().is_none());
+        db.delete_job(claimed.seq).await.unwrap();
+        assert!(db.find_job("t1").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_stream_events_skip_own_origin() {
+        let db = setup(&["t1"]).await;
+        let entry = crate::log_normalizer::LogNormalizer::new().normalize("Reading file: a.rs".to_string(), "t1".to_string());
+        let message = BroadcastMessage {
+            ticket_id: "t1".to_string(),
+            message_type: "code-analysis-complete".to_string(),
+            content: "done".to_string(),
+            timestamp: Utc::now(),
+            org_id: Some("default".to_string()),
+            changed_files: None,
+        };
+        let events: Vec<StreamEventRecord> =
+            [log_event("worker", &entry), broadcast_event("worker", &message), log_event("api", &entry)]
+                .into_iter()
+                .flatten()
+                .collect();
+        db.append_stream_events(&events).await.unwrap();
+
+        let relayed = db.get_stream_events_after(0, "api", 10).await.unwrap();
+        assert_eq!(relayed.iter().map(|e| e.kind.as_str()).collect::<Vec<_>>(), ["log", "broadcast"]);
+        assert_eq!(relayed[1].org_id.as_deref(), Some("default"));
+        assert!(db.get_stream_events_after(relayed[1].seq, "api", 10).await.unwrap().is_empty());
+        assert_eq!(db.latest_stream_event_seq().await.unwrap(), 3);
+
+        let later = (Utc::now() + chrono::Duration::seconds(5)).to_rfc3339();
+        assert_eq!(db.prune_stream_events(&later).await.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_relayed_broadcast_refreshes_cached_ticket() {
+        use crate::mock_agent::{MockAgent, MockAgentConfig};
+        use crate::test_support::{temp_dir, test_database};
+
+        // Two processes on one file, each with its own ticket cache
+        let dir = temp_dir("worker-relay").unwrap();
+        let api_db = test_database(&dir).await.unwrap();
+        let worker_db = test_database(&dir).await.unwrap();
+        api_db.create_project(&project_fixture("p1")).await.unwrap();
+        api_db
+            .create_ticket(&TicketRecord {
+                is_analyzing: true,
+                ..ticket_fixture("t1", "p1")
+            })
+            .await
+            .unwrap();
+        let agent = Arc::new(MockAgent::with_config(MockAgentConfig::from_env()));
+        let state = crate::test_support::test_state(api_db.clone(), agent).unwrap();
+        assert!(api_db.get_ticket("t1").await.unwrap().unwrap().is_analyzing);
+
+        worker_db.update_ticket_result("t1", "Login goes through auth.rs").await.unwrap();
+        worker_db.update_ticket_analyzing("t1", false).await.unwrap();
+        let message = BroadcastMessage {
+            ticket_id: "t1".to_string(),
+            message_type: "code-analysis-complete".to_string(),
+            content: "done".to_string(),
+            timestamp: Utc::now(),
+            org_id: Some("default".to_string()),
+            changed_files: None,
+        };
+        worker_db.append_stream_events(&broadcast_event("worker", &message).into_iter().collect::<Vec<_>>()).await.unwrap();
+        // Still the cached copy until the broadcast is relayed
+        assert!(api_db.get_ticket("t1").await.unwrap().unwrap().is_analyzing);
+
+        let mut received = state.broadcast_tx.subscribe();
+        for event in api_db.get_stream_events_after(0, "api", 10).await.unwrap() {
+            relay_event(&state, event).await;
+        }
+        assert_eq!(received.recv().await.unwrap().message_type, "code-analysis-complete");
+        let ticket = api_db.get_ticket("t1").await.unwrap().unwrap();
+        assert!(!ticket.is_analyzing);
+        assert_eq!(ticket.analysis_result.as_deref(), Some("Login goes through auth.rs"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,141 @@
+//! The snapshot a client gets in reply to its `hello` message: the org's projects, the analyses
+//! running right now and the user's unread notifications, in one frame instead of a
+//! `load-projects` followed by dashboard and feed requests
+//!
+//! ```json
+//! {"ticket_id": "system", "message_type": "hello", "content": "{\"client_id\":\"...\",\"projects\":[...],\"active_analyses\":[...],\"notifications\":{...}}", "timestamp": "..."}
+//! ```
+
+use crate::auth::AuthContext;
+use crate::database::{Database, FeedEntry, ProjectRecord, RunningSessionRow};
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+/// Unread entries included; `unread` still counts all of them
+const NOTIFICATIONS_LIMIT: u32 = 20;
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Notifications {
+    pub unread: i64,
+    /// Last `POST /api/me/activity/read`; None if the user never marked the feed read
+    pub read_at: Option<String>,
+    /// Newest first
+    pub entries: Vec<FeedEntry>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HelloSnapshot {
+    pub client_id: String,
+    pub org_id: String,
+    pub user_id: Option<String>,
+    pub projects: Vec<ProjectRecord>,
+    /// Running sessions of every project of the org, oldest first
+    pub active_analyses: Vec<RunningSessionRow>,
+    /// Empty for anonymous access, which watches nothing
+    pub notifications: Notifications,
+}
+
+async fn notifications(database: &Database, auth: &AuthContext) -> Result<Notifications> {
+    let Some(user_id) = auth.user_id.as_deref() else {
+        return Ok(Notifications::default());
+    };
+    let (read_at, (entries, unread)) = tokio::try_join!(
+        database.get_activity_read_at(user_id),
+        database.list_unread_feed(user_id, &auth.org_id, NOTIFICATIONS_LIMIT),
+    )?;
+    Ok(Notifications { unread, read_at, entries })
+}
+
+pub async fn snapshot(database: &Database, auth: &AuthContext, client_id: &str) -> Result<HelloSnapshot> {
+    let (projects, active_analyses, notifications) = tokio::try_join!(
+        database.list_projects_by_org(&auth.org_id),
+        database.list_org_running_sessions(&auth.org_id),
+        notifications(database, auth),
+    )?;
+
+    Ok(HelloSnapshot {
+        client_id: client_id.to_string(),
+        org_id: auth.org_id.clone(),
+        user_id: auth.user_id.clone(),
+        projects,
+        active_analyses,
+        notifications,
+    })
+}
+
+/// The `hello` reply carrying `snapshot`
+pub fn message(snapshot: &HelloSnapshot) -> Value {
+    json!({
+        "ticket_id": "system",
+        "message_type": "hello",
+        "content": serde_json::to_string(snapshot).unwrap_or_default(),
+        "timestamp": chrono::Utc::now(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::activity;
+    use crate::auth::OrgRole;
+    use crate::database::{TicketRecord, UserRecord, DEFAULT_ORG_ID};
+    use crate::test_support::{project_fixture, test_db, ticket_fixture};
+    use crate::tool_policy::ToolPolicy;
+    use chrono::Utc;
+
+    #[tokio::test]
+    async fn test_snapshot() {
+        let db = test_db().await.unwrap();
+        let now = Utc::now().to_rfc3339();
+        db.create_project(&project_fixture("p1")).await.unwrap();
+        for id in ["alice", "bob"] {
+            db.create_user(&UserRecord {
+                id: id.to_string(),
+                org_id: DEFAULT_ORG_ID.to_string(),
+                email: format!("{}@example.com", id),
+                name: id.to_string(),
+                password_hash: None,
+                role: "member".to_string(),
+                created_at: now.clone(),
+            })
+            .await
+            .unwrap();
+        }
+        for id in ["t1", "t2"] {
+            db.create_ticket(&TicketRecord {
+                title: format!("Ticket {}", id),
+                ..ticket_fixture(id, "p1")
+            })
+            .await
+            .unwrap();
+            activity::watch_created(&db, id, Some("alice")).await;
+        }
+        db.create_session("t2", "claude", Some("r1"), &ToolPolicy::default(), Default::default()).await.unwrap();
+        activity::status_changed(&db, "t1", Some("bob"), "todo", "in-progress").await;
+        // Alice's own actions aren't notifications
+        activity::status_changed(&db, "t2", Some("alice"), "todo", "done").await;
+
+        let alice = AuthContext {
+            user_id: Some("alice".to_string()),
+            org_id: DEFAULT_ORG_ID.to_string(),
+            role: OrgRole::Member,
+        };
+        let hello = snapshot(&db, &alice, "c1").await.unwrap();
+        assert_eq!(hello.projects.len(), 1);
+        assert_eq!(hello.active_analyses.len(), 1);
+        assert_eq!((hello.active_analyses[0].ticket_id.as_str(), hello.active_analyses[0].project_id.as_str()), ("t2", "p1"));
+        assert_eq!(hello.notifications.unread, 1);
+        assert_eq!(hello.notifications.entries[0].ticket_id, "t1");
+
+        db.mark_activity_read("alice", &Utc::now().to_rfc3339()).await.unwrap();
+        let hello = snapshot(&db, &alice, "c1").await.unwrap();
+        assert_eq!(hello.notifications.unread, 0);
+        assert!(hello.notifications.entries.is_empty());
+        assert!(hello.notifications.read_at.is_some());
+
+        let anonymous = snapshot(&db, &AuthContext::anonymous(), "c2").await.unwrap();
+        assert_eq!(anonymous.notifications.unread, 0);
+        assert_eq!(anonymous.projects.len(), 1);
+    }
+}
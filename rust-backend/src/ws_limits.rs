@@ -0,0 +1,250 @@
+//! Flood protection for `/ws` client messages
+//!
+//! Every connection gets a token bucket of `WS_RATE_LIMIT_BURST` messages refilled at
+//! `WS_RATE_LIMIT_PER_SEC`. A message over the rate, larger than `WS_MAX_MESSAGE_BYTES`, not
+//! valid JSON, or naming another organization's project or ticket is dropped and answered with
+//! a `ws-warning` frame:
+//!
+//! ```json
+//! {"ticket_id": "system", "message_type": "ws-warning", "content": "{\"request_id\":null,\"code\":\"rate-limited\",\"message\":\"...\",\"violations\":3,\"max_violations\":20}", "timestamp": "..."}
+//! ```
+//!
+//! After `WS_MAX_VIOLATIONS` dropped messages the connection is closed with a policy-violation
+//! close frame. The count starts over once a client goes a minute without violations.
+
+use axum::extract::ws::{close_code, CloseFrame, Message};
+use serde_json::json;
+use tokio::time::{Duration, Instant};
+
+/// A client this long without violations starts over with a clean record
+const VIOLATION_RESET: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WsLimits {
+    /// Sustained messages per second; 0 disables rate limiting
+    pub messages_per_second: u32,
+    /// Messages a connection may send at once before the rate applies
+    pub burst: u32,
+    pub max_message_bytes: usize,
+    /// Dropped messages before the connection is closed
+    pub max_violations: u32,
+    /// Times the client may fall behind the log stream before it is disconnected; 0 = never
+    pub max_lags: u32,
+}
+
+impl Default for WsLimits {
+    fn default() -> Self {
+        Self {
+            messages_per_second: 10,
+            burst: 30,
+            max_message_bytes: 64 * 1024,
+            max_violations: 20,
+            max_lags: 5,
+        }
+    }
+}
+
+impl WsLimits {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+
+        Self {
+            messages_per_second: std::env::var("WS_RATE_LIMIT_PER_SEC")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(defaults.messages_per_second),
+            burst: std::env::var("WS_RATE_LIMIT_BURST")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|n| *n > 0)
+                .unwrap_or(defaults.burst),
+            max_message_bytes: std::env::var("WS_MAX_MESSAGE_BYTES")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|n| *n > 0)
+                .unwrap_or(defaults.max_message_bytes),
+            max_violations: std::env::var("WS_MAX_VIOLATIONS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|n| *n > 0)
+                .unwrap_or(defaults.max_violations),
+            max_lags: std::env::var("WS_MAX_LAGS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(defaults.max_lags),
+        }
+    }
+}
+
+/// Why a client message was dropped
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum Violation {
+    #[error("Too many messages; slow down")]
+    RateLimited,
+
+    #[error("Message of {0} bytes exceeds the {1} byte limit")]
+    TooLarge(usize, usize),
+
+    #[error("Invalid message: {0}")]
+    Invalid(String),
+
+    #[error("{0} is not accessible")]
+    Forbidden(String),
+}
+
+impl Violation {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Violation::RateLimited => "rate-limited",
+            Violation::TooLarge(..) => "payload-too-large",
+            Violation::Invalid(_) => "invalid-message",
+            Violation::Forbidden(_) => "forbidden",
+        }
+    }
+}
+
+/// Per-connection token bucket and violation count
+#[derive(Debug)]
+pub struct MessageGuard {
+    limits: WsLimits,
+    tokens: f64,
+    refilled_at: Instant,
+    violations: u32,
+    last_violation: Option<Instant>,
+}
+
+impl MessageGuard {
+    pub fn new(limits: WsLimits, now: Instant) -> Self {
+        Self {
+            limits,
+            tokens: limits.burst as f64,
+            refilled_at: now,
+            violations: 0,
+            last_violation: None,
+        }
+    }
+
+    /// Take a token for a message of `size` bytes
+    pub fn admit(&mut self, size: usize, now: Instant) -> Result<(), Violation> {
+        if self.limits.messages_per_second > 0 {
+            let elapsed = now.saturating_duration_since(self.refilled_at).as_secs_f64();
+            self.tokens = (self.tokens + elapsed * self.limits.messages_per_second as f64).min(self.limits.burst as f64);
+            self.refilled_at = now;
+            if self.tokens < 1.0 {
+                return Err(Violation::RateLimited);
+            }
+            self.tokens -= 1.0;
+        }
+        if size > self.limits.max_message_bytes {
+            return Err(Violation::TooLarge(size, self.limits.max_message_bytes));
+        }
+        Ok(())
+    }
+
+    /// Count a dropped message; true once the connection should be closed
+    pub fn record_violation(&mut self, now: Instant) -> bool {
+        if self
+            .last_violation
+            .is_some_and(|at| now.saturating_duration_since(at) >= VIOLATION_RESET)
+        {
+            self.violations = 0;
+        }
+        self.violations += 1;
+        self.last_violation = Some(now);
+        self.violations >= self.limits.max_violations
+    }
+
+    /// `ws-warning` frame telling the client why its message was dropped, echoing the message's
+    /// `requestId` when it could be read
+    pub fn warning_frame(&self, violation: &Violation, request_id: Option<&str>) -> Message {
+        let content = json!({
+            "request_id": request_id,
+            "code": violation.code(),
+            "message": violation.to_string(),
+            "violations": self.violations,
+            "max_violations": self.limits.max_violations,
+        });
+        let frame = json!({
+            "ticket_id": "system",
+            "message_type": "ws-warning",
+            "content": content.to_string(),
+            "timestamp": chrono::Utc::now(),
+        });
+        Message::Text(frame.to_string())
+    }
+}
+
+/// Close frame sent when a client used up its violations
+pub fn close_frame(violation: &Violation) -> Message {
+    Message::Close(Some(CloseFrame {
+        code: close_code::POLICY,
+        reason: format!("Too many rejected messages (last: {})", violation.code()).into(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits() -> WsLimits {
+        WsLimits {
+            messages_per_second: 2,
+            burst: 3,
+            max_message_bytes: 100,
+            max_violations: 3,
+            max_lags: 5,
+        }
+    }
+
+    #[test]
+    fn test_burst_then_rate() {
+        let start = Instant::now();
+        let mut guard = MessageGuard::new(limits(), start);
+        for _ in 0..3 {
+            guard.admit(10, start).unwrap();
+        }
+        assert_eq!(guard.admit(10, start), Err(Violation::RateLimited));
+
+        // 2 per second: one more after half a second, not two
+        let later = start + Duration::from_millis(500);
+        guard.admit(10, later).unwrap();
+        assert_eq!(guard.admit(10, later), Err(Violation::RateLimited));
+
+        assert_eq!(
+            guard.admit(101, later + Duration::from_secs(1)),
+            Err(Violation::TooLarge(101, 100))
+        );
+    }
+
+    #[test]
+    fn test_rate_limit_disabled() {
+        let start = Instant::now();
+        let mut guard = MessageGuard::new(WsLimits { messages_per_second: 0, ..limits() }, start);
+        for _ in 0..100 {
+            guard.admit(10, start).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_violations_close_and_reset() {
+        let start = Instant::now();
+        let mut guard = MessageGuard::new(limits(), start);
+        assert!(!guard.record_violation(start));
+        assert!(!guard.record_violation(start));
+
+        // A quiet minute wipes the record
+        let later = start + VIOLATION_RESET;
+        assert!(!guard.record_violation(later));
+        assert!(!guard.record_violation(later));
+        assert!(guard.record_violation(later));
+
+        let Message::Text(frame) = guard.warning_frame(&Violation::RateLimited, None) else {
+            panic!("expected a text frame");
+        };
+        let frame: serde_json::Value = serde_json::from_str(&frame).unwrap();
+        assert_eq!(frame["message_type"], "ws-warning");
+        let content: serde_json::Value = serde_json::from_str(frame["content"].as_str().unwrap()).unwrap();
+        assert_eq!(content["code"], "rate-limited");
+        assert_eq!(content["violations"], 3);
+    }
+}
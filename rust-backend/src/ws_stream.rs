@@ -0,0 +1,359 @@
+//! Per-connection log streaming options for `/ws`
+//!
+//! Clients opt in with a `subscribe` message:
+//!
+//! ```json
+//! {"type": "subscribe", "batch": {"maxEntries": 50, "windowMs": 100}, "compression": "deflate"}
+//! ```
+//!
+//! With batching, structured logs are coalesced into one `structured-log-batch` frame per
+//! `maxEntries` entries or `windowMs` window, whichever comes first. With `deflate`, frames
+//! larger than [`COMPRESSION_MIN_BYTES`] are sent as binary frames holding the raw-deflate
+//! compressed JSON (`DecompressionStream('deflate-raw')` in browsers); smaller frames stay text.
+//! The WebSocket stack (tungstenite) has no permessage-deflate extension, hence the
+//! application-level compression.
+//!
+//! Frames wait in a per-connection [`Outbox`] of `bufferSize` frames (default
+//! [`DEFAULT_BUFFER_SIZE`]) while the socket is busy. Frames that don't fit, or entries the
+//! connection fell too far behind the broadcast for, are skipped; once there is room again the
+//! client gets a `stream-lagged` frame with the skipped counts and the last log ID it received
+//! per ticket, from which it can ask for the rest:
+//!
+//! ```json
+//! {"message_type": "stream-lagged", "skipped_logs": 120, "skipped_events": 0, "resume": {"t1": "log-id"}}
+//! {"type": "backfill", "ticketId": "t1", "afterId": "log-id"}
+//! ```
+//!
+//! A client that keeps lagging (`WS_MAX_LAGS` times without a quiet minute in between) is
+//! disconnected with close code 1013 (try again later).
+
+use crate::message_store::StructuredLogEntry;
+use axum::extract::ws::{close_code, CloseFrame, Message};
+use flate2::{write::DeflateEncoder, Compression};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::Write;
+use tokio::sync::mpsc;
+use tokio::time::{Duration, Instant};
+
+/// Frames below this size are not worth compressing
+pub const COMPRESSION_MIN_BYTES: usize = 512;
+const MAX_BATCH_ENTRIES: usize = 1000;
+const MAX_BATCH_WINDOW_MS: u64 = 5_000;
+pub const DEFAULT_BUFFER_SIZE: usize = 256;
+const MIN_BUFFER_SIZE: usize = 16;
+const MAX_BUFFER_SIZE: usize = 4096;
+/// Room kept past the largest buffer for frames that must not be skipped (replies, close)
+const DIRECT_HEADROOM: usize = 16;
+/// A client this long without lagging starts over with a clean record
+const LAG_RESET: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WsCompression {
+    None,
+    Deflate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamSettings {
+    /// 1 disables batching (one frame per log entry)
+    pub batch_max_entries: usize,
+    pub batch_window: Duration,
+    pub compression: WsCompression,
+    /// Frames waiting for the socket before new ones are skipped
+    pub buffer_size: usize,
+}
+
+impl Default for StreamSettings {
+    fn default() -> Self {
+        Self {
+            batch_max_entries: 1,
+            batch_window: Duration::from_millis(100),
+            compression: WsCompression::None,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+        }
+    }
+}
+
+impl StreamSettings {
+    pub fn batching(&self) -> bool {
+        self.batch_max_entries > 1
+    }
+
+    /// Settings requested by a `subscribe` message; omitted fields keep their defaults
+    pub fn from_subscribe(message: &Value) -> Self {
+        let defaults = Self::default();
+        let batch = &message["batch"];
+
+        let (batch_max_entries, batch_window) = if batch.is_object() {
+            let max_entries = batch["maxEntries"]
+                .as_u64()
+                .map(|n| (n as usize).clamp(1, MAX_BATCH_ENTRIES))
+                .unwrap_or(50);
+            let window_ms = batch["windowMs"]
+                .as_u64()
+                .map(|ms| ms.clamp(1, MAX_BATCH_WINDOW_MS))
+                .unwrap_or(defaults.batch_window.as_millis() as u64);
+            (max_entries, Duration::from_millis(window_ms))
+        } else {
+            (defaults.batch_max_entries, defaults.batch_window)
+        };
+
+        let compression = match message["compression"].as_str() {
+            Some("deflate") => WsCompression::Deflate,
+            _ => WsCompression::None,
+        };
+
+        let buffer_size = message["bufferSize"]
+            .as_u64()
+            .map(|n| (n as usize).clamp(MIN_BUFFER_SIZE, MAX_BUFFER_SIZE))
+            .unwrap_or(defaults.buffer_size);
+
+        Self {
+            batch_max_entries,
+            batch_window,
+            compression,
+            buffer_size,
+        }
+    }
+}
+
+/// JSON shape of a structured log as sent to WS clients
+pub fn log_json(log_entry: &StructuredLogEntry) -> Value {
+    json!({
+        "id": log_entry.id,
+        "ticket_id": log_entry.ticket_id,
+        "message_type": log_entry.message_type,
+        "content": log_entry.content,
+        "raw_log": log_entry.raw_log,
+        "metadata": log_entry.metadata,
+        "timestamp": log_entry.timestamp.to_rfc3339(),
+    })
+}
+
+pub fn single_log_message(log_entry: &StructuredLogEntry) -> Value {
+    json!({
+        "message_type": "structured-log",
+        "log": log_json(log_entry),
+    })
+}
+
+pub fn batch_message(logs: Vec<Value>) -> Value {
+    json!({
+        "message_type": "structured-log-batch",
+        "logs": logs,
+    })
+}
+
+/// Log entries carried by a `structured-log` or `structured-log-batch` message
+fn frame_logs(message: &Value) -> Vec<&Value> {
+    match message["message_type"].as_str() {
+        Some("structured-log") => vec![&message["log"]],
+        Some("structured-log-batch") => message["logs"].as_array().map(|logs| logs.iter().collect()).unwrap_or_default(),
+        _ => Vec::new(),
+    }
+}
+
+/// Why the connection's send loop has to stop
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutboxError {
+    /// The socket writer is gone
+    Closed,
+    /// The client lagged too often; send [`slow_consumer_close_frame`] and drop it
+    Evict,
+}
+
+/// One connection's frames waiting for the socket writer, bounded by
+/// [`StreamSettings::buffer_size`], with the bookkeeping for `stream-lagged` notices
+#[derive(Debug)]
+pub struct Outbox {
+    tx: mpsc::Sender<Message>,
+    /// Lag episodes before the client is evicted; 0 never evicts
+    max_lags: u32,
+    lags: u32,
+    last_lag: Option<Instant>,
+    /// Entries skipped since the last notice; a notice is pending while either is non-zero
+    skipped_logs: u64,
+    skipped_events: u64,
+    /// Last log ID delivered per ticket, where a backfill picks up
+    last_ids: HashMap<String, String>,
+}
+
+impl Outbox {
+    /// The outbox and the receiving end for the socket writer
+    pub fn new(max_lags: u32) -> (Self, mpsc::Receiver<Message>) {
+        let (tx, rx) = mpsc::channel(MAX_BUFFER_SIZE + DIRECT_HEADROOM);
+        let outbox = Self {
+            tx,
+            max_lags,
+            lags: 0,
+            last_lag: None,
+            skipped_logs: 0,
+            skipped_events: 0,
+            last_ids: HashMap::new(),
+        };
+        (outbox, rx)
+    }
+
+    fn queued(&self) -> usize {
+        self.tx.max_capacity() - self.tx.capacity()
+    }
+
+    /// Queue a frame past the buffer limit, e.g. a reply or the close frame
+    pub fn push_direct(&self, frame: Message) -> Result<(), OutboxError> {
+        self.tx.try_send(frame).map_err(|_| OutboxError::Closed)
+    }
+
+    /// Queue a message, or skip it when the buffer is full
+    pub fn push(&mut self, message: &Value, settings: &StreamSettings, now: Instant) -> Result<(), OutboxError> {
+        self.send_notice(settings)?;
+        let logs = frame_logs(message);
+        if self.queued() >= settings.buffer_size {
+            return match logs.len() {
+                0 => self.lagged(0, 1, now),
+                n => self.lagged(n as u64, 0, now),
+            };
+        }
+        self.push_direct(encode_frame(message, settings.compression))?;
+        for log in logs {
+            if let (Some(ticket_id), Some(id)) = (log["ticket_id"].as_str(), log["id"].as_str()) {
+                self.last_ids.insert(ticket_id.to_string(), id.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Count entries the client missed; the first skip since the last notice counts as a lag
+    pub fn lagged(&mut self, logs: u64, events: u64, now: Instant) -> Result<(), OutboxError> {
+        let new_episode = self.skipped_logs == 0 && self.skipped_events == 0;
+        self.skipped_logs += logs;
+        self.skipped_events += events;
+        if !new_episode {
+            return Ok(());
+        }
+        if self.last_lag.is_some_and(|at| now.saturating_duration_since(at) >= LAG_RESET) {
+            self.lags = 0;
+        }
+        self.lags += 1;
+        self.last_lag = Some(now);
+        if self.max_lags > 0 && self.lags >= self.max_lags {
+            return Err(OutboxError::Evict);
+        }
+        Ok(())
+    }
+
+    /// Queue the pending `stream-lagged` notice once the buffer has room for it
+    pub fn send_notice(&mut self, settings: &StreamSettings) -> Result<(), OutboxError> {
+        if (self.skipped_logs == 0 && self.skipped_events == 0) || self.queued() >= settings.buffer_size {
+            return Ok(());
+        }
+        let notice = json!({
+            "message_type": "stream-lagged",
+            "skipped_logs": self.skipped_logs,
+            "skipped_events": self.skipped_events,
+            "resume": self.last_ids,
+        });
+        self.push_direct(encode_frame(&notice, settings.compression))?;
+        self.skipped_logs = 0;
+        self.skipped_events = 0;
+        Ok(())
+    }
+}
+
+/// Close frame for a client evicted for falling behind
+pub fn slow_consumer_close_frame() -> Message {
+    Message::Close(Some(CloseFrame {
+        code: close_code::AGAIN,
+        reason: "Client too slow for the log stream".into(),
+    }))
+}
+
+/// Serialize a message into a text frame, or a compressed binary frame when enabled
+pub fn encode_frame(message: &Value, compression: WsCompression) -> Message {
+    let text = serde_json::to_string(message).unwrap_or_else(|_| "{}".to_string());
+
+    if compression == WsCompression::Deflate && text.len() >= COMPRESSION_MIN_BYTES {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::fast());
+        if encoder.write_all(text.as_bytes()).is_ok() {
+            if let Ok(compressed) = encoder.finish() {
+                return Message::Binary(compressed);
+            }
+        }
+    }
+
+    Message::Text(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::DeflateDecoder;
+    use std::io::Read;
+
+    #[test]
+    fn test_settings_from_subscribe() {
+        let settings = StreamSettings::from_subscribe(&json!({
+            "type": "subscribe",
+            "batch": { "maxEntries": 20, "windowMs": 250 },
+            "compression": "deflate"
+        }));
+        assert_eq!(settings.batch_max_entries, 20);
+        assert_eq!(settings.batch_window, Duration::from_millis(250));
+        assert_eq!(settings.compression, WsCompression::Deflate);
+
+        let plain = StreamSettings::from_subscribe(&json!({ "type": "subscribe" }));
+        assert_eq!(plain, StreamSettings::default());
+        assert!(!plain.batching());
+    }
+
+    #[test]
+    fn test_outbox_skips_when_full_and_evicts_slow_clients() {
+        let settings = StreamSettings::from_subscribe(&json!({ "type": "subscribe", "bufferSize": 1 }));
+        assert_eq!(settings.buffer_size, MIN_BUFFER_SIZE);
+        let (mut outbox, mut rx) = Outbox::new(2);
+        let log = |id: &str| json!({ "message_type": "structured-log", "log": { "id": id, "ticket_id": "t1" } });
+        let start = Instant::now();
+
+        for i in 0..MIN_BUFFER_SIZE {
+            outbox.push(&log(&i.to_string()), &settings, start).unwrap();
+        }
+        // Full: skipped, counted as one lag however many frames go
+        outbox.push(&log("a"), &settings, start).unwrap();
+        outbox.push(&batch_message(vec![json!({ "id": "b" }), json!({ "id": "c" })]), &settings, start).unwrap();
+        outbox.push(&json!({ "message_type": "ticket-updated" }), &settings, start).unwrap();
+
+        // Once the writer catches up, the notice goes out ahead of the next frame
+        while rx.try_recv().is_ok() {}
+        outbox.push(&log("d"), &settings, start).unwrap();
+        let Message::Text(notice) = rx.try_recv().unwrap() else {
+            panic!("expected a text frame");
+        };
+        let notice: Value = serde_json::from_str(&notice).unwrap();
+        assert_eq!(notice["message_type"], "stream-lagged");
+        assert_eq!(notice["skipped_logs"], 3);
+        assert_eq!(notice["skipped_events"], 1);
+        assert_eq!(notice["resume"]["t1"], (MIN_BUFFER_SIZE - 1).to_string());
+
+        // A second lag within the minute evicts; one after a quiet minute doesn't
+        let later = start + LAG_RESET;
+        assert_eq!(outbox.lagged(5, 0, later), Ok(()));
+        outbox.send_notice(&settings).unwrap();
+        assert_eq!(outbox.lagged(5, 0, later), Err(OutboxError::Evict));
+    }
+
+    #[test]
+    fn test_encode_frame_compresses_large_messages() {
+        let small = json!({ "message_type": "pong" });
+        assert!(matches!(encode_frame(&small, WsCompression::Deflate), Message::Text(_)));
+
+        let large = batch_message(vec![json!({ "content": "x".repeat(4096) })]);
+        let Message::Binary(bytes) = encode_frame(&large, WsCompression::Deflate) else {
+            panic!("expected a binary frame");
+        };
+        assert!(bytes.len() < 4096);
+
+        let mut decoded = String::new();
+        DeflateDecoder::new(&bytes[..]).read_to_string(&mut decoded).unwrap();
+        assert_eq!(serde_json::from_str::<Value>(&decoded).unwrap(), large);
+    }
+}
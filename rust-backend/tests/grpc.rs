@@ -0,0 +1,110 @@
+//! The gRPC service against the scripted `fake-agent`, called in process: CreateTicket,
+//! StartAnalysis, StreamLogs and GetResult. Run with `cargo test --features grpc`.
+#![cfg(feature = "grpc")]
+
+use futures_util::StreamExt;
+use qa_chatbot_backend::grpc_service::proto::analysis_service_server::AnalysisService;
+use qa_chatbot_backend::grpc_service::{proto, GrpcAnalysisService};
+use qa_chatbot_backend::test_support::{FakeAgent, Script, TestApp};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+use tonic::Request;
+
+const FAKE_AGENT: &str = env!("CARGO_BIN_EXE_fake-agent");
+const WAIT: Duration = Duration::from_secs(20);
+
+#[tokio::test]
+async fn test_create_analyze_stream_and_get_result() {
+    let script = Script::new()
+        .init("claude-test")
+        .tool_use("Read", json!({ "file_path": "src/login.rs" }))
+        .result("Luồng đăng nhập: form → POST /api/login → session cookie");
+    let agent = Arc::new(FakeAgent::new(FAKE_AGENT, script).unwrap());
+    let app = TestApp::start(agent).await.unwrap();
+    let project: Value = reqwest::Client::new()
+        .post(app.url("/api/projects"))
+        .json(&json!({ "name": "Shop", "directory_path": app.project_dir() }))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let service = GrpcAnalysisService::new(app.state.clone());
+
+    let ticket = service
+        .create_ticket(Request::new(proto::CreateTicketRequest {
+            project_id: project["id"].as_str().unwrap().to_string(),
+            title: "Login".to_string(),
+            description: "Luồng đăng nhập chạy thế nào?".to_string(),
+            code_context: None,
+            status: None,
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(ticket.status, "todo");
+    assert!(!ticket.is_analyzing);
+
+    let mut logs = service
+        .stream_logs(Request::new(proto::StreamLogsRequest {
+            ticket_id: ticket.id.clone(),
+            include_history: false,
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+
+    let started = service
+        .start_analysis(Request::new(proto::StartAnalysisRequest {
+            ticket_id: ticket.id.clone(),
+            question: None,
+            code_context: None,
+            agent_type: None,
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(started.started, "{}", started.message);
+    let run_id = started.run_id.unwrap();
+
+    let read_logged = async {
+        while let Some(entry) = logs.next().await {
+            let entry = entry.unwrap();
+            assert_eq!(entry.ticket_id, ticket.id);
+            if entry.content.contains("src/login.rs") {
+                return entry;
+            }
+        }
+        panic!("Log stream ended");
+    };
+    let entry = tokio::time::timeout(WAIT, read_logged).await.expect("No log of the Read call");
+    assert_eq!(entry.metadata.get("run_id"), Some(&run_id));
+
+    let finished = async {
+        loop {
+            let result = service
+                .get_result(Request::new(proto::GetResultRequest { ticket_id: ticket.id.clone() }))
+                .await
+                .unwrap()
+                .into_inner();
+            if !result.is_analyzing && result.session_status.as_deref() != Some("running") {
+                return result;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    };
+    let result = tokio::time::timeout(WAIT, finished).await.expect("Analysis didn't finish");
+    assert_eq!(result.session_status.as_deref(), Some("completed"));
+    assert_eq!(result.run_id.as_deref(), Some(run_id.as_str()));
+    assert!(result.analysis_result.unwrap().contains("POST /api/login"));
+
+    let missing = service
+        .get_result(Request::new(proto::GetResultRequest { ticket_id: "missing".to_string() }))
+        .await
+        .unwrap_err();
+    assert_eq!(missing.code(), tonic::Code::NotFound);
+}
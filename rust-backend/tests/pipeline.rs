@@ -0,0 +1,306 @@
+//! The whole pipeline against the scripted `fake-agent`: REST calls start an analysis, its logs
+//! and outcome arrive over `/ws`, and the result and session are stored
+
+use futures_util::{SinkExt, StreamExt};
+use qa_chatbot_backend::code_agent::AnalysisMode;
+use qa_chatbot_backend::project_roots::ProjectRoots;
+use qa_chatbot_backend::test_support::{FakeAgent, Script, TestApp};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::Message;
+
+const FAKE_AGENT: &str = env!("CARGO_BIN_EXE_fake-agent");
+const WAIT: Duration = Duration::from_secs(20);
+
+type Socket = tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+async fn start(script: Script) -> (TestApp, Arc<FakeAgent>) {
+    let agent = Arc::new(FakeAgent::new(FAKE_AGENT, script).unwrap());
+    let app = TestApp::start(agent.clone()).await.unwrap();
+    (app, agent)
+}
+
+/// A project on the app's directory with one ticket; returns the ticket's ID
+async fn create_ticket(app: &TestApp, client: &reqwest::Client) -> String {
+    let project: Value = client
+        .post(app.url("/api/projects"))
+        .json(&json!({ "name": "Shop", "directory_path": app.project_dir() }))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let ticket: Value = client
+        .post(app.url(&format!("/api/projects/{}/tickets", project["id"].as_str().unwrap())))
+        .json(&json!({ "title": "Login", "description": "Luồng đăng nhập chạy thế nào?", "status": "todo" }))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    ticket["id"].as_str().unwrap().to_string()
+}
+
+async fn start_analysis(app: &TestApp, client: &reqwest::Client, ticket_id: &str) {
+    start_analysis_with(app, client, ticket_id, json!({})).await
+}
+
+async fn start_analysis_with(app: &TestApp, client: &reqwest::Client, ticket_id: &str, body: Value) {
+    let started: Value = client
+        .post(app.url(&format!("/api/tickets/{}/analyze", ticket_id)))
+        .json(&body)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(started["success"], true, "{}", started);
+}
+
+/// Messages received until one of type `message_type`, that one last
+async fn receive_until(socket: &mut Socket, message_type: &str) -> Vec<Value> {
+    let mut received = Vec::new();
+    let wait = async {
+        while let Some(frame) = socket.next().await {
+            let Message::Text(text) = frame.unwrap() else { continue };
+            let message: Value = serde_json::from_str(&text).unwrap();
+            let done = message["message_type"] == message_type;
+            received.push(message);
+            if done {
+                return;
+            }
+        }
+    };
+    tokio::time::timeout(WAIT, wait)
+        .await
+        .unwrap_or_else(|_| panic!("No {} within {:?}", message_type, WAIT));
+    received
+}
+
+async fn sessions(app: &TestApp, client: &reqwest::Client, ticket_id: &str) -> Vec<Value> {
+    client
+        .get(app.url(&format!("/api/tickets/{}/sessions", ticket_id)))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_analysis_streams_logs_and_stores_result() {
+    let script = Script::new()
+        .init("claude-test")
+        .tool_use("Read", json!({ "file_path": "src/login.rs" }))
+        .assistant("Đang đọc controller đăng nhập")
+        .result("Luồng đăng nhập: form → POST /api/login → session cookie");
+    let (app, agent) = start(script).await;
+    let client = reqwest::Client::new();
+    let ticket_id = create_ticket(&app, &client).await;
+    let (mut socket, _) = tokio_tungstenite::connect_async(app.ws_url()).await.unwrap();
+
+    start_analysis(&app, &client, &ticket_id).await;
+    let received = receive_until(&mut socket, "code-analysis-complete").await;
+
+    let logs: Vec<&Value> = received
+        .iter()
+        .filter(|message| message["message_type"] == "structured-log")
+        .map(|message| &message["log"])
+        .collect();
+    assert!(logs.iter().all(|log| log["ticket_id"] == ticket_id.as_str()));
+    assert!(logs.iter().any(|log| log["content"].as_str().unwrap_or_default().contains("src/login.rs")));
+    let complete = received.last().unwrap();
+    assert_eq!(complete["ticket_id"], ticket_id.as_str());
+    assert!(complete["content"].as_str().unwrap().contains("POST /api/login"));
+
+    let result = client
+        .get(app.url(&format!("/api/tickets/{}/result", ticket_id)))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(result.contains("Luồng đăng nhập"));
+    let sessions = sessions(&app, &client, &ticket_id).await;
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0]["status"], "completed");
+
+    let args = agent.last_args().unwrap();
+    assert!(args.windows(2).any(|pair| pair == ["--output-format", "stream-json"]));
+}
+
+#[tokio::test]
+async fn test_replay_stream() {
+    let script = Script::new().init("claude-test").assistant("Đang đọc").result("Xong");
+    let (app, _agent) = start(script).await;
+    let client = reqwest::Client::new();
+    let ticket_id = create_ticket(&app, &client).await;
+    let (mut socket, _) = tokio_tungstenite::connect_async(app.ws_url()).await.unwrap();
+
+    start_analysis(&app, &client, &ticket_id).await;
+    let live: Vec<Value> = receive_until(&mut socket, "code-analysis-complete")
+        .await
+        .into_iter()
+        .filter(|message| message["message_type"] == "structured-log")
+        .collect();
+    app.state.msg_store.flush().await;
+    let session_id = sessions(&app, &client, &ticket_id).await[0]["id"].as_str().unwrap().to_string();
+
+    let body = client
+        .get(app.url(&format!("/api/sessions/{}/replay-stream?speed=0", session_id)))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    let frames: Vec<Value> = body
+        .lines()
+        .filter_map(|line| line.strip_prefix("data: "))
+        .map(|data| serde_json::from_str(data).unwrap())
+        .collect();
+    assert!(frames.iter().all(|frame| frame["replay"] == true && frame["session_id"] == session_id.as_str()));
+    let replayed: Vec<&Value> = frames.iter().filter(|frame| frame["message_type"] == "structured-log").map(|frame| &frame["log"]["id"]).collect();
+    assert_eq!(replayed, live.iter().map(|message| &message["log"]["id"]).collect::<Vec<_>>());
+    assert_eq!(frames.last().unwrap()["message_type"], "replay-complete");
+
+    // Over the WebSocket, to the requesting connection
+    socket
+        .send(Message::Text(json!({ "type": "replay-session", "sessionId": session_id, "speed": 0 }).to_string()))
+        .await
+        .unwrap();
+    let received = receive_until(&mut socket, "replay-complete").await;
+    assert_eq!(received.iter().filter(|message| message["replay"] == true).count(), frames.len());
+}
+
+#[tokio::test]
+async fn test_failed_agent_fails_the_session() {
+    let script = Script::new().init("claude-test").stderr("API error: overloaded").exit(3);
+    let (app, _agent) = start(script).await;
+    let client = reqwest::Client::new();
+    let ticket_id = create_ticket(&app, &client).await;
+    let (mut socket, _) = tokio_tungstenite::connect_async(app.ws_url()).await.unwrap();
+
+    start_analysis(&app, &client, &ticket_id).await;
+    let received = receive_until(&mut socket, "code-analysis-complete").await;
+
+    let stderr_logged = received.iter().any(|message| {
+        message["message_type"] == "structured-log"
+            && message["log"]["content"].as_str().unwrap_or_default().contains("API error: overloaded")
+    });
+    assert!(stderr_logged);
+    let sessions = sessions(&app, &client, &ticket_id).await;
+    assert_eq!(sessions[0]["status"], "failed");
+    assert!(sessions[0]["error_message"].as_str().unwrap().contains('3'));
+
+    let raw: Value = client
+        .get(app.url(&format!("/api/sessions/{}/raw", sessions[0]["id"].as_str().unwrap())))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(raw["prompt"].as_str().unwrap().contains("Luồng đăng nhập chạy thế nào?"), "{}", raw);
+    assert!(raw["argv"].as_array().unwrap().iter().any(|arg| arg == "stream-json"));
+    let lines: Vec<Value> = raw["transcript"].as_str().unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert!(lines.iter().any(|line| line["stream"] == "stderr" && line["line"] == "API error: overloaded"));
+    assert_eq!(lines.last().unwrap()["line"], "3");
+}
+
+#[tokio::test]
+async fn test_stop_analysis() {
+    let script = Script::new().init("claude-test").assistant("Đang phân tích").sleep_ms(10_000).result("too late");
+    let (app, _agent) = start(script).await;
+    let client = reqwest::Client::new();
+    let ticket_id = create_ticket(&app, &client).await;
+    let (mut socket, _) = tokio_tungstenite::connect_async(app.ws_url()).await.unwrap();
+
+    start_analysis(&app, &client, &ticket_id).await;
+    // Stop once the agent is running, i.e. has logged something
+    receive_until(&mut socket, "structured-log").await;
+    let stopped: Value = client
+        .post(app.url(&format!("/api/tickets/{}/stop-analysis", ticket_id)))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(stopped["success"], true, "{}", stopped);
+
+    let received = receive_until(&mut socket, "analysis-stopped").await;
+    assert!(received.iter().all(|message| message["message_type"] != "code-analysis-complete"));
+    assert_eq!(sessions(&app, &client, &ticket_id).await[0]["status"], "cancelled");
+    assert!(app.state.running_tasks.lock().await.is_empty());
+}
+
+#[tokio::test]
+async fn test_mode_override_is_per_run_unless_kept() {
+    let script = Script::new().init("claude-test").result("[]");
+    let (app, _agent) = start(script).await;
+    let client = reqwest::Client::new();
+    let ticket_id = create_ticket(&app, &client).await;
+    let (mut socket, _) = tokio_tungstenite::connect_async(app.ws_url()).await.unwrap();
+    let ticket_mode = || async { app.state.database.get_ticket(&ticket_id).await.unwrap().unwrap().mode };
+
+    start_analysis_with(&app, &client, &ticket_id, json!({ "mode": "testcases" })).await;
+    receive_until(&mut socket, "code-analysis-complete").await;
+    assert_eq!(sessions(&app, &client, &ticket_id).await[0]["mode"], "testcases");
+    assert_eq!(ticket_mode().await, AnalysisMode::Ask);
+
+    start_analysis_with(&app, &client, &ticket_id, json!({ "mode": "diagram", "set_ticket_mode": true })).await;
+    let received = receive_until(&mut socket, "code-analysis-complete").await;
+    assert!(received.iter().any(|message| message["message_type"] == "ticket-mode" && message["content"] == "diagram"));
+    assert_eq!(ticket_mode().await, AnalysisMode::Diagram);
+
+    // Without a mode, runs use the ticket's
+    start_analysis(&app, &client, &ticket_id).await;
+    receive_until(&mut socket, "code-analysis-complete").await;
+    let modes: Vec<Value> = sessions(&app, &client, &ticket_id).await.iter().map(|session| session["mode"].clone()).collect();
+    assert!(modes.contains(&json!("testcases")) && modes.iter().filter(|mode| *mode == "diagram").count() >= 2);
+}
+
+#[tokio::test]
+async fn test_ws_project_directory_must_be_under_roots() {
+    let agent = Arc::new(FakeAgent::new(FAKE_AGENT, Script::new()).unwrap());
+    let app = TestApp::start_with(agent, |state, dir| {
+        state.project_roots = ProjectRoots::new(&[dir.join("project").to_str().unwrap()]);
+    })
+    .await
+    .unwrap();
+    let (mut socket, _) = tokio_tungstenite::connect_async(app.ws_url()).await.unwrap();
+    let outside = app.dir.to_str().unwrap();
+
+    let create = json!({ "type": "create-project", "requestId": "r1", "name": "Host", "directoryPath": outside });
+    socket.send(Message::Text(create.to_string())).await.unwrap();
+    let error = receive_until(&mut socket, "ws-error").await.pop().unwrap();
+    let content: Value = serde_json::from_str(error["content"].as_str().unwrap()).unwrap();
+    assert_eq!(content["request_id"], "r1");
+    assert_eq!(content["code"], "invalid-request");
+    assert!(app.state.database.list_projects().await.unwrap().is_empty());
+
+    let inside = app.project_dir();
+    let create = json!({ "type": "create-project", "id": "p1", "name": "Shop", "directoryPath": inside });
+    socket.send(Message::Text(create.to_string())).await.unwrap();
+    receive_until(&mut socket, "project-created").await;
+
+    let update = json!({ "type": "update-project", "requestId": "r2", "id": "p1", "name": "Shop", "directoryPath": outside });
+    socket.send(Message::Text(update.to_string())).await.unwrap();
+    let error = receive_until(&mut socket, "ws-error").await.pop().unwrap();
+    let content: Value = serde_json::from_str(error["content"].as_str().unwrap()).unwrap();
+    assert_eq!(content["request_id"], "r2");
+    assert_eq!(content["code"], "invalid-request");
+    let project = app.state.database.get_project("p1").await.unwrap().unwrap();
+    assert_eq!(std::path::Path::new(&project.directory_path), inside.canonicalize().unwrap());
+}
@@ -87,7 +87,13 @@ export const useTicketStore = create<TicketStore>((set, get) => ({
     set((state) => ({
       tickets: state.tickets.map((ticket) =>
         ticket.id === ticketId || ticket.id === log.ticketId
-          ? { ...ticket, logs: [...ticket.logs, log] }
+          ? {
+              ...ticket,
+              // Compacted tool-call runs are re-sent under the same id with a higher count
+              logs: ticket.logs.some((l) => l.id === log.id)
+                ? ticket.logs.map((l) => (l.id === log.id ? log : l))
+                : [...ticket.logs, log],
+            }
           : ticket
       ),
     })),
@@ -32,6 +32,25 @@ export interface Ticket {
   codeContext?: string
   analysisResult?: string
   isAnalyzing: boolean
+  // File agent đã đọc ở lần phân tích cuối đã thay đổi sau đó
+  stale?: boolean
+  // Kết quả quá lớn được lưu ở blob store: analysisResult chỉ là phần xem trước,
+  // bản đầy đủ lấy qua GET /api/tickets/:id/result
+  analysisResultBlob?: string
+  analysisResultSize?: number
+  // Thứ tự trong cột status, nhỏ nhất ở trên cùng
+  position?: number
+  // Người được giao ticket
+  assigneeId?: string | null
+  // TL;DR vài dòng của analysisResult (project bật summary-settings), null khi chưa có
+  summary?: string | null
+  // Mode dùng cho các lần phân tích không chỉ định mode, mặc định 'ask'
+  mode?: AnalysisMode
+  customFields?: CustomFieldValues
+  // Thời điểm vào status hiện tại
+  statusSince?: string
+  // Ở status hiện tại quá SLA (TICKET_SLA_HOURS)
+  overdue?: boolean
   logs: StructuredLog[]
 }
 
@@ -53,6 +72,78 @@ export interface AnalysisSession {
   errorMessage?: string
 }
 
+// GET /api/tickets/:id/sessions, GET /api/sessions/:id — session thô từ backend (snake_case),
+// kèm môi trường chạy để truy vết kết quả
+export interface SessionRecord {
+  id: string
+  ticket_id: string
+  started_at: string
+  completed_at: string | null
+  status: 'running' | 'completed' | 'failed' | 'cancelled'
+  error_message: string | null
+  agent_type: string | null
+  run_id: string | null
+  tool_policy: string | null
+  // Output của `<cli> --version`; null với agent gọi API trực tiếp
+  cli_version: string | null
+  model: string | null
+  git_commit: string | null
+  // SHA-256 của câu hỏi + code context gửi cho agent
+  prompt_hash: string | null
+  // JSON cấu hình agent (không có API key), mode và path rules
+  config_snapshot: string | null
+  // Session gốc nếu đây là lần phát lại
+  replay_of: string | null
+  // Thử nghiệm prompt và biến thể được gán cho lần chạy
+  experiment_id: string | null
+  variant_id: string | null
+  // Chi phí agent báo về (hiện chỉ Claude Code)
+  cost_usd: number | null
+  // Token input + output agent báo về trong result event
+  tokens: number | null
+  // JSON HookResult[] của các post-run hook chạy sau session này
+  hook_results: string | null
+  // JSON OutputViolation[]: kết quả không đúng yêu cầu của mode
+  output_violations: string | null
+  // JSON ChangedFile[]: file mà các tool ghi/sửa của session đã thay đổi
+  changed_files: string | null
+  // Mode của lần chạy này, có thể khác mode của ticket
+  mode: AnalysisMode | null
+}
+
+export type FileOperation = 'created' | 'modified' | 'deleted'
+
+// GET /api/sessions/:id/changed-files
+export interface ChangedFile {
+  // Tương đối với thư mục project
+  path: string
+  operation: FileOperation
+  // Các tool ghi/sửa đã gọi trên file
+  tools: string[]
+  // Kích thước sau run trừ kích thước trước lần ghi đầu (byte)
+  bytes_delta: number
+}
+
+// GET /api/sessions/:id/raw — chỉ org admin; ?format=gz tải file .jsonl.gz gốc
+export interface SessionRawResponse {
+  session_id: string
+  // Prompt đã ghép đầy đủ gửi cho CLI (secret đã che)
+  prompt: string
+  argv: string[]
+  // Chỉ tên biến môi trường, không lưu giá trị
+  env_names: string[]
+  // JSON lines {"at_ms", "stream": "spawn" | "stdout" | "stderr" | "exit", "line"}
+  transcript: string
+  transcript_size: number
+  created_at: string
+}
+
+// POST /api/sessions/:id/replay — chạy lại đúng prompt trên đúng commit (202)
+export interface ReplayResponse {
+  run_id: string
+  replay_of: string
+}
+
 // WebSocket message types
 export interface WebSocketMessage {
   message_type: string
@@ -68,6 +159,8 @@ export interface RawStructuredLog {
   raw_log?: string
   metadata?: Record<string, string>
   timestamp: string
+  // raw_log chỉ là phần xem trước; bản đầy đủ: GET /api/tickets/:id/logs/:log_id/raw
+  raw_log_blob?: string
 }
 
 // Paginated logs response từ backend
@@ -77,9 +170,56 @@ export interface PaginatedLogsResponse {
   has_more: boolean
 }
 
+// GET /api/tickets/:id/logs/tail?after_id=&wait=30 — long polling thay cho WebSocket.
+// logs rỗng khi hết wait; gửi next_after_id ở lần poll sau
+export interface LogTailResponse {
+  logs: RawStructuredLog[]
+  next_after_id: string | null
+  is_analyzing: boolean
+}
+
+// Query của GET /api/projects/:id/logs (log của mọi ticket trong project)
+export interface ProjectLogsQuery {
+  // Nhiều loại cách nhau bởi dấu phẩy, vd "error,tool_use"
+  message_type?: string
+  ticket_id?: string
+  // YYYY-MM-DD hoặc RFC 3339; `until` dạng ngày được tính cả ngày đó
+  from?: string
+  until?: string
+  // Tìm trong content, không phân biệt hoa thường
+  q?: string
+  sort?: 'asc' | 'desc'
+  limit?: number
+  offset?: number
+}
+
+export interface ProjectLogsResponse extends PaginatedLogsResponse {
+  // Số log theo từng message_type, bỏ qua filter message_type
+  counts_by_type: Record<string, number>
+}
+
 export interface StructuredLogMessage extends WebSocketMessage {
   message_type: 'structured-log'
   log: RawStructuredLog
+  // Có khi phát lại session đã xong (replay-stream / replay-session), không phải log thật
+  replay?: true
+  session_id?: string
+}
+
+// GET /api/sessions/:id/replay-stream?speed=1 (SSE, mở bằng EventSource) hoặc gửi qua WebSocket
+// { type: 'replay-session', sessionId, speed? }: log đã lưu của session, giữ nhịp gốc chia
+// cho speed (0 = gửi ngay), kết thúc bằng message này; chỉ gửi cho người yêu cầu
+export interface ReplayCompleteMessage extends WebSocketMessage {
+  message_type: 'replay-complete'
+  replay: true
+  session_id: string
+  count: number
+}
+
+// Sent instead of 'structured-log' after a `subscribe` message that enables batching
+export interface StructuredLogBatchMessage extends WebSocketMessage {
+  message_type: 'structured-log-batch'
+  logs: RawStructuredLog[]
 }
 
 export interface CodeAnalysisCompleteMessage extends WebSocketMessage {
@@ -87,6 +227,8 @@ export interface CodeAnalysisCompleteMessage extends WebSocketMessage {
   ticket_id: string
   content: string
   timestamp: string
+  // File run đã thay đổi (rỗng với run chỉ đọc)
+  changed_files?: ChangedFile[]
 }
 
 export interface CodeAnalysisErrorMessage extends WebSocketMessage {
@@ -96,7 +238,984 @@ export interface CodeAnalysisErrorMessage extends WebSocketMessage {
   timestamp: string
 }
 
+export type AnalysisStage = 'init' | 'exploring_files' | 'reasoning' | 'writing_answer'
+
+// content là JSON string: { stage, label, elapsed_ms, session_id }
+export interface AnalysisProgressMessage extends WebSocketMessage {
+  message_type: 'analysis-progress'
+  ticket_id: string
+  content: string
+  timestamp: string
+}
+
+// Sau một lần chạy mode 'testcases': content là số test case đã lưu, hoặc lỗi parse
+export interface TestCasesMessage extends WebSocketMessage {
+  message_type: 'test-cases-generated' | 'test-cases-error'
+  ticket_id: string
+  content: string
+  timestamp: string
+}
+
+// Sau một lần chạy mode 'diagram': content là loại diagram đã lưu, hoặc lỗi đọc mermaid
+export interface DiagramMessage extends WebSocketMessage {
+  message_type: 'diagram-generated' | 'diagram-error'
+  ticket_id: string
+  content: string
+  timestamp: string
+}
+
+export type OutputViolationKind = 'file-write' | 'missing-test-cases' | 'missing-diagram' | 'empty-result'
+
+export interface OutputViolation {
+  kind: OutputViolationKind
+  detail: string
+}
+
+// Khi kết quả vi phạm mode: content là JSON string { run_id, violations: OutputViolation[] }
+export interface OutputViolationMessage extends WebSocketMessage {
+  message_type: 'output-violation'
+  ticket_id: string
+  content: string
+  timestamp: string
+}
+
+export type DiagramKind = 'sequence' | 'flowchart' | 'state' | 'class' | 'er'
+
+// GET /api/tickets/:id/diagram (?format=mmd trả source thô, ?format=svg trả ảnh SVG)
+export interface TicketDiagram {
+  ticket_id: string
+  run_id: string | null
+  kind: DiagramKind
+  source: string
+  svg: string | null // null khi server chưa cấu hình MERMAID_CLI_PATH hoặc render lỗi
+  created_at: string
+  svg_available: boolean
+}
+
+export type AnalysisPriority = 'low' | 'normal' | 'high' | 'urgent'
+
+// POST /api/projects/:id/bootstrap; mặc định tạo mọi template của server
+export interface BootstrapProjectRequest {
+  templates?: string[] // 'architecture' | 'business-flows' | 'entry-points' hoặc key trong BOOTSTRAP_TEMPLATES_FILE
+  agent_type?: string
+  priority?: AnalysisPriority
+}
+
+// Ticket được chạy lần lượt theo thứ tự; run_id là của ticket đầu tiên
+export interface BootstrapProjectResponse {
+  tickets: Ticket[]
+  run_id: string
+}
+
+// Phân tích đang chờ slot (ANALYSIS_MAX_CONCURRENT); content là JSON
+// { run_id, priority, position, preempted } — preempted: bị tạm dừng để nhường cho phân tích urgent
+export interface AnalysisQueuedMessage extends WebSocketMessage {
+  message_type: 'analysis-queued'
+  ticket_id: string
+  content: string
+  timestamp: string
+}
+
+// File mà lần phân tích cuối đã đọc vừa thay đổi; ticket được đánh dấu stale
+export interface TicketStaleMessage extends WebSocketMessage {
+  message_type: 'ticket-stale'
+  ticket_id: string
+  content: string
+  timestamp: string
+}
+
+// Agent ở chế độ tương tác (CLAUDE_AGENT_INTERACTIVE) đang chờ xác nhận; content là JSON AgentPermissionRequest
+export interface AgentAwaitingInputMessage extends WebSocketMessage {
+  message_type: 'agent-awaiting-input'
+  ticket_id: string
+  content: string
+  timestamp: string
+}
+
+// Message của client bị bỏ qua (quá nhanh, quá lớn, không hợp lệ, hoặc thuộc org khác);
+// content là JSON WsWarning. Đạt max_violations thì server đóng kết nối (close code 1008)
+export interface WsWarningMessage extends WebSocketMessage {
+  message_type: 'ws-warning'
+  ticket_id: 'system'
+  content: string
+  timestamp: string
+}
+
+export interface WsWarning {
+  // requestId của message bị bỏ qua, nếu đọc được
+  request_id: string | null
+  code: 'rate-limited' | 'payload-too-large' | 'invalid-message' | 'forbidden'
+  message: string
+  violations: number
+  max_violations: number
+}
+
+// Thao tác WebSocket thất bại, chỉ gửi về kết nối đã gửi yêu cầu; content là JSON WsErrorReply.
+// Client đặt `requestId` trong message để ghép lỗi với yêu cầu
+export interface WsErrorMessage extends WebSocketMessage {
+  message_type: 'ws-error'
+  // ticketId của yêu cầu, hoặc 'system'
+  ticket_id: string
+  content: string
+  timestamp: string
+}
+
+export interface WsErrorReply {
+  request_id: string | null
+  request_type: string | null
+  // budget-exceeded: ngân sách tháng đã dùng hết (xem BudgetResponse)
+  code: 'not-found' | 'invalid-request' | 'budget-exceeded' | 'internal'
+  message: string
+}
+
+// Client tụt lại phía sau log stream (outbox đầy hoặc broadcast bị lag): các entry bị bỏ qua.
+// Gửi { type: 'backfill', ticketId, afterId: resume[ticketId] } để nhận lại dưới dạng
+// 'structured-log-batch'. Lag WS_MAX_LAGS lần thì server đóng kết nối (close code 1013)
+export interface StreamLaggedMessage extends WebSocketMessage {
+  message_type: 'stream-lagged'
+  skipped_logs: number
+  skipped_events: number
+  // ID log cuối cùng đã nhận, theo ticket
+  resume: Record<string, string>
+}
+
+// Người đang xem ticket (GET /api/tickets/:id/presence và content của các message presence-*)
+export interface TicketViewer {
+  client_id: string
+  user_id: string | null
+  name: string | null
+  since: string
+  // Ô đang chỉnh sửa, ví dụ 'plan'; hết hiệu lực sau editing_until nếu không gửi lại 'editing'
+  editing: string | null
+  editing_until: string | null
+}
+
+// Chỉ gửi cho các kết nối khác đang xem ticket đó ({ type: 'view-ticket', ticketId });
+// content là JSON TicketViewer
+export interface PresenceMessage extends WebSocketMessage {
+  message_type: 'presence-joined' | 'presence-left' | 'presence-editing'
+  ticket_id: string
+  content: string
+  timestamp: string
+}
+
+export interface TicketWatcher {
+  ticket_id: string
+  user_id: string
+  reason: 'creator' | 'assignee' | 'manual'
+  created_at: string
+}
+
+// Một mục trong GET /api/me/activity: hoạt động trên ticket đang theo dõi, do người khác thực hiện
+export interface ActivityFeedEntry {
+  id: string
+  ticket_id: string
+  ticket_title: string
+  project_id: string
+  kind: 'status-changed' | 'assigned' | 'analysis-completed' | 'analysis-failed'
+  // null khi do hệ thống (kết quả phân tích)
+  actor_id: string | null
+  summary: string
+  created_at: string
+}
+
+export interface ActivityFeedResponse {
+  entries: ActivityFeedEntry[]
+  // Truyền vào `before` để lấy trang cũ hơn
+  next_before: string | null
+}
+
+export interface AgentPermissionRequest {
+  request_id: string | null
+  tool: string | null
+  prompt: string
+}
+
+// Client gửi để trả lời: text là câu trả lời tự do (Cursor) hoặc lý do từ chối (Claude)
+export interface AgentInputMessage {
+  type: 'agent-input'
+  ticketId: string
+  allow?: boolean
+  text?: string
+}
+
 // Type guard để validate LogMessageType
 export function isValidLogMessageType(type: string): type is LogMessageType {
   return ['tool_use', 'assistant', 'error', 'system', 'result'].includes(type)
 }
+
+// GET /api/agents
+export type AnalysisMode = 'ask' | 'testcases' | 'diagram'
+export type AgentAuthStatus = 'api_key' | 'cli_login' | 'not_required' | 'missing'
+
+export interface AgentInfo {
+  id: string
+  name: string
+  modes: AnalysisMode[]
+  available: boolean
+  auth_status: AgentAuthStatus
+  default_timeout_seconds: number
+  model: string | null
+  is_default: boolean
+}
+
+// GET /api/tickets/:id/test-cases
+export interface TestCase {
+  id: string
+  ticket_id: string
+  run_id: string | null
+  position: number
+  title: string
+  preconditions: string[]
+  steps: string[]
+  expected: string
+  priority: string | null
+  flow: string | null
+  created_at: string
+}
+
+// GET /api/tickets/:id/test-cases/export
+export type TestCaseExportFormat = 'csv' | 'gherkin'
+export type GherkinGrouping = 'ticket' | 'flow'
+
+// GET /api/tickets/:id/files — file agent đã đọc trong lần phân tích gần nhất
+export interface TicketFile {
+  ticket_id: string
+  file_path: string
+  run_id: string | null
+  analyzed_at: string
+}
+
+// GET /api/tickets/:id/timeline?run_id= — diễn biến một lần phân tích (mặc định lần gần nhất)
+export type TimelineKind = 'file_read' | 'search' | 'command' | 'tool' | 'summary' | 'error'
+
+export interface TimelineEvent {
+  kind: TimelineKind
+  started_at: string
+  duration_ms: number | null
+  tool: string | null
+  target: string | null
+  text: string | null
+  log_id: string
+}
+
+export interface TicketTimeline {
+  ticket_id: string
+  run_id: string | null
+  started_at: string | null
+  ended_at: string | null
+  events: TimelineEvent[]
+}
+
+// GET /api/projects/:id/coverage
+export interface FileCoverage {
+  path: string
+  tickets: string[]
+  // Ticket được phân tích trước lần sửa file gần nhất (hoặc file đã bị xóa)
+  stale_tickets: string[]
+  last_analyzed_at: string
+  // Commit gần nhất (git) hoặc mtime; null khi không có thay đổi từ lần phân tích
+  changed_at: string | null
+  exists: boolean
+}
+
+export interface DirectoryCoverage {
+  directory: string
+  total_files: number
+  analyzed_files: number
+  stale_files: number
+}
+
+export interface ProjectCoverage {
+  project_id: string
+  total_files: number
+  analyzed_files: number
+  stale_files: number
+  stale_tickets: string[]
+  directories: DirectoryCoverage[]
+  files: FileCoverage[]
+}
+
+// GET/PUT /api/projects/:id/path-policy — glob kiểu gitignore; include rỗng = cho phép tất cả
+export interface ProjectPathPolicy {
+  include: string[]
+  exclude: string[]
+}
+
+// GET/PUT /api/projects/:id/tool-policy — tên tool theo Claude Code, vd 'Read', 'Bash(git log:*)'
+// allowed_tools null = dùng mặc định read-only của mode (PUT null để reset)
+export interface ProjectToolPolicy {
+  allowed_tools: string[] | null
+  default_tools: string[]
+  known_tools: string[]
+}
+
+// GET/PUT /api/projects/:id/log-persistence — chỉ quyết định log nào được lưu DB,
+// WebSocket vẫn nhận tất cả; persistence null = dùng cấu hình server (PUT null để reset)
+export interface LogPersistence {
+  // null = lưu mọi loại
+  message_types: LogMessageType[] | null
+  // Chỉ giữ raw_log cho entry lỗi
+  raw_log_errors_only: boolean
+}
+
+export interface ProjectLogPersistence {
+  persistence: LogPersistence | null
+  default: LogPersistence
+}
+
+// Ngân sách theo tháng (UTC): GET/PUT /api/projects/:id/budget và /api/orgs/current/budget,
+// PUT chỉ org admin với { budget: Budget | null } (null = xóa)
+export interface Budget {
+  // usd: tổng cost_usd, tokens: tổng tokens của các session trong tháng
+  metric: 'usd' | 'tokens'
+  monthly_limit: number
+  // reject: từ chối mọi lần chạy khi hết; require-override: org admin vẫn chạy được với override
+  enforcement: 'reject' | 'require-override'
+}
+
+export interface BudgetStatus {
+  scope: 'project' | 'org'
+  scope_id: string
+  budget: Budget
+  since: string // YYYY-MM-DD
+  used: number
+  percent: number
+}
+
+export interface BudgetResponse {
+  budget: Budget | null
+  // Mọi ngân sách áp dụng (của project và của organization)
+  status: BudgetStatus[]
+}
+
+// Message `budget-threshold` gửi tới các client của organization khi đạt 80% / 100%;
+// content là JSON BudgetThreshold
+export interface BudgetThreshold {
+  scope: 'project' | 'org'
+  scope_id: string
+  project_id: string
+  threshold: 80 | 100
+  budget: Budget
+  used: number
+  percent: number
+  since: string
+}
+
+// GET /api/admin/database/stats (chỉ instance admin); size_bytes null khi SQLite không có dbstat
+export interface DatabaseStats {
+  size_bytes: number
+  free_bytes: number // phần VACUUM thu hồi được
+  page_size: number
+  tables: { name: string; rows: number; size_bytes: number | null }[]
+  indexes: {
+    name: string
+    table: string
+    automatic: boolean // tạo bởi UNIQUE / PRIMARY KEY
+    size_bytes: number | null
+    stat: string | null // sqlite_stat1 của lần ANALYZE gần nhất
+  }[]
+  analyzed: boolean
+}
+
+export type MaintenanceTask = 'integrity-check' | 'vacuum' | 'analyze'
+
+// POST /api/admin/database/integrity-check | vacuum | analyze
+export interface MaintenanceReport {
+  started_at: string
+  size_before: number
+  size_after: number
+  tasks: { task: MaintenanceTask; duration_ms: number; problems?: string[] }[]
+  ok: boolean // false khi integrity check tìm thấy lỗi
+}
+
+// API key riêng của project cho từng provider (anthropic: claude + claude-api,
+// gemini: gemini + gemini-api, cursor), chỉ org admin — server không bao giờ trả key đầy đủ
+export type AgentCredentialProvider = 'anthropic' | 'gemini' | 'cursor'
+
+export interface AgentCredential {
+  provider: AgentCredentialProvider
+  masked_key: string // vd '••••wxyz'
+  agents: string[]
+  updated_by: string | null
+  updated_at: string
+}
+
+// GET /api/projects/:id/agent-credentials
+// enabled = false khi server chưa cấu hình AGENT_CREDENTIALS_KEY (PUT trả 503)
+export interface AgentCredentialsResponse {
+  enabled: boolean
+  credentials: AgentCredential[]
+}
+
+// PUT /api/projects/:id/agent-credentials/:provider
+export interface SetAgentCredentialRequest {
+  api_key: string
+}
+
+// GET/PUT /api/projects/:id/summary-settings — PUT chỉ gửi enabled, max_lines (null = mặc định server)
+export interface ProjectSummarySettings {
+  enabled: boolean
+  max_lines: number | null
+  default_max_lines: number
+  model: string
+}
+
+// Message `ticket-summary`: content là bản tóm tắt mới của ticket
+export interface TicketSummaryMessage extends WebSocketMessage {
+  message_type: 'ticket-summary'
+}
+
+// POST /api/tickets/:id/analyze — mọi trường đều tùy chọn, mặc định lấy từ ticket.
+// Gửi kèm header Idempotency-Key để retry không khởi động lại phân tích
+export interface StartAnalysisRequest {
+  question?: string
+  code_context?: string
+  agent_type?: string
+  // Chỉ áp dụng cho lần chạy này; bỏ trống thì dùng mode của ticket
+  mode?: AnalysisMode
+  // Lưu mode làm mode của ticket (broadcast `ticket-mode`)
+  set_ticket_mode?: boolean
+  priority?: AnalysisPriority
+  // Thêm kết quả của các ticket liên kết vào code context
+  include_linked_results?: boolean
+  // Vẫn chạy khi ngân sách (enforcement 'require-override') đã hết — chỉ org admin
+  override_budget?: boolean
+}
+
+export interface StartAnalysisResponse {
+  success: boolean
+  message: string
+  run_id?: string
+  // Bị từ chối vì ngân sách tháng đã hết
+  budget_exceeded?: boolean
+  // Org admin có thể gửi lại với override_budget: true
+  override_allowed?: boolean
+}
+
+// GET /api/tickets/:id/analysis-status — dùng cho client polling thay vì WebSocket
+export type AnalysisState = 'idle' | 'queued' | 'running' | 'completed' | 'failed' | 'cancelled'
+
+export interface AnalysisStatus {
+  state: AnalysisState
+  run_id: string | null
+  session_id: string | null
+  // Chỉ có khi đang chờ hoặc đang chạy
+  priority: AnalysisPriority | null
+  queue_position: number | null
+  started_at: string | null
+  completed_at: string | null
+  elapsed_seconds: number | null
+  stage: string | null
+  error: string | null
+  // Kết quả phân tích khi state = 'completed'
+  result: string | null
+}
+
+// GET /api/trash — project đã xóa, khôi phục bằng POST /api/trash/projects/:id/restore
+export interface TrashedProject {
+  id: string
+  name: string
+  description?: string
+  directory_path: string
+  created_at: string
+  updated_at: string
+  org_id: string
+  deleted_at: string
+  deleted_by?: string
+  // null khi TRASH_RETENTION_DAYS=0
+  purge_at: string | null
+}
+
+export interface TrashResponse {
+  retention_days: number
+  projects: TrashedProject[]
+}
+
+// POST /api/projects/:id/resolve-context — xem trước các file khớp với code context mơ hồ
+export interface ResolveContextRequest {
+  code_context: string
+  max_files?: number
+}
+
+export interface ResolvedFile {
+  path: string
+  score: number
+  // Ví dụ: "filename: login", "symbol: LoginController", "selected"
+  reasons: string[]
+}
+
+// GET/PUT /api/tickets/:id/context-files — danh sách file đã chọn cho ticket
+export interface TicketContextFiles {
+  resolved_for: string | null
+  resolved_at: string | null
+  files: ResolvedFile[]
+}
+
+export interface SetContextFilesRequest {
+  code_context?: string
+  paths: string[]
+}
+
+// Đọc là "source <link_type> target"; blocks và duplicates không được tạo vòng
+export type TicketLinkType = 'blocks' | 'relates-to' | 'duplicates'
+
+// PUT /api/tickets/:id/position
+export interface UpdateTicketPositionRequest {
+  // Cột đích; mặc định là status hiện tại của ticket
+  status?: TicketStatus
+  // Vị trí trong cột, 0 là trên cùng
+  index: number
+}
+
+// Response của PUT /api/tickets/:id/position và nội dung message `ticket-reordered`
+export interface TicketMove {
+  ticket_id: string
+  status: TicketStatus
+  position: number
+  index: number
+  // Các ticket khác trong cột cũng được đánh số lại: tải lại cả cột
+  renumbered: boolean
+}
+
+export type CustomFieldType = 'text' | 'number' | 'boolean' | 'date' | 'select' | 'multi-select'
+
+// GET/POST /api/projects/:id/custom-fields, PUT/DELETE /api/projects/:id/custom-fields/:field_id
+export interface CustomField {
+  id: string
+  project_id: string
+  name: string
+  field_type: CustomFieldType
+  // Chỉ dùng cho select và multi-select
+  options: string[]
+  created_at: string
+}
+
+// Giá trị custom field theo tên field; date là 'YYYY-MM-DD', multi-select là string[].
+// PUT /api/tickets/:id/custom-fields nhận cùng dạng, null để xoá giá trị
+export type CustomFieldValues = Record<string, string | number | boolean | string[]>
+
+// PUT /api/tickets/:id/assignee; null để bỏ giao
+export interface AssignTicketRequest {
+  assignee_id: string | null
+}
+
+// Nội dung message `ticket-assigned`
+export interface TicketAssignment {
+  ticket_id: string
+  assignee_id: string | null
+  previous_assignee_id: string | null
+  // null với truy cập ẩn danh
+  assigned_by: string | null
+}
+
+// GET/POST /api/tickets/:id/links, DELETE /api/tickets/:id/links/:link_id
+export interface TicketLink {
+  id: string
+  source_ticket_id: string
+  target_ticket_id: string
+  link_type: TicketLinkType
+  created_by: string | null
+  created_at: string
+}
+
+export interface CreateTicketLinkRequest {
+  target_ticket_id: string
+  link_type: TicketLinkType
+}
+
+// GET /api/projects/:id/ticket-graph
+export interface TicketGraphNode {
+  id: string
+  title: string
+  status: TicketStatus
+  is_analyzing: boolean
+  has_result: boolean
+  // Các ticket chặn ticket này mà chưa done
+  blocked_by: string[]
+}
+
+export interface TicketGraphEdge {
+  id: string
+  source: string
+  target: string
+  link_type: TicketLinkType
+}
+
+export interface TicketGraph {
+  nodes: TicketGraphNode[]
+  edges: TicketGraphEdge[]
+}
+
+// POST /api/tickets/:id/share, GET /api/tickets/:id/shares
+export interface ShareLink {
+  id: string
+  ticket_id: string
+  created_by: string | null
+  created_at: string
+  expires_at: string | null
+  revoked_at: string | null
+  has_password: boolean
+  active: boolean
+}
+
+export interface CreateShareRequest {
+  // 1..8760 (một năm); bỏ trống thì link không hết hạn
+  expires_in_hours?: number
+  password?: string
+}
+
+// token chỉ trả về một lần; url là trang chỉ đọc /share/:token
+export interface CreateShareResponse extends ShareLink {
+  token: string
+  url: string
+}
+
+// GET /api/share/:token (mật khẩu qua header X-Share-Password)
+export interface SharedTicket {
+  title: string
+  description: string
+  status: TicketStatus
+  analysis_result: string | null
+  analysis_html: string | null
+  logs: RawStructuredLog[]
+  total_logs: number
+  has_more: boolean
+  expires_at: string | null
+}
+
+export type EmailMode = 'off' | 'immediate' | 'digest'
+
+// GET/PUT /api/me/notifications
+export interface NotificationSettings {
+  email_mode: EmailMode
+  email_available: boolean
+  min_duration_secs: number | null
+  digest_interval_minutes: number | null
+}
+
+// Kiểm tra trước khi phân tích (GET/PUT /api/projects/:id/preflight), chạy theo thứ tự
+export type PreflightCheck =
+  | { type: 'git-clean' }
+  | { type: 'branch'; branch: string }
+  | { type: 'path-exists'; path: string }
+  // Chỉ khi server bật PREFLIGHT_ALLOW_COMMANDS
+  | { type: 'command'; command: string }
+
+export interface ProjectPreflight {
+  checks: PreflightCheck[]
+  allow_commands: boolean
+}
+
+// GET/PUT /api/projects/:id/post-run-hooks — chạy sau lần phân tích có quyền sửa code
+export interface PostRunHooks {
+  hooks: { command: string }[]
+  // Hook lỗi thì đưa ticket về 'in-progress'
+  reopen_on_failure: boolean
+}
+
+export interface HookResult {
+  command: string
+  // null khi bị dừng bởi signal, quá thời gian hoặc không chạy được (xem error)
+  exit_code: number | null
+  error?: string
+  duration_ms: number
+}
+
+// GET/POST /api/question-templates — thư viện câu hỏi dùng chung (project_id null) hoặc của một project
+export interface QuestionTemplate {
+  id: string
+  org_id: string
+  project_id: string | null
+  category: string
+  title: string
+  question: string
+  mode: AnalysisMode
+  code_context: string | null
+  // Số ticket đã tạo từ template
+  usage_count: number
+  created_by: string | null
+  created_at: string
+  updated_at: string
+}
+
+// POST /api/question-templates/:id/tickets
+export interface TicketFromTemplateRequest {
+  // Bắt buộc với template dùng chung
+  project_id?: string
+  status?: string
+  // Chạy phân tích ngay theo mode của template
+  start?: boolean
+  agent_type?: string
+}
+
+export interface TicketFromTemplate {
+  ticket: Ticket
+  mode: AnalysisMode
+  run_id: string | null
+}
+
+// GET/PUT /api/me/preferences — mặc định dùng khi request không chỉ định
+export interface UserPreferences {
+  default_agent: string | null
+  response_language: string | null
+  // Tên IANA, ví dụ 'Asia/Ho_Chi_Minh'
+  timezone: string | null
+  notification_channels: { email: EmailMode }
+  default_project_id: string | null
+  updated_at?: string | null
+}
+
+// GET/POST /api/integrations/slack/workspaces
+export interface SlackWorkspace {
+  team_id: string
+  org_id: string
+  team_name: string
+  installed_by: string | null
+  created_at: string
+}
+
+export interface LinkSlackWorkspaceRequest {
+  bot_token: string
+}
+
+// POST/GET /api/tickets/:id/feedback
+export type FeedbackRating = 'up' | 'down'
+
+export interface ResultFeedback {
+  id: string
+  ticket_id: string
+  session_id: string
+  user_id: string | null
+  rating: 1 | -1
+  comment: string | null
+  created_at: string
+  updated_at: string
+}
+
+// Trường `quality` của analytics: tỉ lệ đánh giá tốt theo agent và mode
+export interface FeedbackQuality {
+  agent_type: string
+  mode: string
+  ratings: number
+  thumbs_up: number
+  thumbs_down: number
+  approval_rate: number
+}
+
+// GET/POST /api/projects/:id/experiments
+export type ExperimentStatus = 'running' | 'stopped'
+
+export interface Experiment {
+  id: string
+  project_id: string
+  name: string
+  status: ExperimentStatus
+  created_by: string | null
+  created_at: string
+  stopped_at: string | null
+}
+
+export interface PromptVariant {
+  id: string
+  experiment_id: string
+  position: number
+  name: string
+  // Có chứa {question}, chỗ đặt câu hỏi
+  template: string
+  weight: number
+}
+
+export interface CreateExperimentRequest {
+  name: string
+  variants: { name: string; template: string; weight?: number }[]
+}
+
+// GET /api/projects/:id/experiments/:experiment_id
+export interface VariantReport extends PromptVariant {
+  variant_id: string
+  runs: number
+  completed: number
+  failed: number
+  cancelled: number
+  avg_duration_ms: number | null
+  ratings: number
+  thumbs_up: number
+  thumbs_down: number
+  approval_rate: number | null
+  total_cost_usd: number | null
+  avg_cost_usd: number | null
+}
+
+export interface ExperimentReport extends Experiment {
+  variants: VariantReport[]
+}
+
+export type AlertAction =
+  | { type: 'webhook'; url: string }
+  | { type: 'email'; to: string[] }
+  // Gửi alert-triggered tới các client của organization
+  | { type: 'notification' }
+
+// GET/POST /api/alert-rules, PUT/DELETE /api/alert-rules/:id (org admin)
+export interface AlertRule {
+  id: string
+  org_id: string
+  // null: mọi project của organization
+  project_id: string | null
+  name: string
+  message_type: LogMessageType | null
+  // Regex trên nội dung log
+  content_pattern: string | null
+  // Số log khớp trong một run để alert bắn (mỗi run tối đa một lần)
+  threshold: number
+  actions: AlertAction[]
+  enabled: boolean
+  created_by: string | null
+  created_at: string
+  updated_at: string
+}
+
+// GET /api/alert-rules/:id/events
+export interface AlertEvent {
+  id: string
+  rule_id: string
+  ticket_id: string
+  run_id: string
+  match_count: number
+  // Log làm alert bắn (tối đa 500 ký tự)
+  sample: string
+  created_at: string
+}
+
+// content là JSON string { rule_id, rule, ticket_id, project_id, run_id, match_count, sample, triggered_at }
+export interface AlertTriggeredMessage extends WebSocketMessage {
+  message_type: 'alert-triggered'
+  ticket_id: string
+  content: string
+  timestamp: string
+}
+
+export interface StatusTime {
+  status: string
+  seconds: number
+}
+
+export interface StatusChange {
+  id: string
+  ticket_id: string
+  from_status: string | null
+  to_status: string
+  actor_id: string | null
+  changed_at: string
+}
+
+// GET /api/tickets/:id/time
+export interface TicketTime {
+  ticket_id: string
+  status: TicketStatus
+  status_since: string
+  // Tổng thời gian ở mỗi status, kể cả lần hiện tại tới bây giờ
+  time_in_status: StatusTime[]
+  // Từ lúc tạo tới lần cuối chuyển sang done; null khi chưa done
+  cycle_time_seconds: number | null
+  sla_hours: number | null
+  overdue: boolean
+  transitions: StatusChange[]
+}
+
+// Trường `time` của analytics: các ticket tạo trong khoảng thời gian
+export interface TimeAnalytics {
+  tickets: number
+  avg_time_in_status: StatusTime[]
+  done: number
+  avg_cycle_time_seconds: number | null
+  overdue: number
+}
+
+export interface RunningAnalysis {
+  session_id: string
+  run_id: string | null
+  ticket_id: string
+  ticket_title: string
+  agent_type: string
+  started_at: string
+  elapsed_seconds: number
+}
+
+export interface Contributor {
+  user_id: string
+  name: string
+  // Ticket do user tạo
+  tickets_created: number
+  // Số hoạt động (đổi status, giao ticket) của user
+  actions: number
+}
+
+// GET /api/admin/tasks
+export interface BackgroundTask {
+  id: number
+  // Ví dụ "analysis <ticket> run <run>", "log-writer", "ws-send <client>"
+  name: string
+  started_at: string
+  // Số lần khởi động lại sau khi panic
+  restarts: number
+}
+
+export interface TaskList {
+  tasks: BackgroundTask[]
+  // Số task đã panic từ khi server khởi động
+  panics: number
+}
+
+// GET /api/search/tickets?q=&project_id=&limit=
+// Không phân biệt hoa thường và dấu: "phan tich" khớp "Phân tích"; trả về Ticket[] xếp theo độ liên quan
+export interface TicketSearchParams {
+  q: string
+  // Chỉ tìm trong một project; mặc định cả tổ chức
+  project_id?: string
+  // Mặc định 20, tối đa 100
+  limit?: number
+}
+
+// GET /api/projects/:id/dashboard
+export interface ProjectDashboard {
+  project_id: string
+  // Mọi status của board, kể cả 0
+  tickets_by_status: Record<string, number>
+  total_tickets: number
+  // 10 hoạt động gần nhất
+  recent_activity: ActivityFeedEntry[]
+  running: RunningAnalysis[]
+  top_contributors: Contributor[]
+  // Chi phí agent báo cáo từ đầu tháng (UTC)
+  spend_this_month: { since: string; cost_usd: number; runs: number }
+}
+
+// WebSocket: client gửi {"type": "hello"} khi kết nối, server trả một frame `hello`
+// với content là HelloSnapshot (JSON) thay cho load-projects và các request khởi động khác
+export interface HelloSnapshot {
+  client_id: string
+  org_id: string
+  // null khi truy cập ẩn danh
+  user_id: string | null
+  // Dạng snake_case như projects-loaded
+  projects: Array<{
+    id: string
+    name: string
+    description?: string
+    directory_path: string
+    created_at: string
+    updated_at: string
+    org_id: string
+  }>
+  // Session đang chạy của mọi project trong tổ chức, cũ nhất trước
+  active_analyses: Array<Omit<RunningAnalysis, 'elapsed_seconds'> & { project_id: string }>
+  notifications: {
+    // Số hoạt động sau lần đọc cuối (POST /api/me/activity/read)
+    unread: number
+    read_at: string | null
+    // 20 hoạt động chưa đọc mới nhất
+    entries: ActivityFeedEntry[]
+  }
+}